@@ -0,0 +1,74 @@
+// Frame rendering: diff_to_ansi and window composition
+//
+// cargo bench --bench render
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use okros::screen::{diff_to_ansi_into, DiffOptions};
+use okros::scrollback::Attrib;
+use okros::window::Window;
+
+const SIZES: [(usize, usize); 2] = [(80, 24), (240, 70)];
+
+/// A screen of colored text, different for each `seed`
+fn frame(width: usize, height: usize, seed: usize) -> Vec<Attrib> {
+    (0..width * height)
+        .map(|i| {
+            let ch = b'!' + ((i + seed) % 90) as u8;
+            let color = 0x07 + ((i / 7 + seed) % 6) as Attrib;
+            (color << 8) | ch as Attrib
+        })
+        .collect()
+}
+
+fn opts(width: usize, height: usize) -> DiffOptions<'static> {
+    DiffOptions {
+        width,
+        height,
+        ..Default::default()
+    }
+}
+
+fn bench_diff(c: &mut Criterion) {
+    let mut out = String::new();
+    for (w, h) in SIZES {
+        let prev = frame(w, h, 0);
+        let full = frame(w, h, 1);
+        // New output scrolled in on the bottom line only
+        let mut one_line = prev.clone();
+        one_line[(h - 2) * w..(h - 1) * w].copy_from_slice(&full[(h - 2) * w..(h - 1) * w]);
+
+        c.bench_function(&format!("diff_to_ansi {}x{} unchanged", w, h), |b| {
+            b.iter(|| diff_to_ansi_into(&mut out, black_box(&prev), &prev, &opts(w, h)))
+        });
+        c.bench_function(&format!("diff_to_ansi {}x{} one line", w, h), |b| {
+            b.iter(|| diff_to_ansi_into(&mut out, black_box(&prev), &one_line, &opts(w, h)))
+        });
+        c.bench_function(&format!("diff_to_ansi {}x{} full repaint", w, h), |b| {
+            b.iter(|| diff_to_ansi_into(&mut out, black_box(&prev), &full, &opts(w, h)))
+        });
+    }
+}
+
+fn bench_compose(c: &mut Criterion) {
+    for (w, h) in SIZES {
+        let mut root = Window::new(std::ptr::null_mut(), w, h);
+        let root_ptr: *mut Window = root.as_mut();
+        let mut output = Window::new(root_ptr, w, h - 1);
+        let mut status = Window::new(root_ptr, w, 1);
+        status.parent_y = (h - 1) as isize;
+        output.canvas.copy_from_slice(&frame(w, h - 1, 0));
+        status.print("status line");
+
+        c.bench_function(&format!("compose {}x{}", w, h), |b| {
+            b.iter(|| {
+                output.dirty = true;
+                black_box(root.refresh())
+            })
+        });
+        drop(status);
+        drop(output);
+    }
+}
+
+criterion_group!(benches, bench_diff, bench_compose);
+criterion_main!(benches);
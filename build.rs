@@ -1,5 +1,6 @@
 // build.rs - Conditional linking for optional Perl feature
 
+#[cfg(feature = "perl")]
 use std::process::Command;
 
 fn main() {
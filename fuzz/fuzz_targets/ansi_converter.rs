@@ -0,0 +1,18 @@
+// AnsiConverter on arbitrary text, fed in reads of varying size
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use okros::ansi::AnsiConverter;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    let mut ac = AnsiConverter::new();
+    let mut events = 0;
+    for chunk in data.chunks(split as usize + 1) {
+        events += ac.feed(chunk).len();
+    }
+    // At most one event per byte
+    assert!(events <= data.len());
+});
@@ -0,0 +1,26 @@
+// MccpInflate on arbitrary bytes after a COMPRESS2 offer, so the fuzzer
+// reaches the zlib path by sending IAC SB COMPRESS2 IAC SE
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use okros::mccp::telopt::{COMPRESS2, IAC, WILL};
+use okros::mccp::{Decompressor, MccpInflate, MAX_INFLATE, MAX_RESIDUAL};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    let mut d = MccpInflate::new();
+    d.receive(&[IAC, WILL, COMPRESS2]);
+    for chunk in data.chunks(split as usize + 1) {
+        d.receive(chunk);
+        let mut out = 0;
+        while d.pending() {
+            out += d.take_output().len();
+        }
+        // A read's output is capped, give or take one inflate step
+        assert!(out <= MAX_INFLATE + MAX_RESIDUAL + chunk.len());
+        d.response();
+        d.take_warning();
+    }
+});
@@ -0,0 +1,22 @@
+// TelnetParser on arbitrary server bytes, fed in reads of varying size
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use okros::telnet::TelnetParser;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, data)) = data.split_first() else {
+        return;
+    };
+    let mut p = TelnetParser::new();
+    let mut text = 0;
+    for chunk in data.chunks(split as usize + 1) {
+        p.feed(chunk);
+        text += p.take_app_out().len();
+        p.take_responses();
+        p.take_prompt_marks();
+        p.take_diagnostics();
+    }
+    // IAC sequences only ever shrink the text
+    assert!(text <= data.len());
+});
@@ -3,19 +3,41 @@
 // Ported from mcl-cpp-reference/h/Action.h and mcl-cpp-reference/Alias.cc
 
 use crate::plugins::stack::Interpreter;
+use crate::profile::Kind;
 use std::any::Any;
 
+/// Languages an action's @lang{...} block may be written in
+const BLOCK_LANGUAGES: [&str; 3] = ["perl", "py", "python"];
+
+/// Trigger pattern of an action whose commands are a script block: matched
+/// here (regex crate syntax) since the block needs the capture groups
+struct BlockPattern(regex::Regex);
+
+/// `@lang{code}` commands: (language, code).
+pub fn script_block(commands: &str) -> Option<(&str, &str)> {
+    let rest = commands.trim().strip_prefix('@')?;
+    let (language, code) = rest.split_once('{')?;
+    let code = code.strip_suffix('}')?;
+    language
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric())
+        .then_some((language, code))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ActionType {
     Trigger,     // Pattern match → execute commands
     Replacement, // Pattern match → substitute text
     Gag,         // Pattern match → suppress line
+    Highlight,   // Pattern match → recolor matched spans
+    Prompt,      // Pattern match on a GA/EOR prompt → execute commands
 }
 
 pub struct Action {
     pub pattern: String,
     pub commands: String,
     pub action_type: ActionType,
+    pub group: Option<String>, // #group it belongs to (None: always active)
     compiled: Option<Box<dyn Any>>,
 }
 
@@ -25,6 +47,7 @@ impl std::fmt::Debug for Action {
             .field("pattern", &self.pattern)
             .field("commands", &self.commands)
             .field("action_type", &self.action_type)
+            .field("group", &self.group)
             .field("compiled", &self.compiled.is_some())
             .finish()
     }
@@ -37,6 +60,7 @@ impl Clone for Action {
             pattern: self.pattern.clone(),
             commands: self.commands.clone(),
             action_type: self.action_type,
+            group: self.group.clone(),
             compiled: None,
         }
     }
@@ -52,6 +76,7 @@ impl Action {
             pattern: pattern.into(),
             commands: commands.into(),
             action_type,
+            group: None,
             compiled: None,
         }
     }
@@ -60,50 +85,128 @@ impl Action {
     /// Must be called before check_match or check_replacement
     pub fn compile(&mut self, interp: &mut dyn Interpreter) {
         self.compiled = match self.action_type {
-            ActionType::Trigger => interp.match_prepare(&self.pattern, &self.commands),
-            ActionType::Replacement | ActionType::Gag => {
-                let replacement = if self.action_type == ActionType::Gag {
-                    "" // Gag = replace with empty string
-                } else {
-                    &self.commands
-                };
-                interp.substitute_prepare(&self.pattern, replacement)
+            ActionType::Trigger | ActionType::Prompt if script_block(&self.commands).is_some() => {
+                regex::Regex::new(&self.pattern)
+                    .ok()
+                    .map(|re| Box::new(BlockPattern(re)) as Box<dyn Any>)
             }
+            ActionType::Trigger | ActionType::Prompt => {
+                interp.match_prepare(&self.pattern, &self.commands)
+            }
+            ActionType::Replacement => interp.substitute_prepare(&self.pattern, &self.commands),
+            // Gag suppresses the whole line, so only a match/no-match answer is needed
+            ActionType::Gag => interp.match_prepare(&self.pattern, "gag"),
+            ActionType::Highlight => interp.span_prepare(&self.pattern),
         };
     }
 
-    /// Check if this action matches the text and run commands (for Trigger type)
-    /// Returns Some(commands) if matched
+    /// Check if this action matches the text and run commands (for Trigger
+    /// and Prompt types) Returns Some(commands) if matched
+    /// A script block runs right away, in its interpreter, and yields None
     pub fn check_match(&self, text: &str, interp: &mut dyn Interpreter) -> Option<String> {
-        if self.action_type != ActionType::Trigger {
+        if !matches!(self.action_type, ActionType::Trigger | ActionType::Prompt) {
             return None;
         }
 
-        if let Some(compiled) = &self.compiled {
-            interp.match_exec(compiled.as_ref(), text)
-        } else {
-            None
+        let compiled = self.compiled.as_ref()?;
+        crate::profile::time(
+            Kind::Action,
+            || self.profile_name(),
+            || self.run_match(compiled.as_ref(), text, interp),
+        )
+    }
+
+    fn run_match(
+        &self,
+        compiled: &dyn Any,
+        text: &str,
+        interp: &mut dyn Interpreter,
+    ) -> Option<String> {
+        if let Some(BlockPattern(re)) = compiled.downcast_ref::<BlockPattern>() {
+            let (language, code) = script_block(&self.commands)?;
+            let caps = re.captures(text)?;
+            let args: Vec<String> = caps
+                .iter()
+                .map(|m| m.map_or(String::new(), |m| m.as_str().to_string()))
+                .collect();
+            interp.run_block(language, code, &args);
+            return None;
         }
+        interp.match_exec(compiled, text)
+    }
+
+    /// "<type> <pattern>" in #profile
+    fn profile_name(&self) -> String {
+        let kind = match self.action_type {
+            ActionType::Trigger => "action",
+            ActionType::Prompt => "prompt",
+            ActionType::Replacement => "subst",
+            ActionType::Gag => "gag",
+            ActionType::Highlight => "highlight",
+        };
+        format!("{} {}", kind, self.pattern)
     }
 
     /// Check if this action should replace text (for Replacement/Gag types)
-    /// Returns Some(new_text) if matched and replaced
+    /// Returns Some(new_text) if matched and replaced; a matching Gag yields
+    /// Some("") so callers drop the entire line
     pub fn check_replacement(&self, text: &str, interp: &mut dyn Interpreter) -> Option<String> {
-        if self.action_type == ActionType::Trigger {
-            return None;
+        let compiled = self.compiled.as_ref()?;
+        crate::profile::time(
+            Kind::Action,
+            || self.profile_name(),
+            || match self.action_type {
+                ActionType::Replacement => interp.match_exec(compiled.as_ref(), text),
+                ActionType::Gag => interp
+                    .match_exec(compiled.as_ref(), text)
+                    .map(|_| String::new()),
+                ActionType::Trigger | ActionType::Highlight | ActionType::Prompt => None,
+            },
+        )
+    }
+
+    /// Check if this action highlights part of the text (for Highlight type)
+    /// Returns (start, end, color) byte ranges for every match
+    pub fn check_highlight(
+        &self,
+        text: &str,
+        interp: &mut dyn Interpreter,
+    ) -> Vec<(usize, usize, u8)> {
+        if self.action_type != ActionType::Highlight {
+            return Vec::new();
         }
+        let (Some(compiled), Some(color)) = (&self.compiled, self.highlight_color()) else {
+            return Vec::new();
+        };
+        let spans = crate::profile::time(
+            Kind::Action,
+            || self.profile_name(),
+            || interp.span_exec(compiled.as_ref(), text),
+        );
+        spans
+            .into_iter()
+            .map(|(start, end)| (start, end, color))
+            .collect()
+    }
 
-        if let Some(compiled) = &self.compiled {
-            interp.match_exec(compiled.as_ref(), text)
-        } else {
-            None
+    /// Color for a Highlight action (stored in the commands field as a color spec)
+    pub fn highlight_color(&self) -> Option<u8> {
+        if self.action_type != ActionType::Highlight {
+            return None;
         }
+        crate::color::parse_color_spec(&self.commands)
     }
 
     /// Parse action from command line format: "pattern" commands
+    /// A trigger written `-prompt "pattern" commands` matches prompts instead
     /// Returns None if parsing fails
     pub fn parse(input: &str, action_type: ActionType) -> Result<Self, String> {
         let input = input.trim_start();
+        if action_type == ActionType::Trigger {
+            if let Some(rest) = input.strip_prefix("-prompt ") {
+                return Self::parse(rest, ActionType::Prompt);
+            }
+        }
 
         // Extract pattern (quoted or first word)
         let (pattern, rest) = if input.starts_with('"') {
@@ -122,11 +225,36 @@ impl Action {
             (pattern, rest)
         };
 
+        // {a;b}: the braces only kept the group together until now
+        let rest = match action_type {
+            ActionType::Trigger | ActionType::Prompt => crate::command_queue::strip_braces(rest),
+            _ => rest,
+        };
+
         // For Replacement/Gag, commands can be empty
-        if rest.is_empty() && action_type == ActionType::Trigger {
+        if rest.is_empty() && matches!(action_type, ActionType::Trigger | ActionType::Prompt) {
             return Err(format!("Missing action string for trigger: {}", input));
         }
 
+        // @perl{...} / @py{...}: a script block instead of MUD commands
+        if rest.starts_with('@') && matches!(action_type, ActionType::Trigger | ActionType::Prompt)
+        {
+            let (language, _) = script_block(rest)
+                .ok_or_else(|| format!("Expected @language{{code}}: {}", rest))?;
+            if !BLOCK_LANGUAGES.contains(&language) {
+                return Err(format!(
+                    "Unknown script language: {} (one of {})",
+                    language,
+                    BLOCK_LANGUAGES.join(", ")
+                ));
+            }
+        }
+
+        // Highlight commands are a color spec: "bold red", "white on blue", ...
+        if action_type == ActionType::Highlight && crate::color::parse_color_spec(rest).is_none() {
+            return Err(format!("Invalid highlight color: {}", rest));
+        }
+
         Ok(Self::new(pattern, rest, action_type))
     }
 }
@@ -150,6 +278,23 @@ mod tests {
         assert_eq!(action.commands, "look");
     }
 
+    #[test]
+    fn test_action_parse_prompt() {
+        let action = Action::parse("-prompt \"^<(\\d+)hp\" rest", ActionType::Trigger).unwrap();
+        assert_eq!(action.action_type, ActionType::Prompt);
+        assert_eq!(action.pattern, "^<(\\d+)hp");
+        assert_eq!(action.commands, "rest");
+        assert!(Action::parse("-prompt \"^>\"", ActionType::Trigger).is_err());
+
+        let mut interp = crate::plugins::native_regex::NativeRegex::new();
+        let mut action = action;
+        action.compile(&mut interp);
+        assert_eq!(
+            action.check_match("<90hp>", &mut interp).as_deref(),
+            Some("rest")
+        );
+    }
+
     #[test]
     fn test_action_parse_replacement() {
         let action = Action::parse("\"stupid\" smart", ActionType::Replacement).unwrap();
@@ -178,4 +323,124 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Missing action string"));
     }
+
+    #[test]
+    fn test_action_parse_highlight() {
+        let action = Action::parse("\"dragon\" bold red", ActionType::Highlight).unwrap();
+        assert_eq!(action.pattern, "dragon");
+        assert_eq!(action.commands, "bold red");
+        assert_eq!(action.highlight_color(), Some(0x84));
+
+        let result = Action::parse("\"dragon\" chartreuse", ActionType::Highlight);
+        assert!(result.unwrap_err().contains("Invalid highlight color"));
+    }
+
+    // Minimal substring "regex" engine to exercise compile/check paths without Perl/Python
+    struct Substr;
+
+    impl Interpreter for Substr {
+        fn run(&mut self, _: &str, _: &str, _: &mut String) -> bool {
+            false
+        }
+        fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn Any>> {
+            Some(Box::new((pattern.to_string(), commands.to_string())))
+        }
+        fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn Any>> {
+            Some(Box::new(pattern.to_string()))
+        }
+        fn match_exec(&mut self, compiled: &dyn Any, text: &str) -> Option<String> {
+            let (pat, cmd) = compiled.downcast_ref::<(String, String)>()?;
+            text.contains(pat.as_str()).then(|| cmd.clone())
+        }
+        fn span_exec(&mut self, compiled: &dyn Any, text: &str) -> Vec<(usize, usize)> {
+            let Some(pat) = compiled.downcast_ref::<String>() else {
+                return Vec::new();
+            };
+            text.match_indices(pat.as_str())
+                .map(|(i, m)| (i, i + m.len()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_action_parse_script_block() {
+        let action = Action::parse(
+            "\"(\\w+) arrives\" @py{greet(args[1])}",
+            ActionType::Trigger,
+        )
+        .unwrap();
+        assert_eq!(
+            script_block(&action.commands),
+            Some(("py", "greet(args[1])"))
+        );
+        assert!(Action::parse("x @perl{print 1", ActionType::Trigger)
+            .unwrap_err()
+            .contains("Expected @language"));
+        assert!(Action::parse("x @lua{print(1)}", ActionType::Trigger)
+            .unwrap_err()
+            .contains("Unknown script language"));
+        // Not a block: sent as is
+        assert!(script_block("@here is text").is_none());
+    }
+
+    #[test]
+    fn test_script_block_runs_with_captures() {
+        #[derive(Default)]
+        struct Blocks(Vec<(String, String, Vec<String>)>);
+        impl Interpreter for Blocks {
+            fn run(&mut self, _: &str, _: &str, _: &mut String) -> bool {
+                false
+            }
+            fn run_block(&mut self, language: &str, code: &str, args: &[String]) -> bool {
+                self.0.push((language.into(), code.into(), args.to_vec()));
+                true
+            }
+        }
+
+        let mut interp = Blocks::default();
+        let mut action = Action::new(
+            r"(\w+) arrives(!)?",
+            "@perl{ greet(@_) }",
+            ActionType::Trigger,
+        );
+        action.compile(&mut interp);
+        assert_eq!(action.check_match("Bob arrives", &mut interp), None);
+        assert_eq!(action.check_match("nobody", &mut interp), None);
+        assert_eq!(
+            interp.0,
+            vec![(
+                "perl".to_string(),
+                " greet(@_) ".to_string(),
+                vec!["Bob arrives".to_string(), "Bob".to_string(), String::new()]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_gag_suppresses_whole_line() {
+        let mut interp = Substr;
+        let mut gag = Action::new("spam", "", ActionType::Gag);
+        gag.compile(&mut interp);
+
+        assert_eq!(
+            gag.check_replacement("some spam here", &mut interp),
+            Some(String::new())
+        );
+        assert_eq!(gag.check_replacement("clean line", &mut interp), None);
+        assert_eq!(gag.check_match("some spam here", &mut interp), None);
+    }
+
+    #[test]
+    fn test_highlight_spans() {
+        let mut interp = Substr;
+        let mut hl = Action::new("orc", "yellow", ActionType::Highlight);
+        hl.compile(&mut interp);
+
+        assert_eq!(
+            hl.check_highlight("orc hits orc", &mut interp),
+            vec![(0, 3, 0x06), (9, 12, 0x06)]
+        );
+        assert!(hl.check_highlight("elf", &mut interp).is_empty());
+        assert_eq!(hl.check_replacement("orc", &mut interp), None);
+    }
 }
@@ -8,6 +8,7 @@ const MAX_ALIAS_BUFFER: usize = 1024;
 pub struct Alias {
     pub name: String,
     pub text: String,
+    pub group: Option<String>, // #group it belongs to (None: always active)
 }
 
 impl Alias {
@@ -15,11 +16,14 @@ impl Alias {
         Self {
             name: name.into(),
             text: text.into(),
+            group: None,
         }
     }
 
     /// Expand alias text with argument substitution
-    /// - %N: single token N (e.g., %1, %2)
+    /// - %0: the whole argument string
+    /// - %N: single token N (e.g., %1, %2); a "quoted phrase" is one
+    ///   token, substituted without its quotes
     /// - %-N: tokens 1 through N (e.g., %-2 = tokens 1 and 2)
     /// - %+N: tokens N through end (e.g., %+2 = token 2 to last)
     /// - %%: literal %
@@ -71,8 +75,11 @@ impl Alias {
                     // Check for %N (single token)
                     if next.is_ascii_digit() {
                         let n = next.to_digit(10).unwrap() as usize;
-                        if let Some(tokens) = self.print_tokens(arg, n, n) {
-                            result.push_str(&tokens);
+                        if n == 0 {
+                            result.push_str(arg);
+                        } else if let Some(token) = self.find_token(arg, n) {
+                            let token = &token[..token_end(token)];
+                            result.push_str(unquote(token));
                         }
                         continue;
                     }
@@ -115,9 +122,10 @@ impl Alias {
                 return Some(rest);
             }
 
-            // Skip to next whitespace
-            if let Some(pos) = rest.find(char::is_whitespace) {
-                rest = &rest[pos..];
+            // Skip to the end of this token
+            let end = token_end(rest);
+            if end < rest.len() {
+                rest = &rest[end..];
                 current += 1;
             } else {
                 // Last token
@@ -157,6 +165,23 @@ impl Alias {
     }
 }
 
+/// Length of the token `s` starts with: up to the next whitespace, or for
+/// a token starting with '"' up to and including the closing quote
+fn token_end(s: &str) -> usize {
+    match s.strip_prefix('"') {
+        Some(quoted) => quoted.find('"').map_or(s.len(), |i| i + 2),
+        None => s.find(char::is_whitespace).unwrap_or(s.len()),
+    }
+}
+
+/// A token without the quotes around it (an unterminated quote is dropped)
+fn unquote(token: &str) -> &str {
+    match token.strip_prefix('"') {
+        Some(inner) => inner.strip_suffix('"').unwrap_or(inner),
+        None => token,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,6 +297,26 @@ mod tests {
         assert_eq!(alias.expand(""), "value %");
     }
 
+    #[test]
+    fn test_alias_quoted_arguments() {
+        let alias = Alias::new("tellq", "tell %1 %2");
+        assert_eq!(
+            alias.expand("\"big bob\" \"hello there\" extra"),
+            "tell big bob hello there"
+        );
+
+        // Ranges keep the text as typed, quotes included
+        let alias = Alias::new("rest", "[%+2] [%-2]");
+        assert_eq!(alias.expand("a \"b c\" d"), "[\"b c\" d] [a \"b c\"]");
+
+        // An unterminated quote runs to the end
+        let alias = Alias::new("say", "say %2|%3|");
+        assert_eq!(
+            alias.expand("x \"never closed here"),
+            "say never closed here||"
+        );
+    }
+
     #[test]
     fn test_alias_multiple_percent_patterns() {
         let alias = Alias::new("test", "%1 and %2 and %3");
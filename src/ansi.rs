@@ -1,7 +1,47 @@
+use crate::color::{BLINK, ITALIC, REVERSE, UNDERLINE};
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AnsiEvent {
     Text(u8),
-    SetColor(u8),
+    /// Color byte (bg << 4 | fg, bit 7 = bold) plus text attributes
+    /// (color::UNDERLINE etc.) in the high byte
+    SetColor(u16),
+    /// Cursor to a column of the current line (CHA, or CUP with its row
+    /// ignored), 0-based.
+    CursorTo(usize),
+    /// Cursor left (negative) or right by columns (CUB/CUF)
+    CursorBy(isize),
+    /// Erase part of the current line (EL; ED is taken to mean the line)
+    Erase(Erase),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Erase {
+    ToEnd,    // Cursor to end of line (EL 0)
+    ToCursor, // Start of line through the cursor (EL 1)
+    Line,     // The whole line (EL 2)
+}
+
+/// Line editing for a CSI sequence ending in `fin`; None if it's not one
+fn cursor_event(params: &str, fin: u8) -> Option<AnsiEvent> {
+    if params.starts_with('?') {
+        return None; // Private modes (cursor visibility etc)
+    }
+    let mut nums = params.split(';').map(|p| p.parse::<usize>().ok());
+    let first = nums.next().flatten();
+    let count = first.unwrap_or(1).max(1);
+    Some(match fin {
+        b'K' | b'J' => AnsiEvent::Erase(match first.unwrap_or(0) {
+            0 => Erase::ToEnd,
+            1 => Erase::ToCursor,
+            _ => Erase::Line,
+        }),
+        b'G' => AnsiEvent::CursorTo(count - 1),
+        b'H' | b'f' => AnsiEvent::CursorTo(nums.next().flatten().unwrap_or(1).max(1) - 1),
+        b'C' => AnsiEvent::CursorBy(count as isize),
+        b'D' => AnsiEvent::CursorBy(-(count as isize)),
+        _ => return None,
+    })
 }
 
 fn inverse_color(idx: u8) -> u8 {
@@ -17,6 +57,10 @@ fn inverse_color(idx: u8) -> u8 {
     }
 }
 
+/// Longest CSI sequence (after ESC) before it's given up on and its bytes
+/// shown as text (C++ Session.cc:444-446)
+pub const MAX_CSI: usize = 16;
+
 #[derive(Default)]
 pub struct AnsiConverter {
     buf: Vec<u8>,
@@ -24,6 +68,8 @@ pub struct AnsiConverter {
     cur_fg: u8,
     cur_bg: u8,
     bold: bool,
+    attrs: u16,  // Underline/italic/reverse/blink
+    default: u8, // Color that ESC[0m / 39 / 49 return to
 }
 
 impl AnsiConverter {
@@ -34,9 +80,20 @@ impl AnsiConverter {
             cur_fg: 7,
             cur_bg: 0,
             bold: false,
+            attrs: 0,
+            default: 0x07,
         }
     }
 
+    /// Change the reset color (bg << 4 | fg, bit 7 = bold) and reset to it
+    pub fn set_default(&mut self, color: u8) {
+        self.default = color;
+        self.cur_fg = color & 0x07;
+        self.cur_bg = (color >> 4) & 0x07;
+        self.bold = color & 0x80 != 0;
+        self.attrs = 0;
+    }
+
     pub fn feed(&mut self, bytes: &[u8]) -> Vec<AnsiEvent> {
         let mut out = Vec::new();
         let mut i = 0usize;
@@ -66,8 +123,8 @@ impl AnsiConverter {
                     self.buf.push(b);
                     i += 1;
 
-                    // C++ Session.cc:444-446 - Lose patience if code doesn't terminate within 16 chars
-                    if self.buf.len() > 16 {
+                    // Lose patience if the code doesn't terminate within MAX_CSI chars
+                    if self.buf.len() > MAX_CSI {
                         self.in_csi = false;
                         self.buf.clear();
                         continue;
@@ -75,24 +132,42 @@ impl AnsiConverter {
 
                     // CSI sequences end with any alphabetic character (A-Z, a-z)
                     if b.is_ascii_alphabetic() {
-                        // Only process 'm' (color codes), ignore others (cursor positioning, etc)
-                        if b == b'm' {
+                        // 'm' sets colors; cursor movement and erasing within the
+                        // line become events; other sequences are dropped
+                        if b != b'm' {
+                            let params =
+                                std::str::from_utf8(&self.buf[1..self.buf.len() - 1]).unwrap_or("");
+                            out.extend(cursor_event(params, b));
+                        } else {
                             let params_str =
                                 std::str::from_utf8(&self.buf[1..self.buf.len() - 1]).unwrap_or("");
                             let mut new_fg = self.cur_fg;
                             let mut new_bg = self.cur_bg;
                             let mut new_bold = self.bold;
+                            let mut attrs = self.attrs;
                             for part in params_str.split(';').filter(|s| !s.is_empty()) {
                                 if let Ok(n) = part.parse::<u32>() {
                                     match n {
                                         0 => {
-                                            new_bold = false;
-                                            new_fg = 7;
-                                            new_bg = 0;
+                                            new_bold = self.default & 0x80 != 0;
+                                            new_fg = self.default & 0x07;
+                                            new_bg = (self.default >> 4) & 0x07;
+                                            attrs = 0;
                                         }
+                                        39 => new_fg = self.default & 0x07,
+                                        49 => new_bg = (self.default >> 4) & 0x07,
                                         1 => {
                                             new_bold = true;
                                         }
+                                        22 => new_bold = self.default & 0x80 != 0,
+                                        3 => attrs |= ITALIC,
+                                        4 => attrs |= UNDERLINE,
+                                        5 | 6 => attrs |= BLINK,
+                                        7 => attrs |= REVERSE,
+                                        23 => attrs &= !ITALIC,
+                                        24 => attrs &= !UNDERLINE,
+                                        25 => attrs &= !BLINK,
+                                        27 => attrs &= !REVERSE,
                                         30..=37 => {
                                             new_fg = inverse_color((n as u8) - 30);
                                         }
@@ -113,11 +188,12 @@ impl AnsiConverter {
                             self.cur_fg = new_fg;
                             self.cur_bg = new_bg;
                             self.bold = new_bold;
+                            self.attrs = attrs;
                             let mut color: u8 = (self.cur_bg << 4) | (self.cur_fg & 0x0F);
                             if self.bold {
                                 color |= 1 << 7;
                             }
-                            out.push(AnsiEvent::SetColor(color));
+                            out.push(AnsiEvent::SetColor(self.attrs | color as u16));
                         }
                         // Exit CSI mode for any alphabetic character (H, J, K, m, etc)
                         self.in_csi = false;
@@ -135,6 +211,21 @@ mod tests {
     use super::*;
     use crate::telnet::TelnetParser;
 
+    #[test]
+    fn reset_returns_to_default_color() {
+        let mut ac = AnsiConverter::new();
+        ac.set_default(0x12); // green on blue
+        let ev = ac.feed(b"\x1b[31m\x1b[0m\x1b[1;33m\x1b[39m");
+        let colors: Vec<u16> = ev
+            .iter()
+            .filter_map(|e| match e {
+                AnsiEvent::SetColor(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(colors, vec![0x14, 0x12, 0x96, 0x92]);
+    }
+
     #[test]
     fn basic_and_fragmented_color() {
         let mut ac = AnsiConverter::new();
@@ -180,6 +271,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn text_attributes_set_and_clear() {
+        let mut ac = AnsiConverter::new();
+        let ev = ac.feed(b"\x1b[4;31m\x1b[7;3m\x1b[24;5m\x1b[22;27m\x1b[0m");
+        let colors: Vec<u16> = ev
+            .iter()
+            .filter_map(|e| match e {
+                AnsiEvent::SetColor(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            vec![
+                UNDERLINE | 0x04,
+                UNDERLINE | REVERSE | ITALIC | 0x04,
+                REVERSE | ITALIC | BLINK | 0x04,
+                ITALIC | BLINK | 0x04,
+                0x07
+            ]
+        );
+    }
+
+    #[test]
+    fn cursor_and_erase_sequences() {
+        let mut ac = AnsiConverter::new();
+        let ev = ac.feed(b"\x1b[K\x1b[1K\x1b[2J\x1b[5G\x1b[3;7H\x1b[H\x1b[2C\x1b[D\x1b[?25l");
+        assert_eq!(
+            ev,
+            vec![
+                AnsiEvent::Erase(Erase::ToEnd),
+                AnsiEvent::Erase(Erase::ToCursor),
+                AnsiEvent::Erase(Erase::Line),
+                AnsiEvent::CursorTo(4),
+                AnsiEvent::CursorTo(6),
+                AnsiEvent::CursorTo(0),
+                AnsiEvent::CursorBy(2),
+                AnsiEvent::CursorBy(-1),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlong_csi_is_given_up_on() {
+        let mut ac = AnsiConverter::new();
+        let long = format!("\x1b[{}mx", "1;".repeat(20));
+        let ev = ac.feed(long.as_bytes());
+        assert!(!ev.iter().any(|e| matches!(e, AnsiEvent::SetColor(_))));
+        // ESC and the MAX_CSI + 1 bytes that exhausted its patience are dropped
+        assert_eq!(ev.len(), long.len() - 2 - MAX_CSI);
+        assert_eq!(ev.last(), Some(&AnsiEvent::Text(b'x')));
+    }
+
     #[test]
     fn telnet_then_ansi_pipeline() {
         let mut t = TelnetParser::new();
@@ -0,0 +1,71 @@
+// ATCP (Achaea Telnet Client Protocol) - out-of-band game data
+//
+// IRE games offer TELOPT_ATCP; once it's on the client says hello with the
+// modules it wants and the server sends messages like "Char.Vitals\nH:..."
+// as subnegotiations. They're queued here and handed to the `sys/atcp`
+// script hook by Session.
+
+use crate::telnet::{subnegotiation, OptionHandler, Side};
+use std::sync::{Arc, Mutex};
+
+/// Telnet option for ATCP
+pub const TELOPT_ATCP: u8 = 200;
+
+/// Sent when ATCP turns on: client name and the modules asked for
+const HELLO: &str = "hello okros 1\nauth 1\nchar_name 1\nchar_vitals 1\nroom_brief 1\nroom_exits 1";
+
+/// Received messages, shared between the handler and Session
+pub type AtcpQueue = Arc<Mutex<Vec<String>>>;
+
+/// Says hello when ATCP turns on and queues each message the server sends
+pub struct Atcp {
+    queue: AtcpQueue,
+}
+
+impl Atcp {
+    pub fn new(queue: AtcpQueue) -> Self {
+        Self { queue }
+    }
+}
+
+impl OptionHandler for Atcp {
+    fn changed(&mut self, side: Side, on: bool, out: &mut Vec<u8>) {
+        if side == Side::Him && on {
+            out.extend(subnegotiation(TELOPT_ATCP, HELLO.as_bytes()));
+        }
+    }
+
+    fn subnegotiation(&mut self, data: &[u8], _out: &mut Vec<u8>) {
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.push(String::from_utf8_lossy(data).into_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telnet::telnet::*;
+    use crate::telnet::TelnetParser;
+
+    #[test]
+    fn hello_then_messages_queued() {
+        let queue = AtcpQueue::default();
+        let mut p = TelnetParser::new();
+        p.register(TELOPT_ATCP, Box::new(Atcp::new(queue.clone())));
+        p.feed(&[IAC, WILL, TELOPT_ATCP]);
+        assert_eq!(p.take_responses(), vec![IAC, DONT, TELOPT_ATCP]);
+
+        p.support(TELOPT_ATCP, Side::Him, true);
+        p.feed(&[IAC, WILL, TELOPT_ATCP]);
+        let mut expected = vec![IAC, DO, TELOPT_ATCP];
+        expected.extend(subnegotiation(TELOPT_ATCP, HELLO.as_bytes()));
+        assert_eq!(p.take_responses(), expected);
+
+        p.feed(&[IAC, SB, TELOPT_ATCP]);
+        p.feed(b"Char.Vitals\nH:90/100");
+        p.feed(&[IAC, SE]);
+        assert_eq!(*queue.lock().unwrap(), vec!["Char.Vitals\nH:90/100"]);
+        assert!(p.take_app_out().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-MUD automatic login
+//!
+//! Answers common name/password prompts with the MUD's configured `user` and
+//! `password`, then runs its `commands` list once on the first prompt after
+//! login. Nothing is sent until the socket has reached Connected.
+
+use crate::mud::Mud;
+
+/// What the caller should do in response to a prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginStep {
+    /// Send this line to the MUD verbatim (credentials: no expansion, no echo)
+    Send(String),
+    /// Run these commands through CommandQueue expansion (semicolons, aliases)
+    Commands(String),
+}
+
+#[derive(Debug, Default)]
+pub struct AutoLogin {
+    commands: String,
+    user: String,
+    password: String,
+    connected: bool,
+    user_sent: bool,
+    password_sent: bool,
+    done: bool,
+    last_prompt: String, // Prompt we last answered, so a re-read doesn't count as new
+}
+
+/// Prompt text ends like a question/input request ("Name:", "known?", "login>")
+fn looks_like_prompt(p: &str) -> bool {
+    p.ends_with(':') || p.ends_with('?') || p.ends_with('>')
+}
+
+fn is_password_prompt(p: &str) -> bool {
+    looks_like_prompt(p) && (p.contains("password") || p.contains("passphrase"))
+}
+
+fn is_user_prompt(p: &str) -> bool {
+    const WORDS: &[&str] = &["login", "user", "account", "name", "character"];
+    looks_like_prompt(p) && WORDS.iter().any(|w| p.contains(w))
+}
+
+impl AutoLogin {
+    pub fn new(mud: &Mud) -> Self {
+        Self {
+            commands: mud.commands.trim().to_string(),
+            user: mud.user.clone(),
+            password: mud.password.clone(),
+            // Nothing configured: nothing to wait for
+            done: mud.commands.trim().is_empty() && mud.user.is_empty() && mud.password.is_empty(),
+            ..Default::default()
+        }
+    }
+
+    /// Socket reached Connected - start watching prompts
+    pub fn connected(&mut self) {
+        self.connected = true;
+    }
+
+    /// Still has something left to send
+    pub fn is_active(&self) -> bool {
+        !self.done
+    }
+
+    fn credentials_pending(&self) -> bool {
+        (!self.user.is_empty() && !self.user_sent)
+            || (!self.password.is_empty() && !self.password_sent)
+    }
+
+    /// Feed the current prompt (incomplete line or GA/EOR prompt)
+    pub fn on_prompt(&mut self, prompt: &str) -> Option<LoginStep> {
+        if self.done || !self.connected {
+            return None;
+        }
+        let p = prompt.trim().to_lowercase();
+        if p.is_empty() || p == self.last_prompt {
+            return None;
+        }
+
+        if !self.password.is_empty() && !self.password_sent && is_password_prompt(&p) {
+            self.password_sent = true;
+            self.last_prompt = p;
+            return Some(LoginStep::Send(self.password.clone()));
+        }
+        if !self.user.is_empty() && !self.user_sent && is_user_prompt(&p) {
+            self.user_sent = true;
+            self.last_prompt = p;
+            return Some(LoginStep::Send(self.user.clone()));
+        }
+        if self.credentials_pending() {
+            return None; // Keep waiting for the login prompt
+        }
+
+        self.done = true;
+        if self.commands.is_empty() {
+            None
+        } else {
+            Some(LoginStep::Commands(self.commands.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mud(commands: &str, user: &str, password: &str) -> Mud {
+        let mut m = Mud::new("Test", "127.0.0.1", 4000);
+        m.commands = commands.to_string();
+        m.user = user.to_string();
+        m.password = password.to_string();
+        m
+    }
+
+    #[test]
+    fn nothing_configured_is_inactive() {
+        let mut login = AutoLogin::new(&mud("", "", ""));
+        login.connected();
+        assert!(!login.is_active());
+        assert_eq!(login.on_prompt("> "), None);
+    }
+
+    #[test]
+    fn commands_wait_for_connect_and_first_prompt() {
+        let mut login = AutoLogin::new(&mud("look;score", "", ""));
+        assert_eq!(login.on_prompt("> "), None);
+        login.connected();
+        assert_eq!(login.on_prompt("   "), None);
+        assert_eq!(
+            login.on_prompt("> "),
+            Some(LoginStep::Commands("look;score".to_string()))
+        );
+        assert!(!login.is_active());
+        assert_eq!(login.on_prompt("> "), None);
+    }
+
+    #[test]
+    fn credentials_then_commands() {
+        let mut login = AutoLogin::new(&mud("look", "bob", "secret"));
+        login.connected();
+        // Banner text doesn't count as a prompt while credentials are pending
+        assert_eq!(login.on_prompt("Welcome to the MUD"), None);
+        assert_eq!(
+            login.on_prompt("By what name do you wish to be known? "),
+            Some(LoginStep::Send("bob".to_string()))
+        );
+        // Same prompt seen again on the next read - not answered twice
+        assert_eq!(
+            login.on_prompt("By what name do you wish to be known? "),
+            None
+        );
+        assert_eq!(
+            login.on_prompt("Password: "),
+            Some(LoginStep::Send("secret".to_string()))
+        );
+        assert_eq!(login.on_prompt("Password: "), None);
+        assert_eq!(
+            login.on_prompt("[100hp 50mv] >"),
+            Some(LoginStep::Commands("look".to_string()))
+        );
+    }
+
+    #[test]
+    fn password_only() {
+        let mut login = AutoLogin::new(&mud("", "", "hunter2"));
+        login.connected();
+        assert_eq!(login.on_prompt("Login: "), None);
+        assert_eq!(
+            login.on_prompt("Enter your password:"),
+            Some(LoginStep::Send("hunter2".to_string()))
+        );
+        assert_eq!(login.on_prompt("> "), None);
+        assert!(!login.is_active());
+    }
+}
@@ -0,0 +1,69 @@
+// Bell - what to do when the MUD sends BEL (0x07)
+//
+// Session strips BEL out of the text so it never lands in scrollback as a
+// control character and queues the line it arrived on. The main loop then
+// beeps the terminal, flashes the status line or runs the sys/bell hook,
+// according to the MUD's `bell` setting.
+
+use std::time::Duration;
+
+/// How long a visual bell inverts the status line
+pub const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BellMode {
+    /// Pass BEL through to the terminal
+    #[default]
+    Beep,
+    /// Flash the status line
+    Visual,
+    /// Run sys/bell with the line the bell arrived on
+    Hook,
+    /// Drop it
+    Off,
+}
+
+impl BellMode {
+    /// `beep`, `visual`, `hook` or `off` (any case)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "beep" | "on" => Ok(BellMode::Beep),
+            "visual" | "flash" => Ok(BellMode::Visual),
+            "hook" => Ok(BellMode::Hook),
+            "off" | "none" => Ok(BellMode::Off),
+            _ => Err(format!(
+                "Unknown bell mode {} (use beep, visual, hook or off)",
+                name.trim()
+            )),
+        }
+    }
+
+    /// Name for the config and #bell
+    pub fn name(self) -> &'static str {
+        match self {
+            BellMode::Beep => "beep",
+            BellMode::Visual => "visual",
+            BellMode::Hook => "hook",
+            BellMode::Off => "off",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_name_round_trip() {
+        for mode in [
+            BellMode::Beep,
+            BellMode::Visual,
+            BellMode::Hook,
+            BellMode::Off,
+        ] {
+            assert_eq!(BellMode::parse(mode.name()), Ok(mode));
+        }
+        assert_eq!(BellMode::parse(" Flash "), Ok(BellMode::Visual));
+        assert!(BellMode::parse("loud").is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// Charset - Latin-1 / UTF-8 transcoding and telnet CHARSET (RFC 2066)
+//
+// Output cells hold one character each, a code point up to U+00FF (the
+// screen prints a cell as that character). Latin-1 bytes are that already;
+// UTF-8 sequences are decoded to one cell, `?` past U+00FF, and bytes that
+// aren't UTF-8 pass through as Latin-1. Typed text is encoded in the MUD's
+// charset on the way out. Servers that offer CHARSET get asked
+// nothing: when they send a REQUEST we accept UTF-8 if it's on their list,
+// else Latin-1, and switch to it.
+
+use crate::telnet::{subnegotiation, OptionHandler};
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+/// Telnet option for CHARSET
+pub const TELOPT_CHARSET: u8 = 42;
+const REQUEST: u8 = 1;
+const ACCEPTED: u8 = 2;
+const REJECTED: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    Latin1,
+}
+
+impl Charset {
+    /// `utf8`, `latin1` or the usual spellings of either (any case)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf8" | "utf-8" => Ok(Charset::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" | "iso_8859-1" => Ok(Charset::Latin1),
+            _ => Err(format!(
+                "Unknown charset {} (use utf8 or latin1)",
+                name.trim()
+            )),
+        }
+    }
+
+    /// Name for the config and #charset
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf8",
+            Charset::Latin1 => "latin1",
+        }
+    }
+
+    /// Name sent in a CHARSET ACCEPTED
+    fn telnet_name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    /// Bytes from the MUD as output cells, one per character. A UTF-8
+    /// sequence cut off at the end of `data` waits in `pending` for the
+    /// next read
+    pub fn decode<'a>(self, data: &'a [u8], pending: &mut Vec<u8>) -> Cow<'a, [u8]> {
+        if self == Charset::Latin1 || (pending.is_empty() && data.is_ascii()) {
+            return Cow::Borrowed(data);
+        }
+        let mut input = std::mem::take(pending);
+        input.extend_from_slice(data);
+        let mut out = Vec::with_capacity(input.len());
+        let mut i = 0;
+        while i < input.len() {
+            let len = match input[i] {
+                0xc2..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf4 => 4,
+                _ => 1,
+            };
+            if len > 1 && i + len > input.len() {
+                // Could still be UTF-8 once the rest arrives
+                let tail = &input[i..];
+                if std::str::from_utf8(tail)
+                    .err()
+                    .is_some_and(|e| e.error_len().is_none())
+                {
+                    *pending = tail.to_vec();
+                    break;
+                }
+            }
+            match input
+                .get(i..i + len)
+                .and_then(|s| std::str::from_utf8(s).ok())
+            {
+                Some(s) if len > 1 => {
+                    let c = s.chars().next().unwrap_or('?');
+                    out.push(u8::try_from(u32::from(c)).unwrap_or(b'?'));
+                    i += len;
+                }
+                _ => {
+                    out.push(input[i]);
+                    i += 1;
+                }
+            }
+        }
+        Cow::Owned(out)
+    }
+
+    /// Typed text for the MUD: characters Latin-1 can't hold become `?`
+    pub fn encode(self, text: &str) -> Cow<'_, [u8]> {
+        match self {
+            Charset::Latin1 if !text.is_ascii() => Cow::Owned(
+                text.chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                    .collect(),
+            ),
+            _ => Cow::Borrowed(text.as_bytes()),
+        }
+    }
+}
+
+/// The charset in use, shared between the CHARSET handler and Session
+pub type SharedCharset = Arc<Mutex<Charset>>;
+
+/// Answers CHARSET REQUEST and switches the shared charset to what it accepted
+pub struct CharsetNegotiation {
+    charset: SharedCharset,
+}
+
+impl CharsetNegotiation {
+    pub fn new(charset: SharedCharset) -> Self {
+        Self { charset }
+    }
+}
+
+impl OptionHandler for CharsetNegotiation {
+    fn subnegotiation(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let Some((&REQUEST, mut list)) = data.split_first() else {
+            return;
+        };
+        // "[TTABLE]" and a version byte may come first (we send no tables)
+        if let Some(rest) = list.strip_prefix(b"[TTABLE]") {
+            list = rest.get(1..).unwrap_or(&[]);
+        }
+        let Some((&sep, names)) = list.split_first() else {
+            return;
+        };
+        let offered: Vec<Charset> = names
+            .split(|&b| b == sep)
+            .filter_map(|name| Charset::parse(&String::from_utf8_lossy(name)).ok())
+            .collect();
+        let choice = [Charset::Utf8, Charset::Latin1]
+            .into_iter()
+            .find(|c| offered.contains(c));
+        match choice {
+            Some(charset) => {
+                if let Ok(mut current) = self.charset.lock() {
+                    *current = charset;
+                }
+                let mut reply = vec![ACCEPTED];
+                reply.extend_from_slice(charset.telnet_name().as_bytes());
+                out.extend(subnegotiation(TELOPT_CHARSET, &reply));
+            }
+            None => out.extend(subnegotiation(TELOPT_CHARSET, &[REJECTED])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telnet::telnet::*;
+    use crate::telnet::{Side, TelnetParser};
+
+    #[test]
+    fn decoded_to_one_cell_per_character() {
+        let mut pending = Vec::new();
+        let latin1 = Charset::parse("ISO-8859-1").unwrap();
+        assert_eq!(latin1, Charset::Latin1);
+        assert_eq!(
+            &*latin1.decode(b"caf\xe9 \x1b[1m\xa3", &mut pending),
+            b"caf\xe9 \x1b[1m\xa3"
+        );
+        assert_eq!(&*latin1.encode("café ☃"), b"caf\xe9 ?");
+
+        let utf8 = Charset::Utf8;
+        assert_eq!(
+            &*utf8.decode("café ☃".as_bytes(), &mut pending),
+            b"caf\xe9 ?"
+        );
+        // Not UTF-8: taken as Latin-1
+        assert_eq!(&*utf8.decode(b"caf\xe9!", &mut pending), b"caf\xe9!");
+        // A sequence split between reads
+        assert_eq!(&*utf8.decode(b"caf\xc3", &mut pending), b"caf");
+        assert_eq!(pending, b"\xc3");
+        assert_eq!(&*utf8.decode(b"\xa9s", &mut pending), b"\xe9s");
+        assert!(pending.is_empty());
+        assert_eq!(&*utf8.encode("café"), "café".as_bytes());
+        assert!(Charset::parse("koi8-r").is_err());
+    }
+
+    #[test]
+    fn request_accepts_utf8_first_then_latin1() {
+        let charset = SharedCharset::default();
+        let mut p = TelnetParser::new();
+        p.support(TELOPT_CHARSET, Side::Us, true);
+        p.register(
+            TELOPT_CHARSET,
+            Box::new(CharsetNegotiation::new(charset.clone())),
+        );
+        p.feed(&[IAC, DO, TELOPT_CHARSET]);
+        assert_eq!(p.take_responses(), vec![IAC, WILL, TELOPT_CHARSET]);
+
+        let mut request = vec![IAC, SB, TELOPT_CHARSET, REQUEST];
+        request.extend_from_slice(b";ISO-8859-1;KOI8-R");
+        request.extend_from_slice(&[IAC, SE]);
+        p.feed(&request);
+        let mut reply = vec![IAC, SB, TELOPT_CHARSET, ACCEPTED];
+        reply.extend_from_slice(b"ISO-8859-1");
+        reply.extend_from_slice(&[IAC, SE]);
+        assert_eq!(p.take_responses(), reply);
+        assert_eq!(*charset.lock().unwrap(), Charset::Latin1);
+
+        let mut request = vec![IAC, SB, TELOPT_CHARSET, REQUEST];
+        request.extend_from_slice(b"[TTABLE]\x01 latin1 UTF-8");
+        request.extend_from_slice(&[IAC, SE]);
+        p.feed(&request);
+        assert!(p.take_responses().ends_with(b"UTF-8\xff\xf0"));
+        assert_eq!(*charset.lock().unwrap(), Charset::Utf8);
+
+        let mut request = vec![IAC, SB, TELOPT_CHARSET, REQUEST];
+        request.extend_from_slice(b" BIG5");
+        request.extend_from_slice(&[IAC, SE]);
+        p.feed(&request);
+        assert_eq!(
+            p.take_responses(),
+            vec![IAC, SB, TELOPT_CHARSET, REJECTED, IAC, SE]
+        );
+    }
+}
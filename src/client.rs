@@ -0,0 +1,246 @@
+// Client - okros as a library
+//
+// What a program embedding okros needs (a chat bridge, a bot, a test
+// harness): connect to a MUD, get its lines and prompts as events, send
+// commands and register triggers. It drives a SessionEngine the way the TTY
+// and control-server front-ends do, with no screen.
+//
+//     let mut client = Client::connect_to("mud.example.org:4000")?;
+//     client.on_match("^You are hungry", |_| vec!["eat bread".into()])?;
+//     client.send("look")?;
+//     for event in client.events() {
+//         if let ClientEvent::Line(text) = event {
+//             println!("{}", text);
+//         }
+//     }
+
+use crate::engine::SessionEngine;
+use crate::mccp::PassthroughDecomp;
+use crate::mud::Mud;
+use crate::select::{poll_fds, READ, WRITE};
+use crate::session::SessionEvent;
+use crate::socket::{self, ConnState, Socket};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long connect() waits for the MUD to answer
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something the MUD sent (or did)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// A completed line, ANSI colors stripped
+    Line(String),
+    /// A GA/EOR-terminated prompt
+    Prompt(String),
+    /// The MUD closed the connection; nothing follows
+    Disconnected,
+}
+
+/// Trigger callback: gets the matching line, returns commands to send
+pub type MatchCallback = Box<dyn FnMut(&str) -> Vec<String> + Send>;
+
+type Triggers = Arc<Mutex<Vec<(Regex, MatchCallback)>>>;
+
+pub struct Client {
+    engine: SessionEngine<PassthroughDecomp>,
+    sock: Option<Socket>,
+    triggers: Triggers,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    /// Connect to a MUD from the config file (its hostname and port)
+    pub fn connect(mud: &Mud) -> Result<Self, String> {
+        Self::connect_host(&mud.hostname, mud.port)
+    }
+
+    /// Connect to `host:port` (`[v6]:port` for IPv6 literals)
+    pub fn connect_to(addr: &str) -> Result<Self, String> {
+        let (host, port) =
+            socket::split_host_port(addr).ok_or_else(|| format!("{}: expected host:port", addr))?;
+        Self::connect_host(host, port)
+    }
+
+    /// Resolve and connect, waiting up to CONNECT_TIMEOUT
+    pub fn connect_host(host: &str, port: u16) -> Result<Self, String> {
+        let err = |e: std::io::Error| format!("{}:{}: {}", host, port, e);
+        let addrs = socket::resolve(host, port).map_err(err)?;
+        let mut sock = Socket::new().map_err(err)?;
+        sock.connect_any(addrs).map_err(err)?;
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        while sock.state == ConnState::Connecting {
+            if Instant::now() >= deadline {
+                return Err(format!("{}:{}: connection timed out", host, port));
+            }
+            let ready = poll_fds(&[(sock.as_raw_fd(), WRITE)], 50).map_err(err)?;
+            if !ready.is_empty() {
+                sock.on_writable().map_err(err)?;
+            }
+            sock.tick();
+        }
+        match sock.state {
+            ConnState::Connected => Ok(Self::from_socket(sock)),
+            _ => Err(format!("{}:{}: connection failed", host, port)),
+        }
+    }
+
+    /// Wrap an already-connected socket
+    pub fn from_socket(sock: Socket) -> Self {
+        let mut engine = SessionEngine::new(PassthroughDecomp::new(), 80, 24, 2000);
+        engine.session.set_event_recording(true);
+        let triggers: Triggers = Arc::default();
+        let matching = triggers.clone();
+        engine
+            .session
+            .set_trigger_callback(Box::new(move |line: &str| {
+                let mut triggers = matching.lock().unwrap();
+                let hits = triggers.iter_mut().filter(|(re, _)| re.is_match(line));
+                hits.flat_map(|(_, callback)| callback(line)).collect()
+            }));
+        Self {
+            engine,
+            sock: Some(sock),
+            triggers,
+            buf: vec![0; 4096],
+        }
+    }
+
+    /// The session underneath: timers, variables, send rate, scrollback
+    pub fn engine(&mut self) -> &mut SessionEngine<PassthroughDecomp> {
+        &mut self.engine
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.sock.is_some()
+    }
+
+    /// Send a command line (paced by the send rate, see engine().sends)
+    pub fn send(&mut self, line: &str) -> Result<(), String> {
+        if self.sock.is_none() {
+            return Err("not connected".to_string());
+        }
+        self.engine.queue_send(line);
+        self.flush();
+        Ok(())
+    }
+
+    /// Run `callback` on every line matching the regex `pattern`; the
+    /// commands it returns are sent
+    pub fn on_match(
+        &mut self,
+        pattern: &str,
+        callback: impl FnMut(&str) -> Vec<String> + Send + 'static,
+    ) -> Result<(), String> {
+        let re = Regex::new(pattern).map_err(|e| e.to_string())?;
+        self.triggers.lock().unwrap().push((re, Box::new(callback)));
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the MUD, returning what it sent. Due timers
+    /// and paced sends go out meanwhile
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<ClientEvent>, String> {
+        let Some(fd) = self.sock.as_ref().map(|s| s.as_raw_fd()) else {
+            return Err("not connected".to_string());
+        };
+        let now = Instant::now();
+        for line in self.engine.run_timers(now) {
+            self.engine.queue_send(&line);
+        }
+        self.flush();
+        let max_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        let ms = self.engine.poll_timeout(now, max_ms);
+        let ready = poll_fds(&[(fd, READ)], ms).map_err(|e| e.to_string())?;
+        let mut closed = false;
+        if !ready.is_empty() {
+            let n = unsafe { libc::read(fd, self.buf.as_mut_ptr() as *mut _, self.buf.len()) };
+            let again =
+                n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::WouldBlock;
+            if n > 0 {
+                let data = &self.buf[..n as usize];
+                self.engine.session.record_read(data.len());
+                self.engine.feed_inbound(data);
+                let replies = self.engine.session.take_telnet_responses();
+                self.write(&replies);
+            } else if !again {
+                closed = true;
+            }
+        }
+        let mut events = Vec::new();
+        for ev in self.engine.session.take_events() {
+            match ev {
+                SessionEvent::Line(text) => events.push(ClientEvent::Line(text)),
+                SessionEvent::Prompt(text) => events.push(ClientEvent::Prompt(text)),
+                SessionEvent::TriggerFired { commands, .. } => {
+                    for command in commands {
+                        self.engine.queue_send(&command);
+                    }
+                }
+            }
+        }
+        if closed {
+            self.close();
+            events.push(ClientEvent::Disconnected);
+        } else {
+            self.flush();
+        }
+        Ok(events)
+    }
+
+    /// Events as they arrive, blocking; ends after Disconnected
+    pub fn events(&mut self) -> Events<'_> {
+        Events {
+            client: self,
+            queued: VecDeque::new(),
+        }
+    }
+
+    /// Drop the connection (and any commands still waiting to be sent)
+    pub fn close(&mut self) {
+        self.sock = None;
+        self.engine.clear_sends();
+    }
+
+    /// Write the sends that are due
+    fn flush(&mut self) {
+        for line in self.engine.take_sends(Instant::now()) {
+            let data = crate::telnet::escape_iac(&self.engine.session.encode_line(&line));
+            self.write(&data);
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let Some(sock) = &self.sock else {
+            return;
+        };
+        if data.is_empty() {
+            return;
+        }
+        let n = unsafe { libc::write(sock.as_raw_fd(), data.as_ptr() as *const _, data.len()) };
+        if n > 0 {
+            self.engine.session.record_written(n as usize);
+        }
+    }
+}
+
+/// Blocking iterator over a Client's events (Client::events)
+pub struct Events<'a> {
+    client: &'a mut Client,
+    queued: VecDeque<ClientEvent>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = ClientEvent;
+
+    fn next(&mut self) -> Option<ClientEvent> {
+        loop {
+            if let Some(ev) = self.queued.pop_front() {
+                return Some(ev);
+            }
+            self.queued
+                .extend(self.client.poll(Duration::from_millis(250)).ok()?);
+        }
+    }
+}
@@ -23,3 +23,251 @@ impl Color {
     pub const CYAN: Self = Self(6);
     pub const WHITE: Self = Self(7);
 }
+
+/// Packed attribute byte for bold foreground (matches screen.rs FG_BOLD)
+pub const FG_BOLD: u8 = 1 << 7;
+
+/// Text attributes, the byte above the color byte in MUD output's color
+/// word (bits 16-23 of a cell).
+pub const UNDERLINE: u16 = 1 << 8;
+pub const ITALIC: u16 = 1 << 9;
+pub const REVERSE: u16 = 1 << 10;
+pub const BLINK: u16 = 1 << 11;
+/// Part of a URL found in the line (url::find_urls); the OSC 8 target is
+/// the text of the run of LINK cells
+pub const LINK: u16 = 1 << 12;
+
+/// Map a color name to the internal (curses-ordered) color index used in
+/// packed attribute bytes - same ordering AnsiConverter produces
+fn color_index(name: &str) -> Option<u8> {
+    match name {
+        "black" => Some(0),
+        "blue" => Some(1),
+        "green" => Some(2),
+        "cyan" => Some(3),
+        "red" => Some(4),
+        "magenta" => Some(5),
+        "yellow" => Some(6),
+        "white" => Some(7),
+        _ => None,
+    }
+}
+
+/// Parse a color spec into a packed attribute byte (bg << 4 | fg, bit 7 = bold)
+///
+/// Accepts `[bold] <fg> [on <bg>]` (e.g. "red", "bold yellow", "white on blue")
+/// or a raw byte in hex ("0x8c") for scripts that already know the encoding.
+pub fn parse_color_spec(spec: &str) -> Option<u8> {
+    let spec = spec.trim().to_lowercase();
+    if let Some(hex) = spec.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).ok();
+    }
+
+    let mut words = spec.split_whitespace().peekable();
+    let mut color = 0u8;
+    if words.peek() == Some(&"bold") {
+        color |= FG_BOLD;
+        words.next();
+    }
+    color |= color_index(words.next()?)?;
+    match (words.next(), words.next()) {
+        (None, _) => {}
+        (Some("on"), Some(bg)) => color |= color_index(bg)? << 4,
+        _ => return None,
+    }
+    if words.next().is_some() {
+        return None;
+    }
+    Some(color)
+}
+
+/// Input line default: white on blue (C++ main.cc:73)
+pub const INPUT_COLOR: u8 = 0x17;
+/// Status line and output default: white on black (C++ main.cc:76)
+pub const STATUS_COLOR: u8 = 0x07;
+pub const OUTPUT_COLOR: u8 = 0x07;
+/// Commands echoed into the output (echoinput): bold cyan
+pub const ECHO_COLOR: u8 = FG_BOLD | 3;
+/// Client diagnostics in the output (refused telnet options, MCCP errors): yellow
+pub const DIAG_COLOR: u8 = 6;
+/// Client messages by level (Session::client_msg): green, bold yellow, bold red
+pub const INFO_COLOR: u8 = 2;
+pub const WARNING_COLOR: u8 = FG_BOLD | 6;
+pub const ERROR_COLOR: u8 = FG_BOLD | 4;
+
+/// Names accepted by `color <item> <spec>` and `#color`
+pub const THEME_ITEMS: [&str; 9] = [
+    "input",
+    "status",
+    "output",
+    "highlight",
+    "echo",
+    "diagnostics",
+    "info",
+    "warning",
+    "error",
+];
+
+/// UI colors set in the config (globally or per MUD) or with `#color`
+/// Unset slots fall back to the inherited theme, then the built-in defaults
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColorTheme {
+    pub input: Option<u8>,     // Input line
+    pub status: Option<u8>,    // Status line
+    pub output: Option<u8>,    // Default fg/bg of MUD output
+    pub highlight: Option<u8>, // Scrollback search match (unset = inverse video)
+    pub echo: Option<u8>,      // Sent commands echoed into the output
+    pub diag: Option<u8>,      // Client diagnostics printed into the output
+    pub info: Option<u8>,      // Client messages, by level
+    pub warning: Option<u8>,
+    pub error: Option<u8>,
+}
+
+impl ColorTheme {
+    pub fn input_color(&self) -> u8 {
+        self.input.unwrap_or(INPUT_COLOR)
+    }
+
+    pub fn status_color(&self) -> u8 {
+        self.status.unwrap_or(STATUS_COLOR)
+    }
+
+    pub fn output_color(&self) -> u8 {
+        self.output.unwrap_or(OUTPUT_COLOR)
+    }
+
+    pub fn echo_color(&self) -> u8 {
+        self.echo.unwrap_or(ECHO_COLOR)
+    }
+
+    pub fn diag_color(&self) -> u8 {
+        self.diag.unwrap_or(DIAG_COLOR)
+    }
+
+    /// Info, warning and error message colors (Session::set_msg_colors)
+    pub fn msg_colors(&self) -> [u8; 3] {
+        [
+            self.info.unwrap_or(INFO_COLOR),
+            self.warning.unwrap_or(WARNING_COLOR),
+            self.error.unwrap_or(ERROR_COLOR),
+        ]
+    }
+
+    fn slot(&mut self, item: &str) -> Option<&mut Option<u8>> {
+        match item.to_lowercase().as_str() {
+            "input" | "inputline" => Some(&mut self.input),
+            "status" | "statusline" => Some(&mut self.status),
+            "output" | "default" => Some(&mut self.output),
+            "highlight" => Some(&mut self.highlight),
+            "echo" => Some(&mut self.echo),
+            "diagnostics" | "diag" => Some(&mut self.diag),
+            "info" => Some(&mut self.info),
+            "warning" => Some(&mut self.warning),
+            "error" => Some(&mut self.error),
+            _ => None,
+        }
+    }
+
+    /// Set `item` from a color spec; "default" unsets it
+    pub fn set(&mut self, item: &str, spec: &str) -> Result<(), String> {
+        let color = if spec.trim().eq_ignore_ascii_case("default") {
+            None
+        } else {
+            Some(parse_color_spec(spec).ok_or_else(|| format!("Invalid color: {}", spec.trim()))?)
+        };
+        let slot = self.slot(item).ok_or_else(|| {
+            format!(
+                "Unknown color item: {} (one of {})",
+                item,
+                THEME_ITEMS.join(", ")
+            )
+        })?;
+        *slot = color;
+        Ok(())
+    }
+
+    /// `over`'s set slots on top of this theme
+    pub fn overlay(&self, over: &ColorTheme) -> ColorTheme {
+        ColorTheme {
+            input: over.input.or(self.input),
+            status: over.status.or(self.status),
+            output: over.output.or(self.output),
+            highlight: over.highlight.or(self.highlight),
+            echo: over.echo.or(self.echo),
+            diag: over.diag.or(self.diag),
+            info: over.info.or(self.info),
+            warning: over.warning.or(self.warning),
+            error: over.error.or(self.error),
+        }
+    }
+
+    /// Slots that are set, in THEME_ITEMS order (for writing the config)
+    pub fn entries(&self) -> Vec<(&'static str, u8)> {
+        THEME_ITEMS
+            .iter()
+            .zip([
+                self.input,
+                self.status,
+                self.output,
+                self.highlight,
+                self.echo,
+                self.diag,
+                self.info,
+                self.warning,
+                self.error,
+            ])
+            .filter_map(|(name, color)| color.map(|c| (*name, c)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_simple_and_compound_specs() {
+        assert_eq!(parse_color_spec("red"), Some(0x04));
+        assert_eq!(parse_color_spec("bold yellow"), Some(0x86));
+        assert_eq!(parse_color_spec("white on blue"), Some(0x17));
+        assert_eq!(parse_color_spec("Bold Green on Black"), Some(0x82));
+        assert_eq!(parse_color_spec("0x8c"), Some(0x8c));
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert_eq!(parse_color_spec(""), None);
+        assert_eq!(parse_color_spec("purple"), None);
+        assert_eq!(parse_color_spec("red on"), None);
+        assert_eq!(parse_color_spec("red blue"), None);
+        assert_eq!(parse_color_spec("red on blue extra"), None);
+    }
+
+    #[test]
+    fn theme_set_and_overlay() {
+        let mut global = ColorTheme::default();
+        global.set("input", "white on red").unwrap();
+        global.set("statusline", "bold yellow on blue").unwrap();
+        assert!(global.set("border", "red").is_err());
+        assert!(global.set("input", "purple").is_err());
+
+        let mut mud = ColorTheme::default();
+        mud.set("input", "0x70").unwrap();
+        mud.set("output", "green").unwrap();
+        let theme = global.overlay(&mud);
+        assert_eq!(theme.input_color(), 0x70);
+        assert_eq!(theme.status_color(), 0x96);
+        assert_eq!(theme.output_color(), 0x02);
+        assert_eq!(theme.echo_color(), ECHO_COLOR);
+        assert_eq!(theme.highlight, None);
+        assert_eq!(theme.entries().len(), 3);
+        assert_eq!(theme.msg_colors(), [INFO_COLOR, WARNING_COLOR, ERROR_COLOR]);
+        mud.set("error", "white on red").unwrap();
+        assert_eq!(global.overlay(&mud).msg_colors()[2], 0x47);
+        mud.set("error", "default").unwrap();
+
+        mud.set("input", "default").unwrap();
+        assert_eq!(global.overlay(&mud).input_color(), 0x47);
+        assert_eq!(ColorTheme::default().input_color(), INPUT_COLOR);
+    }
+}
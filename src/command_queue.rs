@@ -5,6 +5,8 @@
 // C++ pattern: Interpreter class with command queue and expansion logic
 // Rust pattern: CommandQueue struct with expansion methods
 
+use crate::plugins::stack::Interpreter;
+use crate::variables::Variables;
 use chrono::{Datelike, Timelike}; // For day(), month(), hour(), minute(), etc.
 
 /// Session context for variable expansion
@@ -21,10 +23,25 @@ pub const EXPAND_VARIABLES: u32 = 0x01;
 pub const EXPAND_ALIASES: u32 = 0x02;
 pub const EXPAND_SEMICOLON: u32 = 0x04;
 pub const EXPAND_SPEEDWALK: u32 = 0x08;
+/// #var references only ($name, %{name}); EXPAND_VARIABLES includes them
+pub const EXPAND_USER_VARIABLES: u32 = 0x10;
 pub const EXPAND_ALL: u32 = 0xffff;
 
-/// Default flags for entry from the input line (C++ line 12)
-pub const EXPAND_INPUT: u32 = EXPAND_ALIASES | EXPAND_SPEEDWALK;
+/// Default flags for entry from the input line (C++ line 12, plus #var
+/// references)
+pub const EXPAND_INPUT: u32 = EXPAND_ALIASES | EXPAND_SPEEDWALK | EXPAND_USER_VARIABLES;
+
+/// sys/send hook (C++ Interpreter::execute line 68): the script gets each
+/// command on its way to the MUD and returns it, rewritten as it likes;
+/// "" or okros.gag() cancels it. None if cancelled
+pub fn send_hook(interp: &mut dyn Interpreter, line: &str) -> Option<String> {
+    let mut out = String::new();
+    let ran = interp.run_quietly("sys/send", line, &mut out, true);
+    if interp.take_gag() || (ran && out.is_empty()) {
+        return None;
+    }
+    Some(if ran { out } else { line.to_string() })
+}
 
 /// Command queue interpreter (C++ Interpreter class, Interpreter.cc:15, 49-79)
 pub struct CommandQueue {
@@ -32,6 +49,7 @@ pub struct CommandQueue {
     command_character: char,
     speedwalk_enabled: bool,
     speedwalk_character: char,
+    pub variables: Variables, // #var
 }
 
 impl CommandQueue {
@@ -41,6 +59,7 @@ impl CommandQueue {
             command_character: '#',
             speedwalk_enabled: true,  // C++ opt_speedwalk default
             speedwalk_character: '/', // C++ opt_speedwalk_character default
+            variables: Variables::new(),
         }
     }
 
@@ -72,7 +91,17 @@ impl CommandQueue {
         // Expansion pipeline (C++ lines 247-273)
         if flags & EXPAND_VARIABLES != 0 {
             let expanded = self.expand_variables(s, session);
-            self.add_with_context(&expanded, flags & !EXPAND_VARIABLES, back, session, mud);
+            let flags = flags & !(EXPAND_VARIABLES | EXPAND_USER_VARIABLES);
+            self.add_with_context(&expanded, flags, back, session, mud);
+        } else if flags & EXPAND_USER_VARIABLES != 0 {
+            // Typed #commands keep theirs for later (#alias x kill $target)
+            let expanded = if s.starts_with(self.command_character) {
+                s.to_string()
+            } else {
+                self.variables.expand(s)
+            };
+            let flags = flags & !EXPAND_USER_VARIABLES;
+            self.add_with_context(&expanded, flags, back, session, mud);
         } else if flags & EXPAND_ALIASES != 0 {
             self.expand_aliases(s, flags, session, mud);
         } else if flags & EXPAND_SPEEDWALK != 0 {
@@ -106,7 +135,8 @@ impl CommandQueue {
                 break;
             }
 
-            // TODO: Call sys/send hook (C++ line 68)
+            // sys/send (C++ line 68) runs where commands leave for the MUD,
+            // see send_hook()
 
             // MCL command vs MUD command (C++ lines 71-77)
             if line.starts_with(self.command_character) {
@@ -121,6 +151,12 @@ impl CommandQueue {
         result
     }
 
+    /// Like execute(), but MCL command lines are returned too, in order,
+    /// for a caller that interprets them (C++ mclCommand lives in main)
+    pub fn execute_all(&mut self) -> Vec<String> {
+        self.commands.drain(..).collect()
+    }
+
     pub fn set_command_character(&mut self, c: char) {
         self.command_character = c;
     }
@@ -139,14 +175,19 @@ impl CommandQueue {
     ) {
         if s.contains(';') {
             let mut current = String::new();
-            let mut chars = s.chars().peekable();
+            let paired = paired_braces(s);
+            let mut chars = s.chars().enumerate().peekable();
+            let mut depth = 0; // Inside { } nothing is split (tintin style)
 
-            while let Some(ch) = chars.next() {
-                if ch == '\\' && chars.peek() == Some(&';') {
+            while let Some((i, ch)) = chars.next() {
+                if ch == '\\' && chars.peek().map(|&(_, c)| c) == Some(';') {
                     // Escaped semicolon (C++ lines 286-288)
                     current.push(';');
                     chars.next(); // consume the ';'
-                } else if ch == ';' {
+                } else if paired[i] {
+                    depth = if ch == '{' { depth + 1 } else { depth - 1 };
+                    current.push(ch);
+                } else if ch == ';' && depth == 0 {
                     // Split here (C++ lines 289-312)
                     let trimmed = current.trim_end();
                     // Use back=false to maintain order, pass through flags minus SEMICOLON
@@ -242,19 +283,24 @@ impl CommandQueue {
     }
 
     /// Expand variable references (C++ Interpreter::expandVariables, lines 152-227)
-    /// Example: "%h" -> hostname, "%p" -> port, etc.
+    /// Example: "%h" -> hostname, "%p" -> port, etc.; also #var's $name/%{name}
     fn expand_variables(&self, s: &str, session: Option<&SessionContext>) -> String {
         // Quick check - no % means no variables (C++ lines 153-154)
-        if !s.contains('%') {
+        if !s.contains(['%', '$']) {
             return s.to_string();
         }
 
         let mut result = String::new();
-        let mut chars = s.chars().peekable();
+        let mut chars = s.char_indices().peekable();
 
-        while let Some(ch) = chars.next() {
+        while let Some((i, ch)) = chars.next() {
+            if let Some((value, len)) = self.variables.reference(&s[i..]) {
+                result.push_str(value);
+                while chars.next_if(|&(j, _)| j < i + len).is_some() {}
+                continue;
+            }
             if ch == '%' {
-                if let Some(&next_ch) = chars.peek() {
+                if let Some(&(_, next_ch)) = chars.peek() {
                     chars.next(); // consume next char
                     match next_ch {
                         // Session variables (C++ lines 168-186)
@@ -419,6 +465,52 @@ impl CommandQueue {
     }
 }
 
+/// For each char of `s`, whether it's a brace with a partner: an unclosed
+/// `{` (a smiley like `:-{`) or a stray `}` groups nothing
+fn paired_braces(s: &str) -> Vec<bool> {
+    let mut paired = vec![false; s.chars().count()];
+    let mut open = Vec::new();
+    for (i, ch) in s.chars().enumerate() {
+        match ch {
+            '{' => open.push(i),
+            '}' => {
+                if let Some(start) = open.pop() {
+                    paired[start] = true;
+                    paired[i] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    paired
+}
+
+/// `s` without one pair of braces around all of it: `{attack %1;kick %1}`
+/// groups an alias/action/macro body so its semicolons survive until it
+/// runs.
+pub fn strip_braces(s: &str) -> &str {
+    let s = s.trim();
+    let Some(inner) = s.strip_prefix('{').and_then(|r| r.strip_suffix('}')) else {
+        return s;
+    };
+    // "{a} {b}" is two groups, not one
+    let mut depth = 0;
+    for ch in inner.chars() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth == 0 => return s,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    // "{a {b}" has no closing brace for the first '{'
+    if depth == 0 {
+        inner
+    } else {
+        s
+    }
+}
+
 impl Default for CommandQueue {
     fn default() -> Self {
         Self::new()
@@ -441,6 +533,14 @@ mod tests {
         assert_eq!(cmds[1], "south");
     }
 
+    #[test]
+    fn execute_all_keeps_mcl_commands() {
+        let mut cq = CommandQueue::new();
+        cq.add("#bind f2 look;north", EXPAND_SEMICOLON, false);
+        assert_eq!(cq.execute_all(), vec!["#bind f2 look", "north"]);
+        assert!(cq.execute_all().is_empty());
+    }
+
     #[test]
     fn prevents_infinite_recursion() {
         let mut cq = CommandQueue::new();
@@ -477,6 +577,39 @@ mod tests {
         assert_eq!(cmds[0], "say hello;goodbye");
     }
 
+    #[test]
+    fn braces_keep_semicolons() {
+        let mut cq = CommandQueue::new();
+        cq.add(
+            "#alias kill {attack %1;kick %1};say {a;{b;c}};look",
+            EXPAND_SEMICOLON,
+            false,
+        );
+        assert_eq!(
+            cq.execute_all(),
+            vec!["#alias kill {attack %1;kick %1}", "say {a;{b;c}}", "look"]
+        );
+
+        // An unmatched '}' doesn't swallow later splits
+        cq.add("say }x;y", EXPAND_SEMICOLON, false);
+        assert_eq!(cq.execute_all(), vec!["say }x", "y"]);
+
+        // Nor does a '{' that never closes
+        cq.add("say :-{;look", EXPAND_SEMICOLON, false);
+        assert_eq!(cq.execute_all(), vec!["say :-{", "look"]);
+        cq.add("say :-{;say {a;b}", EXPAND_SEMICOLON, false);
+        assert_eq!(cq.execute_all(), vec!["say :-{", "say {a;b}"]);
+    }
+
+    #[test]
+    fn strip_braces_removes_one_outer_group() {
+        assert_eq!(strip_braces(" {attack %1;kick %1} "), "attack %1;kick %1");
+        assert_eq!(strip_braces("{{a};b}"), "{a};b");
+        assert_eq!(strip_braces("{a} {b}"), "{a} {b}");
+        assert_eq!(strip_braces("{a {b}"), "{a {b}");
+        assert_eq!(strip_braces("plain;text"), "plain;text");
+    }
+
     #[test]
     fn speedwalk_simple() {
         let mut cq = CommandQueue::new();
@@ -583,6 +716,26 @@ mod tests {
         assert!(valid_months.contains(&month));
     }
 
+    #[test]
+    fn user_variables_expand_in_commands() {
+        use crate::alias::Alias;
+        use crate::mud::Mud;
+
+        let mut cq = CommandQueue::new();
+        cq.variables.set("target", "orc").unwrap();
+        cq.add("kill $target", EXPAND_INPUT, false);
+        // Typed #commands keep theirs until they run
+        cq.add("#alias k kill $target", EXPAND_INPUT, false);
+        assert_eq!(cq.execute_all(), vec!["kill orc", "#alias k kill $target"]);
+
+        let mut mud = Mud::empty();
+        mud.alias_list
+            .push(Alias::new("k", "kill %{target};look at $target"));
+        cq.variables.set("target", "rat").unwrap();
+        cq.add_with_context("k", EXPAND_INPUT, false, None, Some(&mud));
+        assert_eq!(cq.execute_all(), vec!["kill rat", "look at rat"]);
+    }
+
     #[test]
     fn variable_expansion_with_session() {
         use super::SessionContext;
@@ -799,4 +952,22 @@ mod tests {
         assert_eq!(cmds.len(), 1);
         assert_eq!(cmds[0], "connect game.com 5000");
     }
+
+    #[test]
+    fn send_hook_rewrites_and_cancels() {
+        struct Hook;
+        impl Interpreter for Hook {
+            fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool {
+                match (function, arg) {
+                    ("sys/send", "n") => *out = "north".to_string(),
+                    ("sys/send", "quit") => out.clear(),
+                    _ => return false,
+                }
+                true
+            }
+        }
+        assert_eq!(send_hook(&mut Hook, "n").as_deref(), Some("north"));
+        assert_eq!(send_hook(&mut Hook, "look").as_deref(), Some("look"));
+        assert_eq!(send_hook(&mut Hook, "quit"), None);
+    }
 }
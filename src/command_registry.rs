@@ -0,0 +1,197 @@
+// CommandRegistry - the client's built-in #commands
+//
+// C++ MCL's Interpreter.cc tests the command name against each builtin in
+// turn. Here every builtin registers its name, a usage line and a one-line
+// summary, which is what #help prints. The registry splits "#name args" and
+// either runs the command's handler (commands that need nothing but the
+// context the registry is for, e.g. the engine's timers) or hands the name
+// back to the front-end that registered it (commands that touch the
+// screen, the config or the connection).
+
+/// #help pads usages up to this wide
+const USAGE_COLUMN: usize = 32;
+
+/// Runs a command: (context, name, arguments)
+pub type Handler<C> = fn(&mut C, &str, &str) -> Result<String, String>;
+
+pub struct Builtin<C> {
+    pub name: &'static str,      // Without the '#'
+    pub usage: &'static str,     // e.g. "#wait <seconds> <command>"
+    pub summary: &'static str,   // What it does, for #help
+    handler: Option<Handler<C>>, // None: the front-end runs it
+}
+
+/// What a typed line turned out to be
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch<'a> {
+    /// Not a #command: send it to the MUD
+    NotCommand,
+    /// Handled (#help, an unknown command, a command with a handler)
+    Done(Result<String, String>),
+    /// A front-end command: (name, arguments)
+    Frontend(&'static str, &'a str),
+}
+
+pub struct CommandRegistry<C> {
+    commands: Vec<Builtin<C>>, // Sorted by name
+}
+
+impl<C> Default for CommandRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> CommandRegistry<C> {
+    pub fn new() -> Self {
+        let mut registry = Self {
+            commands: Vec::new(),
+        };
+        registry.insert(Builtin {
+            name: "help",
+            usage: "#help [command]",
+            summary: "List the built-in commands, or describe one",
+            handler: None,
+        });
+        registry
+    }
+
+    fn insert(&mut self, builtin: Builtin<C>) {
+        match self.commands.binary_search_by(|b| b.name.cmp(builtin.name)) {
+            Ok(i) => self.commands[i] = builtin,
+            Err(i) => self.commands.insert(i, builtin),
+        }
+    }
+
+    /// Add (or replace) a command run by `handler`
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        summary: &'static str,
+        handler: Handler<C>,
+    ) {
+        self.insert(Builtin {
+            name,
+            usage,
+            summary,
+            handler: Some(handler),
+        });
+    }
+
+    /// Add (or replace) a command the front-end runs itself
+    pub fn register_frontend(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        summary: &'static str,
+    ) {
+        self.insert(Builtin {
+            name,
+            usage,
+            summary,
+            handler: None,
+        });
+    }
+
+    /// Command by name (with or without the '#')
+    pub fn find(&self, name: &str) -> Option<&Builtin<C>> {
+        let name = name.strip_prefix('#').unwrap_or(name);
+        self.commands
+            .binary_search_by(|b| b.name.cmp(name))
+            .ok()
+            .map(|i| &self.commands[i])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin<C>> {
+        self.commands.iter()
+    }
+
+    /// Split a typed line and run it on `ctx`, or say who handles it
+    pub fn dispatch<'a>(&self, ctx: &mut C, line: &'a str) -> Dispatch<'a> {
+        let Some(rest) = line.trim_start().strip_prefix('#') else {
+            return Dispatch::NotCommand;
+        };
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let args = args.trim();
+        match self.find(name) {
+            Some(b) if b.name == "help" => Dispatch::Done(self.help(args)),
+            Some(b) => match b.handler {
+                Some(handler) => Dispatch::Done(handler(ctx, b.name, args)),
+                None => Dispatch::Frontend(b.name, args),
+            },
+            None => Dispatch::Done(Err(format!("Unknown command #{} (see #help)", name))),
+        }
+    }
+
+    /// #help: every command's usage and summary, or just the one asked about
+    pub fn help(&self, topic: &str) -> Result<String, String> {
+        if !topic.is_empty() {
+            return match self.find(topic) {
+                Some(b) => Ok(format!("Usage: {} - {}", b.usage, b.summary)),
+                None => Err(format!(
+                    "No such command: #{}",
+                    topic.trim_start_matches('#')
+                )),
+            };
+        }
+        // Align the summaries, except after the odd very long usage
+        let width = self
+            .commands
+            .iter()
+            .map(|b| b.usage.len())
+            .filter(|&n| n <= USAGE_COLUMN)
+            .max()
+            .unwrap_or(0);
+        let mut text = String::from("Commands (#help <command> for one):");
+        for b in &self.commands {
+            text.push_str(&format!("\n  {:width$}  {}", b.usage, b.summary));
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(total: &mut usize, _name: &str, args: &str) -> Result<String, String> {
+        *total += args.len();
+        Ok(format!("total {}", total))
+    }
+
+    #[test]
+    fn dispatches_and_describes_commands() {
+        let mut reg: CommandRegistry<usize> = CommandRegistry::new();
+        reg.register("count", "#count <text>", "Count characters", count);
+        reg.register_frontend("quit", "#quit", "Leave");
+
+        let mut total = 0;
+        assert_eq!(reg.dispatch(&mut total, "look"), Dispatch::NotCommand);
+        assert_eq!(
+            reg.dispatch(&mut total, "#quit"),
+            Dispatch::Frontend("quit", "")
+        );
+        assert_eq!(
+            reg.dispatch(&mut total, "#count  abc "),
+            Dispatch::Done(Ok("total 3".into()))
+        );
+        assert_eq!(
+            reg.dispatch(&mut total, "#quitx"),
+            Dispatch::Done(Err("Unknown command #quitx (see #help)".into()))
+        );
+
+        let Dispatch::Done(Ok(listing)) = reg.dispatch(&mut total, "#help") else {
+            panic!("no help");
+        };
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "  #count <text>    Count characters");
+        assert_eq!(lines[3], "  #quit            Leave");
+        assert_eq!(
+            reg.help("#count").unwrap(),
+            "Usage: #count <text> - Count characters"
+        );
+        assert!(reg.help("nope").is_err());
+    }
+}
@@ -1,16 +1,292 @@
 use crate::action::{Action, ActionType};
 use crate::alias::Alias;
+use crate::command_queue::strip_braces;
+use crate::keybind::{KeyAction, KeyBindings};
+use crate::macro_def::Macro;
 use crate::mud::{Mud, MudList};
+use crate::plugins::stack::ScriptLimits;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub server: Option<(Ipv4Addr, u16)>,
+    pub server: Option<SocketAddr>,
     pub mud_list: MudList,
-    pub global_mud: Mud, // Global aliases/actions/macros
+    pub global_mud: Mud,             // Global aliases/actions/macros
+    pub timestamps: bool,            // Prefix completed output lines with HH:MM:SS
+    pub autoreload: bool,            // Reload edited files under ~/.okros/scripts
+    pub wordwrap: bool,              // Wrap long lines at word boundaries
+    pub stickyprompt: bool,          // Show GA/EOR prompts on the input line (default on)
+    pub diagnostics: bool,           // Show refused telnet options and MCCP errors (default on)
+    pub echoinput: bool,             // Echo commands sent to the MUD into the output
+    pub msp: bool,                   // Accept MSP and play sound triggers
+    pub atcp: bool,                  // Accept ATCP; messages go to sys/atcp
+    pub mxp: bool,                   // Accept MXP (its markup is stripped)
+    pub hyperlinks: bool,            // OSC 8 links on URLs in the output
+    pub msp_player: String,          // Player command template (%f file, %v volume, %l repeats)
+    pub ping_command: String,        // Sent by #ping instead of IAC AYT
+    pub sendrate: f64,               // Commands per second sent to the MUD (0 = no limit)
+    pub sendburst: usize,            // Commands sent back to back before pacing starts
+    pub scrollback: usize,           // Rows in the output scrollback (MUDs can override)
+    pub scrollback_spill: bool,      // Old scrollback lines go to a temp file, not away
+    pub scrollback_spill_max: u64,   // Spill file size (bytes) before its older half goes
+    pub histsize: usize,             // Input history lines kept per MUD (MUDs can override)
+    pub keybindings: KeyBindings,    // bind/unbind lines over the default hotkeys
+    pub script_limits: ScriptLimits, // scripttimeout/scriptbudget/scriptsandbox
+    pub connect_timeout: Option<std::time::Duration>, // Give up connecting after this (None: never)
+    pub output_budget: Option<usize>, // Bytes of MUD text shown per read (None: all of it)
+    pub more: Option<usize>,         // Pause output after this many lines without a key
+    pub status_time: std::time::Duration, // Info messages' time in the status line
+    pub trigger_depth: Option<usize>, // Trigger commands in a chain before it's stopped
+    pub trigger_echo_gag: bool,      // Gag the MUD's echoes of trigger commands
+}
+
+/// Parse a `histsize <lines>` value
+fn parse_histsize(value: &str, line_num: usize) -> Result<usize, String> {
+    value
+        .trim_end_matches(';')
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| format!("Line {}: histsize needs a number of lines", line_num))
+}
+
+/// Write a config file only its owner can read (0600, like the history
+/// file): it may hold auto-login passwords
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files; tighten an existing one first
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(text.as_bytes())
+}
+
+/// Parse a `scrollback <rows>` value
+fn parse_scrollback(value: &str, line_num: usize) -> Result<usize, String> {
+    value
+        .trim_end_matches(';')
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (crate::scrollback::MIN_LINES..=crate::scrollback::MAX_LINES).contains(n))
+        .ok_or_else(|| {
+            format!(
+                "Line {}: scrollback needs a number of rows ({} to {})",
+                line_num,
+                crate::scrollback::MIN_LINES,
+                crate::scrollback::MAX_LINES
+            )
+        })
+}
+
+/// Parse an on/off option value
+pub fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim_end_matches(';').to_ascii_lowercase().as_str() {
+        "on" | "yes" | "true" | "1" => Some(true),
+        "off" | "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Expand a leading `~` and `$VAR` / `${VAR}` in a path from the config or
+/// the command line; unset variables are left as written
+pub fn expand_path(s: &str) -> std::path::PathBuf {
+    let home = || std::env::var("HOME").ok();
+    let mut out = String::new();
+    let rest = match s.strip_prefix('~') {
+        Some(r) if r.is_empty() || r.starts_with('/') => match home() {
+            Some(h) => {
+                out.push_str(&h);
+                r
+            }
+            None => s,
+        },
+        _ => s,
+    };
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let (name, end) = if rest[i + 1..].starts_with('{') {
+            match rest[i + 2..].find('}') {
+                Some(len) => (&rest[i + 2..i + 2 + len], i + 3 + len),
+                None => ("", i + 1),
+            }
+        } else {
+            let len = rest[i + 1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len() - i - 1);
+            (&rest[i + 1..i + 1 + len], i + 1 + len)
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => {
+                out.push_str(&value);
+                while chars.peek().is_some_and(|&(j, _)| j < end) {
+                    chars.next();
+                }
+            }
+            None => out.push('$'),
+        }
+    }
+    std::path::PathBuf::from(out)
+}
+
+/// Set by --config: used instead of ~/.okros/config
+static CONFIG_PATH: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+
+/// Command-line flags that take a value (not positional arguments)
+const VALUE_FLAGS: [&str; 6] = [
+    "--config",
+    "--record",
+    "--replay",
+    "--speed",
+    "--restore",
+    "--area",
+];
+
+/// What to connect to at startup: `okros <mudname>` or `okros <host> <port>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupTarget {
+    Mud(String),
+    Address(String, u16),
+}
+
+impl StartupTarget {
+    /// The positional arguments (after the program name), if any
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut positional = Vec::new();
+        let mut rest = args.iter().skip(1);
+        while let Some(arg) = rest.next() {
+            if VALUE_FLAGS.contains(&arg.as_str()) {
+                rest.next();
+            } else if !arg.starts_with("--") {
+                positional.push(arg.as_str());
+            }
+        }
+        match positional[..] {
+            [] => Ok(None),
+            [name] => Ok(Some(StartupTarget::Mud(name.to_string()))),
+            [host, port] => port
+                .parse()
+                .map(|port| Some(StartupTarget::Address(host.to_string(), port)))
+                .map_err(|_| format!("Bad port '{}'", port)),
+            _ => Err("Usage: okros [mudname | host port] [--config FILE]".to_string()),
+        }
+    }
+}
+
+/// Text after the first `words` whitespace-separated words of a config line,
+/// with one trailing ';' terminator removed (the writer doubles a literal one)
+fn value_after(line: &str, words: usize) -> &str {
+    let mut rest = line.trim();
+    for _ in 0..words {
+        rest = rest
+            .split_once(char::is_whitespace)
+            .map(|(_, r)| r.trim_start())
+            .unwrap_or("");
+    }
+    rest.strip_suffix(';').unwrap_or(rest)
+}
+
+/// Inverse of value_after: protect a trailing ';' from being read as the terminator
+fn config_value(s: &str) -> String {
+    if s.ends_with(';') {
+        format!("{};", s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Quote an action pattern for Action::parse (patterns containing '"' can
+/// only be written unquoted)
+fn config_pattern(p: &str) -> String {
+    if p.contains('"') {
+        p.to_string()
+    } else {
+        format!("\"{}\"", p)
+    }
+}
+
+/// host/commands lines for a MUD block written by write_mud_entry
+fn mud_entry_lines(mud: &Mud, indent: &str) -> Vec<String> {
+    let mut lines = vec![format!("{}host {} {}", indent, mud.hostname, mud.port)];
+    if !mud.commands.is_empty() {
+        lines.push(format!(
+            "{}commands {}",
+            indent,
+            config_value(&mud.commands)
+        ));
+    }
+    lines
+}
+
+/// Lines of a MUD's entry in config text: a `MUD name {` block (true) or an
+/// old-format line (false)
+fn find_mud_entry(lines: &[String], name: &str) -> Option<(std::ops::Range<usize>, bool)> {
+    let mut i = 0;
+    while i < lines.len() {
+        let parts: Vec<&str> = lines[i].split_whitespace().collect();
+        if parts.len() >= 2 && parts[0].eq_ignore_ascii_case("mud") {
+            // The closing brace at the block's own depth (group blocks nest)
+            let mut depth = 0;
+            let end = (i + 1..lines.len()).find(|&j| {
+                let line = lines[j].trim();
+                if line.starts_with('}') {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                } else if line.ends_with('{') {
+                    depth += 1;
+                }
+                false
+            })?;
+            if parts[1].trim_end_matches('{') == name {
+                return Some((i..end + 1, true));
+            }
+            i = end + 1;
+            continue;
+        }
+        if parts.len() >= 3 && parts[0] == name && parts[2].parse::<u16>().is_ok() {
+            return Some((i..i + 1, false));
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Keywords accepted both inside MUD blocks and at top level (global MUD)
+const AUTOMATION_KEYWORDS: &[&str] = &[
+    "alias",
+    "action",
+    "subst",
+    "gag",
+    "highlight",
+    "macro",
+    "color",
+    "path",
+    "group",
+];
+
+/// Config files including each other deeper than this are an error (cycles)
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// `group <name> {`: the name, if the line opens a group block
+fn group_block_name(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("group")?.trim().strip_suffix('{')?;
+    let name = rest.trim();
+    (!name.is_empty() && !name.contains(char::is_whitespace)).then_some(name)
 }
 
 impl Config {
@@ -19,7 +295,83 @@ impl Config {
             server: None,
             mud_list: MudList::new(),
             global_mud: Mud::new("__global__", "", 0),
+            timestamps: false,
+            autoreload: false,
+            wordwrap: false,
+            stickyprompt: true,
+            diagnostics: true,
+            echoinput: false,
+            msp: false,
+            atcp: false,
+            mxp: false,
+            hyperlinks: false,
+            msp_player: String::new(),
+            ping_command: String::new(),
+            sendrate: 0.0,
+            sendburst: crate::send_queue::DEFAULT_BURST,
+            scrollback: crate::scrollback::DEFAULT_LINES,
+            scrollback_spill: false,
+            scrollback_spill_max: crate::line_store::DEFAULT_SPILL_LIMIT,
+            histsize: crate::history::DEFAULT_SIZE,
+            keybindings: KeyBindings::new(),
+            script_limits: ScriptLimits::default(),
+            connect_timeout: Some(crate::socket::CONNECT_TIMEOUT),
+            output_budget: None,
+            more: None,
+            status_time: crate::status_line::MESSAGE_TIME,
+            trigger_depth: Some(crate::trigger_guard::DEFAULT_DEPTH),
+            trigger_echo_gag: false,
+        }
+    }
+
+    /// Use `path` (--config) instead of ~/.okros/config; the autosave file,
+    /// scripts and sounds are then looked for next to it.
+    pub fn use_path(path: std::path::PathBuf) {
+        let _ = CONFIG_PATH.set(path);
+    }
+
+    /// --config FILE, if given
+    pub fn path_arg(args: &[String]) -> Result<Option<std::path::PathBuf>, String> {
+        match args.iter().position(|a| a == "--config") {
+            None => Ok(None),
+            Some(i) => args
+                .get(i + 1)
+                .map(|f| Some(expand_path(f)))
+                .ok_or_else(|| "--config needs a file name".to_string()),
+        }
+    }
+
+    /// Default config location: ~/.okros/config (or the --config file)
+    pub fn default_path() -> std::path::PathBuf {
+        if let Some(path) = CONFIG_PATH.get() {
+            return path.clone();
+        }
+        std::env::var("HOME")
+            .map(|h| std::path::PathBuf::from(h).join(".okros/config"))
+            .unwrap_or_else(|_| std::path::PathBuf::from(".okros/config"))
+    }
+
+    /// Runtime automation saved by #save: ~/.okros/autosave
+    pub fn autosave_path() -> std::path::PathBuf {
+        Self::default_path().with_file_name("autosave")
+    }
+
+    /// Merge the autosave file over this config, if there is one
+    pub fn load_autosave(&mut self) -> Result<(), String> {
+        let path = Self::autosave_path();
+        if !path.exists() {
+            return Ok(());
         }
+        self.load_file(&path)
+    }
+
+    /// `okros <mudname>`: the MUD by name, ignoring case if nothing matches exactly
+    pub fn find_mud(&self, name: &str) -> Option<&Mud> {
+        self.mud_list.find(name).or_else(|| {
+            self.mud_list
+                .iter()
+                .find(|m| m.name.eq_ignore_ascii_case(name))
+        })
     }
 
     /// Create config with offline MUD as entry #0
@@ -35,18 +387,29 @@ impl Config {
     }
 
     pub fn set_server_str(&mut self, s: &str) -> Result<(), String> {
-        let (ip_s, port_s) = s
-            .split_once(':')
-            .ok_or_else(|| "expected ip:port".to_string())?;
-        let ip = ip_s.parse::<Ipv4Addr>().map_err(|e| e.to_string())?;
-        let port = port_s.parse::<u16>().map_err(|e| e.to_string())?;
-        self.server = Some((ip, port));
+        // ip:port, or [v6]:port for IPv6
+        let (ip_s, port) = crate::socket::split_host_port(s)
+            .ok_or_else(|| "expected ip:port or [ipv6]:port".to_string())?;
+        let ip = ip_s.parse::<IpAddr>().map_err(|e| e.to_string())?;
+        self.server = Some(SocketAddr::new(ip, port));
         Ok(())
     }
 
     /// Load config from file (supports both old and new formats)
     /// Automatically adds Offline MUD as entry #0 if not present
     pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        self.load_included(path.as_ref(), 0)?;
+
+        // Ensure Offline MUD is present (add as entry #0 if not found)
+        self.ensure_offline_mud();
+
+        Ok(())
+    }
+
+    /// Read one config file; `include <path>` lines read another file right
+    /// there (relative paths from this file's directory), so lines after an
+    /// include override what it set.
+    fn load_included(&mut self, path: &Path, depth: usize) -> Result<(), String> {
         let file = File::open(path).map_err(|e| format!("Failed to open config: {}", e))?;
         let reader = BufReader::new(file);
         let mut lines = reader.lines().enumerate();
@@ -67,18 +430,56 @@ impl Config {
             }
 
             // Check for MUD block format
-            if parts[0].eq_ignore_ascii_case("mud") && parts.len() >= 2 {
+            if parts[0].eq_ignore_ascii_case("include") && parts.len() >= 2 {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(format!("Line {}: Includes nested too deeply", line_num + 1));
+                }
+                let included = path
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .join(expand_path(value_after(trimmed, 1)));
+                self.load_included(&included, depth + 1)
+                    .map_err(|e| format!("{}: {}", included.display(), e))?;
+            } else if let Some(group) = group_block_name(trimmed) {
+                // Top-level group block: the global MUD's
+                let mut global = std::mem::replace(&mut self.global_mud, Mud::empty());
+                let result = self.read_group_block(&mut global, group, &mut lines);
+                self.global_mud = global;
+                result?;
+            } else if AUTOMATION_KEYWORDS.contains(&parts[0].to_lowercase().as_str()) {
+                // Top-level alias/action/macro/color: belongs to the global MUD
+                let mut global = std::mem::replace(&mut self.global_mud, Mud::empty());
+                let result = self.parse_mud_block_line(&mut global, trimmed, line_num + 1);
+                self.global_mud = global;
+                result?;
+            } else if parts[0].eq_ignore_ascii_case("mud") && parts.len() >= 2 {
                 let mudname = parts[1].trim_end_matches('{').trim();
                 self.read_mud_block(mudname, &mut lines)?;
+            } else if parts[0].eq_ignore_ascii_case("msp_player") {
+                // Command line with spaces: rest of the line
+                self.msp_player = value_after(trimmed, 1).to_string();
+            } else if parts[0].eq_ignore_ascii_case("ping_command") {
+                self.ping_command = value_after(trimmed, 1).to_string();
+            } else if parts[0].eq_ignore_ascii_case("bind") && parts.len() >= 3 {
+                // bind <key> <action>: the action is the rest of the line
+                let key = crate::macro_def::key_lookup(parts[1]).ok_or_else(|| {
+                    format!("Line {}: Unknown bind key: {}", line_num + 1, parts[1])
+                })?;
+                self.keybindings
+                    .bind(key, KeyAction::parse(value_after(trimmed, 2)));
+            } else if parts[0].eq_ignore_ascii_case("unbind") && parts.len() == 2 {
+                let key = crate::macro_def::key_lookup(parts[1]).ok_or_else(|| {
+                    format!("Line {}: Unknown unbind key: {}", line_num + 1, parts[1])
+                })?;
+                self.keybindings.unbind(key);
+            } else if parts.len() == 2 {
+                // Global option: <name> <value>
+                self.parse_option(parts[0], parts[1], line_num + 1)?;
             } else {
                 // Old format or other config line
                 self.parse_line(&line, line_num + 1)?;
             }
         }
-
-        // Ensure Offline MUD is present (add as entry #0 if not found)
-        self.ensure_offline_mud();
-
         Ok(())
     }
 
@@ -126,13 +527,20 @@ impl Config {
 
             // Check for end of block
             if trimmed.starts_with('}') {
-                // Add completed MUD to list
-                self.mud_list.insert(mud);
+                // Add completed MUD to list; a later block for the same MUD
+                // (e.g. from the autosave file) is merged into the first
+                match self.mud_list.find_mut(mudname) {
+                    Some(existing) => existing.merge(&mud),
+                    None => self.mud_list.insert(mud),
+                }
                 return Ok(());
             }
 
             // Parse block line
-            self.parse_mud_block_line(&mut mud, trimmed, line_num + 1)?;
+            match group_block_name(trimmed) {
+                Some(group) => self.read_group_block(&mut mud, group, lines)?,
+                None => self.parse_mud_block_line(&mut mud, trimmed, line_num + 1)?,
+            }
         }
 
         Err(format!(
@@ -141,6 +549,44 @@ impl Config {
         ))
     }
 
+    /// Read a `group <name> { ... }` block: its aliases, actions and macros
+    /// are added to `mud` in that #group
+    fn read_group_block(
+        &mut self,
+        mud: &mut Mud,
+        group: &str,
+        lines: &mut impl Iterator<Item = (usize, Result<String, std::io::Error>)>,
+    ) -> Result<(), String> {
+        let mut members = Mud::empty();
+        for (line_num, line_result) in lines.by_ref() {
+            let line =
+                line_result.map_err(|e| format!("Read error at line {}: {}", line_num + 1, e))?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if trimmed.starts_with('}') {
+                let tag = || Some(group.to_string());
+                members.alias_list.iter_mut().for_each(|a| a.group = tag());
+                members.action_list.iter_mut().for_each(|a| a.group = tag());
+                members.macro_list.iter_mut().for_each(|m| m.group = tag());
+                mud.merge(&members);
+                return Ok(());
+            }
+            let keyword = trimmed.split_whitespace().next().unwrap_or("");
+            if !["alias", "action", "subst", "gag", "highlight", "macro"]
+                .contains(&keyword.to_lowercase().as_str())
+            {
+                return Err(format!(
+                    "Line {}: Only aliases, actions and macros can be in a group",
+                    line_num + 1
+                ));
+            }
+            self.parse_mud_block_line(&mut members, trimmed, line_num + 1)?;
+        }
+        Err(format!("Group '{}' not properly terminated with }}", group))
+    }
+
     /// Parse a line inside a MUD block
     fn parse_mud_block_line(
         &mut self,
@@ -163,14 +609,83 @@ impl Config {
                 Ok(())
             }
             "commands" if parts.len() >= 2 => {
-                mud.commands = parts[1..].join(" ").trim_end_matches(';').to_string();
+                mud.commands = value_after(line, 1).to_string();
+                Ok(())
+            }
+            "user" if parts.len() >= 2 => {
+                mud.user = parts[1].trim_end_matches(';').to_string();
+                Ok(())
+            }
+            "password" if parts.len() >= 2 => {
+                mud.password = value_after(line, 1).to_string();
+                Ok(())
+            }
+            "keepalive" if parts.len() >= 2 => {
+                mud.keepalive = Some(
+                    crate::keepalive::Keepalive::parse(value_after(line, 1))
+                        .map_err(|e| format!("Line {}: keepalive: {}", line_num, e))?,
+                );
+                Ok(())
+            }
+            "scrollback" if parts.len() >= 2 => {
+                mud.scrollback = Some(parse_scrollback(parts[1], line_num)?);
+                Ok(())
+            }
+            "histsize" if parts.len() >= 2 => {
+                mud.histsize = Some(parse_histsize(parts[1], line_num)?);
+                Ok(())
+            }
+            "charset" if parts.len() >= 2 => {
+                mud.charset = Some(
+                    crate::charset::Charset::parse(parts[1].trim_end_matches(';'))
+                        .map_err(|e| format!("Line {}: {}", line_num, e))?,
+                );
+                Ok(())
+            }
+            "bell" if parts.len() >= 2 => {
+                mud.bell = Some(
+                    crate::bell::BellMode::parse(parts[1].trim_end_matches(';'))
+                        .map_err(|e| format!("Line {}: {}", line_num, e))?,
+                );
+                Ok(())
+            }
+            "logout" if parts.len() >= 2 => {
+                // logout <command>: what #close sends before hanging up
+                mud.logout = Some(value_after(line, 1).to_string());
+                Ok(())
+            }
+            "script" if parts.len() >= 2 => {
+                // script <file>: loaded when connecting, after inherited ones
+                let script = value_after(line, 1).to_string();
+                if !mud.scripts.contains(&script) {
+                    mud.scripts.push(script);
+                }
                 Ok(())
             }
             "inherit" if parts.len() >= 2 => {
                 let parent_name = parts[1].trim_end_matches(';');
                 if let Some(parent) = self.mud_list.find(parent_name) {
+                    // A parent whose chain leads back here would be a cycle
+                    let mut chain = vec![mud.name.as_str()];
+                    let mut ancestor = Some(parent);
+                    while let Some(m) = ancestor {
+                        chain.push(&m.name);
+                        if m.name == mud.name {
+                            return Err(format!(
+                                "Line {}: Inheritance cycle: {}",
+                                line_num,
+                                chain.join(" -> ")
+                            ));
+                        }
+                        ancestor = m.inherits.as_deref();
+                    }
                     mud.inherits = Some(Box::new(parent.clone()));
                     Ok(())
+                } else if parent_name == mud.name {
+                    Err(format!(
+                        "Line {}: MUD '{}' inherits from itself",
+                        line_num, parent_name
+                    ))
                 } else {
                     Err(format!(
                         "Line {}: Parent MUD '{}' not found",
@@ -180,14 +695,15 @@ impl Config {
             }
             "alias" if parts.len() >= 3 => {
                 let name = parts[1];
-                let expansion = parts[2..].join(" ").trim_end_matches(';').to_string();
-                mud.alias_list.push(Alias::new(name, &expansion));
+                let expansion = strip_braces(value_after(line, 2));
+                mud.alias_list.retain(|a| a.name != name);
+                mud.alias_list.push(Alias::new(name, expansion));
                 Ok(())
             }
             "action" if parts.len() >= 3 => {
                 // Parse action: action "pattern" commands
-                let rest = parts[1..].join(" ").trim_end_matches(';').to_string();
-                match Action::parse(&rest, ActionType::Trigger) {
+                let rest = value_after(line, 1);
+                match Action::parse(rest, ActionType::Trigger) {
                     Ok(action) => {
                         mud.action_list.push(action);
                         Ok(())
@@ -195,10 +711,32 @@ impl Config {
                     Err(e) => Err(format!("Line {}: {}", line_num, e)),
                 }
             }
-            "subst" if parts.len() >= 3 => {
+            "subst" if parts.len() >= 2 => {
                 // Parse substitution: subst "pattern" replacement
-                let rest = parts[1..].join(" ").trim_end_matches(';').to_string();
-                match Action::parse(&rest, ActionType::Replacement) {
+                let rest = value_after(line, 1);
+                match Action::parse(rest, ActionType::Replacement) {
+                    Ok(action) => {
+                        mud.action_list.push(action);
+                        Ok(())
+                    }
+                    Err(e) => Err(format!("Line {}: {}", line_num, e)),
+                }
+            }
+            "gag" if parts.len() >= 2 => {
+                // Parse gag: gag "pattern"
+                let rest = value_after(line, 1);
+                match Action::parse(rest, ActionType::Gag) {
+                    Ok(action) => {
+                        mud.action_list.push(action);
+                        Ok(())
+                    }
+                    Err(e) => Err(format!("Line {}: {}", line_num, e)),
+                }
+            }
+            "highlight" if parts.len() >= 3 => {
+                // Parse highlight: highlight "pattern" color
+                let rest = value_after(line, 1);
+                match Action::parse(rest, ActionType::Highlight) {
                     Ok(action) => {
                         mud.action_list.push(action);
                         Ok(())
@@ -206,15 +744,594 @@ impl Config {
                     Err(e) => Err(format!("Line {}: {}", line_num, e)),
                 }
             }
-            "macro" if parts.len() >= 3 => {
-                // TODO: Implement macro parsing (need key name lookup)
-                // For now, skip macros
+            "color" if parts.len() >= 3 => {
+                // Parse color: color <item> <spec>
+                mud.colors
+                    .set(parts[1], value_after(line, 2))
+                    .map_err(|e| format!("Line {}: {}", line_num, e))
+            }
+            "macro" if parts.len() >= 3 => {
+                // Parse macro: macro <keyname> commands
+                let key = crate::macro_def::key_lookup(parts[1])
+                    .ok_or_else(|| format!("Line {}: Unknown macro key: {}", line_num, parts[1]))?;
+                let text = strip_braces(value_after(line, 2));
+                mud.macro_list.retain(|m| m.key != key);
+                mud.macro_list.push(Macro::new(key, text));
+                Ok(())
+            }
+            "bind" if parts.len() >= 3 => {
+                // bind <key> <action>: this MUD only, over the global keys
+                let key = crate::macro_def::key_lookup(parts[1])
+                    .ok_or_else(|| format!("Line {}: Unknown bind key: {}", line_num, parts[1]))?;
+                mud.bindings
+                    .insert(key, Some(KeyAction::parse(value_after(line, 2))));
+                Ok(())
+            }
+            "unbind" if parts.len() == 2 => {
+                let key = crate::macro_def::key_lookup(parts[1]).ok_or_else(|| {
+                    format!("Line {}: Unknown unbind key: {}", line_num, parts[1])
+                })?;
+                mud.bindings.insert(key, None);
+                Ok(())
+            }
+            "path" if parts.len() >= 3 => {
+                // Parse path: path <name> <speedwalk>
+                let steps = parts[2].trim_end_matches(';');
+                let path = crate::path::Path::parse(parts[1], steps)
+                    .ok_or_else(|| format!("Line {}: Invalid path: {}", line_num, steps))?;
+                mud.path_list.retain(|p| p.name != path.name);
+                mud.path_list.push(path);
+                Ok(())
+            }
+            "group" if parts.len() == 3 => {
+                // Parse group state: group <name> on|off
+                let on = parse_bool(parts[2])
+                    .ok_or_else(|| format!("Line {}: Expected on/off for group", line_num))?;
+                mud.set_group_enabled(parts[1], on);
+                Ok(())
+            }
+            _ => Err(format!(
+                "Line {}: Unknown or invalid MUD block keyword: {}",
+                line_num, parts[0]
+            )),
+        }
+    }
+
+    /// Store a runtime MUD's automation for #save
+    /// A named MUD replaces its saved block; an unnamed session (#open, no
+    /// MUD selected) adds to the global aliases/actions/macros
+    pub fn remember(&mut self, mud: &Mud) {
+        let mut saved = mud.clone();
+        saved.inherits = None;
+        if saved.name.is_empty() {
+            self.global_mud.merge(&saved);
+        } else if let Some(existing) = self.mud_list.find_mut(&saved.name) {
+            *existing = saved;
+        } else {
+            self.mud_list.insert(saved);
+        }
+    }
+
+    /// Write alias/action/macro lines (shared by MUD blocks and globals);
+    /// #group members go in `group <name> {` blocks after the rest
+    fn write_automation(mud: &Mud, indent: &str, out: &mut String) {
+        Self::write_members(mud, None, indent, out);
+        for (item, color) in mud.colors.entries() {
+            out.push_str(&format!("{}color {} 0x{:02x}\n", indent, item, color));
+        }
+        for path in &mud.path_list {
+            out.push_str(&format!("{}path {} {}\n", indent, path.name, path.steps));
+        }
+        let groups: std::collections::BTreeSet<&str> = (mud.alias_list.iter().map(|a| &a.group))
+            .chain(mud.action_list.iter().map(|a| &a.group))
+            .chain(mud.macro_list.iter().map(|m| &m.group))
+            .filter_map(Option::as_deref)
+            .collect();
+        for group in groups {
+            out.push_str(&format!("{}group {} {{\n", indent, group));
+            Self::write_members(mud, Some(group), &format!("{}    ", indent), out);
+            out.push_str(&format!("{}}}\n", indent));
+        }
+        for group in &mud.disabled_groups {
+            out.push_str(&format!("{}group {} off\n", indent, group));
+        }
+    }
+
+    /// Alias/action/macro lines for the members of one #group (None: no group)
+    fn write_members(mud: &Mud, group: Option<&str>, indent: &str, out: &mut String) {
+        let member = |g: &Option<String>| g.as_deref() == group;
+        for alias in mud.alias_list.iter().filter(|a| member(&a.group)) {
+            out.push_str(&format!(
+                "{}alias {} {}\n",
+                indent,
+                alias.name,
+                config_value(&alias.text)
+            ));
+        }
+        for action in mud.action_list.iter().filter(|a| member(&a.group)) {
+            let keyword = match action.action_type {
+                ActionType::Trigger => "action",
+                ActionType::Prompt => "action -prompt",
+                ActionType::Replacement => "subst",
+                ActionType::Gag => "gag",
+                ActionType::Highlight => "highlight",
+            };
+            let line = format!(
+                "{} {} {}",
+                keyword,
+                config_pattern(&action.pattern),
+                config_value(&action.commands)
+            );
+            out.push_str(&format!("{}{}\n", indent, line.trim_end()));
+        }
+        for macro_ in mud.macro_list.iter().filter(|m| member(&m.group)) {
+            // Only keys whose name reads back as the same key can be saved
+            let name = crate::macro_def::key_name(macro_.key);
+            if name.contains(char::is_whitespace)
+                || crate::macro_def::key_lookup(&name) != Some(macro_.key)
+            {
+                continue;
+            }
+            out.push_str(&format!(
+                "{}macro {} {}\n",
+                indent,
+                name,
+                config_value(&macro_.text)
+            ));
+        }
+    }
+
+    /// Serialize to the config file format (options, globals, MUD blocks)
+    /// load_file() of the result gives back the same MUDs and automation
+    pub fn to_config_string(&self) -> String {
+        let mut out = String::from("# okros configuration\n");
+        if self.timestamps {
+            out.push_str("timestamps on\n");
+        }
+        if self.autoreload {
+            out.push_str("autoreload on\n");
+        }
+        if self.wordwrap {
+            out.push_str("wordwrap on\n");
+        }
+        if !self.stickyprompt {
+            out.push_str("stickyprompt off\n");
+        }
+        if !self.diagnostics {
+            out.push_str("diagnostics off\n");
+        }
+        if self.echoinput {
+            out.push_str("echoinput on\n");
+        }
+        if self.msp {
+            out.push_str("msp on\n");
+        }
+        if self.atcp {
+            out.push_str("atcp on\n");
+        }
+        if self.mxp {
+            out.push_str("mxp on\n");
+        }
+        if self.hyperlinks {
+            out.push_str("hyperlinks on\n");
+        }
+        if !self.msp_player.is_empty() {
+            out.push_str(&format!("msp_player {}\n", config_value(&self.msp_player)));
+        }
+        if self.sendrate > 0.0 {
+            out.push_str(&format!("sendrate {}\n", self.sendrate));
+        }
+        if self.sendburst != crate::send_queue::DEFAULT_BURST {
+            out.push_str(&format!("sendburst {}\n", self.sendburst));
+        }
+        if self.scrollback != crate::scrollback::DEFAULT_LINES {
+            out.push_str(&format!("scrollback {}\n", self.scrollback));
+        }
+        if self.scrollback_spill {
+            out.push_str("scrollbackspill on\n");
+        }
+        if self.scrollback_spill_max != crate::line_store::DEFAULT_SPILL_LIMIT {
+            out.push_str(&format!(
+                "scrollbackspillmax {}\n",
+                self.scrollback_spill_max >> 20
+            ));
+        }
+        if self.histsize != crate::history::DEFAULT_SIZE {
+            out.push_str(&format!("histsize {}\n", self.histsize));
+        }
+        if !self.ping_command.is_empty() {
+            out.push_str(&format!(
+                "ping_command {}\n",
+                config_value(&self.ping_command)
+            ));
+        }
+        if self.connect_timeout != Some(crate::socket::CONNECT_TIMEOUT) {
+            let secs = self.connect_timeout.map_or(0, |t| t.as_secs());
+            out.push_str(&format!("connecttimeout {}\n", secs));
+        }
+        if self.trigger_depth != Some(crate::trigger_guard::DEFAULT_DEPTH) {
+            let depth = self
+                .trigger_depth
+                .map_or("off".to_string(), |d| d.to_string());
+            out.push_str(&format!("triggerdepth {}\n", depth));
+        }
+        if self.trigger_echo_gag {
+            out.push_str("triggerechogag on\n");
+        }
+        if self.status_time != crate::status_line::MESSAGE_TIME {
+            out.push_str(&format!("statustime {}\n", self.status_time.as_secs()));
+        }
+        if let Some(bytes) = self.output_budget {
+            out.push_str(&format!("outputbudget {}\n", bytes));
+        }
+        if let Some(lines) = self.more {
+            out.push_str(&format!("more {}\n", lines));
+        }
+        let limits = &self.script_limits;
+        let default_limits = ScriptLimits::default();
+        if limits.timeout != default_limits.timeout {
+            let ms = limits.timeout.map_or(0, |t| t.as_millis());
+            out.push_str(&format!("scripttimeout {}\n", ms));
+        }
+        if limits.budget != default_limits.budget {
+            out.push_str(&format!("scriptbudget {}\n", limits.budget));
+        }
+        if limits.sandbox {
+            out.push_str("scriptsandbox on\n");
+        }
+        for line in self.keybindings.config_lines() {
+            out.push_str(&format!("{}\n", config_value(&line)));
+        }
+        Self::write_automation(&self.global_mud, "", &mut out);
+
+        for mud in self.mud_list.iter() {
+            // The built-in Offline entry is re-added on load
+            if mud.name == "Offline" && mud.hostname.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\nMUD {} {{\n", mud.name));
+            if !mud.hostname.is_empty() {
+                out.push_str(&format!("    host {} {}\n", mud.hostname, mud.port));
+            }
+            if let Some(ref parent) = mud.inherits {
+                if self.mud_list.find(&parent.name).is_some() {
+                    out.push_str(&format!("    inherit {}\n", parent.name));
+                }
+            }
+            for (keyword, value) in [
+                ("commands", &mud.commands),
+                ("user", &mud.user),
+                ("password", &mud.password),
+            ] {
+                if !value.is_empty() {
+                    out.push_str(&format!("    {} {}\n", keyword, config_value(value)));
+                }
+            }
+            if let Some(ref keepalive) = mud.keepalive {
+                out.push_str(&format!(
+                    "    keepalive {}\n",
+                    config_value(&keepalive.config_text())
+                ));
+            }
+            if let Some(lines) = mud.scrollback {
+                out.push_str(&format!("    scrollback {}\n", lines));
+            }
+            if let Some(lines) = mud.histsize {
+                out.push_str(&format!("    histsize {}\n", lines));
+            }
+            if let Some(charset) = mud.charset {
+                out.push_str(&format!("    charset {}\n", charset.name()));
+            }
+            if let Some(bell) = mud.bell {
+                out.push_str(&format!("    bell {}\n", bell.name()));
+            }
+            if let Some(ref logout) = mud.logout {
+                out.push_str(&format!("    logout {}\n", config_value(logout)));
+            }
+            for script in &mud.scripts {
+                out.push_str(&format!("    script {}\n", config_value(script)));
+            }
+            for (key, action) in &mud.bindings {
+                let name = crate::macro_def::key_name(*key);
+                let line = match action {
+                    Some(action) => format!("bind {} {}", name, action.name()),
+                    None => format!("unbind {}", name),
+                };
+                out.push_str(&format!("    {}\n", config_value(&line)));
+            }
+            Self::write_automation(mud, "    ", &mut out);
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    /// Write to_config_string() to a file, creating its directory
+    pub fn save_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        write_private(path, &self.to_config_string())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Add, change or delete one MUD's entry in a config file, leaving the
+    /// rest of the file (comments, options, other MUDs) as it is
+    /// `old_name` is the entry to change (None adds `mud`); `mud` None
+    /// deletes it. A MUD block keeps its other lines (aliases, actions...);
+    /// an old-format `name host port commands` line stays one line
+    pub fn write_mud_entry(
+        path: impl AsRef<Path>,
+        old_name: Option<&str>,
+        mud: Option<&Mud>,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let found = old_name.and_then(|name| find_mud_entry(&lines, name));
+        match (found, mud) {
+            (None, None) => {
+                return Err(format!(
+                    "{} is not in {}",
+                    old_name.unwrap_or_default(),
+                    path.display()
+                ))
+            }
+            (None, Some(mud)) => {
+                if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("MUD {} {{", mud.name));
+                lines.extend(mud_entry_lines(mud, "    "));
+                lines.push("}".to_string());
+            }
+            (Some((range, _)), None) => {
+                // Take a blank separator line with it
+                let mut range = range;
+                if range.start > 0 && lines[range.start - 1].trim().is_empty() {
+                    range.start -= 1;
+                } else if lines.get(range.end).is_some_and(|l| l.trim().is_empty()) {
+                    range.end += 1;
+                }
+                lines.drain(range);
+            }
+            (Some((range, false)), Some(mud)) => {
+                let mut line = format!("{} {} {}", mud.name, mud.hostname, mud.port);
+                if !mud.commands.is_empty() {
+                    line = format!("{} {}", line, mud.commands);
+                }
+                lines.splice(range, [line]);
+            }
+            (Some((range, true)), Some(mud)) => {
+                let indent: String = lines
+                    .get(range.start + 1)
+                    .filter(|l| !l.trim_start().starts_with('}'))
+                    .map(|l| l.chars().take_while(|c| c.is_whitespace()).collect())
+                    .unwrap_or_else(|| "    ".to_string());
+                let kept: Vec<String> = lines[range.start + 1..range.end - 1]
+                    .iter()
+                    .filter(|l| {
+                        let word = l.split_whitespace().next().unwrap_or("");
+                        !word.eq_ignore_ascii_case("host") && !word.eq_ignore_ascii_case("commands")
+                    })
+                    .cloned()
+                    .collect();
+                let mut block = vec![format!("MUD {} {{", mud.name)];
+                block.extend(mud_entry_lines(mud, &indent));
+                block.extend(kept);
+                block.push(lines[range.end - 1].clone());
+                lines.splice(range, block);
+            }
+        }
+        let mut out = lines.join("\n");
+        out.push('\n');
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        write_private(path, &out).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Parse a global option line
+    fn parse_option(&mut self, name: &str, value: &str, line_num: usize) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "timestamps" => {
+                self.timestamps = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for timestamps", line_num))?;
+                Ok(())
+            }
+            "autoreload" => {
+                self.autoreload = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for autoreload", line_num))?;
+                Ok(())
+            }
+            "wordwrap" => {
+                self.wordwrap = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for wordwrap", line_num))?;
+                Ok(())
+            }
+            "echoinput" => {
+                self.echoinput = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for echoinput", line_num))?;
+                Ok(())
+            }
+            "stickyprompt" => {
+                self.stickyprompt = parse_bool(value).ok_or_else(|| {
+                    format!("Line {}: Expected on/off for stickyprompt", line_num)
+                })?;
+                Ok(())
+            }
+            "diagnostics" => {
+                self.diagnostics = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for diagnostics", line_num))?;
+                Ok(())
+            }
+            "msp" => {
+                self.msp = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for msp", line_num))?;
+                Ok(())
+            }
+            "atcp" => {
+                self.atcp = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for atcp", line_num))?;
+                Ok(())
+            }
+            "mxp" => {
+                self.mxp = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for mxp", line_num))?;
+                Ok(())
+            }
+            "hyperlinks" => {
+                self.hyperlinks = parse_bool(value)
+                    .ok_or_else(|| format!("Line {}: Expected on/off for hyperlinks", line_num))?;
+                Ok(())
+            }
+            "sendrate" => {
+                self.sendrate = value
+                    .trim_end_matches(';')
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|&r| crate::send_queue::valid_rate(r))
+                    .ok_or_else(|| {
+                        format!(
+                            "Line {}: Expected 0 or at least {} for sendrate",
+                            line_num,
+                            crate::send_queue::MIN_RATE
+                        )
+                    })?;
+                Ok(())
+            }
+            "scrollback" => {
+                self.scrollback = parse_scrollback(value, line_num)?;
+                Ok(())
+            }
+            "scrollbackspill" => {
+                self.scrollback_spill = parse_bool(value).ok_or_else(|| {
+                    format!("Line {}: Expected on/off for scrollbackspill", line_num)
+                })?;
+                Ok(())
+            }
+            "scrollbackspillmax" => {
+                // Megabytes
+                self.scrollback_spill_max = value
+                    .trim_end_matches(';')
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|mb| (1..=1 << 20).contains(mb))
+                    .map(|mb| mb << 20)
+                    .ok_or_else(|| {
+                        format!(
+                            "Line {}: scrollbackspillmax needs a size in megabytes (1 to {})",
+                            line_num,
+                            1 << 20
+                        )
+                    })?;
+                Ok(())
+            }
+            "histsize" => {
+                self.histsize = parse_histsize(value, line_num)?;
+                Ok(())
+            }
+            "sendburst" => {
+                self.sendburst = value
+                    .trim_end_matches(';')
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|b| *b > 0)
+                    .ok_or_else(|| format!("Line {}: Expected a number for sendburst", line_num))?;
+                Ok(())
+            }
+            "scripttimeout" => {
+                // Milliseconds; 0 or off for none
+                let ms = match parse_bool(value) {
+                    Some(false) => Some(0),
+                    _ => value.trim_end_matches(';').parse::<u64>().ok(),
+                }
+                .ok_or_else(|| {
+                    format!("Line {}: Expected milliseconds for scripttimeout", line_num)
+                })?;
+                self.script_limits.timeout = (ms > 0).then(|| std::time::Duration::from_millis(ms));
+                Ok(())
+            }
+            "connecttimeout" => {
+                // Seconds; 0 or off to wait as long as the OS does
+                let secs = match parse_bool(value) {
+                    Some(false) => Some(0),
+                    _ => value.trim_end_matches(';').parse::<u64>().ok(),
+                }
+                .ok_or_else(|| format!("Line {}: Expected seconds for connecttimeout", line_num))?;
+                self.connect_timeout = (secs > 0).then(|| std::time::Duration::from_secs(secs));
+                Ok(())
+            }
+            "triggerdepth" => {
+                // Trigger commands in a row before a chain is stopped; off for no limit
+                let depth = match parse_bool(value) {
+                    Some(false) => None,
+                    _ => Some(
+                        value
+                            .trim_end_matches(';')
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&depth| depth > 0)
+                            .ok_or_else(|| {
+                                format!("Line {}: Expected a number for triggerdepth", line_num)
+                            })?,
+                    ),
+                };
+                self.trigger_depth = depth;
+                Ok(())
+            }
+            "triggerechogag" => {
+                self.trigger_echo_gag = parse_bool(value).ok_or_else(|| {
+                    format!("Line {}: Expected on/off for triggerechogag", line_num)
+                })?;
+                Ok(())
+            }
+            "statustime" => {
+                // Seconds an info message stays in the status line
+                let secs = value
+                    .trim_end_matches(';')
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|&secs| secs > 0)
+                    .ok_or_else(|| format!("Line {}: Expected seconds for statustime", line_num))?;
+                self.status_time = std::time::Duration::from_secs(secs);
+                Ok(())
+            }
+            key @ ("outputbudget" | "more") => {
+                // Bytes per read / lines per page; 0 or off for no limit
+                let n = match parse_bool(value) {
+                    Some(false) => Some(0),
+                    _ => value.trim_end_matches(';').parse::<usize>().ok(),
+                }
+                .ok_or_else(|| format!("Line {}: Expected a number for {}", line_num, key))?;
+                let limit = (n > 0).then_some(n);
+                if key == "more" {
+                    self.more = limit;
+                } else {
+                    self.output_budget = limit;
+                }
+                Ok(())
+            }
+            "scriptbudget" => {
+                self.script_limits.budget =
+                    value.trim_end_matches(';').parse::<usize>().map_err(|_| {
+                        format!("Line {}: Expected a number for scriptbudget", line_num)
+                    })?;
+                Ok(())
+            }
+            "scriptsandbox" => {
+                self.script_limits.sandbox = parse_bool(value).ok_or_else(|| {
+                    format!("Line {}: Expected on/off for scriptsandbox", line_num)
+                })?;
                 Ok(())
             }
-            _ => Err(format!(
-                "Line {}: Unknown or invalid MUD block keyword: {}",
-                line_num, parts[0]
-            )),
+            _ => Err(format!("Line {}: Unknown option: {}", line_num, name)),
         }
     }
 
@@ -258,7 +1375,31 @@ mod tests {
     fn parse_server_ok() {
         let mut c = Config::new();
         c.set_server_str("127.0.0.1:4000").unwrap();
-        assert_eq!(c.server.unwrap().1, 4000);
+        assert_eq!(c.server.unwrap().port(), 4000);
+        c.set_server_str("[::1]:4001").unwrap();
+        assert_eq!(c.server.unwrap(), "[::1]:4001".parse().unwrap());
+    }
+
+    #[test]
+    fn expand_path_home_and_vars() {
+        use std::path::PathBuf;
+        let home = std::env::var("HOME").unwrap();
+        std::env::set_var("OKROS_TEST_DIR", "/srv/okros");
+        assert_eq!(expand_path("~"), PathBuf::from(&home));
+        assert_eq!(
+            expand_path("~/x.py"),
+            PathBuf::from(format!("{}/x.py", home))
+        );
+        assert_eq!(expand_path("$HOME/x"), PathBuf::from(format!("{}/x", home)));
+        assert_eq!(
+            expand_path("${OKROS_TEST_DIR}_old/$OKROS_TEST_DIR.conf"),
+            PathBuf::from("/srv/okros_old//srv/okros.conf")
+        );
+        // Unset variables, `~user` and a lone `$` stay as written
+        assert_eq!(
+            expand_path("~bob/$OKROS_UNSET_VAR/${OKROS_UNSET_VAR}/$"),
+            PathBuf::from("~bob/$OKROS_UNSET_VAR/${OKROS_UNSET_VAR}/$")
+        );
     }
 
     #[test]
@@ -329,6 +1470,553 @@ mod tests {
         assert_eq!(mud.commands, "look");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn saved_config_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("autosave");
+        std::fs::write(&path, "").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let mut cfg = Config::new();
+        let mut mud = Mud::new("Nodeka", "nodeka.com", 23);
+        mud.password = "hunter2".into();
+        cfg.mud_list.insert(mud);
+        cfg.save_file(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn write_mud_entry_edits_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        std::fs::write(
+            &path,
+            "# my muds\nwordwrap on\nOld old.org 23 look\n\nMUD Nodeka {\n  host nodeka.com 23\n  commands look\n  alias k kill\n}\n",
+        )
+        .unwrap();
+
+        let mut mud = Mud::new("Nodeka2", "nodeka.com", 5000);
+        Config::write_mud_entry(&path, Some("Nodeka"), Some(&mud)).unwrap();
+        mud = Mud::new("Old", "new.org", 4000);
+        mud.commands = "north".into();
+        Config::write_mud_entry(&path, Some("Old"), Some(&mud)).unwrap();
+        Config::write_mud_entry(&path, None, Some(&Mud::new("Fresh", "fresh.net", 7777))).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# my muds\nwordwrap on\nOld new.org 4000 north\n\nMUD Nodeka2 {\n  host nodeka.com 5000\n  alias k kill\n}\n\nMUD Fresh {\n    host fresh.net 7777\n}\n"
+        );
+
+        let mut cfg = Config::new();
+        cfg.load_file(&path).unwrap();
+        assert_eq!(cfg.mud_list.find("Nodeka2").unwrap().alias_list.len(), 1);
+        assert_eq!(cfg.mud_list.find("Fresh").unwrap().port, 7777);
+
+        Config::write_mud_entry(&path, Some("Nodeka2"), None).unwrap();
+        Config::write_mud_entry(&path, Some("Old"), None).unwrap();
+        assert!(Config::write_mud_entry(&path, Some("Nope"), None).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# my muds\nwordwrap on\nMUD Fresh {\n    host fresh.net 7777\n}\n"
+        );
+    }
+
+    #[test]
+    fn config_round_trips_through_writer() {
+        use crate::macro_def::{key_lookup, KEY_F0};
+
+        let mut cfg = Config::new();
+        cfg.timestamps = true;
+        cfg.sendrate = 1.5;
+        cfg.script_limits.timeout = None;
+        cfg.script_limits.sandbox = true;
+        cfg.keybindings.unbind(crate::macro_def::KEY_PGUP);
+        cfg.keybindings
+            .bind(KEY_F0 + 2, KeyAction::parse("#session next"));
+        cfg.global_mud.alias_list.push(Alias::new("gg", "say gg"));
+        cfg.global_mud
+            .action_list
+            .push(Action::new("^spam", "", ActionType::Gag));
+        cfg.global_mud
+            .colors
+            .set("status", "white on blue")
+            .unwrap();
+
+        let base = Mud::new("Base", "mud.example.com", 4000);
+        let mut mud = Mud::with_inherits("Main", "127.0.0.1", 5000, Some(base.clone()));
+        mud.commands = "look;score".to_string();
+        mud.user = "bob".to_string();
+        mud.password = "pass word".to_string();
+        mud.alias_list.push(Alias::new("k", "kill  %1;"));
+        mud.action_list.push(Action::new(
+            "You are hungry",
+            "eat bread",
+            ActionType::Trigger,
+        ));
+        mud.action_list
+            .push(Action::new("stupid", "", ActionType::Replacement));
+        mud.action_list
+            .push(Action::new("Tells you", "bold red", ActionType::Highlight));
+        mud.macro_list.push(Macro::new(KEY_F0 + 1, "cast 'heal'"));
+        mud.macro_list
+            .push(Macro::new(key_lookup("alt-x").unwrap(), "flee"));
+        mud.colors.set("input", "bold white on red").unwrap();
+        cfg.mud_list.insert(base);
+        cfg.mud_list.insert(mud);
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        write!(tmpfile, "{}", cfg.to_config_string()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut loaded = Config::new();
+        loaded.load_file(tmpfile.path()).unwrap();
+        assert!(loaded.timestamps);
+        assert_eq!(loaded.sendrate, 1.5);
+        assert_eq!(loaded.script_limits, cfg.script_limits);
+        assert_eq!(loaded.keybindings, cfg.keybindings);
+        assert_eq!(loaded.global_mud.find_alias("gg").unwrap().text, "say gg");
+        assert_eq!(
+            loaded.global_mud.action_list[0].action_type,
+            ActionType::Gag
+        );
+
+        let m = loaded.mud_list.find("Main").unwrap();
+        assert_eq!((m.hostname.as_str(), m.port), ("127.0.0.1", 5000));
+        assert_eq!(m.inherits.as_ref().unwrap().name, "Base");
+        assert_eq!(m.commands, "look;score");
+        assert_eq!(m.user, "bob");
+        assert_eq!(m.password, "pass word");
+        assert_eq!(m.find_alias("k").unwrap().text, "kill  %1;");
+        let actions: Vec<_> = m
+            .action_list
+            .iter()
+            .map(|a| (a.action_type, a.pattern.as_str(), a.commands.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (ActionType::Trigger, "You are hungry", "eat bread"),
+                (ActionType::Replacement, "stupid", ""),
+                (ActionType::Highlight, "Tells you", "bold red"),
+            ]
+        );
+        assert_eq!(m.find_macro(KEY_F0 + 1).unwrap().text, "cast 'heal'");
+        assert_eq!(
+            m.find_macro(key_lookup("alt-x").unwrap()).unwrap().text,
+            "flee"
+        );
+        let mut main = m.clone();
+        main.inherit_globals(&loaded.global_mud);
+        let theme = main.theme();
+        assert_eq!((theme.input_color(), theme.status_color()), (0xC7, 0x17));
+
+        // Writing again gives the same text
+        assert_eq!(loaded.to_config_string(), cfg.to_config_string());
+    }
+
+    #[test]
+    fn autosave_blocks_merge_into_config() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD Test {{").unwrap();
+        writeln!(tmpfile, "    host 127.0.0.1 4000").unwrap();
+        writeln!(tmpfile, "    alias k kill").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+
+        // Runtime changes, saved the way #save does it
+        let mut runtime = cfg.mud_list.find("Test").unwrap().clone();
+        runtime.alias_list.retain(|a| a.name != "k");
+        runtime.alias_list.push(Alias::new("k", "kick"));
+        runtime.alias_list.push(Alias::new("n", "north"));
+        let mut autosave = Config::new();
+        autosave.remember(&runtime);
+        let mut unnamed = Mud::empty();
+        unnamed.alias_list.push(Alias::new("hi", "say hello"));
+        autosave.remember(&unnamed);
+
+        let mut saved = NamedTempFile::new().unwrap();
+        write!(saved, "{}", autosave.to_config_string()).unwrap();
+        saved.flush().unwrap();
+
+        cfg.load_file(saved.path()).unwrap();
+        assert_eq!(cfg.mud_list.iter().filter(|m| m.name == "Test").count(), 1);
+        let m = cfg.mud_list.find("Test").unwrap();
+        assert_eq!(m.hostname, "127.0.0.1");
+        assert_eq!(m.find_alias("k").unwrap().text, "kick");
+        assert_eq!(m.find_alias("n").unwrap().text, "north");
+        assert_eq!(cfg.global_mud.find_alias("hi").unwrap().text, "say hello");
+    }
+
+    #[test]
+    fn config_bind_lines() {
+        use crate::macro_def::{key_lookup, KEY_ALT, KEY_F0};
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "bind F2 #session next").unwrap();
+        writeln!(tmpfile, "bind ^L redraw").unwrap();
+        writeln!(tmpfile, "unbind alt-o").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        let keys = &cfg.keybindings;
+        assert_eq!(
+            keys.get(KEY_F0 + 2),
+            Some(&KeyAction::Command("#session next".to_string()))
+        );
+        assert_eq!(
+            keys.get(key_lookup("^l").unwrap()),
+            Some(&KeyAction::Redraw)
+        );
+        assert_eq!(keys.get(KEY_ALT + b'o' as i32), None);
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "bind f99 look").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_timestamps_option() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "timestamps on").unwrap();
+        writeln!(tmpfile, "autoreload yes").unwrap();
+        writeln!(tmpfile, "msp on").unwrap();
+        writeln!(tmpfile, "wordwrap on").unwrap();
+        writeln!(tmpfile, "stickyprompt off").unwrap();
+        writeln!(tmpfile, "diagnostics off").unwrap();
+        writeln!(tmpfile, "echoinput on").unwrap();
+        writeln!(tmpfile, "msp_player paplay --volume=%v %f").unwrap();
+        writeln!(tmpfile, "ping_command time").unwrap();
+        writeln!(tmpfile, "sendrate 2.5").unwrap();
+        writeln!(tmpfile, "sendburst 3").unwrap();
+        writeln!(tmpfile, "scripttimeout 250").unwrap();
+        writeln!(tmpfile, "scriptbudget 0").unwrap();
+        writeln!(tmpfile, "scriptsandbox on").unwrap();
+        writeln!(tmpfile, "connecttimeout 10").unwrap();
+        writeln!(tmpfile, "statustime 5").unwrap();
+        writeln!(tmpfile, "triggerdepth off").unwrap();
+        writeln!(tmpfile, "triggerechogag on").unwrap();
+        writeln!(tmpfile, "outputbudget 8192").unwrap();
+        writeln!(tmpfile, "more off").unwrap();
+        writeln!(tmpfile, "TestMUD 127.0.0.1 4000").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        assert!(!cfg.timestamps);
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert!(cfg.timestamps);
+        assert!(cfg.autoreload);
+        assert!(cfg.msp);
+        assert!(cfg.wordwrap);
+        assert!(!cfg.stickyprompt);
+        assert!(!cfg.diagnostics);
+        assert!(cfg.echoinput);
+        assert_eq!(cfg.msp_player, "paplay --volume=%v %f");
+        assert_eq!(cfg.ping_command, "time");
+        assert_eq!((cfg.sendrate, cfg.sendburst), (2.5, 3));
+        assert_eq!(
+            cfg.script_limits,
+            ScriptLimits {
+                timeout: Some(std::time::Duration::from_millis(250)),
+                budget: 0,
+                sandbox: true,
+            }
+        );
+        assert_eq!(
+            cfg.connect_timeout,
+            Some(std::time::Duration::from_secs(10))
+        );
+        assert!(cfg.to_config_string().contains("\nconnecttimeout 10\n"));
+        assert_eq!(cfg.status_time, std::time::Duration::from_secs(5));
+        assert!(cfg.to_config_string().contains("\nstatustime 5\n"));
+        assert_eq!((cfg.trigger_depth, cfg.trigger_echo_gag), (None, true));
+        assert!(cfg
+            .to_config_string()
+            .contains("\ntriggerdepth off\ntriggerechogag on\n"));
+        assert_eq!((cfg.output_budget, cfg.more), (Some(8192), None));
+        assert!(cfg.to_config_string().contains("\noutputbudget 8192\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "timestamps maybe").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+
+        let mut unknown = NamedTempFile::new().unwrap();
+        writeln!(unknown, "frobnicate on").unwrap();
+        unknown.flush().unwrap();
+        assert!(Config::new().load_file(unknown.path()).is_err());
+    }
+
+    #[test]
+    fn config_user_and_password() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  user bob;").unwrap();
+        writeln!(tmpfile, "  password open sesame;").unwrap();
+        writeln!(tmpfile, "  commands look;score;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+
+        let mud = cfg.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.user, "bob");
+        assert_eq!(mud.password, "open sesame");
+        assert_eq!(mud.commands, "look;score");
+    }
+
+    #[test]
+    fn startup_target_args() {
+        let args = |s: &str| -> Vec<String> { s.split(' ').map(String::from).collect() };
+        let target = |s: &str| StartupTarget::from_args(&args(s));
+        assert_eq!(target("okros"), Ok(None));
+        assert_eq!(
+            target("okros --config /tmp/c Nodeka"),
+            Ok(Some(StartupTarget::Mud("Nodeka".into())))
+        );
+        assert_eq!(
+            target("okros nodeka.com 23 --record out.rec"),
+            Ok(Some(StartupTarget::Address("nodeka.com".into(), 23)))
+        );
+        assert!(target("okros nodeka.com telnet").is_err());
+        assert!(target("okros a b c").is_err());
+        assert_eq!(
+            Config::path_arg(&args("okros --config ~/alt.conf")).unwrap(),
+            Some(expand_path("~/alt.conf"))
+        );
+        assert!(Config::path_arg(&args("okros --config")).is_err());
+
+        let mut cfg = Config::new();
+        cfg.mud_list.insert(Mud::new("nodeka", "nodeka.com", 23));
+        cfg.mud_list.insert(Mud::new("Nodeka", "127.0.0.1", 4000));
+        assert_eq!(cfg.find_mud("Nodeka").unwrap().port, 4000);
+        assert_eq!(cfg.find_mud("NODEKA").unwrap().port, 23);
+        assert!(cfg.find_mud("aardwolf").is_none());
+    }
+
+    #[test]
+    fn config_keepalive() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  keepalive 240 blank;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        let keepalive = cfg.mud_list.find("TestMUD").unwrap().keepalive.clone();
+        assert_eq!(keepalive.unwrap().config_text(), "240 blank");
+        assert!(cfg
+            .to_config_string()
+            .contains("\n    keepalive 240 blank\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "MUD TestMUD {{\n  keepalive never\n}}").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_scrollback_global_and_per_mud() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "scrollback 5000").unwrap();
+        writeln!(
+            tmpfile,
+            "MUD Parent {{\n  host 127.0.0.1 4000;\n  scrollback 800;\n}}"
+        )
+        .unwrap();
+        writeln!(tmpfile, "MUD Child {{\n  inherit Parent\n}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        assert_eq!(cfg.scrollback, crate::scrollback::DEFAULT_LINES);
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert_eq!(cfg.scrollback, 5000);
+        let child = cfg.mud_list.find("Child").unwrap();
+        assert_eq!(
+            (child.scrollback, child.scrollback_lines()),
+            (None, Some(800))
+        );
+        let saved = cfg.to_config_string();
+        assert!(saved.contains("scrollback 5000\n"));
+        assert!(saved.contains("\n    scrollback 800\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "scrollback 10").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+
+        let mut huge = NamedTempFile::new().unwrap();
+        writeln!(huge, "scrollback 100000000").unwrap();
+        huge.flush().unwrap();
+        assert!(Config::new().load_file(huge.path()).is_err());
+    }
+
+    #[test]
+    fn config_scrollback_spill() {
+        let cfg = Config::new();
+        assert!(!cfg.scrollback_spill, "spilling is off by default");
+        assert!(!cfg.to_config_string().contains("scrollbackspill"));
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "scrollbackspill on\nscrollbackspillmax 16").unwrap();
+        tmpfile.flush().unwrap();
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert!(cfg.scrollback_spill);
+        assert_eq!(cfg.scrollback_spill_max, 16 << 20);
+        let saved = cfg.to_config_string();
+        assert!(saved.contains("scrollbackspill on\n"));
+        assert!(saved.contains("scrollbackspillmax 16\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "scrollbackspillmax 0").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_histsize_global_and_per_mud() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "histsize 500").unwrap();
+        writeln!(
+            tmpfile,
+            "MUD Parent {{\n  host 127.0.0.1 4000;\n  histsize 50;\n}}"
+        )
+        .unwrap();
+        writeln!(tmpfile, "MUD Child {{\n  inherit Parent\n}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        assert_eq!(cfg.histsize, crate::history::DEFAULT_SIZE);
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert_eq!(cfg.histsize, 500);
+        let child = cfg.mud_list.find("Child").unwrap();
+        assert_eq!((child.histsize, child.history_size()), (None, Some(50)));
+        let saved = cfg.to_config_string();
+        assert!(saved.contains("histsize 500\n"));
+        assert!(saved.contains("\n    histsize 50\n"));
+    }
+
+    #[test]
+    fn config_charset_per_mud() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(
+            tmpfile,
+            "MUD Old {{\n  host 127.0.0.1 4000;\n  charset ISO-8859-1;\n}}"
+        )
+        .unwrap();
+        writeln!(tmpfile, "MUD Child {{\n  inherit Old\n}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        let child = cfg.mud_list.find("Child").unwrap();
+        assert_eq!(child.charset, None);
+        assert_eq!(child.charset(), crate::charset::Charset::Latin1);
+        assert!(cfg.to_config_string().contains("\n    charset latin1\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "MUD Bad {{\n  charset ebcdic\n}}").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_bell_per_mud() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(
+            tmpfile,
+            "MUD Quiet {{\n  host 127.0.0.1 4000;\n  bell visual;\n  logout save;quit\n}}"
+        )
+        .unwrap();
+        writeln!(tmpfile, "MUD Child {{\n  inherit Quiet\n}}").unwrap();
+        writeln!(tmpfile, "MUD Plain {{\n  host 127.0.0.1 4001\n}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        let child = cfg.mud_list.find("Child").unwrap();
+        assert_eq!(child.bell, None);
+        assert_eq!(child.bell(), crate::bell::BellMode::Visual);
+        assert_eq!(child.logout_command(), Some("save;quit"));
+        assert!(cfg.to_config_string().contains("\n    logout save;quit\n"));
+        let plain = cfg.mud_list.find("Plain").unwrap();
+        assert_eq!(plain.bell(), crate::bell::BellMode::Beep);
+        assert!(cfg.to_config_string().contains("\n    bell visual\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "MUD Bad {{\n  bell loud\n}}").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_include_and_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("muds.conf"),
+            "MUD Base {\n  host 127.0.0.1 4000\n  script base.py\n}\nwordwrap on\n",
+        )
+        .unwrap();
+        let main = dir.path().join("config");
+        std::fs::write(
+            &main,
+            "include muds.conf\nwordwrap off\nMUD Child {\n  inherit Base\n  script child.py\n  script child.py\n}\n",
+        )
+        .unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(&main).unwrap();
+        // Lines after the include override it
+        assert!(!cfg.wordwrap);
+        let child = cfg.mud_list.find("Child").unwrap();
+        assert_eq!(child.scripts, vec!["child.py"]);
+        assert_eq!(child.script_chain(), vec!["base.py", "child.py"]);
+        assert!(cfg.to_config_string().contains("\n    script child.py\n"));
+
+        // An include cycle stops at the depth limit; a missing file is an error
+        std::fs::write(&main, "include config\n").unwrap();
+        let err = Config::new().load_file(&main).unwrap_err();
+        assert!(err.contains("nested too deeply"), "{}", err);
+        std::fs::write(&main, "include missing.conf\n").unwrap();
+        assert!(Config::new().load_file(&main).is_err());
+    }
+
+    #[test]
+    fn config_macros_use_key_names() {
+        use crate::macro_def::{KEY_F0, KEY_KP0};
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  macro f1 cast 'heal' self;").unwrap();
+        writeln!(tmpfile, "  macro kp8 north;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+
+        let mud = cfg.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.find_macro(KEY_F0 + 1).unwrap().text, "cast 'heal' self");
+        assert_eq!(mud.find_macro(KEY_KP0 + 8).unwrap().text, "north");
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "MUD Bad {{").unwrap();
+        writeln!(bad, "  macro f99 look;").unwrap();
+        writeln!(bad, "}}").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
     #[test]
     fn config_new_format_with_aliases() {
         let mut tmpfile = NamedTempFile::new().unwrap();
@@ -516,6 +2204,118 @@ mod tests {
         );
     }
 
+    #[test]
+    fn config_path_lines() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "path home 2sw").unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  path bank 3nej;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert_eq!(cfg.global_mud.find_path("home").unwrap().steps, "2sw");
+        let mud = cfg.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.find_path("bank").unwrap().steps, "3nej");
+        let text = cfg.to_config_string();
+        assert!(text.contains("\npath home 2sw\n"));
+        assert!(text.contains("  path bank 3nej\n"));
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "path x 3q").unwrap();
+        bad.flush().unwrap();
+        assert!(Config::new().load_file(bad.path()).is_err());
+    }
+
+    #[test]
+    fn config_group_blocks() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "group chat {{").unwrap();
+        writeln!(tmpfile, "  gag \"^\\[OOC\\]\"").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  alias k kill %1").unwrap();
+        writeln!(tmpfile, "  group combat {{").unwrap();
+        writeln!(tmpfile, "    alias f flee").unwrap();
+        writeln!(tmpfile, "    action \"^You are hit\" flee").unwrap();
+        writeln!(tmpfile, "  }}").unwrap();
+        writeln!(tmpfile, "  group combat off").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert_eq!(cfg.global_mud.action_list[0].group.as_deref(), Some("chat"));
+        let mud = cfg.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.hostname, "127.0.0.1");
+        assert_eq!(mud.find_alias("k").unwrap().group, None);
+        assert!(mud.find_alias("f").is_none());
+        assert_eq!(mud.action_list[0].group.as_deref(), Some("combat"));
+
+        let text = cfg.to_config_string();
+        assert!(text.contains("\n    group combat {\n        alias f flee\n"));
+        assert!(text.contains("    group combat off\n"));
+        let mut saved = NamedTempFile::new().unwrap();
+        write!(saved, "{}", text).unwrap();
+        saved.flush().unwrap();
+        let mut reloaded = Config::new();
+        reloaded.load_file(saved.path()).unwrap();
+        let mud = reloaded.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.alias_list.len(), 2);
+        assert!(!mud.group_enabled(Some("combat")));
+
+        // Editing the MUD's host keeps the group block inside its entry
+        Config::write_mud_entry(
+            saved.path(),
+            Some("TestMUD"),
+            Some(&Mud::new("TestMUD", "h", 5)),
+        )
+        .unwrap();
+        let mut edited = Config::new();
+        edited.load_file(saved.path()).unwrap();
+        assert_eq!(edited.mud_list.find("TestMUD").unwrap().alias_list.len(), 2);
+    }
+
+    #[test]
+    fn config_gag_and_highlight() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  host 127.0.0.1 4000;").unwrap();
+        writeln!(tmpfile, "  gag \"^\\[OOC\\]\";").unwrap();
+        writeln!(tmpfile, "  highlight \"dragon\" bold red;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+
+        let mud = cfg.mud_list.find("TestMUD").unwrap();
+        assert_eq!(mud.action_list.len(), 2);
+        assert_eq!(mud.action_list[0].pattern, "^\\[OOC\\]");
+        assert_eq!(
+            mud.action_list[0].action_type,
+            crate::action::ActionType::Gag
+        );
+        assert_eq!(mud.action_list[1].pattern, "dragon");
+        assert_eq!(mud.action_list[1].highlight_color(), Some(0x84));
+    }
+
+    #[test]
+    fn config_highlight_bad_color_is_error() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD TestMUD {{").unwrap();
+        writeln!(tmpfile, "  highlight \"dragon\" plaid;").unwrap();
+        writeln!(tmpfile, "}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        let err = cfg.load_file(tmpfile.path()).unwrap_err();
+        assert!(err.contains("Invalid highlight color"));
+    }
+
     #[test]
     fn config_automation_with_inheritance() {
         // Test that child MUD inherits parent's automation features
@@ -545,11 +2345,69 @@ mod tests {
         assert!(child.find_alias("p").is_some());
         assert_eq!(child.find_alias("p").unwrap().text, "parent_alias");
 
-        // Child has 1 action (doesn't inherit actions - they're per-MUD)
+        // Actions stay in the parent but fire for the child too
         assert_eq!(child.action_list.len(), 0);
-
-        // Parent has the action
         let parent = cfg.mud_list.find("Parent").unwrap();
         assert_eq!(parent.action_list.len(), 1);
+        let mut child = child.clone();
+        let mut interp = crate::plugins::native_regex::NativeRegex::new();
+        child.compile_actions(&mut interp);
+        assert_eq!(
+            child.check_action_match("parent trigger", &mut interp),
+            vec!["parent_action"]
+        );
+    }
+
+    #[test]
+    fn config_inherit_cycles_rejected() {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD Loop {{\n  inherit Loop\n}}").unwrap();
+        tmpfile.flush().unwrap();
+        let err = Config::new().load_file(tmpfile.path()).unwrap_err();
+        assert!(err.contains("inherits from itself"), "{}", err);
+
+        // A later block for A (e.g. the autosave file) can't close a loop
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "MUD A {{\n  host a.com 4000\n}}").unwrap();
+        writeln!(tmpfile, "MUD B {{\n  inherit A\n}}").unwrap();
+        writeln!(tmpfile, "MUD A {{\n  inherit B\n}}").unwrap();
+        tmpfile.flush().unwrap();
+        let err = Config::new().load_file(tmpfile.path()).unwrap_err();
+        assert!(err.contains("Inheritance cycle: A -> B -> A"), "{}", err);
+    }
+
+    #[test]
+    fn config_bind_per_mud() {
+        use crate::macro_def::KEY_F0;
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        writeln!(tmpfile, "bind F2 score").unwrap();
+        writeln!(
+            tmpfile,
+            "MUD Base {{\n  bind F3 look\n  bind F4 inventory\n}}"
+        )
+        .unwrap();
+        writeln!(tmpfile, "MUD Alt {{\n  inherit Base\n  unbind F4\n}}").unwrap();
+        tmpfile.flush().unwrap();
+
+        let mut cfg = Config::new();
+        cfg.load_file(tmpfile.path()).unwrap();
+        assert_eq!(cfg.keybindings.get(KEY_F0 + 3), None);
+        let alt = cfg.mud_list.find("Alt").unwrap();
+        assert_eq!(
+            alt.find_binding(KEY_F0 + 3),
+            Some(Some(&KeyAction::Command("look".to_string())))
+        );
+        assert_eq!(alt.find_binding(KEY_F0 + 4), Some(None));
+        assert_eq!(alt.find_binding(KEY_F0 + 2), None);
+
+        let saved = cfg.to_config_string();
+        assert!(saved.contains("\n    bind f3 look\n"), "{}", saved);
+        assert!(saved.contains("\n    unbind f4\n"), "{}", saved);
+        let mut resaved = NamedTempFile::new().unwrap();
+        write!(resaved, "{}", saved).unwrap();
+        resaved.flush().unwrap();
+        let mut again = Config::new();
+        again.load_file(resaved.path()).unwrap();
+        assert_eq!(again.mud_list.find("Alt").unwrap().bindings, alt.bindings);
     }
 }
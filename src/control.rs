@@ -1,32 +1,564 @@
+use crate::action::{Action, ActionType};
+use crate::alias::Alias;
+use crate::command_queue::{EXPAND_ALIASES, EXPAND_ALL};
 use crate::engine::SessionEngine;
+use crate::event_bus::{BusEvent, EventKind};
 use crate::mccp::PassthroughDecomp;
+use crate::mud::Mud;
+use crate::offline_mud::{OfflineWorld, WORLD_TICK, WORLD_TICK_INTERVAL};
+use crate::plugins::native_regex::NativeRegex;
+use crate::replay::{Recorder, Replay};
+use crate::scrollback::{GrepMatch, HistoryLine};
 use crate::select::{poll_fds, READ, WRITE};
-use crate::socket::{ConnState, Socket};
+use crate::session::SessionEvent;
+use crate::socket::{self, ConnState, Socket};
+use crate::stats::StatsReport;
+use crate::status_line::StatusField;
 use serde::{Deserialize, Serialize};
-use std::io::{BufRead, BufReader, Write};
-use std::net::ToSocketAddrs;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
 #[derive(Debug, Deserialize)]
 struct Command {
     cmd: String,
+    base64: Option<String>, // Binary data for send_raw/send_subneg
     data: Option<String>,
-    from: Option<u64>,
+    force: Option<bool>,
+    count: Option<usize>,
+    from: Option<i64>, // get_lines: first line number; negative counts back from the newest
     interval_ms: Option<u64>,
+    kind: Option<String>, // add_trigger: action (default), prompt, subst, gag, highlight
     lines: Option<usize>,
+    name: Option<String>,
+    option: Option<u8>, // Telnet option for send_subneg
+    pattern: Option<String>,
+    priority: Option<i32>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "event")]
 enum Event {
     Ok,
-    Error { message: String },
-    Status { attached: bool },
-    Buffer { lines: Vec<String> },
-    Hex { lines: Vec<HexLine> },
+    Error {
+        message: String,
+    },
+    Status {
+        attached: bool,
+        client: u64,                // Id of the asking client
+        attached_by: Option<u64>,   // Id of the attached client, if any
+        world: Option<WorldStatus>, // The player, when serving the offline world
+    },
+    Buffer {
+        lines: Vec<String>,
+    },
+    Hex {
+        lines: Vec<HexLine>,
+    },
+    Grep {
+        matches: Vec<GrepMatch>,
+    },
+    Lines {
+        oldest: usize, // First line number still kept
+        total: usize,  // Line count so far (the next line's number)
+        lines: Vec<HistoryLine>,
+    },
+    Fields {
+        fields: Vec<StatusField>,
+    },
+    Screen {
+        rows: Vec<String>,
+        cursor_x: usize,
+        cursor_y: usize,
+    },
+    BufferStats {
+        rows: usize,         // Scrollback ring size
+        lines: usize,        // Logical lines kept
+        spilled: usize,      // ...of which on disk
+        memory_bytes: usize, // Ring plus lines in memory
+    },
+    NetStats {
+        bytes_in: usize,
+        bytes_out: usize,
+        rate_in: f64, // Bytes/second over the last few seconds
+        rate_out: f64,
+        rtt_ms: Option<u64>, // Last #ping round trip
+    },
+    Stats(StatsReport),
+    // Pushed to subscribers (see "subscribe")
+    #[serde(rename = "line")]
+    Line {
+        text: String,
+    },
+    #[serde(rename = "prompt")]
+    Prompt {
+        text: String,
+    },
+    #[serde(rename = "connect")]
+    Connect {
+        address: String,
+    },
+    #[serde(rename = "disconnect")]
+    Disconnect,
+    // Answer to a "ping" command
+    #[serde(rename = "ping")]
+    Ping {
+        rtt_ms: u64,
+    },
+    #[serde(rename = "trigger_fired")]
+    TriggerFired {
+        line: String,
+        commands: Vec<String>,
+    },
+    #[serde(rename = "timer")]
+    Timer {
+        command: String,
+    },
+    #[serde(rename = "resize")]
+    Resize {
+        width: usize,
+        height: usize,
+    },
+    // Pushed to a client whose attachment was taken over ("attach" + force)
+    #[serde(rename = "detached")]
+    Detached {
+        by: u64,
+    },
+    Aliases {
+        aliases: Vec<AliasDef>,
+    },
+    Triggers {
+        triggers: Vec<TriggerDef>,
+    },
+    Hello(Hello),
+    Schema {
+        schema: u32,   // EVENT_SCHEMA_VERSION
+        events: Value, // Event tag -> JSON Schema of the object
+    },
+}
+
+impl From<BusEvent> for Event {
+    fn from(ev: BusEvent) -> Self {
+        match ev {
+            BusEvent::Connect { address } => Event::Connect { address },
+            BusEvent::Disconnect => Event::Disconnect,
+            BusEvent::Line(text) => Event::Line { text },
+            BusEvent::Prompt(text) => Event::Prompt { text },
+            BusEvent::TriggerFired { line, commands } => Event::TriggerFired { line, commands },
+            BusEvent::Timer { command } => Event::Timer { command },
+            BusEvent::Resize { width, height } => Event::Resize { width, height },
+        }
+    }
+}
+
+impl Event {
+    /// The bus event type a pushed event carries (None for responses and
+    /// pings, which every subscriber gets)
+    fn bus_kind(&self) -> Option<EventKind> {
+        match self {
+            Event::Connect { .. } => Some(EventKind::Connect),
+            Event::Disconnect => Some(EventKind::Disconnect),
+            Event::Line { .. } => Some(EventKind::Line),
+            Event::Prompt { .. } => Some(EventKind::Prompt),
+            Event::TriggerFired { .. } => Some(EventKind::TriggerFired),
+            Event::Timer { .. } => Some(EventKind::Timer),
+            Event::Resize { .. } => Some(EventKind::Resize),
+            _ => None,
+        }
+    }
+}
+
+/// An alias set with add_alias
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AliasDef {
+    pub name: String,
+    pub text: String, // Expansion (%1, %2... for arguments)
+}
+
+/// An action set with add_trigger
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TriggerDef {
+    pub kind: String, // action, prompt, subst, gag or highlight
+    pub pattern: String,
+    pub commands: String, // Commands, replacement or highlight color
+}
+
+impl TriggerDef {
+    /// Checked like the #action, #subst, #gag and #highlight arguments
+    fn new(kind: &str, pattern: &str, commands: &str) -> Result<Self, String> {
+        let action_type = trigger_kind(kind).ok_or_else(|| format!("unknown kind: {}", kind))?;
+        regex::Regex::new(pattern).map_err(|e| e.to_string())?;
+        let commands = match action_type {
+            ActionType::Trigger | ActionType::Prompt => {
+                crate::command_queue::strip_braces(commands)
+            }
+            _ => commands,
+        };
+        match action_type {
+            ActionType::Trigger | ActionType::Prompt if commands.is_empty() => {
+                return Err("missing data".to_string())
+            }
+            ActionType::Trigger | ActionType::Prompt
+                if crate::action::script_block(commands).is_some() =>
+            {
+                // Triggers here are matched with NativeRegex, which can't run them
+                return Err(
+                    "@perl{...}/@py{...} script blocks aren't supported in headless mode (no interpreters)"
+                        .to_string(),
+                );
+            }
+            ActionType::Highlight if crate::color::parse_color_spec(commands).is_none() => {
+                return Err(format!("Invalid highlight color: {}", commands))
+            }
+            _ => {}
+        }
+        Ok(Self {
+            kind: kind.to_string(),
+            pattern: pattern.to_string(),
+            commands: commands.to_string(),
+        })
+    }
+}
+
+/// add_trigger's kinds, named like the config file keywords
+fn trigger_kind(kind: &str) -> Option<ActionType> {
+    Some(match kind {
+        "action" => ActionType::Trigger,
+        "prompt" => ActionType::Prompt,
+        "subst" => ActionType::Replacement,
+        "gag" => ActionType::Gag,
+        "highlight" => ActionType::Highlight,
+        _ => return None,
+    })
+}
+
+/// Aliases and actions set over the socket. Compiled actions can't cross
+/// threads, so they are kept as text and each feeding thread compiles its
+/// own copy (see feed_and_broadcast)
+#[derive(Debug, Default)]
+struct Automation {
+    aliases: Vec<AliasDef>,
+    triggers: Vec<TriggerDef>,
+    generation: u64, // Bumped on every change
+}
+
+impl Automation {
+    /// A Mud holding these aliases and (uncompiled) actions
+    fn mud(&self) -> Mud {
+        let mut mud = Mud::empty();
+        mud.alias_list = self
+            .aliases
+            .iter()
+            .map(|a| Alias::new(&a.name, &a.text))
+            .collect();
+        mud.action_list = self
+            .triggers
+            .iter()
+            .filter_map(|t| {
+                let action_type = trigger_kind(&t.kind)?;
+                Some(Action::new(&t.pattern, &t.commands, action_type))
+            })
+            .collect();
+        mud
+    }
+}
+
+thread_local! {
+    // This thread's compiled Automation: (generation, Mud)
+    static COMPILED: RefCell<Option<(u64, Mud)>> = const { RefCell::new(None) };
+}
+
+/// get_lines without a count
+const DEFAULT_LINE_COUNT: usize = 100;
+
+/// Longest line a TCP client may send before it has authenticated
+const MAX_AUTH_LINE: u64 = 4096;
+
+/// Bumped when a command or response changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Bumped when any event gains, loses or retypes a field (see "schema")
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// Commands the network control server understands, reported by "hello"
+pub const COMMANDS: &[&str] = &[
+    "auth",
+    "hello",
+    "schema",
+    "status",
+    "attach",
+    "detach",
+    "send",
+    "get_buffer",
+    "get_lines",
+    "peek",
+    "hex",
+    "debug",
+    "grep",
+    "stream",
+    "get_screen",
+    "key",
+    "subscribe",
+    "set_field",
+    "get_fields",
+    "command",
+    "sock_send",
+    "send_raw",
+    "send_subneg",
+    "connect",
+    "close",
+    "zap",
+    "net_stats",
+    "buffer_stats",
+    "stats",
+    "ping",
+    "quit",
+    "shutdown",
+    "add_alias",
+    "remove_alias",
+    "list_aliases",
+    "add_trigger",
+    "remove_trigger",
+    "list_triggers",
+];
+
+/// What this build and server can do, so tools needn't probe commands
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Features {
+    pub offline: bool, // The built-in offline world rather than a MUD connection
+    pub python: bool,
+    pub perl: bool,
+    pub mccp: bool,
+    pub tls: bool, // Always false: okros has no TLS transport yet
+}
+
+impl Features {
+    pub fn new(offline: bool) -> Self {
+        Self {
+            offline,
+            python: cfg!(feature = "python"),
+            perl: cfg!(feature = "perl"),
+            mccp: cfg!(feature = "mccp"),
+            tls: false,
+        }
+    }
+}
+
+/// The offline world's player, reported by "status"
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldStatus {
+    pub location: String,
+    pub inventory_count: usize,
+    pub hp: i32,
+    pub fighting: Option<String>,
+}
+
+impl WorldStatus {
+    fn new(world: &OfflineWorld) -> Self {
+        let player = &world.world.player;
+        Self {
+            location: player.location.to_string(),
+            inventory_count: player.inventory.len(),
+            hp: player.hp,
+            fighting: player.fighting.map(str::to_string),
+        }
+    }
+}
+
+/// Answer to "hello": the handshake a client sends first.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hello {
+    pub protocol: u32,
+    pub schema: u32,
+    pub version: &'static str, // okros release
+    pub instance: String,
+    pub features: Features,
+    pub commands: Vec<&'static str>,
+}
+
+impl Hello {
+    pub fn new(instance: &str, offline: bool, commands: &[&'static str]) -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            schema: EVENT_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+            instance: instance.to_string(),
+            features: Features::new(offline),
+            commands: commands.to_vec(),
+        }
+    }
+
+    /// The {"event":"Hello",...} line
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&Event::Hello(self.clone())).unwrap_or_default()
+    }
+}
+
+/// JSON Schemas of the network server's events, keyed by their "event" tag.
+/// Every field is always present (null when an Option is unset)
+pub fn event_schemas() -> Value {
+    let string = json!({"type": "string"});
+    let int = json!({"type": "integer", "minimum": 0});
+    let number = json!({"type": "number"});
+    let boolean = json!({"type": "boolean"});
+    let opt_int = json!({"type": ["integer", "null"], "minimum": 0});
+    let strings = json!({"type": "array", "items": string});
+    let object = |fields: &[(&str, &Value)]| {
+        let props: serde_json::Map<String, Value> = fields
+            .iter()
+            .map(|(name, schema)| (name.to_string(), (*schema).clone()))
+            .collect();
+        let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        json!({"type": "object", "properties": props, "required": required})
+    };
+    let array_of = |item: Value| json!({"type": "array", "items": item});
+    let hex_line = object(&[("hex", &string), ("text", &string), ("colors", &string)]);
+    let grep_match = object(&[("line", &int), ("text", &string)]);
+    let history_line = object(&[("line", &int), ("text", &string), ("ansi", &string)]);
+    let field = object(&[
+        ("name", &string),
+        ("text", &string),
+        ("priority", &json!({"type": "integer"})),
+    ]);
+    let alias_def = object(&[("name", &string), ("text", &string)]);
+    let trigger_def = object(&[
+        (
+            "kind",
+            &json!({"enum": ["action", "prompt", "subst", "gag", "highlight"]}),
+        ),
+        ("pattern", &string),
+        ("commands", &string),
+    ]);
+    let mut world_status = object(&[
+        ("location", &string),
+        ("inventory_count", &int),
+        ("hp", &json!({"type": "integer"})),
+        ("fighting", &json!({"type": ["string", "null"]})),
+    ]);
+    world_status["type"] = json!(["object", "null"]);
+    let features = object(&[
+        ("offline", &boolean),
+        ("python", &boolean),
+        ("perl", &boolean),
+        ("mccp", &boolean),
+        ("tls", &boolean),
+    ]);
+    let events: Vec<(&str, Vec<(&str, Value)>)> = vec![
+        ("Ok", vec![]),
+        ("Error", vec![("message", string.clone())]),
+        (
+            "Status",
+            vec![
+                ("attached", boolean.clone()),
+                ("client", int.clone()),
+                ("attached_by", opt_int.clone()),
+                ("world", world_status),
+            ],
+        ),
+        ("Buffer", vec![("lines", strings.clone())]),
+        ("Hex", vec![("lines", array_of(hex_line))]),
+        ("Grep", vec![("matches", array_of(grep_match))]),
+        (
+            "Lines",
+            vec![
+                ("oldest", int.clone()),
+                ("total", int.clone()),
+                ("lines", array_of(history_line)),
+            ],
+        ),
+        ("Fields", vec![("fields", array_of(field))]),
+        (
+            "Screen",
+            vec![
+                ("rows", strings.clone()),
+                ("cursor_x", int.clone()),
+                ("cursor_y", int.clone()),
+            ],
+        ),
+        (
+            "BufferStats",
+            vec![
+                ("rows", int.clone()),
+                ("lines", int.clone()),
+                ("spilled", int.clone()),
+                ("memory_bytes", int.clone()),
+            ],
+        ),
+        (
+            "NetStats",
+            vec![
+                ("bytes_in", int.clone()),
+                ("bytes_out", int.clone()),
+                ("rate_in", number.clone()),
+                ("rate_out", number),
+                ("rtt_ms", opt_int.clone()),
+            ],
+        ),
+        (
+            "Stats",
+            vec![
+                ("connected_at", json!({"type": ["integer", "null"]})),
+                ("connected_secs", opt_int.clone()),
+                ("bytes_received", int.clone()),
+                ("bytes_sent", int.clone()),
+                ("mccp_compressed", opt_int.clone()),
+                ("mccp_uncompressed", opt_int.clone()),
+                ("lines", int.clone()),
+                ("scrollback_lines", int.clone()),
+                ("scrollback_kb", int.clone()),
+                ("triggers_fired", int.clone()),
+                ("commands_sent", int.clone()),
+                ("latency_ms", opt_int),
+            ],
+        ),
+        ("line", vec![("text", string.clone())]),
+        ("prompt", vec![("text", string.clone())]),
+        ("connect", vec![("address", string.clone())]),
+        ("disconnect", vec![]),
+        ("ping", vec![("rtt_ms", int.clone())]),
+        (
+            "trigger_fired",
+            vec![("line", string.clone()), ("commands", strings.clone())],
+        ),
+        ("timer", vec![("command", string.clone())]),
+        (
+            "resize",
+            vec![("width", int.clone()), ("height", int.clone())],
+        ),
+        ("detached", vec![("by", int.clone())]),
+        ("Aliases", vec![("aliases", array_of(alias_def))]),
+        ("Triggers", vec![("triggers", array_of(trigger_def))]),
+        (
+            "Hello",
+            vec![
+                ("protocol", int.clone()),
+                ("schema", int.clone()),
+                ("version", string.clone()),
+                ("instance", string),
+                ("features", features),
+                ("commands", strings),
+            ],
+        ),
+        (
+            "Schema",
+            vec![("schema", int), ("events", json!({"type": "object"}))],
+        ),
+    ];
+    let mut schemas = serde_json::Map::new();
+    for (tag, fields) in events {
+        let tag_schema = json!({"const": tag});
+        let mut all: Vec<(&str, &Value)> = vec![("event", &tag_schema)];
+        all.extend(fields.iter().map(|(name, schema)| (*name, schema)));
+        schemas.insert(tag.to_string(), object(&all));
+    }
+    Value::Object(schemas)
 }
 
 #[derive(Debug, Serialize)]
@@ -39,100 +571,532 @@ pub struct HexLine {
 pub struct ControlState {
     engine: Arc<Mutex<SessionEngine<PassthroughDecomp>>>,
     sock: Arc<Mutex<Option<Socket>>>,
+    address: Mutex<String>, // Last "connect" target, reported in connect events
+    subscribers: Mutex<Vec<Subscriber>>, // Clients that asked for pushed events
+    next_client: AtomicU64,
+    attached: Mutex<Option<Client>>, // The one client that streams and sends keys
+    recorder: Mutex<Option<Recorder>>, // --record: socket reads are saved here
+    automation: Mutex<Automation>,   // add_alias / add_trigger
+    closing: Mutex<Option<std::time::Instant>>, // "close": hang up by then if the MUD hasn't
+    world: Mutex<Option<OfflineWorld>>, // --offline: played in place of a MUD connection
+    instance: String,                // Socket file name without .sock, for "hello"
+}
+
+/// A connected control client: an id plus its output queue
+#[derive(Clone)]
+struct Client {
+    id: u64,
+    tx: Sender<String>,
+}
+
+impl Client {
+    fn send(&self, event: &Event) -> bool {
+        self.tx.send(serde_json::to_string(event).unwrap()).is_ok()
+    }
 }
 
 pub struct ControlServer {
     path: PathBuf,
     state: Arc<ControlState>,
+    tcp: Option<(TcpListener, Arc<str>)>, // --control-tcp listener and its token
+    replay: Option<Replay>,               // --replay: fed in as if from a MUD
+}
+
+/// A connection a control client talks over (Unix socket or TCP)
+trait ControlStream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+}
+
+impl ControlStream for UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+}
+
+impl ControlStream for TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
 }
 
 impl ControlServer {
-    pub fn new(path: PathBuf, engine: SessionEngine<PassthroughDecomp>) -> Self {
+    pub fn new(path: PathBuf, mut engine: SessionEngine<PassthroughDecomp>) -> Self {
+        // Lines/prompts/triggers are pushed to subscribers as they happen
+        engine.session.set_event_recording(true);
+        engine.bus.set_recording(true);
+        // Nobody is attached until a client asks
+        engine.detach();
+        let instance = instance_name(&path);
         Self {
             path,
             state: Arc::new(ControlState {
                 engine: Arc::new(Mutex::new(engine)),
                 sock: Arc::new(Mutex::new(None)),
+                address: Mutex::new(String::new()),
+                subscribers: Mutex::new(Vec::new()),
+                next_client: AtomicU64::new(1),
+                attached: Mutex::new(None),
+                recorder: Mutex::new(None),
+                automation: Mutex::new(Automation::default()),
+                closing: Mutex::new(None),
+                world: Mutex::new(None),
+                instance,
             }),
+            tcp: None,
+            replay: None,
+        }
+    }
+
+    /// Save everything read from the MUD to `recorder`
+    pub fn record(&mut self, recorder: Recorder) {
+        *self.state.recorder.lock().unwrap() = Some(recorder);
+    }
+
+    /// Feed `replay` through the engine once the server runs
+    pub fn replay(&mut self, replay: Replay) {
+        self.replay = Some(replay);
+    }
+
+    /// Play `world` instead of connecting to a MUD: command lines go to it
+    /// and its output is fed through the engine; NPCs run off a ticker
+    pub fn offline(&mut self, world: OfflineWorld) {
+        let now = std::time::Instant::now();
+        let mut eng = self.state.engine.lock().unwrap();
+        eng.timers.add_tick(WORLD_TICK_INTERVAL, WORLD_TICK, now);
+        *self.state.world.lock().unwrap() = Some(world);
+    }
+
+    /// Also listen on TCP (loopback addresses only), for clients that can't
+    /// reach the Unix socket. Unlike the socket, which only its owner can
+    /// open, a TCP port is open to every local user, so TCP clients must
+    /// first send {"cmd":"auth","data":TOKEN}; the token is written to a
+    /// file only the owner can read, whose path is returned
+    pub fn listen_tcp(&mut self, addr: &str) -> std::io::Result<PathBuf> {
+        let addrs = resolve_addrs(addr)?;
+        if let Some(a) = addrs.iter().find(|a| !a.ip().is_loopback()) {
+            return Err(io_err(&format!("{} is not a loopback address", a.ip())));
         }
+        let listener = TcpListener::bind(&addrs[..])?;
+        let token = new_token()?;
+        let token_path = self.path.with_extension("token");
+        write_token(&token_path, &token)?;
+        self.tcp = Some((listener, token.into()));
+        Ok(token_path)
     }
 
-    pub fn run(self) -> std::io::Result<()> {
+    pub fn run(mut self) -> std::io::Result<()> {
         // Remove existing socket if present
         let _ = std::fs::remove_file(&self.path);
         let listener = UnixListener::bind(&self.path)?;
         let state = self.state.clone();
-        for stream in listener.incoming() {
-            match stream {
-                Ok(s) => {
-                    let st = state.clone();
-                    thread::spawn(move || {
-                        let _ = handle_client(s, st);
-                    });
-                }
-                Err(e) => eprintln!("control: accept error: {}", e),
-            }
+        let intro = state
+            .world
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(OfflineWorld::intro);
+        if let Some(intro) = intro {
+            feed_and_broadcast(&state, intro.as_bytes());
+        }
+        spawn_timer_loop(state.clone());
+        if let Some(replay) = self.replay.take() {
+            spawn_replay_loop(state.clone(), replay);
         }
+        if let Some((tcp, token)) = self.tcp.take() {
+            let st = state.clone();
+            thread::spawn(move || accept_loop(tcp.incoming(), st, Some(token)));
+        }
+        accept_loop(listener.incoming(), state, None);
         Ok(())
     }
 }
 
-fn handle_client(mut stream: UnixStream, state: Arc<ControlState>) -> std::io::Result<()> {
+/// Serve each connection on its own thread; `token` must be presented
+/// before any other command
+fn accept_loop<S: ControlStream>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    state: Arc<ControlState>,
+    token: Option<Arc<str>>,
+) {
+    for stream in incoming {
+        match stream {
+            Ok(s) => {
+                let st = state.clone();
+                let token = token.clone();
+                thread::spawn(move || {
+                    let _ = handle_client(s, st, token);
+                });
+            }
+            Err(e) => eprintln!("control: accept error: {}", e),
+        }
+    }
+}
+
+/// Compare a presented token in time that doesn't depend on where it
+/// first differs from ours
+fn tokens_match(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    let diff = (0..token.len()).fold(given.len() ^ token.len(), |diff, i| {
+        diff | usize::from(given.get(i).copied().unwrap_or(0) ^ token[i])
+    });
+    diff == 0
+}
+
+/// 128 random bits, hex encoded
+fn new_token() -> std::io::Result<String> {
+    let mut bytes = [0u8; 16];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn write_token(path: &Path, token: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let _ = std::fs::remove_file(path);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", token)
+}
+
+fn handle_client<S: ControlStream>(
+    stream: S,
+    state: Arc<ControlState>,
+    token: Option<Arc<str>>,
+) -> std::io::Result<()> {
     let mut reader = BufReader::new(stream.try_clone()?);
+    let client = Client {
+        id: state.next_client.fetch_add(1, Ordering::SeqCst),
+        tx: spawn_writer(stream)?,
+    };
     let mut line = String::new();
+    let mut authed = token.is_none();
     loop {
         line.clear();
-        let n = reader.read_line(&mut line)?;
+        // Until the token is in, a line can't be longer than an auth command
+        let limit = if authed { u64::MAX } else { MAX_AUTH_LINE };
+        // Treat read errors like a hangup so the attachment is released
+        let n = (&mut reader).take(limit).read_line(&mut line).unwrap_or(0);
         if n == 0 {
             break;
         }
+        if n as u64 == limit && !line.ends_with('\n') {
+            client.send(&Event::Error {
+                message: "auth required: line too long".into(),
+            });
+            break;
+        }
         let trimmed = line.trim_end();
         if trimmed.is_empty() {
             continue;
         }
         let cmd: serde_json::Result<Command> = serde_json::from_str(trimmed);
-        match cmd {
+        let response = match cmd {
+            Ok(c) if c.cmd == "auth" => match &token {
+                Some(t) if tokens_match(c.data.as_deref().unwrap_or(""), t) => {
+                    authed = true;
+                    Event::Ok
+                }
+                Some(_) => Event::Error {
+                    message: "bad token".into(),
+                },
+                None => Event::Ok,
+            },
+            Ok(_) if !authed => Event::Error {
+                message: "auth required: send {\"cmd\":\"auth\",\"data\":TOKEN}".into(),
+            },
             Ok(c) if c.cmd == "stream" => {
-                // Enter streaming loop until client disconnects
-                let interval = c.interval_ms.unwrap_or(200);
-                let _ = stream_loop(&mut stream, &state.engine, interval as u64);
-                break;
+                if let Err(message) = check_attached(&state, &client) {
+                    Event::Error { message }
+                } else {
+                    // Stream until the client hangs up or is displaced
+                    let interval = c.interval_ms.unwrap_or(200);
+                    if !stream_loop(&state, &client, interval) {
+                        break;
+                    }
+                    continue;
+                }
             }
-            Ok(c) => {
-                let response = handle_command(c, &state);
-                let s = serde_json::to_string(&response).unwrap();
-                writeln!(stream, "{}", s)?;
-                stream.flush()?;
+            Ok(c) if c.cmd == "subscribe" => match subscription_kinds(c.data.as_deref()) {
+                Ok(kinds) => {
+                    // Subscribing again replaces the event types
+                    let mut subs = state.subscribers.lock().unwrap();
+                    subs.retain(|s| s.client != client.id);
+                    subs.push(Subscriber {
+                        client: client.id,
+                        tx: client.tx.clone(),
+                        kinds,
+                    });
+                    Event::Ok
+                }
+                Err(message) => Event::Error { message },
+            },
+            Ok(c) => handle_command(c, &state, &client),
+            Err(e) => Event::Error {
+                message: format!("bad json: {}", e),
+            },
+        };
+        if !client.send(&response) {
+            break; // Writer gone: client hung up
+        }
+    }
+    release(&state, client.id);
+    Ok(())
+}
+
+/// All output to a client goes through one queue drained by a writer
+/// thread, so responses, pushed events and notifications never interleave
+/// mid-line
+fn spawn_writer<S: ControlStream>(mut out: S) -> std::io::Result<Sender<String>> {
+    let (tx, rx) = channel::<String>();
+    thread::spawn(move || {
+        for msg in rx {
+            if writeln!(out, "{}", msg).and_then(|_| out.flush()).is_err() {
+                break;
             }
-            Err(e) => {
-                let s = serde_json::to_string(&Event::Error {
-                    message: format!("bad json: {}", e),
-                })
-                .unwrap();
-                writeln!(stream, "{}", s)?;
-                stream.flush()?;
+        }
+    });
+    Ok(tx)
+}
+
+/// Attach `client`, taking over from another client only with `force`
+/// The displaced client gets a "detached" event and becomes an observer
+fn attach(state: &ControlState, client: &Client, force: bool) -> Result<(), String> {
+    let mut attached = state.attached.lock().unwrap();
+    if let Some(cur) = attached.as_ref() {
+        if cur.id != client.id {
+            if !force {
+                return Err(format!(
+                    "already attached by client {} (use force to take over)",
+                    cur.id
+                ));
             }
+            cur.send(&Event::Detached { by: client.id });
         }
     }
+    *attached = Some(client.clone());
+    state.engine.lock().unwrap().attach();
     Ok(())
 }
 
-fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
+/// Drop the attachment if `id` holds it (detach or hangup)
+fn release(state: &ControlState, id: u64) {
+    let mut attached = state.attached.lock().unwrap();
+    if attached.as_ref().is_some_and(|c| c.id == id) {
+        *attached = None;
+        state.engine.lock().unwrap().detach();
+    }
+}
+
+/// Observers may not stream or send keys while another client is attached
+fn check_attached(state: &ControlState, client: &Client) -> Result<(), String> {
+    match state.attached.lock().unwrap().as_ref() {
+        Some(cur) if cur.id != client.id => Err(format!("observer: client {} is attached", cur.id)),
+        _ => Ok(()),
+    }
+}
+
+/// A subscribed client and the event types it wants (None: all)
+struct Subscriber {
+    client: u64,
+    tx: Sender<String>,
+    kinds: Option<Vec<EventKind>>,
+}
+
+/// "subscribe" data: event types separated by commas or spaces; none
+/// means every type
+fn subscription_kinds(data: Option<&str>) -> Result<Option<Vec<EventKind>>, String> {
+    let names: Vec<&str> = data
+        .unwrap_or("")
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Ok(None);
+    }
+    names
+        .into_iter()
+        .map(EventKind::parse)
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Push an event to every client subscribed to its type, dropping ones that
+/// went away
+fn broadcast(state: &ControlState, event: &Event) {
+    let mut subs = state.subscribers.lock().unwrap();
+    if subs.is_empty() {
+        return;
+    }
+    let kind = event.bus_kind();
+    let s = serde_json::to_string(event).unwrap();
+    subs.retain(|sub| {
+        let wanted = match (kind, &sub.kinds) {
+            (Some(kind), Some(kinds)) => kinds.contains(&kind),
+            _ => true,
+        };
+        !wanted || sub.tx.send(s.clone()).is_ok()
+    });
+}
+
+/// Push what the engine published on its bus since the last call
+fn broadcast_bus(state: &ControlState) {
+    let events = state.engine.lock().unwrap().bus.take();
+    for event in events {
+        broadcast(state, &event.into());
+    }
+}
+
+/// Feed MUD output to the engine (through the add_trigger substitutions,
+/// gags and highlights), push the resulting session events and run the
+/// triggers that match
+fn feed_and_broadcast(state: &ControlState, data: &[u8]) {
+    let mut re = NativeRegex::new();
+    let (replies, rtt, fired) = COMPILED.with(|compiled| {
+        let mut compiled = compiled.borrow_mut();
+        {
+            let automation = state.automation.lock().unwrap();
+            if compiled
+                .as_ref()
+                .is_none_or(|(generation, _)| *generation != automation.generation)
+            {
+                let mut mud = automation.mud();
+                mud.compile_actions(&mut re);
+                *compiled = Some((automation.generation, mud));
+            }
+        }
+        let mud = &compiled.as_ref().unwrap().1;
+        let mut eng = state.engine.lock().unwrap();
+        let rtt = eng.session.record_read(data.len());
+        eng.feed_inbound_with_mud(data, &mut re, mud);
+        let events = eng.take_events();
+        let mut fired = Vec::new();
+        for ev in &events {
+            let (text, commands) = match ev {
+                SessionEvent::Line(text) => (text, mud.check_action_match(text, &mut re)),
+                SessionEvent::Prompt(text) => (text, mud.check_prompt_match(text, &mut re)),
+                SessionEvent::TriggerFired { .. } => continue,
+            };
+            if !commands.is_empty() {
+                eng.session.stats.triggers_fired += commands.len();
+                fired.push((text.clone(), commands));
+            }
+        }
+        (eng.session.take_telnet_responses(), rtt, fired)
+    });
+    // Telnet negotiation replies (headless mode refuses MSP)
+    if !replies.is_empty() {
+        write_sock(state, &replies);
+    }
+    if let Some(rtt) = rtt {
+        broadcast(
+            state,
+            &Event::Ping {
+                rtt_ms: rtt.as_millis() as u64,
+            },
+        );
+    }
+    broadcast_bus(state);
+    // A trigger's commands are expanded like the TTY client's ($var, ';');
+    // a chain of triggers answering their own output is cut off
+    for (line, commands) in fired {
+        for command in &commands {
+            let now = std::time::Instant::now();
+            let (allowed, alert) = {
+                let mut eng = state.engine.lock().unwrap();
+                let guard = &mut eng.session.trigger_guard;
+                if guard.allow(now) {
+                    guard.sent(command, now);
+                    (true, None)
+                } else {
+                    (false, guard.take_alert())
+                }
+            };
+            if let Some(alert) = alert {
+                echo_line(state, &alert);
+            }
+            if allowed && !run_expanded(state, command, EXPAND_ALL) {
+                echo_line(state, command);
+            }
+        }
+        let fired = BusEvent::TriggerFired { line, commands };
+        state.engine.lock().unwrap().bus.publish(fired);
+        broadcast_bus(state);
+    }
+}
+
+/// Write raw bytes to the MUD, counting them in the session's traffic stats
+/// Returns false if not connected
+/// Standard base64 (padding optional); None if it isn't base64
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.trim().trim_end_matches('=').bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn write_sock(state: &ControlState, data: &[u8]) -> bool {
+    let written = match state.sock.lock().unwrap().as_ref() {
+        Some(sock) => unsafe {
+            libc::write(
+                sock.as_raw_fd(),
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+            )
+        },
+        None => return false,
+    };
+    if written > 0 {
+        let mut eng = state.engine.lock().unwrap();
+        eng.session.record_written(written as usize);
+    }
+    true
+}
+
+fn handle_command(cmd: Command, state: &Arc<ControlState>, client: &Client) -> Event {
     match cmd.cmd.as_str() {
+        // Handshake: protocol/schema versions, build features, commands
+        "hello" => {
+            let offline = state.world.lock().unwrap().is_some();
+            Event::Hello(Hello::new(&state.instance, offline, COMMANDS))
+        }
+        "schema" => Event::Schema {
+            schema: EVENT_SCHEMA_VERSION,
+            events: event_schemas(),
+        },
         "status" => {
-            let eng = state.engine.lock().unwrap();
+            let attached_by = state.attached.lock().unwrap().as_ref().map(|c| c.id);
+            let attached = state.engine.lock().unwrap().is_attached();
+            let world = state.world.lock().unwrap().as_ref().map(WorldStatus::new);
             Event::Status {
-                attached: eng.is_attached(),
+                attached,
+                client: client.id,
+                attached_by,
+                world,
             }
         }
-        "attach" => {
-            let mut eng = state.engine.lock().unwrap();
-            eng.attach();
-            Event::Ok
-        }
+        "attach" => match attach(state, client, cmd.force.unwrap_or(false)) {
+            Ok(()) => Event::Ok,
+            Err(message) => Event::Error { message },
+        },
         "detach" => {
-            let mut eng = state.engine.lock().unwrap();
-            eng.detach();
+            release(state, client.id);
             Event::Ok
         }
         "get_buffer" => {
@@ -152,16 +1116,88 @@ fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
             let hex_lines = eng.peek_hex(count);
             Event::Hex { lines: hex_lines }
         }
+        // Network hex dump: data = "#debug" arguments ("net on [file]", "net
+        // off"); returns the newest lines (default 50)
+        "debug" => {
+            let mut eng = state.engine.lock().unwrap();
+            if let Some(args) = cmd.data.as_deref() {
+                if let Err(message) =
+                    crate::net_debug::debug_command(args, &mut eng.session.net_debug)
+                {
+                    return Event::Error { message };
+                }
+            }
+            let lines = match eng.session.net_debug {
+                Some(ref debug) => debug.recent(cmd.lines.unwrap_or(50)),
+                None => Vec::new(),
+            };
+            Event::Buffer { lines }
+        }
+        // Logical history lines for paging: count of them from line `from`
+        "get_lines" => {
+            let eng = state.engine.lock().unwrap();
+            let from = cmd.from.unwrap_or(-(DEFAULT_LINE_COUNT as i64));
+            let (lines, oldest, total) =
+                eng.get_lines(from, cmd.count.unwrap_or(DEFAULT_LINE_COUNT));
+            Event::Lines {
+                oldest,
+                total,
+                lines,
+            }
+        }
+        // Scrollback rows matching data (a regex); lines = at most that many, newest
+        "grep" => {
+            let eng = state.engine.lock().unwrap();
+            let pattern = cmd.data.as_deref().unwrap_or("");
+            match eng.grep(pattern, cmd.lines.unwrap_or(usize::MAX)) {
+                Ok(matches) => Event::Grep { matches },
+                Err(message) => Event::Error { message },
+            }
+        }
+        // Status line fields: set (empty/missing data clears) and list
+        "set_field" => {
+            let Some(name) = cmd.name else {
+                return Event::Error {
+                    message: "missing name".to_string(),
+                };
+            };
+            let mut eng = state.engine.lock().unwrap();
+            eng.status.set(
+                &name,
+                cmd.data.as_deref().unwrap_or(""),
+                cmd.priority.unwrap_or(0),
+            );
+            Event::Ok
+        }
+        "get_fields" => {
+            let eng = state.engine.lock().unwrap();
+            Event::Fields {
+                fields: eng.status.iter().cloned().collect(),
+            }
+        }
         "connect" => {
+            if state.world.lock().unwrap().is_some() {
+                return Event::Error {
+                    message: "offline: there is no MUD to connect to".to_string(),
+                };
+            }
             if let Some(addr) = &cmd.data {
-                match resolve_ipv4(addr) {
-                    Ok((ip, port)) => {
+                match resolve_addrs(addr) {
+                    Ok(addrs) => {
                         match Socket::new().and_then(|mut s| {
-                            let _ = s.connect_ipv4(ip, port);
+                            let _ = s.connect_any(addrs);
                             Ok(s)
                         }) {
                             Ok(s) => {
+                                // Loopback connects can complete immediately, before
+                                // the net loop ever sees the socket become writable
+                                let connected = s.state == ConnState::Connected;
+                                *state.address.lock().unwrap() = addr.clone();
                                 *state.sock.lock().unwrap() = Some(s);
+                                if connected {
+                                    state.engine.lock().unwrap().connected(addr);
+                                    broadcast_bus(state);
+                                }
                                 spawn_net_loop(state.clone());
                                 Event::Ok
                             }
@@ -180,9 +1216,16 @@ fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
                 }
             }
         }
-        // Append data to the session buffer
+        // Append data to the session buffer; the offline world plays it
+        // like "command"
         "send" => {
             if let Some(data) = cmd.data {
+                if state.world.lock().unwrap().is_some() {
+                    return match run_command_line(state, data.trim_end()) {
+                        Ok(_) => Event::Ok,
+                        Err(message) => Event::Error { message },
+                    };
+                }
                 let mut eng = state.engine.lock().unwrap();
                 if !data.is_empty() {
                     if let Some(sb) = eng.session.scrollback_mut() {
@@ -196,17 +1239,130 @@ fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
                 }
             }
         }
+        // Run a client command line: #wait/#tick/#untick go to the timer
+        // queue, anything else is sent to the MUD
+        "command" => {
+            let Some(data) = cmd.data else {
+                return Event::Error {
+                    message: "missing data".to_string(),
+                };
+            };
+            match run_command_line(state, &data) {
+                Ok(true) => Event::Ok,
+                Ok(false) => Event::Error {
+                    message: "not connected".to_string(),
+                },
+                Err(message) => Event::Error { message },
+            }
+        }
+        // Composited TTY-style screen for remote attach
+        "get_screen" => {
+            let snap = state.engine.lock().unwrap().screen_snapshot();
+            Event::Screen {
+                rows: snap.rows,
+                cursor_x: snap.cursor_x,
+                cursor_y: snap.cursor_y,
+            }
+        }
+        // Raw key bytes from a remote terminal: edit the input line, and run
+        // lines entered with Enter like "command" (echoed when not connected)
+        "key" => {
+            if let Err(message) = check_attached(state, client) {
+                return Event::Error { message };
+            }
+            let Some(data) = cmd.data else {
+                return Event::Error {
+                    message: "missing data".to_string(),
+                };
+            };
+            let lines = state.engine.lock().unwrap().key_input(data.as_bytes());
+            for line in lines {
+                match run_command_line(state, &line) {
+                    Ok(true) => {}
+                    Ok(false) => echo_line(state, &line),
+                    Err(message) => echo_line(state, &message),
+                }
+            }
+            Event::Ok
+        }
+        // Scrollback size and memory use
+        "buffer_stats" => {
+            let eng = state.engine.lock().unwrap();
+            match eng.session.scrollback_ref() {
+                Some(sb) => Event::BufferStats {
+                    rows: sb.lines(),
+                    lines: sb.history().len() - sb.history().first(),
+                    spilled: sb.history().spilled(),
+                    memory_bytes: sb.memory_bytes(),
+                },
+                None => Event::Error {
+                    message: "no scrollback".to_string(),
+                },
+            }
+        }
+        // The #stats summary: connect time, traffic, MCCP, triggers, latency
+        "stats" => Event::Stats(state.engine.lock().unwrap().session.stats_report(None)),
+        // Traffic totals, rolling rates and the last ping time
+        "net_stats" => {
+            let mut eng = state.engine.lock().unwrap();
+            let (rate_in, rate_out) = eng.session.net.rates(std::time::Instant::now());
+            Event::NetStats {
+                bytes_in: eng.session.stats.bytes_read,
+                bytes_out: eng.session.stats.bytes_written,
+                rate_in,
+                rate_out,
+                rtt_ms: eng.session.net.last_rtt().map(|d| d.as_millis() as u64),
+            }
+        }
+        // Log out gracefully: send `data` (the logout command, if any), shut
+        // the write side and let the MUD hang up; the net loop forces it
+        // after CLOSE_GRACE. "zap" drops the connection at once. Either
+        // way the session buffer stays for review
+        "close" | "zap" => {
+            let state_now = state.sock.lock().unwrap().as_ref().map(|s| s.state);
+            match state_now {
+                None => Event::Error {
+                    message: "not connected".to_string(),
+                },
+                Some(ConnState::Connected) if cmd.cmd == "close" => {
+                    if let Some(logout) = cmd.data.as_deref().filter(|l| !l.is_empty()) {
+                        let line = state.engine.lock().unwrap().session.encode_line(logout);
+                        write_sock(state, &line);
+                    }
+                    if let Some(sock) = state.sock.lock().unwrap().as_ref() {
+                        let _ = sock.shutdown_write();
+                    }
+                    *state.closing.lock().unwrap() =
+                        Some(std::time::Instant::now() + socket::CLOSE_GRACE);
+                    Event::Ok
+                }
+                Some(_) => {
+                    hang_up(state);
+                    Event::Ok
+                }
+            }
+        }
+        // Time the server's answer to IAC AYT (or to `data`, a command line);
+        // the result is pushed to subscribers as a "ping" event
+        "ping" => {
+            let payload = match cmd.data.as_deref() {
+                Some(line) if !line.is_empty() => format!("{}\n", line).into_bytes(),
+                _ => crate::netstats::PING_AYT.to_vec(),
+            };
+            if state.sock.lock().unwrap().is_none() {
+                return Event::Error {
+                    message: "not connected".to_string(),
+                };
+            }
+            let now = std::time::Instant::now();
+            state.engine.lock().unwrap().session.net.start_ping(now);
+            write_sock(state, &payload);
+            Event::Ok
+        }
         // Write raw bytes to the connected socket, if any
         "sock_send" => {
             if let Some(data) = cmd.data {
-                if let Some(sock) = &mut *state.sock.lock().unwrap() {
-                    unsafe {
-                        let _ = libc::write(
-                            sock.as_raw_fd(),
-                            data.as_ptr() as *const libc::c_void,
-                            data.len(),
-                        );
-                    }
+                if write_sock(state, data.as_bytes()) {
                     Event::Ok
                 } else {
                     Event::Error {
@@ -219,6 +1375,105 @@ fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
                 }
             }
         }
+        // Bytes (base64, or the data text) with IAC escaped, or a telnet
+        // subnegotiation IAC SB option ... IAC SE
+        "send_raw" | "send_subneg" => {
+            let bytes = match (cmd.base64, cmd.data) {
+                (Some(b64), _) => decode_base64(&b64),
+                (None, Some(data)) => Some(data.into_bytes()),
+                (None, None) => Some(Vec::new()),
+            };
+            match (bytes, cmd.cmd.as_str(), cmd.option) {
+                (None, _, _) => Event::Error {
+                    message: "bad base64".to_string(),
+                },
+                (Some(_), "send_subneg", None) => Event::Error {
+                    message: "missing option".to_string(),
+                },
+                (Some(bytes), _, option) => {
+                    let written = match state.sock.lock().unwrap().as_ref() {
+                        Some(sock) => match option {
+                            Some(option) => sock.send_subneg(option, &bytes),
+                            None => sock.send_raw(&bytes),
+                        },
+                        None => {
+                            return Event::Error {
+                                message: "not connected".to_string(),
+                            }
+                        }
+                    };
+                    match written {
+                        Ok(n) => {
+                            let mut eng = state.engine.lock().unwrap();
+                            eng.session.record_written(n);
+                            Event::Ok
+                        }
+                        Err(e) => Event::Error {
+                            message: e.to_string(),
+                        },
+                    }
+                }
+            }
+        }
+        // Aliases, as #alias: empty data removes one
+        "add_alias" | "remove_alias" => {
+            let Some(name) = cmd.name else {
+                return Event::Error {
+                    message: "missing name".to_string(),
+                };
+            };
+            let text = cmd.data.unwrap_or_default();
+            let mut automation = state.automation.lock().unwrap();
+            automation.aliases.retain(|a| a.name != name);
+            if cmd.cmd == "add_alias" && !text.is_empty() {
+                let text = crate::command_queue::strip_braces(&text).to_string();
+                automation.aliases.push(AliasDef { name, text });
+            }
+            automation.generation += 1;
+            Event::Ok
+        }
+        "list_aliases" => Event::Aliases {
+            aliases: state.automation.lock().unwrap().aliases.clone(),
+        },
+        // Actions, as #action/#subst/#gag/#highlight: one per pattern
+        "add_trigger" => {
+            let Some(pattern) = cmd.pattern else {
+                return Event::Error {
+                    message: "missing pattern".to_string(),
+                };
+            };
+            let kind = cmd.kind.as_deref().unwrap_or("action");
+            match TriggerDef::new(kind, &pattern, cmd.data.as_deref().unwrap_or("")) {
+                Ok(trigger) => {
+                    let mut automation = state.automation.lock().unwrap();
+                    automation.triggers.retain(|t| t.pattern != pattern);
+                    automation.triggers.push(trigger);
+                    automation.generation += 1;
+                    Event::Ok
+                }
+                Err(message) => Event::Error { message },
+            }
+        }
+        "remove_trigger" => {
+            let Some(pattern) = cmd.pattern else {
+                return Event::Error {
+                    message: "missing pattern".to_string(),
+                };
+            };
+            let mut automation = state.automation.lock().unwrap();
+            let before = automation.triggers.len();
+            automation.triggers.retain(|t| t.pattern != pattern);
+            if automation.triggers.len() == before {
+                return Event::Error {
+                    message: format!("no trigger for {}", pattern),
+                };
+            }
+            automation.generation += 1;
+            Event::Ok
+        }
+        "list_triggers" => Event::Triggers {
+            triggers: state.automation.lock().unwrap().triggers.clone(),
+        },
         "quit" | "shutdown" => {
             std::process::exit(0);
         }
@@ -228,27 +1483,126 @@ fn handle_command(cmd: Command, state: &Arc<ControlState>) -> Event {
     }
 }
 
-fn stream_loop(
-    stream: &mut UnixStream,
-    engine: &Arc<Mutex<SessionEngine<PassthroughDecomp>>>,
-    interval_ms: u64,
-) -> std::io::Result<()> {
-    loop {
-        let lines = {
-            let eng = engine.lock().unwrap();
-            eng.viewport_text()
+/// Built-in #commands (timers, #sendrate/#flush, #help) are handled here
+/// (their message echoed into the buffer), anything else goes to the MUD.
+/// Ok(false) if it needed a connection and there isn't one
+fn run_command_line(state: &ControlState, line: &str) -> Result<bool, String> {
+    let result = {
+        let mut eng = state.engine.lock().unwrap();
+        // A command from a client ends a trigger chain (#loopguard)
+        eng.session.trigger_guard.user_input();
+        eng.builtin_command(line)
+    };
+    match result {
+        Some(Ok(msg)) => {
+            for line in msg.lines() {
+                echo_line(state, line);
+            }
+            Ok(true)
+        }
+        Some(Err(message)) => Err(message),
+        None => Ok(run_expanded(state, line, EXPAND_ALIASES)),
+    }
+}
+
+/// Expand `line` per `flags` with the add_alias aliases and run the result:
+/// #commands in the engine (echoing what they say), the rest sent to the
+/// MUD. false if something couldn't be sent for want of a connection
+fn run_expanded(state: &ControlState, line: &str, flags: u32) -> bool {
+    let mud = state.automation.lock().unwrap().mud();
+    let lines = {
+        let mut eng = state.engine.lock().unwrap();
+        eng.commands
+            .add_with_context(line, flags, false, None, Some(&mud));
+        eng.commands.execute_all()
+    };
+    let mut sent = true;
+    for line in lines {
+        let result = state.engine.lock().unwrap().builtin_command(&line);
+        match result {
+            Some(Ok(msg)) => msg.lines().for_each(|l| echo_line(state, l)),
+            Some(Err(message)) => echo_line(state, &message),
+            None => sent &= send_line(state, &line),
+        }
+    }
+    sent
+}
+
+/// Print a line into the session buffer
+fn echo_line(state: &ControlState, line: &str) {
+    let mut eng = state.engine.lock().unwrap();
+    if let Some(sb) = eng.session.scrollback_mut() {
+        sb.print_line(line.as_bytes(), 0x07);
+    }
+}
+
+/// Queue a command line for the MUD and send what the send rate allows
+/// (the offline world answers at once); false if not connected
+fn send_line(state: &ControlState, line: &str) -> bool {
+    let output = state
+        .world
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(|w| w.command(line));
+    if let Some(output) = output {
+        state.engine.lock().unwrap().session.record_send();
+        feed_and_broadcast(state, output.as_bytes());
+        return true;
+    }
+    if state.sock.lock().unwrap().is_none() {
+        return false;
+    }
+    state.engine.lock().unwrap().queue_send(line);
+    flush_sends(state);
+    true
+}
+
+/// Write the queued commands that are due (plus newlines) to the MUD
+fn flush_sends(state: &ControlState) {
+    let data: Vec<Vec<u8>> = {
+        let mut eng = state.engine.lock().unwrap();
+        let lines = eng.take_sends(std::time::Instant::now());
+        lines.iter().map(|l| eng.session.encode_line(l)).collect()
+    };
+    for buf in data {
+        write_sock(state, &buf);
+    }
+}
+
+/// Fire expired #wait/#tick timers: send to the MUD, or echo into the
+/// buffer when not connected (same as the TTY main loop); also releases
+/// paced sends as the send rate allows
+fn spawn_timer_loop(state: Arc<ControlState>) {
+    thread::spawn(move || loop {
+        let (commands, timeout_ms) = {
+            let mut eng = state.engine.lock().unwrap();
+            let now = std::time::Instant::now();
+            let commands = eng.run_timers(now);
+            (commands, eng.poll_timeout(now, 100))
         };
-        let evt = Event::Buffer { lines };
-        let s = serde_json::to_string(&evt).unwrap();
-        if writeln!(stream, "{}", s).is_err() {
-            break;
+        broadcast_bus(&state);
+        for command in commands {
+            if !send_line(&state, &command) {
+                echo_line(&state, &command);
+            }
         }
-        if stream.flush().is_err() {
-            break;
+        flush_sends(&state);
+        thread::sleep(std::time::Duration::from_millis(timeout_ms.max(10) as u64));
+    });
+}
+
+/// Push viewport snapshots until the client hangs up (false) or another
+/// client attaches (true: the client stays connected as an observer)
+fn stream_loop(state: &ControlState, client: &Client, interval_ms: u64) -> bool {
+    while check_attached(state, client).is_ok() {
+        let lines = state.engine.lock().unwrap().viewport_text();
+        if !client.send(&Event::Buffer { lines }) {
+            return false;
         }
-        std::thread::sleep(std::time::Duration::from_millis(interval_ms));
+        thread::sleep(std::time::Duration::from_millis(interval_ms));
     }
-    Ok(())
+    true
 }
 
 pub fn default_socket_path(instance: &str) -> PathBuf {
@@ -260,18 +1614,17 @@ pub fn default_socket_path(instance: &str) -> PathBuf {
     p
 }
 
-fn resolve_ipv4(addr: &str) -> std::io::Result<(std::net::Ipv4Addr, u16)> {
-    let (host, port_str) = addr
-        .split_once(':')
-        .ok_or_else(|| io_err("expected host:port"))?;
-    let port: u16 = port_str.parse().map_err(|_| io_err("bad port"))?;
-    let mut addrs = (host, port).to_socket_addrs()?;
-    while let Some(sa) = addrs.next() {
-        if let std::net::IpAddr::V4(ip) = sa.ip() {
-            return Ok((ip, sa.port()));
-        }
-    }
-    Err(io_err("no IPv4 address"))
+/// The instance a socket path belongs to (see default_socket_path)
+pub fn instance_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn resolve_addrs(addr: &str) -> std::io::Result<Vec<std::net::SocketAddr>> {
+    let (host, port) =
+        socket::split_host_port(addr).ok_or_else(|| io_err("expected host:port or [v6]:port"))?;
+    socket::resolve(host, port)
 }
 
 fn io_err(msg: &str) -> std::io::Error {
@@ -282,6 +1635,14 @@ fn io_err(msg: &str) -> std::io::Error {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("b2tyb3Mh").unwrap(), b"okros!");
+        assert_eq!(decode_base64("YQ==").unwrap(), b"a");
+        assert_eq!(decode_base64("//8").unwrap(), vec![0xff, 0xff]);
+        assert!(decode_base64("not base64!").is_none());
+    }
+
     #[test]
     fn test_default_socket_path() {
         let path = default_socket_path("test_instance");
@@ -299,43 +1660,145 @@ mod tests {
     }
 
     #[test]
-    fn test_resolve_ipv4_with_ip_and_port() {
-        let result = resolve_ipv4("127.0.0.1:4000");
+    fn test_resolve_addrs_with_ip_and_port() {
+        let result = resolve_addrs("127.0.0.1:4000");
         assert!(result.is_ok());
-        let (ip, port) = result.unwrap();
-        assert_eq!(ip.to_string(), "127.0.0.1");
-        assert_eq!(port, 4000);
+        let addrs = result.unwrap();
+        assert_eq!(addrs[0].ip().to_string(), "127.0.0.1");
+        assert_eq!(addrs[0].port(), 4000);
+    }
+
+    #[test]
+    fn test_resolve_addrs_with_ipv6_literal() {
+        let addrs = resolve_addrs("[::1]:4000").unwrap();
+        assert_eq!(addrs, vec!["[::1]:4000".parse().unwrap()]);
     }
 
     #[test]
-    fn test_resolve_ipv4_with_hostname() {
-        // localhost should resolve to 127.0.0.1
-        let result = resolve_ipv4("localhost:8080");
+    fn test_resolve_addrs_with_hostname() {
+        // localhost should resolve to 127.0.0.1 and/or ::1
+        let result = resolve_addrs("localhost:8080");
         assert!(result.is_ok());
-        let (_ip, port) = result.unwrap();
-        assert_eq!(port, 8080);
+        assert!(result.unwrap().iter().all(|a| a.port() == 8080));
     }
 
     #[test]
-    fn test_resolve_ipv4_missing_port() {
-        let result = resolve_ipv4("127.0.0.1");
+    fn test_resolve_addrs_missing_port() {
+        let result = resolve_addrs("127.0.0.1");
         assert!(result.is_err());
         assert!(result.unwrap_err().to_string().contains("host:port"));
     }
 
     #[test]
-    fn test_resolve_ipv4_bad_port() {
-        let result = resolve_ipv4("127.0.0.1:not_a_port");
+    fn test_resolve_addrs_bad_port() {
+        let result = resolve_addrs("127.0.0.1:not_a_port");
         assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("bad port"));
+        assert!(result.unwrap_err().to_string().contains("host:port"));
     }
 
     #[test]
-    fn test_resolve_ipv4_invalid_host() {
-        let result = resolve_ipv4("this-host-does-not-exist-12345:4000");
+    fn test_resolve_addrs_invalid_host() {
+        let result = resolve_addrs("this-host-does-not-exist-12345:4000");
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_listen_tcp_loopback_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let eng = SessionEngine::new(PassthroughDecomp::new(), 80, 20, 100);
+        let mut srv = ControlServer::new(dir.path().join("t.sock"), eng);
+        assert!(srv.listen_tcp("8.8.8.8:4000").is_err());
+        let token_path = srv.listen_tcp("127.0.0.1:0").unwrap();
+        let token = std::fs::read_to_string(&token_path).unwrap();
+        assert_eq!(token.trim().len(), 32);
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&token_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_tcp_client_must_authenticate() {
+        let dir = tempfile::tempdir().unwrap();
+        let eng = SessionEngine::new(PassthroughDecomp::new(), 80, 20, 100);
+        let mut srv = ControlServer::new(dir.path().join("t.sock"), eng);
+        let token_path = srv.listen_tcp("127.0.0.1:0").unwrap();
+        let addr = srv.tcp.as_ref().unwrap().0.local_addr().unwrap();
+        let token = std::fs::read_to_string(&token_path).unwrap();
+        thread::spawn(move || srv.run());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut ask = |json: String| {
+            writeln!(&stream, "{}", json).unwrap();
+            let mut reply = String::new();
+            reader.read_line(&mut reply).unwrap();
+            reply
+        };
+        assert!(ask(r#"{"cmd":"status"}"#.into()).contains("auth required"));
+        assert!(ask(r#"{"cmd":"auth","data":"nope"}"#.into()).contains("bad token"));
+        let auth = format!(r#"{{"cmd":"auth","data":"{}"}}"#, token.trim());
+        assert_eq!(ask(auth).trim(), r#"{"event":"Ok"}"#);
+        assert!(ask(r#"{"cmd":"status"}"#.into()).contains(r#""event":"Status""#));
+
+        // Before auth, an endless line is cut off and the client dropped
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let junk = vec![b'x'; 1 << 20];
+        let writer = thread::spawn(move || (&stream).write_all(&junk));
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert!(reply.contains("line too long"));
+        reply.clear();
+        assert_eq!(reader.read_line(&mut reply).unwrap_or(0), 0);
+        let _ = writer.join();
+    }
+
+    #[test]
+    fn test_offline_world_plays_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.sock");
+        let eng = SessionEngine::new(PassthroughDecomp::new(), 80, 20, 100);
+        let mut srv = ControlServer::new(path.clone(), eng);
+        srv.offline(OfflineWorld::new());
+        thread::spawn(move || srv.run());
+        while !path.exists() {
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut ask = |json: &str| -> Value {
+            writeln!(&stream, "{}", json).unwrap();
+            let mut reply = String::new();
+            reader.read_line(&mut reply).unwrap();
+            serde_json::from_str(&reply).unwrap()
+        };
+        assert_eq!(ask(r#"{"cmd":"hello"}"#)["features"]["offline"], true);
+        assert_eq!(ask(r#"{"cmd":"status"}"#)["world"]["location"], "clearing");
+        assert_eq!(
+            ask(r#"{"cmd":"send","data":"take rusty sword\n"}"#)["event"],
+            "Ok"
+        );
+        assert_eq!(ask(r#"{"cmd":"command","data":"north"}"#)["event"], "Ok");
+        let status = ask(r#"{"cmd":"status"}"#);
+        assert_eq!(status["world"]["location"], "forest");
+        assert_eq!(status["world"]["inventory_count"], 1);
+        let lines = ask(r#"{"cmd":"peek","lines":50}"#)["lines"].to_string();
+        assert!(lines.contains("Forest Clearing"), "{}", lines);
+        assert!(lines.contains("Dense Forest"), "{}", lines);
+        let connect = ask(r#"{"cmd":"connect","data":"127.0.0.1:4000"}"#);
+        assert_eq!(connect["event"], "Error");
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc", "abc123"));
+        assert!(!tokens_match("abc1234", "abc123"));
+        assert!(!tokens_match("", "abc123"));
+    }
+
     #[test]
     fn test_io_err() {
         let err = io_err("test error message");
@@ -375,12 +1838,148 @@ mod tests {
 
     #[test]
     fn test_event_status_serialization() {
-        let event = Event::Status { attached: true };
+        let event = Event::Status {
+            attached: true,
+            client: 1,
+            attached_by: Some(1),
+            world: None,
+        };
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("\"event\":\"Status\""));
         assert!(json.contains("\"attached\":true"));
     }
 
+    #[test]
+    fn test_event_detached_serialization() {
+        let json = serde_json::to_string(&Event::Detached { by: 2 }).unwrap();
+        assert_eq!(json, r#"{"event":"detached","by":2}"#);
+    }
+
+    #[test]
+    fn test_hello_reports_versions_and_features() {
+        let path = default_socket_path("hello_test");
+        assert_eq!(instance_name(&path), "hello_test");
+        let hello: Value =
+            serde_json::from_str(&Hello::new("hello_test", true, COMMANDS).to_json()).unwrap();
+        assert_eq!(hello["event"], "Hello");
+        assert_eq!(hello["protocol"], PROTOCOL_VERSION);
+        assert_eq!(hello["schema"], EVENT_SCHEMA_VERSION);
+        assert_eq!(hello["instance"], "hello_test");
+        assert_eq!(hello["features"]["offline"], true);
+        assert_eq!(hello["features"]["python"], cfg!(feature = "python"));
+        assert_eq!(hello["features"]["tls"], false);
+        assert!(hello["commands"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("schema")));
+    }
+
+    #[test]
+    fn test_event_schemas_match_serialized_events() {
+        let schemas = event_schemas();
+        let events = [
+            Event::Ok,
+            Event::Error {
+                message: "x".into(),
+            },
+            Event::Status {
+                attached: true,
+                client: 1,
+                attached_by: None,
+                world: None,
+            },
+            Event::Buffer { lines: vec![] },
+            Event::Hex {
+                lines: vec![HexLine {
+                    hex: "48:07".into(),
+                    text: "H".into(),
+                    colors: "07".into(),
+                }],
+            },
+            Event::Grep {
+                matches: vec![GrepMatch {
+                    line: 1,
+                    text: "x".into(),
+                }],
+            },
+            Event::Lines {
+                oldest: 0,
+                total: 1,
+                lines: vec![HistoryLine {
+                    line: 0,
+                    text: "x".into(),
+                    ansi: "x".into(),
+                }],
+            },
+            Event::Fields { fields: vec![] },
+            Event::Screen {
+                rows: vec![],
+                cursor_x: 0,
+                cursor_y: 0,
+            },
+            Event::BufferStats {
+                rows: 1,
+                lines: 1,
+                spilled: 0,
+                memory_bytes: 8,
+            },
+            Event::NetStats {
+                bytes_in: 1,
+                bytes_out: 1,
+                rate_in: 0.5,
+                rate_out: 0.0,
+                rtt_ms: Some(3),
+            },
+            Event::Stats(StatsReport::default()),
+            Event::Line { text: "x".into() },
+            Event::Prompt { text: "> ".into() },
+            Event::Connect {
+                address: "h:1".into(),
+            },
+            Event::Disconnect,
+            Event::Ping { rtt_ms: 3 },
+            Event::TriggerFired {
+                line: "x".into(),
+                commands: vec![],
+            },
+            Event::Timer {
+                command: "x".into(),
+            },
+            Event::Resize {
+                width: 1,
+                height: 1,
+            },
+            Event::Detached { by: 2 },
+            Event::Aliases {
+                aliases: vec![AliasDef {
+                    name: "k".into(),
+                    text: "kill %1".into(),
+                }],
+            },
+            Event::Triggers {
+                triggers: vec![TriggerDef::new("gag", "^spam", "").unwrap()],
+            },
+            Event::Hello(Hello::new("t", false, COMMANDS)),
+            Event::Schema {
+                schema: EVENT_SCHEMA_VERSION,
+                events: json!({}),
+            },
+        ];
+        assert_eq!(schemas.as_object().unwrap().len(), events.len());
+        for event in &events {
+            let value = serde_json::to_value(event).unwrap();
+            let obj = value.as_object().unwrap();
+            let schema = &schemas[obj["event"].as_str().unwrap()];
+            let props = schema["properties"].as_object().unwrap();
+            let mut keys: Vec<&String> = obj.keys().collect();
+            let mut want: Vec<&String> = props.keys().collect();
+            keys.sort();
+            want.sort();
+            assert_eq!(keys, want, "{}", obj["event"]);
+            assert_eq!(schema["required"].as_array().unwrap().len(), props.len());
+        }
+    }
+
     #[test]
     fn test_event_buffer_serialization() {
         let event = Event::Buffer {
@@ -391,6 +1990,81 @@ mod tests {
         assert!(json.contains("\"lines\""));
     }
 
+    #[test]
+    fn test_event_fields_serialization() {
+        let event = Event::Fields {
+            fields: vec![StatusField {
+                name: "hp".to_string(),
+                text: "HP 10".to_string(),
+                priority: 5,
+            }],
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"event\":\"Fields\""));
+        assert!(json.contains("\"name\":\"hp\""));
+        assert!(json.contains("\"priority\":5"));
+    }
+
+    #[test]
+    fn test_event_screen_serialization() {
+        let evt = Event::Screen {
+            rows: vec!["hi".to_string()],
+            cursor_x: 4,
+            cursor_y: 20,
+        };
+        assert_eq!(
+            serde_json::to_string(&evt).unwrap(),
+            r#"{"event":"Screen","rows":["hi"],"cursor_x":4,"cursor_y":20}"#
+        );
+    }
+
+    #[test]
+    fn test_event_buffer_stats_serialization() {
+        let evt = Event::BufferStats {
+            rows: 2000,
+            lines: 12,
+            spilled: 0,
+            memory_bytes: 4096,
+        };
+        assert_eq!(
+            serde_json::to_string(&evt).unwrap(),
+            r#"{"event":"BufferStats","rows":2000,"lines":12,"spilled":0,"memory_bytes":4096}"#
+        );
+    }
+
+    #[test]
+    fn test_push_event_serialization() {
+        let line: Event = BusEvent::Line("You see a troll.".to_string()).into();
+        assert_eq!(
+            serde_json::to_string(&line).unwrap(),
+            r#"{"event":"line","text":"You see a troll."}"#
+        );
+        let fired: Event = BusEvent::TriggerFired {
+            line: "You are hungry".to_string(),
+            commands: vec!["eat bread".to_string()],
+        }
+        .into();
+        assert_eq!(
+            serde_json::to_string(&fired).unwrap(),
+            r#"{"event":"trigger_fired","line":"You are hungry","commands":["eat bread"]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::Disconnect).unwrap(),
+            r#"{"event":"disconnect"}"#
+        );
+        let resize: Event = BusEvent::Resize {
+            width: 80,
+            height: 24,
+        }
+        .into();
+        assert_eq!(
+            serde_json::to_string(&resize).unwrap(),
+            r#"{"event":"resize","width":80,"height":24}"#
+        );
+        assert_eq!(resize.bus_kind(), Some(EventKind::Resize));
+        assert_eq!(Event::Ok.bus_kind(), None);
+    }
+
     #[test]
     fn test_command_deserialization_basic() {
         let json = r#"{"cmd":"status"}"#;
@@ -424,6 +2098,19 @@ mod tests {
     }
 }
 
+/// Feed a recording to the engine at its pace, as the net loop would
+fn spawn_replay_loop(state: Arc<ControlState>, mut replay: Replay) {
+    thread::spawn(move || {
+        while !replay.is_done() {
+            for chunk in replay.take_due(std::time::Instant::now()) {
+                feed_and_broadcast(&state, &chunk);
+            }
+            let wait = replay.poll_timeout(std::time::Instant::now(), 250);
+            thread::sleep(std::time::Duration::from_millis(wait.max(1) as u64));
+        }
+    });
+}
+
 fn spawn_net_loop(state: Arc<ControlState>) {
     thread::spawn(move || loop {
         let fd_ev = {
@@ -441,15 +2128,39 @@ fn spawn_net_loop(state: Arc<ControlState>) {
         if fd_ev.is_none() {
             break;
         }
+        if state
+            .closing
+            .lock()
+            .unwrap()
+            .is_some_and(|deadline| std::time::Instant::now() >= deadline)
+        {
+            hang_up(&state);
+            continue;
+        }
         let (fd, ev) = fd_ev.unwrap();
         let ready = poll_fds(&[(fd, ev)], 200).unwrap_or_default();
+        // A slow connect gets the next resolved address raced against it
+        let raced = state
+            .sock
+            .lock()
+            .unwrap()
+            .as_mut()
+            .is_some_and(|s| s.tick());
+        if raced {
+            let address = state.address.lock().unwrap().clone();
+            state.engine.lock().unwrap().connected(&address);
+            broadcast_bus(&state);
+        }
         for (_fd, r) in ready {
             let mut drop_sock = false;
+            let mut connected = false;
+            let mut inbound = Vec::new();
             {
                 let mut s = state.sock.lock().unwrap();
                 if let Some(sock) = s.as_mut() {
                     if (r.revents & WRITE) != 0 && sock.state == ConnState::Connecting {
                         let _ = sock.on_writable();
+                        connected = sock.state == ConnState::Connected;
                     }
                     if (r.revents & READ) != 0 {
                         let mut buf = [0u8; 4096];
@@ -461,17 +2172,42 @@ fn spawn_net_loop(state: Arc<ControlState>) {
                             )
                         };
                         if n > 0 {
-                            let mut eng = state.engine.lock().unwrap();
-                            eng.feed_inbound(&buf[..n as usize]);
+                            inbound.extend_from_slice(&buf[..n as usize]);
+                            let mut recorder = state.recorder.lock().unwrap();
+                            if let Some(Err(e)) = recorder.as_mut().map(|r| r.write(&inbound)) {
+                                eprintln!("control: recording stopped: {}", e);
+                                *recorder = None;
+                            }
                         } else if n == 0 {
                             drop_sock = true;
                         }
                     }
                 }
             }
+            if connected {
+                let address = state.address.lock().unwrap().clone();
+                state.engine.lock().unwrap().connected(&address);
+                broadcast_bus(&state);
+            }
+            if !inbound.is_empty() {
+                feed_and_broadcast(&state, &inbound);
+            }
             if drop_sock {
-                *state.sock.lock().unwrap() = None;
+                hang_up(&state);
             }
         }
     });
 }
+
+/// Drop the MUD connection and tell everyone (disconnect event)
+fn hang_up(state: &ControlState) {
+    *state.sock.lock().unwrap() = None;
+    *state.closing.lock().unwrap() = None;
+    {
+        let mut eng = state.engine.lock().unwrap();
+        eng.clear_sends();
+        eng.session.net.reset();
+        eng.disconnected();
+    }
+    broadcast_bus(state);
+}
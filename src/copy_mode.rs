@@ -0,0 +1,243 @@
+// CopyMode - keyboard selection of scrollback text
+//
+// Copy mode freezes the output window and moves a cursor over the
+// scrollback buffer; a selection runs from an anchor to the cursor and is
+// drawn with Scrollback::highlight_view. The copied text goes to the
+// caller, which keeps it as the paste buffer for the input line and hands
+// it to the terminal clipboard (OSC 52).
+//
+//   arrows / h j k l   move            PgUp / PgDn   move a page
+//   Home / 0, End / $  line start/end  g / G         oldest / newest row
+//   v / space          start/drop the selection
+//   y / Enter          copy (the cursor's row if nothing is selected)
+//   Esc / q            leave copy mode
+
+use crate::input::{KeyCode, KeyEvent};
+use crate::output_window::OutputWindow;
+use crate::scrollback::Attrib;
+
+/// What a keypress did
+#[derive(Debug, PartialEq, Eq)]
+pub enum CopyStep {
+    Continue,
+    Cancel,
+    Copy(String),
+}
+
+pub struct CopyMode {
+    row: usize, // Cursor, in scrollback rows/columns
+    col: usize,
+    anchor: Option<(usize, usize)>,
+}
+
+impl CopyMode {
+    /// Freeze the output and put the cursor at the start of the newest row
+    pub fn new(ow: &mut OutputWindow) -> Self {
+        ow.freeze();
+        let mode = Self {
+            row: ow.cursor_row(),
+            col: 0,
+            anchor: None,
+        };
+        mode.update(ow);
+        mode
+    }
+
+    /// Handle a key; after Cancel or Copy the output window is back to normal
+    pub fn keypress(&mut self, ev: KeyEvent, ow: &mut OutputWindow) -> CopyStep {
+        let page = ow.sb.height.max(1) as isize;
+        match ev {
+            KeyEvent::Key(KeyCode::ArrowUp) | KeyEvent::Byte(b'k') => self.move_by(ow, -1, 0),
+            KeyEvent::Key(KeyCode::ArrowDown) | KeyEvent::Byte(b'j') => self.move_by(ow, 1, 0),
+            KeyEvent::Key(KeyCode::ArrowLeft) | KeyEvent::Byte(b'h') => self.move_by(ow, 0, -1),
+            KeyEvent::Key(KeyCode::ArrowRight) | KeyEvent::Byte(b'l') => self.move_by(ow, 0, 1),
+            KeyEvent::Key(KeyCode::PageUp) => self.move_by(ow, -page, 0),
+            KeyEvent::Key(KeyCode::PageDown) => self.move_by(ow, page, 0),
+            KeyEvent::Key(KeyCode::Home) | KeyEvent::Byte(b'0') => self.col = 0,
+            KeyEvent::Key(KeyCode::End) | KeyEvent::Byte(b'$') => {
+                self.col = row_len(row_cells(ow, self.row)).saturating_sub(1)
+            }
+            KeyEvent::Byte(b'g') => self.row = 0,
+            KeyEvent::Byte(b'G') => self.row = ow.cursor_row(),
+            KeyEvent::Byte(b'v') | KeyEvent::Byte(b' ') => {
+                self.anchor = match self.anchor {
+                    Some(_) => None,
+                    None => Some((self.row, self.col)),
+                }
+            }
+            KeyEvent::Byte(b'y') | KeyEvent::Byte(b'\n') => {
+                let text = match self.anchor {
+                    Some(anchor) => {
+                        let (from, to) = if anchor <= (self.row, self.col) {
+                            (anchor, (self.row, self.col))
+                        } else {
+                            ((self.row, self.col), anchor)
+                        };
+                        selected_text(ow, from, to)
+                    }
+                    None => selected_text(ow, (self.row, 0), (self.row, ow.sb.width - 1)),
+                };
+                self.finish(ow);
+                return CopyStep::Copy(text);
+            }
+            KeyEvent::Key(KeyCode::Escape) | KeyEvent::Byte(b'q') => {
+                self.finish(ow);
+                return CopyStep::Cancel;
+            }
+            _ => {}
+        }
+        self.update(ow);
+        CopyStep::Continue
+    }
+
+    fn move_by(&mut self, ow: &OutputWindow, rows: isize, cols: isize) {
+        self.row = self.row.saturating_add_signed(rows).min(ow.cursor_row());
+        self.col = self.col.saturating_add_signed(cols).min(ow.sb.width - 1);
+    }
+
+    /// Scroll the cursor into view and redraw the selection
+    fn update(&self, ow: &mut OutputWindow) {
+        let width = ow.sb.width;
+        let top = ow.sb.viewpoint / width;
+        if self.row < top {
+            ow.sb.viewpoint = self.row * width;
+        } else if self.row >= top + ow.sb.height {
+            ow.sb.viewpoint = ((self.row + 1 - ow.sb.height) * width).min(ow.sb.canvas_ptr());
+        }
+        let cursor = self.row * width + self.col;
+        let anchor = self.anchor.map_or(cursor, |(r, c)| r * width + c);
+        ow.set_selection(Some(anchor.min(cursor)..anchor.max(cursor) + 1));
+    }
+
+    fn finish(&self, ow: &mut OutputWindow) {
+        ow.set_selection(None);
+        ow.unfreeze();
+    }
+}
+
+fn row_cells(ow: &OutputWindow, row: usize) -> &[Attrib] {
+    let width = ow.sb.width;
+    ow.sb
+        .buf
+        .get(row * width..(row + 1) * width)
+        .unwrap_or_default()
+}
+
+/// Cells up to the last printed character
+fn row_len(cells: &[Attrib]) -> usize {
+    cells
+        .iter()
+        .rposition(|&a| (a & 0xFF) != 0 && (a & 0xFF) != b' ' as Attrib)
+        .map_or(0, |i| i + 1)
+}
+
+/// Text between two (row, col) positions, inclusive; rows end in newlines
+/// except where a full row wrapped onto the next
+fn selected_text(ow: &OutputWindow, from: (usize, usize), to: (usize, usize)) -> String {
+    let width = ow.sb.width;
+    let mut text = String::new();
+    for row in from.0..=to.0 {
+        let cells = row_cells(ow, row);
+        let first = if row == from.0 { from.1 } else { 0 };
+        let last = if row == to.0 { to.1 + 1 } else { width };
+        let end = last.min(row_len(cells));
+        if first < end {
+            text.extend(
+                cells[first..end]
+                    .iter()
+                    .map(|a| (a & 0xFF) as u8)
+                    .filter(|&b| b != 0)
+                    .map(char::from),
+            );
+        }
+        if row < to.0 && row_len(cells) < width {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// OSC 52 sequence putting text on the terminal's clipboard
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[((n >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn output() -> OutputWindow {
+        let mut ow = OutputWindow::new(ptr::null_mut(), 10, 3, 50, 0x07);
+        ow.print(b"alpha\nbravo two\nlong lines wrap\ndelta", 0x07);
+        ow
+    }
+
+    fn keys(mode: &mut CopyMode, ow: &mut OutputWindow, keys: &[u8]) -> CopyStep {
+        let mut step = CopyStep::Continue;
+        for &k in keys {
+            step = mode.keypress(KeyEvent::Byte(k), ow);
+        }
+        step
+    }
+
+    #[test]
+    fn copies_a_selection_across_rows() {
+        let mut ow = output();
+        let mut mode = CopyMode::new(&mut ow);
+        assert!(ow.sb.is_frozen());
+        // Rows: alpha / bravo two / long lines / " wrap" / delta
+        assert_eq!(
+            keys(&mut mode, &mut ow, b"kkkllllllv$jjy"),
+            CopyStep::Copy("two\nlong lines wrap".to_string())
+        );
+        assert!(!ow.sb.is_frozen());
+    }
+
+    #[test]
+    fn copies_the_cursor_row_and_scrolls_to_it() {
+        let mut ow = output();
+        let mut mode = CopyMode::new(&mut ow);
+        keys(&mut mode, &mut ow, b"g");
+        assert_eq!(ow.sb.viewpoint, 0);
+        ow.redraw();
+        // The cursor cell is highlighted (inverse video)
+        assert_eq!(ow.viewport()[0] >> 8, 0x70);
+        assert_eq!(
+            keys(&mut mode, &mut ow, b"y"),
+            CopyStep::Copy("alpha".into())
+        );
+
+        let mut mode = CopyMode::new(&mut ow);
+        assert_eq!(
+            mode.keypress(KeyEvent::Key(KeyCode::Escape), &mut ow),
+            CopyStep::Cancel
+        );
+    }
+
+    #[test]
+    fn osc52_encodes_base64() {
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+        assert_eq!(base64(b"okros!"), "b2tyb3Mh");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+}
@@ -3,7 +3,9 @@
 //! Ported from: Curses.cc
 //! MCL uses ncurses minimally - only for terminal setup and capability queries
 
+use crate::input::{Arrow, KeyCode};
 use std::ffi::{CStr, CString};
+use std::sync::OnceLock;
 
 // =============================================================================
 // FFI declarations
@@ -130,6 +132,205 @@ pub fn get_acs_caps() -> AcsCaps {
     }
 }
 
+// =============================================================================
+// Terminal capabilities beyond ACS
+// =============================================================================
+
+/// Cursor addressing from terminfo `cup`, for the
+/// `<prefix>[%i]%p1%d<separator>%p2%d<suffix>` shape every VT100
+/// descendant uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorAddress {
+    prefix: String,
+    separator: String,
+    suffix: String,
+    origin: usize, // 1 with %i
+}
+
+impl Default for CursorAddress {
+    fn default() -> Self {
+        Self::ansi()
+    }
+}
+
+impl CursorAddress {
+    /// ESC [ row ; col H
+    pub fn ansi() -> Self {
+        Self {
+            prefix: "\x1b[".into(),
+            separator: ";".into(),
+            suffix: "H".into(),
+            origin: 1,
+        }
+    }
+
+    /// None for a `cup` this can't express (arithmetic, %c ...)
+    pub fn parse(cup: &str) -> Option<Self> {
+        let cup = strip_padding(cup);
+        let (prefix, rest) = cup.split_once('%')?;
+        let (origin, rest) = match rest.strip_prefix("i%") {
+            Some(rest) => (1, rest),
+            None => (0, rest),
+        };
+        let rest = rest.strip_prefix("p1%d")?;
+        let (separator, rest) = rest.split_once("%p2%d")?;
+        if separator.contains('%') || rest.contains('%') {
+            return None;
+        }
+        Some(Self {
+            prefix: prefix.to_string(),
+            separator: separator.to_string(),
+            suffix: rest.to_string(),
+            origin,
+        })
+    }
+
+    /// Move to row `y`, column `x` (0-based)
+    pub fn push(&self, out: &mut String, y: usize, x: usize) {
+        use std::fmt::Write;
+        let _ = write!(
+            out,
+            "{}{}{}{}{}",
+            self.prefix,
+            y + self.origin,
+            self.separator,
+            x + self.origin,
+            self.suffix
+        );
+    }
+}
+
+/// Drop terminfo padding ($<5>, $<2*/>): okros never waits on the terminal
+fn strip_padding(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("$<") {
+        out.push_str(&rest[..i]);
+        match rest[i..].find('>') {
+            Some(end) => rest = &rest[i + end + 1..],
+            None => {
+                rest = &rest[i..];
+                break;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// What the screen code sends besides colors (those stay ANSI SGR), from
+/// terminfo. Empty strings are capabilities the terminal doesn't have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermCaps {
+    pub smcup: String, // Enter the alternate screen
+    pub rmcup: String, // Leave it, restoring what was there before
+    pub clear: String,
+    pub el: String,    // Clear to the end of the line
+    pub civis: String, // Hide the cursor
+    pub cnorm: String, // Show it
+    pub sgr0: String,
+    pub smkx: String, // Keypad transmit (application) mode on
+    pub rmkx: String,
+    pub csr: bool, // Has a scrolling region
+    pub cup: CursorAddress,
+    pub keys: Vec<(Vec<u8>, KeyCode)>, // Key sequences the terminal sends
+}
+
+/// Terminfo key capabilities and the keys they are
+const KEY_CAPS: &[(&str, KeyCode)] = &[
+    ("kcuu1", KeyCode::ArrowUp),
+    ("kcud1", KeyCode::ArrowDown),
+    ("kcuf1", KeyCode::ArrowRight),
+    ("kcub1", KeyCode::ArrowLeft),
+    ("kLFT", KeyCode::ShiftArrow(Arrow::Left)),
+    ("kRIT", KeyCode::ShiftArrow(Arrow::Right)),
+    ("khome", KeyCode::Home),
+    ("kend", KeyCode::End),
+    ("kpp", KeyCode::PageUp),
+    ("knp", KeyCode::PageDown),
+    ("kich1", KeyCode::Insert),
+    ("kdch1", KeyCode::Delete),
+    ("kf1", KeyCode::F(1)),
+    ("kf2", KeyCode::F(2)),
+    ("kf3", KeyCode::F(3)),
+    ("kf4", KeyCode::F(4)),
+    ("kf5", KeyCode::F(5)),
+    ("kf6", KeyCode::F(6)),
+    ("kf7", KeyCode::F(7)),
+    ("kf8", KeyCode::F(8)),
+    ("kf9", KeyCode::F(9)),
+    ("kf10", KeyCode::F(10)),
+    ("kf11", KeyCode::F(11)),
+    ("kf12", KeyCode::F(12)),
+];
+
+impl Default for TermCaps {
+    fn default() -> Self {
+        Self::vt100()
+    }
+}
+
+impl TermCaps {
+    /// xterm's strings, for when there is no terminfo entry at all. The
+    /// keys are left to KeyDecoder, which knows the VT100 family's
+    pub fn vt100() -> Self {
+        Self {
+            smcup: "\x1b[?1049h".into(),
+            rmcup: "\x1b[?1049l".into(),
+            clear: "\x1b[H\x1b[2J".into(),
+            el: "\x1b[K".into(),
+            civis: "\x1b[?25l".into(),
+            cnorm: "\x1b[?25h".into(),
+            sgr0: "\x1b[0m".into(),
+            smkx: "\x1b=".into(),
+            rmkx: "\x1b>".into(),
+            csr: true,
+            cup: CursorAddress::ansi(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// From a terminfo lookup. Missing optional capabilities stay empty;
+    /// clear, el, sgr0 and a cup that CursorAddress can't use fall back to VT100
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |name: &str| lookup(name).map(|s| strip_padding(&s));
+        let vt = Self::vt100();
+        Self {
+            smcup: get("smcup").unwrap_or_default(),
+            rmcup: get("rmcup").unwrap_or_default(),
+            clear: get("clear").unwrap_or(vt.clear),
+            el: get("el").unwrap_or(vt.el),
+            civis: get("civis").unwrap_or_default(),
+            cnorm: get("cnorm").unwrap_or_default(),
+            sgr0: get("sgr0").unwrap_or(vt.sgr0),
+            smkx: get("smkx").unwrap_or_default(),
+            rmkx: get("rmkx").unwrap_or_default(),
+            csr: lookup("csr").is_some(),
+            cup: lookup("cup")
+                .and_then(|c| CursorAddress::parse(&c))
+                .unwrap_or(vt.cup),
+            keys: KEY_CAPS
+                .iter()
+                .filter_map(|(name, key)| Some((get(name)?.into_bytes(), *key)))
+                .collect(),
+        }
+    }
+}
+
+static TERM_CAPS: OnceLock<TermCaps> = OnceLock::new();
+
+/// The terminal's capabilities ($TERM's terminfo entry), VT100 if it has none
+pub fn get_term_caps() -> TermCaps {
+    TERM_CAPS
+        .get_or_init(|| unsafe {
+            match init_curses() {
+                Ok(()) => TermCaps::from_lookup(|name| get_capability(name)),
+                Err(_) => TermCaps::vt100(),
+            }
+        })
+        .clone()
+}
+
 /// Get ACS character codes from ncurses
 ///
 /// C++ equivalent (Curses.cc):
@@ -190,6 +391,38 @@ mod tests {
         println!("rmacs: {:?}", caps.rmacs);
     }
 
+    #[test]
+    fn cursor_address_from_cup() {
+        let mut out = String::new();
+        let xterm = CursorAddress::parse("\x1b[%i%p1%d;%p2%dH").unwrap();
+        assert_eq!(xterm, CursorAddress::ansi());
+        xterm.push(&mut out, 0, 9);
+        assert_eq!(out, "\x1b[1;10H");
+        // 0-based with padding; arithmetic and %c can't be expressed
+        out.clear();
+        let odd = CursorAddress::parse("\x1b&a%p1%dy%p2%dC$<6>").unwrap();
+        odd.push(&mut out, 2, 3);
+        assert_eq!(out, "\x1b&a2y3C");
+        assert_eq!(CursorAddress::parse("\x1b=%p1%' '%+%c%p2%' '%+%c"), None);
+    }
+
+    #[test]
+    fn term_caps_fall_back_per_capability() {
+        // A linux-console-like entry: no alternate screen, odd cup
+        let caps = TermCaps::from_lookup(|name| match name {
+            "clear" => Some("\x1b[H\x1b[J$<50>".into()),
+            "cup" => Some("\x1b=%p1%' '%+%c%p2%' '%+%c".into()),
+            "kf1" => Some("\x1b[[A".into()),
+            _ => None,
+        });
+        assert_eq!(caps.smcup, "");
+        assert_eq!(caps.clear, "\x1b[H\x1b[J");
+        assert_eq!(caps.sgr0, "\x1b[0m");
+        assert_eq!(caps.cup, CursorAddress::ansi());
+        assert!(!caps.csr);
+        assert_eq!(caps.keys, vec![(b"\x1b[[A".to_vec(), KeyCode::F(1))]);
+    }
+
     #[test]
     fn test_get_acs_codes() {
         if !has_tty() {
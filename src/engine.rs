@@ -1,12 +1,178 @@
+// SessionEngine - the mode-independent core of a client session
+//
+// Owns the Session pipeline, the command queue (and its #var variables),
+// #wait/#tick timers, paced sends and the event bus. Front-ends drive it: the
+// TTY loop (TtyUi, Session attached to its OutputWindow) and the control
+// server (headless, own scrollback), each playing either a MUD socket or the
+// OfflineWorld.
+
+use crate::command_queue::CommandQueue;
+use crate::command_registry::{CommandRegistry, Dispatch};
+use crate::event_bus::{BusEvent, EventBus};
+use crate::headless_screen::{HeadlessScreen, ScreenSnapshot};
+use crate::keepalive::{Keepalive, KeepaliveSend};
 use crate::mccp::Decompressor;
-use crate::session::Session;
+use crate::mud::Mud;
+use crate::plugins::stack::Interpreter;
+use crate::scrollback::{GrepMatch, HistoryLine};
+use crate::send_queue::SendQueue;
+use crate::session::{Session, SessionEvent};
+use crate::status_line::StatusFields;
+use crate::timer::TimerQueue;
+use regex::Regex;
 use std::cell::RefCell;
+use std::time::Instant;
 
 pub struct SessionEngine<D: Decompressor> {
     pub session: Session<D>,
     attached: bool,
     ansi_cache: RefCell<Option<Vec<String>>>,
     read_cursor: RefCell<usize>, // Track which lines have been read in headless mode
+    pub commands: CommandQueue,  // Typed lines awaiting alias/semicolon expansion
+    pub timers: TimerQueue,      // #wait / #tick
+    pub sends: SendQueue,        // Paced commands bound for the MUD (#sendrate)
+    pub status: StatusFields,    // Status line fields (set via control protocol)
+    pub builtins: CommandRegistry<Self>, // #commands (front-ends add their own)
+    pub bus: EventBus,           // Connects, lines, timers... for scripts and clients
+    screen: Option<HeadlessScreen>, // TTY-style composition for remote attach (lazy)
+}
+
+/// Headless screen sized to the session's scrollback (plus the input line)
+fn new_screen<D: Decompressor>(session: &Session<D>) -> HeadlessScreen {
+    let (width, height) = session
+        .scrollback_ref()
+        .map(|sb| (sb.width, sb.height))
+        .unwrap_or((80, 24));
+    HeadlessScreen::new(width, height)
+}
+
+fn timer_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    name: &str,
+    args: &str,
+) -> Result<String, String> {
+    let result = eng.timers.command(name, args, Instant::now());
+    result.unwrap_or_else(|| Err(format!("Unknown command #{}", name)))
+}
+
+fn send_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    name: &str,
+    args: &str,
+) -> Result<String, String> {
+    let result = eng.sends.command(name, args);
+    eng.update_queue_field();
+    result.unwrap_or_else(|| Err(format!("Unknown command #{}", name)))
+}
+
+fn var_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    name: &str,
+    args: &str,
+) -> Result<String, String> {
+    let result = eng.commands.variables.command(name, args);
+    result.unwrap_or_else(|| Err(format!("Unknown command #{}", name)))
+}
+
+/// #debug net: the network trace
+fn debug_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    _name: &str,
+    args: &str,
+) -> Result<String, String> {
+    crate::net_debug::debug_command(args, &mut eng.session.net_debug)
+}
+
+fn filter_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    _name: &str,
+    args: &str,
+) -> Result<String, String> {
+    eng.session.filters.command(args)
+}
+
+fn loopguard_builtin<D: Decompressor>(
+    eng: &mut SessionEngine<D>,
+    _name: &str,
+    args: &str,
+) -> Result<String, String> {
+    eng.session.trigger_guard.command(args)
+}
+
+fn profile_builtin<D: Decompressor>(
+    _eng: &mut SessionEngine<D>,
+    _name: &str,
+    args: &str,
+) -> Result<String, String> {
+    crate::profile::command(args)
+}
+
+/// Commands every front-end shares
+fn engine_builtins<D: Decompressor>() -> CommandRegistry<SessionEngine<D>> {
+    let mut reg = CommandRegistry::new();
+    reg.register(
+        "wait",
+        "#wait <seconds> <command>",
+        "Run a command once after a delay",
+        timer_builtin,
+    );
+    reg.register(
+        "tick",
+        "#tick [<seconds> <command>]",
+        "Run a command repeatedly; no arguments lists the timers",
+        timer_builtin,
+    );
+    reg.register("untick", "#untick <id>|all", "Cancel timers", timer_builtin);
+    reg.register(
+        "var",
+        "#var [<name> [value]]",
+        "Set a variable ($name or %{name} in commands); no value shows it, no name lists them",
+        var_builtin,
+    );
+    reg.register("unvar", "#unvar <name>", "Remove a variable", var_builtin);
+    reg.register(
+        "sendrate",
+        "#sendrate [<per-second>|off [burst]]",
+        "Pace commands sent to the MUD",
+        send_builtin,
+    );
+    reg.register(
+        "send",
+        "#send <text>",
+        "Send text to the MUD as typed (no alias or speedwalk expansion)",
+        send_builtin,
+    );
+    reg.register(
+        "flush",
+        "#flush",
+        "Drop commands waiting in the send queue",
+        send_builtin,
+    );
+    reg.register(
+        "debug",
+        "#debug net [on [<file>]|off]",
+        "Hex dump everything read from the MUD to a log file",
+        debug_builtin,
+    );
+    reg.register(
+        "filter",
+        "#filter [on|off <stage> | add <name> <function> [before <stage>] | remove <name>]",
+        "List the output filter stages, switch them, or add script ones",
+        filter_builtin,
+    );
+    reg.register(
+        "profile",
+        "#profile [on|off|reset|<count>]",
+        "Time trigger patterns and script hooks; list the slowest",
+        profile_builtin,
+    );
+    reg.register(
+        "loopguard",
+        "#loopguard [<depth>|off | gag on|off]",
+        "Stop trigger chains past a depth; gag the MUD's echoes of trigger commands",
+        loopguard_builtin,
+    );
+    reg
 }
 
 impl<D: Decompressor> SessionEngine<D> {
@@ -16,7 +182,175 @@ impl<D: Decompressor> SessionEngine<D> {
             attached: true,
             ansi_cache: RefCell::new(None),
             read_cursor: RefCell::new(0),
+            commands: CommandQueue::new(),
+            timers: TimerQueue::new(),
+            sends: SendQueue::new(),
+            status: StatusFields::new(),
+            builtins: engine_builtins(),
+            bus: EventBus::new(),
+            screen: None,
+        }
+    }
+
+    /// Composite the screen the way TTY mode would show it
+    pub fn screen_snapshot(&mut self) -> ScreenSnapshot {
+        let Self {
+            session,
+            screen,
+            status,
+            ..
+        } = self;
+        let screen = screen.get_or_insert_with(|| new_screen(session));
+        screen.render(
+            session.scrollback_ref(),
+            session.current_line_colored(),
+            "",
+            status,
+        )
+    }
+
+    /// Raw key bytes from a remote client; returns command lines entered
+    pub fn key_input(&mut self, bytes: &[u8]) -> Vec<String> {
+        let Self {
+            session, screen, ..
+        } = self;
+        let screen = screen.get_or_insert_with(|| new_screen(session));
+        let commands = screen.keys(bytes, session.scrollback_mut());
+        // Paging moves the viewport
+        *self.ansi_cache.borrow_mut() = None;
+        commands
+    }
+
+    /// Handle a #wait/#tick/#untick command line; None if it isn't one
+    pub fn timer_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        self.timers.handle_command(line, Instant::now())
+    }
+
+    /// Commands whose timers expired at `now`, in due order
+    pub fn run_timers(&mut self, now: Instant) -> Vec<String> {
+        self.run_timers_active(now, |_| true)
+    }
+
+    /// run_timers(), skipping timers whose group isn't `active`; each
+    /// command run is published as a timer event
+    pub fn run_timers_active(
+        &mut self,
+        now: Instant,
+        active: impl Fn(Option<&str>) -> bool,
+    ) -> Vec<String> {
+        let commands = self.timers.expire_active(now, active);
+        for command in &commands {
+            self.bus.publish(BusEvent::Timer {
+                command: command.clone(),
+            });
+        }
+        commands
+    }
+
+    /// Session events since the last call, also published on the bus
+    pub fn take_events(&mut self) -> Vec<SessionEvent> {
+        let events = self.session.take_events();
+        for event in &events {
+            self.bus.publish(event.into());
         }
+        events
+    }
+
+    /// The connection to `address` is up: counted for #stats and published
+    pub fn connected(&mut self, address: &str) {
+        self.session.record_connected();
+        self.bus.publish(BusEvent::Connect {
+            address: address.to_string(),
+        });
+    }
+
+    /// The MUD closed the connection
+    pub fn disconnected(&mut self) {
+        self.bus.publish(BusEvent::Disconnect);
+    }
+
+    /// Handle a #sendrate/#flush command line; None if it isn't one
+    pub fn send_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        let result = self.sends.handle_command(line)?;
+        self.update_queue_field();
+        Some(result)
+    }
+
+    /// Look a typed line up in the command registry, running the engine's
+    /// commands (#wait/#tick/#untick, #sendrate/#flush) and #help here
+    pub fn command<'a>(&mut self, line: &'a str) -> Dispatch<'a> {
+        // Handlers get the whole engine, so the registry steps out meanwhile
+        let builtins = std::mem::take(&mut self.builtins);
+        let result = builtins.dispatch(self, line);
+        self.builtins = builtins;
+        result
+    }
+
+    /// Client commands every front-end shares (the engine's own, #help, or
+    /// an unknown #command's error); None for anything else
+    pub fn builtin_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        match self.command(line) {
+            Dispatch::Done(result) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Clamp a poll timeout (ms) so the loop wakes for the next timer or send
+    pub fn poll_timeout(&self, now: Instant, max_ms: i32) -> i32 {
+        self.sends
+            .poll_timeout(now, self.timers.poll_timeout(now, max_ms))
+    }
+
+    /// A #keepalive that has come due (nothing written for its interval):
+    /// a command goes on the send queue, IAC NOP is returned for the caller
+    /// to write. Nothing is due while sends are waiting
+    pub fn keepalive(&mut self, keepalive: &Keepalive, now: Instant) -> Option<Vec<u8>> {
+        if !self.sends.is_empty() || !keepalive.due(self.session.net.idle_out(now)) {
+            return None;
+        }
+        match &keepalive.send {
+            KeepaliveSend::Nop => {
+                Some(vec![crate::telnet::telnet::IAC, crate::telnet::telnet::NOP])
+            }
+            KeepaliveSend::Command(command) => {
+                self.queue_send(command);
+                None
+            }
+        }
+    }
+
+    /// Queue a command for the MUD; take_sends() releases it when the rate allows
+    pub fn queue_send(&mut self, line: &str) {
+        self.sends.push(line);
+        self.update_queue_field();
+    }
+
+    /// Queued commands that may be sent at `now`
+    pub fn take_sends(&mut self, now: Instant) -> Vec<String> {
+        let ready = self.sends.ready(now);
+        if !ready.is_empty() {
+            for _ in &ready {
+                self.session.record_send();
+            }
+            self.update_queue_field();
+        }
+        ready
+    }
+
+    /// Drop queued commands (connection lost)
+    pub fn clear_sends(&mut self) -> usize {
+        let n = self.sends.clear();
+        self.update_queue_field();
+        n
+    }
+
+    /// Show the queued-command count in the status fields
+    fn update_queue_field(&mut self) {
+        self.status.set(
+            "queue",
+            &self.sends.status_text(),
+            crate::status_line::PRIORITY_QUEUE,
+        );
     }
 
     pub fn detach(&mut self) {
@@ -36,6 +370,13 @@ impl<D: Decompressor> SessionEngine<D> {
         *self.ansi_cache.borrow_mut() = None;
     }
 
+    /// feed_inbound(), applying `mud`'s substitutions, gags and highlights
+    /// (compiled for `interp`)
+    pub fn feed_inbound_with_mud(&mut self, chunk: &[u8], interp: &mut dyn Interpreter, mud: &Mud) {
+        self.session.feed_with_mud(chunk, Some(interp), Some(mud));
+        *self.ansi_cache.borrow_mut() = None;
+    }
+
     /// Returns viewport as ANSI-formatted strings (preserves colors)
     /// Uses caching to avoid repeated conversion overhead
     /// NOTE: For TTY mode - use get_scrollback() for headless mode
@@ -79,6 +420,25 @@ impl<D: Decompressor> SessionEngine<D> {
         out
     }
 
+    /// Scrollback rows matching a regex, at most the last `max` of them
+    /// (headless mode; TTY mode greps the OutputWindow's scrollback)
+    pub fn grep(&self, pattern: &str, max: usize) -> Result<Vec<GrepMatch>, String> {
+        let re = Regex::new(pattern).map_err(|e| format!("Bad pattern: {}", e))?;
+        let mut matches = self
+            .session
+            .scrollback_ref()
+            .map_or_else(Vec::new, |sb| sb.grep(&re));
+        matches.drain(..matches.len().saturating_sub(max));
+        Ok(matches)
+    }
+
+    /// History lines for paging: see Scrollback::line_range
+    pub fn get_lines(&self, from: i64, count: usize) -> (Vec<HistoryLine>, usize, usize) {
+        self.session
+            .scrollback_ref()
+            .map_or_else(|| (Vec::new(), 0, 0), |sb| sb.line_range(from, count))
+    }
+
     /// Returns only NEW lines since last read (for headless mode)
     /// Advances read cursor automatically - won't return same line twice
     pub fn get_new_lines(&self) -> Vec<String> {
@@ -235,4 +595,92 @@ mod tests {
         assert_ne!(rows1, rows3);
         assert!(rows3.iter().any(|r| r.contains("Line2")));
     }
+
+    #[test]
+    fn engine_runs_timers() {
+        let mut eng = SessionEngine::new(PassthroughDecomp::new(), 10, 3, 100);
+        assert!(eng.timer_command("look").is_none());
+        assert!(eng.timer_command("#wait 0 look").unwrap().is_ok());
+        let later = Instant::now() + std::time::Duration::from_millis(1);
+        assert_eq!(eng.run_timers(later), vec!["look".to_string()]);
+        assert!(eng.run_timers(later).is_empty());
+    }
+
+    #[test]
+    fn engine_paces_sends_and_shows_queue() {
+        let mut eng = SessionEngine::new(PassthroughDecomp::new(), 10, 3, 100);
+        assert!(eng.send_command("#sendrate 1 1").unwrap().is_ok());
+        eng.queue_send("n");
+        eng.queue_send("e");
+        let now = Instant::now();
+        assert_eq!(eng.take_sends(now), vec!["n".to_string()]);
+        assert_eq!(eng.status.get("queue").unwrap().text, "Queued: 1");
+        assert!(eng.send_command("#flush").unwrap().is_ok());
+        assert!(eng.status.get("queue").is_none());
+        assert!(eng
+            .take_sends(now + std::time::Duration::from_secs(2))
+            .is_empty());
+    }
+
+    #[test]
+    fn engine_keepalive_after_quiet_interval() {
+        use std::time::Duration;
+        let mut eng = SessionEngine::new(PassthroughDecomp::new(), 10, 3, 100);
+        let nop = Keepalive::parse("60").unwrap();
+        let t0 = Instant::now();
+        assert_eq!(eng.keepalive(&nop, t0), None);
+        assert_eq!(eng.keepalive(&nop, t0 + Duration::from_secs(59)), None);
+        assert_eq!(
+            eng.keepalive(&nop, t0 + Duration::from_secs(60)),
+            Some(vec![255, 241])
+        );
+        eng.session.record_written(2);
+        let now = Instant::now();
+        assert_eq!(eng.keepalive(&nop, now), None);
+
+        let blank = Keepalive::parse("60 blank").unwrap();
+        let later = now + Duration::from_secs(61);
+        assert_eq!(eng.keepalive(&blank, later), None);
+        assert_eq!(eng.take_sends(later), vec![String::new()]);
+    }
+
+    #[test]
+    fn engine_dispatches_builtins() {
+        let mut eng = SessionEngine::new(PassthroughDecomp::new(), 10, 3, 100);
+        eng.builtins.register_frontend("quit", "#quit", "Leave");
+        assert_eq!(eng.command("look"), Dispatch::NotCommand);
+        assert_eq!(eng.command("#quit now"), Dispatch::Frontend("quit", "now"));
+        assert!(matches!(eng.command("#wait 1 look"), Dispatch::Done(Ok(_))));
+        assert_eq!(eng.timers.len(), 1);
+        assert!(eng.builtin_command("#frobnicate").unwrap().is_err());
+        let help = eng.builtin_command("#help").unwrap().unwrap();
+        for name in [
+            "#flush",
+            "#help",
+            "#quit",
+            "#sendrate",
+            "#tick",
+            "#untick",
+            "#var",
+            "#wait",
+        ] {
+            assert!(help.contains(name), "{} missing", name);
+        }
+        assert!(eng
+            .builtin_command("#help wait")
+            .unwrap()
+            .unwrap()
+            .starts_with("Usage: #wait"));
+
+        assert!(matches!(eng.command("#var hp 42"), Dispatch::Done(Ok(_))));
+        assert_eq!(eng.commands.variables.get("hp"), Some("42"));
+        assert!(eng.builtin_command("#unvar hp").unwrap().is_ok());
+
+        // #send passes the text through untouched
+        assert!(matches!(
+            eng.command("#send #tick;3n"),
+            Dispatch::Done(Ok(_))
+        ));
+        assert_eq!(eng.take_sends(Instant::now()), vec!["#tick;3n"]);
+    }
 }
@@ -0,0 +1,251 @@
+// EventBus - typed events the engine publishes for scripts and control clients
+//
+// The engine publishes connects, disconnects, lines, prompts, fired
+// triggers, timers and window resizes here. Scripts subscribe a function to
+// an event type with okros.on("line", "func"); the TTY main loop calls it
+// with the event's text. The control server records everything and pushes
+// each event to the clients that subscribed to its type. Nothing is queued
+// for a type nobody listens to.
+
+use crate::session::SessionEvent;
+
+/// Events kept when nobody drains the bus (oldest dropped first)
+const MAX_PENDING: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Connect,
+    Disconnect,
+    Line,
+    Prompt,
+    TriggerFired,
+    Timer,
+    Resize,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 7] = [
+        EventKind::Connect,
+        EventKind::Disconnect,
+        EventKind::Line,
+        EventKind::Prompt,
+        EventKind::TriggerFired,
+        EventKind::Timer,
+        EventKind::Resize,
+    ];
+
+    /// Event type by name (as in the control protocol's "event" tags)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|k| k.name()).collect();
+                format!("Unknown event {} (one of {})", name, names.join(", "))
+            })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Connect => "connect",
+            EventKind::Disconnect => "disconnect",
+            EventKind::Line => "line",
+            EventKind::Prompt => "prompt",
+            EventKind::TriggerFired => "trigger_fired",
+            EventKind::Timer => "timer",
+            EventKind::Resize => "resize",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusEvent {
+    /// The connection to `address` (host:port) is up
+    Connect { address: String },
+    /// The MUD closed the connection
+    Disconnect,
+    /// A completed (and not gagged) line
+    Line(String),
+    /// A GA/EOR-terminated prompt
+    Prompt(String),
+    /// A trigger matched `line` and ran `commands`
+    TriggerFired { line: String, commands: Vec<String> },
+    /// A #wait/#tick timer ran its command
+    Timer { command: String },
+    /// The output window is now this size
+    Resize { width: usize, height: usize },
+}
+
+impl BusEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            BusEvent::Connect { .. } => EventKind::Connect,
+            BusEvent::Disconnect => EventKind::Disconnect,
+            BusEvent::Line(_) => EventKind::Line,
+            BusEvent::Prompt(_) => EventKind::Prompt,
+            BusEvent::TriggerFired { .. } => EventKind::TriggerFired,
+            BusEvent::Timer { .. } => EventKind::Timer,
+            BusEvent::Resize { .. } => EventKind::Resize,
+        }
+    }
+
+    /// What a script handler is called with: the address, line, prompt,
+    /// trigger line, timer command or "width height"
+    pub fn arg(&self) -> String {
+        match self {
+            BusEvent::Connect { address } => address.clone(),
+            BusEvent::Disconnect => String::new(),
+            BusEvent::Line(text) | BusEvent::Prompt(text) => text.clone(),
+            BusEvent::TriggerFired { line, .. } => line.clone(),
+            BusEvent::Timer { command } => command.clone(),
+            BusEvent::Resize { width, height } => format!("{} {}", width, height),
+        }
+    }
+}
+
+impl From<&SessionEvent> for BusEvent {
+    fn from(ev: &SessionEvent) -> Self {
+        match ev {
+            SessionEvent::Line(text) => BusEvent::Line(text.clone()),
+            SessionEvent::Prompt(text) => BusEvent::Prompt(text.clone()),
+            SessionEvent::TriggerFired { line, commands } => BusEvent::TriggerFired {
+                line: line.clone(),
+                commands: commands.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EventBus {
+    pending: Vec<BusEvent>,
+    handlers: Vec<(EventKind, String)>, // Script functions, in subscription order
+    record: bool,                       // Keep every event (control server)
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep every event for take(), not only those scripts listen to
+    pub fn set_recording(&mut self, on: bool) {
+        self.record = on;
+    }
+
+    /// Queue an event if anyone listens to its type
+    pub fn publish(&mut self, event: BusEvent) {
+        let kind = event.kind();
+        if !self.record && !self.handlers.iter().any(|(k, _)| *k == kind) {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.remove(0);
+        }
+        self.pending.push(event);
+    }
+
+    /// Call `function` for each event of type `kind`; false if it already is
+    pub fn subscribe(&mut self, kind: EventKind, function: &str) -> bool {
+        if self
+            .handlers
+            .iter()
+            .any(|(k, f)| *k == kind && f == function)
+        {
+            return false;
+        }
+        self.handlers.push((kind, function.to_string()));
+        true
+    }
+
+    /// Stop calling `function` for `kind`; false if it wasn't subscribed
+    pub fn unsubscribe(&mut self, kind: EventKind, function: &str) -> bool {
+        let before = self.handlers.len();
+        self.handlers
+            .retain(|(k, f)| !(*k == kind && f == function));
+        self.handlers.len() != before
+    }
+
+    pub fn handlers(&self) -> &[(EventKind, String)] {
+        &self.handlers
+    }
+
+    /// Events published since the last call, oldest first
+    pub fn take(&mut self) -> Vec<BusEvent> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Drain the queue as script calls: (function, argument) for each
+    /// handler of each event, in order
+    pub fn take_calls(&mut self) -> Vec<(String, String)> {
+        let mut calls = Vec::new();
+        for event in self.take() {
+            let kind = event.kind();
+            for (_, function) in self.handlers.iter().filter(|(k, _)| *k == kind) {
+                calls.push((function.clone(), event.arg()));
+            }
+        }
+        calls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_subscribed_types_are_queued() {
+        let mut bus = EventBus::new();
+        bus.publish(BusEvent::Line("lost".into()));
+        assert!(bus.take().is_empty());
+
+        assert!(bus.subscribe(EventKind::Line, "on_line"));
+        assert!(!bus.subscribe(EventKind::Line, "on_line"));
+        bus.subscribe(EventKind::Resize, "on_resize");
+        bus.subscribe(EventKind::Line, "log_line");
+        bus.publish(BusEvent::Line("A troll arrives.".into()));
+        bus.publish(BusEvent::Prompt("HP: 10>".into()));
+        bus.publish(BusEvent::Resize {
+            width: 80,
+            height: 20,
+        });
+        assert_eq!(
+            bus.take_calls(),
+            vec![
+                ("on_line".to_string(), "A troll arrives.".to_string()),
+                ("log_line".to_string(), "A troll arrives.".to_string()),
+                ("on_resize".to_string(), "80 20".to_string()),
+            ]
+        );
+
+        assert!(bus.unsubscribe(EventKind::Line, "on_line"));
+        assert!(!bus.unsubscribe(EventKind::Line, "on_line"));
+        assert_eq!(bus.handlers().len(), 2);
+    }
+
+    #[test]
+    fn recording_keeps_everything_bounded() {
+        let mut bus = EventBus::new();
+        bus.set_recording(true);
+        for i in 0..MAX_PENDING + 5 {
+            bus.publish(BusEvent::Timer {
+                command: i.to_string(),
+            });
+        }
+        let events = bus.take();
+        assert_eq!(events.len(), MAX_PENDING);
+        assert_eq!(events[0].arg(), "5");
+        assert_eq!(events[0].kind(), EventKind::Timer);
+    }
+
+    #[test]
+    fn kinds_parse_by_name() {
+        for kind in EventKind::ALL {
+            assert_eq!(EventKind::parse(kind.name()), Ok(kind));
+        }
+        assert!(EventKind::parse("tick")
+            .unwrap_err()
+            .contains("trigger_fired"));
+    }
+}
@@ -0,0 +1,252 @@
+// Filters - the ordered pipeline every completed MUD line goes through
+//
+// Session used to hard-code the order (C++ Session::triggerCheck does too):
+// MSP strip, substitutions, gags, triggers, sys/output, highlights, URLs.
+// Here those are named stages that can be switched off (#filter off gag),
+// with script hooks and Rust LineFilters added between them. Byte-level
+// work (telnet, MCCP, MXP, ANSI) happens before a line exists and isn't
+// part of it.
+
+/// What a LineFilter does with a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterAction {
+    Pass,
+    Replace(String),
+    Gag,
+}
+
+/// A pipeline stage written in Rust (a logger, a spam filter...)
+pub trait LineFilter: Send {
+    fn filter(&mut self, text: &str) -> FilterAction;
+}
+
+/// Stages Session implements itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Msp,       // Strip (and queue) MSP sound triggers
+    Subst,     // Replacement callback and #subst
+    Gag,       // #gag
+    Triggers,  // Trigger callback (trigger_fired events)
+    Output,    // Output callback and the sys/output hook
+    Highlight, // Highlight callback and #highlight
+    Urls,      // Underline and number URLs for #url
+}
+
+const BUILTINS: [(&str, Builtin); 7] = [
+    ("msp", Builtin::Msp),
+    ("subst", Builtin::Subst),
+    ("gag", Builtin::Gag),
+    ("triggers", Builtin::Triggers),
+    ("output", Builtin::Output),
+    ("highlight", Builtin::Highlight),
+    ("urls", Builtin::Urls),
+];
+
+pub enum Stage {
+    Builtin(Builtin),
+    /// A script function, called like sys/output: it returns the line to
+    /// show, "" (or okros.gag()) to gag it
+    Script(String),
+    Custom(Box<dyn LineFilter>),
+}
+
+struct Slot {
+    name: String,
+    stage: Stage,
+    enabled: bool,
+}
+
+pub struct FilterPipeline {
+    slots: Vec<Slot>,
+}
+
+impl Default for FilterPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilterPipeline {
+    /// The built-in stages, all on, in their usual order
+    pub fn new() -> Self {
+        let slots = BUILTINS
+            .iter()
+            .map(|&(name, b)| Slot {
+                name: name.to_string(),
+                stage: Stage::Builtin(b),
+                enabled: true,
+            })
+            .collect();
+        Self { slots }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Stage `i`, None if it is switched off
+    pub fn stage(&self, i: usize) -> Option<&Stage> {
+        self.slots.get(i).filter(|s| s.enabled).map(|s| &s.stage)
+    }
+
+    /// Run custom stage `i` (Pass for any other kind)
+    pub fn run_custom(&mut self, i: usize, text: &str) -> FilterAction {
+        match self.slots.get_mut(i).map(|s| &mut s.stage) {
+            Some(Stage::Custom(filter)) => filter.filter(text),
+            _ => FilterAction::Pass,
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.slots.iter().any(|s| s.name == name && s.enabled)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, on: bool) -> Result<(), String> {
+        let slot = self.find_mut(name)?;
+        slot.enabled = on;
+        Ok(())
+    }
+
+    /// Add a stage before `before` (None: at the end, after "urls")
+    pub fn add(&mut self, name: &str, stage: Stage, before: Option<&str>) -> Result<(), String> {
+        if self.slots.iter().any(|s| s.name == name) {
+            return Err(format!("Filter {} already exists", name));
+        }
+        let at = match before {
+            Some(b) => self.position(b)?,
+            None => self.slots.len(),
+        };
+        let slot = Slot {
+            name: name.to_string(),
+            stage,
+            enabled: true,
+        };
+        self.slots.insert(at, slot);
+        Ok(())
+    }
+
+    /// Remove an added stage (built-in ones can only be switched off)
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let at = self.position(name)?;
+        if matches!(self.slots[at].stage, Stage::Builtin(_)) {
+            return Err(format!("{} is built in: use #filter off {}", name, name));
+        }
+        self.slots.remove(at);
+        Ok(())
+    }
+
+    /// One line per stage, in order
+    pub fn describe(&self) -> String {
+        let lines: Vec<String> = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let kind = match &s.stage {
+                    Stage::Builtin(_) => String::new(),
+                    Stage::Script(function) => format!(" (script {})", function),
+                    Stage::Custom(_) => " (custom)".to_string(),
+                };
+                let state = if s.enabled { "on" } else { "off" };
+                format!("{}. {} {}{}", i + 1, s.name, state, kind)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// #filter [on|off <stage> | add <name> <function> [before <stage>] |
+    /// remove <name>]
+    pub fn command(&mut self, args: &str) -> Result<String, String> {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(self.describe()),
+            [state @ ("on" | "off"), name] => {
+                self.set_enabled(name, *state == "on")?;
+                Ok(format!("Filter {} {}", name, state))
+            }
+            ["add", name, function] => {
+                self.add(name, Stage::Script(function.to_string()), None)?;
+                Ok(format!("Added filter {} ({})", name, function))
+            }
+            ["add", name, function, "before", before] => {
+                self.add(name, Stage::Script(function.to_string()), Some(before))?;
+                Ok(format!(
+                    "Added filter {} ({}) before {}",
+                    name, function, before
+                ))
+            }
+            ["remove", name] => {
+                self.remove(name)?;
+                Ok(format!("Removed filter {}", name))
+            }
+            _ => Err(
+                "Usage: #filter [on|off <stage> | add <name> <function> [before <stage>] | remove <name>]"
+                    .to_string(),
+            ),
+        }
+    }
+
+    fn position(&self, name: &str) -> Result<usize, String> {
+        self.slots
+            .iter()
+            .position(|s| s.name == name)
+            .ok_or_else(|| format!("No filter named {}", name))
+    }
+
+    fn find_mut(&mut self, name: &str) -> Result<&mut Slot, String> {
+        let at = self.position(name)?;
+        Ok(&mut self.slots[at])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Upper;
+
+    impl LineFilter for Upper {
+        fn filter(&mut self, text: &str) -> FilterAction {
+            FilterAction::Replace(text.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn stages_switch_off_and_insert_in_order() {
+        let mut p = FilterPipeline::new();
+        assert_eq!(p.len(), 7);
+        assert!(p.command("off gag").is_ok());
+        assert!(!p.is_enabled("gag"));
+        assert!(p.stage(2).is_none());
+        assert!(p.command("off nope").is_err());
+
+        p.command("add censor censor_line before highlight")
+            .unwrap();
+        p.add("upper", Stage::Custom(Box::new(Upper)), None)
+            .unwrap();
+        assert!(p.command("add censor other").is_err());
+        assert_eq!(
+            p.describe().lines().collect::<Vec<_>>()[2..],
+            [
+                "3. gag off",
+                "4. triggers on",
+                "5. output on",
+                "6. censor on (script censor_line)",
+                "7. highlight on",
+                "8. urls on",
+                "9. upper on (custom)",
+            ]
+        );
+        assert!(matches!(p.stage(5), Some(Stage::Script(f)) if f == "censor_line"));
+        assert_eq!(p.run_custom(8, "hi"), FilterAction::Replace("HI".into()));
+        assert_eq!(p.run_custom(0, "hi"), FilterAction::Pass);
+
+        assert!(p.command("remove highlight").is_err());
+        p.command("remove censor").unwrap();
+        assert_eq!(p.len(), 8);
+    }
+}
@@ -0,0 +1,172 @@
+// FrameWriter - terminal output on its own thread
+//
+// Screen used to write each frame to stdout from the main loop, so a slow
+// terminal or a stalled SSH link held up keyboard input and the MUD socket.
+// Frames now go to a writer thread, one at a time. While one is still
+// being written Screen doesn't make another; once the writer is free the
+// next frame is diffed against what the terminal last got, so the states
+// in between are skipped and the latest one is always what's drawn.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+#[derive(Default)]
+struct Slot {
+    frame: Option<Vec<u8>>, // Waiting to be written
+    writing: bool,
+    spare: Vec<u8>, // The last written frame's buffer, emptied for reuse
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    slot: Mutex<Slot>,
+    changed: Condvar,
+}
+
+pub struct FrameWriter {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FrameWriter {
+    pub fn new<W: Write + Send + 'static>(mut out: W) -> Self {
+        let shared = Arc::new(Shared::default());
+        let sh = shared.clone();
+        let thread = thread::spawn(move || loop {
+            let mut frame = {
+                let mut slot = sh.slot.lock().unwrap();
+                while slot.frame.is_none() && !slot.closed {
+                    slot = sh.changed.wait(slot).unwrap();
+                }
+                let Some(frame) = slot.frame.take() else {
+                    return;
+                };
+                slot.writing = true;
+                frame
+            };
+            let _ = out.write_all(&frame);
+            let _ = out.flush();
+            frame.clear();
+            let mut slot = sh.slot.lock().unwrap();
+            slot.writing = false;
+            slot.spare = frame;
+            sh.changed.notify_all();
+        });
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    /// A frame is still waiting or being written
+    pub fn is_busy(&self) -> bool {
+        let slot = self.shared.slot.lock().unwrap();
+        slot.frame.is_some() || slot.writing
+    }
+
+    /// Hand `frame` to the writer thread without waiting for it (if one is
+    /// already queued it's appended, not replaced: frames are diffs). Returns
+    /// an empty buffer to build the next frame in
+    pub fn submit(&self, mut frame: Vec<u8>) -> Vec<u8> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        match slot.frame.as_mut() {
+            Some(queued) => {
+                queued.append(&mut frame);
+            }
+            None => slot.frame = Some(std::mem::take(&mut frame)),
+        }
+        self.shared.changed.notify_all();
+        match frame.capacity() {
+            0 => std::mem::take(&mut slot.spare),
+            _ => frame,
+        }
+    }
+
+    /// Wait until everything submitted has been written (before the
+    /// terminal is restored or the process execs)
+    pub fn flush(&self) {
+        let mut slot = self.shared.slot.lock().unwrap();
+        while slot.frame.is_some() || slot.writing {
+            slot = self.shared.changed.wait(slot).unwrap();
+        }
+    }
+}
+
+impl Drop for FrameWriter {
+    fn drop(&mut self) {
+        self.flush();
+        self.shared.slot.lock().unwrap().closed = true;
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    /// Collects what's written; each write waits for a go-ahead
+    struct Gated {
+        go: Receiver<()>,
+        out: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for Gated {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.go.recv();
+            self.out.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn gated() -> (FrameWriter, Sender<()>, Arc<Mutex<Vec<u8>>>) {
+        let (tx, go) = channel();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let writer = FrameWriter::new(Gated {
+            go,
+            out: out.clone(),
+        });
+        (writer, tx, out)
+    }
+
+    #[test]
+    fn submit_returns_without_waiting_for_the_terminal() {
+        let (writer, go, out) = gated();
+        assert!(!writer.is_busy());
+        writer.submit(b"frame 1;".to_vec());
+        // Stuck writing: still busy, and a second frame queues behind it
+        assert!(writer.is_busy());
+        writer.submit(b"frame 2;".to_vec());
+        for _ in 0..2 {
+            go.send(()).unwrap();
+        }
+        writer.flush();
+        assert!(!writer.is_busy());
+        assert_eq!(*out.lock().unwrap(), b"frame 1;frame 2;");
+    }
+
+    #[test]
+    fn written_buffers_come_back_for_reuse() {
+        let (writer, go, _out) = gated();
+        go.send(()).unwrap();
+        writer.submit(Vec::with_capacity(64));
+        writer.flush();
+        let spare = writer.submit(b"x".to_vec());
+        assert!(spare.is_empty());
+        assert!(spare.capacity() >= 64);
+        go.send(()).unwrap();
+        drop(writer);
+    }
+}
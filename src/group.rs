@@ -0,0 +1,215 @@
+// Group - named sets of aliases, actions, macros and timers (#group)
+//
+// Anything can be put in a group (in the config with a
+// `group <name> { ... }` block, or later with `#group <name> add ...`);
+// `#group <name> off` then disables the whole set at once - a disabled alias, macro or action doesn't match, a disabled
+// timer comes due without firing. Groups are on unless switched off, and
+// the off ones are remembered per MUD (`group <name> off` in the config).
+
+use crate::mud::Mud;
+use crate::timer::TimerQueue;
+use std::collections::BTreeMap;
+
+const USAGE: &str = "Usage: #group [<name> [on|off|add|remove <alias|action|macro|timer> <what>]]";
+
+/// Members of each group: (aliases, actions, macros, timers)
+fn group_counts(mud: &Mud, timers: &TimerQueue) -> BTreeMap<String, [usize; 4]> {
+    let mut counts: BTreeMap<String, [usize; 4]> = BTreeMap::new();
+    let mut count = |group: &Option<String>, kind: usize| {
+        if let Some(g) = group {
+            counts.entry(g.clone()).or_default()[kind] += 1;
+        }
+    };
+    for m in std::iter::successors(Some(mud), |m| m.inherits.as_deref()) {
+        m.alias_list.iter().for_each(|a| count(&a.group, 0));
+        m.action_list.iter().for_each(|a| count(&a.group, 1));
+        m.macro_list.iter().for_each(|a| count(&a.group, 2));
+    }
+    timers.list().iter().for_each(|t| count(&t.group, 3));
+    for g in &mud.disabled_groups {
+        counts.entry(g.clone()).or_default();
+    }
+    counts
+}
+
+/// One line per member of `group`
+fn describe_group(mud: &Mud, timers: &TimerQueue, group: &str) -> String {
+    let state = if mud.group_enabled(Some(group)) {
+        "on"
+    } else {
+        "off"
+    };
+    let mut text = format!("Group {} ({}):", group, state);
+    let member = |g: &Option<String>| g.as_deref() == Some(group);
+    for m in std::iter::successors(Some(mud), |m| m.inherits.as_deref()) {
+        for a in m.alias_list.iter().filter(|a| member(&a.group)) {
+            text.push_str(&format!("\n  alias {} {}", a.name, a.text));
+        }
+        for a in m.action_list.iter().filter(|a| member(&a.group)) {
+            text.push_str(&format!("\n  action \"{}\" {}", a.pattern, a.commands));
+        }
+        for a in m.macro_list.iter().filter(|a| member(&a.group)) {
+            let key = crate::macro_def::key_name(a.key);
+            text.push_str(&format!("\n  macro {} {}", key, a.text));
+        }
+    }
+    for t in timers.list().iter().filter(|t| member(&t.group)) {
+        text.push_str(&format!("\n  timer {} {}", t.id, t.command));
+    }
+    text
+}
+
+/// Put an alias/action/macro/timer of this MUD in `group` (None: take it out)
+fn assign(
+    mud: &mut Mud,
+    timers: &mut TimerQueue,
+    kind: &str,
+    what: &str,
+    group: Option<&str>,
+) -> Result<(), String> {
+    let group_of = match kind {
+        "alias" => mud
+            .alias_list
+            .iter_mut()
+            .find(|a| a.name == what)
+            .map(|a| &mut a.group),
+        "action" => {
+            let pattern = what.trim_matches('"');
+            mud.action_list
+                .iter_mut()
+                .find(|a| a.pattern == pattern)
+                .map(|a| &mut a.group)
+        }
+        "macro" => {
+            let key = crate::macro_def::key_lookup(what)
+                .ok_or_else(|| format!("Unknown macro key: {}", what))?;
+            mud.macro_list
+                .iter_mut()
+                .find(|m| m.key == key)
+                .map(|m| &mut m.group)
+        }
+        "timer" => {
+            let found = what
+                .parse()
+                .is_ok_and(|id: u32| timers.set_group(id, group));
+            return found.then_some(()).ok_or(format!("No timer {}", what));
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+    let slot = group_of.ok_or_else(|| format!("No {} {}", kind, what))?;
+    *slot = group.map(str::to_string);
+    Ok(())
+}
+
+/// #group [<name> [on | off | add <kind> <what> | remove <kind> <what>]]
+pub fn group_command(args: &str, mud: &mut Mud, timers: &mut TimerQueue) -> Result<String, String> {
+    let mut words = args.splitn(3, char::is_whitespace);
+    let name = words.next().unwrap_or("");
+    let sub = words.next().unwrap_or("");
+    let rest = words.next().unwrap_or("").trim();
+    let (kind, what) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let what = what.trim();
+    match (name, sub) {
+        ("", _) => {
+            let counts = group_counts(mud, timers);
+            if counts.is_empty() {
+                return Ok("No groups.".to_string());
+            }
+            let mut text = String::from("Groups:");
+            for (g, [aliases, actions, macros, timers]) in counts {
+                let state = if mud.group_enabled(Some(&g)) {
+                    "on"
+                } else {
+                    "off"
+                };
+                text.push_str(&format!(
+                    "\n  {} {}: {} aliases, {} actions, {} macros, {} timers",
+                    g, state, aliases, actions, macros, timers
+                ));
+            }
+            Ok(text)
+        }
+        (_, "") => Ok(describe_group(mud, timers, name)),
+        (_, "on" | "off") => {
+            mud.set_group_enabled(name, sub == "on");
+            Ok(format!("Group {} {}", name, sub))
+        }
+        (_, "add") if !what.is_empty() => {
+            assign(mud, timers, kind, what, Some(name))?;
+            Ok(format!("Added {} {} to group {}", kind, what, name))
+        }
+        (_, "remove") if !what.is_empty() => {
+            assign(mud, timers, kind, what, None)?;
+            Ok(format!("Removed {} {} from group {}", kind, what, name))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::{Action, ActionType};
+    use crate::alias::Alias;
+    use crate::plugins::native_regex::NativeRegex;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn groups_switch_members_together() {
+        let mut parent = Mud::new("Parent", "", 0);
+        let mut flee = Alias::new("f", "flee");
+        flee.group = Some("combat".to_string());
+        parent.alias_list.push(flee);
+        let mut mud = Mud::with_inherits("Child", "", 0, Some(parent));
+        mud.alias_list.push(Alias::new("k", "kill"));
+        mud.action_list.push(Action::new(
+            "^You are hungry",
+            "eat bread",
+            ActionType::Trigger,
+        ));
+        let mut timers = TimerQueue::new();
+        let t0 = Instant::now();
+        let id = timers.add_tick(Duration::from_secs(1), "score", t0);
+
+        let mut interp = NativeRegex::new();
+        mud.compile_actions(&mut interp);
+        assert!(group_command("combat add alias k", &mut mud, &mut timers).is_ok());
+        assert!(group_command(
+            "combat add action \"^You are hungry\"",
+            &mut mud,
+            &mut timers
+        )
+        .is_ok());
+        assert!(group_command(&format!("combat add timer {}", id), &mut mud, &mut timers).is_ok());
+        assert!(group_command("combat add alias nope", &mut mud, &mut timers).is_err());
+        assert_eq!(
+            group_command("", &mut mud, &mut timers).unwrap(),
+            "Groups:\n  combat on: 2 aliases, 1 actions, 0 macros, 1 timers"
+        );
+
+        group_command("combat off", &mut mud, &mut timers).unwrap();
+        assert!(mud.find_alias("k").is_none());
+        // Switched off in the parent too
+        assert!(mud.find_alias("f").is_none());
+        assert!(mud
+            .check_action_match("You are hungry", &mut interp)
+            .is_empty());
+        let active = |g: Option<&str>| mud.group_enabled(g);
+        assert!(timers
+            .expire_active(t0 + Duration::from_secs(1), active)
+            .is_empty());
+        assert!(group_command("combat", &mut mud, &mut timers)
+            .unwrap()
+            .starts_with("Group combat (off):\n  alias k kill"));
+
+        group_command("combat on", &mut mud, &mut timers).unwrap();
+        assert_eq!(mud.find_alias("f").unwrap().text, "flee");
+        assert_eq!(
+            mud.check_action_match("You are hungry", &mut interp),
+            vec!["eat bread"]
+        );
+        group_command("combat remove alias k", &mut mud, &mut timers).unwrap();
+        assert_eq!(mud.alias_list[0].group, None);
+        assert!(group_command("combat frob", &mut mud, &mut timers).is_err());
+    }
+}
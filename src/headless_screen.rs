@@ -0,0 +1,258 @@
+// HeadlessScreen - TTY window composition for the headless engine
+//
+// Builds the same window tree as TTY mode (Screen root, output area,
+// InputLine on the bottom row, StatusLine overlaying the top row) but never
+// touches a terminal: render() composites the tree and returns the canvas,
+// so a control client can draw it remotely (get_screen / key).
+
+use crate::command_queue::CommandQueue;
+use crate::history::{HistoryId, HistorySet};
+use crate::input::{paste_lines, KeyCode, KeyDecoder, KeyEvent};
+use crate::input_line::{event_key_code, InputLine};
+use crate::screen::{attrib_row_to_ansi, Screen};
+use crate::scrollback::{Attrib, Scrollback};
+use crate::status_line::{StatusFields, StatusLine};
+use crate::window::Window;
+
+/// Composited screen: ANSI rows plus where the cursor sits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenSnapshot {
+    pub rows: Vec<String>,
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+}
+
+pub struct HeadlessScreen {
+    screen: Screen,
+    output: Box<Window>,
+    input: InputLine,
+    status: StatusLine,
+    decoder: KeyDecoder,
+    history: HistorySet,
+    queue: CommandQueue,
+}
+
+// SAFETY: the window tree's raw pointers only point into this struct's own
+// boxed windows, and the owning SessionEngine is only used under its mutex
+unsafe impl Send for HeadlessScreen {}
+
+impl HeadlessScreen {
+    /// Screen of `width` x (`output_height` + 1): output rows plus the input line
+    pub fn new(width: usize, output_height: usize) -> Self {
+        let mut screen = Screen::new(width, output_height + 1);
+        let root = screen.window_mut() as *mut Window;
+
+        // Same z-order as main.rs: output, input, then status on top
+        let output = Window::new(root, width, output_height);
+        let mut input = InputLine::new(root, width, 0x17, HistoryId::MainInput);
+        input.win.parent_y = output_height as isize;
+        let mut status = StatusLine::new(root, width, 0x07);
+        status.win.parent_y = 0;
+
+        Self {
+            screen,
+            output,
+            input,
+            status,
+            decoder: KeyDecoder::new(),
+            history: HistorySet::new(crate::history::DEFAULT_SIZE),
+            queue: CommandQueue::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.screen.window().width
+    }
+
+    pub fn height(&self) -> usize {
+        self.screen.window().height
+    }
+
+    /// Feed raw terminal bytes from a remote client
+    /// Edits the input line, pages `sb` on PgUp/PgDn, and returns the
+    /// command lines completed with Enter
+    pub fn keys(&mut self, bytes: &[u8], mut sb: Option<&mut Scrollback>) -> Vec<String> {
+        for ev in self.decoder.feed(bytes) {
+            match ev {
+                // PgUp/PgDn: page through scrollback (as in the TTY loop)
+                KeyEvent::Key(KeyCode::PageUp) => {
+                    if let Some(sb) = sb.as_deref_mut() {
+                        sb.set_frozen(true);
+                        sb.page_up();
+                    }
+                }
+                KeyEvent::Key(KeyCode::PageDown) => {
+                    if let Some(sb) = sb.as_deref_mut() {
+                        if sb.page_down() {
+                            sb.set_frozen(false);
+                            sb.viewpoint = sb.canvas_ptr();
+                        }
+                    }
+                }
+                // Bracketed paste from the attached terminal: into the input line
+                KeyEvent::Key(KeyCode::Paste) => {
+                    if let Some(text) = self.decoder.take_paste() {
+                        self.input.insert(&paste_lines(&text).join("\n"));
+                    }
+                }
+                _ => {
+                    if let Some(key) = event_key_code(ev) {
+                        self.input.keypress(key, &mut self.history, &mut self.queue);
+                    }
+                }
+            }
+        }
+        self.queue.execute_all()
+    }
+
+    /// Composite the window tree from the session's scrollback
+    /// `partial` is the incomplete line (prompt) not yet in the scrollback
+    pub fn render(
+        &mut self,
+        sb: Option<&Scrollback>,
+        partial: &[(u8, u16)],
+        status_text: &str,
+        fields: &StatusFields,
+    ) -> ScreenSnapshot {
+        self.draw_output(sb, partial);
+        if self.status.text() != status_text {
+            self.status.set_text(status_text);
+        }
+        self.status.set_fields(fields);
+        self.input.redraw();
+        self.input.win.dirty = true; // Keep dirty for tree refresh (see main.rs)
+        self.status.win.dirty = true;
+        self.output.dirty = true;
+        self.screen.window_mut().refresh();
+
+        let root = self.screen.window();
+        let rows = root
+            .canvas
+            .chunks(root.width)
+            .map(attrib_row_to_ansi)
+            .collect();
+        ScreenSnapshot {
+            rows,
+            cursor_x: self.input.win.cursor_x,
+            cursor_y: self.input.win.parent_y as usize,
+        }
+    }
+
+    /// Copy the scrollback viewport into the output window, with the
+    /// incomplete line drawn where the next line will go (TTY mode prints it
+    /// as it arrives)
+    fn draw_output(&mut self, sb: Option<&Scrollback>, partial: &[(u8, u16)]) {
+        let width = self.output.width;
+        let height = self.output.height;
+        let blank = ((0x07 as Attrib) << 8) | b' ' as Attrib;
+        let mut canvas = vec![blank; width * height];
+
+        if let Some(sb) = sb {
+            let view = sb.viewport_slice();
+            let rows = sb.height.min(height);
+            let cols = sb.width.min(width);
+            for y in 0..rows {
+                for x in 0..cols {
+                    let a = view[y * sb.width + x];
+                    canvas[y * width + x] = if a == 0 { blank } else { a };
+                }
+            }
+
+            if !partial.is_empty() && !sb.is_frozen() {
+                let row = if sb.rows_filled < height {
+                    sb.rows_filled
+                } else {
+                    // Full: scroll up one row to make room
+                    canvas.copy_within(width.., 0);
+                    height - 1
+                };
+                let line = &mut canvas[row * width..(row + 1) * width];
+                line.fill(blank);
+                for (cell, &(ch, color)) in line.iter_mut().zip(partial) {
+                    *cell = ((color as Attrib) << 8) | ch as Attrib;
+                }
+            }
+        }
+        self.output.blit(&canvas);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(row: &str) -> String {
+        // Strip SGR sequences
+        let mut out = String::new();
+        let mut in_esc = false;
+        for c in row.chars() {
+            match (in_esc, c) {
+                (false, '\x1b') => in_esc = true,
+                (true, 'm') => in_esc = false,
+                (true, _) => {}
+                (false, c) => out.push(c),
+            }
+        }
+        out.trim_end().to_string()
+    }
+
+    #[test]
+    fn composes_status_output_and_input() {
+        let mut sb = Scrollback::new(20, 4, 50);
+        sb.print_line(b"line one", 0x07);
+        sb.print_line(b"line two", 0x07);
+        let mut fields = StatusFields::new();
+        fields.set("conn", "Online", 100);
+
+        let mut hs = HeadlessScreen::new(20, 4);
+        let partial: Vec<(u8, u16)> = b"HP> ".iter().map(|&b| (b, 0x07)).collect();
+        let snap = hs.render(Some(&sb), &partial, "hello", &fields);
+
+        assert_eq!(snap.rows.len(), 5);
+        // Status overlays the first output row (same as TTY mode)
+        assert_eq!(plain(&snap.rows[0]), "hello         Online");
+        assert_eq!(plain(&snap.rows[1]), "line two");
+        assert_eq!(plain(&snap.rows[2]), "HP>");
+        assert_eq!(plain(&snap.rows[4]), "mcl>");
+        assert_eq!((snap.cursor_x, snap.cursor_y), (4, 4));
+    }
+
+    #[test]
+    fn keys_edit_input_and_return_commands() {
+        let mut sb = Scrollback::new(20, 4, 50);
+        let mut hs = HeadlessScreen::new(20, 4);
+
+        assert!(hs.keys(b"nortx", Some(&mut sb)).is_empty());
+        // Backspace, then finish the word and press Enter
+        assert_eq!(
+            hs.keys(b"\x7fh\r", Some(&mut sb)),
+            vec!["north".to_string()]
+        );
+
+        // Up arrow recalls history into the input line
+        hs.keys(b"\x1b[A", Some(&mut sb));
+        let snap = hs.render(Some(&sb), &[], "", &StatusFields::new());
+        assert_eq!(plain(&snap.rows[4]), "mcl>north");
+    }
+
+    #[test]
+    fn bracketed_paste_does_not_submit() {
+        let mut hs = HeadlessScreen::new(20, 4);
+        assert!(hs.keys(b"\x1b[200~kill\rrat\r\x1b[201~", None).is_empty());
+        assert_eq!(hs.keys(b"\r", None), vec!["kill rat".to_string()]);
+    }
+
+    #[test]
+    fn page_up_freezes_scrollback() {
+        let mut sb = Scrollback::new(20, 4, 50);
+        for i in 0..20 {
+            sb.print_line(format!("line {}", i).as_bytes(), 0x07);
+        }
+        let mut hs = HeadlessScreen::new(20, 4);
+        hs.keys(b"\x1b[5~", Some(&mut sb));
+        assert!(sb.is_frozen());
+        while !sb.page_down() {}
+        hs.keys(b"\x1b[6~", Some(&mut sb));
+        assert!(!sb.is_frozen());
+    }
+}
@@ -4,12 +4,28 @@
 //
 // C++ pattern: History class (ring buffer), HistorySet class (collection)
 // Rust pattern: History struct, HistorySet struct with save/load
+//
+// Unlike InputLine.cc, the main input's history is kept per MUD (a namespace
+// picked with HistorySet::select when a MUD is chosen), so one game's
+// commands aren't recalled in another. Lines starting with `#` are client
+// commands: they go to a shared #commands namespace that is recalled,
+// interleaved by age, in every MUD.
 
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Namespace of `#` command lines, recalled along with every MUD's
+pub const COMMANDS_NAMESPACE: &str = "#commands";
+
+/// Lines kept per history unless the config sets `histsize`
+pub const DEFAULT_SIZE: usize = 100;
+
+/// Order lines were added in, across histories (timestamps are in seconds)
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
 /// History IDs (C++ InputLine.h:5)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HistoryId {
@@ -36,8 +52,10 @@ impl From<i32> for HistoryId {
 /// Ring buffer history for one input line (C++ History class, InputLine.cc:10-64)
 pub struct History {
     id: HistoryId,
+    namespace: String, // MUD (or COMMANDS_NAMESPACE) for MainInput, else ""
     strings: Vec<Option<String>>,
     timestamps: Vec<u64>,
+    seqs: Vec<u64>,
     max_history: usize,
     current: usize, // Next insertion point
 }
@@ -47,8 +65,10 @@ impl History {
     pub fn new(id: HistoryId, max_history: usize) -> Self {
         Self {
             id,
+            namespace: String::new(),
             strings: vec![None; max_history],
             timestamps: vec![0; max_history],
+            seqs: vec![0; max_history],
             max_history,
             current: 0,
         }
@@ -69,6 +89,7 @@ impl History {
         let idx = self.current % self.max_history;
         self.strings[idx] = Some(s.to_string());
         self.timestamps[idx] = timestamp;
+        self.seqs[idx] = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
         self.current += 1;
     }
 
@@ -86,6 +107,37 @@ impl History {
             .map(|s| (s.as_str(), self.timestamps[idx]))
     }
 
+    /// Lines newest first, with timestamp and insertion order
+    fn entries(&self) -> impl Iterator<Item = (&str, u64, u64)> {
+        (1..=self.current.min(self.max_history)).filter_map(move |count| {
+            let idx = (self.current - count) % self.max_history;
+            let s = self.strings[idx].as_deref()?;
+            Some((s, self.timestamps[idx], self.seqs[idx]))
+        })
+    }
+
+    /// Keep the newest `max_history` lines from now on
+    fn resize(&mut self, max_history: usize) {
+        if max_history == self.max_history || max_history == 0 {
+            return;
+        }
+        let kept: Vec<(String, u64, u64)> = self
+            .entries()
+            .take(max_history)
+            .map(|(s, ts, seq)| (s.to_string(), ts, seq))
+            .collect();
+        self.strings = vec![None; max_history];
+        self.timestamps = vec![0; max_history];
+        self.seqs = vec![0; max_history];
+        self.max_history = max_history;
+        self.current = kept.len();
+        for (idx, (s, ts, seq)) in kept.into_iter().rev().enumerate() {
+            self.strings[idx] = Some(s);
+            self.timestamps[idx] = ts;
+            self.seqs[idx] = seq;
+        }
+    }
+
     pub fn id(&self) -> HistoryId {
         self.id
     }
@@ -95,6 +147,7 @@ impl History {
 pub struct HistorySet {
     histories: Vec<History>,
     max_history: usize,
+    namespace: String, // MUD whose main input history is in use ("" = none)
 }
 
 impl HistorySet {
@@ -102,18 +155,52 @@ impl HistorySet {
         Self {
             histories: Vec::new(),
             max_history,
+            namespace: String::new(),
+        }
+    }
+
+    /// Use `namespace`'s main input history from now on, keeping
+    /// `max_history` lines in it (each MUD and the offline world has one)
+    pub fn select(&mut self, namespace: &str, max_history: usize) {
+        self.namespace = namespace.to_string();
+        self.max_history = max_history.max(1);
+        if let Some(hist) = self
+            .histories
+            .iter_mut()
+            .find(|h| h.id() == HistoryId::MainInput && h.namespace == namespace)
+        {
+            hist.resize(max_history.max(1));
+        }
+    }
+
+    /// Namespace in use for the main input ("" before a MUD is chosen)
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Namespace a line added to `id` goes to
+    fn namespace_for(&self, id: HistoryId, s: &str) -> &str {
+        match id {
+            HistoryId::MainInput if s.starts_with('#') => COMMANDS_NAMESPACE,
+            HistoryId::MainInput => &self.namespace,
+            _ => "",
         }
     }
 
     /// Find or create history for given ID (C++ HistorySet::find, lines 123-131)
-    fn find_or_create(&mut self, id: HistoryId) -> &mut History {
+    fn find_or_create(&mut self, id: HistoryId, namespace: &str) -> &mut History {
         // Find existing
-        if let Some(idx) = self.histories.iter().position(|h| h.id() == id) {
+        if let Some(idx) = self
+            .histories
+            .iter()
+            .position(|h| h.id() == id && h.namespace == namespace)
+        {
             return &mut self.histories[idx];
         }
 
         // Create new
-        let hist = History::new(id, self.max_history);
+        let mut hist = History::new(id, self.max_history);
+        hist.namespace = namespace.to_string();
         self.histories.push(hist);
         self.histories.last_mut().unwrap()
     }
@@ -121,12 +208,45 @@ impl HistorySet {
     /// Add to history (C++ HistorySet::add, lines 116-118)
     pub fn add(&mut self, id: HistoryId, s: &str, timestamp: Option<u64>) {
         let ts = timestamp.unwrap_or_else(current_time);
-        self.find_or_create(id).add(s, ts);
+        let namespace = self.namespace_for(id, s).to_string();
+        self.find_or_create(id, &namespace).add(s, ts);
+    }
+
+    /// Lines recalled for `id`, newest first: the main input's come from
+    /// the selected MUD's history and the shared #commands one
+    fn recall(&self, id: HistoryId) -> Vec<(&str, u64)> {
+        let mut lines: Vec<(&str, u64, u64)> = self
+            .histories
+            .iter()
+            .filter(|h| {
+                h.id() == id
+                    && match id {
+                        HistoryId::MainInput => {
+                            h.namespace == self.namespace || h.namespace == COMMANDS_NAMESPACE
+                        }
+                        _ => h.namespace.is_empty(),
+                    }
+            })
+            .flat_map(|h| h.entries())
+            .collect();
+        lines.sort_by_key(|&(_, ts, seq)| std::cmp::Reverse((ts, seq)));
+        lines.into_iter().map(|(s, ts, _)| (s, ts)).collect()
     }
 
     /// Get from history (C++ HistorySet::get, lines 111-113)
+    /// count=1 gets the newest line
     pub fn get(&mut self, id: HistoryId, count: usize) -> Option<(&str, u64)> {
-        self.find_or_create(id).get(count)
+        self.recall(id).into_iter().nth(count.checked_sub(1)?)
+    }
+
+    /// Newest line at or before `from` (a get() count) containing `query`
+    pub fn search(&mut self, id: HistoryId, query: &str, from: usize) -> Option<(usize, &str)> {
+        self.recall(id)
+            .into_iter()
+            .enumerate()
+            .skip(from.max(1) - 1)
+            .find(|(_, (s, _))| s.contains(query))
+            .map(|(i, (s, _))| (i + 1, s))
     }
 
     /// Save history to ~/.mcl/history (C++ HistorySet::saveHistory, lines 80-94)
@@ -153,14 +273,17 @@ impl HistorySet {
             fs::set_permissions(&path, perms)?;
         }
 
-        // Write all histories (C++ lines 87-90)
-        for hist in &mut self.histories {
-            let mut count = self.max_history;
-            while count > 0 {
-                if let Some((s, ts)) = hist.get(count) {
-                    writeln!(file, "{} {} {}", hist.id() as i32, ts, s)?;
-                }
-                count -= 1;
+        // Write all histories (C++ lines 87-90); a namespaced one's ID is
+        // written "2:<namespace>"
+        for hist in &self.histories {
+            let id = match hist.namespace.as_str() {
+                "" => (hist.id() as i32).to_string(),
+                namespace => format!("{}:{}", hist.id() as i32, namespace),
+            };
+            let mut lines: Vec<(&str, u64, u64)> = hist.entries().collect();
+            lines.reverse();
+            for (s, ts, _) in lines {
+                writeln!(file, "{} {} {}", id, ts, s)?;
             }
         }
 
@@ -185,8 +308,10 @@ impl HistorySet {
             let parts: Vec<&str> = line.splitn(3, ' ').collect();
 
             if parts.len() == 3 {
-                if let (Ok(id), Ok(ts)) = (parts[0].parse::<i32>(), parts[1].parse::<u64>()) {
-                    self.add(HistoryId::from(id), parts[2], Some(ts));
+                let (id, namespace) = parts[0].split_once(':').unwrap_or((parts[0], ""));
+                if let (Ok(id), Ok(ts)) = (id.parse::<i32>(), parts[1].parse::<u64>()) {
+                    self.find_or_create(HistoryId::from(id), namespace)
+                        .add(parts[2], ts);
                 }
             }
         }
@@ -254,6 +379,48 @@ mod tests {
         assert_eq!(h.get(4), None); // "a" is gone
     }
 
+    #[test]
+    fn history_search_newest_first() {
+        let mut hs = HistorySet::new(3);
+        for (i, s) in ["kill rat", "kill orc", "look", "kill troll"]
+            .iter()
+            .enumerate()
+        {
+            hs.add(HistoryId::MainInput, s, Some(i as u64));
+        }
+        let id = HistoryId::MainInput;
+        assert_eq!(hs.search(id, "kill", 1), Some((1, "kill troll")));
+        assert_eq!(hs.search(id, "kill", 2), Some((3, "kill orc")));
+        assert_eq!(hs.search(id, "rat", 1), None); // Wrapped away
+    }
+
+    #[test]
+    fn history_per_mud_with_shared_commands() {
+        let mut hs = HistorySet::new(10);
+        let id = HistoryId::MainInput;
+        hs.select("Aardwolf", 10);
+        hs.add(id, "kill rat", Some(99));
+        hs.add(id, "kill orc", Some(100));
+        hs.add(id, "#stats", Some(100));
+        hs.select("Offline", 2);
+        hs.add(id, "look", Some(101));
+        hs.add(id, "north", Some(102));
+        hs.add(id, "south", Some(103));
+
+        // Offline: its own two newest, then the #command from Aardwolf
+        let offline: Vec<String> = (1..=4)
+            .filter_map(|n| hs.get(id, n).map(|l| l.0.to_string()))
+            .collect();
+        assert_eq!(offline, vec!["south", "north", "#stats"]);
+
+        // Same second: the order they were typed in decides
+        hs.select("Aardwolf", 1);
+        assert_eq!(hs.get(id, 1), Some(("#stats", 100)));
+        assert_eq!(hs.get(id, 2), Some(("kill orc", 100)));
+        assert_eq!(hs.get(id, 3), None); // Trimmed to the newest line
+        assert_eq!(hs.get(HistoryId::OpenMud, 1), None);
+    }
+
     #[test]
     fn history_set_multiple_ids() {
         let mut hs = HistorySet::new(10);
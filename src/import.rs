@@ -0,0 +1,538 @@
+// Import - aliases and triggers from tintin++ and zMUD/CMUD command files
+//
+// `#import tintin <file>` reads #alias, #action, #substitute and #gag
+// statements (#ALIAS, #TRIGGER, #SUBSTITUTE and #GAG for `zmud`) and turns
+// their wildcard patterns into regexes. Anything that can't be converted is
+// listed with its line number instead of being guessed at.
+
+use crate::action::{Action, ActionType};
+use crate::alias::Alias;
+use crate::mud::Mud;
+use std::path::Path;
+
+/// Highest capture group an action's commands can refer to (%1 to %9)
+const MAX_CAPTURES: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tintin,
+    Zmud,
+}
+
+impl Format {
+    /// `tintin` or `zmud` (`cmud` exports are the same)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "tintin" | "tintin++" | "tt++" => Ok(Format::Tintin),
+            "zmud" | "cmud" => Ok(Format::Zmud),
+            _ => Err(format!(
+                "Unknown import format {} (use tintin or zmud)",
+                name.trim()
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Alias,
+    Action,
+    Subst,
+    Gag,
+    Comment,
+}
+
+/// (name, shortest abbreviation, command) per format
+const TINTIN_COMMANDS: &[(&str, usize, Command)] = &[
+    ("alias", 3, Command::Alias),
+    ("action", 3, Command::Action),
+    ("substitute", 3, Command::Subst),
+    ("gag", 3, Command::Gag),
+    ("nop", 3, Command::Comment),
+];
+const ZMUD_COMMANDS: &[(&str, usize, Command)] = &[
+    ("alias", 2, Command::Alias),
+    ("trigger", 2, Command::Action),
+    ("action", 2, Command::Action),
+    ("substitute", 3, Command::Subst),
+    ("gag", 2, Command::Gag),
+    ("noop", 3, Command::Comment),
+];
+
+/// What a file converted to
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub aliases: Vec<Alias>,
+    pub actions: Vec<Action>,
+    pub skipped: Vec<String>, // "Line N: reason" for each statement left out
+}
+
+impl Imported {
+    /// Add everything to the MUD, replacing aliases of the same name and
+    /// actions with the same pattern; returns a summary for the status line
+    /// (actions still need compiling)
+    pub fn apply_to(self, mud: &mut Mud) -> String {
+        let summary = format!(
+            "Imported {} aliases and {} actions ({} skipped)",
+            self.aliases.len(),
+            self.actions.len(),
+            self.skipped.len()
+        );
+        for alias in self.aliases {
+            mud.alias_list.retain(|a| a.name != alias.name);
+            mud.alias_list.push(alias);
+        }
+        for action in self.actions {
+            mud.action_list.retain(|a| a.pattern != action.pattern);
+            mud.action_list.push(action);
+        }
+        summary
+    }
+}
+
+/// Read and convert a command file (invalid UTF-8 is replaced, not refused)
+pub fn import_file(path: &Path, format: Format) -> Result<Imported, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(import(&String::from_utf8_lossy(&bytes), format))
+}
+
+/// Convert the statements in `text`
+pub fn import(text: &str, format: Format) -> Imported {
+    let mut out = Imported::default();
+    for (line_num, statement) in statements(text) {
+        if let Err(e) = convert(&statement, format, &mut out) {
+            out.skipped.push(format!("Line {}: {}", line_num, e));
+        }
+    }
+    out
+}
+
+/// Statements with the line each starts on; one continues over the
+/// following lines while it has braces open, or when the next line opens
+/// with a brace (joined with their indentation dropped, as tintin++ reads
+/// them)
+fn statements(text: &str) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut depth = 0i32;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if current.is_empty() {
+            if line.is_empty() {
+                continue;
+            }
+            match out.pop() {
+                Some((first, previous)) if line.starts_with('{') => {
+                    start = first;
+                    current = previous;
+                }
+                previous => {
+                    out.extend(previous);
+                    start = i + 1;
+                }
+            }
+        }
+        current.push_str(line);
+        for c in line.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth <= 0 {
+            out.push((start, std::mem::take(&mut current)));
+            depth = 0;
+        }
+    }
+    if !current.is_empty() {
+        out.push((start, current));
+    }
+    out
+}
+
+fn convert(statement: &str, format: Format, out: &mut Imported) -> Result<(), String> {
+    let Some(body) = statement.strip_prefix('#') else {
+        return Err(format!("not a command: {}", statement));
+    };
+    let end = body
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(body.len());
+    let (word, rest) = (body[..end].to_ascii_lowercase(), &body[end..]);
+    let table = match format {
+        Format::Tintin => TINTIN_COMMANDS,
+        Format::Zmud => ZMUD_COMMANDS,
+    };
+    let command = table
+        .iter()
+        .find(|(name, min, _)| word.len() >= *min && name.starts_with(word.as_str()))
+        .map(|(_, _, command)| *command)
+        .ok_or_else(|| format!("#{} is not supported", word))?;
+
+    let pattern = |p: &str| match format {
+        Format::Tintin => tintin_pattern(p),
+        Format::Zmud => zmud_pattern(p),
+    };
+    let commands = |c: &str| match format {
+        Format::Tintin => c.to_string(),
+        Format::Zmud => zmud_variables(c),
+    };
+    match command {
+        Command::Comment => {}
+        Command::Alias => {
+            let [name, text] = args(rest)?;
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '%') {
+                return Err(format!("alias name is a pattern: {}", name));
+            }
+            out.aliases.push(Alias::new(name, commands(&text)));
+        }
+        Command::Action | Command::Subst => {
+            let [from, to] = args(rest)?;
+            if command == Command::Action && to.is_empty() {
+                return Err(format!("action with no commands: {}", from));
+            }
+            let action_type = match command {
+                Command::Action => ActionType::Trigger,
+                _ => ActionType::Replacement,
+            };
+            out.actions
+                .push(Action::new(pattern(&from)?, commands(&to), action_type));
+        }
+        Command::Gag => {
+            let [from] = args(rest)?;
+            out.actions
+                .push(Action::new(pattern(&from)?, "", ActionType::Gag));
+        }
+    }
+    Ok(())
+}
+
+/// The first N arguments, each {braced} or a bare word; a bare last one
+/// takes the rest of the statement. Anything after them (tintin priorities,
+/// zMUD classes) is ignored
+fn args<const N: usize>(mut s: &str) -> Result<[String; N], String> {
+    let mut out: [String; N] = std::array::from_fn(|_| String::new());
+    for (i, arg) in out.iter_mut().enumerate() {
+        s = s.trim_start();
+        if s.is_empty() {
+            return Err(format!("expected {} arguments, got {}", N, i));
+        }
+        if s.starts_with('{') {
+            let end = closing_brace(s).ok_or_else(|| format!("unbalanced braces: {}", s))?;
+            *arg = s[1..end].trim().to_string();
+            s = &s[end + 1..];
+        } else if i + 1 == N {
+            *arg = s.trim().to_string();
+        } else {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            *arg = s[..end].to_string();
+            s = &s[end..];
+        }
+    }
+    Ok(out)
+}
+
+/// Byte index of the brace closing the one `s` starts with
+fn closing_brace(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Add `c` to a regex as a literal
+fn push_literal(re: &mut String, c: char) {
+    if "\\.+*?()|[]{}^$".contains(c) {
+        re.push('\\');
+    }
+    re.push(c);
+}
+
+/// tintin++ pattern as a regex: %1-%9 and %* capture anything, %d %w %s %S
+/// (and their capitals) capture digits, a word, spaces or non-spaces; a
+/// leading ^ and a final $ anchor the match
+fn tintin_pattern(pattern: &str) -> Result<String, String> {
+    let mut re = String::new();
+    let mut captures = 0;
+    let mut chars = pattern.chars().peekable();
+    if chars.peek() == Some(&'^') {
+        chars.next();
+        re.push('^');
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                let group = match chars.next() {
+                    Some(d) if d.is_ascii_digit() => {
+                        if chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                            return Err(format!(
+                                "%{}{} is past %9 in {}",
+                                d,
+                                chars.next().unwrap(),
+                                pattern
+                            ));
+                        }
+                        if chars.peek().is_none() {
+                            "(.*)"
+                        } else {
+                            "(.*?)"
+                        }
+                    }
+                    Some('*') => "(.*)",
+                    Some('d') => r"(\d*)",
+                    Some('D') => r"(\D*)",
+                    Some('w') => r"(\w*)",
+                    Some('W') => r"(\W*)",
+                    Some('s') => r"(\s*)",
+                    Some('S') => r"(\S*)",
+                    Some('%') => {
+                        re.push('%');
+                        continue;
+                    }
+                    Some(other) => {
+                        return Err(format!("%{} is not supported in {}", other, pattern))
+                    }
+                    None => {
+                        re.push('%');
+                        continue;
+                    }
+                };
+                captures += 1;
+                re.push_str(group);
+            }
+            '$' if chars.peek().is_none() => re.push('$'),
+            '{' => return Err(format!("embedded regex in {}", pattern)),
+            c => push_literal(&mut re, c),
+        }
+    }
+    if captures > MAX_CAPTURES {
+        return Err(format!(
+            "more than {} wildcards in {}",
+            MAX_CAPTURES, pattern
+        ));
+    }
+    Ok(re)
+}
+
+/// zMUD/CMUD pattern as a regex: * and ? match any text or character,
+/// %d %n %w %a %s %x a number, signed number, word, letters and digits,
+/// spaces or non-spaces; (...) captures, {a|b} is either and ~ quotes.
+/// Without parentheses every wildcard captures, as in zMUD
+fn zmud_pattern(pattern: &str) -> Result<String, String> {
+    let auto = !pattern.contains('(');
+    let wildcard = |re: &mut String, captures: &mut usize, class: &str| {
+        if auto {
+            *captures += 1;
+            re.push('(');
+            re.push_str(class);
+            re.push(')');
+        } else {
+            re.push_str(class);
+        }
+    };
+    let mut re = String::new();
+    let mut captures = 0;
+    let mut braces = 0;
+    let mut chars = pattern.chars().peekable();
+    if chars.peek() == Some(&'^') {
+        chars.next();
+        re.push('^');
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => wildcard(&mut re, &mut captures, ".*"),
+            '?' => re.push('.'),
+            '%' => match chars.next() {
+                Some('d') => wildcard(&mut re, &mut captures, r"\d+"),
+                Some('n') => wildcard(&mut re, &mut captures, r"[-+]?\d+"),
+                Some('w') => wildcard(&mut re, &mut captures, r"\w+"),
+                Some('a') => wildcard(&mut re, &mut captures, "[A-Za-z0-9]+"),
+                Some('s') => wildcard(&mut re, &mut captures, r"\s+"),
+                Some('x') => wildcard(&mut re, &mut captures, r"\S+"),
+                Some(other) => return Err(format!("%{} is not supported in {}", other, pattern)),
+                None => re.push('%'),
+            },
+            '(' => {
+                captures += 1;
+                re.push('(');
+            }
+            ')' => re.push(')'),
+            '{' => {
+                braces += 1;
+                re.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                re.push(')');
+            }
+            '|' if braces > 0 => re.push('|'),
+            '~' => match chars.next() {
+                Some(quoted) => push_literal(&mut re, quoted),
+                None => re.push('~'),
+            },
+            '$' if chars.peek().is_none() => re.push('$'),
+            '[' => return Err(format!("character ranges are not supported in {}", pattern)),
+            c => push_literal(&mut re, c),
+        }
+    }
+    if captures > MAX_CAPTURES {
+        return Err(format!(
+            "more than {} captures in {}",
+            MAX_CAPTURES, pattern
+        ));
+    }
+    Ok(re)
+}
+
+/// zMUD's @name variable references as okros $name ones
+fn zmud_variables(commands: &str) -> String {
+    let mut out = String::with_capacity(commands.len());
+    let mut chars = commands.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '@'
+            && chars
+                .peek()
+                .is_some_and(|n| n.is_ascii_alphabetic() || *n == '_')
+        {
+            out.push('$');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(imported: &Imported) -> Vec<(String, String, ActionType)> {
+        imported
+            .actions
+            .iter()
+            .map(|a| (a.pattern.clone(), a.commands.clone(), a.action_type))
+            .collect()
+    }
+
+    #[test]
+    fn tintin_statements_convert() {
+        let text = "#nop Combat\n\
+                    #alias {k} {kill %1}\n\
+                    #ALI gt {gtell %0}\n\
+                    #action {^%1 tells you '%2'} {reply got it;#var teller %1} {5}\n\
+                    #act {You are hungry.}\n\
+                    {\n    eat bread;\n    drink water\n}\n\
+                    #sub {%d gold coins} {<%1 gold>}\n\
+                    #gag {^A rat scurries}\n\
+                    #highlight {dragon} {red}\n\
+                    #alias {heal %1} {cast heal %1}\n\
+                    #action {%1 %2 %3 %4 %5 %6 %7 %8 %9 %10} {x}\n";
+        let imported = import(text, Format::Tintin);
+
+        let aliases: Vec<(&str, &str)> = imported
+            .aliases
+            .iter()
+            .map(|a| (a.name.as_str(), a.text.as_str()))
+            .collect();
+        assert_eq!(aliases, vec![("k", "kill %1"), ("gt", "gtell %0")]);
+        assert_eq!(
+            summary(&imported),
+            vec![
+                (
+                    "^(.*?) tells you '(.*?)'".to_string(),
+                    "reply got it;#var teller %1".to_string(),
+                    ActionType::Trigger
+                ),
+                (
+                    r"You are hungry\.".to_string(),
+                    "eat bread;drink water".to_string(),
+                    ActionType::Trigger
+                ),
+                (
+                    r"(\d*) gold coins".to_string(),
+                    "<%1 gold>".to_string(),
+                    ActionType::Replacement
+                ),
+                (
+                    "^A rat scurries".to_string(),
+                    String::new(),
+                    ActionType::Gag
+                ),
+            ]
+        );
+        assert_eq!(imported.skipped.len(), 3);
+        assert!(imported.skipped[0].starts_with("Line 12: #highlight"));
+        assert!(imported.skipped[1].starts_with("Line 13: alias name"));
+        assert!(imported.skipped[2].contains("past %9"));
+        for action in &imported.actions {
+            assert!(
+                regex::Regex::new(&action.pattern).is_ok(),
+                "{}",
+                action.pattern
+            );
+        }
+    }
+
+    #[test]
+    fn zmud_statements_convert() {
+        let text = "#CLASS {Combat}\n\
+                    #ALIAS k {kill %1}\n\
+                    #TRIGGER {^(%w) says, '(*)'} {say @greeting %1}\n\
+                    #TR {You have %d gold~.} {#var gold %1} Combat\n\
+                    #TRIGGER {{Bob|Alice} arrives} {wave}\n\
+                    #SUB {[a-z] rats} {rats}\n";
+        let imported = import(text, Format::Zmud);
+        assert_eq!(imported.aliases.len(), 1);
+        assert_eq!(
+            summary(&imported),
+            vec![
+                (
+                    r"^(\w+) says, '(.*)'".to_string(),
+                    "say $greeting %1".to_string(),
+                    ActionType::Trigger
+                ),
+                (
+                    r"You have (\d+) gold\.".to_string(),
+                    "#var gold %1".to_string(),
+                    ActionType::Trigger
+                ),
+                (
+                    "(?:Bob|Alice) arrives".to_string(),
+                    "wave".to_string(),
+                    ActionType::Trigger
+                ),
+            ]
+        );
+        assert_eq!(imported.skipped.len(), 2);
+        assert!(imported.skipped[0].starts_with("Line 1: #class"));
+        assert!(imported.skipped[1].contains("character ranges"));
+    }
+
+    #[test]
+    fn apply_replaces_by_name_and_pattern() {
+        let mut mud = Mud::new("Test", "localhost", 4000);
+        mud.alias_list.push(Alias::new("k", "kick %1"));
+        mud.alias_list.push(Alias::new("l", "look"));
+        let imported = import("#alias {k} {kill %1}\n#gag {spam}\n", Format::Tintin);
+        assert_eq!(
+            imported.apply_to(&mut mud),
+            "Imported 1 aliases and 1 actions (0 skipped)"
+        );
+        assert_eq!(mud.alias_list.len(), 2);
+        assert_eq!(mud.alias_list[1].text, "kill %1");
+        assert_eq!(mud.action_list[0].action_type, ActionType::Gag);
+        assert!(Format::parse("mushclient").is_err());
+    }
+}
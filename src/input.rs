@@ -1,5 +1,15 @@
 // Key normalization for terminal ESC sequences (subset), inspired by Toy 6.
 
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arrow {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum KeyCode {
     Escape,
@@ -7,6 +17,8 @@ pub enum KeyCode {
     ArrowDown,
     ArrowLeft,
     ArrowRight,
+    ShiftArrow(Arrow), // xterm CSI 1;2A etc.
+    CtrlArrow(Arrow),  // xterm CSI 1;5A etc. (Ctrl wins over Shift)
     Home,
     End,
     PageUp,
@@ -14,7 +26,9 @@ pub enum KeyCode {
     Insert,
     Delete,
     F(u8),
-    Alt(u8), // Alt + ASCII byte
+    Keypad(u8), // Keypad digit 0-9 (application keypad mode)
+    Alt(u8),    // Alt + ASCII byte
+    Paste,      // Bracketed paste ended; text from KeyDecoder::take_paste()
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -26,18 +40,31 @@ pub enum KeyEvent {
 enum EscState {
     None,
     Esc,
-    Csi(Vec<u8>),
-    EfO, // ESC O ...
+    Csi(Vec<u8>),   // ESC [ params...
+    Ss3(Vec<u8>),   // ESC O [params] final (F1-F4, app cursor/keypad keys)
+    Paste(Vec<u8>), // Between ESC [ 200 ~ and ESC [ 201 ~ (bracketed paste)
 }
 
+/// End of a bracketed paste
+const PASTE_END: &[u8] = b"\x1b[201~";
+/// Longest bracketed paste; past this it's taken as it stands and the rest
+/// decodes as keys (the end marker may have been lost)
+const MAX_PASTE: usize = 1 << 20;
+
 pub struct KeyDecoder {
     state: EscState,
+    pastes: VecDeque<String>, // One per KeyCode::Paste event, oldest first
+    extra: Vec<(Vec<u8>, KeyCode)>, // Terminfo keys the built-in table lacks
+    raw: Vec<u8>,             // The escape sequence so far, for `extra`
 }
 
 impl Default for KeyDecoder {
     fn default() -> Self {
         Self {
             state: EscState::None,
+            pastes: VecDeque::new(),
+            extra: Vec::new(),
+            raw: Vec::new(),
         }
     }
 }
@@ -45,12 +72,124 @@ impl KeyDecoder {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Also decode `seq` as `key` (terminfo's key capabilities, for
+    /// terminals outside the VT100/xterm family). Sequences the built-in
+    /// table already decodes to `key` are skipped
+    pub fn add_sequence(&mut self, seq: &[u8], key: KeyCode) {
+        if seq.len() < 2 || seq[0] != 0x1B {
+            return;
+        }
+        if KeyDecoder::new().feed(seq) != [KeyEvent::Key(key)] {
+            self.extra.push((seq.to_vec(), key));
+        }
+    }
+
+    /// Text of the oldest KeyCode::Paste event not yet taken
+    pub fn take_paste(&mut self) -> Option<String> {
+        self.pastes.pop_front()
+    }
+}
+
+/// Pasted text split into lines (CR, LF or CRLF breaks), trailing breaks dropped
+pub fn paste_lines(text: &str) -> Vec<String> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let text = text.trim_end_matches('\n');
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split('\n').map(String::from).collect()
+}
+
+/// Split "15;5" into (15, 5); missing values default to 1 like xterm
+fn csi_params(params: &[u8]) -> (u32, u32) {
+    let s = std::str::from_utf8(params).unwrap_or("");
+    let mut it = s.split(';').map(|p| p.parse::<u32>().unwrap_or(1));
+    (it.next().unwrap_or(1), it.next().unwrap_or(1))
+}
+
+/// Arrow key with xterm modifier parameter (2=Shift, 3=Alt, 5=Ctrl, ...)
+fn arrow_key(arrow: Arrow, modifier: u32) -> KeyCode {
+    let bits = modifier.saturating_sub(1);
+    if bits & 4 != 0 {
+        KeyCode::CtrlArrow(arrow)
+    } else if bits & 1 != 0 {
+        KeyCode::ShiftArrow(arrow)
+    } else {
+        match arrow {
+            Arrow::Up => KeyCode::ArrowUp,
+            Arrow::Down => KeyCode::ArrowDown,
+            Arrow::Left => KeyCode::ArrowLeft,
+            Arrow::Right => KeyCode::ArrowRight,
+        }
+    }
+}
+
+/// Letter-terminated keys shared by CSI and SS3 (arrows, Home/End, F1-F4)
+fn letter_key(fin: u8, modifier: u32) -> Option<KeyCode> {
+    Some(match fin {
+        b'A' => arrow_key(Arrow::Up, modifier),
+        b'B' => arrow_key(Arrow::Down, modifier),
+        b'C' => arrow_key(Arrow::Right, modifier),
+        b'D' => arrow_key(Arrow::Left, modifier),
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P'..=b'S' => KeyCode::F(fin - b'P' + 1),
+        _ => return None,
+    })
+}
+
+/// Tilde-terminated vt220 keys: ESC [ n ~ (modifier after ';' is ignored)
+fn tilde_key(n: u32) -> Option<KeyCode> {
+    Some(match n {
+        1 | 7 => KeyCode::Home,
+        2 => KeyCode::Insert,
+        3 => KeyCode::Delete,
+        4 | 8 => KeyCode::End,
+        5 => KeyCode::PageUp,
+        6 => KeyCode::PageDown,
+        11..=15 => KeyCode::F((n - 10) as u8),
+        17..=21 => KeyCode::F((n - 11) as u8),
+        23 | 24 => KeyCode::F((n - 12) as u8),
+        _ => return None,
+    })
+}
+
+/// SS3 final byte: application cursor keys, F1-F4 and the application keypad
+fn ss3_key(fin: u8, modifier: u32) -> Option<KeyEvent> {
+    if let Some(key) = letter_key(fin, modifier) {
+        return Some(KeyEvent::Key(key));
+    }
+    Some(match fin {
+        b'p'..=b'y' => KeyEvent::Key(KeyCode::Keypad(fin - b'p')),
+        b'M' => KeyEvent::Byte(b'\n'), // Keypad Enter
+        b'j' => KeyEvent::Byte(b'*'),
+        b'k' => KeyEvent::Byte(b'+'),
+        b'l' => KeyEvent::Byte(b','),
+        b'm' => KeyEvent::Byte(b'-'),
+        b'n' => KeyEvent::Byte(b'.'),
+        b'o' => KeyEvent::Byte(b'/'),
+        b'X' => KeyEvent::Byte(b'='),
+        _ => return None,
+    })
 }
 
 impl KeyDecoder {
     pub fn feed(&mut self, bytes: &[u8]) -> Vec<KeyEvent> {
         let mut out = Vec::new();
         for &b in bytes {
+            if !self.extra.is_empty() && !matches!(self.state, EscState::Paste(_)) {
+                if matches!(self.state, EscState::None) {
+                    self.raw.clear();
+                }
+                self.raw.push(b);
+                let found = self.extra.iter().find(|(seq, _)| *seq == self.raw);
+                if let Some(&(_, key)) = found {
+                    out.push(KeyEvent::Key(key));
+                    self.state = EscState::None;
+                    continue;
+                }
+            }
             match &mut self.state {
                 EscState::None => {
                     if b == 0x1B {
@@ -63,7 +202,7 @@ impl KeyDecoder {
                     if b == b'[' {
                         self.state = EscState::Csi(Vec::new());
                     } else if b == b'O' {
-                        self.state = EscState::EfO;
+                        self.state = EscState::Ss3(Vec::new());
                     }
                     // Alt-<letter>
                     else if (b as char).is_ascii_alphabetic() {
@@ -75,44 +214,70 @@ impl KeyDecoder {
                     }
                 }
                 EscState::Csi(buf) => {
-                    // Collect until a final byte in @A-Z~ range
-                    if b.is_ascii_alphabetic() {
-                        // Final letter
-                        match b {
-                            b'A' => out.push(KeyEvent::Key(KeyCode::ArrowUp)),
-                            b'B' => out.push(KeyEvent::Key(KeyCode::ArrowDown)),
-                            b'C' => out.push(KeyEvent::Key(KeyCode::ArrowRight)),
-                            b'D' => out.push(KeyEvent::Key(KeyCode::ArrowLeft)),
-                            b'H' => out.push(KeyEvent::Key(KeyCode::Home)),
-                            b'F' => out.push(KeyEvent::Key(KeyCode::End)),
-                            _ => { /* ignore unknown */ }
+                    if b == b'[' && buf.is_empty() {
+                        // Linux console F1-F5: ESC [ [ A..E
+                        buf.push(b);
+                    } else if buf.first() == Some(&b'[') {
+                        if (b'A'..=b'E').contains(&b) {
+                            out.push(KeyEvent::Key(KeyCode::F(b - b'A' + 1)));
+                        }
+                        self.state = EscState::None;
+                    } else if b.is_ascii_alphabetic() {
+                        // Final letter, maybe with modifier: ESC [ 1 ; 5 A
+                        let (_, modifier) = csi_params(buf);
+                        if let Some(key) = letter_key(b, modifier) {
+                            out.push(KeyEvent::Key(key));
                         }
                         self.state = EscState::None;
                     } else if b == b'~' {
-                        // Tilde-terminated sequences like [5~, [6~, [2~, [3~
-                        let seq = std::str::from_utf8(&buf[..]).unwrap_or("");
-                        match seq {
-                            "2" => out.push(KeyEvent::Key(KeyCode::Insert)),
-                            "3" => out.push(KeyEvent::Key(KeyCode::Delete)),
-                            "5" => out.push(KeyEvent::Key(KeyCode::PageUp)),
-                            "6" => out.push(KeyEvent::Key(KeyCode::PageDown)),
-                            _ => {}
+                        // Tilde-terminated sequences like [5~, [15~, [3;5~
+                        let (n, _) = csi_params(buf);
+                        if n == 200 {
+                            // Bracketed paste: taken verbatim until ESC [ 201 ~
+                            self.state = EscState::Paste(Vec::new());
+                            continue;
+                        }
+                        if let Some(key) = tilde_key(n) {
+                            out.push(KeyEvent::Key(key));
                         }
                         self.state = EscState::None;
-                    } else {
+                    } else if (0x20..=0x3F).contains(&b) {
+                        // Parameter/intermediate bytes (digits, ';', '?' ...)
                         buf.push(b);
+                    } else {
+                        // Unknown final byte: drop the sequence
+                        self.state = EscState::None;
                     }
                 }
-                EscState::EfO => {
-                    // ESC O P..S : F1..F4 on some terms
-                    match b {
-                        b'P' => out.push(KeyEvent::Key(KeyCode::F(1))),
-                        b'Q' => out.push(KeyEvent::Key(KeyCode::F(2))),
-                        b'R' => out.push(KeyEvent::Key(KeyCode::F(3))),
-                        b'S' => out.push(KeyEvent::Key(KeyCode::F(4))),
-                        _ => {}
+                EscState::Paste(buf) => {
+                    buf.push(b);
+                    let ended = buf.ends_with(PASTE_END);
+                    if ended || buf.len() >= MAX_PASTE {
+                        if ended {
+                            buf.truncate(buf.len() - PASTE_END.len());
+                        }
+                        let text = String::from_utf8_lossy(buf).into_owned();
+                        self.pastes.push_back(text);
+                        out.push(KeyEvent::Key(KeyCode::Paste));
+                        self.state = EscState::None;
+                    }
+                }
+                EscState::Ss3(buf) => {
+                    if b.is_ascii_digit() || b == b';' {
+                        // Modified F1-F4 on some terms: ESC O 1;2P / ESC O 2P
+                        buf.push(b);
+                    } else {
+                        let params = std::str::from_utf8(buf).unwrap_or("");
+                        let modifier = params
+                            .rsplit(';')
+                            .next()
+                            .and_then(|m| m.parse().ok())
+                            .unwrap_or(1);
+                        if let Some(ev) = ss3_key(b, modifier) {
+                            out.push(ev);
+                        }
+                        self.state = EscState::None;
                     }
-                    self.state = EscState::None;
                 }
             }
         }
@@ -151,6 +316,179 @@ mod tests {
         assert!(ev.iter().any(|e| matches!(e, KeyEvent::Key(KeyCode::F(4)))));
     }
 
+    #[test]
+    fn application_keypad_digits() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(b"\x1bOp\x1bOt\x1bOy");
+        assert_eq!(
+            ev,
+            vec![
+                KeyEvent::Key(KeyCode::Keypad(0)),
+                KeyEvent::Key(KeyCode::Keypad(4)),
+                KeyEvent::Key(KeyCode::Keypad(9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn function_keys_f1_to_f12() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(
+            b"\x1b[11~\x1b[12~\x1b[13~\x1b[14~\x1b[15~\x1b[17~\x1b[18~\x1b[19~\x1b[20~\x1b[21~\x1b[23~\x1b[24~",
+        );
+        let expected: Vec<KeyEvent> = (1..=12).map(|n| KeyEvent::Key(KeyCode::F(n))).collect();
+        assert_eq!(ev, expected);
+
+        // Linux console F1-F5 and modified F-keys
+        let ev = d.feed(b"\x1b[[A\x1b[[E\x1b[15;2~\x1bO2P\x1b[1;5Q");
+        assert_eq!(
+            ev,
+            vec![
+                KeyEvent::Key(KeyCode::F(1)),
+                KeyEvent::Key(KeyCode::F(5)),
+                KeyEvent::Key(KeyCode::F(5)),
+                KeyEvent::Key(KeyCode::F(1)),
+                KeyEvent::Key(KeyCode::F(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn modified_arrows() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(b"\x1b[1;2A\x1b[1;5D\x1b[1;6C\x1b[1;3B");
+        assert_eq!(
+            ev,
+            vec![
+                KeyEvent::Key(KeyCode::ShiftArrow(Arrow::Up)),
+                KeyEvent::Key(KeyCode::CtrlArrow(Arrow::Left)),
+                KeyEvent::Key(KeyCode::CtrlArrow(Arrow::Right)),
+                KeyEvent::Key(KeyCode::ArrowDown),
+            ]
+        );
+    }
+
+    #[test]
+    fn home_end_variants_and_app_cursor() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(b"\x1b[1~\x1b[4~\x1b[7~\x1b[8~\x1b[H\x1b[F\x1bOA\x1bOH");
+        assert_eq!(
+            ev,
+            vec![
+                KeyEvent::Key(KeyCode::Home),
+                KeyEvent::Key(KeyCode::End),
+                KeyEvent::Key(KeyCode::Home),
+                KeyEvent::Key(KeyCode::End),
+                KeyEvent::Key(KeyCode::Home),
+                KeyEvent::Key(KeyCode::End),
+                KeyEvent::Key(KeyCode::ArrowUp),
+                KeyEvent::Key(KeyCode::Home),
+            ]
+        );
+    }
+
+    #[test]
+    fn application_keypad_operators() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(b"\x1bOk\x1bOm\x1bOj\x1bOo\x1bOn\x1bOM");
+        assert_eq!(
+            ev,
+            b"+-*/.\n"
+                .iter()
+                .map(|&b| KeyEvent::Byte(b))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn bracketed_paste_is_one_event() {
+        let mut d = KeyDecoder::new();
+        let mut out = d.feed(b"a\x1b[200~look\r\nsay \x1b[A");
+        out.extend(d.feed(b"hi\n\x1b[20"));
+        out.extend(d.feed(b"1~b"));
+        assert_eq!(
+            out,
+            vec![
+                KeyEvent::Byte(b'a'),
+                KeyEvent::Key(KeyCode::Paste),
+                KeyEvent::Byte(b'b'),
+            ]
+        );
+        assert_eq!(d.take_paste().as_deref(), Some("look\r\nsay \x1b[Ahi\n"));
+        assert_eq!(d.take_paste(), None);
+        assert_eq!(paste_lines("a\r\nb\rc\n\n"), vec!["a", "b", "c"]);
+        assert!(paste_lines("\r\n").is_empty());
+    }
+
+    #[test]
+    fn paste_without_end_marker_is_cut_off() {
+        let mut d = KeyDecoder::new();
+        let mut out = d.feed(b"\x1b[200~");
+        out.extend(d.feed(&vec![b'x'; MAX_PASTE + 1]));
+        assert_eq!(
+            out,
+            vec![KeyEvent::Key(KeyCode::Paste), KeyEvent::Byte(b'x')]
+        );
+        assert_eq!(d.take_paste().map(|p| p.len()), Some(MAX_PASTE));
+        assert_eq!(
+            d.feed(b"\x1b[Ay"),
+            vec![KeyEvent::Key(KeyCode::ArrowUp), KeyEvent::Byte(b'y')]
+        );
+    }
+
+    #[test]
+    fn fragmented_sequences_byte_at_a_time() {
+        let input = b"\x1b[1;5A\x1b[24~\x1bOt\x1b[[Bx";
+        let mut d = KeyDecoder::new();
+        let mut out = Vec::new();
+        for &b in input.iter() {
+            out.extend(d.feed(&[b]));
+        }
+        assert_eq!(
+            out,
+            vec![
+                KeyEvent::Key(KeyCode::CtrlArrow(Arrow::Up)),
+                KeyEvent::Key(KeyCode::F(12)),
+                KeyEvent::Key(KeyCode::Keypad(4)),
+                KeyEvent::Key(KeyCode::F(2)),
+                KeyEvent::Byte(b'x'),
+            ]
+        );
+
+        // Split mid-parameter
+        let mut out = d.feed(b"\x1b[1");
+        out.extend(d.feed(b"5;"));
+        out.extend(d.feed(b"2~"));
+        assert_eq!(out, vec![KeyEvent::Key(KeyCode::F(5))]);
+    }
+
+    #[test]
+    fn unknown_sequences_are_dropped() {
+        let mut d = KeyDecoder::new();
+        let ev = d.feed(b"\x1b[99~\x1b[?x\x1bOZa");
+        assert_eq!(ev, vec![KeyEvent::Byte(b'a')]);
+    }
+
+    #[test]
+    fn terminfo_sequences_fill_gaps() {
+        let mut d = KeyDecoder::new();
+        // rxvt's shifted arrows; xterm's own are already known
+        d.add_sequence(b"\x1b[d", KeyCode::ShiftArrow(Arrow::Left));
+        d.add_sequence(b"\x1b[D", KeyCode::ArrowLeft);
+        d.add_sequence(b"\x1bOP", KeyCode::F(1));
+        assert_eq!(d.extra.len(), 1);
+        let mut out = d.feed(b"\x1b[");
+        out.extend(d.feed(b"dx\x1b[D"));
+        assert_eq!(
+            out,
+            vec![
+                KeyEvent::Key(KeyCode::ShiftArrow(Arrow::Left)),
+                KeyEvent::Byte(b'x'),
+                KeyEvent::Key(KeyCode::ArrowLeft),
+            ]
+        );
+    }
+
     #[test]
     fn fragmentation_across_chunks() {
         let mut d = KeyDecoder::new();
@@ -8,7 +8,7 @@
 use crate::history::HistoryId;
 use crate::input::{KeyCode, KeyEvent};
 use crate::input_line::InputLine;
-use crate::window::Window;
+use crate::window::{BorderStyle, Window};
 
 /// Callback type for InputBox execute
 /// NOTE: Send bound removed to allow capturing raw pointers (e.g., *mut OutputWindow)
@@ -78,8 +78,8 @@ impl InputBox {
         self.win.set_color(0x1F);
         self.win.clear();
 
-        // Draw border (similar to Selection - C++ uses Bordered style)
-        self.draw_border();
+        // Draw border (C++ uses Bordered style)
+        self.win.draw_border(BorderStyle::Single, None, 0x1F);
 
         // Print prompt at position (1,1) inside border (C++ lines 37-38)
         self.win.gotoxy(2, 2);
@@ -91,34 +91,6 @@ impl InputBox {
         self.win.dirty = false;
     }
 
-    /// Draw border (adapted from Selection::redraw)
-    fn draw_border(&mut self) {
-        let width = self.win.width;
-        let height = self.win.height;
-
-        // Top border
-        self.win.gotoxy(0, 0);
-        self.win.print("+");
-        for _ in 1..width - 1 {
-            self.win.print("-");
-        }
-        self.win.print("+");
-
-        // Left and right borders
-        for y in 1..height - 1 {
-            self.win.put_char(0, y, b'|', 0x1F);
-            self.win.put_char(width - 1, y, b'|', 0x1F);
-        }
-
-        // Bottom border
-        self.win.gotoxy(0, height - 1);
-        self.win.print("+");
-        for _ in 1..width - 1 {
-            self.win.print("-");
-        }
-        self.win.print("+");
-    }
-
     /// Handle keypress (C++ InputBox.cc:42-50)
     pub fn keypress(&mut self, key: KeyEvent) -> bool {
         // Check for Escape key (C++ lines 43-46)
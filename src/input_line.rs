@@ -7,11 +7,39 @@
 
 use crate::command_queue::{CommandQueue, EXPAND_INPUT, EXPAND_NONE, EXPAND_SEMICOLON};
 use crate::history::{HistoryId, HistorySet};
+use crate::input::{KeyCode, KeyEvent};
+use crate::scrollback::Attrib;
 use crate::window::Window;
 
 const MAX_INPUT_BUF: usize = 4096;
 const MAX_PROMPT_BUF: usize = 80;
 
+/// Ctrl-R reverse incremental search over the line's history.
+struct Search {
+    query: Vec<u8>,
+    found: usize,  // History count of the line shown, 0 for none yet
+    failed: bool,  // The query matches nothing older
+    saved: String, // Line before the search, put back by Esc
+}
+
+/// Convert a decoded key to the code InputLine::keypress() expects
+/// Returns None for keys the input line doesn't handle
+pub fn event_key_code(ev: KeyEvent) -> Option<i32> {
+    Some(match ev {
+        KeyEvent::Byte(b'\n') => 0x0D, // Enter
+        KeyEvent::Byte(b) => b as i32,
+        KeyEvent::Key(KeyCode::Keypad(d)) => (b'0' + d) as i32,
+        KeyEvent::Key(KeyCode::ArrowLeft) => 0x104,
+        KeyEvent::Key(KeyCode::ArrowRight) => 0x105,
+        KeyEvent::Key(KeyCode::ArrowUp) => 0x103,
+        KeyEvent::Key(KeyCode::ArrowDown) => 0x102,
+        KeyEvent::Key(KeyCode::Home) => 0x01, // Ctrl-A
+        KeyEvent::Key(KeyCode::End) => 0x05,  // Ctrl-E
+        KeyEvent::Key(KeyCode::Delete) => 0x14E,
+        _ => return None,
+    })
+}
+
 /// InputLine displays user input at bottom of screen (C++ InputLine class, InputLine.cc:199-505)
 pub struct InputLine {
     pub win: Box<Window>,
@@ -34,6 +62,9 @@ pub struct InputLine {
     histwordsize: usize,    // Minimum length to save to history
     expand_semicolon: bool, // Expand semicolons in execute()
     echo_input: bool,       // Echo input to output window
+    masked: bool,           // Server echo on (password): show '*', skip history
+    secret: Option<String>, // Line entered while masked, sent as-is by the caller
+    search: Option<Search>, // Ctrl-R in progress
 }
 
 impl InputLine {
@@ -56,6 +87,9 @@ impl InputLine {
             histwordsize: 3,        // C++ opt_histwordsize default
             expand_semicolon: true, // C++ opt_expand_semicolon default
             echo_input: false,      // C++ opt_echoinput default
+            masked: false,
+            secret: None,
+            search: None,
         }
     }
 
@@ -75,11 +109,41 @@ impl InputLine {
                 result.push(ch);
             }
         }
+        // Leave room to type: a MUD prompt can be wider than the screen
+        let keep = result
+            .char_indices()
+            .nth(self.win.width / 2)
+            .map_or(result.len(), |(i, _)| i);
+        result.truncate(keep);
 
         self.prompt_buf = result;
+        self.adjust();
         self.win.dirty = true;
     }
 
+    /// Change the line's color (#color input)
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+        self.win.color = color;
+        self.win.dirty = true;
+    }
+
+    /// Mask input while the server has echo turned off (telnet WILL ECHO)
+    pub fn set_masked(&mut self, on: bool) {
+        self.masked = on;
+        self.win.dirty = true;
+    }
+
+    pub fn is_masked(&self) -> bool {
+        self.masked
+    }
+
+    /// Line entered in masked mode: it bypasses history, the command queue
+    /// and alias expansion, so the caller sends it straight to the server
+    pub fn take_secret(&mut self) -> Option<String> {
+        self.secret.take()
+    }
+
     /// Set input buffer contents (C++ InputLine::set, lines 212-220)
     pub fn set(&mut self, s: &str) {
         self.input_buf = s.as_bytes().to_vec();
@@ -90,6 +154,22 @@ impl InputLine {
         self.win.dirty = true;
     }
 
+    /// Insert text at the cursor (paste); newlines become spaces, other
+    /// control characters are dropped
+    pub fn insert(&mut self, text: &str) {
+        let bytes = text.bytes().map(|b| if b == b'\n' { b' ' } else { b });
+        for b in bytes.filter(|&b| b >= 0x20) {
+            if self.max_pos >= MAX_INPUT_BUF - 1 {
+                break;
+            }
+            self.input_buf.insert(self.cursor_pos, b);
+            self.max_pos += 1;
+            self.cursor_pos += 1;
+        }
+        self.adjust();
+        self.win.dirty = true;
+    }
+
     /// Clear input line (C++ sets to empty string)
     pub fn clear(&mut self) {
         self.set("");
@@ -110,6 +190,11 @@ impl InputLine {
     ) -> bool {
         // TODO: Call embed_interp->run_quietly("keypress", ...) (C++ line 236-250)
 
+        if self.search.is_some() && self.search_keypress(key, history) {
+            self.win.dirty = true;
+            return true;
+        }
+
         match key {
             // Backspace / Ctrl-H (C++ lines 253-267)
             0x08 | 0x7F => {
@@ -117,6 +202,7 @@ impl InputLine {
                     if self.cursor_pos == self.max_pos {
                         self.max_pos -= 1;
                         self.cursor_pos -= 1;
+                        self.input_buf.truncate(self.max_pos);
                     } else {
                         // In middle of line
                         self.input_buf.remove(self.cursor_pos - 1);
@@ -135,7 +221,7 @@ impl InputLine {
 
             // Ctrl-C: Save to history but don't execute (C++ lines 272-278)
             0x03 => {
-                if self.max_pos > 0 {
+                if self.max_pos > 0 && !self.masked {
                     let text = String::from_utf8_lossy(&self.input_buf[..self.max_pos]);
                     history.add(self.history_id, &text, None);
                     self.set("");
@@ -148,6 +234,19 @@ impl InputLine {
                 self.max_pos = self.cursor_pos;
             }
 
+            // Ctrl-R: Reverse incremental history search
+            0x12 => {
+                if self.history_id != HistoryId::None && !self.masked {
+                    self.search = Some(Search {
+                        query: Vec::new(),
+                        found: 0,
+                        failed: false,
+                        saved: self.get_input(),
+                    });
+                    self.adjust();
+                }
+            }
+
             // Escape: Clear line (C++ lines 282-284)
             0x1B => {
                 self.set("");
@@ -209,7 +308,7 @@ impl InputLine {
                 let text = String::from_utf8_lossy(&self.input_buf[..self.max_pos]).to_string();
 
                 // Save to history if long enough (C++ lines 326-327)
-                if text.len() >= self.histwordsize {
+                if text.len() >= self.histwordsize && !self.masked {
                     history.add(self.history_id, &text, None);
                 }
 
@@ -224,7 +323,11 @@ impl InputLine {
                                         // TODO: move/resize window (C++ lines 335-337)
 
                 // Execute (C++ line 339)
-                self.execute(&text, command_queue);
+                if self.masked {
+                    self.secret = Some(text);
+                } else {
+                    self.execute(&text, command_queue);
+                }
             }
 
             // Arrow left (C++ lines 358-366)
@@ -306,6 +409,67 @@ impl InputLine {
         true
     }
 
+    /// A key during Ctrl-R: typing narrows the match, Ctrl-R again finds the
+    /// next older one, Enter keeps the match on the line, Esc or Ctrl-G puts
+    /// the old line back. Any other key keeps the match and is handled as
+    /// usual (returns false)
+    fn search_keypress(&mut self, key: i32, history: &mut HistorySet) -> bool {
+        let Some(search) = self.search.as_mut() else {
+            return false;
+        };
+        let from = match key {
+            0x12 => search.found + 1,
+            0x08 | 0x7F => {
+                search.query.pop();
+                1
+            }
+            ch if (0x20..0x100).contains(&ch) => {
+                search.query.push(ch as u8);
+                search.found
+            }
+            0x1B | 0x07 => {
+                let saved = std::mem::take(&mut search.saved);
+                self.search = None;
+                self.set(&saved);
+                return true;
+            }
+            0x0D => {
+                self.search = None;
+                self.adjust();
+                return true;
+            }
+            _ => {
+                self.search = None;
+                self.adjust();
+                return false;
+            }
+        };
+
+        if search.query.is_empty() {
+            search.found = 0;
+            search.failed = false;
+            let saved = search.saved.clone();
+            self.set(&saved);
+            return true;
+        }
+        let query = String::from_utf8_lossy(&search.query).into_owned();
+        match history.search(self.history_id, &query, from) {
+            Some((count, line)) => {
+                search.found = count;
+                search.failed = false;
+                let at = line.find(&query).unwrap_or(0);
+                self.history_pos = count;
+                self.set(line);
+                self.cursor_pos = at;
+                self.left_pos = 0;
+                self.adjust();
+            }
+            // Keep showing the last match, like readline
+            None => search.failed = true,
+        }
+        true
+    }
+
     /// Execute command (C++ MainInputLine::execute, lines 512-522)
     fn execute(&mut self, text: &str, command_queue: &mut CommandQueue) {
         // TODO: Call embed_interp->run_quietly("sys/userinput", ...) (C++ line 513)
@@ -323,13 +487,32 @@ impl InputLine {
         // }
     }
 
+    /// Current prompt text
+    pub fn prompt(&self) -> &str {
+        &self.prompt_buf
+    }
+
+    /// The prompt as drawn: the search mini-prompt during Ctrl-R
+    fn shown_prompt(&self) -> String {
+        match &self.search {
+            Some(search) => format!(
+                "({}reverse-i-search)'{}': ",
+                if search.failed { "failing " } else { "" },
+                String::from_utf8_lossy(&search.query)
+            ),
+            None => self.prompt_buf.clone(),
+        }
+    }
+
     /// Adjust left_pos for horizontal scrolling (C++ InputLine::adjust, lines 476-487)
     fn adjust(&mut self) {
         // TODO: Handle multiline input (C++ lines 477-482)
 
         // Single-line scrolling (C++ lines 484-486)
-        let prompt_len = self.prompt_buf.len();
-        while 1 + prompt_len + self.cursor_pos - self.left_pos >= self.win.width {
+        let prompt_len = self.shown_prompt().len();
+        while 1 + prompt_len + self.cursor_pos - self.left_pos >= self.win.width
+            && self.left_pos < self.cursor_pos
+        {
             self.left_pos += 1;
         }
     }
@@ -337,23 +520,24 @@ impl InputLine {
     /// Redraw window (C++ InputLine::redraw, lines 433-456)
     pub fn redraw(&mut self) {
         let width = self.win.width;
-        let prompt_len = self.prompt_buf.len();
+        let prompt = self.shown_prompt();
+        let prompt_len = prompt.len();
 
         // Fill with spaces in input color
-        let blank = ((self.color as u16) << 8) | (b' ' as u16);
+        let blank = ((self.color as Attrib) << 8) | (b' ' as Attrib);
         for a in &mut self.win.canvas {
             *a = blank;
         }
 
         // Write prompt
-        for (i, ch) in self.prompt_buf.bytes().enumerate().take(width) {
-            self.win.canvas[i] = ((self.color as u16) << 8) | (ch as u16);
+        for (i, ch) in prompt.bytes().enumerate().take(width) {
+            self.win.canvas[i] = ((self.color as Attrib) << 8) | (ch as Attrib);
         }
 
         // Write input buffer (C++ line 448 - show "<" if scrolled)
         let mut x = prompt_len;
         if self.left_pos > 0 && x < width {
-            self.win.canvas[x] = ((self.color as u16) << 8) | (b'<' as u16);
+            self.win.canvas[x] = ((self.color as Attrib) << 8) | (b'<' as Attrib);
             x += 1;
         }
 
@@ -362,7 +546,8 @@ impl InputLine {
             if x >= width {
                 break;
             }
-            self.win.canvas[x] = ((self.color as u16) << 8) | (self.input_buf[i] as u16);
+            let ch = if self.masked { b'*' } else { self.input_buf[i] };
+            self.win.canvas[x] = ((self.color as Attrib) << 8) | (ch as Attrib);
             x += 1;
         }
 
@@ -427,6 +612,10 @@ mod tests {
 
         assert_eq!(il.max_pos, 1);
         assert_eq!(&il.input_buf[..1], b"a");
+
+        // Typing after a backspace at EOL replaces the deleted char
+        il.keypress('c' as i32, &mut hist, &mut cq);
+        assert_eq!(il.get_input(), "ac");
     }
 
     #[test]
@@ -443,6 +632,19 @@ mod tests {
         assert_eq!(il.cursor_pos, 5);
     }
 
+    #[test]
+    fn insert_at_cursor() {
+        let mut il = InputLine::new(ptr::null_mut(), 80, 0x07, HistoryId::None);
+        let mut hist = HistorySet::new(10);
+        let mut cq = CommandQueue::new();
+
+        il.set("say !");
+        il.keypress(0x104, &mut hist, &mut cq); // Left
+        il.insert("two\nlines\x07");
+        assert_eq!(il.get_input(), "say two lines!");
+        assert_eq!(il.cursor_pos, 13);
+    }
+
     #[test]
     fn history_cycling() {
         let mut il = InputLine::new(ptr::null_mut(), 80, 0x07, HistoryId::MainInput);
@@ -464,4 +666,78 @@ mod tests {
         il.keypress(0x102, &mut hist, &mut cq); // Down
         assert_eq!(&il.input_buf[..il.max_pos], b"second");
     }
+
+    #[test]
+    fn masked_input_skips_history_and_queue() {
+        let mut il = InputLine::new(ptr::null_mut(), 20, 0x07, HistoryId::MainInput);
+        let mut hist = HistorySet::new(10);
+        let mut cq = CommandQueue::new();
+
+        il.set_masked(true);
+        il.set("hunter2");
+        il.redraw();
+        let shown: Vec<u8> = il.win.canvas.iter().map(|a| (a & 0xFF) as u8).collect();
+        assert!(!shown.windows(7).any(|w| w == b"hunter2"));
+        assert!(shown.windows(7).any(|w| w == b"*******"));
+
+        il.keypress(0x0D, &mut hist, &mut cq); // Enter
+        assert_eq!(il.take_secret().as_deref(), Some("hunter2"));
+        assert!(cq.execute().is_empty());
+        assert!(hist.get(HistoryId::MainInput, 1).is_none());
+
+        il.set_masked(false);
+        il.set("look");
+        il.keypress(0x0D, &mut hist, &mut cq);
+        assert_eq!(il.take_secret(), None);
+        assert_eq!(cq.execute(), vec!["look".to_string()]);
+    }
+
+    #[test]
+    fn reverse_search() {
+        let mut il = InputLine::new(ptr::null_mut(), 80, 0x07, HistoryId::MainInput);
+        let mut hist = HistorySet::new(10);
+        let mut cq = CommandQueue::new();
+        for line in ["kill orc", "look", "kill troll", "say hi"] {
+            hist.add(HistoryId::MainInput, line, None);
+        }
+
+        il.set("draft");
+        il.keypress(0x12, &mut hist, &mut cq); // Ctrl-R
+        il.keypress('k' as i32, &mut hist, &mut cq);
+        il.keypress('i' as i32, &mut hist, &mut cq);
+        assert_eq!(il.get_input(), "kill troll");
+        il.redraw();
+        let shown: String = il
+            .win
+            .canvas
+            .iter()
+            .map(|a| (a & 0xFF) as u8 as char)
+            .collect();
+        assert!(shown.starts_with("(reverse-i-search)'ki': kill troll"));
+
+        il.keypress(0x12, &mut hist, &mut cq); // Next older
+        assert_eq!(il.get_input(), "kill orc");
+        il.keypress(0x12, &mut hist, &mut cq); // None older: stays
+        assert_eq!(il.get_input(), "kill orc");
+        il.redraw();
+        assert_eq!((il.win.canvas[1] & 0xFF) as u8, b'f'); // "(failing ..."
+
+        // Esc puts the old line back
+        il.keypress(0x1B, &mut hist, &mut cq);
+        assert_eq!(il.get_input(), "draft");
+        assert_eq!(il.prompt(), "mcl>");
+
+        // Enter keeps the match for editing, then sends as usual
+        il.keypress(0x12, &mut hist, &mut cq);
+        for ch in "oo".chars() {
+            il.keypress(ch as i32, &mut hist, &mut cq);
+        }
+        assert_eq!(il.cursor_pos, 1);
+        il.keypress(0x0D, &mut hist, &mut cq);
+        assert_eq!(il.get_input(), "look");
+        assert!(cq.execute().is_empty());
+        il.keypress(0x05, &mut hist, &mut cq); // Ctrl-E: handled as usual
+        il.keypress(0x0D, &mut hist, &mut cq);
+        assert_eq!(cq.execute(), vec!["look".to_string()]);
+    }
 }
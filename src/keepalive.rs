@@ -0,0 +1,141 @@
+// Keepalive - something sent when the connection has been quiet (#keepalive)
+//
+// NAT routers and some firewalls forget a TCP connection
+// that carries nothing for a few minutes, so an idle character loses the
+// link. With a keepalive set, nothing written to the MUD for `interval`
+// sends telnet IAC NOP (which servers ignore) or a configured command - a
+// blank line for servers that drop unknown telnet commands. It's set per
+// MUD, like its host (`keepalive <seconds> [nop|blank|<command>]` in a MUD
+// block).
+
+use crate::mud::Mud;
+use std::fmt;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: #keepalive [off | <seconds> [nop|blank|<command>]]";
+
+/// What a keepalive sends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeepaliveSend {
+    /// Telnet IAC NOP
+    Nop,
+    /// A command through the send queue ("" for a blank line)
+    Command(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub send: KeepaliveSend,
+}
+
+impl Keepalive {
+    /// From `<seconds> [nop|blank|<command>]`
+    pub fn parse(args: &str) -> Result<Self, String> {
+        let (secs, what) = args
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((args.trim(), ""));
+        let secs: u64 = secs
+            .parse()
+            .ok()
+            .filter(|&s| s > 0)
+            .ok_or_else(|| format!("Expected seconds, not '{}'", secs))?;
+        let send = match what.trim() {
+            "" | "nop" => KeepaliveSend::Nop,
+            "blank" => KeepaliveSend::Command(String::new()),
+            command => KeepaliveSend::Command(command.to_string()),
+        };
+        Ok(Self {
+            interval: Duration::from_secs(secs),
+            send,
+        })
+    }
+
+    /// Has the connection been quiet long enough?
+    pub fn due(&self, idle: Duration) -> bool {
+        idle >= self.interval
+    }
+
+    /// The arguments parse() reads back, for the config file
+    pub fn config_text(&self) -> String {
+        match &self.send {
+            KeepaliveSend::Nop => self.interval.as_secs().to_string(),
+            KeepaliveSend::Command(c) if c.is_empty() => {
+                format!("{} blank", self.interval.as_secs())
+            }
+            KeepaliveSend::Command(c) => format!("{} {}", self.interval.as_secs(), c),
+        }
+    }
+}
+
+impl fmt::Display for Keepalive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match &self.send {
+            KeepaliveSend::Nop => "IAC NOP".to_string(),
+            KeepaliveSend::Command(c) if c.is_empty() => "a blank line".to_string(),
+            KeepaliveSend::Command(c) => format!("'{}'", c),
+        };
+        write!(
+            f,
+            "{} after {}s without output",
+            what,
+            self.interval.as_secs()
+        )
+    }
+}
+
+/// #keepalive [off | <seconds> [nop|blank|<command>]] for this MUD
+pub fn keepalive_command(args: &str, mud: &mut Mud) -> Result<String, String> {
+    match args.trim() {
+        "" => Ok(match &mud.keepalive {
+            Some(k) => format!("Keepalive: {}", k),
+            None => "Keepalive is off".to_string(),
+        }),
+        "off" => {
+            mud.keepalive = None;
+            Ok("Keepalive off".to_string())
+        }
+        args => {
+            let keepalive = Keepalive::parse(args).map_err(|e| format!("{} ({})", e, USAGE))?;
+            let message = format!("Keepalive: {}", keepalive);
+            mud.keepalive = Some(keepalive);
+            Ok(message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_describes_keepalives() {
+        let nop = Keepalive::parse("60").unwrap();
+        assert_eq!(nop.send, KeepaliveSend::Nop);
+        assert!(!nop.due(Duration::from_secs(59)));
+        assert!(nop.due(Duration::from_secs(60)));
+        assert_eq!(nop.config_text(), "60");
+        let blank = Keepalive::parse("300 blank").unwrap();
+        assert_eq!(blank.send, KeepaliveSend::Command(String::new()));
+        assert_eq!(blank.config_text(), "300 blank");
+        assert_eq!(
+            Keepalive::parse("90 score brief").unwrap().config_text(),
+            "90 score brief"
+        );
+        assert!(Keepalive::parse("0").is_err());
+        assert!(Keepalive::parse("soon").is_err());
+
+        let mut mud = Mud::empty();
+        mud.keepalive = Some(blank);
+        assert_eq!(
+            keepalive_command("", &mut mud).unwrap(),
+            "Keepalive: a blank line after 300s without output"
+        );
+        keepalive_command("60 nop", &mut mud).unwrap();
+        assert_eq!(mud.keepalive, Some(nop));
+        keepalive_command("off", &mut mud).unwrap();
+        assert_eq!(mud.keepalive, None);
+        assert!(keepalive_command("x", &mut mud).is_err());
+    }
+}
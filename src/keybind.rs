@@ -0,0 +1,237 @@
+// Keybindings - user-remappable keys (bind <key> <action>)
+//
+// C++ MCL hard-codes its hotkeys (Hotkey.cc: Alt-O, Alt-/, PgUp...) and only
+// lets macros send text. Here the hotkeys live in a table the config file
+// and #bind can change: a key (macro_def key names: f2, ^l, alt-o...) maps to
+// a built-in client action or to a command line run through the interpreter.
+// The table is consulted before macros and the input line.
+
+use crate::input::KeyEvent;
+use crate::macro_def::{event_key, key_lookup, key_name, KEY_ALT, KEY_PGDN, KEY_PGUP};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyAction {
+    ConnectMenu,     // Open the MUD selection menu
+    Search,          // Search the scrollback
+    PageUp,          // Scroll back a page
+    PageDown,        // Scroll forward a page (past the end leaves scrollback)
+    Redraw,          // Repaint the whole terminal
+    CopyMode,        // Select scrollback text with the keyboard
+    Paste,           // Insert the copy mode paste buffer into the input line
+    Quit,            // Exit okros
+    Command(String), // Anything else: run as if typed (aliases, ';', #commands)
+}
+
+/// Built-in action names as written in bind lines
+const ACTION_NAMES: &[(&str, KeyAction)] = &[
+    ("menu", KeyAction::ConnectMenu),
+    ("search", KeyAction::Search),
+    ("pageup", KeyAction::PageUp),
+    ("pagedown", KeyAction::PageDown),
+    ("redraw", KeyAction::Redraw),
+    ("copy", KeyAction::CopyMode),
+    ("paste", KeyAction::Paste),
+    ("quit", KeyAction::Quit),
+];
+
+impl KeyAction {
+    /// A built-in action name, otherwise a command line
+    /// (prefix a command with '\' to send a word like "quit" to the MUD)
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        ACTION_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(text))
+            .map(|(_, action)| action.clone())
+            .unwrap_or_else(|| KeyAction::Command(text.to_string()))
+    }
+
+    /// Inverse of parse
+    pub fn name(&self) -> &str {
+        match self {
+            KeyAction::Command(text) => text,
+            builtin => ACTION_NAMES
+                .iter()
+                .find(|(_, action)| action == builtin)
+                .map_or("", |(name, _)| name),
+        }
+    }
+}
+
+/// Key code -> action, starting from the C++ hotkeys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    binds: BTreeMap<i32, KeyAction>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        Self {
+            binds: Self::defaults().into_iter().collect(),
+        }
+    }
+
+    /// Hotkeys every okros starts with (C++ Hotkey.cc:77-78, ScrollbackController;
+    /// Alt-C/Alt-V copy mode and paste are okros additions)
+    fn defaults() -> Vec<(i32, KeyAction)> {
+        vec![
+            (KEY_ALT + b'o' as i32, KeyAction::ConnectMenu),
+            (KEY_ALT + b'/' as i32, KeyAction::Search),
+            (KEY_PGUP, KeyAction::PageUp),
+            (KEY_PGDN, KeyAction::PageDown),
+            (KEY_ALT + b'c' as i32, KeyAction::CopyMode),
+            (KEY_ALT + b'v' as i32, KeyAction::Paste),
+        ]
+    }
+
+    pub fn bind(&mut self, key: i32, action: KeyAction) {
+        self.binds.insert(key, action);
+    }
+
+    /// Remove a binding; false if the key wasn't bound
+    pub fn unbind(&mut self, key: i32) -> bool {
+        self.binds.remove(&key).is_some()
+    }
+
+    pub fn get(&self, key: i32) -> Option<&KeyAction> {
+        self.binds.get(&key)
+    }
+
+    /// Action bound to a decoded key event
+    pub fn lookup(&self, ev: KeyEvent) -> Option<&KeyAction> {
+        event_key(ev).and_then(|key| self.get(key))
+    }
+
+    /// "key action" lines in key order
+    pub fn list(&self) -> Vec<String> {
+        self.binds
+            .iter()
+            .map(|(key, action)| format!("{} {}", key_name(*key), action.name()))
+            .collect()
+    }
+
+    /// Config lines turning the defaults into this table
+    /// (unbind for removed defaults, bind for new or changed keys)
+    pub fn config_lines(&self) -> Vec<String> {
+        let defaults: BTreeMap<i32, KeyAction> = Self::defaults().into_iter().collect();
+        let mut lines: Vec<String> = defaults
+            .keys()
+            .filter(|key| !self.binds.contains_key(key))
+            .map(|key| format!("unbind {}", key_name(*key)))
+            .collect();
+        for (key, action) in &self.binds {
+            if defaults.get(key) != Some(action) {
+                lines.push(format!("bind {} {}", key_name(*key), action.name()));
+            }
+        }
+        lines
+    }
+
+    /// Handle a binding command line; None if it isn't one
+    ///   #bind                 - list bindings, one per line
+    ///   #bind <key>           - show one binding
+    ///   #bind <key> <action>  - bind a key (built-in action or command line)
+    ///   #unbind <key>         - remove a binding
+    pub fn handle_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        let line = line.trim();
+        let (cmd, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        let (name, action) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let action = action.trim();
+        let key = key_lookup(name).ok_or_else(|| format!("Invalid key name: {}", name));
+        let result = match cmd {
+            "#bind" if args.is_empty() => Ok(self.list().join("\n")),
+            "#bind" if action.is_empty() => key.map(|key| match self.get(key) {
+                Some(action) => format!("{} is bound to {}", key_name(key), action.name()),
+                None => format!("{} is not bound", key_name(key)),
+            }),
+            "#bind" => key.map(|key| {
+                let action = KeyAction::parse(action);
+                let msg = format!("Bound {} to {}", key_name(key), action.name());
+                self.bind(key, action);
+                msg
+            }),
+            "#unbind" if args.is_empty() || !action.is_empty() => {
+                Err("Usage: #unbind <key>".to_string())
+            }
+            "#unbind" => key.and_then(|key| {
+                if self.unbind(key) {
+                    Ok(format!("Unbound {}", key_name(key)))
+                } else {
+                    Err(format!("{} is not bound", key_name(key)))
+                }
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::KeyCode;
+
+    #[test]
+    fn defaults_cover_the_hotkeys() {
+        let keys = KeyBindings::new();
+        assert_eq!(
+            keys.lookup(KeyEvent::Key(KeyCode::Alt(b'o'))),
+            Some(&KeyAction::ConnectMenu)
+        );
+        assert_eq!(
+            keys.lookup(KeyEvent::Key(KeyCode::PageUp)),
+            Some(&KeyAction::PageUp)
+        );
+        assert_eq!(keys.lookup(KeyEvent::Byte(b'a')), None);
+        assert!(keys.config_lines().is_empty());
+    }
+
+    #[test]
+    fn bind_commands_and_builtins() {
+        let mut keys = KeyBindings::new();
+        assert_eq!(
+            keys.handle_command("#bind F2 #session next").unwrap(),
+            Ok("Bound f2 to #session next".to_string())
+        );
+        assert!(keys.handle_command("#bind ^L redraw").unwrap().is_ok());
+        assert_eq!(
+            keys.lookup(KeyEvent::Key(KeyCode::F(2))),
+            Some(&KeyAction::Command("#session next".to_string()))
+        );
+        assert_eq!(keys.lookup(KeyEvent::Byte(0x0C)), Some(&KeyAction::Redraw));
+        assert_eq!(
+            keys.handle_command("#bind ^l").unwrap(),
+            Ok("^l is bound to redraw".to_string())
+        );
+        assert!(keys.handle_command("#bind f99 look").unwrap().is_err());
+
+        assert!(keys.handle_command("#unbind alt-o").unwrap().is_ok());
+        assert!(keys.handle_command("#unbind alt-o").unwrap().is_err());
+        assert_eq!(keys.lookup(KeyEvent::Key(KeyCode::Alt(b'o'))), None);
+
+        let listing = keys.handle_command("#bind").unwrap().unwrap();
+        assert!(listing.lines().any(|l| l == "f2 #session next"));
+        assert!(listing.lines().any(|l| l == "pgup pageup"));
+        assert!(keys.handle_command("#macro f1 look").is_none());
+    }
+
+    #[test]
+    fn config_lines_record_changes_from_defaults() {
+        let mut keys = KeyBindings::new();
+        keys.unbind(KEY_PGUP);
+        keys.bind(KEY_PGDN, KeyAction::parse("\\pagedown"));
+        keys.bind(key_lookup("^l").unwrap(), KeyAction::Redraw);
+        assert_eq!(
+            keys.config_lines(),
+            vec!["unbind pgup", "bind ^l redraw", "bind pgdn \\pagedown"]
+        );
+    }
+}
@@ -0,0 +1,566 @@
+// Layout - user-splittable panes for the area above the input line
+//
+// C++ MCL's screen is fixed: status line, output window, input line
+// (main.cc:52-76). Here the space above the input line is a binary tree:
+// leaves are panes showing a content source, inner nodes split their
+// rectangle top/bottom or side by side with a one-cell separator between
+// the halves. The tree is pure geometry; TtyUi owns the windows.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub type PaneId = usize;
+
+/// Default chat pane pattern: says, tells, chats, shouts and whispers
+pub const CHAT_PATTERN: &str = r"(?i)\b(says?|tells? you|chats?|shouts?|whispers?)\b";
+
+/// What a pane shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Output,       // The session's scrollback (always exactly one)
+    Chat(String), // Completed MUD lines matching a regex
+    Map,          // Reserved for the mapper
+    Log(PathBuf), // Lines appended to a file
+}
+
+impl Source {
+    /// "output", "chat [regex]", "map" or "log <file>"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (name, arg) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let arg = arg.trim();
+        match (name, arg.is_empty()) {
+            ("output", true) => Ok(Source::Output),
+            ("chat", true) => Ok(Source::Chat(CHAT_PATTERN.to_string())),
+            ("chat", false) => regex::Regex::new(arg)
+                .map(|_| Source::Chat(arg.to_string()))
+                .map_err(|e| format!("Bad chat pattern: {}", e)),
+            ("map", true) => Ok(Source::Map),
+            ("log", false) => Ok(Source::Log(PathBuf::from(arg))),
+            ("log", true) => Err("Usage: #window split log <file>".to_string()),
+            _ => Err(format!("Unknown pane source: {}", text)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Source::Output => "output".to_string(),
+            Source::Chat(pattern) if pattern == CHAT_PATTERN => "chat".to_string(),
+            Source::Chat(pattern) => format!("chat {}", pattern),
+            Source::Map => "map".to_string(),
+            Source::Log(path) => format!("log {}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDir {
+    Horizontal, // Panes stacked top and bottom (#window split)
+    Vertical,   // Panes side by side (#window vsplit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Cells along a split direction
+    fn extent(&self, dir: SplitDir) -> usize {
+        match dir {
+            SplitDir::Horizontal => self.height,
+            SplitDir::Vertical => self.width,
+        }
+    }
+
+    /// Cut into first half, separator and second half; `first` is the
+    /// requested size of the first half (None = even split), clamped so both
+    /// halves keep at least one cell
+    fn split(&self, dir: SplitDir, first: Option<usize>) -> (Rect, Rect, Rect) {
+        let total = self.extent(dir);
+        let a = if total < 3 {
+            total
+        } else {
+            first.unwrap_or((total - 1) / 2).clamp(1, total - 2)
+        };
+        let sep = 1.min(total - a);
+        let b = total - a - sep;
+        let r = *self;
+        match dir {
+            SplitDir::Horizontal => (
+                Rect::new(r.x, r.y, r.width, a),
+                Rect::new(r.x, r.y + a, r.width, sep),
+                Rect::new(r.x, r.y + a + sep, r.width, b),
+            ),
+            SplitDir::Vertical => (
+                Rect::new(r.x, r.y, a, r.height),
+                Rect::new(r.x + a, r.y, sep, r.height),
+                Rect::new(r.x + a + sep, r.y, b, r.height),
+            ),
+        }
+    }
+}
+
+/// New size for the focused pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resize {
+    To(usize), // resize 10
+    By(isize), // resize +3 / resize -3
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowCommand {
+    List,
+    Split(SplitDir, Source),
+    Close(Option<PaneId>), // None = the focused pane
+    Resize(Resize),
+    Focus(PaneId),
+    FocusNext,
+}
+
+/// Parse a #window line; None if it isn't one
+///   #window                          - list panes
+///   #window split|vsplit [source]    - split the focused pane (default: chat)
+///   #window close [id]               - close a pane
+///   #window resize <n|+n|-n>         - size of the focused pane along its split
+///   #window focus <id> / #window next
+pub fn parse_command(line: &str) -> Option<Result<WindowCommand, String>> {
+    let line = line.trim();
+    let args = match line.strip_prefix("#window") {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest.trim(),
+        _ => return None,
+    };
+    let (sub, arg) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let arg = arg.trim();
+    let pane_id = |arg: &str| {
+        arg.parse::<PaneId>()
+            .map_err(|_| format!("Bad pane id: {}", arg))
+    };
+    let source = |arg: &str| {
+        if arg.is_empty() {
+            Ok(Source::Chat(CHAT_PATTERN.to_string()))
+        } else {
+            Source::parse(arg)
+        }
+    };
+    let result = match sub {
+        "" | "list" => Ok(WindowCommand::List),
+        "split" => source(arg).map(|s| WindowCommand::Split(SplitDir::Horizontal, s)),
+        "vsplit" => source(arg).map(|s| WindowCommand::Split(SplitDir::Vertical, s)),
+        "close" if arg.is_empty() => Ok(WindowCommand::Close(None)),
+        "close" => pane_id(arg).map(|id| WindowCommand::Close(Some(id))),
+        "resize" => {
+            let amount = if arg.starts_with(['+', '-']) {
+                arg.parse::<isize>().ok().map(Resize::By)
+            } else {
+                arg.parse::<usize>().ok().map(Resize::To)
+            };
+            amount
+                .map(WindowCommand::Resize)
+                .ok_or_else(|| "Usage: #window resize <n|+n|-n>".to_string())
+        }
+        "focus" => pane_id(arg).map(WindowCommand::Focus),
+        "next" => Ok(WindowCommand::FocusNext),
+        _ => Err(
+            "Usage: #window [split|vsplit [source]|close [id]|resize n|focus id|next]".to_string(),
+        ),
+    };
+    Some(result)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Pane(PaneId),
+    Split {
+        dir: SplitDir,
+        size: Option<usize>, // Cells of the first child; None = even split
+        first: Box<Node>,
+        second: Box<Node>,
+    },
+}
+
+impl Node {
+    fn first_pane(&self) -> PaneId {
+        match self {
+            Node::Pane(id) => *id,
+            Node::Split { first, .. } => first.first_pane(),
+        }
+    }
+
+    fn arrange(&self, area: Rect, out: &mut Arrangement) {
+        match self {
+            Node::Pane(id) => out.panes.push((*id, area)),
+            Node::Split {
+                dir,
+                size,
+                first,
+                second,
+            } => {
+                let (a, sep, b) = area.split(*dir, *size);
+                first.arrange(a, out);
+                out.separators.push((*dir, sep));
+                second.arrange(b, out);
+            }
+        }
+    }
+
+    /// Replace pane `id` with `with(id)`; true if found
+    fn replace(&mut self, id: PaneId, with: &mut dyn FnMut(PaneId) -> Node) -> bool {
+        match self {
+            Node::Pane(p) if *p == id => {
+                *self = with(id);
+                true
+            }
+            Node::Pane(_) => false,
+            Node::Split { first, second, .. } => {
+                first.replace(id, with) || second.replace(id, with)
+            }
+        }
+    }
+
+    /// Remove pane `id`, its sibling taking the parent's place; returns the
+    /// sibling subtree's first pane
+    fn remove(&mut self, id: PaneId) -> Option<PaneId> {
+        let Node::Split { first, second, .. } = self else {
+            return None;
+        };
+        let keep = if **first == Node::Pane(id) {
+            Some(std::mem::replace(second.as_mut(), Node::Pane(0)))
+        } else if **second == Node::Pane(id) {
+            Some(std::mem::replace(first.as_mut(), Node::Pane(0)))
+        } else {
+            None
+        };
+        match keep {
+            Some(node) => {
+                let next = node.first_pane();
+                *self = node;
+                Some(next)
+            }
+            None => first.remove(id).or_else(|| second.remove(id)),
+        }
+    }
+
+    /// Set the size of pane `id` in the nearest enclosing split
+    fn resize(&mut self, id: PaneId, area: Rect, amount: Resize) -> bool {
+        let Node::Split {
+            dir,
+            size,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+        let (a, _, b) = area.split(*dir, *size);
+        let total = area.extent(*dir);
+        let (current, is_first) = if **first == Node::Pane(id) {
+            (a.extent(*dir), true)
+        } else if **second == Node::Pane(id) {
+            (b.extent(*dir), false)
+        } else {
+            return first.resize(id, a, amount) || second.resize(id, b, amount);
+        };
+        let wanted = match amount {
+            Resize::To(n) => n,
+            Resize::By(n) => current.saturating_add_signed(n),
+        }
+        .clamp(1, total.saturating_sub(2).max(1));
+        *size = Some(if is_first {
+            wanted
+        } else {
+            total.saturating_sub(wanted + 1)
+        });
+        true
+    }
+}
+
+/// Pane and separator rectangles for one screen area
+#[derive(Debug, Default, PartialEq)]
+pub struct Arrangement {
+    pub panes: Vec<(PaneId, Rect)>,
+    pub separators: Vec<(SplitDir, Rect)>,
+}
+
+/// The pane tree, its sources and which pane has focus
+pub struct Layout {
+    root: Node,
+    sources: BTreeMap<PaneId, Source>,
+    focus: PaneId,
+    next_id: PaneId,
+}
+
+/// Id of the session output pane every layout starts with
+pub const OUTPUT_PANE: PaneId = 1;
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layout {
+    /// One pane showing the session output (the C++ layout)
+    pub fn new() -> Self {
+        Self {
+            root: Node::Pane(OUTPUT_PANE),
+            sources: BTreeMap::from([(OUTPUT_PANE, Source::Output)]),
+            focus: OUTPUT_PANE,
+            next_id: OUTPUT_PANE + 1,
+        }
+    }
+
+    pub fn source(&self, id: PaneId) -> Option<&Source> {
+        self.sources.get(&id)
+    }
+
+    pub fn focus(&self) -> PaneId {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, id: PaneId) -> Result<(), String> {
+        if !self.sources.contains_key(&id) {
+            return Err(format!("No pane {}", id));
+        }
+        self.focus = id;
+        Ok(())
+    }
+
+    /// Move focus to the next pane in screen order
+    pub fn focus_next(&mut self) {
+        let mut order = Arrangement::default();
+        self.root.arrange(Rect::new(0, 0, 0, 0), &mut order);
+        let ids: Vec<PaneId> = order.panes.iter().map(|(id, _)| *id).collect();
+        let at = ids.iter().position(|id| *id == self.focus).unwrap_or(0);
+        self.focus = ids[(at + 1) % ids.len()];
+    }
+
+    /// Split the focused pane; the new pane takes the bottom or right half
+    /// and the focus
+    pub fn split(&mut self, dir: SplitDir, source: Source, area: Rect) -> Result<PaneId, String> {
+        if source == Source::Output {
+            return Err("The session output already has a pane".to_string());
+        }
+        let rect = self
+            .arrange(area)
+            .panes
+            .into_iter()
+            .find(|(id, _)| *id == self.focus)
+            .map(|(_, rect)| rect)
+            .unwrap_or(area);
+        if rect.extent(dir) < 3 {
+            return Err("Pane too small to split".to_string());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.root.replace(self.focus, &mut |old| Node::Split {
+            dir,
+            size: None,
+            first: Box::new(Node::Pane(old)),
+            second: Box::new(Node::Pane(id)),
+        });
+        self.sources.insert(id, source);
+        self.focus = id;
+        Ok(id)
+    }
+
+    /// Close a pane; the session output pane stays
+    pub fn close(&mut self, id: PaneId) -> Result<(), String> {
+        match self.sources.get(&id) {
+            None => return Err(format!("No pane {}", id)),
+            Some(Source::Output) => return Err("The output pane can't be closed".to_string()),
+            Some(_) => {}
+        }
+        let next = self.root.remove(id);
+        self.sources.remove(&id);
+        if self.focus == id {
+            self.focus = next.unwrap_or(OUTPUT_PANE);
+        }
+        Ok(())
+    }
+
+    /// Resize the focused pane along the split that created it
+    pub fn resize(&mut self, amount: Resize, area: Rect) -> Result<(), String> {
+        if self.root.resize(self.focus, area, amount) {
+            Ok(())
+        } else {
+            Err("Only one pane; nothing to resize".to_string())
+        }
+    }
+
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        let mut out = Arrangement::default();
+        self.root.arrange(area, &mut out);
+        out
+    }
+
+    /// "id source" lines in screen order, the focused pane marked with '*'
+    pub fn list(&self) -> Vec<String> {
+        self.arrange(Rect::default())
+            .panes
+            .iter()
+            .map(|(id, _)| {
+                let mark = if *id == self.focus { "*" } else { " " };
+                format!("{}{} {}", mark, id, self.sources[id].describe())
+            })
+            .collect()
+    }
+
+    /// Apply a parsed #window command; Ok is a message for the user
+    pub fn apply(&mut self, cmd: WindowCommand, area: Rect) -> Result<String, String> {
+        match cmd {
+            WindowCommand::List => Ok(self.list().join("\n")),
+            WindowCommand::Split(dir, source) => {
+                let desc = source.describe();
+                self.split(dir, source, area)
+                    .map(|id| format!("Pane {}: {}", id, desc))
+            }
+            WindowCommand::Close(id) => {
+                let id = id.unwrap_or(self.focus);
+                self.close(id).map(|()| format!("Closed pane {}", id))
+            }
+            WindowCommand::Resize(amount) => self
+                .resize(amount, area)
+                .map(|()| format!("Resized pane {}", self.focus)),
+            WindowCommand::Focus(id) => self.set_focus(id).map(|()| format!("Focus: pane {}", id)),
+            WindowCommand::FocusNext => {
+                self.focus_next();
+                Ok(format!(
+                    "Focus: pane {} ({})",
+                    self.focus,
+                    self.sources[&self.focus].describe()
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 80,
+        height: 23,
+    };
+
+    #[test]
+    fn split_and_close() {
+        let mut layout = Layout::new();
+        assert_eq!(layout.arrange(AREA).panes, vec![(OUTPUT_PANE, AREA)]);
+
+        let chat = layout
+            .split(SplitDir::Horizontal, Source::Map, AREA)
+            .unwrap();
+        assert_eq!(layout.focus(), chat);
+        let arr = layout.arrange(AREA);
+        assert_eq!(
+            arr.panes,
+            vec![
+                (OUTPUT_PANE, Rect::new(0, 0, 80, 11)),
+                (chat, Rect::new(0, 12, 80, 11)),
+            ]
+        );
+        assert_eq!(
+            arr.separators,
+            vec![(SplitDir::Horizontal, Rect::new(0, 11, 80, 1))]
+        );
+
+        let log = layout
+            .split(SplitDir::Vertical, Source::Log("x.log".into()), AREA)
+            .unwrap();
+        assert_eq!(
+            layout.arrange(AREA).panes[1..],
+            [
+                (chat, Rect::new(0, 12, 39, 11)),
+                (log, Rect::new(40, 12, 40, 11)),
+            ]
+        );
+
+        assert!(layout.close(OUTPUT_PANE).is_err());
+        assert!(layout
+            .split(SplitDir::Vertical, Source::Output, AREA)
+            .is_err());
+        layout.close(chat).unwrap();
+        assert_eq!(layout.focus(), log);
+        assert_eq!(
+            layout.arrange(AREA).panes[1],
+            (log, Rect::new(0, 12, 80, 11))
+        );
+        layout.close(log).unwrap();
+        assert_eq!(layout.arrange(AREA).panes, vec![(OUTPUT_PANE, AREA)]);
+        assert_eq!(layout.focus(), OUTPUT_PANE);
+    }
+
+    #[test]
+    fn resize_and_focus() {
+        let mut layout = Layout::new();
+        assert!(layout.resize(Resize::To(5), AREA).is_err());
+        let chat = layout
+            .split(SplitDir::Horizontal, Source::Map, AREA)
+            .unwrap();
+
+        layout.resize(Resize::To(5), AREA).unwrap();
+        assert_eq!(
+            layout.arrange(AREA).panes[1],
+            (chat, Rect::new(0, 18, 80, 5))
+        );
+        layout.resize(Resize::By(-2), AREA).unwrap();
+        assert_eq!(layout.arrange(AREA).panes[1].1.height, 3);
+        layout.resize(Resize::To(100), AREA).unwrap();
+        assert_eq!(layout.arrange(AREA).panes[0].1.height, 1);
+
+        layout.focus_next();
+        assert_eq!(layout.focus(), OUTPUT_PANE);
+        layout.resize(Resize::To(15), AREA).unwrap();
+        assert_eq!(layout.arrange(AREA).panes[1].1.height, 7);
+        assert!(layout.set_focus(9).is_err());
+        assert_eq!(layout.list(), vec!["*1 output", " 2 map"]);
+    }
+
+    #[test]
+    fn window_commands() {
+        assert_eq!(parse_command("#windows"), None);
+        assert_eq!(parse_command("#window"), Some(Ok(WindowCommand::List)));
+        assert_eq!(
+            parse_command("#window split"),
+            Some(Ok(WindowCommand::Split(
+                SplitDir::Horizontal,
+                Source::Chat(CHAT_PATTERN.to_string())
+            )))
+        );
+        assert_eq!(
+            parse_command("#window vsplit log /tmp/a b.log"),
+            Some(Ok(WindowCommand::Split(
+                SplitDir::Vertical,
+                Source::Log("/tmp/a b.log".into())
+            )))
+        );
+        assert_eq!(
+            parse_command("#window resize -3"),
+            Some(Ok(WindowCommand::Resize(Resize::By(-3))))
+        );
+        assert_eq!(
+            parse_command("#window close 2"),
+            Some(Ok(WindowCommand::Close(Some(2))))
+        );
+        assert!(parse_command("#window split chat (").unwrap().is_err());
+        assert!(parse_command("#window split log").unwrap().is_err());
+        assert!(parse_command("#window resize big").unwrap().is_err());
+        assert!(parse_command("#window explode").unwrap().is_err());
+    }
+}
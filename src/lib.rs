@@ -1,28 +1,87 @@
+//! okros - a MUD client, usable as a library
+//!
+//! [`Client`] is the embedding API: connect to a MUD, read its lines and
+//! prompts as [`ClientEvent`]s, send commands and register triggers. The
+//! session core it drives ([`engine::SessionEngine`], [`session`], [`telnet`],
+//! [`config`], [`mud`] ...) is public too. Modules hidden from the docs are
+//! the okros binary's terminal front-end and may change without notice.
+
+pub use client::{Client, ClientEvent};
+
 pub mod ansi;
+pub mod atcp;
+pub mod auto_login;
+pub mod bell;
+pub mod charset;
+pub mod client;
 pub mod color;
 pub mod command_queue;
+pub mod command_registry;
 pub mod config;
 pub mod control;
+#[doc(hidden)]
+pub mod copy_mode;
 pub mod debug_log;
 pub mod engine;
+pub mod event_bus;
+pub mod filters;
+#[doc(hidden)]
+pub mod frame_writer;
+pub mod group;
+pub mod headless_screen;
 pub mod history;
+pub mod import;
+#[doc(hidden)]
 pub mod input_box;
+#[doc(hidden)]
 pub mod input_line;
+pub mod keepalive;
+#[doc(hidden)]
+pub mod keybind;
+#[doc(hidden)]
+pub mod layout;
+pub mod line_store;
 pub mod mccp;
+#[doc(hidden)]
+pub mod message_box;
+pub mod msp;
 pub mod mud;
+#[doc(hidden)]
 pub mod mud_selection;
+pub mod mxp;
+pub mod net_debug;
+pub mod netstats;
+#[doc(hidden)]
 pub mod output_window;
+#[doc(hidden)]
+pub mod pane;
+pub mod path;
+pub mod profile;
+pub mod replay;
+pub mod resolver;
+#[doc(hidden)]
+pub mod restart;
+#[doc(hidden)]
 pub mod screen;
+pub mod scripts;
 pub mod scrollback;
 pub mod scrollback_search;
 pub mod select;
 pub mod selectable;
+#[doc(hidden)]
 pub mod selection;
+pub mod send_queue;
 pub mod socket;
+pub mod stats;
 pub mod status_line;
 pub mod telnet;
+pub mod timer;
+pub mod trigger_guard;
+pub mod variables;
+#[doc(hidden)]
 pub mod window;
 pub mod plugins {
+    pub mod native_regex;
     #[cfg(feature = "perl")]
     pub mod perl;
     #[cfg(feature = "python")]
@@ -31,10 +90,18 @@ pub mod plugins {
 }
 pub mod action;
 pub mod alias;
+#[doc(hidden)]
 pub mod curses;
+#[doc(hidden)]
 pub mod input;
 pub mod macro_def;
 pub mod offline_mud;
 pub mod session;
 pub mod session_manager;
+#[doc(hidden)]
 pub mod tty;
+#[doc(hidden)]
+pub mod tty_ui;
+#[doc(hidden)]
+pub mod ttyrec;
+pub mod url;
@@ -0,0 +1,334 @@
+// LineStore - every line printed to a scrollback, kept whole
+//
+// The scrollback ring is screen-width rows that forget whatever scrolls off
+// its top; this keeps the lines behind it. Lines are stored unwrapped (one
+// Attrib per character) so they can be re-rendered at any width and
+// searched. Past the memory cap the oldest lines are dropped, or, with
+// spilling on (`scrollbackspill on`), go to a temporary file (four bytes
+// per cell, little-endian, unlinked on unix as soon as it's open) that is
+// cut back to its newer half whenever it passes its size limit. Lines are
+// numbered from 0, the first line ever pushed, so numbers stay valid as
+// lines move to disk.
+
+use crate::scrollback::Attrib;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lines kept in memory by default
+pub const DEFAULT_MEMORY_LINES: usize = 10_000;
+/// Spill file size past which its older half is dropped (`scrollbackspillmax`)
+pub const DEFAULT_SPILL_LIMIT: u64 = 64 << 20;
+
+/// Temp file names must be unique per store
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Spilled lines: the file and where each line starts in it
+struct Spill {
+    file: File,
+    path: PathBuf,
+    offsets: Vec<u64>,
+    end: u64,
+}
+
+impl Spill {
+    fn create() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "okros-history-{}-{}",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&path);
+        Ok(Self {
+            file,
+            path,
+            offsets: Vec::new(),
+            end: 0,
+        })
+    }
+
+    fn append<'a>(&mut self, lines: impl Iterator<Item = &'a Vec<Attrib>>) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        let mut offsets = Vec::new();
+        for line in lines {
+            offsets.push(self.end + data.len() as u64);
+            data.extend(line.iter().flat_map(|a| a.to_le_bytes()));
+        }
+        (&self.file).seek(SeekFrom::Start(self.end))?;
+        (&self.file).write_all(&data)?;
+        self.offsets.extend(offsets);
+        self.end += data.len() as u64;
+        Ok(())
+    }
+
+    /// Byte range of the i'th spilled line
+    fn span(&self, i: usize) -> (u64, usize) {
+        let start = self.offsets[i];
+        let end = self.offsets.get(i + 1).copied().unwrap_or(self.end);
+        (start, (end - start) as usize)
+    }
+
+    /// Copy the lines in the newest `bytes` (at least the last one) to a
+    /// new file in place of this one; returns how many were dropped
+    fn keep_newest(&mut self, bytes: u64) -> std::io::Result<usize> {
+        let last = self.offsets.len().saturating_sub(1);
+        let first = self
+            .offsets
+            .partition_point(|&o| self.end - o > bytes)
+            .min(last);
+        let base = self.offsets.get(first).copied().unwrap_or(self.end);
+        let mut data = vec![0; (self.end - base) as usize];
+        (&self.file).seek(SeekFrom::Start(base))?;
+        (&self.file).read_exact(&mut data)?;
+        let mut fresh = Spill::create()?;
+        (&fresh.file).write_all(&data)?;
+        fresh.offsets = self.offsets[first..].iter().map(|o| o - base).collect();
+        fresh.end = data.len() as u64;
+        *self = fresh;
+        Ok(first)
+    }
+
+    fn read(&self, i: usize) -> Option<Vec<Attrib>> {
+        let (start, len) = self.span(i);
+        let mut data = vec![0; len];
+        (&self.file).seek(SeekFrom::Start(start)).ok()?;
+        (&self.file).read_exact(&mut data).ok()?;
+        Some(decode(&data))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn decode(data: &[u8]) -> Vec<Attrib> {
+    data.chunks_exact(4)
+        .map(|b| Attrib::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+pub struct LineStore {
+    lines: VecDeque<Vec<Attrib>>, // In memory, newest last
+    memory_lines: usize,
+    spill_enabled: bool,
+    spill_limit: u64, // Bytes the spill file may grow to
+    spill: Option<Spill>,
+    dropped: usize, // Lines before the first spilled/in-memory one
+}
+
+impl Default for LineStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_LINES, false)
+    }
+}
+
+impl LineStore {
+    /// Keep `memory_lines` lines in memory; older ones spill to disk if
+    /// `spill`, otherwise they are dropped
+    pub fn new(memory_lines: usize, spill: bool) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            memory_lines: memory_lines.max(1),
+            spill_enabled: spill,
+            spill_limit: DEFAULT_SPILL_LIMIT,
+            spill: None,
+            dropped: 0,
+        }
+    }
+
+    /// Switch spilling on or off, with the spill file's size limit in
+    /// bytes; switching it off drops what was on disk
+    pub fn set_spill(&mut self, on: bool, limit: u64) {
+        self.spill_enabled = on;
+        self.spill_limit = limit.max(1);
+        if !on {
+            self.dropped += self.spilled();
+            self.spill = None;
+        }
+    }
+
+    pub fn spill_enabled(&self) -> bool {
+        self.spill_enabled
+    }
+
+    /// Lines ever pushed (the number the next one gets)
+    pub fn len(&self) -> usize {
+        self.first_in_memory() + self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of the oldest line still available
+    pub fn first(&self) -> usize {
+        self.dropped
+    }
+
+    /// Lines held in the spill file
+    pub fn spilled(&self) -> usize {
+        self.spill.as_ref().map_or(0, |s| s.offsets.len())
+    }
+
+    fn first_in_memory(&self) -> usize {
+        self.dropped + self.spilled()
+    }
+
+    /// Bytes used by the lines in memory
+    pub fn memory_bytes(&self) -> usize {
+        self.lines
+            .iter()
+            .map(|l| l.capacity() * std::mem::size_of::<Attrib>())
+            .sum()
+    }
+
+    pub fn push(&mut self, mut line: Vec<Attrib>) {
+        // Lines come from a growing print buffer: don't keep its spare room
+        line.shrink_to_fit();
+        self.lines.push_back(line);
+        if self.lines.len() > self.memory_lines {
+            self.evict();
+        }
+    }
+
+    /// Move the oldest quarter of the memory lines to disk (or drop them)
+    fn evict(&mut self) {
+        let count = (self.memory_lines / 4).max(1).min(self.lines.len());
+        if self.spill_enabled && self.spill.is_none() {
+            match Spill::create() {
+                Ok(spill) => self.spill = Some(spill),
+                Err(_) => self.spill_enabled = false,
+            }
+        }
+        if let Some(spill) = self.spill.as_mut().filter(|_| self.spill_enabled) {
+            let mut result = spill.append(self.lines.range(..count));
+            if result.is_ok() && spill.end > self.spill_limit {
+                result = spill
+                    .keep_newest(self.spill_limit / 2)
+                    .map(|gone| self.dropped += gone);
+            }
+            if result.is_err() {
+                // Disk full or similar: give up on the file, drop from now on
+                self.dropped = self.first_in_memory() + count;
+                self.spill = None;
+                self.spill_enabled = false;
+            }
+        } else {
+            self.dropped += count;
+        }
+        self.lines.drain(..count);
+    }
+
+    /// Line number n, read back from disk if it was spilled
+    pub fn get(&self, n: usize) -> Option<Cow<'_, [Attrib]>> {
+        if n < self.dropped {
+            return None;
+        }
+        let mem = self.first_in_memory();
+        if n >= mem {
+            return self.lines.get(n - mem).map(|l| Cow::Borrowed(l.as_slice()));
+        }
+        self.spill.as_ref()?.read(n - self.dropped).map(Cow::Owned)
+    }
+
+    /// Lines from number `from` on, oldest first
+    pub fn iter_from(&self, from: usize) -> impl Iterator<Item = (usize, Cow<'_, [Attrib]>)> + '_ {
+        (from.max(self.dropped)..self.len()).filter_map(move |n| Some((n, self.get(n)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(s: &str) -> Vec<Attrib> {
+        s.bytes().map(|b| 0x0700 | b as Attrib).collect()
+    }
+
+    fn text(cells: &[Attrib]) -> String {
+        cells.iter().map(|a| (a & 0xFF) as u8 as char).collect()
+    }
+
+    #[test]
+    fn spills_old_lines_to_disk() {
+        let mut store = LineStore::new(4, true);
+        for i in 0..10 {
+            store.push(line(&format!("line {}", i)));
+        }
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.first(), 0);
+        assert!(store.spilled() >= 6);
+        assert_eq!(text(&store.get(0).unwrap()), "line 0");
+        assert_eq!(text(&store.get(9).unwrap()), "line 9");
+        assert!(store.get(10).is_none());
+
+        let all: Vec<String> = store.iter_from(3).map(|(_, l)| text(&l)).collect();
+        assert_eq!(all.len(), 7);
+        assert_eq!(all[0], "line 3");
+        assert_eq!(all[6], "line 9");
+        let numbers: Vec<usize> = store.iter_from(8).map(|(n, _)| n).collect();
+        assert_eq!(numbers, vec![8, 9]);
+    }
+
+    #[test]
+    fn spill_file_keeps_its_newer_half_past_the_limit() {
+        let mut store = LineStore::new(4, true);
+        // "line N" is 6 cells, 24 bytes on disk
+        store.set_spill(true, 24 * 10);
+        for i in 0..40 {
+            store.push(line(&format!("line {}", i % 10)));
+        }
+        assert_eq!(store.len(), 40);
+        assert!(store.first() > 0);
+        assert!(store.spilled() * 24 <= 24 * 10);
+        let first = store.first();
+        assert!(store.get(first - 1).is_none());
+        assert_eq!(
+            text(&store.get(first).unwrap()),
+            format!("line {}", first % 10)
+        );
+        assert_eq!(store.iter_from(0).count(), 40 - first);
+
+        // Off: the file goes, memory lines stay
+        store.set_spill(false, DEFAULT_SPILL_LIMIT);
+        assert_eq!(store.spilled(), 0);
+        assert_eq!(store.first(), store.len() - store.iter_from(0).count());
+        assert!(!LineStore::default().spill_enabled());
+    }
+
+    #[test]
+    fn drops_old_lines_without_spill() {
+        let mut store = LineStore::new(4, false);
+        for i in 0..10 {
+            store.push(line(&format!("{}", i)));
+        }
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.spilled(), 0);
+        assert!(store.first() > 0);
+        assert!(store.get(0).is_none());
+        let first = store.first();
+        let kept: Vec<usize> = store.iter_from(0).map(|(n, _)| n).collect();
+        assert_eq!(kept, (first..10).collect::<Vec<_>>());
+        assert_eq!(text(&store.get(first).unwrap()), first.to_string());
+    }
+
+    #[test]
+    fn stored_lines_drop_spare_capacity() {
+        let mut store = LineStore::default();
+        let mut line = Vec::with_capacity(4096);
+        line.extend([b'x' as Attrib; 10]);
+        store.push(line);
+        assert_eq!(store.memory_bytes(), 10 * std::mem::size_of::<Attrib>());
+    }
+}
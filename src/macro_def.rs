@@ -1,11 +1,123 @@
 // Macro - Keyboard shortcut bindings
 //
 // Ported from mcl-cpp-reference/h/Alias.h (Macro struct)
+// Key table ported from mcl-cpp-reference/Interpreter.cc key_lookup()/key_name()
+
+use crate::input::{KeyCode, KeyEvent};
+
+// Macro key codes: plain bytes are their ASCII value, special keys use the
+// ncurses values InputLine::keypress() already understands (0x102..)
+pub const KEY_DOWN: i32 = 0x102;
+pub const KEY_UP: i32 = 0x103;
+pub const KEY_LEFT: i32 = 0x104;
+pub const KEY_RIGHT: i32 = 0x105;
+pub const KEY_HOME: i32 = 0x106;
+pub const KEY_F0: i32 = 0x108; // KEY_F(n) = KEY_F0 + n
+pub const KEY_DELETE: i32 = 0x14A;
+pub const KEY_INSERT: i32 = 0x14B;
+pub const KEY_PGDN: i32 = 0x152;
+pub const KEY_PGUP: i32 = 0x153;
+pub const KEY_END: i32 = 0x168;
+pub const KEY_KP0: i32 = 0x200; // KP0..KP9 = KEY_KP0 + digit (no curses equivalent)
+pub const KEY_ALT: i32 = 0x300; // Alt-x = KEY_ALT + 'x'
+
+/// Named keys (C++ key table), besides F1..F12, KP0..KP9, Alt-x and ^x
+const KEY_NAMES: &[(&str, i32)] = &[
+    ("up", KEY_UP),
+    ("down", KEY_DOWN),
+    ("left", KEY_LEFT),
+    ("right", KEY_RIGHT),
+    ("home", KEY_HOME),
+    ("end", KEY_END),
+    ("pgup", KEY_PGUP),
+    ("pgdn", KEY_PGDN),
+    ("insert", KEY_INSERT),
+    ("delete", KEY_DELETE),
+    ("escape", 0x1B),
+    ("tab", 0x09),
+    ("backspace", 0x7F),
+];
+
+/// Map a symbolic key name to its macro key code (C++ key_lookup)
+/// Accepts f1..f12, kp0..kp9, alt-x / m-x, ^x (control), the names above
+/// and any single character. Case-insensitive except for single characters.
+pub fn key_lookup(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return c.is_ascii().then_some(c as i32);
+    }
+
+    let lower = name.to_ascii_lowercase();
+    if let Some(&(_, code)) = KEY_NAMES.iter().find(|(n, _)| *n == lower) {
+        return Some(code);
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<i32>().ok()) {
+        return (1..=12).contains(&n).then_some(KEY_F0 + n);
+    }
+    if let Some(d) = lower.strip_prefix("kp").and_then(|d| d.parse::<i32>().ok()) {
+        return (0..=9).contains(&d).then_some(KEY_KP0 + d);
+    }
+    let alt = lower
+        .strip_prefix("alt-")
+        .or_else(|| lower.strip_prefix("m-"));
+    if let Some(rest) = alt {
+        let b = *rest.as_bytes().first()?;
+        return (rest.len() == 1 && b.is_ascii_graphic()).then_some(KEY_ALT + b as i32);
+    }
+    if let Some(rest) = lower.strip_prefix('^') {
+        let b = *rest.as_bytes().first()?;
+        return (rest.len() == 1 && b.is_ascii_lowercase()).then_some((b - b'a' + 1) as i32);
+    }
+    None
+}
+
+/// Printable name for a macro key code (C++ key_name), inverse of key_lookup
+pub fn key_name(key: i32) -> String {
+    if let Some(&(name, _)) = KEY_NAMES.iter().find(|(_, code)| *code == key) {
+        return name.to_string();
+    }
+    match key {
+        k if (KEY_F0 + 1..=KEY_F0 + 12).contains(&k) => format!("f{}", k - KEY_F0),
+        k if (KEY_KP0..=KEY_KP0 + 9).contains(&k) => format!("kp{}", k - KEY_KP0),
+        k if (KEY_ALT + 0x21..KEY_ALT + 0x7F).contains(&k) => {
+            format!("alt-{}", (k - KEY_ALT) as u8 as char)
+        }
+        k if (1..=26).contains(&k) => format!("^{}", (b'a' + k as u8 - 1) as char),
+        k if (0x20..0x7F).contains(&k) => ((k as u8) as char).to_string(),
+        k => format!("0x{:x}", k),
+    }
+}
+
+/// Macro key code for a decoded key event, None for keys macros can't bind
+pub fn event_key(ev: KeyEvent) -> Option<i32> {
+    Some(match ev {
+        KeyEvent::Byte(b) => b as i32,
+        KeyEvent::Key(code) => match code {
+            KeyCode::Escape => 0x1B,
+            KeyCode::ArrowUp => KEY_UP,
+            KeyCode::ArrowDown => KEY_DOWN,
+            KeyCode::ArrowLeft => KEY_LEFT,
+            KeyCode::ArrowRight => KEY_RIGHT,
+            KeyCode::Home => KEY_HOME,
+            KeyCode::End => KEY_END,
+            KeyCode::PageUp => KEY_PGUP,
+            KeyCode::PageDown => KEY_PGDN,
+            KeyCode::Insert => KEY_INSERT,
+            KeyCode::Delete => KEY_DELETE,
+            KeyCode::F(n) if (1..=12).contains(&n) => KEY_F0 + n as i32,
+            KeyCode::F(_) => return None,
+            KeyCode::Keypad(d) => KEY_KP0 + d as i32,
+            KeyCode::Alt(b) => KEY_ALT + b as i32,
+            KeyCode::ShiftArrow(_) | KeyCode::CtrlArrow(_) | KeyCode::Paste => return None,
+        },
+    })
+}
 
 #[derive(Debug, Clone)]
 pub struct Macro {
     pub key: i32,
     pub text: String,
+    pub group: Option<String>, // #group it belongs to (None: always active)
 }
 
 impl Macro {
@@ -13,6 +125,7 @@ impl Macro {
         Self {
             key,
             text: text.into(),
+            group: None,
         }
     }
 }
@@ -46,6 +159,45 @@ mod tests {
         assert_eq!(m.text, "");
     }
 
+    #[test]
+    fn key_lookup_names() {
+        assert_eq!(key_lookup("F1"), Some(KEY_F0 + 1));
+        assert_eq!(key_lookup("f12"), Some(KEY_F0 + 12));
+        assert_eq!(key_lookup("f13"), None);
+        assert_eq!(key_lookup("KP0"), Some(KEY_KP0));
+        assert_eq!(key_lookup("kp9"), Some(KEY_KP0 + 9));
+        assert_eq!(key_lookup("kp10"), None);
+        assert_eq!(key_lookup("alt-x"), Some(KEY_ALT + b'x' as i32));
+        assert_eq!(key_lookup("M-x"), Some(KEY_ALT + b'x' as i32));
+        assert_eq!(key_lookup("^a"), Some(1));
+        assert_eq!(key_lookup("PgUp"), Some(KEY_PGUP));
+        assert_eq!(key_lookup("Q"), Some(b'Q' as i32));
+        assert_eq!(key_lookup("bogus"), None);
+        assert_eq!(key_lookup(""), None);
+    }
+
+    #[test]
+    fn key_name_round_trips() {
+        for name in ["f1", "f12", "kp0", "kp7", "alt-q", "^z", "pgdn", "up", "x"] {
+            assert_eq!(key_name(key_lookup(name).unwrap()), name);
+        }
+    }
+
+    #[test]
+    fn event_keys_match_lookup() {
+        assert_eq!(event_key(KeyEvent::Key(KeyCode::F(5))), key_lookup("f5"));
+        assert_eq!(
+            event_key(KeyEvent::Key(KeyCode::Alt(b'n'))),
+            key_lookup("alt-n")
+        );
+        assert_eq!(
+            event_key(KeyEvent::Key(KeyCode::Keypad(8))),
+            key_lookup("kp8")
+        );
+        assert_eq!(event_key(KeyEvent::Byte(b'a')), key_lookup("a"));
+        assert_eq!(event_key(KeyEvent::Key(KeyCode::F(20))), None);
+    }
+
     #[test]
     fn test_macro_multiline_text() {
         let m = Macro::new(10, "north\nsouth\nlook");
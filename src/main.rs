@@ -1,244 +1,875 @@
-use libc::{fcntl, F_SETFL, O_NONBLOCK};
+use okros::command_registry::Dispatch;
 use okros::control::{default_socket_path, ControlServer};
-use okros::curses::get_acs_caps;
 use okros::engine::SessionEngine;
-use okros::input::{KeyCode, KeyDecoder, KeyEvent};
+use okros::input::{KeyCode, KeyEvent};
 use okros::mccp::PassthroughDecomp;
-use okros::select::{poll_fds, READ, WRITE};
-use okros::session::Session;
+use okros::select::{Event, HookCall, Selector};
+use okros::selectable::Interest;
+use okros::session::{MsgLevel, Session};
 use okros::socket::{ConnState, Socket};
 use std::io::{self, BufRead, Read, Write};
-use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
-
-/// Resolve hostname to IPv4 address
-/// Supports both hostnames (e.g., "nodeka.com") and IPv4 addresses (e.g., "127.0.0.1")
-fn resolve_hostname(hostname: &str, port: u16) -> Result<Ipv4Addr, String> {
-    // First, try parsing as IPv4 address directly
-    if let Ok(ip) = hostname.parse::<Ipv4Addr>() {
-        return Ok(ip);
+use std::time::{Duration, Instant};
+
+/// Resolve hostname to the addresses to try, IPv6 and IPv4 interleaved
+/// Supports hostnames (e.g., "nodeka.com") and IPv4/IPv6 literals (e.g., "127.0.0.1", "[::1]")
+/// #commands the TTY loop runs itself: (name, usage, summary). The engine
+/// registers #help and its timer and pacing commands
+const TTY_COMMANDS: &[(&str, &str, &str)] = &[
+    ("open", "#open <host> <port>", "Connect to a MUD server"),
+    (
+        "close",
+        "#close",
+        "Log out (the MUD's logout command) and close the connection",
+    ),
+    ("zap", "#zap", "Drop the connection at once"),
+    ("quit", "#quit", "Leave okros"),
+    (
+        "restart",
+        "#restart [binary]",
+        "Re-exec okros (or another binary), keeping the connection",
+    ),
+    (
+        "alias",
+        "#alias <name> [expansion]",
+        "Define an alias (%1, %2... for arguments); no expansion removes it",
+    ),
+    (
+        "action",
+        "#action [-prompt] \"pattern\" <commands>",
+        "Run commands when a line (or prompt) matches",
+    ),
+    (
+        "subst",
+        "#subst \"pattern\" <replacement>",
+        "Replace text matching a pattern",
+    ),
+    ("gag", "#gag <pattern>", "Hide lines matching a pattern"),
+    (
+        "highlight",
+        "#highlight <pattern> <color>",
+        "Color text matching a pattern",
+    ),
+    (
+        "macro",
+        "#macro <key> [text]",
+        "Bind a key to text; no text removes the macro",
+    ),
+    (
+        "bind",
+        "#bind [<key> [<action>]]",
+        "Remap a key; no arguments lists the bindings",
+    ),
+    ("unbind", "#unbind <key>", "Remove a key binding"),
+    (
+        "window",
+        "#window [split|vsplit|close|resize|focus|next]",
+        "Split, size and switch panes",
+    ),
+    ("grep", "#grep <regex>", "Search the scrollback"),
+    (
+        "lastlog",
+        "#lastlog <text>",
+        "Search the scrollback for plain text",
+    ),
+    (
+        "save",
+        "#save [[-c] <file>]",
+        "Save aliases, actions and macros, or the scrollback to a file",
+    ),
+    ("load", "#load", "Re-read the config file and autosave"),
+    (
+        "import",
+        "#import tintin|zmud <file>",
+        "Convert aliases and triggers from a tintin++ or zMUD/CMUD file",
+    ),
+    (
+        "reload",
+        "#reload [file]",
+        "Re-run scripts and recompile actions",
+    ),
+    (
+        "interpreter",
+        "#interpreter [list | enable|disable <backend|function>]",
+        "List, switch off or on script backends and hook functions",
+    ),
+    (
+        "autoreload",
+        "#autoreload on|off",
+        "Reload scripts when they change",
+    ),
+    (
+        "timestamps",
+        "#timestamps on|off",
+        "Timestamp lines in the scrollback",
+    ),
+    (
+        "stickyprompt",
+        "#stickyprompt on|off",
+        "Show GA/EOR prompts on the input line",
+    ),
+    (
+        "diagnostics",
+        "#diagnostics on|off",
+        "Show refused telnet options and MCCP errors",
+    ),
+    ("wordwrap", "#wordwrap on|off", "Wrap long lines at spaces"),
+    (
+        "more",
+        "#more [<lines>|off]",
+        "Pause the output after this many lines until a key is pressed",
+    ),
+    (
+        "echoinput",
+        "#echoinput on|off",
+        "Echo commands sent to the MUD into the output",
+    ),
+    ("msp", "#msp on|off", "Play MUD Sound Protocol triggers"),
+    (
+        "url",
+        "#url [<n>]",
+        "Open URL <n> from the output (1 = latest); no argument lists them",
+    ),
+    (
+        "buffer",
+        "#buffer [<rows> | spill on|off]",
+        "Show the scrollback's size and memory use, resize it, or spill old lines to disk",
+    ),
+    (
+        "record",
+        "#record [<file>|off]",
+        "Save the screen as a ttyrec recording (asciinema for .cast files)",
+    ),
+    (
+        "ping",
+        "#ping [command]",
+        "Measure the server's round-trip time",
+    ),
+    (
+        "color",
+        "#color [<item> <color>]",
+        "Set UI colors for this MUD",
+    ),
+    (
+        "path",
+        "#path [learn <name>|stop|go <name>|delete <name>]",
+        "Record a walk and replay it as a speedwalk",
+    ),
+    (
+        "group",
+        "#group [<name> [on|off|add|remove ...]]",
+        "List groups, or switch a group's aliases/actions/macros/timers",
+    ),
+    (
+        "keepalive",
+        "#keepalive [off|<seconds> [nop|blank|<cmd>]]",
+        "Send something when this MUD's connection has been quiet",
+    ),
+    (
+        "charset",
+        "#charset [utf8|latin1]",
+        "Show or set the charset this MUD's text is in",
+    ),
+    (
+        "bell",
+        "#bell [beep|visual|hook|off]",
+        "Show or set what a bell from this MUD does",
+    ),
+    (
+        "stats",
+        "#stats",
+        "Show connection, traffic and trigger statistics",
+    ),
+];
+
+/// Write to the MUD socket, counting the bytes in the session's traffic stats
+fn write_mud(s: &Socket, session: &mut Session<PassthroughDecomp>, data: &[u8]) {
+    let n = unsafe {
+        libc::write(
+            s.as_raw_fd(),
+            data.as_ptr() as *const libc::c_void,
+            data.len(),
+        )
+    };
+    if n > 0 {
+        session.record_written(n as usize);
     }
+}
 
-    // If not a direct IP, do DNS resolution
-    let addr_str = format!("{}:{}", hostname, port);
-    match addr_str.to_socket_addrs() {
-        Ok(mut addrs) => {
-            // Find first IPv4 address
-            if let Some(SocketAddr::V4(v4_addr)) = addrs.find(|a| a.is_ipv4()) {
-                Ok(*v4_addr.ip())
-            } else {
-                Err(format!("No IPv4 address found for {}", hostname))
-            }
+/// --record FILE and --replay FILE [--speed N]
+fn record_replay_args(
+    args: &[String],
+) -> Result<
+    (
+        Option<okros::replay::Recorder>,
+        Option<okros::replay::Replay>,
+    ),
+    String,
+> {
+    use okros::replay::{speed_arg, Recorder, Replay};
+    let file = |flag: &str| -> Result<Option<std::path::PathBuf>, String> {
+        match args.iter().position(|a| a == flag) {
+            None => Ok(None),
+            Some(i) => args
+                .get(i + 1)
+                .map(|f| Some(okros::config::expand_path(f)))
+                .ok_or_else(|| format!("{} needs a file name", flag)),
         }
-        Err(e) => Err(format!("DNS lookup failed for {}: {}", hostname, e)),
-    }
+    };
+    let recorder = file("--record")?
+        .map(|path| Recorder::create(&path))
+        .transpose()?;
+    let replay = file("--replay")?
+        .map(|path| Replay::load(&path, speed_arg(args)?))
+        .transpose()?;
+    Ok((recorder, replay))
 }
 
 fn main() {
     // Clear debug log at startup
     okros::debug_log::clear_debug_log();
 
-    // CLI: --headless [--offline] --instance NAME | --attach NAME | --offline
+    // CLI: --headless [--offline [--area FILE]] --instance NAME [--control-tcp HOST:PORT]
+    //      | --attach NAME [--force]
+    //      | --offline [--area FILE]
+    //      [--record FILE | --replay FILE [--speed N]] in TTY and headless network mode
+    //      [mudname | host port] [--config FILE] in TTY mode: connect at startup
+    //      --restore FD is internal: passed to the new binary by #restart
     let args: Vec<String> = std::env::args().collect();
-    if args.len() > 2 && args[1] == "--headless" {
-        // Check for --offline flag in args
-        let offline = args.iter().any(|a| a == "--offline");
-
-        if offline {
-            // Headless offline mode: control socket + internal MUD
-            run_headless_offline_mode(&args);
-            return;
-        } else {
-            // Regular headless mode: control socket + network
-            let inst = args
-                .get(3)
-                .cloned()
-                .unwrap_or_else(|| "default".to_string());
-            let path = default_socket_path(&inst);
-            let eng = SessionEngine::new(PassthroughDecomp::new(), 80, 20, 2000);
-            let srv = ControlServer::new(path.clone(), eng);
-            eprintln!("Headless engine; control socket at {}", path.display());
-            let _ = srv.run();
+    match okros::config::Config::path_arg(&args) {
+        Ok(path) => path.into_iter().for_each(okros::config::Config::use_path),
+        Err(e) => {
+            eprintln!("{}", e);
             return;
         }
+    }
+    if args.len() > 2 && args[1] == "--headless" {
+        // Control socket + network, or + the offline world with --offline
+        let inst = args
+            .iter()
+            .position(|a| a == "--instance")
+            .and_then(|i| args.get(i + 1))
+            .cloned()
+            .unwrap_or_else(|| "default".to_string());
+        let path = default_socket_path(&inst);
+        let (recorder, replay) = match record_replay_args(&args) {
+            Ok(rr) => rr,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let eng = SessionEngine::new(PassthroughDecomp::new(), 80, 20, 2000);
+        let mut srv = ControlServer::new(path.clone(), eng);
+        if let Some(r) = recorder {
+            srv.record(r);
+        }
+        if let Some(r) = replay {
+            srv.replay(r);
+        }
+        if args.iter().any(|a| a == "--offline") {
+            let Some(world) = offline_world(&args) else {
+                return;
+            };
+            srv.offline(world);
+        }
+        eprintln!("Headless engine; control socket at {}", path.display());
+        if let Some(addr) = args
+            .iter()
+            .position(|a| a == "--control-tcp")
+            .and_then(|i| args.get(i + 1))
+        {
+            match srv.listen_tcp(addr) {
+                Ok(token) => eprintln!(
+                    "Control also on tcp {} (auth token in {})",
+                    addr,
+                    token.display()
+                ),
+                Err(e) => {
+                    eprintln!("--control-tcp {}: {}", addr, e);
+                    return;
+                }
+            }
+        }
+        let _ = srv.run();
+        return;
     } else if args.len() > 2 && args[1] == "--attach" {
         let inst = args
             .get(2)
             .cloned()
             .unwrap_or_else(|| "default".to_string());
         let path = default_socket_path(&inst);
-        match std::os::unix::net::UnixStream::connect(&path) {
-            Ok(mut s) => {
-                let _ = s.set_read_timeout(Some(std::time::Duration::from_millis(500)));
-                let _ = writeln!(s, "{}", serde_json::json!({"cmd":"get_buffer"}).to_string());
-                let mut buf = String::new();
-                let mut br = std::io::BufReader::new(s);
-                let _ = br.read_line(&mut buf);
-                println!("{}", buf.trim_end());
-            }
+        let force = args.iter().any(|a| a == "--force");
+        if let Err(e) = run_attach_mode(&path, force) {
+            eprintln!("attach failed: {}", e);
+        }
+        return;
+    } else if args.len() > 3 && (args[1] == "--send" || args[1] == "--script") {
+        // --send NAME "north;look" | --script FILE NAME (FILE "-" = stdin)
+        let (inst, text) = if args[1] == "--send" {
+            (&args[2], Ok(args[3].clone()))
+        } else if args[2] == "-" {
+            (&args[3], io::read_to_string(io::stdin()))
+        } else {
+            (&args[3], std::fs::read_to_string(&args[2]))
+        };
+        let wait = match args.iter().position(|a| a == "--wait") {
+            Some(i) => match args.get(i + 1).and_then(|ms| ms.parse().ok()) {
+                Some(ms) => Duration::from_millis(ms),
+                None => {
+                    eprintln!("--wait: expected milliseconds");
+                    std::process::exit(2);
+                }
+            },
+            None => BATCH_WAIT,
+        };
+        let result = text.and_then(|text| {
+            let lines = batch_lines(&text, args[1] == "--send");
+            run_batch_mode(&default_socket_path(inst), &lines, wait)
+        });
+        match result {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
             Err(e) => {
-                eprintln!("attach failed: {}", e);
+                eprintln!("{}: {}", args[1], e);
+                std::process::exit(1);
             }
         }
         return;
-    } else if args.len() > 1 && args[1] == "--offline" {
-        // Offline mode: internal MUD
-        run_offline_mode();
-        return;
+    }
+    let (mut recorder, mut replay) = match record_replay_args(&args) {
+        Ok(rr) => rr,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let target = match okros::config::StartupTarget::from_args(&args) {
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    // --offline [--area FILE]: start in the offline world; quitting it exits
+    let offline = args.iter().any(|a| a == "--offline");
+    let mut start_world = None;
+    if offline {
+        match offline_world(&args) {
+            Some(w) => start_world = Some(w),
+            None => return,
+        }
     }
 
     // Interactive TTY mode - suppress stdout before entering UI
     // (messages would corrupt the screen)
 
-    // Initialize embedded interpreters (matching main.cc:64, 101-105)
-    #[cfg(feature = "python")]
-    let mut python_interp = {
-        use okros::plugins::python::PythonInterpreter;
-        PythonInterpreter::new().ok()
+    // Config file (script limits apply before any script runs)
+    let mut config = okros::config::Config::new();
+    let config_loaded = config
+        .load_file(okros::config::Config::default_path())
+        .is_ok();
+    // okros <mudname>: the MUD must be in the config, with a host to connect to
+    let startup_mud = match &target {
+        Some(okros::config::StartupTarget::Mud(name)) => match config.find_mud(name) {
+            Some(m) if !m.hostname.is_empty() => Some(m.clone()),
+            _ => {
+                eprintln!(
+                    "No MUD named '{}' in {}",
+                    name,
+                    okros::config::Config::default_path().display()
+                );
+                return;
+            }
+        },
+        _ => None,
     };
 
+    // Embedded interpreters, stacked (matching main.cc:64, 101-105): Perl,
+    // then Python, then the native regex backend so actions always work.
+    // Hooks run through every enabled backend; actions use the first one.
+    use okros::plugins::stack::Interpreter;
+    let mut interps: okros::plugins::stack::StackedInterpreter<Box<dyn Interpreter>> =
+        okros::plugins::stack::StackedInterpreter::new();
     #[cfg(feature = "perl")]
-    let mut perl_interp = {
-        use okros::plugins::perl::PerlPlugin;
-        PerlPlugin::new().ok()
-    };
+    if let Ok(i) = okros::plugins::perl::PerlPlugin::new() {
+        interps.add_backend("perl", "pl", Box::new(i));
+    }
+    #[cfg(feature = "python")]
+    if let Ok(i) = okros::plugins::python::PythonInterpreter::new() {
+        interps.add_backend("python", "py", Box::new(i));
+    }
+    interps.add_backend(
+        "regex",
+        "",
+        Box::new(okros::plugins::native_regex::NativeRegex::new()),
+    );
+
+    // Hook timeout and sandbox (scripttimeout/scriptsandbox)
+    let limits_error = interps.set_limits(&config.script_limits).err();
+
+    // Load ~/.okros/scripts before sys/init so scripts can define it
+    let scripts_dir = okros::scripts::scripts_dir();
+    // Per-MUD `script` files are relative to the config file
+    let config_dir = okros::config::Config::default_path()
+        .parent()
+        .map(std::path::Path::to_path_buf)
+        .unwrap_or_default();
+    let (_, script_errors) = okros::scripts::load_all(&mut interps.by_extension(), &scripts_dir);
 
     // Set initial interpreter variables (main.cc:101-105)
     let current_time = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs() as i64;
-
-    #[cfg(feature = "python")]
-    if let Some(ref mut interp) = python_interp {
-        use okros::plugins::stack::Interpreter;
-        interp.set_int("now", current_time);
-        interp.set_str("VERSION", env!("CARGO_PKG_VERSION"));
-        interp.set_str("commandCharacter", "#");
-        // Run sys/init script if it exists
-        let mut out = String::new();
-        let _ = interp.run_quietly("sys/init", "", &mut out, true);
+    interps.set_int("now", current_time);
+    interps.set_str("VERSION", env!("CARGO_PKG_VERSION"));
+    interps.set_str("commandCharacter", "#");
+    // Run sys/init script if it exists
+    let _ = interps.run_quietly("sys/init", "", &mut String::new(), true);
+
+    // Interactive TTY mode: raw terminal, cleared screen and the widget tree
+    // (C++ main.cc:52-76); colors come from the config via apply_theme below
+    let mut ui =
+        match okros::tty_ui::TtyUi::new(config.scrollback, &okros::color::ColorTheme::default()) {
+            Ok(ui) => ui,
+            Err(e) => {
+                eprintln!("tty init failed: {}", e);
+                return;
+            }
+        };
+    let (width, height) = (ui.width(), ui.height());
+
+    // Session (MCCP->Telnet->ANSI->Scrollback), command queue, timers and
+    // paced sends. TTY mode: Session writes directly to OutputWindow.sb
+    // (C++ Session.h:35 Window *window)
+    let mut engine = SessionEngine::new(PassthroughDecomp::new(), width, height - 1, 2000);
+    engine.session.attach_window(ui.output_ptr());
+    for &(name, usage, summary) in TTY_COMMANDS {
+        engine.builtins.register_frontend(name, usage, summary);
     }
-
-    #[cfg(feature = "perl")]
-    if let Some(ref mut interp) = perl_interp {
-        use okros::plugins::stack::Interpreter;
-        interp.set_int("now", current_time);
-        interp.set_str("VERSION", env!("CARGO_PKG_VERSION"));
-        interp.set_str("commandCharacter", "#");
-        // Run sys/init script if it exists
-        let mut out = String::new();
-        let _ = interp.run_quietly("sys/init", "", &mut out, true);
+    // #echoinput: commands sent to the MUD are echoed into the output
+    let mut echo_input = config.echoinput;
+    // Config file plus automation saved with #save (if any)
+    if config_loaded {
+        engine.session.set_timestamps(config.timestamps);
+        engine.session.set_word_wrap(config.wordwrap);
+        engine.session.set_msp(config.msp);
+        engine.session.set_atcp(config.atcp);
+        engine.session.set_mxp(config.mxp);
+        ui.screen.hyperlinks = config.hyperlinks;
     }
-
-    // Interactive TTY mode: set raw mode, clear screen, hide cursor
-    let mut tty = match okros::tty::Tty::new() {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("tty init failed: {}", e);
-            return;
-        }
-    };
-    let _ = tty.enable_raw();
-    let _ = tty.keypad_application_mode(true);
-
-    // Clear screen and hide cursor
-    print!("\x1b[2J\x1b[H\x1b[?25l");
-    std::io::stdout().flush().unwrap();
-
-    // Get terminal size (C++ Screen.cc:16-34)
-    let (width, height) = unsafe {
-        let mut ws: libc::winsize = std::mem::zeroed();
-        if libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut ws) < 0 {
-            eprintln!("Failed to get terminal size, using 80x24");
-            (80usize, 24usize)
-        } else {
-            (ws.ws_col as usize, ws.ws_row as usize)
-        }
-    };
-    let caps = get_acs_caps();
-
-    // Create Screen (root Window) - C++ main.cc:52
-    let mut screen = okros::screen::Screen::new(width, height);
-
-    // Create OutputWindow as child of Screen - C++ main.cc:69
-    // C++ OutputWindow.cc:9-10: Window(_parent, wh_full, _parent->height-1)
-    let mut output = okros::output_window::OutputWindow::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        height - 1, // C++: _parent->height-1 (StatusLine overlaps at top)
-        2000,
-        0x07,
-    );
-    // Position OutputWindow at row 0 - C++ defaults parent_y to 0
-    // StatusLine will overlap at top (higher z-order)
-    output.win.parent_y = 0;
-
-    // Session for processing incoming bytes (MCCP->Telnet->ANSI->Scrollback)
-    // TTY mode: Session writes directly to OutputWindow.sb (C++ Session.h:35 Window *window)
-    let mut session = Session::new(PassthroughDecomp::new(), width, height - 1, 2000);
-    session.attach_window(&mut output as *mut okros::output_window::OutputWindow);
-
-    // History and command queue
-    let mut history = okros::history::HistorySet::new(100);
-    let mut command_queue = okros::command_queue::CommandQueue::new();
-
-    // Input line buffer (0x17 = blue background, white foreground) - C++ main.cc:73 InputLine creation
-    let mut input = okros::input_line::InputLine::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        0x17,
-        okros::history::HistoryId::MainInput,
-    );
-    input.win.parent_y = (height - 1) as isize; // Bottom row
-
-    // Status line (0x07 = black background, white foreground) - C++ main.cc:76 StatusLine creation
-    // IMPORTANT: Created last = top z-order, overlays OutputWindow at top
-    let mut status = okros::status_line::StatusLine::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        0x07,
-    );
-    status.win.parent_y = 0; // Top row
-    status.set_text("okros v0.1 - Press Alt-O for connect menu, #quit to exit");
-
-    // Simple demo loop: read stdin nonblocking, normalize keys, print them; quit on 'q'
-    unsafe {
-        let _ = fcntl(libc::STDIN_FILENO, F_SETFL, O_NONBLOCK);
+    ui.output
+        .sb
+        .set_spill(config.scrollback_spill, config.scrollback_spill_max);
+    engine.session.set_sticky_prompt(config.stickyprompt);
+    engine.session.set_diagnostics(config.diagnostics);
+    ui.history.select("", config.histsize);
+    // Completed lines are also offered to chat panes (#window)
+    engine.session.set_event_recording(true);
+    ui.bindings = config.keybindings.clone();
+    // Plays MSP sound triggers (no-op without msp_player)
+    let mut msp_player = okros::msp::MspPlayer::new(config.msp_player.clone());
+    let autosave_error = config.load_autosave().err();
+    // Polls the scripts directory once a second while autoreload is on
+    let mut script_watcher = config
+        .autoreload
+        .then(|| okros::scripts::ScriptWatcher::new(scripts_dir.clone()));
+
+    ui.status
+        .set_text("okros v0.1 - Press Alt-O for connect menu, #quit to exit");
+    if let Some(ref e) = autosave_error {
+        engine
+            .session
+            .client_msg(&format!("Autosave not loaded: {}", e), MsgLevel::Warning);
+    }
+    for e in script_errors.iter().chain(limits_error.as_ref()) {
+        engine.session.client_msg(e, MsgLevel::Error);
     }
+
     // MUD instance (contains socket + aliases/actions/macros)
     let mut mud = okros::mud::Mud::empty();
-    // Optional: try to connect if OKROS_CONNECT=hostname:PORT is set
+    mud.inherit_globals(&config.global_mud);
+    mud.compile_actions(&mut interps);
+
+    // Push the MUD's colors (own over inherited over global) to the UI
+    macro_rules! apply_theme {
+        () => {{
+            let theme = mud.theme();
+            ui.input.set_color(theme.input_color());
+            ui.status.set_color(theme.status_color());
+            ui.output.set_color(theme.output_color());
+            ui.output.set_highlight_color(theme.highlight);
+            engine.session.set_default_color(theme.output_color());
+            engine.session.set_diag_color(theme.diag_color());
+            engine.session.set_msg_colors(theme.msg_colors());
+        }};
+    }
+    apply_theme!();
+
+    // A matched trigger's commands are expanded ($var, ';') and may be
+    // engine #commands (e.g. #var hp %1); the rest go to the MUD
+    macro_rules! run_trigger_commands {
+        ($line:expr, $commands:expr) => {{
+            use okros::command_queue::EXPAND_ALL;
+            engine.session.stats.triggers_fired += 1;
+            engine
+                .bus
+                .publish(okros::event_bus::BusEvent::TriggerFired {
+                    line: $line.to_string(),
+                    commands: vec![$commands.to_string()],
+                });
+            // A chain of triggers answering their own output is cut off
+            let now = std::time::Instant::now();
+            if engine.session.trigger_guard.allow(now) {
+                engine
+                    .commands
+                    .add_with_context($commands, EXPAND_ALL, false, None, Some(&mud));
+            } else if let Some(alert) = engine.session.trigger_guard.take_alert() {
+                engine.session.client_msg(&alert, MsgLevel::Error);
+            }
+            for line in engine.commands.execute_all() {
+                match engine.command(&line) {
+                    Dispatch::NotCommand => {
+                        engine.session.trigger_guard.sent(&line, now);
+                        engine.sends.push(line)
+                    }
+                    Dispatch::Done(Err(e)) => engine.session.client_msg(&e, MsgLevel::Error),
+                    Dispatch::Done(Ok(_)) => {}
+                    Dispatch::Frontend(name, _) => ui
+                        .status
+                        .set_text(format!("#{} can't run from a trigger", name)),
+                }
+            }
+        }};
+    }
     let mut sock: Option<Socket> = None;
-    if let Ok(addr) = std::env::var("OKROS_CONNECT") {
-        if let Some((host, port_s)) = addr.split_once(':') {
-            if let Ok(port) = port_s.parse::<u16>() {
-                match resolve_hostname(host, port) {
-                    Ok(ip) => {
-                        let mut s = Socket::new().unwrap();
-                        let _ = s.connect_ipv4(ip, port);
-                        sock = Some(s);
-                        status.set_text(format!("Connecting to {}:{} -> {}...", host, port, ip));
+    // Hostname being resolved for #open / the connect menu, with the MUD's
+    // name for the status line; Escape cancels it
+    let mut lookup: Option<(String, okros::resolver::Lookup)> = None;
+    // Seconds left last shown while connecting (connecttimeout countdown)
+    let mut connect_countdown: Option<u64> = None;
+    // #close in progress: when to hang up regardless, and whether the write
+    // side is shut yet (after the logout command has gone out)
+    let mut closing: Option<(std::time::Instant, bool)> = None;
+    // The offline MUD, when picked from the connect menu: commands go to it
+    // instead of a socket and its output is fed to the Session
+    let mut world: Option<okros::offline_mud::OfflineWorld> = None;
+    let mut world_ticker: Option<u32> = None;
+    // Output that doesn't come from the socket (offline MUD, --replay)
+    macro_rules! feed_output {
+        ($data:expr) => {{
+            engine
+                .session
+                .feed_with_mud($data, Some(&mut interps), Some(&mud));
+            if let Some(prompt) = engine.session.take_prompt_update() {
+                ui.input.set_prompt(&prompt);
+            }
+            for event in engine.take_events() {
+                if let okros::session::SessionEvent::Line(text) = event {
+                    ui.pane_line(&text);
+                    for commands in mud.check_action_match(&text, &mut interps) {
+                        run_trigger_commands!(&text, &commands);
+                    }
+                }
+            }
+        }};
+    }
+    macro_rules! leave_world {
+        () => {{
+            if let Some(id) = world_ticker.take() {
+                engine.timers.cancel(id);
+            }
+            world = None;
+        }};
+    }
+    // Play the offline world in place of a connection; NPCs and combat run
+    // off an engine ticker, like #tick
+    macro_rules! enter_world {
+        ($world:expr) => {{
+            use okros::offline_mud::{WORLD_TICK, WORLD_TICK_INTERVAL};
+            sock = None;
+            lookup = None;
+            if let Some(id) = world_ticker.take() {
+                engine.timers.cancel(id);
+            }
+            let mut w = $world;
+            let intro = w.intro();
+            world = Some(w);
+            world_ticker = Some(engine.timers.add_tick(
+                WORLD_TICK_INTERVAL,
+                WORLD_TICK,
+                std::time::Instant::now(),
+            ));
+            feed_output!(intro.as_bytes());
+            ui.status
+                .set_text("Offline MUD - type 'help' for commands, 'quit' to leave");
+        }};
+    }
+    // #alias/#action/#macro... definitions: into the MUD's own lists, or while
+    // no MUD is picked (or after #open) into the global scope, which every
+    // MUD connected later inherits
+    macro_rules! define {
+        (|$scope:ident| $body:expr) => {{
+            if mud.name.is_empty() {
+                let $scope = &mut config.global_mud;
+                $body;
+                mud.inherit_globals(&config.global_mud);
+                mud.compile_actions(&mut interps);
+            } else {
+                let $scope = &mut mud;
+                $body;
+            }
+        }};
+    }
+    // Per-MUD auto-login (user/password/commands), armed when connecting from the menu
+    let mut login = okros::auto_login::AutoLogin::new(&mud);
+    // The connection came up / went away: auto-login, the event bus and the
+    // sys/connect / sys/disconnect script hooks (with the MUD's name)
+    macro_rules! connection_up {
+        ($address:expr) => {{
+            engine.session.client_msg("Connected.", MsgLevel::Info);
+            engine.connected($address);
+            login.connected();
+            let _ = interps.run_quietly("sys/connect", &mud.name, &mut String::new(), true);
+        }};
+    }
+    macro_rules! connection_down {
+        () => {{
+            engine.disconnected();
+            let _ = interps.run_quietly("sys/disconnect", &mud.name, &mut String::new(), true);
+            engine.session.net.reset();
+            engine.session.stats.connect_time = 0;
+            engine.sends.clear();
+            sock.take(); // Closes the socket
+            closing = None;
+            ui.input.set_masked(false);
+        }};
+    }
+    // Make `$mud` the current MUD (connect menu, okros <mudname>): the old
+    // connection goes (sys/disconnect runs with its scripts), then sys/switch
+    macro_rules! switch_mud {
+        ($mud:expr) => {{
+            let old_name = mud.name.clone();
+            if sock
+                .as_ref()
+                .is_some_and(|s| s.state == ConnState::Connected)
+            {
+                connection_down!();
+            }
+            let previous = mud.script_chain();
+            mud = $mud;
+            mud.inherit_globals(&config.global_mud);
+            ui.output
+                .set_scrollback_lines(mud.scrollback_lines().unwrap_or(config.scrollback));
+            ui.history
+                .select(&mud.name, mud.history_size().unwrap_or(config.histsize));
+            engine.session.set_charset(mud.charset());
+            // The MUD's `script` files replace the last one's
+            let (_, errors) = okros::scripts::switch_mud_scripts(
+                &mut interps.by_extension(),
+                &scripts_dir,
+                &config_dir,
+                &previous,
+                &mud.script_chain(),
+            );
+            for e in errors {
+                ui.output.print_line(e.as_bytes(), 0x07);
+            }
+            mud.compile_actions(&mut interps);
+            apply_theme!();
+            okros::scripts::set_session_vars(&mut interps, &mud.name, &mud.hostname, mud.port);
+            if !old_name.is_empty() && old_name != mud.name {
+                let _ = interps.run_quietly("sys/switch", &old_name, &mut String::new(), true);
+            }
+        }};
+    }
+    // After the Session showed MUD text (a read, or held-back text pumped
+    // later): echo/prompt changes, MSP, chat panes, auto-login and triggers
+    macro_rules! output_shown {
+        () => {{
+            // Server echo (telnet WILL ECHO) means a password is being asked for
+            if let Some(masked) = engine.session.take_echo_change() {
+                ui.input.set_masked(masked);
+            }
+            // Sticky prompt: the latest GA/EOR prompt replaces the input line's
+            if let Some(prompt) = engine.session.take_prompt_update() {
+                ui.input.set_prompt(&prompt);
+            }
+            for trigger in engine.session.take_msp_triggers() {
+                if let Err(e) = msp_player.play(&trigger) {
+                    engine
+                        .session
+                        .client_msg(&format!("MSP: {}", e), MsgLevel::Warning);
+                }
+            }
+            for event in engine.take_events() {
+                if let okros::session::SessionEvent::Line(text) = event {
+                    ui.pane_line(&text);
+                    for commands in mud.check_action_match(&text, &mut interps) {
+                        run_trigger_commands!(&text, &commands);
                     }
-                    Err(e) => {
-                        status.set_text(format!("OKROS_CONNECT DNS error: {}", e));
+                }
+            }
+            // NOTE: Session now writes directly to OutputWindow.sb (no manual blit needed)
+
+            // The incomplete line, for a login prompt sent without GA/EOR
+            // (cells are Latin-1 code points, one per character)
+            let current_line: String = engine
+                .session
+                .current_line()
+                .iter()
+                .map(|&b| b as char)
+                .collect();
+
+            // Auto-login: GA/EOR prompt, else the incomplete line is the prompt
+            let ga_prompt = engine.session.take_last_prompt();
+            if login.is_active() {
+                use okros::auto_login::LoginStep;
+                let prompt = ga_prompt.clone().unwrap_or_else(|| current_line.clone());
+                let to_send = match login.on_prompt(&prompt) {
+                    Some(LoginStep::Send(text)) => vec![text],
+                    Some(LoginStep::Commands(cmds)) => {
+                        use okros::command_queue::EXPAND_ALL;
+                        engine.commands.add_with_context(
+                            &cmds,
+                            EXPAND_ALL,
+                            false,
+                            None,
+                            Some(&mud),
+                        );
+                        ui.status.set_text(format!("Auto-login: {}", cmds));
+                        engine.commands.execute()
                     }
+                    None => Vec::new(),
+                };
+                for text in to_send {
+                    engine.sends.push(text);
+                }
+            }
+
+            // #action -prompt triggers: matched against GA/EOR prompts
+            if let Some(prompt) = ga_prompt {
+                for commands in mud.check_prompt_match(&prompt, &mut interps) {
+                    run_trigger_commands!(&prompt, &commands);
                 }
             }
+        }};
+    }
+    // #path learn: moves sent to the MUD are recorded until #path stop
+    let mut path_recording: Option<okros::path::PathRecorder> = None;
+
+    // Started by #restart: pick up the previous process's connection and output
+    if args.len() > 2 && args[1] == okros::restart::RESTORE_FLAG {
+        match args[2].parse().map_err(|_| io::Error::other("bad fd")) {
+            Ok(fd) => match okros::restart::restore(fd, args.get(3).and_then(|p| p.parse().ok())) {
+                Ok((state, fds)) => {
+                    ui.output.restore_history(&state.scrollback);
+                    mud.name = state.mud_name;
+                    mud.hostname = state.hostname;
+                    mud.port = state.port;
+                    okros::scripts::set_session_vars(
+                        &mut interps,
+                        &mud.name,
+                        &mud.hostname,
+                        mud.port,
+                    );
+                    engine.session.stats.connect_time = state.connect_time;
+                    engine.session.stats.bytes_read = state.bytes_read;
+                    engine.session.stats.bytes_written = state.bytes_written;
+                    if let Some(&fd) = fds.first() {
+                        sock = Some(Socket::from_connected_fd(fd));
+                        engine.session.state = okros::session::SessionState::Connected;
+                    }
+                    engine.session.client_msg(
+                        &format!(
+                            "Restarted okros v{} - connection preserved",
+                            env!("CARGO_PKG_VERSION")
+                        ),
+                        MsgLevel::Info,
+                    );
+                }
+                Err(e) => engine
+                    .session
+                    .client_msg(&format!("Restore failed: {}", e), MsgLevel::Error),
+            },
+            Err(e) => engine
+                .session
+                .client_msg(&format!("Restore failed: {}", e), MsgLevel::Error),
         }
     }
 
-    let mut dec = KeyDecoder::new();
+    // okros <mudname> | <host> <port> connects at startup; so does
+    // OKROS_CONNECT=host:port (or [v6]:port for IPv6 literals)
+    let address = match target {
+        Some(okros::config::StartupTarget::Address(host, port)) => Some((host, port)),
+        _ => std::env::var("OKROS_CONNECT").ok().and_then(|addr| {
+            okros::socket::split_host_port(&addr).map(|(host, port)| (host.to_string(), port))
+        }),
+    };
+    if let Some(selected) = startup_mud {
+        switch_mud!(selected);
+        lookup = Some((
+            mud.name.clone(),
+            okros::resolver::Lookup::start(&mud.hostname, mud.port),
+        ));
+        login = okros::auto_login::AutoLogin::new(&mud);
+        ui.status
+            .set_text(format!("Resolving {}... (Escape cancels)", mud.hostname));
+    } else if let Some((host, port)) = address {
+        okros::scripts::set_session_vars(&mut interps, &mud.name, &host, port);
+        lookup = Some((String::new(), okros::resolver::Lookup::start(&host, port)));
+        ui.status
+            .set_text(format!("Resolving {}... (Escape cancels)", host));
+    } else if let Some(w) = start_world.take() {
+        enter_world!(w);
+    }
+
     let mut buf = [0u8; 1024];
     let mut quit = false;
     let mut last_callout_time = current_time;
 
+    // Everything bound for the MUD goes through the engine's send queue
+    engine.sends.set_limits(config.sendrate, config.sendburst);
+    // A flooding MUD: text shown per read, #more paging
+    engine.session.set_output_budget(config.output_budget);
+    engine.session.set_more(config.more);
+    ui.status.set_message_time(config.status_time);
+    engine
+        .session
+        .trigger_guard
+        .set_max_depth(config.trigger_depth);
+    engine
+        .session
+        .trigger_guard
+        .set_gag_echoes(config.trigger_echo_gag);
+
+    // Poll registry (C++ Selectable.cc) - stdin is permanent, the socket comes
+    // and goes; fds watched by scripts (okros.watch) have callbacks that
+    // queue their hook for the loop to run
+    let mut selector: Selector<Vec<HookCall>> = Selector::new();
+    selector.register(libc::STDIN_FILENO, Interest::READ);
+    let mut sock_reg: Option<(std::os::fd::RawFd, okros::select::Token)> = None;
+    let mut script_watches: std::collections::HashMap<std::os::fd::RawFd, okros::select::Token> =
+        Default::default();
+
     // Modal state for connect menu and search
     enum ModalState {
         Normal,
-        ConnectMenu(okros::mud_selection::MudSelection),
+        ConnectMenu(Box<okros::mud_selection::MudSelection>),
         SearchDialog(okros::input_box::InputBox),
+        CopyMode(okros::copy_mode::CopyMode),
+        ConfirmQuit(okros::message_box::MessageBox),
+        Info(okros::message_box::MessageBox), // Closed by any answer (#stats)
     }
     let mut modal = ModalState::Normal;
+    // #quit / the quit key: ask first while connected
+    let connected =
+        |s: &Option<Socket>| s.as_ref().is_some_and(|s| s.state == ConnState::Connected);
+    let confirm_quit = |parent: *mut okros::window::Window| {
+        use okros::message_box::{Buttons, MessageBox};
+        let question = "Really quit with an open connection?";
+        ModalState::ConfirmQuit(MessageBox::new(parent, "Quit", question, Buttons::OkCancel))
+    };
 
     // Main event loop (matching main.cc:141-170)
     while !quit {
@@ -255,317 +886,1310 @@ fn main() {
                 dialog.redraw();
                 dialog.window().dirty = true;
             }
-            ModalState::Normal => {}
-        }
-
-        // Composition workaround: manually call redraw before tree refresh
-        // C++ uses inheritance (IS-A Window), Rust uses composition (HAS-A Window)
-        // So win is in tree, but redraw() must be called manually (virtual dispatch equivalent)
-        if output.win.dirty {
-            output.redraw();
-            output.win.dirty = true; // Keep dirty for tree refresh
-        }
-
-        if input.win.dirty {
-            input.redraw();
-            input.win.dirty = true; // Keep dirty for tree refresh
+            ModalState::CopyMode(_)
+            | ModalState::ConfirmQuit(_)
+            | ModalState::Info(_)
+            | ModalState::Normal => {}
         }
 
-        // Refresh Screen (calls Window::refresh() to composite tree, then refreshTTY) - C++ main.cc:142
-        // Window::refresh() automatically composites all windows including MudSelection via tree walk
-        screen.refresh(&caps);
+        // Redraw widgets and refresh the Screen - C++ main.cc:142
+        // Window::refresh() composites all windows including MudSelection via tree walk
+        ui.render();
 
         // 2. Poll file descriptors (main.cc:147) - stdin + socket with 250ms timeout
-        let mut fds = vec![(libc::STDIN_FILENO, READ)];
-        if let Some(s) = &sock {
-            let mut ev = READ;
+        // Keep the socket registration in step with `sock`, which #open, the
+        // connect menu and connection loss all replace directly
+        let sock_fd = sock.as_ref().map(|s| s.as_raw_fd());
+        if sock_reg.map(|(fd, _)| fd) != sock_fd {
+            if let Some((_, token)) = sock_reg.take() {
+                selector.deregister(token);
+            }
+            sock_reg = sock_fd.map(|fd| (fd, selector.register(fd, Interest::READ)));
+        }
+        if let (Some(s), Some((_, token))) = (&sock, sock_reg) {
+            let mut interest = Interest::READ;
             if s.state == ConnState::Connecting {
-                ev |= WRITE;
+                interest |= Interest::WRITE;
+            }
+            selector.set_interest(token, interest);
+        }
+        let now = std::time::Instant::now();
+        ui.poll_panes();
+        let mut timeout = ui.poll_timeout(engine.poll_timeout(now, 250));
+        if let Some(r) = &replay {
+            timeout = r.poll_timeout(now, timeout);
+        }
+        if lookup.is_some() {
+            timeout = timeout.min(okros::resolver::POLL_INTERVAL.as_millis() as i32);
+        }
+        // Held-back output goes on without waiting for the next read
+        if engine.session.pending_output() {
+            timeout = 0;
+        }
+        let mut hook_calls = Vec::new();
+        let ready = selector
+            .dispatch(&mut hook_calls, timeout)
+            .unwrap_or_default();
+        for (function, arg) in hook_calls {
+            let _ = interps.run(&function, &arg, &mut String::new());
+        }
+        // --replay: recorded chunks go through the Session as they come due
+        if let Some(r) = replay.as_mut() {
+            for chunk in r.take_due(std::time::Instant::now()) {
+                feed_output!(&chunk);
+            }
+            if r.is_done() {
+                replay = None;
+                ui.status.set_text("Replay finished");
+            }
+        }
+        // A hostname lookup finished: connect to what it found
+        if let Some((name, l)) = lookup.as_mut() {
+            match l.poll() {
+                Some(Ok(addrs)) => {
+                    let ip = addrs[0].ip();
+                    let mut s = Socket::new().unwrap();
+                    let _ = s.connect_any(addrs);
+                    sock = Some(s);
+                    ui.status.set_text(if name.is_empty() {
+                        format!("Connecting to {}:{} -> {}...", l.host(), l.port(), ip)
+                    } else {
+                        format!(
+                            "Connecting to {} ({}:{} -> {})...",
+                            name,
+                            l.host(),
+                            l.port(),
+                            ip
+                        )
+                    });
+                    lookup = None;
+                }
+                Some(Err(e)) => {
+                    engine
+                        .session
+                        .client_msg(&format!("DNS error: {}", e), MsgLevel::Error);
+                    lookup = None;
+                }
+                None => {
+                    if let Some(progress) = l.progress() {
+                        ui.status.set_text(format!("{} (Escape cancels)", progress));
+                    }
+                }
+            }
+        }
+        // A slow connect gets the next resolved address raced against it
+        if let Some(s) = sock.as_mut() {
+            if s.tick() {
+                connection_up!(&s.remote.map(|a| a.to_string()).unwrap_or_default());
+            }
+        }
+        // Still connecting: count down in the status line, give up at connecttimeout
+        if let (Some(s), Some(limit)) = (sock.as_mut(), config.connect_timeout) {
+            if s.check_timeout(limit) {
+                engine.session.client_msg(
+                    &format!("Connection timed out after {}s", limit.as_secs()),
+                    MsgLevel::Error,
+                );
+                sock = None;
+            } else if let Some(left) = s.connect_time_left(limit) {
+                let secs = left.as_secs() + 1;
+                if secs < limit.as_secs() && connect_countdown != Some(secs) {
+                    ui.status.set_text(format!(
+                        "Still connecting... {}s left (Escape cancels)",
+                        secs
+                    ));
+                }
+                connect_countdown = Some(secs);
             }
-            fds.push((s.as_raw_fd(), ev));
         }
-        let ready = poll_fds(&fds, 250).unwrap_or_default();
 
         // 3. Process I/O events
-        for (fd, r) in ready {
-            if fd == libc::STDIN_FILENO && (r.revents & READ) != 0 {
+        for Event { fd, ready: r, .. } in ready {
+            if fd == libc::STDIN_FILENO && r.readable() {
                 // TTY input (keyboard)
-                if let Ok(n) = io::stdin().read(&mut buf) {
-                    if n > 0 {
-                        for ev in dec.feed(&buf[..n]) {
-                            // Handle modal dialogs first
-                            match &mut modal {
-                                ModalState::ConnectMenu(ref mut menu) => {
-                                    if menu.keypress(ev) {
-                                        // Keypress handled - dirty flag set, Window::refresh() will call redraw()
-
-                                        // Enter pressed - connect to selected MUD
-                                        if matches!(ev, KeyEvent::Byte(b'\n')) {
-                                            let idx = menu.get_selection();
-                                            if let Some((name, hostname, port)) =
-                                                menu.get_mud_at(idx as usize)
-                                            {
-                                                // Check if this is the Offline MUD (no hostname)
-                                                if hostname.is_empty() {
-                                                    status.set_text(
-                                                        "Offline MUD - use cargo run --offline instead",
-                                                    );
-                                                    modal = ModalState::Normal;
-                                                } else {
-                                                    // Resolve hostname and connect to network MUD
-                                                    match resolve_hostname(hostname, port) {
-                                                        Ok(ip) => {
-                                                            let mut s = Socket::new().unwrap();
-                                                            let _ = s.connect_ipv4(ip, port);
-                                                            sock = Some(s);
-                                                            status.set_text(format!(
-                                                                "Connecting to {} ({}:{} -> {})...",
-                                                                name, hostname, port, ip
-                                                            ));
-                                                            modal = ModalState::Normal;
-                                                        }
-                                                        Err(e) => {
-                                                            status.set_text(format!(
-                                                                "DNS error: {}",
-                                                                e
-                                                            ));
-                                                        }
-                                                    }
-                                                }
+                if let Some(events) = ui.read_keys() {
+                    // Any key lets #more show the next page
+                    engine.session.resume();
+                    for ev in events {
+                        // Handle modal dialogs first
+                        match &mut modal {
+                            ModalState::ConnectMenu(ref mut menu) => {
+                                // a/e/d: add/edit/delete form or confirmation
+                                let busy = menu.is_busy();
+                                let handled = menu.keypress(ev);
+                                if busy || menu.is_busy() {
+                                    config.mud_list = menu.config().mud_list.clone();
+                                    if let Some(prompt) = menu.prompt() {
+                                        ui.status.set_text(prompt);
+                                    } else {
+                                        ui.status.set_text(menu.take_message().unwrap_or_default());
+                                    }
+                                    continue;
+                                }
+                                if let Some(msg) = menu.take_message() {
+                                    ui.status.set_text(msg);
+                                }
+                                if handled {
+                                    // Keypress handled - dirty flag set, Window::refresh() will call redraw()
+
+                                    // Enter pressed - connect to selected MUD
+                                    if matches!(ev, KeyEvent::Byte(b'\n')) {
+                                        let idx = menu.get_selection();
+                                        if let Some(selected) = menu.get_mud(idx as usize) {
+                                            switch_mud!(selected.clone());
+                                        }
+                                        if let Some((name, hostname, port)) =
+                                            menu.get_mud_at(idx as usize)
+                                        {
+                                            // The Offline MUD (no hostname) runs in-process
+                                            if hostname.is_empty() {
+                                                enter_world!(
+                                                    okros::offline_mud::OfflineWorld::new()
+                                                );
+                                                modal = ModalState::Normal;
+                                            } else {
+                                                leave_world!();
+                                                // Resolve the hostname in the background;
+                                                // the main loop connects when it's done
+                                                lookup = Some((
+                                                    name.to_string(),
+                                                    okros::resolver::Lookup::start(hostname, port),
+                                                ));
+                                                login = okros::auto_login::AutoLogin::new(&mud);
+                                                ui.status.set_text(format!(
+                                                    "Resolving {}... (Escape cancels)",
+                                                    hostname
+                                                ));
+                                                modal = ModalState::Normal;
                                             }
                                         }
-                                    } else if matches!(ev, KeyEvent::Key(KeyCode::Escape)) {
-                                        // Escape pressed - exit connect menu
+                                    }
+                                } else if matches!(ev, KeyEvent::Key(KeyCode::Escape)) {
+                                    // Escape pressed - exit connect menu
+                                    modal = ModalState::Normal;
+                                    ui.status.set_text("Connect menu closed.");
+                                }
+                                continue; // Skip normal processing while in modal
+                            }
+                            ModalState::SearchDialog(ref mut dialog) => {
+                                if dialog.keypress(ev) {
+                                    // Keypress handled (Escape or Enter)
+                                    modal = ModalState::Normal;
+                                    ui.status.set_text("");
+                                }
+                                continue; // Skip normal processing while in modal
+                            }
+                            ModalState::CopyMode(ref mut mode) => {
+                                use okros::copy_mode::CopyStep;
+                                match mode.keypress(ev, &mut ui.output) {
+                                    CopyStep::Continue => {}
+                                    CopyStep::Cancel => {
+                                        modal = ModalState::Normal;
+                                        ui.status.set_text("");
+                                    }
+                                    CopyStep::Copy(text) => {
+                                        ui.status.set_text(format!(
+                                            "Copied {} characters (Alt-V pastes)",
+                                            text.chars().count()
+                                        ));
+                                        ui.copy(text);
                                         modal = ModalState::Normal;
-                                        status.set_text("Connect menu closed.");
                                     }
-                                    continue; // Skip normal processing while in modal
                                 }
-                                ModalState::SearchDialog(ref mut dialog) => {
-                                    if dialog.keypress(ev) {
-                                        // Keypress handled (Escape or Enter)
+                                continue; // Skip normal processing while in modal
+                            }
+                            ModalState::ConfirmQuit(ref mut dialog) => {
+                                use okros::message_box::BoxAnswer;
+                                match dialog.keypress(ev) {
+                                    BoxAnswer::Pending => {}
+                                    BoxAnswer::Ok(_) => {
+                                        quit = true;
+                                        ui.status.set_text("Quit.");
                                         modal = ModalState::Normal;
-                                        status.set_text("");
                                     }
-                                    continue; // Skip normal processing while in modal
+                                    BoxAnswer::Cancel => {
+                                        modal = ModalState::Normal;
+                                        ui.status.set_text("");
+                                        ui.redraw_all();
+                                    }
                                 }
-                                ModalState::Normal => {
-                                    // Normal processing below
+                                continue; // Skip normal processing while in modal
+                            }
+                            ModalState::Info(ref mut dialog) => {
+                                use okros::message_box::BoxAnswer;
+                                if dialog.keypress(ev) != BoxAnswer::Pending {
+                                    modal = ModalState::Normal;
+                                    ui.redraw_all();
                                 }
+                                continue; // Skip normal processing while in modal
                             }
+                            ModalState::Normal => {
+                                // Normal processing below
+                            }
+                        }
+
+                        // Escape gives up on a hostname lookup or a connect still running
+                        if matches!(ev, KeyEvent::Key(KeyCode::Escape)) {
+                            if let Some((_, l)) = lookup.take() {
+                                ui.status
+                                    .set_text(format!("Lookup of {} cancelled", l.host()));
+                                continue;
+                            }
+                            if sock.as_mut().is_some_and(|s| s.cancel()) {
+                                sock = None;
+                                ui.status.set_text("Connect cancelled");
+                                continue;
+                            }
+                        }
+
+                        // Bracketed paste (and its "send N lines?" answer)
+                        if ui.paste_key(ev, &mut engine.commands) {
+                            continue;
+                        }
 
-                            // Alt-O: Open connect menu
-                            if matches!(ev, KeyEvent::Key(KeyCode::Alt(b'o'))) {
-                                // Load config file
-                                let config_path = std::env::var("HOME")
-                                    .map(|h| std::path::PathBuf::from(h).join(".okros/config"))
-                                    .unwrap_or_else(|_| std::path::PathBuf::from(".okros/config"));
-
-                                let mut config = okros::config::Config::new();
-                                if config.load_file(&config_path).is_ok() {
-                                    // Create MUD selection window as child of Screen
-                                    let menu = okros::mud_selection::MudSelection::new(
-                                        screen.window_mut() as *mut okros::window::Window,
-                                        config,
+                        // Bound keys (#bind; defaults are the C++ hotkeys) before
+                        // macros and line editing
+                        if let Some(action) = ui.binding(ev, &mud) {
+                            use okros::keybind::KeyAction;
+                            match action {
+                                KeyAction::ConnectMenu => {
+                                    // Load config file
+                                    let config_path = okros::config::Config::default_path();
+
+                                    let mut loaded = okros::config::Config::new();
+                                    // No config yet: start from the Offline entry so
+                                    // MUDs can be added from the menu
+                                    let result = if config_path.exists() {
+                                        loaded.load_file(&config_path)
+                                    } else {
+                                        loaded = okros::config::Config::with_offline_mud();
+                                        Ok(())
+                                    };
+                                    if let Err(e) = result {
+                                        engine.session.client_msg(
+                                            &format!("Config not loaded: {}", e),
+                                            MsgLevel::Error,
+                                        );
+                                    } else {
+                                        if let Err(e) = loaded.load_autosave() {
+                                            ui.status
+                                                .set_text(format!("Autosave not loaded: {}", e));
+                                        }
+                                        engine.session.set_timestamps(loaded.timestamps);
+                                        engine.session.set_word_wrap(loaded.wordwrap);
+                                        echo_input = loaded.echoinput;
+                                        engine.session.set_msp(loaded.msp);
+                                        engine.session.set_atcp(loaded.atcp);
+                                        engine.session.set_mxp(loaded.mxp);
+                                        ui.screen.hyperlinks = loaded.hyperlinks;
+                                        ui.output.set_scrollback_lines(
+                                            mud.scrollback_lines().unwrap_or(loaded.scrollback),
+                                        );
+                                        ui.output.sb.set_spill(
+                                            loaded.scrollback_spill,
+                                            loaded.scrollback_spill_max,
+                                        );
+                                        let namespace = ui.history.namespace().to_string();
+                                        ui.history.select(
+                                            &namespace,
+                                            mud.history_size().unwrap_or(loaded.histsize),
+                                        );
+                                        engine.session.set_sticky_prompt(loaded.stickyprompt);
+                                        engine.session.set_diagnostics(loaded.diagnostics);
+                                        msp_player.set_command(loaded.msp_player.clone());
+                                        ui.bindings = loaded.keybindings.clone();
+                                        config = loaded.clone();
+                                        // Create MUD selection window as child of Screen
+                                        let menu = okros::mud_selection::MudSelection::new(
+                                            ui.screen.window_mut() as *mut okros::window::Window,
+                                            loaded,
+                                        );
+                                        modal = ModalState::ConnectMenu(Box::new(menu));
+                                        ui.status.set_text("Select MUD (arrows, Enter connects, a/e/d add/edit/delete, Esc closes)");
+                                    }
+                                }
+                                KeyAction::Search => {
+                                    // Search scrollback (C++ Hotkey.cc:77-78)
+                                    use okros::scrollback_search::create_scrollback_search;
+                                    // Create search dialog (searches backwards by default)
+                                    let search_dialog = create_scrollback_search(
+                                        ui.screen.window_mut() as *mut okros::window::Window,
+                                        ui.output_ptr(),
+                                        false, // forward = false (search backwards)
                                     );
-                                    if menu.count() > 0 {
-                                        modal = ModalState::ConnectMenu(menu);
-                                        status.set_text("Select MUD (arrows to navigate, Enter to connect, Esc to cancel)");
+                                    modal = ModalState::SearchDialog(search_dialog);
+                                    ui.status.set_text("Enter search text (Esc to cancel)");
+                                }
+                                // Page through scrollback (C++ ScrollbackController)
+                                KeyAction::PageUp if ui.page_focused(true) => {}
+                                KeyAction::PageDown if ui.page_focused(false) => {}
+                                KeyAction::PageUp => {
+                                    ui.output.freeze();
+                                    ui.output.page_up();
+                                    ui.status
+                                        .set_text("Scrollback - PgDn past the end to return");
+                                }
+                                KeyAction::PageDown => {
+                                    if ui.output.page_down() {
+                                        // Already at the bottom: leave scrollback mode
+                                        ui.output.unfreeze();
+                                        ui.output.redraw();
+                                        ui.status.set_text("");
+                                    }
+                                }
+                                KeyAction::Redraw => ui.redraw_all(),
+                                KeyAction::CopyMode => {
+                                    let mode = okros::copy_mode::CopyMode::new(&mut ui.output);
+                                    modal = ModalState::CopyMode(mode);
+                                    ui.status.set_text(
+                                        "Copy mode: arrows move, v selects, y copies, Esc leaves",
+                                    );
+                                }
+                                KeyAction::Paste => ui.input.insert(&ui.paste),
+                                KeyAction::Quit if connected(&sock) => {
+                                    let parent = ui.screen.window_mut() as *mut _;
+                                    modal = confirm_quit(parent);
+                                    ui.status.set_text("Quit? (y/n)");
+                                }
+                                KeyAction::Quit => {
+                                    quit = true;
+                                    ui.status.set_text("Quit.");
+                                }
+                                KeyAction::Command(text) => {
+                                    use okros::command_queue::EXPAND_ALL;
+                                    engine.commands.add_with_context(
+                                        &text,
+                                        EXPAND_ALL,
+                                        false,
+                                        None,
+                                        Some(&mud),
+                                    );
+                                }
+                            }
+                            continue;
+                        }
+
+                        // Keyboard macros take precedence over line editing (C++ Hotkey.cc)
+                        if let Some(m) =
+                            okros::macro_def::event_key(ev).and_then(|key| mud.find_macro(key))
+                        {
+                            use okros::command_queue::EXPAND_ALL;
+                            engine.commands.add_with_context(
+                                &m.text,
+                                EXPAND_ALL,
+                                false,
+                                None,
+                                Some(&mud),
+                            );
+                            continue;
+                        }
+
+                        // Line editing; Enter queues the line
+                        ui.edit_key(ev, &mut engine.commands);
+                        // Password typed while the server echoes: sent untouched,
+                        // after any commands still waiting in the send queue
+                        if let Some(secret) = ui.input.take_secret() {
+                            if sock.is_some() {
+                                engine.sends.push_secret(secret);
+                            }
+                        }
+                    }
+
+                    // Process any queued commands
+                    let commands = engine.commands.execute_all();
+                    if !commands.is_empty() {
+                        // Typing ends a trigger chain (#loopguard)
+                        engine.session.trigger_guard.user_input();
+                    }
+                    for line in commands {
+                        // #commands go through the registry: #help and the engine's
+                        // own run there, the rest come back here by name
+                        match engine.command(&line) {
+                            Dispatch::Frontend("quit", _) if connected(&sock) => {
+                                let parent = ui.screen.window_mut() as *mut _;
+                                modal = confirm_quit(parent);
+                                ui.status.set_text("Quit? (y/n)");
+                            }
+                            Dispatch::Frontend("quit", _) => {
+                                quit = true;
+                                ui.status.set_text("Quit.");
+                            }
+                            Dispatch::Frontend("stats", _) => {
+                                use okros::message_box::{Buttons, MessageBox};
+                                let report = engine.session.stats_report(Some(&ui.output.sb));
+                                let parent = ui.screen.window_mut() as *mut _;
+                                modal = ModalState::Info(MessageBox::new(
+                                    parent,
+                                    "Session statistics",
+                                    &report.text(),
+                                    Buttons::Ok,
+                                ));
+                            }
+                            Dispatch::Frontend("restart", args) => {
+                                // #restart [binary] - exec a new okros, keeping the connection
+                                let binary = match args {
+                                    "" => okros::restart::current_binary(),
+                                    path => Ok(okros::config::expand_path(path)),
+                                };
+                                let mut state = okros::restart::RestartState::new();
+                                state.mud_name = mud.name.clone();
+                                state.hostname = mud.hostname.clone();
+                                state.port = mud.port;
+                                if let Some(remote) = sock.as_ref().and_then(|s| s.remote) {
+                                    state.hostname = remote.ip().to_string();
+                                    state.port = remote.port();
+                                }
+                                state.connect_time = engine.session.stats.connect_time;
+                                state.bytes_read = engine.session.stats.bytes_read;
+                                state.bytes_written = engine.session.stats.bytes_written;
+                                state.scrollback = ui.output.history_rows();
+                                let fds: Vec<_> = sock.iter().map(|s| s.as_raw_fd()).collect();
+
+                                // The new process re-enters raw mode from a cooked terminal
+                                ui.screen.flush_output();
+                                let _ = ui.tty.disable_raw();
+                                let err = match binary {
+                                    Ok(binary) => {
+                                        okros::restart::exec_restart(&binary, &state, &fds)
+                                    }
+                                    Err(e) => e,
+                                };
+                                let _ = ui.tty.enable_raw();
+                                engine.session.client_msg(
+                                    &format!("Restart failed: {}", err),
+                                    MsgLevel::Error,
+                                );
+                            }
+                            Dispatch::Done(result) => {
+                                // #help, #wait/#tick/#untick, #sendrate/#flush, unknown commands
+                                match result {
+                                    Ok(listing) if listing.contains('\n') => {
+                                        for entry in listing.lines() {
+                                            ui.output.print_line(entry.as_bytes(), 0x07);
+                                        }
+                                    }
+                                    Ok(msg) => ui.status.set_text(msg),
+                                    Err(msg) => engine.session.client_msg(&msg, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend("timestamps", arg) => {
+                                // #timestamps on|off (no argument: show current setting)
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Timestamps are {}",
+                                        if engine.session.timestamps() {
+                                            "on"
+                                        } else {
+                                            "off"
+                                        }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    engine.session.set_timestamps(on);
+                                    ui.status.set_text(format!(
+                                        "Timestamps {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status
+                                        .post("Usage: #timestamps on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("reload", arg) => {
+                                // #reload [file]: re-run script files (all of
+                                // ~/.okros/scripts by default), then recompile actions
+                                let result = if arg.is_empty() {
+                                    let (n, errors) = okros::scripts::load_all(
+                                        &mut interps.by_extension(),
+                                        &scripts_dir,
+                                    );
+                                    match errors.into_iter().next() {
+                                        Some(e) => Err(e),
+                                        None => Ok(format!("Reloaded {} script(s)", n)),
+                                    }
+                                } else {
+                                    let path = okros::scripts::resolve_script(&scripts_dir, arg);
+                                    okros::scripts::load_script(&mut interps.by_extension(), &path)
+                                        .map(|()| format!("Reloaded {}", path.display()))
+                                };
+                                mud.compile_actions(&mut interps);
+                                match result {
+                                    Ok(msg) => ui.status.set_text(msg),
+                                    Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend("buffer", arg) => {
+                                // #buffer [<rows> | spill on|off]: resize the scrollback,
+                                // keeping its lines, or switch spilling to disk
+                                let spill = arg
+                                    .strip_prefix("spill")
+                                    .map(|on| okros::config::parse_bool(on.trim()));
+                                match arg.parse::<usize>() {
+                                    _ if arg.is_empty() => {
+                                        ui.status.set_text(ui.output.sb.usage_text())
+                                    }
+                                    _ if spill.is_some_and(|on| on.is_some()) => {
+                                        config.scrollback_spill = spill.flatten().unwrap_or(false);
+                                        ui.output.sb.set_spill(
+                                            config.scrollback_spill,
+                                            config.scrollback_spill_max,
+                                        );
+                                        ui.status.set_text(ui.output.sb.usage_text());
+                                    }
+                                    Ok(rows) if rows > okros::scrollback::MAX_LINES => {
+                                        ui.status.post(
+                                            format!(
+                                                "At most {} rows of scrollback",
+                                                okros::scrollback::MAX_LINES
+                                            ),
+                                            MsgLevel::Error,
+                                        );
+                                    }
+                                    Ok(rows) => {
+                                        ui.output.set_scrollback_lines(rows);
+                                        ui.status.set_text(ui.output.sb.usage_text());
+                                    }
+                                    Err(_) => ui.status.post(
+                                        "Usage: #buffer [<rows> | spill on|off]",
+                                        MsgLevel::Warning,
+                                    ),
+                                }
+                            }
+                            Dispatch::Frontend("record", arg) => {
+                                // #record [<file>|off]: save frames as ttyrec/asciicast
+                                let msg = match arg {
+                                    "" => match &ui.screen.recording {
+                                        Some(r) => format!("Recording to {}", r.path().display()),
+                                        None => "Not recording".to_string(),
+                                    },
+                                    "off" => match ui.screen.recording.take() {
+                                        Some(r) => format!("Saved {}", r.path().display()),
+                                        None => "Not recording".to_string(),
+                                    },
+                                    file => {
+                                        let path = okros::config::expand_path(file);
+                                        let (w, h) = (ui.width(), ui.height());
+                                        match okros::ttyrec::TtyRecorder::create(&path, w, h) {
+                                            Ok(r) => {
+                                                ui.screen.recording = Some(r);
+                                                // Start from a complete picture
+                                                ui.screen.invalidate();
+                                                format!("Recording to {}", path.display())
+                                            }
+                                            Err(e) => e,
+                                        }
+                                    }
+                                };
+                                ui.status.set_text(msg);
+                            }
+                            Dispatch::Frontend("url", arg) => {
+                                // #url [<n>]: list recent URLs or open one
+                                let urls = &engine.session.urls;
+                                if arg.is_empty() {
+                                    if urls.is_empty() {
+                                        ui.status.set_text("No URLs seen yet");
+                                    }
+                                    for (n, url) in urls.iter().take(10) {
+                                        let entry = format!("{:>3}  {}", n, url);
+                                        ui.output.print_line(entry.as_bytes(), 0x07);
+                                    }
+                                } else {
+                                    let result = match arg.parse::<usize>() {
+                                        Ok(n) => urls
+                                            .get(n)
+                                            .ok_or_else(|| format!("No URL {}", n))
+                                            .and_then(|url| {
+                                                okros::url::open_url(url)
+                                                    .map(|()| format!("Opening {}", url))
+                                            }),
+                                        Err(_) => Err("Usage: #url [<n>]".to_string()),
+                                    };
+                                    match result {
+                                        Ok(msg) => ui.status.set_text(msg),
+                                        Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("interpreter", args) => {
+                                // Backends or functions switched: actions move
+                                // to whichever backend now comes first
+                                let result = interps.command(args);
+                                mud.compile_actions(&mut interps);
+                                match result {
+                                    Ok(msg) => ui.status.set_text(msg),
+                                    Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend("color", args) => {
+                                // #color <item> <spec|default>: input, status, output, highlight
+                                match args.split_once(' ') {
+                                    Some((item, spec)) => match mud.colors.set(item, spec) {
+                                        Ok(()) => {
+                                            apply_theme!();
+                                            ui.status.set_text(format!("Color {} set", item));
+                                        }
+                                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                                    },
+                                    None => {
+                                        let theme = mud.theme();
+                                        ui.status.set_text(format!(
+                                            "Colors: input 0x{:02x} status 0x{:02x} output 0x{:02x} highlight {} echo 0x{:02x} diagnostics 0x{:02x} info 0x{:02x} warning 0x{:02x} error 0x{:02x}",
+                                            theme.input_color(),
+                                            theme.status_color(),
+                                            theme.output_color(),
+                                            theme.highlight.map_or("inverse".to_string(), |c| format!("0x{:02x}", c)),
+                                            theme.echo_color(),
+                                            theme.diag_color(),
+                                            theme.msg_colors()[0],
+                                            theme.msg_colors()[1],
+                                            theme.msg_colors()[2]
+                                        ));
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("stickyprompt", arg) => {
+                                // #stickyprompt on|off: GA/EOR prompts on the input line, not in the output
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Sticky prompt is {}",
+                                        if engine.session.sticky_prompt() {
+                                            "on"
+                                        } else {
+                                            "off"
+                                        }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    engine.session.set_sticky_prompt(on);
+                                    ui.status.set_text(format!(
+                                        "Sticky prompt {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status
+                                        .post("Usage: #stickyprompt on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("diagnostics", arg) => {
+                                // #diagnostics on|off: refused telnet options and MCCP
+                                // errors in the output (always logged)
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Diagnostics are {}",
+                                        if engine.session.diagnostics() {
+                                            "on"
+                                        } else {
+                                            "off"
+                                        }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    engine.session.set_diagnostics(on);
+                                    ui.status.set_text(format!(
+                                        "Diagnostics {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status
+                                        .post("Usage: #diagnostics on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("wordwrap", arg) => {
+                                // #wordwrap on|off: wrap long lines at spaces, not mid-word
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Word wrap is {}",
+                                        if ui.output.sb.word_wrap() {
+                                            "on"
+                                        } else {
+                                            "off"
+                                        }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    engine.session.set_word_wrap(on);
+                                    ui.status.set_text(format!(
+                                        "Word wrap {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status.post("Usage: #wordwrap on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("more", arg) => {
+                                // #more [<lines>|off]: page the output
+                                let lines = match okros::config::parse_bool(arg) {
+                                    Some(false) => Some(0),
+                                    _ => arg.parse::<usize>().ok(),
+                                };
+                                if arg.is_empty() {
+                                    ui.status.set_text(match engine.session.more() {
+                                        Some(n) => format!("Output pauses every {} lines", n),
+                                        None => "Output paging is off".to_string(),
+                                    });
+                                } else if let Some(n) = lines {
+                                    engine.session.set_more((n > 0).then_some(n));
+                                    ui.status.set_text(if n > 0 {
+                                        format!("Output pauses every {} lines", n)
                                     } else {
-                                        status.set_text("No MUDs found in config");
+                                        "Output paging is off".to_string()
+                                    });
+                                } else {
+                                    ui.status
+                                        .post("Usage: #more [<lines>|off]", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("echoinput", arg) => {
+                                // #echoinput on|off: show what was sent, aliases expanded
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Echo input is {}",
+                                        if echo_input { "on" } else { "off" }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    echo_input = on;
+                                    ui.status.set_text(format!(
+                                        "Echo input {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status
+                                        .post("Usage: #echoinput on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("msp", arg) => {
+                                // #msp on|off: accept MSP and play sound triggers
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "MSP is {}",
+                                        if engine.session.msp() { "on" } else { "off" }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    engine.session.set_msp(on);
+                                    if !on {
+                                        msp_player.stop();
                                     }
+                                    ui.status
+                                        .set_text(format!("MSP {}", if on { "on" } else { "off" }));
+                                } else {
+                                    ui.status.post("Usage: #msp on|off", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend("ping", arg) => {
+                                // #ping [command]: time the server's answer to IAC AYT,
+                                // or to a command (default: the ping_command option)
+                                let command = if arg.is_empty() {
+                                    config.ping_command.as_str()
                                 } else {
-                                    status.set_text("Config file not found");
+                                    arg
+                                };
+                                match sock {
+                                    Some(ref s) if s.state == ConnState::Connected => {
+                                        let data = if command.is_empty() {
+                                            okros::netstats::PING_AYT.to_vec()
+                                        } else {
+                                            format!("{}\n", command).into_bytes()
+                                        };
+                                        engine.session.net.start_ping(std::time::Instant::now());
+                                        write_mud(s, &mut engine.session, &data);
+                                        ui.status.set_text("Ping...");
+                                    }
+                                    _ => ui.status.post("Not connected.", MsgLevel::Warning),
                                 }
-                                continue;
                             }
-
-                            // Alt-/: Search scrollback (C++ Hotkey.cc:77-78)
-                            if matches!(ev, KeyEvent::Key(KeyCode::Alt(b'/'))) {
-                                use okros::scrollback_search::create_scrollback_search;
-                                // Create search dialog (searches backwards by default)
-                                let search_dialog = create_scrollback_search(
-                                    screen.window_mut() as *mut okros::window::Window,
-                                    &mut output as *mut okros::output_window::OutputWindow,
-                                    false, // forward = false (search backwards)
-                                );
-                                modal = ModalState::SearchDialog(search_dialog);
-                                status.set_text("Enter search text (Esc to cancel)");
-                                continue;
+                            Dispatch::Frontend("autoreload", arg) => {
+                                // #autoreload on|off: watch ~/.okros/scripts for edits
+                                if arg.is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Autoreload is {}",
+                                        if script_watcher.is_some() {
+                                            "on"
+                                        } else {
+                                            "off"
+                                        }
+                                    ));
+                                } else if let Some(on) = okros::config::parse_bool(arg) {
+                                    script_watcher = on.then(|| {
+                                        okros::scripts::ScriptWatcher::new(scripts_dir.clone())
+                                    });
+                                    ui.status.set_text(format!(
+                                        "Autoreload {}",
+                                        if on { "on" } else { "off" }
+                                    ));
+                                } else {
+                                    ui.status
+                                        .post("Usage: #autoreload on|off", MsgLevel::Warning);
+                                }
                             }
-
-                            // Convert KeyEvent to i32 keycode for InputLine::keypress()
-                            let key_code = match ev {
-                                KeyEvent::Byte(b'\n') => 0x0D, // Enter
-                                KeyEvent::Byte(b) => b as i32,
-                                KeyEvent::Key(KeyCode::ArrowLeft) => 0x104,
-                                KeyEvent::Key(KeyCode::ArrowRight) => 0x105,
-                                KeyEvent::Key(KeyCode::ArrowUp) => 0x103,
-                                KeyEvent::Key(KeyCode::ArrowDown) => 0x102,
-                                KeyEvent::Key(KeyCode::Home) => 0x01, // Ctrl-A
-                                KeyEvent::Key(KeyCode::End) => 0x05,  // Ctrl-E
-                                KeyEvent::Key(KeyCode::Delete) => 0x14E,
-                                _ => continue, // Ignore unhandled keys
-                            };
-
-                            // Handle keypress
-                            input.keypress(key_code, &mut history, &mut command_queue);
-                        }
-
-                        // Process any queued commands
-                        let commands = command_queue.execute();
-                        for line in commands {
-                            // Check for # commands (basic interpreter)
-                            if line.starts_with("#quit") {
-                                quit = true;
-                                status.set_text("Quit.");
-                            } else if line.starts_with("#open ") {
+                            Dispatch::Frontend("open", args) => {
                                 // #open <host> <port>
-                                let args = &line[6..];
                                 if let Some((host_str, port_str)) = args.trim().split_once(' ') {
                                     if let Ok(port) = port_str.parse::<u16>() {
-                                        // Resolve hostname (supports both DNS and IPv4)
-                                        match resolve_hostname(host_str, port) {
-                                            Ok(ip) => {
-                                                let mut s = Socket::new().unwrap();
-                                                let _ = s.connect_ipv4(ip, port);
-                                                sock = Some(s);
-                                                status.set_text(format!(
-                                                    "Connecting to {}:{} -> {}...",
-                                                    host_str, port, ip
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                status.set_text(format!("DNS error: {}", e));
-                                            }
+                                        if connected(&sock) {
+                                            connection_down!();
                                         }
+                                        okros::scripts::set_session_vars(
+                                            &mut interps,
+                                            &mud.name,
+                                            host_str,
+                                            port,
+                                        );
+                                        // Resolved in the background (DNS names and
+                                        // IP literals); the main loop connects
+                                        lookup = Some((
+                                            String::new(),
+                                            okros::resolver::Lookup::start(host_str, port),
+                                        ));
+                                        ui.status.set_text(format!(
+                                            "Resolving {}... (Escape cancels)",
+                                            host_str
+                                        ));
                                     } else {
-                                        status.set_text("Usage: #open <host> <port>");
+                                        ui.status
+                                            .post("Usage: #open <host> <port>", MsgLevel::Warning);
                                     }
                                 } else {
-                                    status.set_text("Usage: #open <host> <port>");
+                                    ui.status
+                                        .post("Usage: #open <host> <port>", MsgLevel::Warning);
+                                }
+                            }
+                            Dispatch::Frontend(name @ ("close" | "zap"), _) => {
+                                // #close / #zap: whatever stage the connection is at;
+                                // the scrollback stays for review
+                                if let Some((_, l)) = lookup.take() {
+                                    ui.status
+                                        .set_text(format!("Lookup of {} cancelled", l.host()));
+                                } else if sock.as_mut().is_some_and(|s| s.cancel()) {
+                                    sock = None;
+                                    ui.status.set_text("Connect cancelled");
+                                } else if !connected(&sock) {
+                                    ui.status.post("Not connected", MsgLevel::Warning);
+                                } else if name == "zap" {
+                                    connection_down!();
+                                    engine
+                                        .session
+                                        .client_msg("Connection zapped.", MsgLevel::Info);
+                                } else {
+                                    // The logout command goes out, then a half-close;
+                                    // the MUD's goodbye still shows until it hangs up
+                                    if let Some(logout) = mud.logout_command() {
+                                        use okros::command_queue::EXPAND_ALL;
+                                        engine.commands.add_with_context(
+                                            logout,
+                                            EXPAND_ALL,
+                                            false,
+                                            None,
+                                            Some(&mud),
+                                        );
+                                        for line in engine.commands.execute_all() {
+                                            if let Dispatch::NotCommand = engine.command(&line) {
+                                                engine.sends.push(line);
+                                            }
+                                        }
+                                    }
+                                    let grace = okros::socket::CLOSE_GRACE;
+                                    closing = Some((std::time::Instant::now() + grace, false));
+                                    ui.status.set_text("Closing connection...");
                                 }
-                            } else if line.starts_with("#alias ") {
+                            }
+                            Dispatch::Frontend("alias", args) => {
                                 // #alias <name> <expansion>
-                                let args = line[7..].trim().to_string();
                                 if let Some((name, text)) = args.split_once(' ') {
                                     use okros::alias::Alias;
-                                    if let Some(pos) =
-                                        mud.alias_list.iter().position(|a| a.name == name)
+                                    let text = okros::command_queue::strip_braces(text);
+                                    define!(|scope| if let Some(pos) =
+                                        scope.alias_list.iter().position(|a| a.name == name)
                                     {
-                                        mud.alias_list[pos] = Alias::new(name, text);
-                                        status.set_text(format!(
+                                        scope.alias_list[pos] = Alias::new(name, text);
+                                        ui.status.set_text(format!(
                                             "Updated alias '{}' = {}",
                                             name, text
                                         ));
                                     } else {
-                                        mud.alias_list.push(Alias::new(name, text));
-                                        status
+                                        scope.alias_list.push(Alias::new(name, text));
+                                        ui.status
                                             .set_text(format!("Added alias '{}' = {}", name, text));
-                                    }
+                                    });
                                 } else if !args.is_empty() {
                                     // Remove alias
-                                    mud.alias_list.retain(|a| a.name != args);
-                                    status.set_text(format!("Removed alias '{}'", args));
+                                    define!(|scope| scope.alias_list.retain(|a| a.name != args));
+                                    ui.status.set_text(format!("Removed alias '{}'", args));
                                 } else {
-                                    status.set_text("Usage: #alias <name> <expansion>");
+                                    ui.status.post(
+                                        "Usage: #alias <name> <expansion>",
+                                        MsgLevel::Warning,
+                                    );
                                 }
-                            } else if line.starts_with("#save") {
+                            }
+                            Dispatch::Frontend("save", args) => {
                                 // #save [-c] <filename> (C++ Interpreter.cc:791-804)
-                                let args = line[5..].trim();
                                 let (use_color, filename) = if args.starts_with("-c ") {
                                     (true, args[3..].trim())
                                 } else {
                                     (false, args)
                                 };
 
-                                if filename.is_empty() {
-                                    status.set_text("Specify file to save scrollback to.");
+                                if filename.is_empty() && !use_color {
+                                    // Bare #save: persist aliases/actions/macros to the
+                                    // autosave file, keeping other MUDs' saved entries
+                                    let path = okros::config::Config::autosave_path();
+                                    let mut saved = okros::config::Config::new();
+                                    let result = saved.load_autosave().and_then(|_| {
+                                        saved.timestamps = engine.session.timestamps();
+                                        saved.keybindings = ui.bindings.clone();
+                                        if mud.name.is_empty() {
+                                            // Unconnected definitions went to the global scope
+                                            saved.global_mud.merge(&config.global_mud);
+                                        }
+                                        saved.remember(&mud);
+                                        saved.save_file(&path)
+                                    });
+                                    match result {
+                                        Ok(()) => ui.status.set_text(format!(
+                                            "Saved aliases, actions and macros to {}",
+                                            path.display()
+                                        )),
+                                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                                    }
+                                } else if filename.is_empty() {
+                                    ui.status.set_text("Specify file to save scrollback to.");
                                 } else {
-                                    // Save scrollback to file
-                                    if let Some(msg) = output.save_to_file(filename, use_color) {
-                                        status.set_text(msg);
+                                    // Save scrollback to file (~ and $VAR expanded)
+                                    let path = okros::config::expand_path(filename);
+                                    if let Some(msg) =
+                                        ui.output.save_to_file(&path.to_string_lossy(), use_color)
+                                    {
+                                        ui.status.set_text(msg);
                                     }
                                 }
-                            } else if line.starts_with("#action ") {
-                                // #action <pattern> <commands>
-                                let args = line[8..].trim().to_string();
-                                use okros::action::{Action, ActionType};
-                                match Action::parse(&args, ActionType::Trigger) {
-                                    Ok(mut action) => {
-                                        // Compile action with available interpreter
-                                        #[cfg(feature = "perl")]
-                                        if let Some(ref mut interp) = perl_interp {
-                                            use okros::plugins::stack::Interpreter;
-                                            action.compile(interp);
+                            }
+                            Dispatch::Frontend("load", _) => {
+                                // Re-read config + autosave and merge them into this session
+                                let mut loaded = okros::config::Config::new();
+                                let _ = loaded.load_file(okros::config::Config::default_path());
+                                match loaded.load_autosave() {
+                                    Ok(()) => {
+                                        if let Some(saved) = loaded.mud_list.find(&mud.name) {
+                                            mud.merge(saved);
                                         }
-                                        #[cfg(all(feature = "python", not(feature = "perl")))]
-                                        if let Some(ref mut interp) = python_interp {
-                                            use okros::plugins::stack::Interpreter;
-                                            action.compile(interp);
+                                        config.global_mud = loaded.global_mud.clone();
+                                        mud.inherits = None;
+                                        if let Some(parent) = loaded
+                                            .mud_list
+                                            .find(&mud.name)
+                                            .and_then(|m| m.inherits.as_deref())
+                                        {
+                                            mud.inherits = Some(Box::new(parent.clone()));
                                         }
-
-                                        mud.action_list.retain(|a| a.pattern != action.pattern);
-                                        status.set_text(format!(
-                                            "Added trigger: {} => {}",
-                                            action.pattern, action.commands
+                                        mud.inherit_globals(&config.global_mud);
+                                        mud.compile_actions(&mut interps);
+                                        apply_theme!();
+                                        engine.session.set_timestamps(loaded.timestamps);
+                                        engine.session.set_word_wrap(loaded.wordwrap);
+                                        echo_input = loaded.echoinput;
+                                        engine.session.set_msp(loaded.msp);
+                                        engine.session.set_atcp(loaded.atcp);
+                                        engine.session.set_mxp(loaded.mxp);
+                                        ui.screen.hyperlinks = loaded.hyperlinks;
+                                        ui.output.set_scrollback_lines(
+                                            mud.scrollback_lines().unwrap_or(loaded.scrollback),
+                                        );
+                                        ui.output.sb.set_spill(
+                                            loaded.scrollback_spill,
+                                            loaded.scrollback_spill_max,
+                                        );
+                                        let namespace = ui.history.namespace().to_string();
+                                        ui.history.select(
+                                            &namespace,
+                                            mud.history_size().unwrap_or(loaded.histsize),
+                                        );
+                                        engine.session.set_sticky_prompt(loaded.stickyprompt);
+                                        engine.session.set_diagnostics(loaded.diagnostics);
+                                        msp_player.set_command(loaded.msp_player.clone());
+                                        engine.sends.set_limits(loaded.sendrate, loaded.sendburst);
+                                        engine.session.set_output_budget(loaded.output_budget);
+                                        engine.session.set_more(loaded.more);
+                                        ui.status.set_message_time(loaded.status_time);
+                                        engine
+                                            .session
+                                            .trigger_guard
+                                            .set_max_depth(loaded.trigger_depth);
+                                        engine
+                                            .session
+                                            .trigger_guard
+                                            .set_gag_echoes(loaded.trigger_echo_gag);
+                                        if let Some(e) =
+                                            interps.set_limits(&loaded.script_limits).err()
+                                        {
+                                            ui.status.post(e, MsgLevel::Error);
+                                        }
+                                        ui.bindings = loaded.keybindings.clone();
+                                        config = loaded;
+                                        ui.status.set_text("Loaded config and autosave");
+                                    }
+                                    Err(e) => ui.status.post(e, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend("import", args) => {
+                                // #import tintin|zmud <file>: kept for this MUD by #save
+                                use okros::import::{import_file, Format};
+                                match args.split_once(' ') {
+                                    Some((format, file)) if !file.trim().is_empty() => {
+                                        let path = okros::config::expand_path(file.trim());
+                                        match Format::parse(format)
+                                            .and_then(|f| import_file(&path, f))
+                                        {
+                                            Ok(imported) => {
+                                                for skipped in &imported.skipped {
+                                                    let line =
+                                                        format!("Import skipped {}", skipped);
+                                                    ui.output.print_line(line.as_bytes(), 0x07);
+                                                }
+                                                let summary;
+                                                define!(|scope| summary = imported.apply_to(scope));
+                                                mud.compile_actions(&mut interps);
+                                                ui.status.set_text(summary);
+                                            }
+                                            Err(e) => ui.status.post(e, MsgLevel::Error),
+                                        }
+                                    }
+                                    _ => ui.status.post(
+                                        "Usage: #import tintin|zmud <file>",
+                                        MsgLevel::Warning,
+                                    ),
+                                }
+                            }
+                            Dispatch::Frontend("action", args) => {
+                                // #action [-prompt] <pattern> <commands>
+                                use okros::action::{Action, ActionType};
+                                match Action::parse(args, ActionType::Trigger) {
+                                    Ok(mut action) => {
+                                        action.compile(&mut interps);
+
+                                        let kind = match action.action_type {
+                                            ActionType::Prompt => "prompt trigger",
+                                            _ => "trigger",
+                                        };
+                                        ui.status.set_text(format!(
+                                            "Added {}: {} => {}",
+                                            kind, action.pattern, action.commands
                                         ));
-                                        mud.action_list.push(action);
+                                        define!(|scope| {
+                                            scope
+                                                .action_list
+                                                .retain(|a| a.pattern != action.pattern);
+                                            scope.action_list.push(action);
+                                        });
                                     }
-                                    Err(e) => status.set_text(e),
+                                    Err(e) => ui.status.post(e, MsgLevel::Error),
                                 }
-                            } else if line.starts_with("#subst ") {
+                            }
+                            Dispatch::Frontend("subst", args) => {
                                 // #subst <pattern> <replacement>
-                                let args = line[7..].trim().to_string();
                                 use okros::action::{Action, ActionType};
-                                match Action::parse(&args, ActionType::Replacement) {
+                                match Action::parse(args, ActionType::Replacement) {
                                     Ok(mut action) => {
-                                        // Compile substitution with available interpreter
-                                        #[cfg(feature = "perl")]
-                                        if let Some(ref mut interp) = perl_interp {
-                                            use okros::plugins::stack::Interpreter;
-                                            action.compile(interp);
-                                        }
-                                        #[cfg(all(feature = "python", not(feature = "perl")))]
-                                        if let Some(ref mut interp) = python_interp {
-                                            use okros::plugins::stack::Interpreter;
-                                            action.compile(interp);
-                                        }
+                                        action.compile(&mut interps);
 
-                                        mud.action_list.retain(|a| a.pattern != action.pattern);
-                                        status.set_text(format!(
+                                        ui.status.set_text(format!(
                                             "Added substitute: {} => {}",
                                             action.pattern, action.commands
                                         ));
-                                        mud.action_list.push(action);
+                                        define!(|scope| {
+                                            scope
+                                                .action_list
+                                                .retain(|a| a.pattern != action.pattern);
+                                            scope.action_list.push(action);
+                                        });
+                                    }
+                                    Err(e) => ui.status.post(e, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend(name @ ("gag" | "highlight"), args) => {
+                                // #gag <pattern>  /  #highlight <pattern> <color>
+                                use okros::action::{Action, ActionType};
+                                let action_type = if name == "gag" {
+                                    ActionType::Gag
+                                } else {
+                                    ActionType::Highlight
+                                };
+                                match Action::parse(args, action_type) {
+                                    Ok(mut action) => {
+                                        action.compile(&mut interps);
+
+                                        ui.status.set_text(if action_type == ActionType::Gag {
+                                            format!("Added gag: {}", action.pattern)
+                                        } else {
+                                            format!(
+                                                "Added highlight: {} => {}",
+                                                action.pattern, action.commands
+                                            )
+                                        });
+                                        define!(|scope| {
+                                            scope
+                                                .action_list
+                                                .retain(|a| a.pattern != action.pattern);
+                                            scope.action_list.push(action);
+                                        });
                                     }
-                                    Err(e) => status.set_text(e),
+                                    Err(e) => ui.status.post(e, MsgLevel::Error),
                                 }
-                            } else if line.starts_with("#macro ") {
-                                // #macro <keyname> <text>
-                                let args = line[7..].trim().to_string();
-                                if let Some((key_name, text)) = args.split_once(' ') {
-                                    // For now, just use ASCII value as key code
-                                    // TODO: implement key_lookup() like C++ for named keys (F1, etc.)
-                                    if let Some(ch) = key_name.chars().next() {
-                                        use okros::macro_def::Macro;
-                                        let key = ch as i32;
-                                        mud.macro_list.retain(|m| m.key != key);
-                                        mud.macro_list.push(Macro::new(key, text));
-                                        status.set_text(format!(
+                            }
+                            Dispatch::Frontend("macro", args) => {
+                                // #macro <keyname> [text] (C++ key_lookup names: f1, kp5, alt-x, ^a...)
+                                let (key_name, text) = args.split_once(' ').unwrap_or((args, ""));
+                                let text = okros::command_queue::strip_braces(text);
+                                use okros::macro_def::{
+                                    key_lookup, key_name as describe_key, Macro,
+                                };
+                                match key_lookup(key_name) {
+                                    Some(key) if text.is_empty() => {
+                                        // No text: remove the macro
+                                        define!(|scope| scope.macro_list.retain(|m| m.key != key));
+                                        ui.status.set_text(format!(
+                                            "Removed macro {}",
+                                            describe_key(key)
+                                        ));
+                                    }
+                                    Some(key) => {
+                                        define!(|scope| {
+                                            scope.macro_list.retain(|m| m.key != key);
+                                            scope.macro_list.push(Macro::new(key, text));
+                                        });
+                                        ui.status.set_text(format!(
                                             "Added macro: {} => {}",
-                                            key_name, text
+                                            describe_key(key),
+                                            text
                                         ));
-                                    } else {
-                                        status.set_text("Invalid key name");
                                     }
+                                    None => ui
+                                        .status
+                                        .set_text(format!("Invalid key name: {}", key_name)),
+                                }
+                            }
+                            Dispatch::Frontend("bind" | "unbind", _) => {
+                                if let Some(result) = ui.bindings.handle_command(&line) {
+                                    // #bind [<key> [<action>]] / #unbind <key>
+                                    match result {
+                                        Ok(listing) if listing.contains('\n') => {
+                                            for entry in listing.lines() {
+                                                ui.output.print_line(entry.as_bytes(), 0x07);
+                                            }
+                                        }
+                                        Ok(msg) => ui.status.set_text(msg),
+                                        Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("grep" | "lastlog", _) => {
+                                if let Some(result) = ui.grep_command(&line) {
+                                    // #grep <regex> / #lastlog <text>
+                                    match result {
+                                        Ok(msg) => ui.status.set_text(msg),
+                                        Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("window", _) => {
+                                if let Some(result) = ui.window_command(&line) {
+                                    // #window [split|vsplit|close|resize|focus|next]
+                                    match result {
+                                        Ok(listing) if listing.contains('\n') => {
+                                            for entry in listing.lines() {
+                                                ui.output.print_line(entry.as_bytes(), 0x07);
+                                            }
+                                        }
+                                        Ok(msg) => ui.status.set_text(msg),
+                                        Err(msg) => ui.status.post(msg, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("path", args) => {
+                                // #path learn/stop/go/delete; go hands the steps to the
+                                // queue's speedwalk expansion
+                                use okros::path::{path_command, PathReply};
+                                match path_command(args, &mut path_recording, &mut mud) {
+                                    Ok(PathReply::Walk(name, steps)) => {
+                                        use okros::command_queue::EXPAND_SPEEDWALK;
+                                        let walk = format!("/{}", steps);
+                                        engine.commands.add(&walk, EXPAND_SPEEDWALK, false);
+                                        for step in engine.commands.execute() {
+                                            if sock.is_some() {
+                                                engine.sends.push(step);
+                                            } else {
+                                                ui.output.print_line(step.as_bytes(), 0x07);
+                                            }
+                                        }
+                                        ui.status.set_text(format!("Walking {}: {}", name, steps));
+                                    }
+                                    Ok(PathReply::Message(listing)) if listing.contains('\n') => {
+                                        for entry in listing.lines() {
+                                            ui.output.print_line(entry.as_bytes(), 0x07);
+                                        }
+                                    }
+                                    Ok(PathReply::Message(msg)) | Err(msg) => {
+                                        ui.status.set_text(msg)
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("keepalive", args) => {
+                                // #keepalive [off | <seconds> [nop|blank|<command>]]
+                                let result = okros::keepalive::keepalive_command(args, &mut mud);
+                                match result {
+                                    Ok(msg) => ui.status.set_text(msg),
+                                    Err(e) => ui.status.post(e, MsgLevel::Error),
+                                }
+                            }
+                            Dispatch::Frontend("charset", args) => {
+                                // #charset [utf8|latin1]: kept for this MUD by #save
+                                if args.trim().is_empty() {
+                                    ui.status.set_text(format!(
+                                        "Charset: {}",
+                                        engine.session.charset().name()
+                                    ));
+                                } else {
+                                    match okros::charset::Charset::parse(args) {
+                                        Ok(charset) => {
+                                            engine.session.set_charset(charset);
+                                            mud.charset = Some(charset);
+                                            ui.status.set_text(format!(
+                                                "Charset set to {}",
+                                                charset.name()
+                                            ));
+                                        }
+                                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("bell", args) => {
+                                // #bell [beep|visual|hook|off]: kept for this MUD by #save
+                                if args.trim().is_empty() {
+                                    ui.status.set_text(format!("Bell: {}", mud.bell().name()));
                                 } else {
-                                    status.set_text("Usage: #macro <key> <text>");
+                                    match okros::bell::BellMode::parse(args) {
+                                        Ok(mode) => {
+                                            mud.bell = Some(mode);
+                                            ui.status
+                                                .set_text(format!("Bell set to {}", mode.name()));
+                                        }
+                                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                                    }
+                                }
+                            }
+                            Dispatch::Frontend("group", args) => {
+                                // #group [<name> [on|off|add|remove <kind> <what>]]
+                                use okros::group::group_command;
+                                match group_command(args, &mut mud, &mut engine.timers) {
+                                    Ok(listing) if listing.contains('\n') => {
+                                        for entry in listing.lines() {
+                                            ui.output.print_line(entry.as_bytes(), 0x07);
+                                        }
+                                    }
+                                    Ok(msg) => ui.status.set_text(msg),
+                                    Err(msg) => ui.status.post(msg, MsgLevel::Error),
                                 }
-                            } else if line.starts_with("#") {
-                                // Other # commands - just echo for now
-                                output.print_line(line.as_bytes(), 0x07);
-                            } else {
+                            }
+                            Dispatch::Frontend(name, _) => {
+                                ui.status
+                                    .set_text(format!("#{} isn't available here", name));
+                            }
+                            Dispatch::NotCommand => {
                                 // Check for alias expansion
                                 let mut send_text = line.clone();
 
@@ -576,14 +2200,16 @@ fn main() {
 
                                     // Check if command is an alias
                                     if let Some(alias) = mud.find_alias(cmd) {
-                                        send_text = alias.expand(args);
-                                        status.set_text(format!("{} -> {}", cmd, send_text));
+                                        send_text =
+                                            engine.commands.variables.expand(&alias.expand(args));
+                                        ui.status.set_text(format!("{} -> {}", cmd, send_text));
                                     }
                                 } else {
                                     // No arguments, check if entire line is an alias
                                     if let Some(alias) = mud.find_alias(&line) {
-                                        send_text = alias.expand("");
-                                        status.set_text(format!(
+                                        send_text =
+                                            engine.commands.variables.expand(&alias.expand(""));
+                                        ui.status.set_text(format!(
                                             "{} -> {}",
                                             line.trim(),
                                             send_text
@@ -591,19 +2217,11 @@ fn main() {
                                     }
                                 }
 
-                                // Send to MUD (or echo if no socket)
-                                if let Some(ref mut s) = sock {
-                                    let mut send_buf = send_text.into_bytes();
-                                    send_buf.push(b'\n');
-                                    unsafe {
-                                        libc::write(
-                                            s.as_raw_fd(),
-                                            send_buf.as_ptr() as *const libc::c_void,
-                                            send_buf.len(),
-                                        );
-                                    }
+                                // Send to MUD or the offline world (or echo if neither)
+                                if sock.is_some() || world.is_some() {
+                                    engine.sends.push(send_text);
                                 } else {
-                                    output.print_line(send_text.as_bytes(), 0x07);
+                                    ui.output.print_line(send_text.as_bytes(), 0x07);
                                 }
                             }
                         }
@@ -612,14 +2230,14 @@ fn main() {
             } else if let Some(s) = &mut sock {
                 if fd == s.as_raw_fd() {
                     // Socket writable (connection completing)
-                    if (r.revents & WRITE) != 0 && s.state == ConnState::Connecting {
+                    if r.writable() && s.state == ConnState::Connecting {
                         let _ = s.on_writable();
                         if s.state == ConnState::Connected {
-                            status.set_text("Connected.");
+                            connection_up!(&s.remote.map(|a| a.to_string()).unwrap_or_default());
                         }
                     }
                     // Socket readable (MUD data)
-                    if (r.revents & READ) != 0 {
+                    if r.readable() {
                         let n = unsafe {
                             libc::read(
                                 s.as_raw_fd(),
@@ -628,104 +2246,64 @@ fn main() {
                             )
                         };
                         if n > 0 {
-                            session.feed(&buf[..n as usize]);
-                            // NOTE: Session now writes directly to OutputWindow.sb (no manual blit needed)
-
-                            // Check triggers/actions on current incomplete line
-                            // TODO: This should check completed lines from scrollback,
-                            // but for MVP we check the current incomplete line
-                            let current_line = session.current_line();
-                            if !current_line.is_empty() {
-                                let line_str = String::from_utf8_lossy(&current_line);
-
-                                // Check triggers with available interpreter
-                                #[cfg(feature = "perl")]
-                                if let Some(ref mut interp) = perl_interp {
-                                    use okros::action::ActionType;
-                                    use okros::plugins::stack::Interpreter;
-
-                                    for action in &mud.action_list {
-                                        if action.action_type == ActionType::Trigger {
-                                            if let Some(commands) =
-                                                action.check_match(&line_str, interp)
-                                            {
-                                                // Trigger matched - execute commands
-                                                // For now, just send the commands to MUD
-                                                if let Some(ref mut s) = sock {
-                                                    let mut cmd_buf = commands.into_bytes();
-                                                    cmd_buf.push(b'\n');
-                                                    unsafe {
-                                                        libc::write(
-                                                            s.as_raw_fd(),
-                                                            cmd_buf.as_ptr() as *const libc::c_void,
-                                                            cmd_buf.len(),
-                                                        );
-                                                    }
-                                                    status.set_text(format!(
-                                                        "Trigger fired: {}",
-                                                        action.pattern
-                                                    ));
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                #[cfg(all(feature = "python", not(feature = "perl")))]
-                                if let Some(ref mut interp) = python_interp {
-                                    use okros::action::ActionType;
-                                    use okros::plugins::stack::Interpreter;
-
-                                    for action in &mud.action_list {
-                                        if action.action_type == ActionType::Trigger {
-                                            if let Some(commands) =
-                                                action.check_match(&line_str, interp)
-                                            {
-                                                // Trigger matched - execute commands
-                                                if let Some(ref mut s) = sock {
-                                                    let mut cmd_buf = commands.into_bytes();
-                                                    cmd_buf.push(b'\n');
-                                                    unsafe {
-                                                        libc::write(
-                                                            s.as_raw_fd(),
-                                                            cmd_buf.as_ptr() as *const libc::c_void,
-                                                            cmd_buf.len(),
-                                                        );
-                                                    }
-                                                    status.set_text(format!(
-                                                        "Trigger fired: {}",
-                                                        action.pattern
-                                                    ));
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+                            if let Some(Err(e)) =
+                                recorder.as_mut().map(|r| r.write(&buf[..n as usize]))
+                            {
+                                ui.status
+                                    .post(format!("Recording stopped: {}", e), MsgLevel::Error);
+                                recorder = None;
+                            }
+                            if let Some(rtt) = engine.session.record_read(n as usize) {
+                                ui.status.set_text(format!("Ping: {} ms", rtt.as_millis()));
                             }
+                            // sys/output and sys/prompt run in the scripting interpreter;
+                            // #subst/#gag/#highlight rewrite lines before they're shown
+                            engine.session.feed_with_mud(
+                                &buf[..n as usize],
+                                Some(&mut interps),
+                                Some(&mud),
+                            );
+                            // Telnet negotiation replies (EOR, MSP, MCCP)
+                            let replies = engine.session.take_telnet_responses();
+                            if !replies.is_empty() {
+                                write_mud(s, &mut engine.session, &replies);
+                            }
+                            output_shown!();
                         } else if n == 0 {
                             // Connection closed
-                            status.set_text("Connection closed.");
-                            sock = None;
+                            engine
+                                .session
+                                .client_msg("Connection closed.", MsgLevel::Info);
+                            connection_down!();
                         }
                     }
                 }
             }
         }
 
-        // 4. Run interpreter hooks (main.cc:149)
-        #[cfg(feature = "python")]
-        if let Some(ref mut interp) = python_interp {
-            use okros::plugins::stack::Interpreter;
-            let mut out = String::new();
-            let _ = interp.run_quietly("sys/postoutput", "", &mut out, true);
+        // BEL from the MUD (or a replay): beep, flash or sys/bell, per MUD
+        for line in engine.session.take_bells() {
+            match mud.bell() {
+                okros::bell::BellMode::Hook => {
+                    let _ = interps.run_quietly("sys/bell", &line, &mut String::new(), true);
+                }
+                mode => ui.bell(mode),
+            }
         }
 
-        #[cfg(feature = "perl")]
-        if let Some(ref mut interp) = perl_interp {
-            use okros::plugins::stack::Interpreter;
-            let mut out = String::new();
-            let _ = interp.run_quietly("sys/postoutput", "", &mut out, true);
+        // Window size changes, then bus events to the scripts that asked
+        // for them (okros.on)
+        if let Some((width, height)) = ui.take_resize() {
+            engine
+                .bus
+                .publish(okros::event_bus::BusEvent::Resize { width, height });
         }
+        for (function, arg) in engine.bus.take_calls() {
+            let _ = interps.run_quietly(&function, &arg, &mut String::new(), true);
+        }
+
+        // 4. Run interpreter hooks (main.cc:149)
+        let _ = interps.run_quietly("sys/postoutput", "", &mut String::new(), true);
 
         // 5. Session idle callbacks (main.cc:155) - time updates, etc.
         // (not implemented yet in Session)
@@ -741,312 +2319,483 @@ fn main() {
 
         if now != last_callout_time {
             last_callout_time = now;
+            ui.status.set_field(
+                "clock",
+                &chrono::Local::now().format("%H:%M").to_string(),
+                okros::status_line::PRIORITY_CLOCK,
+            );
+
+            interps.set_int("now", now);
+            let instant = std::time::Instant::now();
+            interps.set_int("idle", engine.session.idle(instant).as_secs() as i64);
+            interps.set_int(
+                "outputIdle",
+                engine.session.output_idle(instant).as_secs() as i64,
+            );
+            let _ = interps.run_quietly("sys/idle", "", &mut String::new(), true);
+
+            if engine.session.net.expire_ping(std::time::Instant::now()) {
+                ui.status.set_text("Ping: no reply");
+            }
 
-            #[cfg(feature = "python")]
-            if let Some(ref mut interp) = python_interp {
-                use okros::plugins::stack::Interpreter;
-                interp.set_int("now", now);
-                let mut out = String::new();
-                let _ = interp.run_quietly("sys/idle", "", &mut out, true);
+            // autoreload: re-run edited scripts, then recompile actions
+            let changed = script_watcher
+                .as_mut()
+                .map(|w| w.changed())
+                .unwrap_or_default();
+            if !changed.is_empty() {
+                for path in &changed {
+                    match okros::scripts::load_script(&mut interps.by_extension(), path) {
+                        Ok(()) => ui.status.set_text(format!("Reloaded {}", path.display())),
+                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                    }
+                }
+                mud.compile_actions(&mut interps);
             }
+        }
 
-            #[cfg(feature = "perl")]
-            if let Some(ref mut interp) = perl_interp {
-                use okros::plugins::stack::Interpreter;
-                interp.set_int("now", now);
-                let mut out = String::new();
-                let _ = interp.run_quietly("sys/idle", "", &mut out, true);
+        // 8. Expired #wait/#tick timers - send to MUD (or echo if no socket)
+        let due =
+            engine.run_timers_active(std::time::Instant::now(), |group| mud.group_enabled(group));
+        for command in due {
+            if let (Some(w), okros::offline_mud::WORLD_TICK) = (world.as_mut(), command.as_str()) {
+                let output = w.command(&command);
+                feed_output!(output.as_bytes());
+                continue;
+            }
+            let command = engine.commands.variables.expand(&command);
+            if sock.is_some() || world.is_some() {
+                engine.sends.push(command);
+            } else {
+                ui.output.print_line(command.as_bytes(), 0x07);
             }
         }
-    }
 
-    // Restore keypad mode, show cursor, clear screen
-    let _ = tty.keypad_application_mode(false);
-    print!("\x1b[?25h\x1b[2J\x1b[H");
-    std::io::stdout().flush().unwrap();
-}
+        // #keepalive: IAC NOP (or its command) once nothing has been sent for a while
+        if let (Some(s), Some(keepalive)) = (&sock, &mud.keepalive) {
+            if s.state == ConnState::Connected {
+                if let Some(nop) = engine.keepalive(keepalive, std::time::Instant::now()) {
+                    write_mud(s, &mut engine.session, &nop);
+                }
+            }
+        }
 
-fn run_offline_mode() {
-    use okros::offline_mud::{parse, World};
+        // Requests queued by scripts through the okros API object
+        let mut requests = interps.take_requests();
+        // A runaway script can't flood the MUD (scriptbudget)
+        if let Some(msg) = config.script_limits.enforce_budget(&mut requests) {
+            ui.status.set_text(msg);
+        }
+        for request in requests {
+            use okros::plugins::stack::ScriptRequest;
+            match request {
+                // okros.send(text): to the MUD (or echo if no socket), like timers
+                ScriptRequest::Send(text) => {
+                    if sock.is_some() || world.is_some() {
+                        engine.sends.push(text);
+                    } else {
+                        ui.output.print_line(text.as_bytes(), 0x07);
+                    }
+                }
+                ScriptRequest::Print(text, color) => {
+                    for line in text.lines() {
+                        ui.output.print_line(line.as_bytes(), color);
+                    }
+                }
+                ScriptRequest::SetStatus(text) => ui.status.set_text(text),
+                ScriptRequest::SetVar(name, value) => {
+                    if let Err(e) = engine.commands.variables.set(&name, &value) {
+                        ui.status.post(e, MsgLevel::Error);
+                    }
+                }
+                ScriptRequest::SendRaw(data) => engine.session.send_raw(&data),
+                ScriptRequest::SendSubneg(option, payload) => {
+                    engine.session.send_subneg(option, &payload)
+                }
+                ScriptRequest::Watch(fd, interest, function) => {
+                    let callback = okros::select::script_callback(function);
+                    let token = selector.register_with(fd, interest, callback);
+                    if let Some(old) = script_watches.insert(fd, token) {
+                        selector.deregister(old);
+                    }
+                }
+                ScriptRequest::Unwatch(fd) => {
+                    if let Some(token) = script_watches.remove(&fd) {
+                        selector.deregister(token);
+                    }
+                }
+                ScriptRequest::Filter(args) => match engine.session.filters.command(&args) {
+                    Ok(msg) => ui.status.set_text(msg),
+                    Err(e) => ui.status.post(e, MsgLevel::Error),
+                },
+                ScriptRequest::On(event, function) => {
+                    match okros::event_bus::EventKind::parse(&event) {
+                        Ok(kind) => {
+                            engine.bus.subscribe(kind, &function);
+                        }
+                        Err(e) => ui.status.post(e, MsgLevel::Error),
+                    }
+                }
+                ScriptRequest::Off(event, function) => {
+                    if let Ok(kind) = okros::event_bus::EventKind::parse(&event) {
+                        engine.bus.unsubscribe(kind, &function);
+                    }
+                }
+            }
+        }
+        // Raw telnet data queued outside a read (okros.send_raw/send_subneg)
+        let raw = engine.session.take_telnet_responses();
+        if let Some(ref s) = sock {
+            if !raw.is_empty() {
+                write_mud(s, &mut engine.session, &raw);
+            }
+        }
 
-    // Initialize internal MUD
-    let mut world = World::new();
+        // Paced sends: whatever the send rate allows goes out now (echoed
+        // when offline, e.g. #send); sys/send may rewrite or cancel each one
+        for (line, secret) in engine.sends.ready_sends(std::time::Instant::now()) {
+            if secret {
+                // A password: not echoed, recorded or shown to sys/send
+                if let Some(ref s) = sock {
+                    let send_buf = engine.session.encode_line(&line);
+                    write_mud(s, &mut engine.session, &send_buf);
+                }
+                continue;
+            }
+            let Some(line) = okros::command_queue::send_hook(&mut interps, &line) else {
+                continue;
+            };
+            if let Some(recorder) = path_recording.as_mut() {
+                recorder.record(&line);
+            }
+            match sock {
+                Some(ref s) => {
+                    // Passwords (server echo off) aren't echoed
+                    if echo_input && !ui.input.is_masked() {
+                        let color = mud.theme().echo_color();
+                        ui.output.print_soft_line(line.as_bytes(), color as u16);
+                    }
+                    let send_buf = engine.session.encode_line(&line);
+                    write_mud(s, &mut engine.session, &send_buf);
+                    engine.session.record_send();
+                }
+                None => match world.as_mut() {
+                    Some(w) => {
+                        if echo_input {
+                            let color = mud.theme().echo_color();
+                            ui.output.print_soft_line(line.as_bytes(), color as u16);
+                        }
+                        let output = w.command(&line);
+                        engine.session.record_send();
+                        feed_output!(output.as_bytes());
+                        if world.as_ref().is_some_and(|w| w.quit_requested()) {
+                            leave_world!();
+                            ui.status.set_text("Left the offline MUD");
+                            quit |= offline;
+                        }
+                    }
+                    None => ui.output.print_line(line.as_bytes(), 0x07),
+                },
+            }
+        }
 
-    // Set up TTY
-    let mut tty = match okros::tty::Tty::new() {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("tty init failed: {}", e);
-            return;
+        // #close: half-close once the logout command has gone out, and hang
+        // up if the MUD hasn't by the deadline
+        if let Some((deadline, shut)) = closing {
+            if sock.is_none() {
+                closing = None;
+            } else if std::time::Instant::now() >= deadline {
+                connection_down!();
+                engine
+                    .session
+                    .client_msg("Connection closed.", MsgLevel::Info);
+            } else if !shut && engine.sends.is_empty() {
+                if let Some(s) = &sock {
+                    let _ = s.shutdown_write();
+                }
+                closing = Some((deadline, true));
+            }
         }
-    };
-    let _ = tty.enable_raw();
-    let _ = tty.keypad_application_mode(true);
 
-    // UI setup
-    let width = 80usize;
-    let height = 24usize;
-    let caps = get_acs_caps();
-
-    // Create Screen (root Window)
-    let mut screen = okros::screen::Screen::new(width, height);
-
-    // Create OutputWindow as child of Screen - C++ OutputWindow.cc:9-10
-    let mut output = okros::output_window::OutputWindow::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        height - 1, // C++: _parent->height-1
-        200,
-        0x07,
-    );
-    output.win.parent_y = 0; // C++ defaults to 0
+        // Output held back over the budget (or until a key after #more)
+        if engine.session.pending_output() {
+            engine.session.pump(Some(&mut interps), Some(&mud));
+            output_shown!();
+        }
 
-    // Session for processing output (matches OutputWindow size)
-    let mut session = Session::new(PassthroughDecomp::new(), width, height - 1, 200);
+        // 9. Status line fields (set_field only redraws on change)
+        {
+            use okros::status_line::{
+                idle_text, PRIORITY_ACTIVITY, PRIORITY_CONNECTION, PRIORITY_IDLE, PRIORITY_MCCP,
+                PRIORITY_NET, PRIORITY_QUEUE,
+            };
+            let conn = match &sock {
+                None if world.is_some() => "Offline MUD".to_string(),
+                None => "Offline".to_string(),
+                Some(s) if s.state == ConnState::Connecting => "Connecting...".to_string(),
+                Some(_) if mud.name.is_empty() => "Connected".to_string(),
+                Some(_) => mud.name.clone(),
+            };
+            ui.status.set_field("conn", &conn, PRIORITY_CONNECTION);
 
-    // History and command queue
-    let mut history = okros::history::HistorySet::new(100);
-    let mut command_queue = okros::command_queue::CommandQueue::new();
+            // Traffic rates and last ping while connected (empty text clears)
+            let net = match &sock {
+                Some(s) if s.state == ConnState::Connected => {
+                    engine.session.net.status_text(std::time::Instant::now())
+                }
+                _ => String::new(),
+            };
+            ui.status.set_field("net", &net, PRIORITY_NET);
 
-    // Input line (created before StatusLine for correct z-order)
-    let mut input = okros::input_line::InputLine::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        0x07,
-        okros::history::HistoryId::MainInput,
-    );
-    input.win.parent_y = (height - 1) as isize;
+            // Time since the last command sent, while connected
+            let idle = match &sock {
+                Some(s) if s.state == ConnState::Connected => {
+                    idle_text(engine.session.idle(std::time::Instant::now()))
+                }
+                _ => String::new(),
+            };
+            ui.status.set_field("idle", &idle, PRIORITY_IDLE);
 
-    // Status line (created last = top z-order)
-    let mut status = okros::status_line::StatusLine::new(
-        screen.window_mut() as *mut okros::window::Window,
-        width,
-        0x07,
-    );
-    status.win.parent_y = 0;
-    status.set_text("Internal MUD - type 'help' for commands, 'quit' to exit");
+            // Output that arrived while the view is scrolled back
+            if ui.output.sb.viewpoint >= ui.output.sb.canvas_ptr() {
+                engine.session.mark_seen();
+            }
+            let activity = if engine.session.has_unseen() {
+                "New output"
+            } else {
+                ""
+            };
+            ui.status.set_field("activity", activity, PRIORITY_ACTIVITY);
 
-    // Show initial room
-    let look_output = world.execute(parse("look").unwrap());
-    session.feed(look_output.as_bytes());
+            // #more pause, and lines a flood pushed out of the backlog
+            let more = match engine.session.paused() {
+                Some(n) => format!("-- More -- ({} lines)", n),
+                None => String::new(),
+            };
+            ui.status.set_field("more", &more, PRIORITY_ACTIVITY);
+            let dropped = match engine.session.dropped_lines() {
+                0 => String::new(),
+                n => format!("Dropped {} lines", n),
+            };
+            ui.status.set_field("dropped", &dropped, PRIORITY_QUEUE);
+            ui.status
+                .set_field("queue", &engine.sends.status_text(), PRIORITY_QUEUE);
+
+            if let Some((comp, uncomp)) = engine.session.compression_stats() {
+                let ratio = (comp * 100).checked_div(uncomp).unwrap_or(100);
+                ui.status
+                    .set_field("mccp", &format!("MCCP {}%", ratio), PRIORITY_MCCP);
+            }
 
-    // Set stdin nonblocking
-    unsafe {
-        let _ = fcntl(libc::STDIN_FILENO, F_SETFL, O_NONBLOCK);
+            // Fields queued by the script `status(name, text, priority)` builtin
+            for (name, text, priority) in interps.take_status_fields() {
+                ui.status.set_field(&name, &text, priority);
+            }
+        }
     }
+}
 
-    let mut dec = KeyDecoder::new();
-    let mut buf = [0u8; 1024];
-    let mut quit = false;
-
-    // Main event loop for offline mode
-    while !quit {
-        // Copy session scrollback to OutputWindow (offline mode has own scrollback)
-        if let Some(viewport) = session.scrollback_viewport() {
-            output.win.blit(viewport);
-            output.win.dirty = true;
+/// Ctrl-] leaves --attach (like telnet's escape character)
+const ATTACH_ESCAPE: u8 = 0x1D;
+
+/// Remote-attach TUI: forward keystrokes to a headless instance ("key") and
+/// draw its composited screen ("get_screen") until Ctrl-] or the daemon exits
+/// `force` takes the attachment over from another client
+fn run_attach_mode(path: &std::path::Path, force: bool) -> io::Result<()> {
+    use okros::select::{poll_fds, READ};
+    use serde_json::{json, Value};
+
+    let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut request = |req: Value| -> io::Result<Value> {
+        writeln!(stream, "{}", req)?;
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::other("control socket closed"));
         }
+        let resp: Value = serde_json::from_str(&line).map_err(io::Error::other)?;
+        match resp["event"].as_str() {
+            Some("detached") => Err(io::Error::other(format!(
+                "taken over by client {}",
+                resp["by"]
+            ))),
+            Some("Error") => Err(io::Error::other(
+                resp["message"].as_str().unwrap_or("error").to_string(),
+            )),
+            _ => Ok(resp),
+        }
+    };
+    // Only one client drives the session; the rest are observers
+    request(json!({"cmd":"attach","force":force}))?;
 
-        // Render UI (Window hierarchy)
-        screen.refresh(&caps);
-
-        // Poll stdin with 250ms timeout
-        let fds = vec![(libc::STDIN_FILENO, READ)];
-        let ready = poll_fds(&fds, 250).unwrap_or_default();
-
-        // Process input
-        for (fd, r) in ready {
-            if fd == libc::STDIN_FILENO && (r.revents & READ) != 0 {
-                if let Ok(n) = io::stdin().read(&mut buf) {
-                    if n > 0 {
-                        for ev in dec.feed(&buf[..n]) {
-                            // Convert KeyEvent to i32 keycode for InputLine::keypress()
-                            let key_code = match ev {
-                                KeyEvent::Byte(b'\n') => 0x0D, // Enter
-                                KeyEvent::Byte(b) => b as i32,
-                                KeyEvent::Key(KeyCode::ArrowLeft) => 0x104,
-                                KeyEvent::Key(KeyCode::ArrowRight) => 0x105,
-                                KeyEvent::Key(KeyCode::ArrowUp) => 0x103,
-                                KeyEvent::Key(KeyCode::ArrowDown) => 0x102,
-                                KeyEvent::Key(KeyCode::Home) => 0x01, // Ctrl-A
-                                KeyEvent::Key(KeyCode::End) => 0x05,  // Ctrl-E
-                                KeyEvent::Key(KeyCode::Delete) => 0x14E,
-                                _ => continue, // Ignore unhandled keys
-                            };
-
-                            // Handle keypress
-                            input.keypress(key_code, &mut history, &mut command_queue);
-                        }
-
-                        // Process any queued commands
-                        let commands = command_queue.execute();
-                        for cmd_str in commands {
-                            // Parse and execute MUD command
-                            match parse(&cmd_str) {
-                                Ok(cmd) => {
-                                    // Check for quit command
-                                    if matches!(cmd, okros::offline_mud::parser::Command::Quit) {
-                                        quit = true;
-                                    }
-                                    let output = world.execute(cmd);
-                                    session.feed(output.as_bytes());
-                                }
-                                Err(e) => {
-                                    // Parse error - show in red
-                                    let err_msg = format!("\x1b[31m{}\x1b[0m\n", e);
-                                    session.feed(err_msg.as_bytes());
-                                }
-                            }
-                        }
-                    }
+    let mut tty = okros::tty::Tty::new()?;
+    tty.enable_raw()?;
+    let _ = tty.keypad_application_mode(true);
+    let _ = tty.bracketed_paste(true);
+    let term = okros::curses::get_term_caps();
+    print!("{}{}", term.smcup, term.clear);
+
+    let mut last: Option<Value> = None;
+    let result = loop {
+        // Redraw only when the remote screen changed
+        let screen = match request(json!({"cmd":"get_screen"})) {
+            Ok(v) => v,
+            Err(e) => break Err(e),
+        };
+        if last.as_ref() != Some(&screen) {
+            let mut out = String::new();
+            if let Some(rows) = screen["rows"].as_array() {
+                for (y, row) in rows.iter().enumerate() {
+                    term.cup.push(&mut out, y, 0);
+                    out.push_str(row.as_str().unwrap_or(""));
+                    out.push_str(&term.sgr0);
+                    out.push_str(&term.el);
                 }
             }
+            term.cup.push(
+                &mut out,
+                screen["cursor_y"].as_u64().unwrap_or(0) as usize,
+                screen["cursor_x"].as_u64().unwrap_or(0) as usize,
+            );
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(out.as_bytes());
+            let _ = stdout.flush();
+            last = Some(screen);
         }
-    }
-
-    // Restore keypad mode
-    let _ = tty.keypad_application_mode(false);
-}
 
-fn run_headless_offline_mode(args: &[String]) {
-    use okros::offline_mud::{parse, World};
-    use serde_json::json;
-    use std::io::{BufRead, BufReader, Write};
-    use std::os::unix::net::UnixListener;
-    use std::thread;
-
-    // Parse instance name from args
-    let inst = args
-        .iter()
-        .position(|a| a == "--instance")
-        .and_then(|i| args.get(i + 1))
-        .cloned()
-        .unwrap_or_else(|| "default".to_string());
-
-    let path = default_socket_path(&inst);
-
-    // Remove existing socket if present
-    let _ = std::fs::remove_file(&path);
-
-    // Create Unix socket listener
-    let listener = match UnixListener::bind(&path) {
-        Ok(l) => l,
-        Err(e) => {
-            eprintln!("Failed to bind socket: {}", e);
-            return;
+        let ready = poll_fds(&[(libc::STDIN_FILENO, READ)], 100).unwrap_or_default();
+        if ready.is_empty() {
+            continue;
+        }
+        let mut buf = [0u8; 1024];
+        let n = match io::stdin().read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e),
+        };
+        let keys = &buf[..n];
+        let (keys, detach) = match keys.iter().position(|&b| b == ATTACH_ESCAPE) {
+            Some(i) => (&keys[..i], true),
+            None => (keys, false),
+        };
+        if !keys.is_empty() {
+            let data = String::from_utf8_lossy(keys);
+            if let Err(e) = request(json!({"cmd":"key","data":data})) {
+                break Err(e);
+            }
+        }
+        if detach {
+            break Ok(());
         }
     };
 
-    eprintln!("Headless offline MUD; control socket at {}", path.display());
+    let _ = tty.keypad_application_mode(false);
+    let _ = tty.disable_raw();
+    let leave = if term.rmcup.is_empty() {
+        &term.clear
+    } else {
+        &term.rmcup
+    };
+    print!("{}{}", term.sgr0, leave);
+    let _ = io::stdout().flush();
+    result
+}
 
-    // Server state: World + Session
-    struct OfflineMudServer {
-        world: World,
-        session: Session<PassthroughDecomp>,
+/// How long --send/--script wait for output to stop after each line
+const BATCH_WAIT: Duration = Duration::from_millis(500);
+
+/// Command lines for --send (one line, split at ';' as typed) or --script
+/// (one per line, blank lines skipped)
+fn batch_lines(text: &str, split: bool) -> Vec<String> {
+    use okros::command_queue::{CommandQueue, EXPAND_SEMICOLON};
+    let mut queue = CommandQueue::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if split {
+            queue.add(line, EXPAND_SEMICOLON, false);
+        } else {
+            queue.add(line, okros::command_queue::EXPAND_NONE, false);
+        }
     }
+    queue.execute_all()
+}
 
-    impl OfflineMudServer {
-        fn new() -> Self {
-            let mut world = World::new();
-            let session = Session::new(PassthroughDecomp::new(), 80, 24, 2000);
-
-            // Show initial room
-            let initial = world.execute(parse("look").unwrap());
-            let mut server = Self { world, session };
-            server.session.feed(initial.as_bytes());
-            server
+/// --send/--script: run each line on a headless instance as the "command"
+/// request does (#commands included) and print the output that follows,
+/// until none arrives for `wait`. Ok(false) if any line failed
+fn run_batch_mode(path: &std::path::Path, lines: &[String], wait: Duration) -> io::Result<bool> {
+    use serde_json::{json, Value};
+
+    let stream = std::os::unix::net::UnixStream::connect(path)
+        .map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))?;
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut request = |req: Value| -> io::Result<Value> {
+        writeln!(&stream, "{}", req)?;
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::other("control socket closed"));
         }
-
-        fn handle_command(&mut self, cmd_json: &str) -> String {
-            let cmd: serde_json::Value = match serde_json::from_str(cmd_json) {
-                Ok(v) => v,
-                Err(_) => return json!({"event":"Error","message":"Invalid JSON"}).to_string(),
+        serde_json::from_str(&line).map_err(io::Error::other)
+    };
+    let colors = unsafe { libc::isatty(1) } == 1;
+    let mut next = request(json!({"cmd":"get_lines","count":0}))?["total"]
+        .as_u64()
+        .unwrap_or(0);
+    let mut ok = true;
+    for line in lines {
+        let resp = request(json!({"cmd":"command","data":line}))?;
+        if resp["event"] == "Error" {
+            eprintln!("{}: {}", line, resp["message"].as_str().unwrap_or("error"));
+            ok = false;
+        }
+        // The newest line may still be growing (a prompt, say): it's held
+        // back until a later one arrives or the output goes quiet
+        let mut quiet_since = Instant::now();
+        loop {
+            std::thread::sleep(Duration::from_millis(50));
+            let resp = request(json!({"cmd":"get_lines","from":next,"count":1000}))?;
+            let got = resp["lines"].as_array().cloned().unwrap_or_default();
+            let quiet = quiet_since.elapsed() >= wait;
+            let show = if quiet {
+                got.len()
+            } else {
+                got.len().saturating_sub(1)
             };
-
-            let cmd_type = cmd["cmd"].as_str().unwrap_or("");
-
-            match cmd_type {
-                "send" => {
-                    let data = cmd["data"].as_str().unwrap_or("");
-
-                    // Parse MUD command
-                    match parse(data.trim()) {
-                        Ok(mud_cmd) => {
-                            // Execute in World
-                            let output = self.world.execute(mud_cmd);
-
-                            // Feed to Session pipeline (ANSI → scrollback)
-                            self.session.feed(output.as_bytes());
-
-                            json!({"event":"Ok"}).to_string()
-                        }
-                        Err(e) => {
-                            // Parse error - show in session
-                            let err_msg = format!("\x1b[31m{}\x1b[0m\n", e);
-                            self.session.feed(err_msg.as_bytes());
-                            json!({"event":"Ok"}).to_string()
-                        }
-                    }
-                }
-                "get_buffer" => {
-                    // Extract scrollback as lines (headless mode has own scrollback)
-                    let viewport = self.session.scrollback_viewport().unwrap_or(&[]);
-                    let text: String = viewport.iter().map(|&a| (a & 0xFF) as u8 as char).collect();
-
-                    let lines: Vec<String> = text
-                        .lines()
-                        .map(|s| s.trim_end().to_string())
-                        .filter(|s| !s.is_empty())
-                        .collect();
-
-                    json!({"event":"Buffer","lines":lines}).to_string()
-                }
-                "status" => {
-                    let location = self.world.player.location;
-                    let inv_count = self.world.player.inventory.len();
-                    json!({
-                        "event":"Status",
-                        "location":location,
-                        "inventory_count":inv_count
-                    })
-                    .to_string()
-                }
-                _ => json!({"event":"Error","message":"Unknown command"}).to_string(),
+            for l in &got[..show] {
+                let field = if colors { "ansi" } else { "text" };
+                println!("{}", l[field].as_str().unwrap_or(""));
+                next = l["line"].as_u64().unwrap_or(next) + 1;
+            }
+            if show > 0 {
+                quiet_since = Instant::now();
+            }
+            if quiet {
+                break;
             }
         }
     }
+    if colors {
+        print!("\x1b[0m");
+    }
+    io::stdout().flush()?;
+    Ok(ok)
+}
 
-    // Create shared server state
-    use std::sync::{Arc, Mutex};
-    let server = Arc::new(Mutex::new(OfflineMudServer::new()));
-
-    // Accept connections and handle them
-    for stream in listener.incoming() {
-        match stream {
-            Ok(s) => {
-                let server_clone = server.clone();
-                thread::spawn(move || {
-                    let mut reader = BufReader::new(match s.try_clone() {
-                        Ok(s) => s,
-                        Err(_) => return,
-                    });
-                    let mut writer = s;
-                    let mut line = String::new();
-
-                    while reader.read_line(&mut line).unwrap_or(0) > 0 {
-                        let response = {
-                            let mut srv = server_clone.lock().unwrap();
-                            srv.handle_command(line.trim())
-                        };
-                        if writeln!(writer, "{}", response).is_err() {
-                            break;
-                        }
-                        line.clear();
-                    }
-                });
+/// The offline world: built in, or from `--area FILE`
+fn offline_world(args: &[String]) -> Option<okros::offline_mud::OfflineWorld> {
+    use okros::offline_mud::OfflineWorld;
+    match args.iter().position(|a| a == "--area") {
+        None => Some(OfflineWorld::new()),
+        Some(i) => match args.get(i + 1).map(|path| OfflineWorld::with_area(path)) {
+            Some(Ok(world)) => Some(world),
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                None
             }
-            Err(e) => eprintln!("control: accept error: {}", e),
-        }
+            None => {
+                eprintln!("--area needs a file name");
+                None
+            }
+        },
     }
 }
 
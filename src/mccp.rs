@@ -8,6 +8,14 @@ pub trait Decompressor {
     fn response(&mut self) -> Option<Vec<u8>> {
         None
     }
+    /// (compressed, uncompressed) byte counts once compression has started
+    fn compression_stats(&self) -> Option<(usize, usize)> {
+        None
+    }
+    /// Problem worth telling the user about (e.g. a corrupt stream), once
+    fn take_warning(&mut self) -> Option<String> {
+        None
+    }
 }
 
 pub struct PassthroughDecomp {
@@ -35,6 +43,7 @@ pub mod telopt {
     pub const WILL: u8 = 251;
     pub const DO: u8 = 253;
     pub const DONT: u8 = 254;
+    pub const WONT: u8 = 252;
     pub const SB: u8 = 250;
     pub const SE: u8 = 240;
     pub const COMPRESS: u8 = 85;
@@ -181,6 +190,15 @@ mod tests {
     }
 }
 
+/// MCCP v1/v2 decompressor
+///
+/// A stream runs from IAC SB COMPRESS[2] ... SE to the end of its zlib
+/// data, after which the bytes are plain telnet again and the server may
+/// start a new stream later. A corrupt stream is not fatal: the client
+/// asks the server to stop (DONT), reads on as plain text, refuses any
+/// further offers and leaves a warning for the user. So is one that
+/// inflates past MAX_INFLATE in a single read (a decompression bomb), or
+/// that leaves more than MAX_RESIDUAL bytes unconsumed between reads
 #[cfg(feature = "mccp")]
 pub struct MccpInflate {
     residual: Vec<u8>,
@@ -188,11 +206,21 @@ pub struct MccpInflate {
     responses: Vec<u8>,
     got_v2: bool,
     compressing: bool,
-    error: bool,
+    version: u8, // COMPRESS or COMPRESS2 of the running stream
+    error: bool, // A stream failed; compression stays off from then on
+    warning: Option<String>,
     comp: usize,
     uncomp: usize,
     dec: Option<flate2::Decompress>,
 }
+/// Output one read may inflate to before the stream is cut off
+#[cfg(feature = "mccp")]
+pub const MAX_INFLATE: usize = 1 << 20;
+
+/// Most input held back between reads (partial sequences, stalled streams)
+#[cfg(feature = "mccp")]
+pub const MAX_RESIDUAL: usize = 64 * 1024;
+
 #[cfg(feature = "mccp")]
 impl MccpInflate {
     pub fn new() -> Self {
@@ -202,7 +230,9 @@ impl MccpInflate {
             responses: Vec::new(),
             got_v2: false,
             compressing: false,
+            version: telopt::COMPRESS2,
             error: false,
+            warning: None,
             comp: 0,
             uncomp: 0,
             dec: None,
@@ -211,15 +241,34 @@ impl MccpInflate {
     pub fn stats(&self) -> (usize, usize) {
         (self.comp, self.uncomp)
     }
+
+    /// Give up on the running stream: ask the server to stop and read on as
+    /// plain text, refusing further offers
+    fn fail(&mut self, why: String) {
+        self.responses
+            .extend_from_slice(&[telopt::IAC, telopt::DONT, self.version]);
+        self.warning = Some(format!("MCCP: {}, compression turned off", why));
+        self.error = true;
+        self.compressing = false;
+        self.dec = None;
+    }
 }
 #[cfg(feature = "mccp")]
 impl Decompressor for MccpInflate {
     fn receive(&mut self, input: &[u8]) {
         use telopt::*;
         self.residual.extend_from_slice(input);
+        let out_limit = self.out.len() + MAX_INFLATE;
         let mut i = 0usize;
         while i < self.residual.len() {
             let b = self.residual[i];
+            if self.compressing && self.out.len() >= out_limit {
+                // The rest of this read is compressed data we won't inflate
+                self.out.truncate(out_limit);
+                self.fail(format!("a read inflated past {} KiB", MAX_INFLATE / 1024));
+                i = self.residual.len();
+                break;
+            }
             if !self.compressing {
                 if b != IAC {
                     self.out.push(b);
@@ -240,6 +289,12 @@ impl Decompressor for MccpInflate {
                         break;
                     }
                     let opt = self.residual[i + 2];
+                    if (opt == COMPRESS || opt == COMPRESS2) && self.error {
+                        // Broke once already: don't go round again
+                        self.responses.extend_from_slice(&[IAC, DONT, opt]);
+                        i += 3;
+                        continue;
+                    }
                     if opt == COMPRESS2 {
                         self.responses.extend_from_slice(&[IAC, DO, COMPRESS2]);
                         self.got_v2 = true;
@@ -268,7 +323,10 @@ impl Decompressor for MccpInflate {
                             && self.residual[i + 3] == IAC
                             && self.residual[i + 4] == SE)
                     {
-                        self.compressing = true;
+                        // A fresh zlib stream each time (the server may
+                        // end one and start another later)
+                        self.compressing = !self.error;
+                        self.version = opt;
                         self.dec = Some(flate2::Decompress::new(true));
                         i += 5;
                         continue;
@@ -298,16 +356,18 @@ impl Decompressor for MccpInflate {
                         i += used;
                         self.out.truncate(out_start + prod);
                         if status == flate2::Status::StreamEnd {
+                            // Clean teardown: what follows is plain telnet
                             self.compressing = false;
                             self.dec = None;
-                        }
-                        if used == 0 && prod == 0 {
+                        } else if used == 0 && prod == 0 {
                             break;
                         }
                     }
-                    Err(_) => {
-                        self.error = true;
-                        break;
+                    Err(e) => {
+                        // The rest of this read is compressed data we can't use
+                        self.out.truncate(out_start);
+                        self.fail(format!("decompression failed ({})", e));
+                        i = self.residual.len();
                     }
                 }
             }
@@ -315,9 +375,18 @@ impl Decompressor for MccpInflate {
         if i > 0 {
             self.residual.drain(0..i);
         }
+        if self.residual.len() > MAX_RESIDUAL {
+            self.residual.clear();
+            if self.compressing {
+                self.fail(format!(
+                    "stream stalled with {} KiB unread",
+                    MAX_RESIDUAL / 1024
+                ));
+            }
+        }
     }
     fn pending(&self) -> bool {
-        !self.error && !self.out.is_empty()
+        !self.out.is_empty()
     }
     fn take_output(&mut self) -> Vec<u8> {
         std::mem::take(&mut self.out)
@@ -325,6 +394,12 @@ impl Decompressor for MccpInflate {
     fn error(&self) -> bool {
         self.error
     }
+    fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+    fn compression_stats(&self) -> Option<(usize, usize)> {
+        (self.comp > 0).then(|| self.stats())
+    }
     fn response(&mut self) -> Option<Vec<u8>> {
         if self.responses.is_empty() {
             None
@@ -363,6 +438,9 @@ mod mccp_real_tests {
         }
         assert_eq!(out, b"hello");
         assert!(!d.error());
+        let (comp, uncomp) = d.compression_stats().unwrap();
+        assert_eq!(uncomp, 5);
+        assert!(comp > 0);
     }
 
     #[test]
@@ -380,13 +458,73 @@ mod mccp_real_tests {
         assert_eq!(out, b"v1");
     }
 
+    fn drain(d: &mut MccpInflate) -> Vec<u8> {
+        let mut out = Vec::new();
+        while d.pending() {
+            out.extend(d.take_output());
+        }
+        out
+    }
+
     #[test]
-    fn invalid_stream_sets_error() {
+    fn invalid_stream_falls_back_to_plain() {
         let mut d = MccpInflate::new();
         d.receive(&[IAC, WILL, COMPRESS2]);
         let _ = d.response();
+        d.receive(b"before");
         d.receive(&[IAC, SB, COMPRESS2, IAC, SE]);
         d.receive(&[0, 1, 2, 3]);
         assert!(d.error());
+        assert_eq!(d.response().unwrap(), vec![IAC, DONT, COMPRESS2]);
+        assert!(d
+            .take_warning()
+            .unwrap()
+            .starts_with("MCCP: decompression failed"));
+        assert_eq!(drain(&mut d), b"before");
+        // Output keeps flowing as plain text, and compression stays off
+        d.receive(b"after");
+        assert_eq!(drain(&mut d), b"after");
+        d.receive(&[IAC, WILL, COMPRESS2]);
+        assert_eq!(d.response().unwrap(), vec![IAC, DONT, COMPRESS2]);
+    }
+
+    #[test]
+    fn decompression_bomb_is_cut_off() {
+        let mut d = MccpInflate::new();
+        d.receive(&[IAC, WILL, COMPRESS2]);
+        let _ = d.response();
+        d.receive(&[IAC, SB, COMPRESS2, IAC, SE]);
+        d.receive(&compress_bytes(&vec![b'x'; 4 * MAX_INFLATE]));
+        let out = drain(&mut d);
+        assert_eq!(out.len(), MAX_INFLATE);
+        assert!(out.iter().all(|&b| b == b'x'));
+        assert!(d.error());
+        assert_eq!(d.response().unwrap(), vec![IAC, DONT, COMPRESS2]);
+        assert!(d.take_warning().unwrap().contains("inflated past 1024 KiB"));
+        d.receive(b"plain");
+        assert_eq!(drain(&mut d), b"plain");
+    }
+
+    #[test]
+    fn stream_end_then_restart() {
+        let mut d = MccpInflate::new();
+        d.receive(&[IAC, WILL, COMPRESS2]);
+        let _ = d.response();
+        // One read: start, whole stream, plain bytes after its end
+        let mut data = vec![IAC, SB, COMPRESS2, IAC, SE];
+        data.extend(compress_bytes(b"zipped "));
+        data.extend_from_slice(b"plain ");
+        data.extend_from_slice(&[IAC, WILL, 1]); // Passed on to the telnet parser
+        d.receive(&data);
+        assert_eq!(drain(&mut d), b"zipped plain \xff\xfb\x01");
+
+        // The server turns compression back on: a new zlib stream
+        let mut data = vec![IAC, SB, COMPRESS2, IAC, SE];
+        data.extend(compress_bytes(b"again"));
+        d.receive(&data[..7]);
+        d.receive(&data[7..]);
+        d.receive(b"!");
+        assert_eq!(drain(&mut d), b"again!");
+        assert!(!d.error());
     }
 }
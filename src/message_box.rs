@@ -0,0 +1,252 @@
+// MessageBox - modal popup with a message, OK/Cancel buttons and
+// optionally a line of text to fill in
+//
+// Used for confirmations ("Really quit with an open connection?").
+// The box is a bordered, titled child of the screen window, centered on
+// it; the front-end sends it keys until keypress() says it's answered.
+// Dropping it takes the window off the screen.
+
+use crate::input::{KeyCode, KeyEvent};
+use crate::window::{BorderStyle, Window};
+
+/// White on blue, like InputBox and the menus
+const COLOR: u8 = 0x1F;
+/// The focused button: black on cyan
+const FOCUS_COLOR: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buttons {
+    Ok,
+    OkCancel,
+}
+
+/// What a key did to the box
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoxAnswer {
+    /// Still waiting
+    Pending,
+    /// OK chosen: the text entered ("" without a text field)
+    Ok(String),
+    Cancel,
+}
+
+pub struct MessageBox {
+    win: Box<Window>,
+    title: String,
+    lines: Vec<String>,
+    buttons: Buttons,
+    cancel_focused: bool,
+    entry: Option<String>, // Text field contents
+}
+
+impl MessageBox {
+    /// A message (lines split on '\n') centered on `parent`
+    pub fn new(parent: *mut Window, title: &str, message: &str, buttons: Buttons) -> Self {
+        Self::build(parent, title, message, buttons, None)
+    }
+
+    /// A message with a text field below it, starting as `text`
+    pub fn prompt(parent: *mut Window, title: &str, message: &str, text: &str) -> Self {
+        Self::build(
+            parent,
+            title,
+            message,
+            Buttons::OkCancel,
+            Some(text.to_string()),
+        )
+    }
+
+    fn build(
+        parent: *mut Window,
+        title: &str,
+        message: &str,
+        buttons: Buttons,
+        entry: Option<String>,
+    ) -> Self {
+        let lines: Vec<String> = message.lines().map(str::to_string).collect();
+        let widest = lines.iter().map(String::len).max().unwrap_or(0);
+        let (parent_w, parent_h) = match parent.is_null() {
+            true => (80, 24),
+            false => unsafe { ((*parent).width, (*parent).height) },
+        };
+        // Border, a blank column each side; room for the title and buttons.
+        // Never bigger than the parent, however small that is
+        let width = (widest.max(title.len() + 2).max(22) + 4).min(parent_w);
+        // Border, message, [blank, field], blank, buttons, border
+        let field_rows = if entry.is_some() { 2 } else { 0 };
+        let height = (lines.len() + field_rows + 4).min(parent_h);
+        let mut win = Window::new(parent, width, height);
+        win.parent_x = (parent_w.saturating_sub(width) / 2) as isize;
+        win.parent_y = (parent_h.saturating_sub(height) / 2) as isize;
+        let mut mb = Self {
+            win,
+            title: title.to_string(),
+            lines,
+            buttons,
+            cancel_focused: false,
+            entry,
+        };
+        mb.redraw();
+        mb
+    }
+
+    pub fn redraw(&mut self) {
+        let (w, h) = (self.win.width, self.win.height);
+        self.win.set_color(COLOR);
+        self.win.clear();
+        self.win
+            .draw_border(BorderStyle::Single, Some(&self.title), COLOR);
+        let inner = w.saturating_sub(4);
+        for (i, line) in self.lines.iter().enumerate().take(h.saturating_sub(4)) {
+            self.win.gotoxy(2, 1 + i);
+            self.win.print(&line[..line.len().min(inner)]);
+        }
+        if let Some(ref text) = self.entry {
+            // The end of the text, on a black field
+            let y = 2 + self.lines.len();
+            let shown = &text[text.len().saturating_sub(inner.saturating_sub(1))..];
+            for x in 0..inner {
+                let ch = shown.as_bytes().get(x).copied().unwrap_or(b' ');
+                self.win.put_char(2 + x, y, ch, 0x07);
+            }
+            self.win.set_cursor(2 + shown.len(), y);
+        }
+        // Buttons centered on the last row inside the border
+        let labels: &[&str] = match self.buttons {
+            Buttons::Ok => &["[ OK ]"],
+            Buttons::OkCancel => &["[ OK ]", "[ Cancel ]"],
+        };
+        let total = labels.iter().map(|l| l.len()).sum::<usize>() + 2 * (labels.len() - 1);
+        let mut x = w.saturating_sub(total) / 2;
+        for (i, label) in labels.iter().enumerate() {
+            let focused = (i == 1) == self.cancel_focused;
+            let color = if focused { FOCUS_COLOR } else { COLOR };
+            for (j, b) in label.bytes().enumerate() {
+                self.win.put_char(x + j, h.saturating_sub(2), b, color);
+            }
+            x += label.len() + 2;
+        }
+        self.win.dirty = true;
+    }
+
+    fn answer(&self, ok: bool) -> BoxAnswer {
+        match ok {
+            true => BoxAnswer::Ok(self.entry.clone().unwrap_or_default()),
+            false => BoxAnswer::Cancel,
+        }
+    }
+
+    /// Tab/arrows move between the buttons, Enter picks the focused one,
+    /// Escape cancels; without a text field y/n answer too
+    pub fn keypress(&mut self, key: KeyEvent) -> BoxAnswer {
+        let two = self.buttons == Buttons::OkCancel;
+        match key {
+            KeyEvent::Key(KeyCode::Escape) => return BoxAnswer::Cancel,
+            KeyEvent::Byte(b'\n' | b'\r') => return self.answer(!self.cancel_focused),
+            KeyEvent::Byte(b'\t') | KeyEvent::Key(KeyCode::ArrowLeft | KeyCode::ArrowRight)
+                if two =>
+            {
+                self.cancel_focused = !self.cancel_focused;
+            }
+            KeyEvent::Byte(c) => match self.entry {
+                Some(ref mut text) => match c {
+                    0x7F | 0x08 => {
+                        text.pop();
+                    }
+                    0x15 => text.clear(), // Ctrl-U
+                    0x20..=0x7E => text.push(c as char),
+                    _ => {}
+                },
+                None => match c.to_ascii_lowercase() {
+                    b'y' => return self.answer(true),
+                    b'n' if two => return BoxAnswer::Cancel,
+                    _ => {}
+                },
+            },
+            _ => {}
+        }
+        self.redraw();
+        BoxAnswer::Pending
+    }
+
+    pub fn window(&mut self) -> &mut Window {
+        self.win.as_mut()
+    }
+}
+
+impl Drop for MessageBox {
+    fn drop(&mut self) {
+        self.win.die();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn row(win: &Window, y: usize) -> String {
+        win.canvas[y * win.width..(y + 1) * win.width]
+            .iter()
+            .map(|a| (a & 0xFF) as u8 as char)
+            .collect()
+    }
+
+    #[test]
+    fn confirm_box_answers() {
+        let mut root = Window::new(ptr::null_mut(), 80, 24);
+        let parent: *mut Window = root.as_mut();
+        let mut mb = MessageBox::new(
+            parent,
+            "Quit",
+            "Really quit with an open connection?",
+            Buttons::OkCancel,
+        );
+        assert!(!root.child_first.is_null());
+        let win = mb.window();
+        assert_eq!((win.width, win.height), (40, 5));
+        assert_eq!((win.parent_x, win.parent_y), (20, 9));
+        assert!(row(win, 0).contains(" Quit "));
+        assert!(row(win, 1).contains("Really quit"));
+        assert!(row(win, 3).contains("[ OK ]  [ Cancel ]"));
+
+        assert_eq!(mb.keypress(KeyEvent::Byte(b'\t')), BoxAnswer::Pending);
+        assert_eq!(mb.keypress(KeyEvent::Byte(b'\r')), BoxAnswer::Cancel);
+        assert_eq!(
+            mb.keypress(KeyEvent::Byte(b'y')),
+            BoxAnswer::Ok(String::new())
+        );
+        assert_eq!(
+            mb.keypress(KeyEvent::Key(KeyCode::Escape)),
+            BoxAnswer::Cancel
+        );
+        drop(mb);
+        assert!(root.child_first.is_null());
+    }
+
+    #[test]
+    fn box_fits_a_tiny_parent() {
+        for (w, h) in [(5, 3), (1, 1), (0, 0)] {
+            let mut root = Window::new(ptr::null_mut(), w, h);
+            let mut mb = MessageBox::prompt(root.as_mut(), "Save", "File name:", "log");
+            let win = mb.window();
+            assert_eq!((win.width, win.height), (w, h));
+            assert_eq!((win.parent_x, win.parent_y), (0, 0));
+            assert_eq!(mb.keypress(KeyEvent::Byte(b'x')), BoxAnswer::Pending);
+        }
+    }
+
+    #[test]
+    fn prompt_box_edits_text() {
+        let mut root = Window::new(ptr::null_mut(), 80, 24);
+        let mut mb = MessageBox::prompt(root.as_mut(), "Save", "File name:", "log");
+        for key in [b'x', 0x7F, b'.', b't', b'x', b't', b'n'] {
+            assert_eq!(mb.keypress(KeyEvent::Byte(key)), BoxAnswer::Pending);
+        }
+        assert!(row(mb.window(), 3).contains("log.txtn"));
+        assert_eq!(
+            mb.keypress(KeyEvent::Byte(b'\n')),
+            BoxAnswer::Ok("log.txtn".to_string())
+        );
+    }
+}
@@ -0,0 +1,254 @@
+// MSP (MUD Sound Protocol) - inline sound and music triggers
+//
+// After negotiating TELOPT_MSP the MUD embeds "!!SOUND(file V=.. L=.. P=..
+// T=.. U=..)" and "!!MUSIC(file V=.. L=.. C=.. T=.. U=..)" in its text.
+// Session strips them from the display and queues them; MspPlayer hands
+// them to an external player command (nothing plays without one).
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Telnet option for MSP
+pub const TELOPT_MSP: u8 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MspKind {
+    Sound,
+    Music,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MspTrigger {
+    pub kind: MspKind,
+    pub file: String,  // Relative to the sounds dir; "Off" stops playback
+    pub volume: u32,   // V=0..100
+    pub repeats: i32,  // L=, -1 loops forever
+    pub priority: u32, // P= (sounds only)
+    pub sound_type: Option<String>, // T= subdirectory hint
+    pub url: Option<String>, // U= download location
+}
+
+impl MspTrigger {
+    /// Parse the text between the parentheses: "file V=80 L=2 ..."
+    pub fn parse(kind: MspKind, args: &str) -> Option<Self> {
+        let mut words = args.split_whitespace();
+        let file = words.next()?.to_string();
+        let mut t = Self {
+            kind,
+            file,
+            volume: 100,
+            repeats: 1,
+            priority: 50,
+            sound_type: None,
+            url: None,
+        };
+        for word in words {
+            let Some((key, value)) = word.split_once('=') else {
+                continue;
+            };
+            match key.to_ascii_uppercase().as_str() {
+                "V" => t.volume = value.parse::<u32>().map_or(t.volume, |v| v.min(100)),
+                "L" => t.repeats = value.parse().unwrap_or(t.repeats),
+                "P" => t.priority = value.parse().unwrap_or(t.priority),
+                "T" => t.sound_type = Some(value.to_string()),
+                "U" => t.url = Some(value.to_string()),
+                _ => {} // C= (music continue) and unknown keys
+            }
+        }
+        Some(t)
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.file.eq_ignore_ascii_case("off")
+    }
+}
+
+/// Find MSP triggers in a line: the byte range each occupies plus the trigger
+/// An unterminated "!!SOUND(" is left alone
+pub fn find_triggers(text: &[u8]) -> Vec<(std::ops::Range<usize>, MspTrigger)> {
+    const TAGS: [(&[u8], MspKind); 2] =
+        [(b"!!SOUND(", MspKind::Sound), (b"!!MUSIC(", MspKind::Music)];
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let tag = TAGS.iter().find(|(tag, _)| {
+            text[i..].len() >= tag.len() && text[i..i + tag.len()].eq_ignore_ascii_case(tag)
+        });
+        let Some(&(tag, kind)) = tag else {
+            i += 1;
+            continue;
+        };
+        let start = i + tag.len();
+        let Some(close) = text[start..].iter().position(|&b| b == b')') else {
+            break;
+        };
+        let end = start + close + 1;
+        let args = String::from_utf8_lossy(&text[start..start + close]);
+        if let Some(t) = MspTrigger::parse(kind, &args) {
+            found.push((i..end, t));
+        }
+        i = end;
+    }
+    found
+}
+
+/// Runs triggers through an external command, one sound and one music
+/// process at a time (a new one replaces the old)
+pub struct MspPlayer {
+    command: String, // %f = file path, %v = volume, %l = repeats
+    dir: PathBuf,
+    sound: Option<Child>,
+    music: Option<Child>,
+}
+
+impl MspPlayer {
+    /// `command` is a shell template such as "paplay --volume=%v %f";
+    /// empty ignores all triggers
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            dir: Self::sounds_dir(),
+            sound: None,
+            music: None,
+        }
+    }
+
+    /// Sound files: ~/.okros/sounds
+    pub fn sounds_dir() -> PathBuf {
+        crate::config::Config::default_path().with_file_name("sounds")
+    }
+
+    pub fn set_command(&mut self, command: impl Into<String>) {
+        self.command = command.into();
+    }
+
+    pub fn play(&mut self, t: &MspTrigger) -> io::Result<()> {
+        let slot = match t.kind {
+            MspKind::Sound => &mut self.sound,
+            MspKind::Music => &mut self.music,
+        };
+        if let Some(mut child) = slot.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if self.command.is_empty() || t.is_off() {
+            return Ok(());
+        }
+        // The file name comes from the server: keep it inside the sounds dir
+        let rel = Path::new(&t.file);
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("bad sound file name: {}", t.file),
+            ));
+        }
+        let cmd = expand_command(&self.command, &self.dir.join(rel), t);
+        *slot = Some(spawn_quiet(&cmd)?);
+        Ok(())
+    }
+
+    /// Kill any sound or music still playing
+    pub fn stop(&mut self) {
+        for mut child in [self.sound.take(), self.music.take()].into_iter().flatten() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for MspPlayer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Fill in %f (shell-quoted path), %v and %l
+pub fn expand_command(template: &str, path: &Path, t: &MspTrigger) -> String {
+    template
+        .replace("%f", &shell_quote(&path.to_string_lossy()))
+        .replace("%v", &t.volume.to_string())
+        .replace("%l", &t.repeats.to_string())
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Run a shell command detached from the terminal (no stdio, so it can't
+/// draw over the screen)
+pub fn spawn_quiet(cmd: &str) -> io::Result<Child> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sound_and_music_triggers() {
+        let line = b"You hear thunder. !!SOUND(weather/thunder.wav V=80 L=2 P=70 T=weather) Wow !!music(theme.mid C=1)";
+        let found = find_triggers(line);
+        assert_eq!(found.len(), 2);
+
+        let (range, sound) = &found[0];
+        assert_eq!(
+            &line[range.clone()],
+            b"!!SOUND(weather/thunder.wav V=80 L=2 P=70 T=weather)"
+        );
+        assert_eq!(sound.kind, MspKind::Sound);
+        assert_eq!(sound.file, "weather/thunder.wav");
+        assert_eq!((sound.volume, sound.repeats, sound.priority), (80, 2, 70));
+        assert_eq!(sound.sound_type.as_deref(), Some("weather"));
+
+        let (_, music) = &found[1];
+        assert_eq!(music.kind, MspKind::Music);
+        assert_eq!((music.file.as_str(), music.volume), ("theme.mid", 100));
+    }
+
+    #[test]
+    fn unterminated_trigger_is_left_alone() {
+        assert!(find_triggers(b"!!SOUND(oops").is_empty());
+        assert!(find_triggers(b"plain text").is_empty());
+    }
+
+    #[test]
+    fn expands_command_with_quoted_path() {
+        let t = MspTrigger::parse(MspKind::Sound, "it's.wav V=30").unwrap();
+        let cmd = expand_command("play -v %v %f", Path::new("/snd/it's.wav"), &t);
+        assert_eq!(cmd, "play -v 30 '/snd/it'\\''s.wav'");
+    }
+
+    #[test]
+    fn player_rejects_paths_outside_sounds_dir() {
+        let mut player = MspPlayer::new("true");
+        let t = MspTrigger::parse(MspKind::Sound, "../../etc/passwd").unwrap();
+        assert!(player.play(&t).is_err());
+        let t = MspTrigger::parse(MspKind::Sound, "/etc/passwd").unwrap();
+        assert!(player.play(&t).is_err());
+        // No command: triggers are ignored
+        let mut silent = MspPlayer::new("");
+        assert!(silent.play(&t).is_ok());
+    }
+
+    #[test]
+    fn player_runs_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("played");
+        let mut player = MspPlayer::new(format!("echo %f %v > '{}'", marker.display()));
+        player.dir = PathBuf::from("/snd");
+        let t = MspTrigger::parse(MspKind::Sound, "bell.wav V=40").unwrap();
+        player.play(&t).unwrap();
+        player.sound.take().unwrap().wait().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&marker).unwrap(),
+            "/snd/bell.wav 40\n"
+        );
+    }
+}
@@ -1,10 +1,16 @@
 use crate::action::Action;
 use crate::alias::Alias;
+use crate::bell::BellMode;
+use crate::charset::Charset;
+use crate::color::ColorTheme;
 use crate::config::Config;
+use crate::keepalive::Keepalive;
+use crate::keybind::KeyAction;
 use crate::macro_def::Macro;
+use crate::path::Path;
 use crate::socket::{ConnState, Socket};
+use std::collections::{BTreeMap, BTreeSet};
 use std::io;
-use std::net::Ipv4Addr;
 
 /// MUD definition - can be saved/loaded from config file
 /// May or may not have an active socket connection
@@ -14,11 +20,24 @@ pub struct Mud {
     pub hostname: String,
     pub port: u16,
     pub commands: String, // Auto-execute commands on connect
+    pub user: String,     // Sent at the login name prompt (auto-login)
+    pub password: String, // Sent at the password prompt (auto-login)
     pub comment: String,
     pub inherits: Option<Box<Mud>>, // Parent MUD for inheritance
     pub alias_list: Vec<Alias>,
     pub action_list: Vec<Action>,
     pub macro_list: Vec<Macro>,
+    pub path_list: Vec<Path>,                       // #path walks
+    pub colors: ColorTheme,                         // UI colors set for this MUD (see theme())
+    pub disabled_groups: BTreeSet<String>,          // #group ... off
+    pub keepalive: Option<Keepalive>,               // Sent when the connection is quiet
+    pub scripts: Vec<String>,                       // Script files loaded when connecting
+    pub scrollback: Option<usize>,                  // Scrollback rows while connected here
+    pub histsize: Option<usize>,                    // Input history lines kept for this MUD
+    pub charset: Option<Charset>,                   // Text encoding the MUD uses
+    pub bell: Option<BellMode>,                     // What an incoming BEL does
+    pub logout: Option<String>,                     // Sent by #close before hanging up
+    pub bindings: BTreeMap<i32, Option<KeyAction>>, // bind/unbind over the global keys
     // Runtime state (not saved to config, not cloned)
     pub sock: Option<Socket>,
     pub state: ConnState,
@@ -33,11 +52,24 @@ impl Clone for Mud {
             hostname: self.hostname.clone(),
             port: self.port,
             commands: self.commands.clone(),
+            user: self.user.clone(),
+            password: self.password.clone(),
             comment: self.comment.clone(),
             inherits: self.inherits.clone(),
             alias_list: self.alias_list.clone(),
             action_list: self.action_list.clone(),
             macro_list: self.macro_list.clone(),
+            path_list: self.path_list.clone(),
+            colors: self.colors,
+            disabled_groups: self.disabled_groups.clone(),
+            keepalive: self.keepalive.clone(),
+            scripts: self.scripts.clone(),
+            scrollback: self.scrollback,
+            histsize: self.histsize,
+            charset: self.charset,
+            bell: self.bell,
+            logout: self.logout.clone(),
+            bindings: self.bindings.clone(),
             sock: None,
             state: ConnState::Idle,
             loaded: false,
@@ -53,11 +85,24 @@ impl Mud {
             hostname: hostname.to_string(),
             port,
             commands: String::new(),
+            user: String::new(),
+            password: String::new(),
             comment: String::new(),
             inherits: None,
             alias_list: Vec::new(),
             action_list: Vec::new(),
             macro_list: Vec::new(),
+            path_list: Vec::new(),
+            colors: ColorTheme::default(),
+            disabled_groups: BTreeSet::new(),
+            keepalive: None,
+            scripts: Vec::new(),
+            scrollback: None,
+            histsize: None,
+            charset: None,
+            bell: None,
+            logout: None,
+            bindings: BTreeMap::new(),
             sock: None,
             state: ConnState::Idle,
             loaded: false,
@@ -76,10 +121,31 @@ impl Mud {
         mud
     }
 
+    /// Is a #group (None: no group) switched on?
+    pub fn group_enabled(&self, group: Option<&str>) -> bool {
+        group.is_none_or(|g| !self.disabled_groups.contains(g))
+    }
+
+    /// Switch a #group on or off, here and in the parent MUDs
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        if enabled {
+            self.disabled_groups.remove(group);
+        } else {
+            self.disabled_groups.insert(group.to_string());
+        }
+        if let Some(ref mut parent) = self.inherits {
+            parent.set_group_enabled(group, enabled);
+        }
+    }
+
     /// Find alias by name (with inheritance)
     pub fn find_alias(&self, name: &str) -> Option<&Alias> {
         // Check own list first
-        if let Some(alias) = self.alias_list.iter().find(|a| a.name == name) {
+        if let Some(alias) = self
+            .alias_list
+            .iter()
+            .find(|a| a.name == name && self.group_enabled(a.group.as_deref()))
+        {
             return Some(alias);
         }
         // Check parent MUD if not found
@@ -92,7 +158,11 @@ impl Mud {
     /// Find macro by key code (with inheritance)
     pub fn find_macro(&self, key: i32) -> Option<&Macro> {
         // Check own list first
-        if let Some(macro_) = self.macro_list.iter().find(|m| m.key == key) {
+        if let Some(macro_) = self
+            .macro_list
+            .iter()
+            .find(|m| m.key == key && self.group_enabled(m.group.as_deref()))
+        {
             return Some(macro_);
         }
         // Check parent MUD if not found
@@ -102,6 +172,23 @@ impl Mud {
         None
     }
 
+    /// Binding for a key code: Some(None) if this MUD (or the nearest parent
+    /// that mentions the key) unbinds it, None to fall back to the global keys
+    pub fn find_binding(&self, key: i32) -> Option<Option<&KeyAction>> {
+        match self.bindings.get(&key) {
+            Some(action) => Some(action.as_ref()),
+            None => self.inherits.as_ref()?.find_binding(key),
+        }
+    }
+
+    /// Find a #path walk by name (with inheritance)
+    pub fn find_path(&self, name: &str) -> Option<&Path> {
+        match self.path_list.iter().find(|p| p.name == name) {
+            Some(path) => Some(path),
+            None => self.inherits.as_ref()?.find_path(name),
+        }
+    }
+
     /// Check all actions for trigger matches (C++ Session.cc:640 triggerCheck)
     /// Returns vector of command strings to execute for matching triggers
     pub fn check_action_match(
@@ -109,24 +196,53 @@ impl Mud {
         text: &str,
         interp: &mut dyn crate::plugins::stack::Interpreter,
     ) -> Vec<String> {
-        use crate::action::ActionType;
+        self.matching_commands(crate::action::ActionType::Trigger, text, interp)
+    }
 
-        let mut commands = Vec::new();
+    /// Commands of the `#action -prompt` triggers matching a GA/EOR prompt
+    pub fn check_prompt_match(
+        &self,
+        prompt: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+    ) -> Vec<String> {
+        self.matching_commands(crate::action::ActionType::Prompt, prompt, interp)
+    }
 
-        // Check own actions first
-        for action in &self.action_list {
-            if action.action_type == ActionType::Trigger {
-                if let Some(cmd) = action.check_match(text, interp) {
-                    commands.push(cmd);
+    /// Own actions, then the inherited ones no nearer MUD redefines (same
+    /// type and pattern), each with its depth in the chain and owning MUD
+    fn action_chain(&self) -> Vec<(usize, &Mud, &Action)> {
+        let mut chain: Vec<(usize, &Mud, &Action)> = Vec::new();
+        let mut level = Some(self);
+        let mut depth = 0;
+        while let Some(mud) = level {
+            for action in &mud.action_list {
+                let overridden = chain.iter().any(|(d, _, a)| {
+                    *d < depth && a.action_type == action.action_type && a.pattern == action.pattern
+                });
+                if !overridden {
+                    chain.push((depth, mud, action));
                 }
             }
+            level = mud.inherits.as_deref();
+            depth += 1;
         }
+        chain
+    }
 
-        // Check parent MUD actions
-        if let Some(ref parent) = self.inherits {
-            commands.extend(parent.check_action_match(text, interp));
+    fn matching_commands(
+        &self,
+        action_type: crate::action::ActionType,
+        text: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+    ) -> Vec<String> {
+        let mut commands = Vec::new();
+        for (_, owner, action) in self.action_chain() {
+            if action.action_type == action_type && owner.group_enabled(action.group.as_deref()) {
+                if let Some(cmd) = action.check_match(text, interp) {
+                    commands.push(cmd);
+                }
+            }
         }
-
         commands
     }
 
@@ -138,30 +254,52 @@ impl Mud {
         interp: &mut dyn crate::plugins::stack::Interpreter,
     ) -> Option<String> {
         use crate::action::ActionType;
+        self.replace_with(text, interp, &[ActionType::Replacement, ActionType::Gag])
+    }
+
+    /// check_replacement() with only the #subst actions
+    pub fn check_substitution(
+        &self,
+        text: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+    ) -> Option<String> {
+        self.replace_with(text, interp, &[crate::action::ActionType::Replacement])
+    }
 
+    /// Does a #gag action match?
+    pub fn check_gag(
+        &self,
+        text: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+    ) -> bool {
+        let gag = [crate::action::ActionType::Gag];
+        self.replace_with(text, interp, &gag)
+            .is_some_and(|t| t.is_empty())
+    }
+
+    fn replace_with(
+        &self,
+        text: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+        types: &[crate::action::ActionType],
+    ) -> Option<String> {
         let mut current = text.to_string();
         let mut modified = false;
 
-        // Check own replacements first
-        for action in &self.action_list {
-            if action.action_type == ActionType::Replacement
-                || action.action_type == ActionType::Gag
-            {
+        // Own replacements first, then inherited ones
+        for (_, owner, action) in self.action_chain() {
+            if types.contains(&action.action_type) && owner.group_enabled(action.group.as_deref()) {
                 if let Some(replaced) = action.check_replacement(&current, interp) {
+                    // Gagged line - nothing left for later replacements to work on
+                    if replaced.is_empty() {
+                        return Some(replaced);
+                    }
                     current = replaced;
                     modified = true;
                 }
             }
         }
 
-        // Check parent MUD replacements
-        if let Some(ref parent) = self.inherits {
-            if let Some(replaced) = parent.check_replacement(&current, interp) {
-                current = replaced;
-                modified = true;
-            }
-        }
-
         if modified {
             Some(current)
         } else {
@@ -169,6 +307,174 @@ impl Mud {
         }
     }
 
+    /// Collect highlight spans for text from this MUD's and parent MUDs' actions
+    /// Returns (start, end, color) byte ranges; later entries take precedence
+    pub fn check_highlight(
+        &self,
+        text: &str,
+        interp: &mut dyn crate::plugins::stack::Interpreter,
+    ) -> Vec<(usize, usize, u8)> {
+        // Parents first so this MUD's own highlights paint over inherited ones
+        let mut chain = self.action_chain();
+        chain.sort_by_key(|(depth, _, _)| std::cmp::Reverse(*depth));
+        let mut spans = Vec::new();
+        for (_, owner, action) in chain {
+            if owner.group_enabled(action.group.as_deref()) {
+                spans.extend(action.check_highlight(text, interp));
+            }
+        }
+        spans
+    }
+
+    /// Merge another definition of the same MUD into this one (autosave over
+    /// config): connection details that are set win, automation entries replace
+    /// same-named ones and new ones are appended
+    pub fn merge(&mut self, other: &Mud) {
+        if !other.hostname.is_empty() {
+            self.hostname = other.hostname.clone();
+            self.port = other.port;
+        }
+        for (dst, src) in [
+            (&mut self.commands, &other.commands),
+            (&mut self.user, &other.user),
+            (&mut self.password, &other.password),
+            (&mut self.comment, &other.comment),
+        ] {
+            if !src.is_empty() {
+                *dst = src.clone();
+            }
+        }
+        if other.inherits.is_some() {
+            self.inherits = other.inherits.clone();
+        }
+        if other.keepalive.is_some() {
+            self.keepalive = other.keepalive.clone();
+        }
+        if other.scrollback.is_some() {
+            self.scrollback = other.scrollback;
+        }
+        if other.histsize.is_some() {
+            self.histsize = other.histsize;
+        }
+        if other.charset.is_some() {
+            self.charset = other.charset;
+        }
+        if other.bell.is_some() {
+            self.bell = other.bell;
+        }
+        if other.logout.is_some() {
+            self.logout = other.logout.clone();
+        }
+        self.bindings
+            .extend(other.bindings.iter().map(|(k, a)| (*k, a.clone())));
+        self.colors = self.colors.overlay(&other.colors);
+        for alias in &other.alias_list {
+            self.alias_list.retain(|a| a.name != alias.name);
+            self.alias_list.push(alias.clone());
+        }
+        for action in &other.action_list {
+            self.action_list
+                .retain(|a| a.action_type != action.action_type || a.pattern != action.pattern);
+            self.action_list.push(action.clone());
+        }
+        for macro_ in &other.macro_list {
+            self.macro_list.retain(|m| m.key != macro_.key);
+            self.macro_list.push(macro_.clone());
+        }
+        for path in &other.path_list {
+            self.path_list.retain(|p| p.name != path.name);
+            self.path_list.push(path.clone());
+        }
+        self.disabled_groups
+            .extend(other.disabled_groups.iter().cloned());
+        for script in &other.scripts {
+            if !self.scripts.contains(script) {
+                self.scripts.push(script.clone());
+            }
+        }
+    }
+
+    /// Make `global` the last parent in the inheritance chain, so global
+    /// aliases/actions/macros apply after this MUD's own (C++ globalMUD);
+    /// an older copy of the global scope already there is replaced
+    pub fn inherit_globals(&mut self, global: &Mud) {
+        match self.inherits {
+            Some(ref parent) if parent.name == global.name => {
+                self.inherits = Some(Box::new(global.clone()))
+            }
+            Some(ref mut parent) => parent.inherit_globals(global),
+            None => self.inherits = Some(Box::new(global.clone())),
+        }
+    }
+
+    /// Colors in effect: this MUD's over its parents' (globals included)
+    pub fn theme(&self) -> ColorTheme {
+        match self.inherits {
+            Some(ref parent) => parent.theme().overlay(&self.colors),
+            None => self.colors,
+        }
+    }
+
+    /// Scrollback rows set for this MUD or the nearest parent that sets it
+    pub fn scrollback_lines(&self) -> Option<usize> {
+        self.scrollback
+            .or_else(|| self.inherits.as_ref()?.scrollback_lines())
+    }
+
+    /// Input history size set for this MUD or the nearest parent that sets it
+    pub fn history_size(&self) -> Option<usize> {
+        self.histsize
+            .or_else(|| self.inherits.as_ref()?.history_size())
+    }
+
+    /// Charset set for this MUD or the nearest parent (UTF-8 if none is)
+    pub fn charset(&self) -> Charset {
+        self.charset
+            .or_else(|| Some(self.inherits.as_ref()?.charset()))
+            .unwrap_or_default()
+    }
+
+    /// Bell mode set for this MUD or the nearest parent (beep if none is)
+    pub fn bell(&self) -> BellMode {
+        self.bell
+            .or_else(|| Some(self.inherits.as_ref()?.bell()))
+            .unwrap_or_default()
+    }
+
+    /// Command #close sends before hanging up (own over inherited)
+    pub fn logout_command(&self) -> Option<&str> {
+        match self.logout {
+            Some(ref command) => Some(command),
+            None => self.inherits.as_ref()?.logout_command(),
+        }
+    }
+
+    /// Script files for this MUD in load order: parents' first, so this
+    /// MUD's own scripts can override what they define
+    pub fn script_chain(&self) -> Vec<String> {
+        let mut chain = match self.inherits {
+            Some(ref parent) => parent.script_chain(),
+            None => Vec::new(),
+        };
+        for script in &self.scripts {
+            if !chain.contains(script) {
+                chain.push(script.clone());
+            }
+        }
+        chain
+    }
+
+    /// Compile every action (own and inherited) with the given interpreter
+    /// Actions loaded from a config file start out uncompiled
+    pub fn compile_actions(&mut self, interp: &mut dyn crate::plugins::stack::Interpreter) {
+        for action in &mut self.action_list {
+            action.compile(interp);
+        }
+        if let Some(ref mut parent) = self.inherits {
+            parent.compile_actions(interp);
+        }
+    }
+
     /// Connect to this MUD's hostname/port
     pub fn connect(&mut self) -> io::Result<()> {
         if self.hostname.is_empty() || self.port == 0 {
@@ -177,22 +483,17 @@ impl Mud {
                 "MUD has no hostname/port",
             ));
         }
-        // Parse hostname as IPv4 for now (DNS resolution not yet implemented here)
-        let ip: Ipv4Addr = self
-            .hostname
-            .parse()
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid IPv4 address"))?;
-
+        let addrs = crate::socket::resolve(&self.hostname, self.port)?;
         let mut s = Socket::new()?;
-        let _ = s.connect_ipv4(ip, self.port);
+        let _ = s.connect_any(addrs);
         self.state = s.state;
         self.sock = Some(s);
         Ok(())
     }
     pub fn connect_from_config(&mut self, cfg: &Config) -> io::Result<()> {
-        if let Some((ip, port)) = &cfg.server {
+        if let Some(addr) = cfg.server {
             let mut s = Socket::new()?;
-            let _ = s.connect_ipv4(*ip, *port);
+            let _ = s.connect(addr);
             self.state = s.state;
             self.sock = Some(s);
             Ok(())
@@ -228,6 +529,12 @@ impl MudList {
         self.muds.iter_mut().find(|m| m.name == name)
     }
 
+    /// Remove a MUD by name
+    pub fn remove(&mut self, name: &str) -> Option<Mud> {
+        let i = self.muds.iter().position(|m| m.name == name)?;
+        Some(self.muds.remove(i))
+    }
+
     /// Get MUD by index
     pub fn get(&self, index: usize) -> Option<&Mud> {
         self.muds.get(index)
@@ -247,14 +554,14 @@ impl MudList {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::net::TcpListener;
+    use std::net::{Ipv4Addr, TcpListener};
 
     #[test]
     fn connect_loopback_from_config() {
         let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
         let port = listener.local_addr().unwrap().port();
         let mut cfg = Config::new();
-        cfg.server = Some((Ipv4Addr::LOCALHOST, port));
+        cfg.server = Some((Ipv4Addr::LOCALHOST, port).into());
         let mut m = Mud::empty();
         m.connect_from_config(&cfg).unwrap();
         assert!(matches!(
@@ -305,6 +612,145 @@ mod tests {
         assert!(child.find_alias("x").is_none());
     }
 
+    #[test]
+    fn mud_merge_replaces_and_appends() {
+        use crate::action::{Action, ActionType};
+        use crate::alias::Alias;
+
+        let mut base = Mud::new("Test", "127.0.0.1", 4000);
+        base.commands = "look".to_string();
+        base.alias_list.push(Alias::new("k", "kill"));
+        base.alias_list.push(Alias::new("e", "east"));
+        base.action_list
+            .push(Action::new("^hungry", "eat bread", ActionType::Trigger));
+
+        let mut saved = Mud::new("Test", "", 0);
+        saved.alias_list.push(Alias::new("k", "kick"));
+        saved.alias_list.push(Alias::new("w", "west"));
+        saved
+            .action_list
+            .push(Action::new("^hungry", "eat apple", ActionType::Trigger));
+        saved
+            .action_list
+            .push(Action::new("^hungry", "", ActionType::Gag));
+
+        base.merge(&saved);
+        assert_eq!(base.hostname, "127.0.0.1");
+        assert_eq!(base.port, 4000);
+        assert_eq!(base.commands, "look");
+        assert_eq!(base.find_alias("k").unwrap().text, "kick");
+        assert_eq!(base.find_alias("e").unwrap().text, "east");
+        assert_eq!(base.find_alias("w").unwrap().text, "west");
+        assert_eq!(base.action_list.len(), 2);
+        assert_eq!(base.action_list[0].commands, "eat apple");
+    }
+
+    #[test]
+    fn mud_inherit_globals_goes_last() {
+        let mut global = Mud::new("__global__", "", 0);
+        global
+            .alias_list
+            .push(crate::alias::Alias::new("g", "global"));
+        let parent = Mud::new("Parent", "127.0.0.1", 4000);
+        let mut child = Mud::with_inherits("Child", "127.0.0.1", 5000, Some(parent));
+
+        child.inherit_globals(&global);
+        assert_eq!(child.inherits.as_ref().unwrap().name, "Parent");
+        assert_eq!(child.find_alias("g").unwrap().text, "global");
+
+        // A changed global scope replaces the old copy instead of stacking
+        global.alias_list[0] = crate::alias::Alias::new("g", "changed");
+        child.inherit_globals(&global);
+        assert_eq!(child.find_alias("g").unwrap().text, "changed");
+        let parent = child.inherits.as_ref().unwrap();
+        assert!(parent.inherits.as_ref().unwrap().inherits.is_none());
+    }
+
+    #[test]
+    fn mud_script_chain_parents_first() {
+        let mut parent = Mud::new("Parent", "127.0.0.1", 4000);
+        parent.scripts = vec!["common.py".to_string(), "shared.py".to_string()];
+        let mut child = Mud::with_inherits("Child", "127.0.0.1", 5000, Some(parent));
+        child.scripts = vec!["child.py".to_string(), "shared.py".to_string()];
+        assert_eq!(
+            child.script_chain(),
+            vec!["common.py", "shared.py", "child.py"]
+        );
+    }
+
+    #[test]
+    fn mud_prompt_triggers_only_match_prompts() {
+        use crate::action::{Action, ActionType};
+        let mut interp = crate::plugins::native_regex::NativeRegex::new();
+        let mut parent = Mud::new("Parent", "", 0);
+        parent
+            .action_list
+            .push(Action::parse("-prompt \"^<\" sleep", ActionType::Trigger).unwrap());
+        let mut mud = Mud::with_inherits("Child", "", 0, Some(parent));
+        mud.action_list
+            .push(Action::parse("\"^<\" wake", ActionType::Trigger).unwrap());
+        mud.compile_actions(&mut interp);
+        assert_eq!(
+            mud.check_prompt_match("<100hp>", &mut interp),
+            vec!["sleep"]
+        );
+        assert_eq!(mud.check_action_match("<100hp>", &mut interp), vec!["wake"]);
+    }
+
+    #[test]
+    fn mud_child_actions_override_parent() {
+        use crate::action::{Action, ActionType};
+        let mut interp = crate::plugins::native_regex::NativeRegex::new();
+        let mut grandparent = Mud::new("Base", "", 0);
+        grandparent
+            .action_list
+            .push(Action::parse("\"^You are hungry\" eat", ActionType::Trigger).unwrap());
+        grandparent
+            .action_list
+            .push(Action::parse("\"^Dawn\" wake", ActionType::Trigger).unwrap());
+        let parent = Mud::with_inherits("Parent", "", 0, Some(grandparent));
+        let mut child = Mud::with_inherits("Child", "", 0, Some(parent));
+        child
+            .action_list
+            .push(Action::parse("\"^You are hungry\" eat bread", ActionType::Trigger).unwrap());
+        child.compile_actions(&mut interp);
+
+        assert_eq!(
+            child.check_action_match("You are hungry.", &mut interp),
+            vec!["eat bread"]
+        );
+        assert_eq!(
+            child.check_action_match("Dawn breaks.", &mut interp),
+            vec!["wake"]
+        );
+        let base = child.inherits.as_ref().unwrap().inherits.as_ref().unwrap();
+        assert_eq!(
+            base.check_action_match("You are hungry.", &mut interp),
+            vec!["eat"]
+        );
+    }
+
+    #[test]
+    fn mud_bindings_inherit_and_override() {
+        use crate::macro_def::KEY_F0;
+        let mut parent = Mud::new("Parent", "", 0);
+        parent
+            .bindings
+            .insert(KEY_F0 + 2, Some(KeyAction::parse("score")));
+        parent
+            .bindings
+            .insert(KEY_F0 + 3, Some(KeyAction::parse("inventory")));
+        let mut child = Mud::with_inherits("Child", "", 0, Some(parent));
+        child.bindings.insert(KEY_F0 + 3, None);
+
+        assert_eq!(
+            child.find_binding(KEY_F0 + 2),
+            Some(Some(&KeyAction::Command("score".to_string())))
+        );
+        assert_eq!(child.find_binding(KEY_F0 + 3), Some(None));
+        assert_eq!(child.find_binding(KEY_F0 + 4), None);
+    }
+
     #[test]
     fn mud_find_macro() {
         let mut mud = Mud::new("TestMUD", "127.0.0.1", 4000);
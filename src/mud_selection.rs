@@ -1,16 +1,34 @@
 // MUDSelection - Specialized selection widget for MUD connect menu
 //
 // Ported from mcl-cpp-reference/Selection.cc:170-213 (1:1 port)
+// Extension: 'a' adds, 'e' edits and 'd' deletes an entry,
+// writing the change to the config file with Config::write_mud_entry
 
 use crate::config::Config;
 use crate::input::{KeyCode, KeyEvent};
+use crate::mud::Mud;
 use crate::selection::Selection;
 use crate::window::Window;
+use std::path::PathBuf;
+
+/// Prompts of the add/edit form, in order
+const FORM_FIELDS: [&str; 4] = ["Name", "Host", "Port", "Commands"];
+
+/// An add/edit in progress: one answer per FORM_FIELDS entry
+struct MudForm {
+    editing: Option<String>, // Name of the MUD being edited (None = adding)
+    values: [String; 4],
+    field: usize,
+}
 
 /// Specialized selection widget for choosing MUDs from config (C++ Selection.cc:39-48)
 pub struct MudSelection {
     selection: Selection,
     config: Config,
+    path: PathBuf, // Config file the manager writes to
+    form: Option<MudForm>,
+    deleting: bool, // Waiting for y/n to delete the selected MUD
+    message: Option<String>,
 }
 
 impl MudSelection {
@@ -32,10 +50,24 @@ impl MudSelection {
         let x = 0;
         let y = (parent_height / 4) as isize; // parent->height / 4 (centered)
 
-        let mut selection = Selection::new(parent, width, height, x, y);
+        let selection = Selection::new(parent, width, height, x, y);
+        let mut menu = Self {
+            selection,
+            config,
+            path: Config::default_path(),
+            form: None,
+            deleting: false,
+            message: None,
+        };
+        menu.fill();
+        menu
+    }
 
-        // Populate selection with MUD names
-        for mud in config.mud_list.iter() {
+    /// (Re)populate the list from config.mud_list
+    fn fill(&mut self) {
+        let selected = self.selection.get_selection();
+        self.selection.clear();
+        for mud in self.config.mud_list.iter() {
             // Format: "mudname hostname port commands"
             let display = if !mud.hostname.is_empty() {
                 format!(
@@ -48,10 +80,187 @@ impl MudSelection {
             } else {
                 mud.name.clone()
             };
-            selection.add_string(display, 0);
+            self.selection.add_string(display, 0);
+        }
+        let last = self.selection.count() as i32 - 1;
+        self.selection
+            .set_selection(selected.clamp(0, last.max(0)).min(last));
+    }
+
+    /// Write changes to this file instead of ~/.okros/config
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    /// The MUD list as edited so far
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// True while the add/edit form or a delete confirmation has the keys
+    pub fn is_busy(&self) -> bool {
+        self.form.is_some() || self.deleting
+    }
+
+    /// What the form or delete confirmation is asking (for the status line)
+    pub fn prompt(&self) -> Option<String> {
+        if self.deleting {
+            let name = self.get_selected_mud_name().unwrap_or_default();
+            return Some(format!("Delete {}? (y/n)", name));
+        }
+        let form = self.form.as_ref()?;
+        let what = match &form.editing {
+            Some(name) => format!("Edit {}", name),
+            None => "Add MUD".to_string(),
+        };
+        Some(format!(
+            "{} - {}: {}_ (Enter next, Esc cancel)",
+            what, FORM_FIELDS[form.field], form.values[form.field]
+        ))
+    }
+
+    /// Result of the last add/edit/delete (success or error), once
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Start the form, empty or filled in from the selected MUD
+    fn open_form(&mut self, edit: bool) {
+        let mut form = MudForm {
+            editing: None,
+            values: Default::default(),
+            field: 0,
+        };
+        if edit {
+            let Some(mud) = self.selected_mud() else {
+                return;
+            };
+            if mud.hostname.is_empty() {
+                self.message = Some(format!("{} can't be edited", mud.name));
+                return;
+            }
+            form.values = [
+                mud.name.clone(),
+                mud.hostname.clone(),
+                mud.port.to_string(),
+                mud.commands.clone(),
+            ];
+            form.editing = Some(mud.name.clone());
         }
+        self.form = Some(form);
+    }
 
-        Self { selection, config }
+    fn selected_mud(&self) -> Option<&Mud> {
+        let idx = self.selection.get_selection();
+        self.config.mud_list.get(usize::try_from(idx).ok()?)
+    }
+
+    fn form_key(&mut self, event: KeyEvent) {
+        let Some(form) = self.form.as_mut() else {
+            return;
+        };
+        let value = &mut form.values[form.field];
+        match event {
+            KeyEvent::Key(KeyCode::Escape) => self.form = None,
+            KeyEvent::Byte(b'\n') | KeyEvent::Byte(b'\r') => {
+                form.field += 1;
+                if form.field == FORM_FIELDS.len() {
+                    let form = self.form.take().unwrap();
+                    self.message = Some(match self.save_form(form) {
+                        Ok(msg) => msg,
+                        Err(e) => e,
+                    });
+                }
+            }
+            KeyEvent::Byte(0x08) | KeyEvent::Byte(0x7F) => {
+                value.pop();
+            }
+            KeyEvent::Byte(0x15) => value.clear(), // Ctrl-U
+            KeyEvent::Byte(b @ b' '..=b'~') => value.push(b as char),
+            _ => {}
+        }
+    }
+
+    /// Check a finished form and apply it to the list and the config file
+    fn save_form(&mut self, form: MudForm) -> Result<String, String> {
+        let [name, host, port, commands] = form.values.map(|v| v.trim().to_string());
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err("MUD name must be one word".to_string());
+        }
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            return Err("Host must be one word".to_string());
+        }
+        let port: u16 = port
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| format!("Bad port: {}", port))?;
+        let renamed = form.editing.as_deref().is_some_and(|old| old != name);
+        if (form.editing.is_none() || renamed) && self.config.mud_list.find(&name).is_some() {
+            return Err(format!("There is already a MUD called {}", name));
+        }
+        if let Some(old) = form.editing.as_deref().filter(|_| renamed) {
+            self.check_not_inherited(old)?;
+        }
+
+        let mut mud = match form.editing.as_deref() {
+            Some(old) => self
+                .config
+                .mud_list
+                .find(old)
+                .cloned()
+                .unwrap_or_else(Mud::empty),
+            None => Mud::empty(),
+        };
+        mud.name = name;
+        mud.hostname = host;
+        mud.port = port;
+        mud.commands = commands;
+        Config::write_mud_entry(&self.path, form.editing.as_deref(), Some(&mud))?;
+        let msg = match form.editing {
+            Some(old) => {
+                if let Some(existing) = self.config.mud_list.find_mut(&old) {
+                    *existing = mud;
+                }
+                format!("Saved {} to {}", old, self.path.display())
+            }
+            None => {
+                let msg = format!("Added {} to {}", mud.name, self.path.display());
+                self.config.mud_list.insert(mud);
+                self.fill();
+                self.selection
+                    .set_selection(self.selection.count() as i32 - 1);
+                return Ok(msg);
+            }
+        };
+        self.fill();
+        Ok(msg)
+    }
+
+    /// A MUD other entries inherit from can't be renamed or deleted (their
+    /// `inherit` lines would stop loading)
+    fn check_not_inherited(&self, name: &str) -> Result<(), String> {
+        match self
+            .config
+            .mud_list
+            .iter()
+            .find(|m| m.inherits.as_ref().is_some_and(|p| p.name == name))
+        {
+            Some(child) => Err(format!("{} inherits from {}", child.name, name)),
+            None => Ok(()),
+        }
+    }
+
+    fn delete_selected(&mut self) -> Result<String, String> {
+        let name = self
+            .selected_mud()
+            .map(|m| m.name.clone())
+            .ok_or("Nothing selected")?;
+        self.check_not_inherited(&name)?;
+        Config::write_mud_entry(&self.path, Some(&name), None)?;
+        self.config.mud_list.remove(&name);
+        self.fill();
+        Ok(format!("Deleted {} from {}", name, self.path.display()))
     }
 
     /// Get current selection index
@@ -80,6 +289,11 @@ impl MudSelection {
             .map(|m| (m.name.as_str(), m.hostname.as_str(), m.port))
     }
 
+    /// Get full MUD definition at index (commands, login, actions...)
+    pub fn get_mud(&self, index: usize) -> Option<&Mud> {
+        self.config.mud_list.get(index)
+    }
+
     /// Get mutable window pointer for tree operations
     pub fn window_mut_ptr(&mut self) -> *mut Window {
         self.selection.window_mut_ptr()
@@ -92,6 +306,42 @@ impl MudSelection {
 
     /// Handle keypress event
     pub fn keypress(&mut self, event: KeyEvent) -> bool {
+        if self.form.is_some() {
+            self.form_key(event);
+            return true;
+        }
+        if self.deleting {
+            self.deleting = false;
+            if matches!(event, KeyEvent::Byte(b'y') | KeyEvent::Byte(b'Y')) {
+                self.message = Some(match self.delete_selected() {
+                    Ok(msg) => msg,
+                    Err(e) => e,
+                });
+            }
+            return true;
+        }
+        match event {
+            KeyEvent::Byte(b'a') => {
+                self.open_form(false);
+                return true;
+            }
+            KeyEvent::Byte(b'e') => {
+                self.open_form(true);
+                return true;
+            }
+            KeyEvent::Byte(b'd') => {
+                match self.selected_mud() {
+                    Some(mud) if mud.hostname.is_empty() => {
+                        self.message = Some(format!("{} can't be deleted", mud.name))
+                    }
+                    Some(_) => self.deleting = true,
+                    None => {}
+                }
+                return true;
+            }
+            _ => {}
+        }
+
         // Special handling for Alt-A (show aliases) - not implemented yet
         if matches!(event, KeyEvent::Key(KeyCode::Alt(b'a'))) {
             // TODO: Show alias selection for selected MUD
@@ -126,7 +376,6 @@ fn truncate(s: &str, max_len: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mud::Mud;
     use std::ptr;
 
     #[test]
@@ -178,6 +427,60 @@ mod tests {
         assert_eq!(sel.get_selected_mud_name(), Some("TestMUD"));
     }
 
+    fn typed(sel: &mut MudSelection, text: &str) {
+        for b in text.bytes() {
+            sel.keypress(KeyEvent::Byte(b));
+        }
+    }
+
+    #[test]
+    fn mud_selection_manages_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        std::fs::write(&path, "MUD Nodeka {\n  host nodeka.com 23\n}\n").unwrap();
+        let mut config = Config::new();
+        config.load_file(&path).unwrap(); // Offline + Nodeka
+        let mut sel = MudSelection::new(ptr::null_mut(), config);
+        sel.set_config_path(path.clone());
+
+        // Add
+        typed(&mut sel, "a");
+        assert!(sel.is_busy());
+        assert_eq!(
+            sel.prompt().unwrap(),
+            "Add MUD - Name: _ (Enter next, Esc cancel)"
+        );
+        typed(&mut sel, "Aardwolf\naardmud.org\n4000\n\n");
+        assert!(!sel.is_busy());
+        assert!(sel.take_message().unwrap().starts_with("Added Aardwolf"));
+        assert_eq!(sel.count(), 3);
+        assert_eq!(sel.get_selected_mud_name(), Some("Aardwolf"));
+
+        // Edit: change the port, keep the rest
+        typed(&mut sel, "e\n\n");
+        sel.keypress(KeyEvent::Byte(0x15));
+        typed(&mut sel, "4010\nlook\n");
+        assert_eq!(sel.get_mud(2).unwrap().port, 4010);
+        assert_eq!(sel.get_mud(2).unwrap().commands, "look");
+
+        // Bad input leaves the list alone
+        typed(&mut sel, "anew\nhost\nnotaport\n\n");
+        assert_eq!(sel.take_message().unwrap(), "Bad port: notaport");
+        assert_eq!(sel.count(), 3);
+
+        // Delete (the Offline entry is protected)
+        sel.keypress(KeyEvent::Key(KeyCode::Home));
+        typed(&mut sel, "d");
+        assert_eq!(sel.take_message().unwrap(), "Offline can't be deleted");
+        sel.keypress(KeyEvent::Key(KeyCode::ArrowDown));
+        typed(&mut sel, "dy");
+        assert!(sel.take_message().unwrap().starts_with("Deleted Nodeka"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "MUD Aardwolf {\n    host aardmud.org 4010\n    commands look\n}\n"
+        );
+    }
+
     #[test]
     fn truncate_long_string() {
         assert_eq!(truncate("short", 10), "short");
@@ -0,0 +1,229 @@
+// MXP (MUD eXtension Protocol) - markup stripping
+//
+// MXP servers put line-mode escapes (ESC [ n z) and HTML-like tags
+// (<COLOR red>, <SEND href="...">, <!ELEMENT ...>) into their text, with
+// &lt; style entities for literal characters. okros doesn't render MXP:
+// MxpFilter removes the markup and keeps the text between the tags, so
+// menus and links read as plain text.
+
+/// Telnet option for MXP
+pub const TELOPT_MXP: u8 = 91;
+
+/// Line modes set by ESC [ n z (open and secure differ in which tags are
+/// allowed; both are stripped here)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Open,   // Tags parsed (0, 5)
+    Secure, // Tags parsed (1, 4, 6)
+    Locked, // No markup: text shown as is (2, 7)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Text,
+    Escape(Vec<u8>), // After ESC: "[" and digits so far
+    Tag(Option<u8>), // Inside <...>; the quote character we're in
+    Entity(Vec<u8>), // After '&': the name so far
+}
+
+/// Longest entity name looked for before '&' is taken literally
+const MAX_ENTITY: usize = 8;
+
+/// Longest ESC [ digits... held while waiting for the 'z'; past it the
+/// sequence goes on to the ANSI parser as is
+const MAX_ESCAPE: usize = 16;
+
+/// Strips MXP markup from MUD text, keeping state across reads
+pub struct MxpFilter {
+    state: State,
+    line_mode: Option<Mode>, // This line only (0-2), back to default at '\n'
+    default_mode: Mode,      // Locked modes 5-7; 3 resets to open
+}
+
+impl MxpFilter {
+    pub fn new() -> Self {
+        Self {
+            state: State::Text,
+            line_mode: None,
+            default_mode: Mode::Open,
+        }
+    }
+
+    fn mode(&self) -> Mode {
+        self.line_mode.unwrap_or(self.default_mode)
+    }
+
+    fn set_mode(&mut self, n: u32) {
+        match n {
+            0 => self.line_mode = Some(Mode::Open),
+            1 | 4 => self.line_mode = Some(Mode::Secure),
+            2 => self.line_mode = Some(Mode::Locked),
+            3 => {
+                self.line_mode = None;
+                self.default_mode = Mode::Open;
+            }
+            5 => self.default_mode = Mode::Open,
+            6 => self.default_mode = Mode::Secure,
+            7 => self.default_mode = Mode::Locked,
+            _ => {}
+        }
+    }
+
+    /// `data` without MXP markup. Line-mode escapes are always removed;
+    /// tags and entities only while `active` (MXP negotiated) and the line
+    /// isn't locked. Other escape sequences (ANSI colors) pass through.
+    pub fn filter(&mut self, data: &[u8], active: bool) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &b in data {
+            match std::mem::replace(&mut self.state, State::Text) {
+                State::Text => match b {
+                    0x1B => self.state = State::Escape(Vec::new()),
+                    b'<' if active && self.mode() != Mode::Locked => self.state = State::Tag(None),
+                    b'&' if active && self.mode() != Mode::Locked => {
+                        self.state = State::Entity(Vec::new())
+                    }
+                    b'\n' => {
+                        self.line_mode = None;
+                        out.push(b);
+                    }
+                    _ => out.push(b),
+                },
+                State::Escape(mut seq) => {
+                    let wanted = match seq.len() {
+                        0 => b == b'[',
+                        n => b.is_ascii_digit() && n < MAX_ESCAPE,
+                    };
+                    if wanted {
+                        seq.push(b);
+                        self.state = State::Escape(seq);
+                    } else if b == b'z' && seq.len() > 1 {
+                        let n = std::str::from_utf8(&seq[1..])
+                            .ok()
+                            .and_then(|s| s.parse().ok());
+                        self.set_mode(n.unwrap_or(0));
+                    } else {
+                        // Not ours: hand the sequence on to the ANSI parser
+                        out.push(0x1B);
+                        out.extend_from_slice(&seq);
+                        match b {
+                            0x1B => self.state = State::Escape(Vec::new()),
+                            _ => out.push(b),
+                        }
+                    }
+                }
+                State::Tag(quote) => {
+                    self.state = match (quote, b) {
+                        (None, b'>') => State::Text,
+                        (None, b'"' | b'\'') => State::Tag(Some(b)),
+                        (Some(q), _) if q == b => State::Tag(None),
+                        _ => State::Tag(quote),
+                    };
+                }
+                State::Entity(mut name) => {
+                    if b == b';' {
+                        match decode_entity(&name) {
+                            Some(c) => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                            None => {
+                                out.push(b'&');
+                                out.extend_from_slice(&name);
+                                out.push(b';');
+                            }
+                        }
+                    } else if (b.is_ascii_alphanumeric() || b == b'#') && name.len() < MAX_ENTITY {
+                        name.push(b);
+                        self.state = State::Entity(name);
+                    } else {
+                        // Not an entity: a literal '&'
+                        out.push(b'&');
+                        out.extend_from_slice(&name);
+                        self.state = State::Text;
+                        out.extend(self.filter(&[b], active));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Default for MxpFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// &lt; &gt; &amp; &quot; &nbsp; and numeric &#nn; entities
+fn decode_entity(name: &[u8]) -> Option<char> {
+    match name {
+        b"lt" => Some('<'),
+        b"gt" => Some('>'),
+        b"amp" => Some('&'),
+        b"quot" => Some('"'),
+        b"apos" => Some('\''),
+        b"nbsp" => Some(' '),
+        [b'#', digits @ ..] => std::str::from_utf8(digits)
+            .ok()?
+            .parse::<u32>()
+            .ok()
+            .and_then(char::from_u32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(data: &str) -> String {
+        String::from_utf8(MxpFilter::new().filter(data.as_bytes(), true)).unwrap()
+    }
+
+    #[test]
+    fn strips_tags_and_decodes_entities() {
+        assert_eq!(
+            strip("\x1b[1z<SEND href=\"go north\">north</SEND> &lt;3 &amp; &#65;\n"),
+            "north <3 & A\n"
+        );
+        assert_eq!(
+            strip("<!ELEMENT RName '<FONT \"Red\">' FLAG=\"RoomName\">Hall"),
+            "Hall"
+        );
+        // Unknown entities and a lone '&' stay as written
+        assert_eq!(strip("a &foo; b & c"), "a &foo; b & c");
+    }
+
+    #[test]
+    fn ansi_colors_pass_through() {
+        assert_eq!(strip("\x1b[31m<B>red</B>\x1b[0m"), "\x1b[31mred\x1b[0m");
+    }
+
+    #[test]
+    fn locked_lines_and_inactive_mxp_keep_text() {
+        assert_eq!(strip("\x1b[2z<not a tag>\n<B>x</B>"), "<not a tag>\nx");
+        assert_eq!(strip("\x1b[7z<a>\n<b>\x1b[3z<c>"), "<a>\n<b>");
+        let mut f = MxpFilter::new();
+        assert_eq!(f.filter(b"\x1b[1z<b> &amp;", false), b"<b> &amp;");
+    }
+
+    #[test]
+    fn markup_split_across_reads() {
+        let mut f = MxpFilter::new();
+        let mut out = f.filter(b"<COLOR ", true);
+        out.extend(f.filter(b"red>hi &l", true));
+        out.extend(f.filter(b"t;\x1b[", true));
+        out.extend(f.filter(b"1z!", true));
+        assert_eq!(out, b"hi <!");
+    }
+
+    #[test]
+    fn endless_escapes_are_handed_on() {
+        let seq = format!("\x1b[{}z", "9".repeat(100));
+        let mut f = MxpFilter::new();
+        let mut out = Vec::new();
+        for &b in seq.as_bytes() {
+            out.extend(f.filter(&[b], true));
+            assert!(!matches!(&f.state, State::Escape(s) if s.len() > MAX_ESCAPE));
+        }
+        assert_eq!(out, seq.as_bytes());
+    }
+}
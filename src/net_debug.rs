@@ -0,0 +1,323 @@
+// NetDebug - hex dump of the raw MUD stream (#debug net)
+//
+// Every read from the MUD is written out as hex + ASCII,
+// once as received and, when MCCP is decompressing, again as the telnet
+// layer sees it; telnet commands in the stream are spelled out under the
+// dump ("IAC WILL ECHO"). Lines go to a log file (show it live with
+// `#window split log <file>`) and the newest are kept for the control
+// protocol's `debug` command.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where `#debug net on` writes without a file name
+pub const DEFAULT_LOG: &str = "/tmp/okros_net.log";
+
+/// Lines kept for `recent`
+const KEEP_LINES: usize = 1000;
+
+/// Bytes per dump line
+const ROW: usize = 16;
+
+/// A subnegotiation longer than this is annotated without waiting for its end
+const MAX_PENDING: usize = 64 * 1024;
+
+const USAGE: &str = "Usage: #debug net [on [<file>]|off]";
+
+/// Telnet command byte names (240..=255)
+pub(crate) fn command_name(b: u8) -> Option<&'static str> {
+    Some(match b {
+        240 => "SE",
+        241 => "NOP",
+        242 => "DM",
+        243 => "BRK",
+        244 => "IP",
+        245 => "AO",
+        246 => "AYT",
+        247 => "EC",
+        248 => "EL",
+        249 => "GA",
+        250 => "SB",
+        251 => "WILL",
+        252 => "WONT",
+        253 => "DO",
+        254 => "DONT",
+        255 => "IAC",
+        _ => return None,
+    })
+}
+
+/// Telnet option name, or its number
+pub(crate) fn option_name(b: u8) -> String {
+    let name = match b {
+        0 => "BINARY",
+        1 => "ECHO",
+        3 => "SGA",
+        24 => "TTYPE",
+        25 => "EOR",
+        31 => "NAWS",
+        34 => "LINEMODE",
+        39 => "NEW-ENVIRON",
+        42 => "CHARSET",
+        69 => "MSDP",
+        70 => "MSSP",
+        85 => "MCCP1",
+        86 => "MCCP2",
+        90 => "MSP",
+        91 => "MXP",
+        93 => "ZMP",
+        200 => "ATCP",
+        201 => "GMCP",
+        _ => return b.to_string(),
+    };
+    name.to_string()
+}
+
+/// "0010  48 65 6c 6c 6f ...  |Hello...|" lines; offsets count from `offset`
+pub fn hex_dump(offset: u64, data: &[u8]) -> Vec<String> {
+    data.chunks(ROW)
+        .enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect();
+            format!(
+                "{:06x}  {:<width$}  |{}|",
+                offset + (i * ROW) as u64,
+                hex.join(" "),
+                ascii,
+                width = ROW * 3 - 1
+            )
+        })
+        .collect()
+}
+
+/// Telnet commands in a stream that may split them across reads
+#[derive(Default)]
+struct TelnetAnnotator {
+    pending: Vec<u8>, // An unfinished command from the last read
+}
+
+impl TelnetAnnotator {
+    fn feed(&mut self, data: &[u8]) -> Vec<String> {
+        let mut buf = std::mem::take(&mut self.pending);
+        buf.extend_from_slice(data);
+        let mut notes = Vec::new();
+        let mut i = 0;
+        while let Some(at) = buf[i..].iter().position(|&b| b == 255).map(|n| i + n) {
+            match Self::command(&buf[at..]) {
+                Some((note, len)) => {
+                    if let Some(note) = note {
+                        notes.push(note);
+                    }
+                    i = at + len;
+                }
+                None if buf.len() - at > MAX_PENDING => {
+                    notes.push(format!(
+                        "IAC SB ... ({} bytes, no IAC SE yet)",
+                        buf.len() - at
+                    ));
+                    i = buf.len();
+                }
+                None => {
+                    self.pending = buf[at..].to_vec();
+                    break;
+                }
+            }
+        }
+        notes
+    }
+
+    /// The command at the start of `s` (which starts with IAC): its
+    /// description (None for an escaped 255) and length; None if incomplete
+    fn command(s: &[u8]) -> Option<(Option<String>, usize)> {
+        let cmd = *s.get(1)?;
+        match cmd {
+            255 => Some((None, 2)),
+            251..=254 => {
+                let opt = *s.get(2)?;
+                let note = format!("IAC {} {}", command_name(cmd)?, option_name(opt));
+                Some((Some(note), 3))
+            }
+            250 => {
+                let opt = *s.get(2)?;
+                // Ends at IAC SE; IAC IAC inside is an escaped 255
+                let mut j = 3;
+                let mut payload = 0;
+                loop {
+                    match (s.get(j)?, s.get(j + 1)) {
+                        (255, Some(240)) => break,
+                        (255, Some(255)) => j += 2,
+                        (255, None) => return None,
+                        _ => j += 1,
+                    }
+                    payload += 1;
+                }
+                let note = format!("IAC SB {} ({} bytes) IAC SE", option_name(opt), payload);
+                Some((Some(note), j + 2))
+            }
+            _ => {
+                let name = command_name(cmd).map_or_else(|| cmd.to_string(), str::to_string);
+                Some((Some(format!("IAC {}", name)), 2))
+            }
+        }
+    }
+}
+
+pub struct NetDebug {
+    path: PathBuf,
+    file: File,
+    lines: VecDeque<String>,
+    raw_offset: u64,  // Bytes read from the socket so far
+    data_offset: u64, // Bytes out of the decompressor so far
+    telnet: TelnetAnnotator,
+}
+
+impl NetDebug {
+    /// Append to the log at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Can't open {}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            file,
+            lines: VecDeque::new(),
+            raw_offset: 0,
+            data_offset: 0,
+            telnet: TelnetAnnotator::default(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn emit(&mut self, line: String) {
+        let _ = writeln!(self.file, "{}", line);
+        if self.lines.len() == KEEP_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// A read from the socket, before MCCP
+    pub fn raw(&mut self, data: &[u8]) {
+        self.emit(format!("<< read {} bytes", data.len()));
+        for line in hex_dump(self.raw_offset, data) {
+            self.emit(line);
+        }
+        self.raw_offset += data.len() as u64;
+    }
+
+    /// What the telnet layer gets (after MCCP); dumped again only if the
+    /// decompressor changed it
+    pub fn data(&mut self, raw: &[u8], data: &[u8]) {
+        if data != raw {
+            self.emit(format!("<< decompressed {} bytes", data.len()));
+            for line in hex_dump(self.data_offset, data) {
+                self.emit(line);
+            }
+        }
+        self.data_offset += data.len() as u64;
+        for note in self.telnet.feed(data) {
+            self.emit(format!("   {}", note));
+        }
+    }
+
+    /// The newest `n` lines, oldest first
+    pub fn recent(&self, n: usize) -> Vec<String> {
+        let skip = self.lines.len().saturating_sub(n);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// #debug net [on [<file>] | off]
+pub fn debug_command(args: &str, debug: &mut Option<NetDebug>) -> Result<String, String> {
+    let mut words = args.split_whitespace();
+    if words.next() != Some("net") {
+        return Err(USAGE.to_string());
+    }
+    match (words.next(), words.next()) {
+        (None, _) => Ok(match debug {
+            Some(d) => format!("Network debug on, writing {}", d.path().display()),
+            None => "Network debug off".to_string(),
+        }),
+        (Some("on"), file) => {
+            let d = NetDebug::open(file.unwrap_or(DEFAULT_LOG))?;
+            let message = format!("Network debug on, writing {}", d.path().display());
+            *debug = Some(d);
+            Ok(message)
+        }
+        (Some("off"), None) => {
+            *debug = None;
+            Ok("Network debug off".to_string())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dumps_hex_and_ascii() {
+        let lines = hex_dump(0x10, b"Hello\xff\xfb\x01 world, and more");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "000010  48 65 6c 6c 6f ff fb 01 20 77 6f 72 6c 64 2c 20  |Hello... world, |"
+        );
+        assert!(lines[1].starts_with("000020  61 6e 64 20 6d 6f 72 65    "));
+        assert!(lines[1].ends_with("|and more|"));
+    }
+
+    #[test]
+    fn annotates_telnet_across_reads() {
+        let mut t = TelnetAnnotator::default();
+        assert_eq!(t.feed(b"hi\xff\xfb"), Vec::<String>::new());
+        assert_eq!(
+            t.feed(b"\x01\xff\xff\xff\xf9"),
+            vec!["IAC WILL ECHO", "IAC GA"]
+        );
+        assert_eq!(
+            t.feed(b"\xff\xfa\x18\x01\xff\xff\xff\xf0\xff\xfd\x56"),
+            vec!["IAC SB TTYPE (2 bytes) IAC SE", "IAC DO MCCP2"]
+        );
+    }
+
+    #[test]
+    fn debug_net_logs_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("net.log");
+        let mut debug = None;
+        let msg = debug_command(&format!("net on {}", path.display()), &mut debug).unwrap();
+        assert!(msg.ends_with("net.log"));
+        let d = debug.as_mut().unwrap();
+        d.raw(b"ok\xff\xfb\x01");
+        d.data(b"ok\xff\xfb\x01", b"ok\xff\xfb\x01");
+        assert_eq!(
+            d.recent(10),
+            vec![
+                "<< read 5 bytes".to_string(),
+                format!("000000  6f 6b ff fb 01{}  |ok...|", " ".repeat(33)),
+                "   IAC WILL ECHO".to_string(),
+            ]
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+        assert!(debug_command("net off", &mut debug).is_ok());
+        assert!(debug.is_none());
+        assert!(debug_command("frob", &mut debug).is_err());
+    }
+}
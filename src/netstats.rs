@@ -0,0 +1,196 @@
+// Network statistics - traffic rates and round-trip time
+//
+// C++ MCL only counts bytes per session (Session.cc:325, the #stats totals).
+// NetStats adds rolling bytes/second averages and #ping: send IAC AYT (or a
+// configured command the MUD answers) and time the server's next output.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Traffic rates are averaged over this much history
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// A ping with no reply by then is given up
+pub const PING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sent by #ping when no ping command is configured: telnet "Are You There"
+pub const PING_AYT: [u8; 2] = [crate::telnet::telnet::IAC, crate::telnet::telnet::AYT];
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    inbound: usize,
+    outbound: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetStats {
+    samples: VecDeque<Sample>, // Reads/writes within RATE_WINDOW, oldest first
+    ping_sent: Option<Instant>,
+    last_rtt: Option<Duration>,
+    last_out: Option<Instant>, // Last write, or when idle_out() started counting
+}
+
+impl NetStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count bytes read; returns the round-trip time if this answers a ping
+    pub fn record_in(&mut self, n: usize, now: Instant) -> Option<Duration> {
+        self.push(now, n, 0);
+        let sent = self.ping_sent.take()?;
+        let rtt = now.saturating_duration_since(sent);
+        self.last_rtt = Some(rtt);
+        Some(rtt)
+    }
+
+    pub fn record_out(&mut self, n: usize, now: Instant) {
+        self.push(now, 0, n);
+        self.last_out = Some(now);
+    }
+
+    /// Time since anything was written (#keepalive); on a connection with
+    /// no writes yet, since the first call
+    pub fn idle_out(&mut self, now: Instant) -> Duration {
+        now.saturating_duration_since(*self.last_out.get_or_insert(now))
+    }
+
+    fn push(&mut self, at: Instant, inbound: usize, outbound: usize) {
+        self.samples.push_back(Sample {
+            at,
+            inbound,
+            outbound,
+        });
+        self.prune(at);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|s| now.saturating_duration_since(s.at) > RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// (in, out) bytes per second over the last RATE_WINDOW
+    pub fn rates(&mut self, now: Instant) -> (f64, f64) {
+        self.prune(now);
+        let (inbound, outbound) = self
+            .samples
+            .iter()
+            .fold((0, 0), |(i, o), s| (i + s.inbound, o + s.outbound));
+        let secs = RATE_WINDOW.as_secs_f64();
+        (inbound as f64 / secs, outbound as f64 / secs)
+    }
+
+    /// Start timing a ping (the caller sends it)
+    pub fn start_ping(&mut self, now: Instant) {
+        self.ping_sent = Some(now);
+    }
+
+    pub fn ping_pending(&self) -> bool {
+        self.ping_sent.is_some()
+    }
+
+    /// Give up on a ping older than PING_TIMEOUT; true if one was dropped
+    pub fn expire_ping(&mut self, now: Instant) -> bool {
+        match self.ping_sent {
+            Some(sent) if now.saturating_duration_since(sent) > PING_TIMEOUT => {
+                self.ping_sent = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.last_rtt
+    }
+
+    /// Forget rates and ping state (new connection)
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Status line segment: "in 1.2K/s out 40B/s 85ms"
+    pub fn status_text(&mut self, now: Instant) -> String {
+        let (inbound, outbound) = self.rates(now);
+        let mut text = format!("in {} out {}", format_rate(inbound), format_rate(outbound));
+        if let Some(rtt) = self.last_rtt {
+            text.push_str(&format!(" {}ms", rtt.as_millis()));
+        }
+        text
+    }
+}
+
+/// Bytes/second with a B, K or M suffix
+pub fn format_rate(rate: f64) -> String {
+    if rate < 1024.0 {
+        format!("{:.0}B/s", rate)
+    } else if rate < 1024.0 * 1024.0 {
+        format!("{:.1}K/s", rate / 1024.0)
+    } else {
+        format!("{:.1}M/s", rate / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_average_over_window() {
+        let t0 = Instant::now();
+        let mut net = NetStats::new();
+        net.record_in(5000, t0);
+        net.record_out(500, t0 + Duration::from_secs(1));
+        assert_eq!(net.rates(t0 + Duration::from_secs(2)), (1000.0, 100.0));
+        // The read falls out of the window first
+        assert_eq!(net.rates(t0 + Duration::from_secs(6)), (0.0, 100.0));
+        assert_eq!(net.rates(t0 + Duration::from_secs(7)), (0.0, 0.0));
+        assert_eq!(net.status_text(t0), "in 0B/s out 0B/s");
+    }
+
+    #[test]
+    fn ping_times_next_read() {
+        let t0 = Instant::now();
+        let mut net = NetStats::new();
+        assert_eq!(net.record_in(10, t0), None);
+        net.start_ping(t0);
+        assert!(net.ping_pending());
+        let rtt = net.record_in(5, t0 + Duration::from_millis(85));
+        assert_eq!(rtt, Some(Duration::from_millis(85)));
+        assert!(!net.ping_pending());
+        assert_eq!(net.record_in(5, t0 + Duration::from_millis(90)), None);
+        assert!(net
+            .status_text(t0 + Duration::from_secs(1))
+            .ends_with(" 85ms"));
+
+        net.start_ping(t0);
+        assert!(!net.expire_ping(t0 + Duration::from_secs(1)));
+        assert!(net.expire_ping(t0 + PING_TIMEOUT + Duration::from_secs(1)));
+        assert!(!net.ping_pending());
+    }
+
+    #[test]
+    fn idle_out_counts_from_last_write() {
+        let t0 = Instant::now();
+        let mut net = NetStats::new();
+        assert_eq!(net.idle_out(t0), Duration::ZERO);
+        assert_eq!(net.idle_out(t0 + Duration::from_secs(3)).as_secs(), 3);
+        net.record_out(2, t0 + Duration::from_secs(4));
+        assert_eq!(net.idle_out(t0 + Duration::from_secs(5)).as_secs(), 1);
+        net.reset();
+        assert_eq!(net.idle_out(t0 + Duration::from_secs(9)), Duration::ZERO);
+    }
+
+    #[test]
+    fn format_rate_units() {
+        assert_eq!(format_rate(40.0), "40B/s");
+        assert_eq!(format_rate(1536.0), "1.5K/s");
+        assert_eq!(format_rate(3.0 * 1024.0 * 1024.0), "3.0M/s");
+    }
+}
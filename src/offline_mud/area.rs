@@ -0,0 +1,265 @@
+// Area files - external worlds for the offline MUD
+//
+// The built-in five rooms are fine for a demo, but testing triggers or
+// mappers needs bigger, purpose-built worlds. An area file is JSON:
+//
+//   {
+//     "name": "Test Keep",                  (optional, shown on load)
+//     "start": "gate",                      (room the player starts in)
+//     "rooms": [
+//       { "id": "gate", "name": "Keep Gate", "description": "...",
+//         "exits": { "north": "hall" },     (north/south/east/west/up/down)
+//         "items": ["lamp"] }               (optional)
+//     ],
+//     "items": [
+//       { "id": "lamp", "name": "brass lamp", "description": "..." }
+//     ],
+//     "npcs": [                             (optional)
+//       { "id": "guard", "name": "keep guard", "room": "hall",
+//         "hp": 20, "damage": [1, 4],
+//         "aggressive": false, "wanders": false, "loot": ["lamp"] }
+//     ]
+//   }
+//
+// Every exit, item and NPC reference is checked on load. ROM/Merc .are
+// files are not read; convert them to this schema.
+
+use super::game::{Direction, Item, Npc, Player, Room, World, PLAYER_MAX_HP};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AreaFile {
+    #[serde(default)]
+    pub name: String,
+    pub start: String,
+    pub rooms: Vec<AreaRoom>,
+    #[serde(default)]
+    pub items: Vec<AreaItem>,
+    #[serde(default)]
+    pub npcs: Vec<AreaNpc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AreaRoom {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub exits: HashMap<String, String>,
+    #[serde(default)]
+    pub items: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AreaItem {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AreaNpc {
+    pub id: String,
+    pub name: String,
+    pub room: String,
+    #[serde(default = "default_npc_hp")]
+    pub hp: i32,
+    #[serde(default = "default_npc_damage")]
+    pub damage: (i32, i32),
+    #[serde(default)]
+    pub aggressive: bool,
+    #[serde(default)]
+    pub wanders: bool,
+    #[serde(default)]
+    pub loot: Vec<String>,
+}
+
+fn default_npc_hp() -> i32 {
+    10
+}
+
+fn default_npc_damage() -> (i32, i32) {
+    (1, 3)
+}
+
+/// The world uses &'static str ids; an area's strings are leaked to get them.
+/// Areas are small and loaded a handful of times per run.
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+impl AreaFile {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid area file: {}", e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        // `--area` and `loadarea` paths may use ~ and $VAR
+        let path = &crate::config::expand_path(&path.as_ref().to_string_lossy());
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&json)
+    }
+
+    /// Check references and build the world
+    pub fn into_world(self) -> Result<World, String> {
+        let room_ids: Vec<&str> = self.rooms.iter().map(|r| r.id.as_str()).collect();
+        let item_ids: Vec<&str> = self.items.iter().map(|i| i.id.as_str()).collect();
+        let has_room = |id: &str| room_ids.contains(&id);
+        let has_item = |id: &str| item_ids.contains(&id);
+
+        if self.rooms.is_empty() {
+            return Err("Area has no rooms".to_string());
+        }
+        if !has_room(&self.start) {
+            return Err(format!("Start room '{}' does not exist", self.start));
+        }
+        for (i, id) in room_ids.iter().enumerate() {
+            if room_ids[..i].contains(id) {
+                return Err(format!("Duplicate room '{}'", id));
+            }
+        }
+        for (i, id) in item_ids.iter().enumerate() {
+            if item_ids[..i].contains(id) {
+                return Err(format!("Duplicate item '{}'", id));
+            }
+        }
+
+        let mut rooms = HashMap::new();
+        for room in &self.rooms {
+            let mut exits = HashMap::new();
+            for (dir, to) in &room.exits {
+                let d = Direction::parse(dir)
+                    .ok_or_else(|| format!("Room '{}': bad exit direction '{}'", room.id, dir))?;
+                if !has_room(to) {
+                    return Err(format!("Room '{}': exit to unknown room '{}'", room.id, to));
+                }
+                exits.insert(d, leak(to));
+            }
+            for item in &room.items {
+                if !has_item(item) {
+                    return Err(format!("Room '{}': unknown item '{}'", room.id, item));
+                }
+            }
+            let id = leak(&room.id);
+            rooms.insert(
+                id,
+                Room {
+                    id,
+                    name: leak(&room.name),
+                    description: leak(&room.description),
+                    exits,
+                    items: room.items.iter().map(|i| leak(i)).collect(),
+                },
+            );
+        }
+
+        let items = self
+            .items
+            .iter()
+            .map(|item| {
+                let id = leak(&item.id);
+                let item = Item {
+                    id,
+                    name: leak(&item.name),
+                    description: leak(&item.description),
+                };
+                (id, item)
+            })
+            .collect();
+
+        let mut npcs = Vec::new();
+        for npc in &self.npcs {
+            if !has_room(&npc.room) {
+                return Err(format!("NPC '{}': unknown room '{}'", npc.id, npc.room));
+            }
+            if let Some(item) = npc.loot.iter().find(|i| !has_item(i)) {
+                return Err(format!("NPC '{}': unknown loot item '{}'", npc.id, item));
+            }
+            if npc.hp <= 0 || npc.damage.0 < 0 || npc.damage.0 > npc.damage.1 {
+                return Err(format!("NPC '{}': bad hp or damage", npc.id));
+            }
+            let mut n = Npc::new(
+                leak(&npc.id),
+                leak(&npc.name),
+                leak(&npc.room),
+                npc.hp,
+                npc.damage,
+            );
+            n.aggressive = npc.aggressive;
+            n.wanders = npc.wanders;
+            n.loot = npc.loot.iter().map(|i| leak(i)).collect();
+            npcs.push(n);
+        }
+
+        let player = Player {
+            location: leak(&self.start),
+            inventory: vec![],
+            max_inventory: 5,
+            hp: PLAYER_MAX_HP,
+            max_hp: PLAYER_MAX_HP,
+            fighting: None,
+        };
+        Ok(World::from_parts(rooms, items, npcs, player))
+    }
+}
+
+/// Read an area file into a fresh world
+pub fn load_area(path: impl AsRef<Path>) -> Result<World, String> {
+    AreaFile::load(path)?.into_world()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEEP: &str = r#"{
+        "name": "Test Keep",
+        "start": "gate",
+        "rooms": [
+            { "id": "gate", "name": "Keep Gate", "description": "A gate.",
+              "exits": { "north": "hall" }, "items": ["lamp"] },
+            { "id": "hall", "name": "Great Hall", "exits": { "s": "gate" } }
+        ],
+        "items": [ { "id": "lamp", "name": "brass lamp" } ],
+        "npcs": [ { "id": "guard", "name": "keep guard", "room": "hall",
+                    "aggressive": true, "loot": ["lamp"] } ]
+    }"#;
+
+    #[test]
+    fn loads_rooms_items_and_npcs() {
+        let mut world = AreaFile::parse(KEEP).unwrap().into_world().unwrap();
+        assert_eq!(world.player.location, "gate");
+        assert!(world.item_in_room("lamp"));
+        world.move_player(Direction::North).unwrap();
+        assert_eq!(world.current_room().name, "Great Hall");
+        assert!(world.tick().contains("The keep guard attacks you!"));
+        world.move_player(Direction::South).unwrap();
+        assert_eq!(world.player.location, "gate");
+    }
+
+    #[test]
+    fn rejects_bad_references() {
+        let bad = |from: &str, to: &str| {
+            AreaFile::parse(&KEEP.replace(from, to))
+                .and_then(|a| a.into_world())
+                .err()
+                .unwrap()
+        };
+        assert!(bad(r#""start": "gate""#, r#""start": "moat""#).contains("Start room"));
+        assert!(bad(r#""north": "hall""#, r#""north": "tower""#).contains("unknown room"));
+        assert!(bad(r#""north": "hall""#, r#""sideways": "hall""#).contains("exit direction"));
+        assert!(bad(r#""items": ["lamp"]"#, r#""items": ["sword"]"#).contains("unknown item"));
+        assert!(bad(r#""loot": ["lamp"]"#, r#""loot": ["gem"]"#).contains("loot"));
+        assert!(bad(r#""room": "hall""#, r#""room": "attic""#).contains("NPC 'guard'"));
+        assert!(bad(r#""name": "Test Keep""#, r#""title": "x""#).contains("Invalid area"));
+    }
+}
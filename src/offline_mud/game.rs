@@ -2,8 +2,15 @@ use std::collections::HashMap;
 
 pub type RoomId = &'static str;
 pub type ItemId = &'static str;
+pub type NpcId = &'static str;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Player hit points (also the respawn value)
+pub const PLAYER_MAX_HP: i32 = 30;
+
+/// World ticks a dead NPC stays dead
+pub const RESPAWN_TICKS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Direction {
     North,
     South,
@@ -43,17 +50,81 @@ pub struct Room {
     pub items: Vec<ItemId>,
 }
 
+#[derive(Debug, Clone)]
+pub struct Npc {
+    pub id: NpcId, // Also the keyword for attack
+    pub name: &'static str,
+    pub home: RoomId, // Respawn room
+    pub location: RoomId,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub damage: (i32, i32), // Min/max per hit
+    pub aggressive: bool,   // Attacks the player on sight
+    pub wanders: bool,      // Moves through random exits
+    pub loot: Vec<ItemId>,  // Dropped on death
+    pub fighting: bool,     // Engaged with the player
+    pub dead_ticks: u32,    // Ticks until respawn; 0 = alive
+}
+
+impl Npc {
+    pub fn new(id: NpcId, name: &'static str, home: RoomId, hp: i32, damage: (i32, i32)) -> Self {
+        Self {
+            id,
+            name,
+            home,
+            location: home,
+            hp,
+            max_hp: hp,
+            damage,
+            aggressive: false,
+            wanders: false,
+            loot: Vec::new(),
+            fighting: false,
+            dead_ticks: 0,
+        }
+    }
+
+    pub fn aggressive(mut self) -> Self {
+        self.aggressive = true;
+        self
+    }
+
+    pub fn wandering(mut self) -> Self {
+        self.wanders = true;
+        self
+    }
+
+    pub fn dropping(mut self, item: ItemId) -> Self {
+        self.loot.push(item);
+        self
+    }
+
+    /// Keyword match for attack: the id or any word of the name
+    pub fn matches(&self, target: &str) -> bool {
+        self.id == target || self.name == target || self.name.split(' ').any(|w| w == target)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.dead_ticks == 0
+    }
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub location: RoomId,
     pub inventory: Vec<ItemId>,
     pub max_inventory: usize,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub fighting: Option<NpcId>,
 }
 
 pub struct World {
     pub rooms: HashMap<RoomId, Room>,
     pub items: HashMap<ItemId, Item>,
+    pub npcs: Vec<Npc>, // Vec, not a map: tick output comes in a fixed order
     pub player: Player,
+    rng: u64, // xorshift state; fixed seed so runs repeat
 }
 
 impl World {
@@ -85,6 +156,15 @@ impl World {
             },
         );
 
+        items.insert(
+            "coins",
+            Item {
+                id: "coins",
+                name: "gold coins",
+                description: "A handful of tarnished gold coins.",
+            },
+        );
+
         // Create rooms
         let mut rooms = HashMap::new();
 
@@ -162,16 +242,42 @@ impl World {
             },
         );
 
+        // Creatures: a harmless wanderer and two that fight back
+        let npcs = vec![
+            Npc::new("rabbit", "white rabbit", "clearing", 5, (0, 1)).wandering(),
+            Npc::new("goblin", "snarling goblin", "cave", 15, (1, 4))
+                .aggressive()
+                .dropping("coins"),
+            Npc::new("wolf", "grey wolf", "forest", 20, (2, 5))
+                .aggressive()
+                .wandering(),
+        ];
+
         let player = Player {
             location: "clearing",
             inventory: vec![],
             max_inventory: 5,
+            hp: PLAYER_MAX_HP,
+            max_hp: PLAYER_MAX_HP,
+            fighting: None,
         };
 
+        Self::from_parts(rooms, items, npcs, player)
+    }
+
+    /// A world from prebuilt pieces (area files)
+    pub fn from_parts(
+        rooms: HashMap<RoomId, Room>,
+        items: HashMap<ItemId, Item>,
+        npcs: Vec<Npc>,
+        player: Player,
+    ) -> Self {
         World {
             rooms,
             items,
+            npcs,
             player,
+            rng: 0x2545_F491_4F6C_DD1D,
         }
     }
 
@@ -230,12 +336,222 @@ impl World {
         let current = self.current_room();
         if let Some(&next_room) = current.exits.get(&direction) {
             self.player.location = next_room;
+            self.stop_fighting();
             Ok(next_room)
         } else {
             Err("You can't go that way.".to_string())
         }
     }
 
+    /// Random number in lo..=hi (xorshift64)
+    fn roll(&mut self, lo: i32, hi: i32) -> i32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        lo + (self.rng % (hi - lo + 1).max(1) as u64) as i32
+    }
+
+    /// Reseed the random numbers (combat rolls, wandering)
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = seed.max(1);
+    }
+
+    /// Living NPC in the player's room matching `target`
+    fn npc_here(&self, target: &str) -> Option<usize> {
+        self.npcs
+            .iter()
+            .position(|n| n.is_alive() && n.location == self.player.location && n.matches(target))
+    }
+
+    fn item_exists(&self, item_id: ItemId) -> bool {
+        self.item_in_inventory(item_id) || self.rooms.values().any(|r| r.items.contains(&item_id))
+    }
+
+    /// Leaving a room or dying ends every fight
+    fn stop_fighting(&mut self) {
+        self.player.fighting = None;
+        for npc in &mut self.npcs {
+            npc.fighting = false;
+        }
+    }
+
+    /// The player swings at NPC `i`; returns the combat text
+    fn player_hits(&mut self, i: usize) -> String {
+        let (lo, hi) = if self.item_in_inventory("sword") {
+            (2, 6)
+        } else {
+            (1, 3)
+        };
+        let name = self.npcs[i].name;
+        if self.roll(1, 4) == 1 {
+            return format!("You miss the {}.\n", name);
+        }
+        let damage = self.roll(lo, hi);
+        let npc = &mut self.npcs[i];
+        npc.hp -= damage;
+        if npc.hp > 0 {
+            return format!(
+                "You hit the {} for {} damage. ({}/{})\n",
+                name, damage, npc.hp, npc.max_hp
+            );
+        }
+        let mut out = format!(
+            "You hit the {} for {} damage.\n\x1b[1;31mThe {} is dead!\x1b[0m\n",
+            name, damage, name
+        );
+        npc.dead_ticks = RESPAWN_TICKS;
+        npc.fighting = false;
+        let location = npc.location;
+        let loot = npc.loot.clone();
+        self.player.fighting = None;
+        for item in loot {
+            if self.item_exists(item) {
+                continue;
+            }
+            self.rooms.get_mut(location).unwrap().items.push(item);
+            if let Some(it) = self.items.get(item) {
+                out.push_str(&format!("The {} drops {}.\n", name, it.name));
+            }
+        }
+        out
+    }
+
+    /// NPC `i` swings at the player; returns the combat text
+    fn npc_hits(&mut self, i: usize) -> String {
+        let (lo, hi) = self.npcs[i].damage;
+        let name = self.npcs[i].name;
+        if self.roll(1, 4) == 1 {
+            return format!("The {} misses you.\n", name);
+        }
+        let damage = self.roll(lo, hi);
+        self.player.hp -= damage;
+        if self.player.hp > 0 {
+            return format!(
+                "\x1b[31mThe {} hits you for {} damage.\x1b[0m\n",
+                name, damage
+            );
+        }
+        // Death: back to the clearing at full health, keeping inventory
+        self.stop_fighting();
+        self.player.hp = self.player.max_hp;
+        self.player.location = "clearing";
+        format!(
+            "\x1b[31mThe {} hits you for {} damage.\x1b[0m\n\x1b[1;31mYou have been killed by the {}!\x1b[0m\nYou wake up in the forest clearing.\n",
+            name, damage, name
+        )
+    }
+
+    /// `attack <npc>`: start a fight and take the first swing
+    pub fn attack(&mut self, target: &str) -> String {
+        let Some(i) = self.npc_here(target) else {
+            return format_error("You don't see that here.");
+        };
+        self.player.fighting = Some(self.npcs[i].id);
+        self.npcs[i].fighting = true;
+        format!("You attack the {}!\n", self.npcs[i].name) + &self.player_hits(i)
+    }
+
+    /// `flee`: leave through a random exit, ending the fight
+    pub fn flee(&mut self) -> String {
+        if self.player.fighting.is_none() && !self.npcs.iter().any(|n| n.fighting) {
+            return format_error("You aren't fighting anyone.");
+        }
+        let mut exits: Vec<Direction> = self.current_room().exits.keys().copied().collect();
+        exits.sort();
+        if exits.is_empty() {
+            return format_error("There is nowhere to run!");
+        }
+        let dir = exits[self.roll(0, exits.len() as i32 - 1) as usize];
+        let _ = self.move_player(dir);
+        format!("You flee {}!\n", format!("{:?}", dir).to_lowercase()) + &self.format_look()
+    }
+
+    /// One world tick: combat rounds, aggression, regeneration, wandering
+    /// and respawns. Returns what the player sees (empty if nothing).
+    pub fn tick(&mut self) -> String {
+        let mut out = String::new();
+        let here = self.player.location;
+
+        // The player's swing at their target
+        if let Some(target) = self.player.fighting {
+            match self
+                .npcs
+                .iter()
+                .position(|n| n.id == target && n.is_alive() && n.location == here)
+            {
+                Some(i) => out.push_str(&self.player_hits(i)),
+                None => self.player.fighting = None,
+            }
+        }
+
+        // NPCs fighting the player (aggressive ones start on sight)
+        for i in 0..self.npcs.len() {
+            let npc = &self.npcs[i];
+            if !npc.is_alive() || npc.location != self.player.location {
+                continue;
+            }
+            if !npc.fighting && npc.aggressive {
+                out.push_str(&format!("\x1b[1;31mThe {} attacks you!\x1b[0m\n", npc.name));
+                self.npcs[i].fighting = true;
+            }
+            if self.npcs[i].fighting {
+                out.push_str(&self.npc_hits(i));
+                if self.player.location != here {
+                    break; // Died and respawned elsewhere
+                }
+            }
+        }
+
+        // Out of combat the player heals
+        let in_combat = self.npcs.iter().any(|n| n.fighting);
+        if !in_combat && self.player.hp < self.player.max_hp {
+            self.player.hp += 1;
+        }
+
+        for i in 0..self.npcs.len() {
+            // Respawn at home
+            if !self.npcs[i].is_alive() {
+                let npc = &mut self.npcs[i];
+                npc.dead_ticks -= 1;
+                if npc.dead_ticks == 0 {
+                    npc.hp = npc.max_hp;
+                    npc.location = npc.home;
+                    if npc.location == self.player.location {
+                        out.push_str(&format!("A {} arrives.\n", npc.name));
+                    }
+                }
+                continue;
+            }
+            // Wander through a random exit now and then
+            if !self.npcs[i].wanders || self.npcs[i].fighting || self.roll(1, 4) != 1 {
+                continue;
+            }
+            let from = self.npcs[i].location;
+            let mut exits: Vec<(Direction, RoomId)> = self.rooms[from]
+                .exits
+                .iter()
+                .map(|(d, r)| (*d, *r))
+                .collect();
+            exits.sort();
+            if exits.is_empty() {
+                continue;
+            }
+            let (dir, to) = exits[self.roll(0, exits.len() as i32 - 1) as usize];
+            self.npcs[i].location = to;
+            let name = self.npcs[i].name;
+            if from == self.player.location {
+                out.push_str(&format!(
+                    "The {} leaves {}.\n",
+                    name,
+                    format!("{:?}", dir).to_lowercase()
+                ));
+            } else if to == self.player.location {
+                out.push_str(&format!("A {} arrives.\n", name));
+            }
+        }
+        out
+    }
+
     pub fn execute(&mut self, cmd: super::parser::Command) -> String {
         use super::parser::Command;
 
@@ -295,6 +611,9 @@ impl World {
                 }
             }
             Command::Inventory => self.format_inventory(),
+            Command::Attack(target) => self.attack(&target),
+            Command::Flee => self.flee(),
+            Command::Score => self.format_score(),
             Command::Help => format_help(),
             Command::Quit => "\x1b[33mGoodbye!\x1b[0m\n".to_string(),
         }
@@ -332,9 +651,31 @@ impl World {
             output.push_str(&format!("\x1b[33mItems: {}\x1b[0m\n", item_list.join(", ")));
         }
 
+        // Creatures (magenta)
+        for npc in &self.npcs {
+            if npc.is_alive() && npc.location == room.id {
+                output.push_str(&format!("\x1b[35mA {} is here.\x1b[0m\n", npc.name));
+            }
+        }
+
         output
     }
 
+    fn format_score(&self) -> String {
+        let mut out = format!("HP: {}/{}\n", self.player.hp, self.player.max_hp);
+        if let Some(npc) = self
+            .player
+            .fighting
+            .and_then(|id| self.npcs.iter().find(|n| n.id == id))
+        {
+            out.push_str(&format!(
+                "Fighting: {} ({}/{})\n",
+                npc.name, npc.hp, npc.max_hp
+            ));
+        }
+        out
+    }
+
     fn format_inventory(&self) -> String {
         if self.player.inventory.is_empty() {
             "You are carrying nothing.\n".to_string()
@@ -363,6 +704,10 @@ fn format_help() -> String {
     help.push_str("  take <item>      - Pick up an item\n");
     help.push_str("  drop <item>      - Drop an item\n");
     help.push_str("  inventory (i)    - Show your inventory\n");
+    help.push_str("  attack <target>  - Fight a creature (also kill, k)\n");
+    help.push_str("  flee             - Run from a fight through a random exit\n");
+    help.push_str("  score            - Show your hit points\n");
+    help.push_str("  loadarea <file>  - Replace the world with a JSON area file\n");
     help.push_str("  help (?)         - Show this help\n");
     help.push_str("  quit (q)         - Quit the game\n");
     help
@@ -377,7 +722,9 @@ mod tests {
         let world = World::new();
         assert_eq!(world.player.location, "clearing");
         assert_eq!(world.rooms.len(), 5);
-        assert_eq!(world.items.len(), 3);
+        assert_eq!(world.items.len(), 4);
+        assert_eq!(world.npcs.len(), 3);
+        assert_eq!(world.player.hp, PLAYER_MAX_HP);
     }
 
     #[test]
@@ -418,6 +765,96 @@ mod tests {
         assert_eq!(world.player.inventory.len(), 0);
     }
 
+    #[test]
+    fn test_combat_loot_and_respawn() {
+        let mut world = World::new();
+        world.move_player(Direction::East).unwrap();
+        assert!(world.format_look().contains("snarling goblin is here"));
+        assert!(world.attack("dragon").contains("You don't see that here"));
+
+        let out = world.attack("goblin");
+        assert!(out.starts_with("You attack the snarling goblin!"));
+        assert_eq!(world.player.fighting, Some("goblin"));
+
+        // Fight it out; the goblin can't kill a 30 HP player this fast
+        world.player.hp = 1000;
+        let mut log = String::new();
+        while world.npcs[1].is_alive() {
+            log.push_str(&world.tick());
+        }
+        assert!(log.contains("The snarling goblin is dead!"));
+        assert!(log.contains("The snarling goblin drops gold coins."));
+        assert!(world.item_in_room("coins"));
+        assert_eq!(world.player.fighting, None);
+        assert!(!world.format_look().contains("goblin is here"));
+
+        // Back after RESPAWN_TICKS, without a second set of coins
+        let log: String = (0..RESPAWN_TICKS).map(|_| world.tick()).collect();
+        assert!(log.contains("A snarling goblin arrives."));
+        assert!(world.npcs[1].is_alive());
+        world.npcs[1].hp = 1;
+        world.player.hp = 1000;
+        world.attack("goblin");
+        while world.npcs[1].is_alive() {
+            world.tick();
+        }
+        let coins = world.current_room().items.iter().filter(|&&i| i == "coins");
+        assert_eq!(coins.count(), 1);
+    }
+
+    #[test]
+    fn test_aggressive_npc_attacks_and_player_dies() {
+        let mut world = World::new();
+        world.move_player(Direction::East).unwrap();
+        world.player.hp = 1;
+        world.npcs[1].damage = (5, 5);
+        let mut log = String::new();
+        while world.player.location == "cave" {
+            log.push_str(&world.tick());
+        }
+        assert!(log.contains("The snarling goblin attacks you!"));
+        assert!(log.contains("You have been killed by the snarling goblin!"));
+        assert_eq!(world.player.location, "clearing");
+        assert_eq!(world.player.hp, PLAYER_MAX_HP);
+        assert!(!world.npcs[1].fighting);
+    }
+
+    #[test]
+    fn test_flee_ends_fight_and_regenerates() {
+        let mut world = World::new();
+        assert!(world.flee().contains("You aren't fighting"));
+        world.move_player(Direction::East).unwrap();
+        world.tick(); // The goblin attacks
+        assert!(world.npcs[1].fighting);
+        let out = world.flee();
+        assert!(out.starts_with("You flee west!"));
+        assert_eq!(world.player.location, "clearing");
+        assert!(!world.npcs[1].fighting);
+
+        world.player.hp = 10;
+        world.npcs[2].wanders = false; // Keep the wolf out of the clearing
+        world.tick();
+        assert_eq!(world.player.hp, 11);
+        assert!(world.format_score().starts_with("HP: 11/30"));
+    }
+
+    #[test]
+    fn test_wanderers_move_through_exits() {
+        let mut world = World::new();
+        world.seed(7);
+        let start = world.npcs[0].location;
+        let mut moved = false;
+        for _ in 0..50 {
+            world.tick();
+            let rabbit = &world.npcs[0];
+            moved |= rabbit.location != start;
+            assert!(world.rooms.contains_key(rabbit.location));
+        }
+        assert!(moved);
+        // The goblin stays home
+        assert_eq!(world.npcs[1].location, "cave");
+    }
+
     #[test]
     fn test_inventory_full() {
         let mut world = World::new();
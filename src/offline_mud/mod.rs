@@ -1,8 +1,123 @@
 // Internal MUD for offline demo and testing
 // Ported from toys/toy12_internal_mud/
 
+pub mod area;
 pub mod game;
 pub mod parser;
 
 pub use game::World;
 pub use parser::{parse, Command};
+
+use std::time::Duration;
+
+/// Command line that advances the world one tick (NPC moves, combat rounds).
+/// The offline modes schedule it as an engine ticker, so the world acts on
+/// its own like a real MUD; `#untick` pauses it and typing it steps it.
+pub const WORLD_TICK: &str = "@tick";
+
+/// How often the world ticks
+pub const WORLD_TICK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The built-in world standing in for a MUD connection: command lines in,
+/// MUD output out. The TTY loop and the control server both feed its output
+/// through a Session like network data.
+pub struct OfflineWorld {
+    pub world: World,
+    quit: bool,
+}
+
+impl Default for OfflineWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OfflineWorld {
+    pub fn new() -> Self {
+        Self {
+            world: World::new(),
+            quit: false,
+        }
+    }
+
+    /// Start in an area file's world instead of the built-in one
+    pub fn with_area(path: &str) -> Result<Self, String> {
+        Ok(Self {
+            world: area::load_area(path)?,
+            quit: false,
+        })
+    }
+
+    /// `loadarea <file>`: replace the world; the old one stays on error
+    pub fn load_area(&mut self, path: &str) -> String {
+        match area::AreaFile::load(path).and_then(|a| {
+            let name = a.name.clone();
+            a.into_world().map(|w| (name, w))
+        }) {
+            Ok((name, world)) => {
+                self.world = world;
+                let name = if name.is_empty() { path } else { &name };
+                format!("\x1b[33mLoaded area {}.\x1b[0m\n", name) + &self.intro()
+            }
+            Err(e) => format!("\x1b[31m{}\x1b[0m\n", e),
+        }
+    }
+
+    /// Description of the starting room, shown on "connect"
+    pub fn intro(&mut self) -> String {
+        self.world.execute(Command::Look)
+    }
+
+    /// Run one command line; parse errors come back in red
+    pub fn command(&mut self, line: &str) -> String {
+        let line = line.trim();
+        if line == WORLD_TICK {
+            return self.world.tick();
+        }
+        // Before parse(), which lowercases: file names keep their case
+        let (verb, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if verb.eq_ignore_ascii_case("loadarea") {
+            return match rest.trim() {
+                "" => "\x1b[31mLoad which area file?\x1b[0m\n".to_string(),
+                path => self.load_area(path),
+            };
+        }
+        match parse(line) {
+            Ok(cmd) => {
+                if cmd == Command::Quit {
+                    self.quit = true;
+                }
+                self.world.execute(cmd)
+            }
+            Err(e) => format!("\x1b[31m{}\x1b[0m\n", e),
+        }
+    }
+
+    /// The player typed quit
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_world_runs_commands() {
+        let mut w = OfflineWorld::new();
+        assert!(!w.intro().is_empty());
+        assert!(w.command("xyzzy plugh").starts_with("\x1b[31m"));
+        assert!(!w.quit_requested());
+        assert!(w
+            .command("loadarea /nonexistent.json")
+            .contains("Failed to read"));
+        assert_eq!(w.world.rooms.len(), 5);
+        w.world.player.location = "cave";
+        assert!(w
+            .command(WORLD_TICK)
+            .contains("The snarling goblin attacks you!"));
+        w.command("quit");
+        assert!(w.quit_requested());
+    }
+}
@@ -7,6 +7,9 @@ pub enum Command {
     Take(String),
     Drop(String),
     Inventory,
+    Attack(String),
+    Flee,
+    Score,
     Help,
     Quit,
 }
@@ -59,6 +62,16 @@ pub fn parse(input: &str) -> Result<Command, String> {
         // Inventory
         "inventory" | "inv" | "i" => Ok(Command::Inventory),
 
+        // Combat
+        "attack" | "kill" | "k" => {
+            if parts.len() < 2 {
+                return Err("Attack what?".to_string());
+            }
+            Ok(Command::Attack(parts[1..].join(" ")))
+        }
+        "flee" => Ok(Command::Flee),
+        "score" | "sc" | "hp" => Ok(Command::Score),
+
         // Meta
         "help" | "?" => Ok(Command::Help),
         "quit" | "q" | "exit" => Ok(Command::Quit),
@@ -124,6 +137,21 @@ mod tests {
         assert_eq!(parse("i"), Ok(Command::Inventory));
     }
 
+    #[test]
+    fn test_parse_combat() {
+        assert_eq!(
+            parse("kill goblin"),
+            Ok(Command::Attack("goblin".to_string()))
+        );
+        assert_eq!(
+            parse("attack grey wolf"),
+            Ok(Command::Attack("grey wolf".to_string()))
+        );
+        assert!(parse("k").is_err());
+        assert_eq!(parse("flee"), Ok(Command::Flee));
+        assert_eq!(parse("score"), Ok(Command::Score));
+    }
+
     #[test]
     fn test_parse_meta() {
         assert_eq!(parse("help"), Ok(Command::Help));
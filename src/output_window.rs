@@ -1,5 +1,6 @@
-use crate::scrollback::{Attrib, Scrollback};
+use crate::scrollback::{Attrib, Scrollback, MAX_LINES, MIN_LINES};
 use crate::window::Window;
+use std::ops::Range;
 
 /// Search highlight information (C++ OutputWindow.cc:37-42)
 #[derive(Default)]
@@ -21,6 +22,8 @@ pub struct OutputWindow {
     cursor_x: usize, // Cursor position within scrollback (C++ Window.h:72-73)
     cursor_y: usize,
     highlight: Highlight,
+    highlight_color: Option<u8>, // Search match color (None = swap fg/bg)
+    selection: Option<Range<usize>>, // Copy mode selection (offsets into sb.buf)
 }
 
 impl OutputWindow {
@@ -41,23 +44,42 @@ impl OutputWindow {
                 x: 0,
                 len: 0,
             },
+            highlight_color: None,
+            selection: None,
         }
     }
 
+    /// Default color for blank space and status messages (#color output)
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+        self.win.color = color;
+        self.win.dirty = true;
+    }
+
+    /// Color for the scrollback search match (None = inverse video)
+    pub fn set_highlight_color(&mut self, color: Option<u8>) {
+        self.highlight_color = color;
+        self.win.dirty = true;
+    }
+
     /// Print characters to scrollback like C++ Window::print (Window.cc:169-247)
-    /// Writes character-by-character at cursor position
-    pub fn print(&mut self, s: &[u8], color: u8) {
+    /// Writes character-by-character at cursor position; `color` may carry
+    /// text attributes (color::UNDERLINE etc.) above the color byte
+    pub fn print(&mut self, s: &[u8], color: u16) {
         self.win.dirty = true;
 
         for &ch in s {
             if ch == b'\n' {
+                self.sb.end_line();
                 // Move to next line (C++ line 215-220)
                 self.cursor_x = 0;
                 self.cursor_y += 1;
-            } else if ch == b'\r' {
-                // Ignore carriage return (handled in ANSI parser)
+            } else if ch == b'\r' || ch == 0 {
+                // Ignore carriage return (handled in ANSI parser); NUL marks wrap padding
                 continue;
             } else {
+                self.sb
+                    .record_cell(((color as Attrib) << 8) | (ch as Attrib));
                 // Regular character - need to scroll if at bottom
                 while self.cursor_y >= self.sb.height {
                     self.scroll_one_line();
@@ -66,7 +88,7 @@ impl OutputWindow {
                 // Write character at cursor position (relative to canvas_off like C++ canvas pointer)
                 let offset = self.sb.canvas_off + self.cursor_y * self.sb.width + self.cursor_x;
                 if offset < self.sb.buf.len() {
-                    self.sb.buf[offset] = ((color as u16) << 8) | (ch as u16);
+                    self.sb.buf[offset] = ((color as Attrib) << 8) | (ch as Attrib);
                 }
 
                 self.cursor_x += 1;
@@ -75,6 +97,9 @@ impl OutputWindow {
                 if self.cursor_x >= self.sb.width {
                     self.cursor_y += 1;
                     self.cursor_x = 0;
+                    if self.sb.word_wrap() && ch != b' ' {
+                        self.carry_word(color);
+                    }
                 }
             }
         }
@@ -83,6 +108,173 @@ impl OutputWindow {
         // We already set win.dirty = true at start of this function
     }
 
+    /// Print `text` as a line of its own, first ending a partly printed one
+    /// (C++ SOFT_CR: a newline only if the cursor isn't at a row's start)
+    pub fn print_soft_line(&mut self, text: &[u8], color: u16) {
+        if self.cursor_x != 0 {
+            self.print(b"\n", color);
+        }
+        self.print(text, color);
+        self.print(b"\n", color);
+    }
+
+    /// Word wrap: the row just filled ends mid-word, so move that word's
+    /// cells to the (new) cursor row and pad the gap with NUL cells
+    /// The word is moved even if it would have ended exactly at the edge
+    fn carry_word(&mut self, color: u16) {
+        let width = self.sb.width;
+        let row = self.sb.canvas_off + (self.cursor_y - 1) * width;
+        let Some(cells) = self.sb.buf.get(row..row + width) else {
+            return;
+        };
+        // Keep words longer than the row (no space to break at) hard-wrapped
+        let Some(space) = cells[1..]
+            .iter()
+            .rposition(|&a| (a & 0xFF) == b' ' as Attrib)
+        else {
+            return;
+        };
+        let word = cells[space + 2..].to_vec();
+        for a in &mut self.sb.buf[row + space + 2..row + width] {
+            *a = (color as Attrib) << 8;
+        }
+        while self.cursor_y >= self.sb.height {
+            self.scroll_one_line();
+        }
+        let offset = self.sb.canvas_off + self.cursor_y * width;
+        if offset + word.len() <= self.sb.buf.len() {
+            self.sb.buf[offset..offset + word.len()].copy_from_slice(&word);
+            self.cursor_x = word.len();
+        }
+    }
+
+    /// Wrap long lines at word boundaries (see carry_word)
+    pub fn set_word_wrap(&mut self, on: bool) {
+        self.sb.set_word_wrap(on);
+    }
+
+    /// Erase the last `n` printed cells, moving the cursor back over them
+    /// Used when a completed line is gagged or rewritten after being printed
+    /// Word wrap padding (NUL cells) is erased but not counted
+    pub fn unprint(&mut self, n: usize) {
+        self.win.dirty = true;
+        self.sb.unrecord(n);
+
+        let mut erased = 0;
+        while erased < n {
+            if self.cursor_x == 0 {
+                // Wrapped line - step back to the end of the previous row
+                if self.cursor_y == 0 {
+                    break;
+                }
+                self.cursor_y -= 1;
+                self.cursor_x = self.sb.width;
+            }
+            self.cursor_x -= 1;
+            let offset = self.sb.canvas_off + self.cursor_y * self.sb.width + self.cursor_x;
+            if offset < self.sb.buf.len() {
+                if self.sb.buf[offset] & 0xFF != 0 {
+                    erased += 1;
+                }
+                self.sb.buf[offset] = 0x0720;
+            } else {
+                erased += 1;
+            }
+        }
+    }
+
+    /// All rows written so far (oldest first), trailing blanks trimmed
+    /// The last row is the current, possibly incomplete, line
+    pub fn history_rows(&self) -> Vec<Vec<Attrib>> {
+        let rows = self.sb.canvas_off / self.sb.width + self.cursor_y + 1;
+        (0..rows)
+            .map(|r| {
+                // Cursor may sit one row past the canvas until the next char scrolls
+                let Some(row) = self.sb.buf.get(r * self.sb.width..(r + 1) * self.sb.width) else {
+                    return Vec::new();
+                };
+                let len = row
+                    .iter()
+                    .rposition(|&a| (a & 0xFF) != 0 && (a & 0xFF) != b' ' as Attrib)
+                    .map_or(0, |i| i + 1);
+                row[..len].to_vec()
+            })
+            .collect()
+    }
+
+    /// Re-print rows captured by history_rows() (used after #restart)
+    pub fn restore_history(&mut self, rows: &[Vec<Attrib>]) {
+        for (i, row) in rows.iter().enumerate() {
+            for &a in row {
+                self.print(&[(a & 0xFF) as u8], (a >> 8) as u16);
+            }
+            // A full row already wrapped the cursor onto the next one
+            if i + 1 < rows.len() && row.len() < self.sb.width {
+                self.print(b"\n", self.color as u16);
+            }
+        }
+    }
+
+    /// Change the window size, re-wrapping the scrollback's logical lines to
+    /// the new width; a view scrolled back stays on the same logical line
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if width == self.sb.width && height == self.sb.height {
+            return;
+        }
+        self.rebuild(width, height, self.sb.lines);
+    }
+
+    /// Change how many rows the scrollback ring holds (#buffer), refilling it
+    /// from the logical lines so nothing on screen is lost
+    pub fn set_scrollback_lines(&mut self, lines: usize) {
+        let lines = lines.max(MIN_LINES).max(self.sb.height * 2).min(MAX_LINES);
+        if lines != self.sb.lines {
+            self.rebuild(self.sb.width, self.sb.height, lines);
+        }
+    }
+
+    /// Rebuild the ring at a new size from the scrollback's logical lines
+    fn rebuild(&mut self, width: usize, height: usize, lines: usize) {
+        let old_width = self.sb.width;
+        let anchor = if self.sb.viewpoint < self.sb.canvas_off {
+            let bottom = self.sb.canvas_off / old_width + self.cursor_y;
+            let rows_up = bottom.saturating_sub(self.sb.viewpoint / old_width);
+            self.sb.line_at_row(old_width, rows_up)
+        } else {
+            None
+        };
+        let mut rows = self.sb.render(width, lines);
+        if self.cursor_x == 0 {
+            // The cursor starts a new row: keep it there
+            rows.push(Vec::new());
+        }
+        let word_wrap = self.sb.word_wrap();
+        let frozen = self.sb.is_frozen();
+        let fresh = Scrollback::new(width, height, lines);
+        let old = std::mem::replace(&mut self.sb, fresh);
+        self.win.resize(width, height);
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.highlight = Highlight {
+            line: -1,
+            x: 0,
+            len: 0,
+        };
+        self.selection = None;
+        // Rows are already wrapped; word wrap would move words again
+        self.restore_history(&rows);
+        // The logical lines are unchanged; keep them rather than the rows
+        self.sb.take_history(old);
+        self.sb.set_word_wrap(word_wrap);
+        self.sb.set_frozen(frozen);
+        if let Some(line) = anchor {
+            let rows = self.sb.canvas_off / width + self.cursor_y + 1;
+            let top = rows.saturating_sub(self.sb.rows_from_line(width, line));
+            self.sb.viewpoint = (top * width).min(self.sb.canvas_off);
+        }
+        self.win.dirty = true;
+    }
+
     /// Scroll by one line (C++ OutputWindow::scroll, lines 32-64)
     fn scroll_one_line(&mut self) {
         const COPY_LINES: usize = 250;
@@ -149,40 +341,38 @@ impl OutputWindow {
     /// Redraw window: blit scrollback viewport to canvas (C++ Window::redraw pattern)
     /// Updated to handle search highlighting (C++ OutputWindow::draw_on_parent lines 239-274)
     pub fn redraw(&mut self) {
-        let view = self.sb.viewport_slice();
+        // Copy mode selection, drawn with Scrollback::highlight_view
+        if let Some(sel) = &self.selection {
+            let view = self.sb.highlight_view(std::slice::from_ref(sel), None);
+            self.win.blit(&view);
+            return;
+        }
 
-        // Check if we need to highlight search result (C++ lines 246-248)
+        // Search result: inverse video (C++ lines 246-264) or the theme's
+        // highlight color; a match wrapped onto the next row stays lit
         if self.highlight.line >= 0 {
-            let viewpoint_line = (self.sb.viewpoint / self.sb.width) + self.sb.top_line;
-            let highlight_line = self.highlight.line as usize;
-
-            // Is highlighted line visible in viewport? (C++ lines 246-248)
-            if highlight_line >= viewpoint_line && highlight_line < viewpoint_line + self.sb.height
-            {
-                let line_in_view = highlight_line - viewpoint_line;
-                let start_offset = line_in_view * self.sb.width + self.highlight.x;
-                let end_offset = start_offset + self.highlight.len;
-
-                // Create modified view with inverted colors for highlight (C++ lines 251-264)
-                let mut modified_view = view.to_vec();
-
-                if end_offset <= modified_view.len() {
-                    for attrib in &mut modified_view[start_offset..end_offset] {
-                        // Invert colors: swap foreground and background (C++ lines 259-263)
-                        let color = ((*attrib & 0xFF00) >> 8) as u8;
-                        let bg = (color & 0x0F) << 4;
-                        let fg = (color & 0xF0) >> 4;
-                        *attrib = (*attrib & 0x00FF) | (((bg | fg) as u16) << 8);
-                    }
-
-                    self.win.blit(&modified_view);
-                    return;
-                }
+            if let Some(row) = (self.highlight.line as usize).checked_sub(self.sb.top_line) {
+                let start = row * self.sb.width + self.highlight.x;
+                let span = start..start + self.highlight.len;
+                let view = self.sb.highlight_view(&[span], self.highlight_color);
+                self.win.blit(&view);
+                return;
             }
         }
 
         // Normal blit without highlighting
-        self.win.blit(view);
+        self.win.blit(self.sb.viewport_slice());
+    }
+
+    /// Highlight a span of the scrollback (offsets into sb.buf), None to clear
+    pub fn set_selection(&mut self, selection: Option<Range<usize>>) {
+        self.selection = selection;
+        self.win.dirty = true;
+    }
+
+    /// Scrollback row the cursor is on (the newest row written)
+    pub fn cursor_row(&self) -> usize {
+        self.sb.canvas_off / self.sb.width + self.cursor_y
     }
 
     /// Get viewport for direct rendering
@@ -408,7 +598,8 @@ impl OutputWindow {
                     last_color = color;
                 }
 
-                // Write character (C++ line 315)
+                // Write character (C++ line 315); empty and padding cells as spaces
+                let ch = if ch < 32 { b' ' } else { ch };
                 if let Err(e) = write!(file, "{}", ch as char) {
                     return Some(format!("Write error: {}", e));
                 }
@@ -468,7 +659,7 @@ mod tests {
         assert_eq!(&text[0..5], b"hello");
         assert_eq!(&text[5..10], b"world");
         // Render diff from blank to current
-        let prev = vec![0u16; v.len()];
+        let prev = vec![0; v.len()];
         let s = diff_to_ansi(
             &prev,
             v,
@@ -480,6 +671,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.contains("hello"));
@@ -487,6 +680,161 @@ mod tests {
         assert!(s.contains("worl"));
     }
 
+    #[test]
+    fn unprint_erases_across_wrap() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 4, 3, 20, 0);
+        ow.print(b"ab\n", 0x07);
+        ow.print(b"spammy", 0x07); // wraps onto a second row
+        ow.unprint(6);
+        ow.print(b"cd", 0x07);
+        ow.redraw();
+        let text: Vec<u8> = ow.viewport().iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(&text[0..2], b"ab");
+        assert_eq!(&text[4..8], b"cd  ");
+        assert!(!text.contains(&b's'));
+    }
+
+    #[test]
+    fn word_wrap_moves_word_and_unprints_padding() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 8, 3, 20, 0);
+        ow.set_word_wrap(true);
+        ow.print(b"hello world", 0x07);
+        ow.redraw();
+        let text: Vec<u8> = ow.viewport().iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(&text[0..6], b"hello ");
+        assert_eq!(&text[8..13], b"world");
+
+        // Rewriting the line erases the padding without over-counting
+        ow.unprint(11);
+        ow.print(b"hi", 0x07);
+        ow.redraw();
+        let text: Vec<u8> = ow.viewport().iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(&text[0..8], b"hi      ");
+        assert!(!text.contains(&b'w'));
+    }
+
+    #[test]
+    fn history_rows_round_trip() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 4, 2, 20, 0);
+        ow.print(b"one\ntwo\nthree\nfo", 0x07);
+        let rows = ow.history_rows();
+        assert_eq!(rows.len(), 5); // "thre" wraps onto "e"
+
+        let mut restored = OutputWindow::new(ptr::null_mut(), 4, 2, 20, 0);
+        restored.restore_history(&rows);
+        assert_eq!(restored.history_rows(), rows);
+    }
+
+    #[test]
+    fn resize_keeps_history() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 10, 3, 20, 0x07);
+        ow.print(b"alpha\nbeta\ngamma\ndelta", 0x07);
+        ow.resize(6, 2);
+        assert_eq!((ow.win.width, ow.win.height), (6, 2));
+        ow.redraw();
+        let text: Vec<u8> = ow.viewport().iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(&text, b"gamma delta ");
+        assert_eq!(ow.history_rows().len(), 4);
+    }
+
+    #[test]
+    fn soft_line_starts_a_row_only_when_needed() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 8, 3, 20, 0x07);
+        ow.print(b"HP 10> ", 0x07);
+        ow.print_soft_line(b"north", 0x83);
+        ow.print_soft_line(b"look", 0x83);
+        let rows = ow.history_rows();
+        let text: Vec<String> = rows
+            .iter()
+            .map(|r| crate::scrollback::row_text(r))
+            .collect();
+        assert_eq!(text, vec!["HP 10>", "north", "look", ""]);
+        assert_eq!(rows[1][0] >> 8, 0x83);
+    }
+
+    #[test]
+    fn resize_rewraps_lines() {
+        use std::ptr;
+
+        let text = |ow: &mut OutputWindow| -> Vec<u8> {
+            ow.redraw();
+            let cells = ow.viewport().iter().map(|a| (a & 0xFF) as u8);
+            cells.map(|c| if c == 0 { b' ' } else { c }).collect()
+        };
+        let mut ow = OutputWindow::new(ptr::null_mut(), 5, 3, 40, 0x07);
+        ow.print(b"abcdefgh\nxyz", 0x07);
+        assert_eq!(&text(&mut ow), b"abcdefgh  xyz  ");
+        ow.resize(10, 2);
+        assert_eq!(&text(&mut ow), b"abcdefgh  xyz       ");
+        ow.print(b"!", 0x07);
+        ow.resize(4, 3);
+        assert_eq!(&text(&mut ow), b"abcdefghxyz!");
+        assert_eq!(ow.sb.history().len(), 1);
+    }
+
+    #[test]
+    fn scrollback_resize_keeps_lines() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 10, 3, 600, 0x07);
+        for i in 0..700 {
+            ow.print(format!("line {}\n", i).as_bytes(), 0x07);
+        }
+        let before = ow.sb.memory_bytes();
+        ow.set_scrollback_lines(1000);
+        assert_eq!(ow.sb.lines(), 1000);
+        assert!(ow.sb.memory_bytes() > before);
+        // Lines the old ring had already dropped come back from the history
+        let rows = ow.history_rows();
+        assert_eq!(rows.len(), 701);
+        assert_eq!(crate::scrollback::row_text(&rows[0]), "line 0");
+        assert_eq!(crate::scrollback::row_text(&rows[699]), "line 699");
+        // Too small a ring is raised to the minimum
+        ow.set_scrollback_lines(10);
+        assert_eq!(ow.sb.lines(), MIN_LINES);
+        assert_eq!(Scrollback::new(10, 3, usize::MAX).lines(), MAX_LINES);
+        assert!(ow
+            .sb
+            .usage_text()
+            .starts_with("Scrollback: 500 rows; 700 lines kept"));
+    }
+
+    #[test]
+    fn resize_keeps_scrolled_view_on_its_line() {
+        use std::ptr;
+
+        let mut ow = OutputWindow::new(ptr::null_mut(), 8, 2, 40, 0x07);
+        for i in 0..10 {
+            ow.print(format!("line {} of ten\n", i).as_bytes(), 0x07);
+        }
+        ow.sb.set_frozen(true);
+        while ow
+            .sb
+            .line_at_row(8, ow.sb.canvas_off / 8 + 1 - ow.sb.viewpoint / 8)
+            != Some(4)
+        {
+            ow.sb.line_up();
+        }
+        ow.resize(16, 2);
+        ow.redraw();
+        let top: Vec<u8> = ow.viewport()[..16]
+            .iter()
+            .map(|a| (a & 0xFF) as u8)
+            .collect();
+        assert_eq!(&top, b"line 4 of ten   ");
+        assert!(ow.sb.viewpoint < ow.sb.canvas_off);
+    }
+
     #[test]
     fn save_to_file_plain_text() {
         use std::fs;
@@ -0,0 +1,274 @@
+// Pane - a layout pane showing something other than the session output
+//
+// The output pane is the session's OutputWindow; every other pane (chat,
+// map, log tail) is a Window with a bounded list of plain-text lines,
+// wrapped to the pane width and drawn bottom-aligned like the scrollback.
+
+use crate::layout::{Rect, Source};
+use crate::scrollback::wrap_rows;
+use crate::window::Window;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Lines kept per pane
+const MAX_LINES: usize = 500;
+
+/// How often a log pane checks its file
+const LOG_POLL: Duration = Duration::from_secs(1);
+
+/// Most of a log file read at once: a big log (or a burst between polls)
+/// starts this far from its end, which is plenty for MAX_LINES lines
+const LOG_TAIL: u64 = 64 * 1024;
+
+/// File being followed by a log pane
+struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>, // Bytes after the last newline
+    last_poll: Option<Instant>,
+}
+
+pub struct TextPane {
+    pub win: Box<Window>,
+    lines: VecDeque<String>,
+    scroll: usize, // Rows scrolled back from the bottom
+    filter: Option<Regex>,
+    log: Option<LogTail>,
+}
+
+impl TextPane {
+    pub fn new(parent: *mut Window, source: &Source, rect: Rect, color: u8) -> Self {
+        let mut win = Window::new(parent, rect.width, rect.height);
+        win.color = color;
+        win.parent_x = rect.x as isize;
+        win.parent_y = rect.y as isize;
+        let mut pane = Self {
+            win,
+            lines: VecDeque::new(),
+            scroll: 0,
+            filter: None,
+            log: None,
+        };
+        match source {
+            Source::Output => {}
+            Source::Chat(pattern) => pane.filter = Regex::new(pattern).ok(),
+            Source::Map => pane.push("No mapper loaded"),
+            Source::Log(path) => {
+                pane.log = Some(LogTail {
+                    path: path.clone(),
+                    offset: 0,
+                    partial: Vec::new(),
+                    last_poll: None,
+                });
+                pane.poll(Instant::now());
+            }
+        }
+        pane
+    }
+
+    /// Move/resize to a layout rectangle
+    pub fn place(&mut self, rect: Rect) {
+        if (rect.width, rect.height) != (self.win.width, self.win.height) {
+            self.win.resize(rect.width, rect.height);
+        }
+        self.win.parent_x = rect.x as isize;
+        self.win.parent_y = rect.y as isize;
+        self.win.dirty = true;
+    }
+
+    fn push(&mut self, line: &str) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.to_string());
+        self.win.dirty = true;
+    }
+
+    /// Offer a completed MUD line; chat panes keep the ones matching their pattern
+    pub fn mud_line(&mut self, line: &str) {
+        if self.filter.as_ref().is_some_and(|re| re.is_match(line)) {
+            self.push(line);
+        }
+    }
+
+    /// Log panes: pick up lines appended to the file (at most once per LOG_POLL)
+    pub fn poll(&mut self, now: Instant) {
+        let Some(log) = self.log.as_mut() else {
+            return;
+        };
+        if log
+            .last_poll
+            .is_some_and(|at| now.duration_since(at) < LOG_POLL)
+        {
+            return;
+        }
+        log.last_poll = Some(now);
+        let Ok(mut file) = File::open(&log.path) else {
+            return;
+        };
+        let len = file.metadata().map_or(0, |m| m.len());
+        if len < log.offset {
+            // Truncated or replaced: start over
+            log.offset = 0;
+            log.partial.clear();
+        }
+        // Too far behind: jump to the tail, from the byte before it so a
+        // line cut in half can be told from one that starts there
+        let skip_to = len.saturating_sub(LOG_TAIL);
+        let skipping = skip_to > log.offset;
+        let start = if skipping { skip_to - 1 } else { log.offset };
+        if len == log.offset || file.seek(SeekFrom::Start(start)).is_err() {
+            return;
+        }
+        let mut data = Vec::new();
+        if file.read_to_end(&mut data).is_err() {
+            return;
+        }
+        log.offset = start + data.len() as u64;
+        if skipping {
+            // Drop the cut line (or just the newline before the tail)
+            log.partial.clear();
+            let first = data
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(data.len(), |i| i + 1);
+            data.drain(..first);
+        }
+        log.partial.extend_from_slice(&data);
+        let Some(end) = log.partial.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let complete: Vec<u8> = log.partial.drain(..=end).collect();
+        for line in String::from_utf8_lossy(&complete).lines() {
+            self.push(line.trim_end_matches('\r'));
+        }
+    }
+
+    /// Scroll back (up) or forward a page; false at the end
+    pub fn page(&mut self, up: bool) -> bool {
+        let rows = self.rows().len();
+        let max = rows.saturating_sub(self.win.height);
+        let page = self.win.height.max(1);
+        let scroll = if up {
+            (self.scroll + page).min(max)
+        } else {
+            self.scroll.saturating_sub(page)
+        };
+        let moved = scroll != self.scroll;
+        self.scroll = scroll;
+        self.win.dirty = true;
+        moved
+    }
+
+    /// Every line wrapped to the pane width
+    fn rows(&self) -> Vec<&[u8]> {
+        let width = self.win.width.max(1);
+        self.lines
+            .iter()
+            .flat_map(|line| {
+                let bytes = line.as_bytes();
+                wrap_rows(bytes, width, true)
+                    .into_iter()
+                    .map(move |r| &bytes[r])
+            })
+            .collect()
+    }
+
+    /// Paint the newest rows (minus any scroll) bottom-aligned
+    pub fn redraw(&mut self) {
+        let color = self.win.color;
+        let rows: Vec<Vec<u8>> = self.rows().into_iter().map(<[u8]>::to_vec).collect();
+        let height = self.win.height;
+        let end = rows.len().saturating_sub(self.scroll.min(rows.len()));
+        let start = end.saturating_sub(height);
+        let top = height - (end - start);
+        self.win.clear();
+        for (i, row) in rows[start..end].iter().enumerate() {
+            for (x, &ch) in row.iter().enumerate() {
+                self.win.put_char(x, top + i, ch, color);
+            }
+        }
+        self.win.dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::CHAT_PATTERN;
+    use std::ptr;
+
+    fn text(pane: &TextPane) -> Vec<String> {
+        pane.win
+            .canvas
+            .chunks(pane.win.width)
+            .map(|row| {
+                let s: String = row.iter().map(|a| (a & 0xFF) as u8 as char).collect();
+                s.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chat_pane_filters_and_wraps() {
+        let source = Source::Chat(CHAT_PATTERN.to_string());
+        let mut pane = TextPane::new(ptr::null_mut(), &source, Rect::new(0, 0, 12, 3), 0x07);
+        pane.mud_line("You are hungry.");
+        pane.mud_line("Bob tells you 'hi there'");
+        pane.redraw();
+        assert_eq!(text(&pane), vec!["Bob tells", "you 'hi", "there'"]);
+        pane.mud_line("Ann says 'yo'");
+        pane.redraw();
+        assert_eq!(text(&pane), vec!["there'", "Ann says", "'yo'"]);
+
+        assert!(pane.page(true));
+        pane.redraw();
+        assert_eq!(text(&pane)[0], "Bob tells");
+        assert!(pane.page(false));
+    }
+
+    #[test]
+    fn log_pane_follows_appends() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("okros_pane_{}.log", std::process::id()));
+        std::fs::write(&path, "first\nsecond\npart").unwrap();
+        let source = Source::Log(path.clone());
+        let mut pane = TextPane::new(ptr::null_mut(), &source, Rect::new(0, 0, 20, 3), 0x07);
+        pane.redraw();
+        assert_eq!(text(&pane), vec!["", "first", "second"]);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"ial\nthird\n").unwrap();
+        pane.poll(Instant::now()); // Too soon after the first poll
+        pane.redraw();
+        assert_eq!(text(&pane)[2], "second");
+        pane.poll(Instant::now() + LOG_POLL);
+        pane.redraw();
+        assert_eq!(text(&pane), vec!["second", "partial", "third"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn log_pane_starts_at_the_tail_of_a_big_file() {
+        let path = std::env::temp_dir().join(format!("okros_pane_big_{}.log", std::process::id()));
+        // 1000-byte lines: only the last 65 or so fit in LOG_TAIL
+        let log: String = (0..1000)
+            .map(|n| format!("{:<999}\n", format!("line {}", n)))
+            .collect();
+        std::fs::write(&path, &log).unwrap();
+        let source = Source::Log(path.clone());
+        let pane = TextPane::new(ptr::null_mut(), &source, Rect::new(0, 0, 20, 3), 0x07);
+        assert!(pane.lines.len() < 70, "{} lines read", pane.lines.len());
+        assert_eq!(pane.lines.back().unwrap().trim_end(), "line 999");
+        assert!(pane.lines.iter().all(|l| l.starts_with("line 9")));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,235 @@
+// Path - walks recorded with #path and replayed as speedwalks
+//
+// `#path learn <name>` watches the commands sent to the MUD
+// and keeps the movement ones; `#path stop` stores them on the MUD as a
+// speedwalk string ("3n2ej" - the extended letters h/j/k/l are nw/ne/sw/se),
+// which `#path go <name>` hands to the command queue's speedwalk expansion.
+// Paths are saved like aliases (`path <name> <steps>` in the config).
+
+use crate::mud::Mud;
+
+/// Speedwalk letters a path may contain
+const STEP_LETTERS: &str = "nsewudhjkl";
+
+/// Longest run written as one count (the speedwalk repeat limit)
+const MAX_RUN: usize = 99;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub name: String,
+    pub steps: String, // Speedwalk without the speedwalk character
+}
+
+impl Path {
+    /// A path from config text; None if `steps` isn't a speedwalk
+    pub fn parse(name: &str, steps: &str) -> Option<Self> {
+        let valid = !name.is_empty()
+            && !name.contains(char::is_whitespace)
+            && steps.ends_with(|c: char| STEP_LETTERS.contains(c))
+            && steps
+                .chars()
+                .all(|c| c.is_ascii_digit() || STEP_LETTERS.contains(c));
+        valid.then(|| Self {
+            name: name.to_string(),
+            steps: steps.to_string(),
+        })
+    }
+}
+
+/// Speedwalk letter for a movement command ("n", "north", "ne"...)
+pub fn step_letter(command: &str) -> Option<char> {
+    Some(match command.trim().to_ascii_lowercase().as_str() {
+        "n" | "north" => 'n',
+        "s" | "south" => 's',
+        "e" | "east" => 'e',
+        "w" | "west" => 'w',
+        "u" | "up" => 'u',
+        "d" | "down" => 'd',
+        "nw" | "northwest" => 'h',
+        "ne" | "northeast" => 'j',
+        "sw" | "southwest" => 'k',
+        "se" | "southeast" => 'l',
+        _ => return None,
+    })
+}
+
+/// Run-length speedwalk for a list of step letters
+fn compress(steps: &[char]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < steps.len() {
+        let run = steps[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&c| c == steps[i])
+            .count();
+        if run > 1 {
+            out.push_str(&run.to_string());
+        }
+        out.push(steps[i]);
+        i += run;
+    }
+    out
+}
+
+/// A path being learned
+pub struct PathRecorder {
+    name: String,
+    steps: Vec<char>,
+}
+
+impl PathRecorder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Note a command sent to the MUD; true if it was a move
+    pub fn record(&mut self, command: &str) -> bool {
+        match step_letter(command) {
+            Some(c) => {
+                self.steps.push(c);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of moves so far
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn finish(self) -> Path {
+        Path {
+            steps: compress(&self.steps),
+            name: self.name,
+        }
+    }
+}
+
+/// What #path asks the front-end to do
+#[derive(Debug, PartialEq, Eq)]
+pub enum PathReply {
+    Message(String),
+    /// Walk a path: (name, speedwalk steps)
+    Walk(String, String),
+}
+
+/// #path [learn <name> | stop | go <name> | delete <name>]
+pub fn path_command(
+    args: &str,
+    recording: &mut Option<PathRecorder>,
+    mud: &mut Mud,
+) -> Result<PathReply, String> {
+    let (sub, name) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let name = name.trim();
+    let message = match (sub, name.is_empty()) {
+        ("", _) => {
+            let mut text = match recording {
+                Some(r) => format!("Learning path {} ({} moves)", r.name, r.len()),
+                None => String::from("Paths:"),
+            };
+            let mut paths: Vec<&Path> = mud.path_list.iter().collect();
+            let mut parent = mud.inherits.as_deref();
+            while let Some(p) = parent {
+                paths.extend(&p.path_list);
+                parent = p.inherits.as_deref();
+            }
+            if paths.is_empty() && recording.is_none() {
+                return Ok(PathReply::Message("No paths.".to_string()));
+            }
+            for path in paths {
+                text.push_str(&format!("\n  {} {}", path.name, path.steps));
+            }
+            text
+        }
+        ("learn", false) if name.contains(char::is_whitespace) => {
+            return Err("Path names are one word".to_string())
+        }
+        ("learn", false) => {
+            *recording = Some(PathRecorder::new(name));
+            format!("Learning path {}: walk it, then #path stop", name)
+        }
+        ("stop", _) => {
+            let recorder = recording.take().ok_or("Not learning a path")?;
+            if recorder.is_empty() {
+                return Err(format!("Path {} has no moves; not saved", recorder.name));
+            }
+            let path = recorder.finish();
+            let message = format!("Saved path {}: {}", path.name, path.steps);
+            mud.path_list.retain(|p| p.name != path.name);
+            mud.path_list.push(path);
+            message
+        }
+        ("go", false) => {
+            let path = mud
+                .find_path(name)
+                .ok_or_else(|| format!("No path {}", name))?;
+            return Ok(PathReply::Walk(path.name.clone(), path.steps.clone()));
+        }
+        ("delete", false) => {
+            let before = mud.path_list.len();
+            mud.path_list.retain(|p| p.name != name);
+            if mud.path_list.len() == before {
+                return Err(format!("No path {}", name));
+            }
+            format!("Deleted path {}", name)
+        }
+        _ => return Err("Usage: #path [learn <name>|stop|go <name>|delete <name>]".to_string()),
+    };
+    Ok(PathReply::Message(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_queue::{CommandQueue, EXPAND_SPEEDWALK};
+
+    #[test]
+    fn learns_and_walks_a_path() {
+        let mut mud = Mud::empty();
+        let mut rec = None;
+        assert!(path_command("stop", &mut rec, &mut mud).is_err());
+        path_command("learn bank", &mut rec, &mut mud).unwrap();
+        for cmd in ["n", "north", "say hi", "N", "east", "ne", "d"] {
+            rec.as_mut().unwrap().record(cmd);
+        }
+        assert_eq!(rec.as_ref().unwrap().len(), 6);
+        assert_eq!(
+            path_command("stop", &mut rec, &mut mud).unwrap(),
+            PathReply::Message("Saved path bank: 3nejd".to_string())
+        );
+        assert!(rec.is_none());
+
+        let PathReply::Walk(_, steps) = path_command("go bank", &mut rec, &mut mud).unwrap() else {
+            panic!("not a walk");
+        };
+        let mut cq = CommandQueue::new();
+        cq.add(&format!("/{}", steps), EXPAND_SPEEDWALK, false);
+        assert_eq!(cq.execute(), vec!["n", "n", "n", "e", "ne", "d"]);
+
+        assert!(path_command("go nowhere", &mut rec, &mut mud).is_err());
+        assert!(path_command("delete bank", &mut rec, &mut mud).is_ok());
+        assert!(mud.path_list.is_empty());
+    }
+
+    #[test]
+    fn compresses_long_runs() {
+        let steps = vec!['s'; 120];
+        assert_eq!(compress(&steps), "99s21s");
+        assert!(Path::parse("x", "99s21s").is_some());
+        assert!(Path::parse("x", "3q").is_none());
+        assert!(Path::parse("x", "3").is_none());
+    }
+}
@@ -0,0 +1,192 @@
+//! Native regex backend for actions (no embedded interpreter required)
+//!
+//! Implements the regex half of the Interpreter trait (match_prepare,
+//! substitute_prepare, match_exec, span_prepare/span_exec) with the regex
+//! crate, so triggers, substitutions, gags and highlights work in builds
+//! without the perl/python features. Script hooks (run/eval) are no-ops.
+//!
+//! Trigger commands and replacements may refer to capture groups as %1..%9,
+//! %0 for the whole match and %% for a literal percent sign.
+
+use crate::plugins::stack::Interpreter;
+use regex::{Captures, Regex};
+use std::any::Any;
+
+struct MatchPattern(Regex, String);
+struct SubstitutePattern(Regex, String);
+struct SpanPattern(Regex);
+
+/// Regex-only interpreter used when no Perl/Python interpreter is available
+#[derive(Default)]
+pub struct NativeRegex;
+
+impl NativeRegex {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Expand %N capture references in `template` from `caps`
+/// Unmatched/optional groups expand to nothing, like Perl's undef
+pub fn expand_captures(template: &str, caps: &Captures) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some('%') => {
+                chars.next();
+                out.push('%');
+            }
+            Some(d) if d.is_ascii_digit() => {
+                chars.next();
+                let n = d.to_digit(10).unwrap() as usize;
+                if let Some(m) = caps.get(n) {
+                    out.push_str(m.as_str());
+                }
+            }
+            _ => out.push('%'),
+        }
+    }
+    out
+}
+
+impl Interpreter for NativeRegex {
+    fn run(&mut self, _function: &str, _arg: &str, _out: &mut String) -> bool {
+        false
+    }
+
+    /// Compile trigger pattern; invalid patterns yield None like a failed Perl eval
+    fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn Any>> {
+        let re = Regex::new(pattern).ok()?;
+        Some(Box::new(MatchPattern(re, commands.to_string())))
+    }
+
+    fn substitute_prepare(&mut self, pattern: &str, replacement: &str) -> Option<Box<dyn Any>> {
+        let re = Regex::new(pattern).ok()?;
+        Some(Box::new(SubstitutePattern(re, replacement.to_string())))
+    }
+
+    /// Trigger: commands with captures expanded. Substitution: s///g result,
+    /// None if nothing matched (same contract as the Perl/Python backends)
+    fn match_exec(&mut self, compiled: &dyn Any, text: &str) -> Option<String> {
+        if let Some(MatchPattern(re, commands)) = compiled.downcast_ref::<MatchPattern>() {
+            let caps = re.captures(text)?;
+            return Some(expand_captures(commands, &caps));
+        }
+
+        if let Some(SubstitutePattern(re, replacement)) =
+            compiled.downcast_ref::<SubstitutePattern>()
+        {
+            if !re.is_match(text) {
+                return None;
+            }
+            let result = re
+                .replace_all(text, |caps: &Captures| expand_captures(replacement, caps))
+                .into_owned();
+            return Some(result);
+        }
+
+        None
+    }
+
+    fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn Any>> {
+        let re = Regex::new(pattern).ok()?;
+        Some(Box::new(SpanPattern(re)))
+    }
+
+    fn span_exec(&mut self, compiled: &dyn Any, text: &str) -> Vec<(usize, usize)> {
+        let Some(SpanPattern(re)) = compiled.downcast_ref::<SpanPattern>() else {
+            return Vec::new();
+        };
+        re.find_iter(text)
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_match_expands_captures() {
+        let mut re = NativeRegex::new();
+        let compiled = re
+            .match_prepare(r"^(\w+) tells you '(.*)'$", "reply %1 you said: %2 (100%%)")
+            .unwrap();
+
+        assert_eq!(
+            re.match_exec(compiled.as_ref(), "Bob tells you 'hi there'"),
+            Some("reply Bob you said: hi there (100%)".to_string())
+        );
+        assert_eq!(re.match_exec(compiled.as_ref(), "Bob says hi"), None);
+    }
+
+    #[test]
+    fn whole_match_and_missing_groups() {
+        let mut re = NativeRegex::new();
+        let compiled = re
+            .match_prepare(r"(\d+)( gold)?", "got %0 [%2] %9 %x")
+            .unwrap();
+        assert_eq!(
+            re.match_exec(compiled.as_ref(), "You find 42 coins"),
+            Some("got 42 []  %x".to_string())
+        );
+    }
+
+    #[test]
+    fn substitution_replaces_all() {
+        let mut re = NativeRegex::new();
+        let compiled = re.substitute_prepare(r"(\d+)", "<%1>").unwrap();
+        assert_eq!(
+            re.match_exec(compiled.as_ref(), "1 and 22"),
+            Some("<1> and <22>".to_string())
+        );
+        assert_eq!(re.match_exec(compiled.as_ref(), "none"), None);
+    }
+
+    #[test]
+    fn spans_skip_empty_matches() {
+        let mut re = NativeRegex::new();
+        let compiled = re.span_prepare(r"o*").unwrap();
+        assert_eq!(
+            re.span_exec(compiled.as_ref(), "foo bo"),
+            vec![(1, 3), (5, 6)]
+        );
+    }
+
+    #[test]
+    fn invalid_pattern_does_not_compile() {
+        let mut re = NativeRegex::new();
+        assert!(re.match_prepare("(unclosed", "x").is_none());
+        assert!(re.substitute_prepare("[", "x").is_none());
+        assert!(re.span_prepare("*").is_none());
+    }
+
+    #[test]
+    fn drives_actions_end_to_end() {
+        use crate::action::{Action, ActionType};
+
+        let mut re = NativeRegex::new();
+        let mut trigger =
+            Action::parse(r#""^(\w+) attacks you" kill %1"#, ActionType::Trigger).unwrap();
+        trigger.compile(&mut re);
+        assert_eq!(
+            trigger.check_match("orc attacks you", &mut re),
+            Some("kill orc".to_string())
+        );
+
+        let mut gag = Action::new(r"^\[OOC\]", "", ActionType::Gag);
+        gag.compile(&mut re);
+        assert_eq!(
+            gag.check_replacement("[OOC] spam", &mut re),
+            Some(String::new())
+        );
+        assert_eq!(gag.check_replacement("say [OOC]", &mut re), None);
+    }
+}
@@ -3,7 +3,7 @@
 //! Ported from: plugins/PerlEmbeddedInterpreter.cc
 //! Uses raw Perl C API FFI (validated in toy5)
 
-use crate::plugins::stack::Interpreter;
+use crate::plugins::stack::{parse_requests, Interpreter, ScriptLimits, ScriptRequest};
 use std::ffi::{CStr, CString};
 use std::ptr;
 
@@ -131,10 +131,45 @@ unsafe extern "C" fn xs_init(interp: *mut PerlInterpreter) {
 // Perl interpreter wrapper
 // =============================================================================
 
+/// `status` builtin: records are drained by take_status_fields()
+const STATUS_BUILTIN: &str = r#"
+our @okros_status;
+sub status {
+    my ($name, $text, $priority) = @_;
+    push @okros_status, join("\t", $name, defined $text ? $text : "", $priority || 0);
+}
+"#;
+
+/// `okros` API package: requests are drained by take_requests(), gag() by
+/// take_gag(). Works as okros::send(...) or okros->send(...)
+const API_BUILTIN: &str = r#"
+package okros;
+our @queue;
+our $gagged = 0;
+sub _args { shift if @_ && defined $_[0] && $_[0] eq 'okros'; @_ }
+sub _esc { my $f = shift; $f = "" unless defined $f; $f =~ s/\\/\\\\/g; $f =~ s/\t/\\t/g; $f =~ s/\n/\\n/g; $f }
+sub _put { push @queue, join("\t", map { _esc($_) } @_) }
+sub send { my ($text) = _args(@_); _put("send", $text) }
+sub print { my ($text, $color) = _args(@_); _put("print", $text, defined $color ? int($color) : 7) }
+sub set_status { my ($text) = _args(@_); _put("status", $text) }
+sub set_var { my ($name, $value) = _args(@_); _put("var", $name, $value) }
+sub send_raw { my ($data) = _args(@_); _put("raw", unpack("H*", defined $data ? $data : "")) }
+sub send_subneg { my ($option, $payload) = _args(@_); _put("subneg", int($option), unpack("H*", defined $payload ? $payload : "")) }
+sub _fd { my $fd = shift; ref $fd ? fileno($fd) : int($fd) }
+sub watch { my ($fd, $function, $mode) = _args(@_); _put("watch", _fd($fd), $mode || "r", $function) }
+sub unwatch { my ($fd) = _args(@_); _put("unwatch", _fd($fd)) }
+sub filter { my ($args) = _args(@_); _put("filter", $args) }
+sub on { my ($event, $function) = _args(@_); _put("on", $event, $function) }
+sub off { my ($event, $function) = _args(@_); _put("off", $event, $function) }
+sub gag { $gagged = 1 }
+package main;
+"#;
+
 /// Perl interpreter wrapper matching C++ PerlEmbeddedInterpreter patterns
 pub struct PerlPlugin {
     interp: *mut PerlInterpreter,
     initialized: bool,
+    timeout_secs: u64, // alarm() for a hook call, whole seconds (0 = none)
 }
 
 impl PerlPlugin {
@@ -194,10 +229,16 @@ impl PerlPlugin {
                 return Err(format!("perl_run failed with code {}", run_result));
             }
 
-            Ok(PerlPlugin {
+            let mut plugin = PerlPlugin {
                 interp,
                 initialized: true,
-            })
+                timeout_secs: 0,
+            };
+            // okros builtins: status(name, text, priority) queues a status field
+            let _ = plugin.eval_internal(STATUS_BUILTIN);
+            // okros::send/print/set_status/set_var/gag (see ScriptRequest)
+            let _ = plugin.eval_internal(API_BUILTIN);
+            Ok(plugin)
         }
     }
 
@@ -215,14 +256,33 @@ impl PerlPlugin {
         function: &str,
         arg: &str,
     ) -> Result<String, String> {
-        // Build Perl code: $result = function(arg);
-        let code = format!("$_ = {}(q{{{}}})", function, arg);
+        // Build Perl code: $result = function(arg); under alarm() for the
+        // hook timeout (Time::HiRes is XS, which this interpreter can't load)
+        let call = format!("{}(q{{{}}})", function, arg);
+        let code = match self.timeout_secs {
+            0 => format!("$_ = {}", call),
+            secs => format!(
+                "$_ = eval {{ local $SIG{{ALRM}} = sub {{ die \"script took longer than {}s\\n\" }}; \
+                 alarm {}; my $r = {}; alarm 0; $r }}; alarm 0; die $@ if $@",
+                secs, secs, call
+            ),
+        };
         let c_code = CString::new(code).map_err(|e| e.to_string())?;
 
         let result_sv = perl_eval_pv(self.interp, c_code.as_ptr(), 0);
         if result_sv.is_null() {
             return Err("Function call returned null".into());
         }
+        // The call died (missing function, timeout): $@ says why
+        let errsv = CString::new("@").unwrap();
+        let err = perl_get_sv(self.interp, errsv.as_ptr(), GV_ADD);
+        if !err.is_null() {
+            let mut len: libc::size_t = 0;
+            let ptr = sv_2pv(self.interp, err, &mut len);
+            if !ptr.is_null() && len > 0 {
+                return Err(CStr::from_ptr(ptr).to_string_lossy().into_owned());
+            }
+        }
 
         // Get string from $_
         let underscore = CString::new("_").unwrap();
@@ -291,6 +351,33 @@ impl Interpreter for PerlPlugin {
         }
     }
 
+    /// @perl{...}: the block runs as a sub with the captures in @_
+    fn run_block(&mut self, language: &str, code: &str, args: &[String]) -> bool {
+        if language != "perl" {
+            return false;
+        }
+        let args: Vec<String> = args
+            .iter()
+            .map(|a| format!("'{}'", a.replace('\\', "\\\\").replace('\'', "\\'")))
+            .collect();
+        let call = format!("sub {{ {} }}->({})", code, args.join(", "));
+        unsafe {
+            let _ = self.eval_internal(&call);
+        }
+        true
+    }
+
+    /// Hook timeout (rounded up to whole seconds; more than alarm() takes
+    /// is no limit); no sandbox for Perl
+    fn set_limits(&mut self, limits: &ScriptLimits) -> Result<(), String> {
+        self.timeout_secs = limits
+            .timeout
+            .map(|t| t.as_secs().saturating_add(u64::from(t.subsec_nanos() > 0)))
+            .filter(|&secs| secs <= u64::from(u32::MAX))
+            .unwrap_or(0);
+        Ok(())
+    }
+
     /// Set integer variable in Perl
     fn set_int(&mut self, var: &str, val: i64) {
         unsafe {
@@ -348,6 +435,34 @@ impl Interpreter for PerlPlugin {
         }
     }
 
+    /// Drain fields queued by the `status` builtin
+    fn take_status_fields(&mut self) -> Vec<(String, String, i32)> {
+        unsafe {
+            let _ = self.eval_internal(
+                "$okros_status_out = join(\"\\n\", @okros_status); @okros_status = ();",
+            );
+        }
+        crate::status_line::parse_field_updates(&self.get_str("okros_status_out"))
+    }
+
+    /// Drain requests queued through the `okros` API package
+    fn take_requests(&mut self) -> Vec<ScriptRequest> {
+        unsafe {
+            let _ = self.eval_internal(
+                "$okros_requests_out = join(\"\\n\", @okros::queue); @okros::queue = ();",
+            );
+        }
+        parse_requests(&self.get_str("okros_requests_out"))
+    }
+
+    fn take_gag(&mut self) -> bool {
+        unsafe {
+            let _ =
+                self.eval_internal("$okros_gag_out = $okros::gagged ? 1 : 0; $okros::gagged = 0;");
+        }
+        self.get_int("okros_gag_out") != 0
+    }
+
     /// Prepare regex pattern for trigger matching (C++ match_prepare)
     /// Returns compiled Perl sub that matches pattern and sets $_ to commands if matched
     fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn std::any::Any>> {
@@ -397,6 +512,40 @@ impl Interpreter for PerlPlugin {
         }
     }
 
+    /// Prepare regex span matcher for highlights
+    /// Returns compiled Perl sub that sets $_ to "start end,start end,..." for every match
+    fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn std::any::Any>> {
+        unsafe {
+            // @- / @+ hold offsets of the last match; skip empty matches to avoid looping
+            let code = format!(
+                "sub {{ my @s; while (/{pat}/g) {{ push @s, \"$-[0] $+[0]\" if $+[0] > $-[0]; }} $_ = join(\",\", @s); }}",
+                pat = pattern.replace("\"", "\\\"")
+            );
+
+            if let Ok(c_code) = CString::new(code) {
+                let sv = perl_eval_pv(self.interp, c_code.as_ptr(), 1);
+                if !sv.is_null() {
+                    return Some(Box::new(sv as usize));
+                }
+            }
+            None
+        }
+    }
+
+    /// Execute span matcher, parsing the offsets left in $_
+    fn span_exec(&mut self, compiled: &dyn std::any::Any, text: &str) -> Vec<(usize, usize)> {
+        let Some(spans) = self.match_exec(compiled, text) else {
+            return Vec::new();
+        };
+        spans
+            .split(',')
+            .filter_map(|pair| {
+                let (start, end) = pair.split_once(' ')?;
+                Some((start.parse().ok()?, end.parse().ok()?))
+            })
+            .collect()
+    }
+
     /// Execute compiled regex (C++ match)
     /// Sets $_ to text, calls compiled sub, returns result from $_
     fn match_exec(&mut self, compiled: &dyn std::any::Any, text: &str) -> Option<String> {
@@ -529,5 +678,52 @@ mod tests {
 
         let result = interp.match_exec(sub_compiled.as_ref(), "no numbers here");
         assert_eq!(result, None); // No substitution, returns None
+
+        // Test 11: Span prepare and exec (highlight offsets)
+        let span_compiled = interp.span_prepare(r"\d+").unwrap();
+        let spans = interp.span_exec(span_compiled.as_ref(), "42 apples, 7 pears");
+        assert_eq!(spans, vec![(0, 2), (11, 12)]);
+        assert!(interp
+            .span_exec(span_compiled.as_ref(), "no numbers here")
+            .is_empty());
+
+        // Test 12: status builtin queues fields until drained
+        interp.eval(
+            "status('hp', 'HP 10/20', 5); status('mana', 'Mana 3');",
+            &mut out,
+        );
+        assert_eq!(
+            interp.take_status_fields(),
+            vec![
+                ("hp".to_string(), "HP 10/20".to_string(), 5),
+                ("mana".to_string(), "Mana 3".to_string(), 0),
+            ]
+        );
+        assert!(interp.take_status_fields().is_empty());
+
+        // Test 13: okros API package queues requests; gag() sets a flag
+        interp.eval(
+            "okros::send('north'); okros->print(\"two\\nlines\", 3); okros::set_status('HP low');",
+            &mut out,
+        );
+        interp.eval(
+            "okros::send_raw(\"a\\xff\"); okros->send_subneg(201, 'hi'); okros::watch(7, 'on_chat');",
+            &mut out,
+        );
+        assert_eq!(
+            interp.take_requests(),
+            vec![
+                ScriptRequest::Send("north".to_string()),
+                ScriptRequest::Print("two\nlines".to_string(), 3),
+                ScriptRequest::SetStatus("HP low".to_string()),
+                ScriptRequest::SendRaw(vec![b'a', 0xff]),
+                ScriptRequest::SendSubneg(201, b"hi".to_vec()),
+                ScriptRequest::Watch(7, crate::selectable::Interest::READ, "on_chat".to_string()),
+            ]
+        );
+        assert!(interp.take_requests().is_empty());
+        interp.eval("okros::gag();", &mut out);
+        assert!(interp.take_gag());
+        assert!(!interp.take_gag());
     }
 }
@@ -3,17 +3,166 @@
 //! Ported from: plugins/PythonEmbeddedInterpreter.cc
 //! Uses pyo3 for Python C API abstraction (simpler than raw C API)
 
-use crate::plugins::stack::Interpreter;
+use crate::plugins::stack::{parse_requests, Interpreter, ScriptLimits, ScriptRequest};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyModule};
+use std::os::raw::c_long;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // Wrapper types to distinguish match patterns from substitution patterns in match_exec
 struct MatchPattern(Py<PyAny>, String);
 struct SubstitutePattern(Py<PyAny>, String);
+struct SpanPattern(Py<PyAny>);
+
+/// `status` builtin: records are drained by take_status_fields()
+const STATUS_BUILTIN: &str = r#"
+_okros_status = []
+def status(name, text="", priority=0):
+    _okros_status.append("%s\t%s\t%d" % (name, text, priority))
+"#;
+
+/// `okros` API object: requests are drained by take_requests(), gag() by take_gag()
+const API_BUILTIN: &str = r#"
+def _okros_bytes(data):
+    return data.encode("latin-1") if isinstance(data, str) else bytes(data)
+class _Okros:
+    def __init__(self):
+        self.queue = []
+        self.gagged = False
+    def _put(self, *fields):
+        esc = lambda f: str(f).replace("\\", "\\\\").replace("\t", "\\t").replace("\n", "\\n")
+        self.queue.append("\t".join(esc(f) for f in fields))
+    def send(self, text):
+        self._put("send", text)
+    def print(self, text, color=7):
+        self._put("print", text, int(color))
+    def set_status(self, text):
+        self._put("status", text)
+    def set_var(self, name, value):
+        self._put("var", name, value)
+    def send_raw(self, data):
+        self._put("raw", _okros_bytes(data).hex())
+    def send_subneg(self, option, payload=b""):
+        self._put("subneg", int(option), _okros_bytes(payload).hex())
+    def watch(self, fd, function, mode="r"):
+        fd = fd if isinstance(fd, int) else fd.fileno()
+        self._put("watch", fd, mode, function)
+    def unwatch(self, fd):
+        self._put("unwatch", fd if isinstance(fd, int) else fd.fileno())
+    def filter(self, args):
+        self._put("filter", args)
+    def on(self, event, function):
+        self._put("on", event, function)
+    def off(self, event, function):
+        self._put("off", event, function)
+    def gag(self):
+        self.gagged = True
+okros = _Okros()
+"#;
+
+/// ScriptLimits sandbox: the audit hook behind `_okros_set_sandbox` refuses
+/// file writes, reads outside the Python installation, sockets and
+/// subprocesses. Audit hooks can't be removed, so it is installed once and
+/// switched.
+const SANDBOX_BUILTIN: &str = r#"
+def _okros_make_sandbox():
+    import os, sys
+    dirs = {sys.prefix, sys.base_prefix, sys.exec_prefix, sys.base_exec_prefix}
+    dirs |= {p for p in sys.path if "-packages" in p}
+    roots = tuple(os.path.join(os.path.realpath(d), "") for d in dirs)
+    blocked = ("socket.", "subprocess.", "os.system", "os.exec", "os.posix_spawn",
+               "os.spawn", "os.fork", "os.kill", "pty.", "ctypes.", "shutil.",
+               "os.remove", "os.rmdir", "os.rename", "os.mkdir", "os.chmod", "os.chown",
+               "os.truncate", "os.link", "os.symlink", "os.utime", "webbrowser.")
+    writes = os.O_WRONLY | os.O_RDWR | os.O_APPEND | os.O_CREAT | os.O_TRUNC
+    state = [False]
+    def hook(event, args):
+        if not state[0]:
+            return
+        if event == "open":
+            path, mode, flags = args
+            if isinstance(path, int):
+                return
+            writing = any(c in mode for c in "wax+") if mode else bool(flags & writes)
+            if not writing and os.path.realpath(os.fsdecode(path)).startswith(roots):
+                return
+        elif not event.startswith(blocked):
+            return
+        raise PermissionError("okros sandbox: %s not allowed" % event)
+    sys.addaudithook(hook)
+    def set_sandbox(on):
+        state[0] = bool(on)
+    return set_sandbox
+if "_okros_set_sandbox" not in globals():
+    _okros_set_sandbox = _okros_make_sandbox()
+"#;
+
+/// How often the watchdog looks at the running hook
+const WATCHDOG_POLL: Duration = Duration::from_millis(10);
+
+/// ScriptLimits timeout: a thread that raises TimeoutError in a hook past
+/// its deadline (PyThreadState_SetAsyncExc). Stops loops in script code,
+/// not a blocking C call like time.sleep.
+struct Watchdog {
+    armed: Arc<Mutex<Option<(Instant, c_long)>>>, // Deadline and Python thread id
+}
+
+impl Watchdog {
+    fn start() -> Self {
+        let armed = Arc::new(Mutex::new(None));
+        let weak = Arc::downgrade(&armed);
+        std::thread::spawn(move || {
+            while let Some(armed) = weak.upgrade() {
+                std::thread::sleep(WATCHDOG_POLL);
+                let due = match *armed.lock().unwrap() {
+                    Some((deadline, _)) => Instant::now() >= deadline,
+                    None => false,
+                };
+                if !due {
+                    continue;
+                }
+                // The hook is still running only if it is still armed, and
+                // past its deadline, once the GIL is ours (arm() and disarm()
+                // run with the GIL held)
+                Python::with_gil(|_py| {
+                    let mut armed = armed.lock().unwrap();
+                    if let Some((_, thread)) = armed.take_if(|(d, _)| Instant::now() >= *d) {
+                        unsafe {
+                            pyo3::ffi::PyThreadState_SetAsyncExc(
+                                thread,
+                                pyo3::ffi::PyExc_TimeoutError,
+                            );
+                        }
+                    }
+                });
+            }
+        });
+        Self { armed }
+    }
+
+    fn arm(&self, py: Python<'_>, limit: Duration) -> PyResult<()> {
+        let thread = py
+            .import_bound("_thread")?
+            .call_method0("get_ident")?
+            .extract::<c_long>()?;
+        // A limit past the end of time is no limit
+        *self.armed.lock().unwrap() = Instant::now()
+            .checked_add(limit)
+            .map(|deadline| (deadline, thread));
+        Ok(())
+    }
+
+    fn disarm(&self) {
+        *self.armed.lock().unwrap() = None;
+    }
+}
 
 /// Python interpreter wrapper matching C++ PythonEmbeddedInterpreter patterns
 pub struct PythonInterpreter {
     globals: Py<PyDict>,
+    timeout: Option<Duration>,  // How long a hook may run
+    watchdog: Option<Watchdog>, // Started by the first timeout
 }
 
 impl PythonInterpreter {
@@ -34,10 +183,21 @@ impl PythonInterpreter {
             // Get globals dict from __main__
             let globals = main_module.dict();
 
+            // okros builtins: status(name, text, priority) queues a status field
+            py.run_bound(STATUS_BUILTIN, Some(&globals), Some(&globals))?;
+            // okros.send/print/set_status/set_var/gag (see ScriptRequest)
+            py.run_bound(API_BUILTIN, Some(&globals), Some(&globals))?;
+            // File/network sandbox (see set_limits)
+            py.run_bound(SANDBOX_BUILTIN, Some(&globals), Some(&globals))?;
+
             // Store globals (pyo3 handles refcounting automatically)
             let globals = globals.clone().unbind();
 
-            Ok(PythonInterpreter { globals })
+            Ok(PythonInterpreter {
+                globals,
+                timeout: None,
+                watchdog: None,
+            })
         })
     }
 
@@ -87,8 +247,16 @@ impl PythonInterpreter {
                 ))
             })?;
 
-            // Call function with arg
-            let result = func.call1((arg,))?;
+            // Call function with arg, under the timeout if there is one
+            let result = match (self.timeout, &self.watchdog) {
+                (Some(limit), Some(watchdog)) => {
+                    watchdog.arm(py, limit)?;
+                    let result = func.call1((arg,));
+                    watchdog.disarm();
+                    result?
+                }
+                _ => func.call1((arg,))?,
+            };
 
             // Extract string result
             result.extract::<String>()
@@ -157,6 +325,20 @@ impl Interpreter for PythonInterpreter {
         }
     }
 
+    /// @py{...} / @python{...}: the block runs with the captures in `args`
+    fn run_block(&mut self, language: &str, code: &str, args: &[String]) -> bool {
+        if !matches!(language, "py" | "python") {
+            return false;
+        }
+        Python::with_gil(|py| {
+            let _ = self.globals.bind(py).set_item("args", args.to_vec());
+        });
+        if let Err(e) = self.eval_internal(code) {
+            Python::with_gil(|py| e.print(py));
+        }
+        true
+    }
+
     /// Set integer variable in Python globals
     ///
     /// C++ equivalent (PythonEmbeddedInterpreter.cc:206-216):
@@ -223,6 +405,49 @@ impl Interpreter for PythonInterpreter {
         })
     }
 
+    /// Drain fields queued by the `status` builtin
+    fn take_status_fields(&mut self) -> Vec<(String, String, i32)> {
+        if self
+            .eval_internal("_okros_status_out = '\\n'.join(_okros_status); del _okros_status[:]")
+            .is_err()
+        {
+            return Vec::new();
+        }
+        crate::status_line::parse_field_updates(&self.get_str("_okros_status_out"))
+    }
+
+    /// Drain requests queued through the `okros` API object
+    fn take_requests(&mut self) -> Vec<ScriptRequest> {
+        if self
+            .eval_internal("_okros_requests_out = '\\n'.join(okros.queue); del okros.queue[:]")
+            .is_err()
+        {
+            return Vec::new();
+        }
+        parse_requests(&self.get_str("_okros_requests_out"))
+    }
+
+    fn take_gag(&mut self) -> bool {
+        if self
+            .eval_internal("_okros_gag_out = int(okros.gagged); okros.gagged = False")
+            .is_err()
+        {
+            return false;
+        }
+        self.get_int("_okros_gag_out") != 0
+    }
+
+    /// Hook timeout and the file/network sandbox
+    fn set_limits(&mut self, limits: &ScriptLimits) -> Result<(), String> {
+        self.timeout = limits.timeout;
+        if self.timeout.is_some() && self.watchdog.is_none() {
+            self.watchdog = Some(Watchdog::start());
+        }
+        let on = if limits.sandbox { "True" } else { "False" };
+        self.eval_internal(&format!("_okros_set_sandbox({})", on))
+            .map_err(|e| format!("Python sandbox: {}", e))
+    }
+
     /// Prepare regex pattern for trigger matching (Python version)
     /// Returns compiled regex + commands as opaque data
     fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn std::any::Any>> {
@@ -254,6 +479,50 @@ impl Interpreter for PythonInterpreter {
         })
     }
 
+    /// Prepare regex span matcher for highlights (Python version)
+    fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn std::any::Any>> {
+        Python::with_gil(|py| {
+            let re_module = py.import_bound("re").ok()?;
+            let compiled = re_module.call_method1("compile", (pattern,)).ok()?;
+            Some(Box::new(SpanPattern(compiled.unbind())) as Box<dyn std::any::Any>)
+        })
+    }
+
+    /// Collect (start, end) of every match via finditer (Python version)
+    ///
+    /// Python reports character offsets; convert them to byte offsets so callers
+    /// can index the line buffer directly.
+    fn span_exec(&mut self, compiled: &dyn std::any::Any, text: &str) -> Vec<(usize, usize)> {
+        let Some(SpanPattern(regex)) = compiled.downcast_ref::<SpanPattern>() else {
+            return Vec::new();
+        };
+        Python::with_gil(|py| {
+            let mut spans = Vec::new();
+            let Ok(iter) = regex.bind(py).call_method1("finditer", (text,)) else {
+                return spans;
+            };
+            let Ok(iter) = iter.iter() else {
+                return spans;
+            };
+            let byte_offset = |chars: usize| {
+                text.char_indices()
+                    .nth(chars)
+                    .map(|(i, _)| i)
+                    .unwrap_or(text.len())
+            };
+            for m in iter.flatten() {
+                let start: Option<usize> = m.call_method0("start").and_then(|v| v.extract()).ok();
+                let end: Option<usize> = m.call_method0("end").and_then(|v| v.extract()).ok();
+                if let (Some(start), Some(end)) = (start, end) {
+                    if end > start {
+                        spans.push((byte_offset(start), byte_offset(end)));
+                    }
+                }
+            }
+            spans
+        })
+    }
+
     /// Execute compiled regex (Python version)
     fn match_exec(&mut self, compiled: &dyn std::any::Any, text: &str) -> Option<String> {
         use pyo3::prelude::*;
@@ -424,4 +693,130 @@ mod tests {
         let result = interp.match_exec(compiled.as_ref(), "no numbers here");
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_status_builtin() {
+        let mut interp = PythonInterpreter::new().unwrap();
+        let mut out = String::new();
+        interp.eval("status('hp', 'HP 10/20', 5)", &mut out);
+        interp.eval("status('mana', 'Mana 3')", &mut out);
+        assert_eq!(
+            interp.take_status_fields(),
+            vec![
+                ("hp".to_string(), "HP 10/20".to_string(), 5),
+                ("mana".to_string(), "Mana 3".to_string(), 0),
+            ]
+        );
+        assert!(interp.take_status_fields().is_empty());
+    }
+
+    #[test]
+    fn test_okros_api_object() {
+        let mut interp = PythonInterpreter::new().unwrap();
+        let mut out = String::new();
+        interp.eval("okros.send('north')", &mut out);
+        interp.eval("okros.print('two\\nlines\\tx', 3)", &mut out);
+        interp.eval("okros.set_status('HP low')", &mut out);
+        interp.eval("okros.send_raw(b'a\\xff')", &mut out);
+        interp.eval("okros.send_subneg(201, 'hi')", &mut out);
+        interp.eval("okros.watch(7, 'on_chat', 'rw')", &mut out);
+        interp.eval("okros.unwatch(7)", &mut out);
+        interp.eval("okros.filter('add censor censor_line')", &mut out);
+        interp.eval("okros.on('line', 'log_line')", &mut out);
+        assert_eq!(
+            interp.take_requests(),
+            vec![
+                ScriptRequest::Send("north".to_string()),
+                ScriptRequest::Print("two\nlines\tx".to_string(), 3),
+                ScriptRequest::SetStatus("HP low".to_string()),
+                ScriptRequest::SendRaw(vec![b'a', 0xff]),
+                ScriptRequest::SendSubneg(201, b"hi".to_vec()),
+                ScriptRequest::Watch(
+                    7,
+                    crate::selectable::Interest::READ | crate::selectable::Interest::WRITE,
+                    "on_chat".to_string()
+                ),
+                ScriptRequest::Unwatch(7),
+                ScriptRequest::Filter("add censor censor_line".to_string()),
+                ScriptRequest::On("line".to_string(), "log_line".to_string()),
+            ]
+        );
+        assert!(interp.take_requests().is_empty());
+
+        assert!(!interp.take_gag());
+        interp.eval("okros.gag()", &mut out);
+        assert!(interp.take_gag());
+        assert!(!interp.take_gag());
+    }
+
+    #[test]
+    fn test_span_prepare_and_exec() {
+        let mut interp = PythonInterpreter::new().unwrap();
+
+        let compiled = interp.span_prepare(r"\d+").unwrap();
+        let spans = interp.span_exec(compiled.as_ref(), "42 apples, 7 pears");
+        assert_eq!(spans, vec![(0, 2), (11, 12)]);
+
+        assert!(interp
+            .span_exec(compiled.as_ref(), "no numbers here")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_huge_timeout_is_no_limit() {
+        let mut interp = PythonInterpreter::new().unwrap();
+        let mut out = String::new();
+        interp.eval("def quick(s):\n  return s + '!'", &mut out);
+        let limits = ScriptLimits {
+            timeout: Some(std::time::Duration::from_millis(u64::MAX)),
+            budget: 0,
+            sandbox: false,
+        };
+        interp.set_limits(&limits).unwrap();
+        assert!(interp.run("quick", "hi", &mut out));
+        assert_eq!(out, "hi!");
+    }
+
+    #[test]
+    fn test_limits_timeout_and_sandbox() {
+        use std::time::{Duration, Instant};
+        let mut interp = PythonInterpreter::new().unwrap();
+        let mut out = String::new();
+        interp.eval(
+            "def spin(s):\n  while True: pass\ndef quick(s):\n  return s + '!'",
+            &mut out,
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("written");
+        interp.eval(
+            &format!(
+                "def write(s):\n  open({:?}, 'w').write(s)\n  return 'ok'",
+                path.display().to_string()
+            ),
+            &mut out,
+        );
+        let limits = ScriptLimits {
+            timeout: Some(Duration::from_millis(100)),
+            budget: 0,
+            sandbox: true,
+        };
+        interp.set_limits(&limits).unwrap();
+
+        let start = Instant::now();
+        assert!(!interp.run_quietly("spin", "x", &mut out, true));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(interp.run("quick", "hi", &mut out));
+        assert_eq!(out, "hi!");
+        assert!(!interp.run_quietly("write", "x", &mut out, true));
+        assert!(!path.exists());
+
+        interp
+            .set_limits(&ScriptLimits {
+                sandbox: false,
+                ..limits
+            })
+            .unwrap();
+        assert!(interp.run("write", "x", &mut out));
+        assert!(path.exists());
+    }
 }
@@ -1,4 +1,172 @@
+use crate::profile::Kind;
+use crate::selectable::Interest;
 use std::any::Any;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+/// Requests queued through the script `okros` API object
+/// (okros.send / okros.print / okros.set_status / okros.set_var /
+/// okros.send_raw / okros.send_subneg / okros.watch / okros.unwatch /
+/// okros.filter),
+/// drained by the main loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptRequest {
+    /// Send a line to the MUD
+    Send(String),
+    /// Print a line to the output window in a color byte
+    Print(String, u8),
+    /// Show a message on the status line
+    SetStatus(String),
+    /// Set a #var variable: (name, value)
+    SetVar(String, String),
+    /// Bytes for the MUD as they are (IAC is escaped on the way out)
+    SendRaw(Vec<u8>),
+    /// A telnet subnegotiation: (option, payload)
+    SendSubneg(u8, Vec<u8>),
+    /// Run a function when an fd is ready: (fd, interest, function). The
+    /// main loop polls it with stdin and the socket.
+    Watch(RawFd, Interest, String),
+    /// Stop watching an fd
+    Unwatch(RawFd),
+    /// #filter arguments: switch, add or remove a filter stage
+    Filter(String),
+    /// Call a function for each bus event of a type: (event, function)
+    On(String, String),
+    /// Stop calling it
+    Off(String, String),
+}
+
+/// Parse requests queued by the `okros` API object: one tab-separated
+/// record per line ("send\ttext", "print\ttext\tcolor", "status\ttext",
+/// "var\tname\tvalue", "raw\thex", "subneg\toption\thex",
+/// "watch\tfd\tmode\tfunction" with mode r, w or rw, "unwatch\tfd",
+/// "filter\targs", "on\tevent\tfunction", "off\tevent\tfunction"),
+/// with backslash, tab and newline escaped in the text and bytes in hex
+pub fn parse_requests(s: &str) -> Vec<ScriptRequest> {
+    s.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let kind = parts.next()?;
+            let text = unescape_field(parts.next().unwrap_or(""));
+            match kind {
+                "send" => Some(ScriptRequest::Send(text)),
+                "print" => {
+                    let color = parts.next().and_then(|c| c.trim().parse().ok());
+                    Some(ScriptRequest::Print(text, color.unwrap_or(0x07)))
+                }
+                "status" => Some(ScriptRequest::SetStatus(text)),
+                "var" => Some(ScriptRequest::SetVar(
+                    text,
+                    unescape_field(parts.next().unwrap_or("")),
+                )),
+                "raw" => Some(ScriptRequest::SendRaw(decode_hex(&text)?)),
+                "subneg" => Some(ScriptRequest::SendSubneg(
+                    text.trim().parse().ok()?,
+                    decode_hex(parts.next().unwrap_or(""))?,
+                )),
+                "watch" => {
+                    let (mode, function) = parts.next()?.split_once('\t')?;
+                    Some(ScriptRequest::Watch(
+                        text.trim().parse().ok()?,
+                        watch_interest(mode)?,
+                        unescape_field(function),
+                    ))
+                }
+                "unwatch" => Some(ScriptRequest::Unwatch(text.trim().parse().ok()?)),
+                "filter" => Some(ScriptRequest::Filter(text)),
+                "on" => Some(ScriptRequest::On(text, unescape_field(parts.next()?))),
+                "off" => Some(ScriptRequest::Off(text, unescape_field(parts.next()?))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// okros.watch mode: "r", "w" or "rw"
+fn watch_interest(mode: &str) -> Option<Interest> {
+    let mut interest = Interest::empty();
+    for c in mode.chars() {
+        match c {
+            'r' => interest |= Interest::READ,
+            'w' => interest |= Interest::WRITE,
+            _ => return None,
+        }
+    }
+    (!interest.is_empty()).then_some(interest)
+}
+
+/// Bytes from a hex string ("fffa" -> [0xff, 0xfa]); None if it isn't one
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn unescape_field(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// `scripttimeout` default: a hook running longer than this is aborted
+pub const DEFAULT_SCRIPT_TIMEOUT_MS: u64 = 1000;
+/// `scriptbudget` default: script requests acted on per main loop tick
+pub const DEFAULT_SCRIPT_BUDGET: usize = 1000;
+
+/// Resource guards around script execution (config `scripttimeout`,
+/// `scriptbudget`, `scriptsandbox`), so a buggy trigger script can't hang
+/// the client. They guard against mistakes, not hostile code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptLimits {
+    /// Wall-clock limit for one hook call (sys/output etc.); None = none
+    pub timeout: Option<Duration>,
+    /// okros API requests acted on per tick, the rest dropped (0 = no limit)
+    pub budget: usize,
+    /// Python: no file writes, network or subprocesses
+    pub sandbox: bool,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_millis(DEFAULT_SCRIPT_TIMEOUT_MS)),
+            budget: DEFAULT_SCRIPT_BUDGET,
+            sandbox: false,
+        }
+    }
+}
+
+impl ScriptLimits {
+    /// Drop the requests over budget; says how many went, if any did
+    pub fn enforce_budget(&self, requests: &mut Vec<ScriptRequest>) -> Option<String> {
+        if self.budget == 0 || requests.len() <= self.budget {
+            return None;
+        }
+        let dropped = requests.len() - self.budget;
+        requests.truncate(self.budget);
+        Some(format!(
+            "Script budget: {} request(s) over {} this tick dropped",
+            dropped, self.budget
+        ))
+    }
+}
 
 pub trait Interpreter {
     fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool;
@@ -15,6 +183,11 @@ pub trait Interpreter {
         false
     }
     fn eval(&mut self, _expr: &str, _out: &mut String) {}
+    // An #action's @perl{...}/@py{...} block, with the trigger's capture
+    // groups as arguments (%0 first); false if `language` isn't this one's
+    fn run_block(&mut self, _language: &str, _code: &str, _args: &[String]) -> bool {
+        false
+    }
     fn set_int(&mut self, _var: &str, _val: i64) {}
     fn set_str(&mut self, _var: &str, _val: &str) {}
     fn get_int(&mut self, _name: &str) -> i64 {
@@ -34,10 +207,111 @@ pub trait Interpreter {
     fn match_exec(&mut self, _compiled: &dyn Any, _text: &str) -> Option<String> {
         None
     }
+
+    // Span matching for highlight actions: byte ranges of every match in text
+    fn span_prepare(&mut self, _pattern: &str) -> Option<Box<dyn Any>> {
+        None
+    }
+    fn span_exec(&mut self, _compiled: &dyn Any, _text: &str) -> Vec<(usize, usize)> {
+        Vec::new()
+    }
+
+    // Status line fields queued by the script `status(name, text, priority)`
+    // builtin since the last call: (name, text, priority), empty text clears
+    fn take_status_fields(&mut self) -> Vec<(String, String, i32)> {
+        Vec::new()
+    }
+
+    // okros.send/print/set_status requests queued since the last call
+    fn take_requests(&mut self) -> Vec<ScriptRequest> {
+        Vec::new()
+    }
+
+    // Whether okros.gag() was called since the last check (checked right
+    // after sys/output to drop the line being processed)
+    fn take_gag(&mut self) -> bool {
+        false
+    }
+
+    // Timeout and sandbox from ScriptLimits; interpreters that can't
+    // enforce them ignore them
+    fn set_limits(&mut self, _limits: &ScriptLimits) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Boxed interpreters stack up like any other (StackedInterpreter<Box<dyn Interpreter>>)
+impl<T: Interpreter + ?Sized> Interpreter for Box<T> {
+    fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool {
+        (**self).run(function, arg, out)
+    }
+    fn run_quietly(&mut self, function: &str, arg: &str, out: &mut String, quiet: bool) -> bool {
+        (**self).run_quietly(function, arg, out, quiet)
+    }
+    fn load_file(&mut self, filename: &str, suppress: bool) -> bool {
+        (**self).load_file(filename, suppress)
+    }
+    fn eval(&mut self, expr: &str, out: &mut String) {
+        (**self).eval(expr, out)
+    }
+    fn run_block(&mut self, language: &str, code: &str, args: &[String]) -> bool {
+        (**self).run_block(language, code, args)
+    }
+    fn set_int(&mut self, var: &str, val: i64) {
+        (**self).set_int(var, val)
+    }
+    fn set_str(&mut self, var: &str, val: &str) {
+        (**self).set_str(var, val)
+    }
+    fn get_int(&mut self, name: &str) -> i64 {
+        (**self).get_int(name)
+    }
+    fn get_str(&mut self, name: &str) -> String {
+        (**self).get_str(name)
+    }
+    fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn Any>> {
+        (**self).match_prepare(pattern, commands)
+    }
+    fn substitute_prepare(&mut self, pattern: &str, replacement: &str) -> Option<Box<dyn Any>> {
+        (**self).substitute_prepare(pattern, replacement)
+    }
+    fn match_exec(&mut self, compiled: &dyn Any, text: &str) -> Option<String> {
+        (**self).match_exec(compiled, text)
+    }
+    fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn Any>> {
+        (**self).span_prepare(pattern)
+    }
+    fn span_exec(&mut self, compiled: &dyn Any, text: &str) -> Vec<(usize, usize)> {
+        (**self).span_exec(compiled, text)
+    }
+    fn take_status_fields(&mut self) -> Vec<(String, String, i32)> {
+        (**self).take_status_fields()
+    }
+    fn take_requests(&mut self) -> Vec<ScriptRequest> {
+        (**self).take_requests()
+    }
+    fn take_gag(&mut self) -> bool {
+        (**self).take_gag()
+    }
+    fn set_limits(&mut self, limits: &ScriptLimits) -> Result<(), String> {
+        (**self).set_limits(limits)
+    }
 }
 
+/// One interpreter in the stack
+struct Backend<I> {
+    name: String,      // "perl", "python", ... ("" if added without one)
+    extension: String, // Script files it loads ("" for none)
+    enabled: bool,     // #interpreter enable/disable
+    interp: I,
+}
+
+/// Interpreters run as one (C++ StackedInterpreter, Embedded.cc): hooks
+/// run through every enabled backend in turn, each getting the previous
+/// one's output; actions, eval and variable reads go to the first enabled
+/// backend. Functions can be disabled across the stack.
 pub struct StackedInterpreter<I: Interpreter> {
-    list: Vec<I>,
+    list: Vec<Backend<I>>,
     disabled: Vec<String>,
 }
 
@@ -49,7 +323,16 @@ impl<I: Interpreter> StackedInterpreter<I> {
         }
     }
     pub fn add(&mut self, i: I) {
-        self.list.push(i);
+        self.add_backend("", "", i);
+    }
+    /// Add `i` as `name`, loading scripts ending in `.extension`
+    pub fn add_backend(&mut self, name: &str, extension: &str, i: I) {
+        self.list.push(Backend {
+            name: name.to_string(),
+            extension: extension.to_string(),
+            enabled: true,
+            interp: i,
+        });
     }
     pub fn disable(&mut self, fname: &str) {
         if !self.disabled.iter().any(|s| s == fname) {
@@ -63,69 +346,223 @@ impl<I: Interpreter> StackedInterpreter<I> {
         !self.disabled.iter().any(|s| s == fname)
     }
 
-    pub fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool {
-        if !self.is_enabled(function) {
-            return false;
-        }
-        let mut cur = arg.to_string();
-        let mut any = false;
-        for i in &mut self.list {
-            let mut tmp = String::new();
-            if i.run(function, &cur, &mut tmp) {
-                cur = tmp;
-                any = true;
+    /// Switch the backend called `name`; false if there is none
+    pub fn set_backend_enabled(&mut self, name: &str, on: bool) -> bool {
+        match self.list.iter_mut().find(|b| b.name == name) {
+            Some(b) => {
+                b.enabled = on;
+                true
             }
+            None => false,
         }
-        if any {
-            *out = cur;
-        }
-        any
     }
 
-    pub fn run_quietly(
+    /// Backend names and whether each is enabled, in stack order
+    pub fn backends(&self) -> Vec<(&str, bool)> {
+        self.list
+            .iter()
+            .map(|b| (b.name.as_str(), b.enabled))
+            .collect()
+    }
+
+    fn active(&mut self) -> impl Iterator<Item = &mut I> {
+        self.list
+            .iter_mut()
+            .filter(|b| b.enabled)
+            .map(|b| &mut b.interp)
+    }
+
+    /// Run `function` through each enabled backend, feeding each one the
+    /// previous one's output
+    fn chain(
         &mut self,
         function: &str,
         arg: &str,
         out: &mut String,
-        suppress_error: bool,
+        mut call: impl FnMut(&mut I, &str, &str, &mut String) -> bool,
     ) -> bool {
         if !self.is_enabled(function) {
             return false;
         }
+        let start = crate::profile::enabled().then(std::time::Instant::now);
         let mut cur = arg.to_string();
         let mut any = false;
-        for i in &mut self.list {
+        for i in self.active() {
             let mut tmp = String::new();
-            if i.run_quietly(function, &cur, &mut tmp, suppress_error) {
+            if call(i, function, &cur, &mut tmp) {
                 cur = tmp;
                 any = true;
             }
         }
+        if let Some(start) = start {
+            crate::profile::record(Kind::Hook, function.to_string(), start.elapsed());
+        }
         if any {
             *out = cur;
         }
         any
     }
 
-    pub fn set_int(&mut self, var: &str, val: i64) {
-        for i in &mut self.list {
+    /// The first enabled backend: actions, eval and variable reads
+    fn primary(&mut self) -> Option<&mut I> {
+        self.active().next()
+    }
+
+    /// Enabled backends that load script files, by extension (okros::scripts)
+    pub fn by_extension(&mut self) -> Vec<(&str, &mut dyn Interpreter)>
+    where
+        I: 'static,
+    {
+        self.list
+            .iter_mut()
+            .filter(|b| b.enabled && !b.extension.is_empty())
+            .map(|b| (b.extension.as_str(), &mut b.interp as &mut dyn Interpreter))
+            .collect()
+    }
+
+    /// #interpreter [list] | enable|disable <backend|function>
+    pub fn command(&mut self, args: &str) -> Result<String, String> {
+        let usage = || "Usage: #interpreter [list] | enable|disable <backend|function>".to_string();
+        let mut words = args.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None | Some("list"), None, _) => {
+                let backends: Vec<String> = self
+                    .backends()
+                    .iter()
+                    .map(|(name, on)| format!("{} {}", name, if *on { "on" } else { "off" }))
+                    .collect();
+                let mut msg = format!("Interpreters: {}", backends.join(", "));
+                if !self.disabled.is_empty() {
+                    msg.push_str(&format!("; disabled: {}", self.disabled.join(", ")));
+                }
+                Ok(msg)
+            }
+            (Some(verb @ ("enable" | "disable")), Some(target), None) => {
+                let on = verb == "enable";
+                if self.set_backend_enabled(target, on) {
+                    return Ok(format!("Interpreter {} {}d", target, verb));
+                }
+                if on {
+                    self.enable(target);
+                } else {
+                    self.disable(target);
+                }
+                Ok(format!("Function {} {}d", target, verb))
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+impl<I: Interpreter> Default for StackedInterpreter<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Interpreter> Interpreter for StackedInterpreter<I> {
+    fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool {
+        self.chain(function, arg, out, |i, f, a, o| i.run(f, a, o))
+    }
+
+    fn run_quietly(
+        &mut self,
+        function: &str,
+        arg: &str,
+        out: &mut String,
+        suppress_error: bool,
+    ) -> bool {
+        self.chain(function, arg, out, |i, f, a, o| {
+            i.run_quietly(f, a, o, suppress_error)
+        })
+    }
+    /// The backend for the file's extension, else the first one
+    fn load_file(&mut self, filename: &str, suppress: bool) -> bool {
+        let ext = std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let by_ext = self
+            .list
+            .iter()
+            .position(|b| b.enabled && !b.extension.is_empty() && b.extension == ext);
+        match by_ext {
+            Some(n) => self.list[n].interp.load_file(filename, suppress),
+            None => self
+                .primary()
+                .is_some_and(|i| i.load_file(filename, suppress)),
+        }
+    }
+
+    fn eval(&mut self, expr: &str, out: &mut String) {
+        if let Some(i) = self.primary() {
+            i.eval(expr, out);
+        }
+    }
+    /// Goes to the first enabled backend for `language`
+    fn run_block(&mut self, language: &str, code: &str, args: &[String]) -> bool {
+        self.active().any(|i| i.run_block(language, code, args))
+    }
+    fn set_int(&mut self, var: &str, val: i64) {
+        for i in self.active() {
             i.set_int(var, val);
         }
     }
-    pub fn set_str(&mut self, var: &str, val: &str) {
-        for i in &mut self.list {
+    fn set_str(&mut self, var: &str, val: &str) {
+        for i in self.active() {
             i.set_str(var, val);
         }
     }
-    pub fn get_int(&mut self, name: &str) -> i64 {
-        self.list.first_mut().map(|i| i.get_int(name)).unwrap_or(0)
+    fn get_int(&mut self, name: &str) -> i64 {
+        self.primary().map(|i| i.get_int(name)).unwrap_or(0)
     }
-    pub fn get_str(&mut self, name: &str) -> String {
-        self.list
-            .first_mut()
-            .map(|i| i.get_str(name))
+    fn get_str(&mut self, name: &str) -> String {
+        self.primary().map(|i| i.get_str(name)).unwrap_or_default()
+    }
+
+    // Actions are compiled and matched by the same (first enabled) backend;
+    // recompile them after switching backends
+    fn match_prepare(&mut self, pattern: &str, commands: &str) -> Option<Box<dyn Any>> {
+        self.primary()?.match_prepare(pattern, commands)
+    }
+    fn substitute_prepare(&mut self, pattern: &str, replacement: &str) -> Option<Box<dyn Any>> {
+        self.primary()?.substitute_prepare(pattern, replacement)
+    }
+    fn match_exec(&mut self, compiled: &dyn Any, text: &str) -> Option<String> {
+        self.primary()?.match_exec(compiled, text)
+    }
+    fn span_prepare(&mut self, pattern: &str) -> Option<Box<dyn Any>> {
+        self.primary()?.span_prepare(pattern)
+    }
+    fn span_exec(&mut self, compiled: &dyn Any, text: &str) -> Vec<(usize, usize)> {
+        self.primary()
+            .map(|i| i.span_exec(compiled, text))
             .unwrap_or_default()
     }
+
+    // Everything queued is drained, disabled backends included
+    fn take_status_fields(&mut self) -> Vec<(String, String, i32)> {
+        self.list
+            .iter_mut()
+            .flat_map(|b| b.interp.take_status_fields())
+            .collect()
+    }
+    fn take_requests(&mut self) -> Vec<ScriptRequest> {
+        self.list
+            .iter_mut()
+            .flat_map(|b| b.interp.take_requests())
+            .collect()
+    }
+    fn take_gag(&mut self) -> bool {
+        self.list
+            .iter_mut()
+            .fold(false, |gag, b| b.interp.take_gag() | gag)
+    }
+    fn set_limits(&mut self, limits: &ScriptLimits) -> Result<(), String> {
+        self.list
+            .iter_mut()
+            .try_for_each(|b| b.interp.set_limits(limits))
+    }
 }
 
 #[cfg(test)]
@@ -151,6 +588,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_queued_requests() {
+        let queued =
+            "send\tnorth\nprint\tline\\none\\tx\t3\nstatus\tHP low\nprint\tplain\nbogus\tx\nvar\thp\t9\\t9\nraw\t61ff\nraw\tzz\nsubneg\t201\t7b7d\nwatch\t5\trw\tchat_in\nwatch\t5\tx\tf\nunwatch\t5\nfilter\toff gag\non\tline\tlog_line\non\tprompt\noff\tline\tlog_line";
+        assert_eq!(
+            parse_requests(queued),
+            vec![
+                ScriptRequest::Send("north".to_string()),
+                ScriptRequest::Print("line\none\tx".to_string(), 3),
+                ScriptRequest::SetStatus("HP low".to_string()),
+                ScriptRequest::Print("plain".to_string(), 0x07),
+                ScriptRequest::SetVar("hp".to_string(), "9\t9".to_string()),
+                ScriptRequest::SendRaw(vec![b'a', 0xff]),
+                ScriptRequest::SendSubneg(201, b"{}".to_vec()),
+                ScriptRequest::Watch(5, Interest::READ | Interest::WRITE, "chat_in".to_string()),
+                ScriptRequest::Unwatch(5),
+                ScriptRequest::Filter("off gag".to_string()),
+                ScriptRequest::On("line".to_string(), "log_line".to_string()),
+                ScriptRequest::Off("line".to_string(), "log_line".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn budget_drops_requests_over_it() {
+        let limits = ScriptLimits {
+            budget: 2,
+            ..ScriptLimits::default()
+        };
+        let mut requests: Vec<_> = (0..5).map(|i| ScriptRequest::Send(i.to_string())).collect();
+        let msg = limits.enforce_budget(&mut requests).unwrap();
+        assert!(msg.contains("3 request(s) over 2"));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(limits.enforce_budget(&mut requests), None);
+        let unlimited = ScriptLimits {
+            budget: 0,
+            ..limits
+        };
+        requests.extend(requests.clone());
+        assert_eq!(unlimited.enforce_budget(&mut requests), None);
+    }
+
     #[test]
     fn chains_in_order() {
         let mut st = StackedInterpreter::new();
@@ -255,4 +734,40 @@ mod tests {
         assert_eq!(out, "x!");
         assert!(!st.run("sys/a", "x", &mut out));
     }
+
+    #[test]
+    fn disabled_backend_is_skipped() {
+        let mut st = StackedInterpreter::new();
+        st.add_backend("perl", "pl", Mock { name: "A" });
+        st.add_backend("python", "py", Mock { name: "B" });
+        let mut out = String::new();
+        assert!(st.set_backend_enabled("perl", false));
+        assert!(!st.set_backend_enabled("lua", false));
+        assert!(st.run("sys/test", "in", &mut out));
+        assert_eq!(out, "in[B]");
+        assert_eq!(st.get_str("x"), "B");
+        let exts: Vec<&str> = st.by_extension().into_iter().map(|(e, _)| e).collect();
+        assert_eq!(exts, vec!["py"]);
+    }
+
+    #[test]
+    fn interpreter_command() {
+        let mut st = StackedInterpreter::new();
+        st.add_backend("perl", "pl", Mock { name: "A" });
+        st.add_backend("python", "py", Mock { name: "B" });
+        assert_eq!(st.command("").unwrap(), "Interpreters: perl on, python on");
+        st.command("disable python").unwrap();
+        st.command("disable sys/test").unwrap();
+        assert_eq!(
+            st.command("list").unwrap(),
+            "Interpreters: perl on, python off; disabled: sys/test"
+        );
+        let mut out = String::new();
+        assert!(!st.run("sys/test", "in", &mut out));
+        st.command("enable sys/test").unwrap();
+        st.command("enable python").unwrap();
+        assert!(st.run("sys/test", "in", &mut out));
+        assert_eq!(out, "in[A][B]");
+        assert!(st.command("toggle perl").is_err());
+    }
 }
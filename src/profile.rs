@@ -0,0 +1,198 @@
+// Profile - opt-in timing of trigger patterns and script hooks
+//
+// `#profile on` times every action pattern evaluated against a line
+// (triggers, substitutions, gags, highlights) and every interpreter hook
+// call (sys/output, sys/prompt, ...), keeping a count, total, worst and a
+// rolling average per pattern or hook. `#profile` lists the slowest. Off,
+// each evaluation costs one atomic load. The table is global because the
+// control server matches lines on its own threads.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TABLE: Mutex<Option<HashMap<(Kind, String), Stat>>> = Mutex::new(None);
+
+/// Entries listed by `#profile` with no count
+pub const DEFAULT_REPORT: usize = 10;
+
+/// Weight of the newest call in the rolling average
+const RECENT_WEIGHT: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    Action, // An action's pattern, named "<type> <pattern>"
+    Hook,   // An interpreter function
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stat {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+    pub recent_us: f64, // Rolling average, microseconds
+}
+
+impl Stat {
+    fn add(&mut self, d: Duration) {
+        let us = d.as_secs_f64() * 1e6;
+        self.recent_us = match self.calls {
+            0 => us,
+            _ => self.recent_us + (us - self.recent_us) * RECENT_WEIGHT,
+        };
+        self.calls += 1;
+        self.total += d;
+        self.max = self.max.max(d);
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+/// Forget everything timed so far
+pub fn reset() {
+    if let Ok(mut table) = TABLE.lock() {
+        *table = None;
+    }
+}
+
+/// Run `f`, timing it under `name` (only built when profiling is on)
+pub fn time<T>(kind: Kind, name: impl FnOnce() -> String, f: impl FnOnce() -> T) -> T {
+    if !enabled() {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    record(kind, name(), start.elapsed());
+    result
+}
+
+pub fn record(kind: Kind, name: String, d: Duration) {
+    if let Ok(mut table) = TABLE.lock() {
+        table
+            .get_or_insert_with(HashMap::new)
+            .entry((kind, name))
+            .or_default()
+            .add(d);
+    }
+}
+
+/// The `limit` entries with the most total time, slowest first
+pub fn slowest(limit: usize) -> Vec<(Kind, String, Stat)> {
+    let Ok(table) = TABLE.lock() else {
+        return Vec::new();
+    };
+    let mut entries: Vec<(Kind, String, Stat)> = table
+        .iter()
+        .flatten()
+        .map(|((kind, name), stat)| (*kind, name.clone(), *stat))
+        .collect();
+    entries.sort_by(|a, b| b.2.total.cmp(&a.2.total).then_with(|| a.1.cmp(&b.1)));
+    entries.truncate(limit);
+    entries
+}
+
+/// The #profile listing
+pub fn report(limit: usize) -> String {
+    let entries = slowest(limit);
+    let state = if enabled() { "on" } else { "off" };
+    if entries.is_empty() {
+        return format!("Profiling is {}; nothing timed yet", state);
+    }
+    let mut lines = vec![format!(
+        "Profiling is {}; slowest by total time (us): calls, total, avg, recent, max",
+        state
+    )];
+    for (kind, name, stat) in entries {
+        let kind = match kind {
+            Kind::Action => "action",
+            Kind::Hook => "hook",
+        };
+        let total_us = stat.total.as_micros();
+        lines.push(format!(
+            "{:>8} {:>10} {:>8} {:>8.0} {:>8}  {} {}",
+            stat.calls,
+            total_us,
+            total_us / u128::from(stat.calls.max(1)),
+            stat.recent_us,
+            stat.max.as_micros(),
+            kind,
+            name
+        ));
+    }
+    lines.join("\n")
+}
+
+/// #profile [on|off|reset|<count>]
+pub fn command(args: &str) -> Result<String, String> {
+    match args.trim() {
+        "" => Ok(report(DEFAULT_REPORT)),
+        "on" => {
+            set_enabled(true);
+            Ok("Profiling on: #profile lists the slowest patterns and hooks".to_string())
+        }
+        "off" => {
+            set_enabled(false);
+            Ok("Profiling off (#profile reset clears the times)".to_string())
+        }
+        "reset" => {
+            reset();
+            Ok("Profile times cleared".to_string())
+        }
+        n => match n.parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(report(limit)),
+            _ => Err("Usage: #profile [on|off|reset|<count>]".to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_keeps_total_max_and_rolling_average() {
+        let mut stat = Stat::default();
+        stat.add(Duration::from_micros(100));
+        assert_eq!(stat.recent_us, 100.0);
+        stat.add(Duration::from_micros(300));
+        assert_eq!(stat.calls, 2);
+        assert_eq!(stat.total, Duration::from_micros(400));
+        assert_eq!(stat.max, Duration::from_micros(300));
+        assert!((stat.recent_us - 120.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn command_switches_and_reports() {
+        // The table is global: only this test touches it
+        assert!(command("bogus").is_err());
+        command("on").unwrap();
+        let hit = time(Kind::Hook, || "sys/test".to_string(), || 7);
+        assert_eq!(hit, 7);
+        record(
+            Kind::Action,
+            "action ^slow".to_string(),
+            Duration::from_millis(5),
+        );
+        command("off").unwrap();
+        time(Kind::Hook, || "sys/never".to_string(), || ());
+
+        let listing = report(10);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert!(lines[0].starts_with("Profiling is off"));
+        assert!(lines[1].ends_with("action action ^slow"), "{}", lines[1]);
+        assert!(lines.iter().any(|l| l.ends_with("hook sys/test")));
+        assert!(!listing.contains("sys/never"));
+        assert_eq!(slowest(1).len(), 1);
+
+        command("reset").unwrap();
+        assert!(command("").unwrap().contains("nothing timed yet"));
+    }
+}
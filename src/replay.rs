@@ -0,0 +1,238 @@
+// Record and replay raw server streams - a test harness
+//
+// `--record FILE` writes every chunk read from the MUD socket, stamped with
+// the time since recording began. `--replay FILE [--speed N]` feeds a
+// recording back through the Session, TUI or headless, at the original pace
+// (N times faster), so rendering and trigger bugs seen on
+// one server can be reproduced without it.
+//
+// File format: MAGIC, then per chunk a little-endian u64 (milliseconds since
+// the start), a u32 length and the bytes as read.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Start of every recording
+pub const MAGIC: &[u8] = b"OKREC1\n";
+
+/// One read from the socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub at: Duration, // Since the recording started
+    pub data: Vec<u8>,
+}
+
+/// Appends socket reads to a recording
+pub struct Recorder {
+    file: File,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Create (or truncate) `path` and start the clock
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let mut file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        file.write_all(MAGIC)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Record `data` as read now
+    pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let record = encode(self.start.elapsed(), data);
+        self.file.write_all(&record).map_err(|e| e.to_string())
+    }
+}
+
+/// One chunk in the file format
+pub fn encode(at: Duration, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + data.len());
+    out.extend_from_slice(&(at.as_millis() as u64).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// The chunks of a recording; a chunk cut short at the end (okros died
+/// mid-write) is dropped
+pub fn decode(bytes: &[u8]) -> Result<Vec<Chunk>, String> {
+    let mut rest = bytes.strip_prefix(MAGIC).ok_or("not an okros recording")?;
+    let mut chunks = Vec::new();
+    while rest.len() >= 12 {
+        let ms = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+        let Some(data) = rest.get(12..12 + len) else {
+            break;
+        };
+        chunks.push(Chunk {
+            at: Duration::from_millis(ms),
+            data: data.to_vec(),
+        });
+        rest = &rest[12 + len..];
+    }
+    Ok(chunks)
+}
+
+/// Hands a recording's chunks out as their time comes. The clock starts at
+/// the first take_due
+pub struct Replay {
+    chunks: VecDeque<Chunk>,
+    speed: f64,
+    start: Option<Instant>,
+}
+
+impl Replay {
+    /// `speed` 2.0 plays twice as fast; 0 (or less) sends everything at once
+    pub fn new(chunks: Vec<Chunk>, speed: f64) -> Self {
+        Self {
+            chunks: chunks.into(),
+            speed,
+            start: None,
+        }
+    }
+
+    pub fn load(path: &Path, speed: f64) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self::new(decode(&bytes)?, speed))
+    }
+
+    /// When `chunk` is due, relative to the start (Duration::MAX if that's
+    /// past what a Duration holds)
+    fn offset(&self, chunk: &Chunk) -> Duration {
+        if self.speed > 0.0 {
+            Duration::try_from_secs_f64(chunk.at.as_secs_f64() / self.speed)
+                .unwrap_or(Duration::MAX)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// When `chunk` is due; None if it's too far off for an Instant
+    fn due_at(&self, start: Instant, chunk: &Chunk) -> Option<Instant> {
+        start.checked_add(self.offset(chunk))
+    }
+
+    /// The chunks whose time has come, in order
+    pub fn take_due(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let start = *self.start.get_or_insert(now);
+        let mut due = Vec::new();
+        while let Some(chunk) = self.chunks.front() {
+            if self.due_at(start, chunk).is_none_or(|due| due > now) {
+                break;
+            }
+            due.push(self.chunks.pop_front().unwrap().data);
+        }
+        due
+    }
+
+    /// Milliseconds until the next chunk, at most `max_ms`
+    pub fn poll_timeout(&self, now: Instant, max_ms: i32) -> i32 {
+        match (self.start, self.chunks.front()) {
+            (_, None) => max_ms,
+            (None, Some(_)) => 0,
+            (Some(start), Some(chunk)) => match self.due_at(start, chunk) {
+                Some(due) => {
+                    let wait = due.saturating_duration_since(now);
+                    wait.as_millis().min(max_ms as u128) as i32
+                }
+                None => max_ms,
+            },
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+/// `--speed N` from the command line, 1.0 without it; N must be above 0
+pub fn speed_arg(args: &[String]) -> Result<f64, String> {
+    match args.iter().position(|a| a == "--speed") {
+        None => Ok(1.0),
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|speed| speed.is_finite() && *speed > 0.0)
+            .ok_or_else(|| "--speed needs a number above 0 (e.g. 1000 for no delays)".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(ms: u64, data: &[u8]) -> Chunk {
+        Chunk {
+            at: Duration::from_millis(ms),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut file = MAGIC.to_vec();
+        file.extend(encode(Duration::from_millis(5), b"Hel"));
+        file.extend(encode(Duration::from_millis(1500), b"\x1b[31mlo\n"));
+        assert_eq!(
+            decode(&file).unwrap(),
+            vec![chunk(5, b"Hel"), chunk(1500, b"\x1b[31mlo\n")]
+        );
+        // A truncated last chunk is dropped; other files are refused
+        assert_eq!(decode(&file[..file.len() - 2]).unwrap().len(), 1);
+        assert!(decode(b"hello").is_err());
+    }
+
+    #[test]
+    fn replay_keeps_pace_scaled_by_speed() {
+        let chunks = vec![chunk(0, b"a"), chunk(1000, b"b"), chunk(3000, b"c")];
+        let t0 = Instant::now();
+        let mut r = Replay::new(chunks.clone(), 2.0);
+        assert_eq!(r.poll_timeout(t0, 250), 0);
+        assert_eq!(r.take_due(t0), vec![b"a".to_vec()]);
+        assert_eq!(r.poll_timeout(t0, 1000), 500);
+        assert!(r.take_due(t0 + Duration::from_millis(499)).is_empty());
+        assert_eq!(
+            r.take_due(t0 + Duration::from_millis(1500)),
+            vec![b"b".to_vec(), b"c".to_vec()]
+        );
+        assert!(r.is_done());
+
+        let mut instant = Replay::new(chunks, 0.0);
+        assert_eq!(instant.take_due(t0).len(), 3);
+    }
+
+    #[test]
+    fn speed_from_args() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(speed_arg(&args("okros --replay f")), Ok(1.0));
+        assert_eq!(speed_arg(&args("okros --replay f --speed 4")), Ok(4.0));
+        assert!(speed_arg(&args("okros --speed")).is_err());
+        for bad in ["0", "-2", "NaN", "inf"] {
+            let line = format!("okros --replay f --speed {}", bad);
+            assert!(speed_arg(&args(&line)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn extreme_speeds_and_timestamps_dont_panic() {
+        let t0 = Instant::now();
+        let chunks = vec![chunk(1000, b"a")];
+        let mut slow = Replay::new(chunks, 1e-300);
+        assert!(slow.take_due(t0).is_empty());
+        assert_eq!(slow.poll_timeout(t0, 250), 250);
+
+        let far = Chunk {
+            at: Duration::MAX,
+            data: b"b".to_vec(),
+        };
+        let mut late = Replay::new(vec![far], 1.0);
+        assert!(late.take_due(t0).is_empty());
+        assert_eq!(late.poll_timeout(t0, 250), 250);
+    }
+}
@@ -0,0 +1,139 @@
+// Resolver - DNS lookups off the UI thread
+//
+// getaddrinfo blocks, so resolving a MUD's hostname in the main loop froze
+// the screen for as long as a slow DNS server took. A Lookup runs it on a
+// thread of its own; the main loop polls for the answer each pass and drops
+// the Lookup to cancel it (the thread's late answer goes nowhere). Address
+// literals need no thread.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// How often the main loop checks on a pending lookup
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Answer = Result<Vec<SocketAddr>, String>;
+
+pub struct Lookup {
+    host: String,
+    port: u16,
+    started: Instant,
+    shown: u64, // Seconds last reported by progress()
+    rx: Receiver<Answer>,
+}
+
+impl Lookup {
+    /// Start resolving `host` (name or address literal) for `port`
+    pub fn start(host: &str, port: u16) -> Self {
+        let (tx, rx) = channel();
+        let literal = host
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .unwrap_or(host)
+            .parse::<IpAddr>()
+            .is_ok();
+        let resolve = {
+            let host = host.to_string();
+            move || {
+                crate::socket::resolve(&host, port)
+                    .map_err(|e| format!("DNS lookup failed for {}: {}", host, e))
+            }
+        };
+        if literal {
+            let _ = tx.send(resolve());
+        } else {
+            // If the thread can't start, tx is dropped and poll() reports it
+            let _ = std::thread::Builder::new()
+                .name("dns".to_string())
+                .spawn(move || {
+                    let _ = tx.send(resolve());
+                });
+        }
+        Self {
+            host: host.to_string(),
+            port,
+            started: Instant::now(),
+            shown: 0,
+            rx,
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// "Resolving <host>... 3s" each time another whole second has passed
+    pub fn progress(&mut self) -> Option<String> {
+        let secs = self.started.elapsed().as_secs();
+        if secs == self.shown {
+            return None;
+        }
+        self.shown = secs;
+        Some(format!("Resolving {}... {}s", self.host, secs))
+    }
+
+    /// The addresses to try (see socket::resolve) or an error once the
+    /// lookup is done; None while it's still running
+    pub fn poll(&self) -> Option<Answer> {
+        match self.rx.try_recv() {
+            Ok(answer) => Some(answer),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(format!("DNS lookup for {} failed", self.host)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(lookup: &Lookup) -> Answer {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(answer) = lookup.poll() {
+                return answer;
+            }
+            assert!(Instant::now() < deadline, "lookup never finished");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn literals_answer_at_once() {
+        let lookup = Lookup::start("[::1]", 4000);
+        assert_eq!(lookup.poll(), Some(Ok(vec!["[::1]:4000".parse().unwrap()])));
+        assert_eq!((lookup.host(), lookup.port()), ("[::1]", 4000));
+    }
+
+    #[test]
+    fn progress_once_a_second() {
+        let mut lookup = Lookup::start("127.0.0.1", 23);
+        assert_eq!(lookup.progress(), None);
+        lookup.started -= Duration::from_secs(2);
+        assert_eq!(
+            lookup.progress().as_deref(),
+            Some("Resolving 127.0.0.1... 2s")
+        );
+        assert_eq!(lookup.progress(), None);
+    }
+
+    #[test]
+    fn names_resolve_on_a_thread() {
+        let lookup = Lookup::start("localhost", 23);
+        let addrs = wait(&lookup).unwrap();
+        assert!(addrs.iter().all(|a| a.port() == 23 && a.ip().is_loopback()));
+
+        let bad = Lookup::start("no-such-host.invalid", 23);
+        assert!(wait(&bad).unwrap_err().contains("no-such-host.invalid"));
+
+        // Dropping a pending lookup cancels it; the thread's answer is discarded
+        drop(Lookup::start("localhost", 23));
+    }
+}
@@ -0,0 +1,457 @@
+// Restart - Userland restart that keeps MUD connections open
+//
+// Protocol:
+// 1. Old process creates a socketpair and forks a short-lived helper
+// 2. Helper keeps copies of the MUD socket fds and sends them, plus the
+//    serialized RestartState, over its end with SCM_RIGHTS
+// 3. Old process marks its own fds close-on-exec and execs the new binary
+//    with `--restore <fd> <helper pid>` - same pid, same controlling tty
+// 4. New process receives state + fds, acks, and reaps the helper as it exits
+//
+// The state is serialized before the fork: the helper only calls sendmsg,
+// read and _exit, as nothing else is safe in the child of a threaded process.
+//
+// Wire format on the socketpair: u32 length (native endian) + JSON payload,
+// with the fds attached as ancillary data to the first message.
+
+use crate::scrollback::Attrib;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::mem;
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever RestartState changes incompatibly
+pub const RESTART_VERSION: u32 = 1;
+
+/// Command line flag the new binary is started with
+pub const RESTORE_FLAG: &str = "--restore";
+
+/// Maximum fds passed in one handoff (MUD socket + future chat/control sockets)
+const MAX_FDS: usize = 16;
+
+/// Control buffer for MAX_FDS fds, on the stack (u64s keep cmsghdr aligned)
+type CmsgBuf = [u64; 32];
+
+/// Session state carried across a restart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RestartState {
+    pub version: u32,
+    pub mud_name: String,
+    pub hostname: String,
+    pub port: u16,
+    pub connect_time: i64,
+    pub bytes_read: usize,
+    pub bytes_written: usize,
+    /// Output history rows (char | color << 8), oldest first
+    pub scrollback: Vec<Vec<Attrib>>,
+}
+
+impl RestartState {
+    pub fn new() -> Self {
+        Self {
+            version: RESTART_VERSION,
+            ..Default::default()
+        }
+    }
+}
+
+fn io_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Send bytes with fds attached as SCM_RIGHTS ancillary data
+pub fn send_with_fds(sock: RawFd, data: &[u8], fds: &[RawFd]) -> io::Result<()> {
+    if fds.len() > MAX_FDS {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many fds"));
+    }
+    if data.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty payload"));
+    }
+
+    let fd_bytes = mem::size_of_val(fds) as u32;
+    let space = unsafe { libc::CMSG_SPACE(fd_bytes) } as usize;
+    let mut cmsg_buf: CmsgBuf = [0; 32];
+    debug_assert!(space <= mem::size_of::<CmsgBuf>());
+
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if !fds.is_empty() {
+        msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fd_bytes) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr() as *const u8,
+                libc::CMSG_DATA(cmsg),
+                fd_bytes as usize,
+            );
+        }
+    }
+
+    let n = unsafe { libc::sendmsg(sock, &msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The fds went with the first byte; push any remainder as plain data
+    write_all(sock, &data[n as usize..])
+}
+
+/// Receive up to `buf.len()` bytes plus any fds attached as SCM_RIGHTS
+pub fn recv_with_fds(sock: RawFd, buf: &mut [u8]) -> io::Result<(usize, Vec<RawFd>)> {
+    let space = unsafe { libc::CMSG_SPACE((MAX_FDS * mem::size_of::<RawFd>()) as u32) } as usize;
+    let mut cmsg_buf = vec![0u8; space];
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    let n = unsafe { libc::recvmsg(sock, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut fds = Vec::new();
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                let count = data_len / mem::size_of::<RawFd>();
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                for i in 0..count {
+                    fds.push(std::ptr::read_unaligned(data.add(i)));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((n as usize, fds))
+}
+
+fn write_all(sock: RawFd, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let n = unsafe { libc::write(sock, data.as_ptr() as *const libc::c_void, data.len()) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        data = &data[n as usize..];
+    }
+    Ok(())
+}
+
+fn read_exact(sock: RawFd, mut buf: &mut [u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = unsafe { libc::read(sock, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "handoff closed early",
+            ));
+        }
+        buf = &mut buf[n as usize..];
+    }
+    Ok(())
+}
+
+/// A RestartState as sent over the handoff socketpair (length + JSON)
+fn encode_state(state: &RestartState) -> io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(state).map_err(|e| io_err(&e.to_string()))?;
+    let mut frame = (json.len() as u32).to_ne_bytes().to_vec();
+    frame.extend_from_slice(&json);
+    Ok(frame)
+}
+
+/// Send a RestartState and fds over one end of the handoff socketpair
+pub fn send_state(sock: RawFd, state: &RestartState, fds: &[RawFd]) -> io::Result<()> {
+    send_with_fds(sock, &encode_state(state)?, fds)
+}
+
+/// Receive a RestartState and fds from the handoff socketpair, then ack.
+/// On any error the received fds are closed
+pub fn recv_state(sock: RawFd) -> io::Result<(RestartState, Vec<RawFd>)> {
+    let mut len_buf = [0u8; 4];
+    let (n, fds) = recv_with_fds(sock, &mut len_buf)?;
+    match recv_rest(sock, &mut len_buf, n) {
+        Ok(state) => Ok((state, fds)),
+        Err(e) => {
+            for fd in fds {
+                unsafe { libc::close(fd) };
+            }
+            Err(e)
+        }
+    }
+}
+
+/// The rest of recv_state once the fds are in: the JSON, then the ack
+fn recv_rest(sock: RawFd, len_buf: &mut [u8; 4], got: usize) -> io::Result<RestartState> {
+    read_exact(sock, &mut len_buf[got..])?;
+    let mut json = vec![0u8; u32::from_ne_bytes(*len_buf) as usize];
+    read_exact(sock, &mut json)?;
+    let state: RestartState = serde_json::from_slice(&json).map_err(|e| io_err(&e.to_string()))?;
+    if state.version != RESTART_VERSION {
+        return Err(io_err(&format!(
+            "restart state version {} (expected {})",
+            state.version, RESTART_VERSION
+        )));
+    }
+    // Ack so the helper knows the fds are safely in the new process
+    write_all(sock, b"k")?;
+    Ok(state)
+}
+
+fn set_cloexec(fd: RawFd, on: bool) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags >= 0 {
+            let flags = if on {
+                flags | libc::FD_CLOEXEC
+            } else {
+                flags & !libc::FD_CLOEXEC
+            };
+            libc::fcntl(fd, libc::F_SETFD, flags);
+        }
+    }
+}
+
+/// Path of the running binary, tolerating it having been replaced on disk
+pub fn current_binary() -> io::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    let s = exe.to_string_lossy();
+    // Linux reports "/path/okros (deleted)" after an in-place upgrade
+    Ok(match s.strip_suffix(" (deleted)") {
+        Some(stripped) => PathBuf::from(stripped),
+        None => exe,
+    })
+}
+
+/// Exec `binary` as the new okros, handing it `state` and `fds`
+///
+/// Only returns on failure; on success the calling process image is replaced.
+pub fn exec_restart(binary: &Path, state: &RestartState, fds: &[RawFd]) -> io::Error {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = match CString::new(binary.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return io_err("binary path contains NUL"),
+    };
+    if unsafe { libc::access(c_path.as_ptr(), libc::X_OK) } != 0 {
+        return io::Error::last_os_error();
+    }
+    if fds.len() > MAX_FDS {
+        return io::Error::new(io::ErrorKind::InvalidInput, "too many fds");
+    }
+    // Everything that allocates happens before the fork
+    let frame = match encode_state(state) {
+        Ok(frame) => frame,
+        Err(e) => return e,
+    };
+    let flag = CString::new(RESTORE_FLAG).unwrap();
+
+    let mut pair = [0 as RawFd; 2];
+    if unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, pair.as_mut_ptr()) } < 0 {
+        return io::Error::last_os_error();
+    }
+    let (ours, helpers) = (pair[0], pair[1]);
+
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        let err = io::Error::last_os_error();
+        unsafe {
+            libc::close(ours);
+            libc::close(helpers);
+        }
+        return err;
+    }
+
+    if pid == 0 {
+        // Helper: hand over the fds, wait for the ack (or EOF if exec failed), exit
+        unsafe { libc::close(ours) };
+        let mut ack = [0u8; 1];
+        let ok =
+            send_with_fds(helpers, &frame, fds).is_ok() && read_exact(helpers, &mut ack).is_ok();
+        unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+    }
+
+    unsafe { libc::close(helpers) };
+
+    // Our copies of the MUD sockets must not leak into the new image -
+    // the helper's copies arriving via SCM_RIGHTS are the ones that survive
+    for &fd in fds {
+        set_cloexec(fd, true);
+    }
+    set_cloexec(ours, false);
+
+    let fd_arg = CString::new(ours.to_string()).unwrap();
+    let pid_arg = CString::new(pid.to_string()).unwrap();
+    let argv = [
+        c_path.as_ptr(),
+        flag.as_ptr(),
+        fd_arg.as_ptr(),
+        pid_arg.as_ptr(),
+        std::ptr::null(),
+    ];
+    unsafe { libc::execv(c_path.as_ptr(), argv.as_ptr()) };
+
+    // exec failed - undo so the session keeps running; helper sees EOF and exits
+    let err = io::Error::last_os_error();
+    for &fd in fds {
+        set_cloexec(fd, false);
+    }
+    unsafe {
+        libc::close(ours);
+        libc::waitpid(pid, std::ptr::null_mut(), 0);
+    }
+    err
+}
+
+/// Called by the new binary with the fd and helper pid from
+/// `--restore <fd> <pid>`
+pub fn restore(fd: RawFd, helper: Option<libc::pid_t>) -> io::Result<(RestartState, Vec<RawFd>)> {
+    let result = recv_state(fd);
+    unsafe {
+        libc::close(fd);
+        // Reap the helper: it exits on our ack, or on EOF if we failed
+        if let Some(pid) = helper {
+            while libc::waitpid(pid, std::ptr::null_mut(), 0) < 0
+                && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+            {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socketpair() -> (RawFd, RawFd) {
+        let mut pair = [0 as RawFd; 2];
+        let rc =
+            unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, pair.as_mut_ptr()) };
+        assert_eq!(rc, 0);
+        (pair[0], pair[1])
+    }
+
+    #[test]
+    fn state_and_fds_round_trip() {
+        let (a, b) = socketpair();
+        let (pipe_r, pipe_w) = {
+            let mut p = [0 as RawFd; 2];
+            assert_eq!(unsafe { libc::pipe(p.as_mut_ptr()) }, 0);
+            (p[0], p[1])
+        };
+
+        let mut state = RestartState::new();
+        state.mud_name = "Nodeka".to_string();
+        state.hostname = "nodeka.com".to_string();
+        state.port = 23;
+        state.scrollback = vec![vec![0x0741, 0x0742], vec![0x8443]];
+
+        let sender = std::thread::spawn(move || {
+            send_state(a, &state, &[pipe_w]).unwrap();
+            let mut ack = [0u8; 1];
+            read_exact(a, &mut ack).unwrap();
+            unsafe { libc::close(a) };
+            (state, ack[0])
+        });
+
+        let (got, fds) = recv_state(b).unwrap();
+        let (sent, ack) = sender.join().unwrap();
+        assert_eq!(got, sent);
+        assert_eq!(ack, b'k');
+        assert_eq!(fds.len(), 1);
+
+        // Received fd is a working duplicate of the pipe's write end
+        assert_ne!(fds[0], pipe_w);
+        unsafe {
+            assert_eq!(
+                libc::write(fds[0], b"x".as_ptr() as *const libc::c_void, 1),
+                1
+            );
+            let mut c = 0u8;
+            assert_eq!(
+                libc::read(pipe_r, &mut c as *mut u8 as *mut libc::c_void, 1),
+                1
+            );
+            assert_eq!(c, b'x');
+            libc::close(fds[0]);
+            libc::close(pipe_r);
+            libc::close(pipe_w);
+            libc::close(b);
+        }
+    }
+
+    #[test]
+    fn version_mismatch_rejected() {
+        let (a, b) = socketpair();
+        let mut state = RestartState::new();
+        state.version = RESTART_VERSION + 1;
+        send_state(a, &state, &[]).unwrap();
+
+        let err = recv_state(b).unwrap_err();
+        assert!(err.to_string().contains("version"));
+        unsafe {
+            libc::close(a);
+            libc::close(b);
+        }
+    }
+
+    #[test]
+    fn fds_closed_when_state_is_garbage() {
+        let (a, b) = socketpair();
+        let (pipe_r, pipe_w) = {
+            let mut p = [0 as RawFd; 2];
+            assert_eq!(unsafe { libc::pipe(p.as_mut_ptr()) }, 0);
+            (p[0], p[1])
+        };
+        let mut frame = 5u32.to_ne_bytes().to_vec();
+        frame.extend_from_slice(b"nope!");
+        send_with_fds(a, &frame, &[pipe_w]).unwrap();
+        unsafe { libc::close(pipe_w) };
+
+        assert!(recv_state(b).is_err());
+        // Our copy was the last write end: the reader sees EOF (not EAGAIN)
+        unsafe { libc::fcntl(pipe_r, libc::F_SETFL, libc::O_NONBLOCK) };
+        let mut c = 0u8;
+        let n = unsafe { libc::read(pipe_r, &mut c as *mut u8 as *mut libc::c_void, 1) };
+        assert_eq!(n, 0);
+        unsafe {
+            libc::close(pipe_r);
+            libc::close(a);
+            libc::close(b);
+        }
+    }
+
+    #[test]
+    fn exec_missing_binary_fails_cleanly() {
+        let err = exec_restart(Path::new("/nonexistent/okros"), &RestartState::new(), &[]);
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -5,10 +5,13 @@
 // C++ inheritance: Screen : public Window
 // Rust pattern: Screen owns a Window, delegates to it
 
-use crate::curses::AcsCaps;
+use crate::color::{BLINK, ITALIC, LINK, REVERSE, UNDERLINE};
+use crate::curses::{AcsCaps, CursorAddress, TermCaps};
+use crate::frame_writer::FrameWriter;
 use crate::scrollback::Attrib;
+use crate::ttyrec::TtyRecorder;
 use crate::window::Window;
-use std::io::{self, Write};
+use std::fmt::Write as _;
 use std::ptr;
 
 const FG_BOLD: u8 = 1 << 7;
@@ -26,40 +29,57 @@ fn reverse_color_conv_table(idx: u8) -> u8 {
     }
 }
 
-pub fn get_color_code(color: u8, set_bg: bool) -> String {
-    let fg = 30 + reverse_color_conv_table(color & 0x07) as i32;
-    let bold = (color & FG_BOLD) != 0;
-    let bg = 40 + reverse_color_conv_table((color >> 4) & 0x07) as i32;
-    if fg == 37 && bg == 40 && !bold {
-        return "\u{1b}[0m".to_string();
+pub fn get_color_code(color: u16, set_bg: bool) -> String {
+    let mut out = String::new();
+    push_color_code(&mut out, color, set_bg);
+    out
+}
+
+/// get_color_code, appended to `out` without allocating
+fn push_color_code(out: &mut String, color: u16, set_bg: bool) {
+    let fg = 30 + reverse_color_conv_table(color as u8 & 0x07) as i32;
+    let bold = (color as u8 & FG_BOLD) != 0;
+    let bg = 40 + reverse_color_conv_table((color as u8 >> 4) & 0x07) as i32;
+    if fg == 37 && bg == 40 && !bold && color >> 8 == 0 {
+        out.push_str("\u{1b}[0m");
+        return;
+    }
+    out.push_str(if bold { "\u{1b}[1" } else { "\u{1b}[0" });
+    // Text attributes above the color byte
+    for (bit, code) in [
+        (UNDERLINE, ";4"),
+        (ITALIC, ";3"),
+        (REVERSE, ";7"),
+        (BLINK, ";5"),
+    ] {
+        if color & bit != 0 {
+            out.push_str(code);
+        }
     }
-    let bg_part = if set_bg {
-        format!("{};", bg)
-    } else {
-        String::new()
-    };
-    if bold {
-        format!("\u{1b}[1;{}{}m", bg_part, fg)
-    } else {
-        format!("\u{1b}[0;{}{}m", bg_part, fg)
+    out.push(';');
+    if set_bg {
+        let _ = write!(out, "{};", bg);
     }
+    let _ = write!(out, "{}m", fg);
 }
 
-fn vt_home() -> &'static str {
-    "\u{1b}[H"
-}
-fn vt_goto(y1: usize, x1: usize) -> String {
-    format!("\u{1b}[{};{}H", y1, x1)
+fn vt_goto(out: &mut String, y1: usize, x1: usize, cup: Option<&CursorAddress>) {
+    match cup {
+        Some(cup) => cup.push(out, y1 - 1, x1 - 1),
+        None => {
+            let _ = write!(out, "\u{1b}[{};{}H", y1, x1);
+        }
+    }
 }
 
 /// Convert a row of Attrib cells to an ANSI-formatted string (for headless mode)
 /// Preserves all color information as escape sequences
 pub fn attrib_row_to_ansi(row: &[Attrib]) -> String {
     let mut out = String::new();
-    let mut current_color: Option<u8> = None;
+    let mut current_color: Option<u16> = None;
 
     for &attr in row {
-        let color = (attr >> 8) as u8;
+        let color = (attr >> 8) as u16;
         let ch = (attr & 0xFF) as u8;
 
         // Emit color change if needed
@@ -88,6 +108,8 @@ pub struct DiffOptions<'a> {
     pub smacs: Option<&'a str>,
     pub rmacs: Option<&'a str>,
     pub set_bg_always: bool,
+    pub hyperlinks: bool,               // Wrap color::LINK cells in OSC 8
+    pub cup: Option<&'a CursorAddress>, // Terminfo cursor addressing; None = ANSI
 }
 impl<'a> Default for DiffOptions<'a> {
     fn default() -> Self {
@@ -99,20 +121,39 @@ impl<'a> Default for DiffOptions<'a> {
             smacs: None,
             rmacs: None,
             set_bg_always: true,
+            hyperlinks: false,
+            cup: None,
         }
     }
 }
 
 pub fn diff_to_ansi(prev: &[Attrib], next: &[Attrib], opt: &DiffOptions) -> String {
+    let mut out = String::new();
+    diff_to_ansi_into(&mut out, prev, next, opt);
+    out
+}
+
+/// diff_to_ansi into `out` (cleared first), so a caller that keeps the
+/// String doesn't allocate every frame. Unchanged rows are skipped with one
+/// slice comparison
+pub fn diff_to_ansi_into(out: &mut String, prev: &[Attrib], next: &[Attrib], opt: &DiffOptions) {
     assert_eq!(prev.len(), next.len());
     assert_eq!(prev.len(), opt.width * opt.height);
-    let mut out = String::new();
-    out.push_str(vt_home());
+    out.clear();
+    match opt.cup {
+        Some(cup) => cup.push(out, 0, 0),
+        None => out.push_str("\u{1b}[H"),
+    }
     let mut saved_color: i32 = -1;
     let mut last_x = 0usize; // 0-based like C++
     let mut last_y = 0usize; // 0-based like C++
     let mut acs = false;
+    let mut link: Option<String> = None; // OSC 8 target being written
     for y in 0..opt.height {
+        let row = y * opt.width..(y + 1) * opt.width;
+        if prev[row.clone()] == next[row] {
+            continue;
+        }
         for x in 0..opt.width {
             if y == opt.height - 1 && x == opt.width - 1 {
                 continue;
@@ -121,10 +162,17 @@ pub fn diff_to_ansi(prev: &[Attrib], next: &[Attrib], opt: &DiffOptions) -> Stri
             if prev[idx] == next[idx] {
                 continue;
             }
-            let color = (next[idx] >> 8) as u8;
+            let color = (next[idx] >> 8) as u16;
             let ch = (next[idx] & 0xFF) as u8;
+            if opt.hyperlinks {
+                let target = (color & LINK != 0).then(|| link_target(next, idx));
+                if target != link {
+                    out.push_str(&crate::url::osc8(target.as_deref().unwrap_or("")));
+                    link = target;
+                }
+            }
             if (color as i32) != saved_color {
-                out.push_str(&get_color_code(color, opt.set_bg_always));
+                push_color_code(out, color, opt.set_bg_always);
                 saved_color = color as i32;
             }
             // Are we there yet? (C++ Screen.cc:256-271)
@@ -135,9 +183,9 @@ pub fn diff_to_ansi(prev: &[Attrib], next: &[Attrib], opt: &DiffOptions) -> Stri
                     && idx > 0
                     && (next[idx - 1] >> 8) as i32 == saved_color
                 {
-                    print_character(&mut out, (next[idx - 1] & 0xFF) as u8, &mut acs, opt);
+                    print_character(out, (next[idx - 1] & 0xFF) as u8, &mut acs, opt);
                 } else {
-                    out.push_str(&vt_goto(y + 1, x + 1));
+                    vt_goto(out, y + 1, x + 1, opt.cup);
                 }
             }
             last_y = y;
@@ -146,29 +194,60 @@ pub fn diff_to_ansi(prev: &[Attrib], next: &[Attrib], opt: &DiffOptions) -> Stri
                 last_x = 0;
                 last_y += 1;
             }
-            print_character(&mut out, ch, &mut acs, opt);
+            print_character(out, ch, &mut acs, opt);
         }
     }
-    out.push_str(&vt_goto(opt.cursor_y + 1, opt.cursor_x + 1));
+    if link.is_some() {
+        out.push_str(&crate::url::osc8(""));
+    }
+    vt_goto(out, opt.cursor_y + 1, opt.cursor_x + 1, opt.cup);
     if acs {
         if let Some(r) = opt.rmacs {
             out.push_str(r);
         }
     }
-    out
+}
+
+/// The URL a LINK cell belongs to: the text of its run of LINK cells
+/// (which may wrap onto the next row)
+fn link_target(cells: &[Attrib], idx: usize) -> String {
+    let is_link = |c: &Attrib| (c >> 8) as u16 & LINK != 0;
+    let start = cells[..idx]
+        .iter()
+        .rposition(|c| !is_link(c))
+        .map_or(0, |i| i + 1);
+    let end = cells[idx..]
+        .iter()
+        .position(|c| !is_link(c))
+        .map_or(cells.len(), |i| idx + i);
+    cells[start..end]
+        .iter()
+        .map(|c| (c & 0xFF) as u8 as char)
+        .collect()
+}
+
+/// Terminal character for a window::special byte: the VT100 alternate
+/// character set letter (drawn between smacs/rmacs), or an ASCII stand-in
+/// for terminals without one
+pub fn special_char(ch: u8, acs: bool) -> Option<char> {
+    let i = ch.checked_sub(crate::window::special::VLINE)? as usize;
+    let set = if acs { b"xqlkmja~" } else { b"|-++++#o" };
+    set.get(i).map(|&c| c as char)
 }
 
 /// Print character with ACS handling (C++ Screen::printCharacter)
 #[inline]
 fn print_character(out: &mut String, ch: u8, acs: &mut bool, opt: &DiffOptions) {
-    if ch >= 0xEC && ch < 0xEC + 8 {
+    if let (Some(c), None) = (special_char(ch, false), opt.smacs) {
+        out.push(c);
+    } else if let Some(c) = special_char(ch, true) {
         if !*acs {
             if let Some(s) = opt.smacs {
                 out.push_str(s);
             }
             *acs = true;
         }
-        out.push('#');
+        out.push(c);
     } else {
         if *acs {
             if let Some(r) = opt.rmacs {
@@ -237,7 +316,13 @@ pub struct Screen {
     scr_y: usize,
     scr_w: usize,
     scr_h: usize,
-    using_virtual: bool, // /dev/vcsa vs TTY (always false on macOS)
+    using_virtual: bool,         // /dev/vcsa vs TTY (always false on macOS)
+    pub hyperlinks: bool,        // OSC 8 links on URLs (config `hyperlinks`)
+    ansi: String,                // Output of the last frame, reused by the next
+    writer: Option<FrameWriter>, // Started by the first frame
+    frame_pending: bool,         // Changed while the writer was busy
+    pub recording: Option<TtyRecorder>, // #record: frames are also saved here
+    pub term: TermCaps,          // Set from terminfo by TtyUi
 }
 
 impl Screen {
@@ -248,7 +333,7 @@ impl Screen {
         window.clear();
 
         // TTY mode (macOS/non-Linux) - C++ Screen.cc:52-59
-        let last_screen = vec![0u16; width * height];
+        let last_screen = vec![0; width * height];
 
         Self {
             window,
@@ -258,6 +343,12 @@ impl Screen {
             scr_w: 0,
             scr_h: 0,
             using_virtual: false,
+            hyperlinks: false,
+            ansi: String::new(),
+            writer: None,
+            frame_pending: false,
+            recording: None,
+            term: TermCaps::vt100(),
         }
     }
 
@@ -274,10 +365,27 @@ impl Screen {
         // Call Window::refresh() to composite tree (C++ Screen.cc:84)
         // refreshTTY only if something changed (C++ Screen.cc:187)
         if self.window.refresh() {
-            self.refresh_tty(caps);
-            true
-        } else {
-            false
+            self.frame_pending = true;
+        }
+        // While the terminal is still taking the last frame, changes wait
+        // and go out together in the next one
+        if !self.frame_pending || self.writer.as_ref().is_some_and(|w| w.is_busy()) {
+            return false;
+        }
+        self.frame_pending = false;
+        self.refresh_tty(caps);
+        true
+    }
+
+    /// Changes are waiting for the writer to finish the last frame
+    pub fn frame_pending(&self) -> bool {
+        self.frame_pending
+    }
+
+    /// Wait until the terminal has been sent every frame
+    pub fn flush_output(&self) {
+        if let Some(writer) = &self.writer {
+            writer.flush();
         }
     }
 
@@ -285,9 +393,11 @@ impl Screen {
     fn refresh_tty(&mut self, caps: &AcsCaps) {
         let width = self.window.width;
         let height = self.window.height;
+        let scroll = self.scroll_region(width, height);
 
         // Generate ANSI escape codes by diffing last_screen vs canvas
-        let ansi = diff_to_ansi(
+        diff_to_ansi_into(
+            &mut self.ansi,
             &self.last_screen,
             &self.window.canvas,
             &DiffOptions {
@@ -298,18 +408,64 @@ impl Screen {
                 smacs: caps.smacs.as_deref(),
                 rmacs: caps.rmacs.as_deref(),
                 set_bg_always: true,
+                hyperlinks: self.hyperlinks,
+                cup: Some(&self.term.cup),
             },
         );
 
-        // Write to stdout (C++ Screen.cc:295)
-        let mut out = io::stdout();
-        let _ = out.write_all(ansi.as_bytes());
-        let _ = out.flush();
+        // Write to stdout (C++ Screen.cc:295), on the writer thread
+        if let Some(scroll) = scroll {
+            self.ansi.insert_str(0, &scroll);
+        }
+        // A recording that can't be written to just stops (#record shows it)
+        if let Some(Err(_)) = self.recording.as_mut().map(|r| r.write_frame(&self.ansi)) {
+            self.recording = None;
+        }
+        let writer = self.writer.get_or_insert_with(FrameWriter::stdout);
+        let spare = writer.submit(std::mem::take(&mut self.ansi).into_bytes());
+        self.ansi = String::from_utf8(spare).unwrap_or_default();
 
         // Update last_screen for next diff (C++ Screen.cc:299)
         self.last_screen.copy_from_slice(&self.window.canvas);
     }
 
+    /// If the scrolling region's contents moved up, scroll the terminal to
+    /// match (CSI r and newlines) and shift last_screen the same way, so the
+    /// diff only has the new rows left to draw. Returns the escape sequence
+    fn scroll_region(&mut self, width: usize, height: usize) -> Option<String> {
+        // DECSTBM scrolls whole rows: the region must span the screen
+        let (y, h) = (self.scr_y, self.scr_h);
+        if !self.term.csr || h < 2 || self.scr_x != 0 || self.scr_w != width || y + h > height {
+            return None;
+        }
+        let region = y * width..(y + h) * width;
+        if self.last_screen[region.clone()] == self.window.canvas[region.clone()] {
+            return None;
+        }
+        let n = plan_scroll_up(&self.last_screen, &self.window.canvas, width, height, y, h)?;
+        // Scrolling further than half the region costs more than it saves
+        if n > h / 2 {
+            return None;
+        }
+        // New rows come in blank in the default color
+        let mut seq = self.term.sgr0.clone();
+        seq.push_str(&emit_scroll_ansi(width, height, y, h, n));
+        let last = &mut self.last_screen[region];
+        last.copy_within(n * width.., 0);
+        let blank = (0x07 << 8) | b' ' as Attrib;
+        let kept = last.len() - n * width;
+        last[kept..].fill(blank);
+        Some(seq)
+    }
+
+    /// Forget what the terminal shows so the next refresh repaints every cell
+    /// (after something else drew on it, e.g. ^L)
+    pub fn invalidate(&mut self) {
+        // No canvas cell is ever 0xFFFF, so every cell differs
+        self.last_screen.fill(0xFFFF);
+        self.window.dirty = true;
+    }
+
     /// Get mutable window reference
     pub fn window_mut(&mut self) -> &mut Window {
         &mut self.window
@@ -330,7 +486,7 @@ impl Screen {
 mod tests {
     use super::*;
     fn cell(ch: u8, color: u8) -> Attrib {
-        ((color as u16) << 8) | ch as u16
+        ((color as Attrib) << 8) | ch as Attrib
     }
     #[test]
     fn color_change_and_reset() {
@@ -350,6 +506,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.contains("\u{1b}[1;"));
@@ -357,6 +515,17 @@ mod tests {
         assert_eq!(code, "\u{1b}[0m");
     }
     #[test]
+    fn color_code_carries_text_attributes() {
+        use crate::color::{REVERSE, UNDERLINE};
+        assert_eq!(get_color_code(UNDERLINE | 0x07, true), "\u{1b}[0;4;40;37m");
+        assert_eq!(
+            get_color_code(UNDERLINE | REVERSE | 0x84, false),
+            "\u{1b}[1;4;7;31m"
+        );
+        let row = [((UNDERLINE | 0x07) as Attrib) << 8 | b'x' as Attrib];
+        assert!(attrib_row_to_ansi(&row).starts_with("\u{1b}[0;4;40;37mx"));
+    }
+    #[test]
     fn skip_bottom_right() {
         let w = 2;
         let h = 2;
@@ -374,10 +543,43 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(!s.contains("\u{1b}[2;2H"));
     }
+    #[test]
+    fn link_cells_get_osc8() {
+        let (w, h) = (6, 2);
+        let prev = vec![cell(b' ', 0x07); w * h];
+        let mut next = prev.clone();
+        let link = ((UNDERLINE | LINK | 0x07) as Attrib) << 8;
+        // "http:/" wraps onto "/a" in the next row
+        for (i, ch) in b"http://a".iter().enumerate() {
+            next[i] = link | *ch as Attrib;
+        }
+        next[8] = cell(b'!', 0x07);
+        let opt = DiffOptions {
+            width: w,
+            height: h,
+            hyperlinks: true,
+            ..Default::default()
+        };
+        let s = diff_to_ansi(&prev, &next, &opt);
+        assert_eq!(s.matches("\x1b]8;;http://a\x1b\\").count(), 1);
+        assert!(s.contains("/a\x1b]8;;\x1b\\"));
+        let plain = diff_to_ansi(
+            &prev,
+            &next,
+            &DiffOptions {
+                hyperlinks: false,
+                ..opt
+            },
+        );
+        assert!(!plain.contains("\x1b]8"));
+    }
+
     #[test]
     fn minimal_cursoring() {
         let w = 3;
@@ -397,6 +599,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.contains("\u{1b}[1;1H"));
@@ -442,11 +646,33 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.starts_with("\u{1b}[H"));
         assert!(s.ends_with("\u{1b}[2;2H"));
     }
+
+    #[test]
+    fn terminfo_cursor_addressing() {
+        let cup = CursorAddress::parse("\u{1b}&a%p1%dy%p2%dC").unwrap();
+        let prev = vec![cell(b' ', 0); 4 * 2];
+        let mut next = prev.clone();
+        next[2] = cell(b'X', 0);
+        let opt = DiffOptions {
+            width: 4,
+            height: 2,
+            cursor_x: 1,
+            cursor_y: 1,
+            cup: Some(&cup),
+            ..Default::default()
+        };
+        let s = diff_to_ansi(&prev, &next, &opt);
+        assert!(s.starts_with("\u{1b}&a0y0C"));
+        assert!(s.contains("\u{1b}&a0y2CX"));
+        assert!(s.ends_with("\u{1b}&a1y1C"));
+    }
     #[test]
     fn control_chars_render_as_spaces() {
         let w = 2;
@@ -465,6 +691,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.contains(" "));
@@ -488,11 +716,37 @@ mod tests {
                 smacs: Some("[SM]"),
                 rmacs: Some("[RM]"),
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         let start = s.find("[SM]").unwrap();
         let end = s.find("[RM]").unwrap();
         assert!(start < end);
+        assert!(s.contains("[SM]x"));
+    }
+    #[test]
+    fn specials_without_acs_use_ascii() {
+        let w = 3;
+        let h = 2;
+        let prev = vec![cell(b' ', 0); w * h];
+        let mut next = prev.clone();
+        next[0] = cell(0xEE, 0);
+        next[1] = cell(0xED, 0);
+        next[2] = cell(0xEF, 0);
+        let s = diff_to_ansi(
+            &prev,
+            &next,
+            &DiffOptions {
+                width: w,
+                height: h,
+                ..Default::default()
+            },
+        );
+        assert!(s.contains("+-+"));
+        assert_eq!(special_char(0xF3, true), Some('~'));
+        assert_eq!(special_char(0xF4, true), None);
+        assert_eq!(special_char(b'x', false), None);
     }
     #[test]
     fn acs_two_specials_then_normal() {
@@ -514,6 +768,8 @@ mod tests {
                 smacs: Some("[SM]"),
                 rmacs: Some("[RM]"),
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         let i_sm = s.find("[SM]").unwrap();
@@ -541,6 +797,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(s.contains("\u{1b}[1;1H"));
@@ -565,6 +823,8 @@ mod tests {
                 smacs: Some("[SM]"),
                 rmacs: Some("[RM]"),
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(!s.contains("[SM]"));
@@ -588,6 +848,8 @@ mod tests {
                 smacs: Some("[SM]"),
                 rmacs: Some("[RM]"),
                 set_bg_always: true,
+                hyperlinks: false,
+                cup: None,
             },
         );
         let goto = format!("\u{1b}[{};{}H", 1, 3);
@@ -614,6 +876,8 @@ mod tests {
                 smacs: None,
                 rmacs: None,
                 set_bg_always: false,
+                hyperlinks: false,
+                cup: None,
             },
         );
         assert!(!s.contains(";40;"));
@@ -653,6 +917,45 @@ mod tests {
         assert!(s.contains('A'));
     }
 
+    #[test]
+    fn unchanged_rows_skipped_and_buffer_reused() {
+        let (w, h) = (4, 3);
+        let prev = vec![cell(b' ', 0x07); w * h];
+        let mut next = prev.clone();
+        next[w + 1] = cell(b'X', 0x07);
+        let opt = DiffOptions {
+            width: w,
+            height: h,
+            ..Default::default()
+        };
+        let mut out = "stale".to_string();
+        diff_to_ansi_into(&mut out, &prev, &next, &opt);
+        assert_eq!(out, "\u{1b}[H\u{1b}[0m\u{1b}[2;2HX\u{1b}[1;1H");
+        assert_eq!(out, diff_to_ansi(&prev, &next, &opt));
+    }
+
+    #[test]
+    fn output_scroll_becomes_terminal_scroll() {
+        let (w, h) = (3, 5);
+        let mut screen = Screen::new(w, h);
+        screen.set_scrolling_region(0, 1, w, 3);
+        let row = |c: u8| vec![cell(c, 0x07); w];
+        let rows = |cs: &[u8]| cs.iter().flat_map(|&c| row(c)).collect::<Vec<_>>();
+        screen.last_screen = rows(b"SABCI");
+        screen.window.canvas = rows(b"SBCDI");
+        let seq = screen.scroll_region(w, h).unwrap();
+        assert_eq!(seq, "\u{1b}[0m\u{1b}[2;4r\u{1b}[4;1H\n\u{1b}[1;5r");
+        // Only the new bottom row of the region is left to draw
+        assert_eq!(screen.last_screen, rows(b"SBC I"));
+
+        // Nothing moved, or the region isn't full width: no scroll
+        screen.last_screen = screen.window.canvas.clone();
+        assert!(screen.scroll_region(w, h).is_none());
+        screen.last_screen = rows(b"SABCI");
+        screen.set_scrolling_region(1, 1, w - 1, 3);
+        assert!(screen.scroll_region(w, h).is_none());
+    }
+
     #[test]
     fn screen_creation() {
         let screen = Screen::new(80, 24);
@@ -0,0 +1,272 @@
+// Script files - load, reload and watch ~/.okros/scripts
+//
+// C++ MCL loads its interpreter's startup file once (Embedded.cc). Here every
+// *.py / *.pl file in the scripts directory is loaded into the interpreter
+// for its extension at startup, `#reload [file]` runs them again, and
+// ScriptWatcher reports edited files for the autoreload option. MUD blocks
+// can add their own `script <file>` lines, loaded when connecting.
+
+use crate::plugins::stack::Interpreter;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directory holding user scripts: ~/.okros/scripts
+pub fn scripts_dir() -> PathBuf {
+    crate::config::Config::default_path().with_file_name("scripts")
+}
+
+/// Script files in `dir` (any extension), sorted so load order is stable
+pub fn script_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// `#reload` argument: absolute/relative paths as given (`~` and `$VAR`
+/// expanded), bare names from `dir`
+pub fn resolve_script(dir: &Path, name: &str) -> PathBuf {
+    let path = crate::config::expand_path(name);
+    if path.components().count() > 1 || path.is_absolute() || path.exists() {
+        path
+    } else {
+        dir.join(path)
+    }
+}
+
+/// Load `path` into the interpreter registered for its extension
+/// `interps` pairs an extension ("py", "pl") with its interpreter
+pub fn load_script(
+    interps: &mut [(&str, &mut dyn Interpreter)],
+    path: &Path,
+) -> Result<(), String> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let Some((_, interp)) = interps.iter_mut().find(|(e, _)| *e == ext) else {
+        return Err(format!("no interpreter for {}", path.display()));
+    };
+    if !path.is_file() {
+        return Err(format!("no such script: {}", path.display()));
+    }
+    // Suppress interpreter error output: it would land on the TTY screen
+    if interp.load_file(&path.to_string_lossy(), true) {
+        Ok(())
+    } else {
+        Err(format!("error loading {}", path.display()))
+    }
+}
+
+/// Load every script in `dir` that some interpreter handles
+/// Returns how many loaded, plus the errors
+pub fn load_all(interps: &mut [(&str, &mut dyn Interpreter)], dir: &Path) -> (usize, Vec<String>) {
+    let mut loaded = 0;
+    let mut errors = Vec::new();
+    for path in script_files(dir) {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if !interps.iter().any(|(e, _)| *e == ext) {
+            continue;
+        }
+        match load_script(interps, &path) {
+            Ok(()) => loaded += 1,
+            Err(e) => errors.push(e),
+        }
+    }
+    (loaded, errors)
+}
+
+/// Switch MUD scripts (`script <file>` in a MUD block) from `previous` to
+/// `next`. Interpreters can't unload code, so if the old MUD had scripts the
+/// scripts directory is loaded again first, putting back anything they
+/// overrode; then `next` loads in order. Relative names are from `base`
+/// (the config file's directory), so they aren't loaded for every MUD.
+/// Returns how many loaded, plus the errors
+pub fn switch_mud_scripts(
+    interps: &mut [(&str, &mut dyn Interpreter)],
+    dir: &Path,
+    base: &Path,
+    previous: &[String],
+    next: &[String],
+) -> (usize, Vec<String>) {
+    let (mut loaded, mut errors) = match previous.is_empty() {
+        true => (0, Vec::new()),
+        false => load_all(interps, dir),
+    };
+    for name in next {
+        match load_script(interps, &base.join(crate::config::expand_path(name))) {
+            Ok(()) => loaded += 1,
+            Err(e) => errors.push(e),
+        }
+    }
+    (loaded, errors)
+}
+
+/// Variables scripts read for the session being connected: $mud, $host
+/// and $port (C++ MCL sets them when a Session starts)
+pub fn set_session_vars(interp: &mut dyn Interpreter, mud: &str, host: &str, port: u16) {
+    interp.set_str("mud", mud);
+    interp.set_str("host", host);
+    interp.set_int("port", port as i64);
+}
+
+/// Polls a directory for new or modified files (mtime based; no inotify)
+pub struct ScriptWatcher {
+    dir: PathBuf,
+    mtimes: HashMap<PathBuf, SystemTime>,
+}
+
+impl ScriptWatcher {
+    /// Start watching `dir`; files already there count as seen
+    pub fn new(dir: PathBuf) -> Self {
+        let mut w = Self {
+            dir,
+            mtimes: HashMap::new(),
+        };
+        w.changed();
+        w
+    }
+
+    /// Files created or modified since the last call
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let mut seen = HashMap::new();
+        for path in script_files(&self.dir) {
+            let Ok(mtime) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+                continue;
+            };
+            if self.mtimes.get(&path) != Some(&mtime) {
+                changed.push(path.clone());
+            }
+            seen.insert(path, mtime);
+        }
+        self.mtimes = seen;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder {
+        loaded: Vec<String>,
+        vars: Vec<(String, String)>,
+    }
+
+    impl Interpreter for Recorder {
+        fn run(&mut self, _: &str, _: &str, _: &mut String) -> bool {
+            false
+        }
+        fn load_file(&mut self, filename: &str, _suppress: bool) -> bool {
+            self.loaded.push(filename.to_string());
+            !filename.ends_with("bad.py")
+        }
+        fn set_int(&mut self, var: &str, val: i64) {
+            self.vars.push((var.to_string(), val.to_string()));
+        }
+        fn set_str(&mut self, var: &str, val: &str) {
+            self.vars.push((var.to_string(), val.to_string()));
+        }
+    }
+
+    #[test]
+    fn session_vars_name_the_mud() {
+        let mut interp = Recorder::default();
+        set_session_vars(&mut interp, "Nodeka", "nodeka.com", 23);
+        let vars: Vec<(&str, &str)> = interp
+            .vars
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            vars,
+            vec![("mud", "Nodeka"), ("host", "nodeka.com"), ("port", "23")]
+        );
+    }
+
+    #[test]
+    fn load_all_routes_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.py", "a.py", "bad.py", "notes.txt", "x.pl"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let mut py = Recorder::default();
+        let (loaded, errors) = load_all(&mut [("py", &mut py)], dir.path());
+        assert_eq!(loaded, 2);
+        assert_eq!(errors.len(), 1);
+        let names: Vec<_> = py
+            .loaded
+            .iter()
+            .map(|p| Path::new(p).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.py", "b.py", "bad.py"]);
+
+        let err = load_script(&mut [("py", &mut py)], &dir.path().join("x.pl"));
+        assert!(err.unwrap_err().contains("no interpreter"));
+    }
+
+    #[test]
+    fn switch_mud_scripts_reloads_globals_first() {
+        let base = tempfile::tempdir().unwrap();
+        let dir = base.path().join("scripts");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("global.py"), "").unwrap();
+        for name in ["a.py", "b.py"] {
+            std::fs::write(base.path().join(name), "").unwrap();
+        }
+        let mut py = Recorder::default();
+        let first = ["a.py".to_string()];
+        let (loaded, errors) =
+            switch_mud_scripts(&mut [("py", &mut py)], &dir, base.path(), &[], &first);
+        assert_eq!((loaded, errors.len()), (1, 0));
+
+        py.loaded.clear();
+        let next = ["b.py".to_string(), "missing.py".to_string()];
+        let (loaded, errors) =
+            switch_mud_scripts(&mut [("py", &mut py)], &dir, base.path(), &first, &next);
+        assert_eq!((loaded, errors.len()), (2, 1));
+        let names: Vec<_> = py
+            .loaded
+            .iter()
+            .map(|p| Path::new(p).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["global.py", "b.py"]);
+    }
+
+    #[test]
+    fn resolve_script_names() {
+        let dir = Path::new("/scripts");
+        assert_eq!(resolve_script(dir, "main.py"), dir.join("main.py"));
+        assert_eq!(resolve_script(dir, "/tmp/x.py"), PathBuf::from("/tmp/x.py"));
+        assert_eq!(resolve_script(dir, "sub/x.py"), PathBuf::from("sub/x.py"));
+    }
+
+    #[test]
+    fn watcher_reports_new_and_modified_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.py");
+        std::fs::write(&path, "x = 1").unwrap();
+        let mut w = ScriptWatcher::new(dir.path().to_path_buf());
+        assert!(w.changed().is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        std::fs::write(dir.path().join("new.py"), "").unwrap();
+        let mut changed = w.changed();
+        changed.sort();
+        assert_eq!(changed, vec![path, dir.path().join("new.py")]);
+        assert!(w.changed().is_empty());
+    }
+}
@@ -1,4 +1,24 @@
-pub type Attrib = u16;
+// Scrollback - the output ring drawn on screen, plus the logical lines behind it
+//
+// The ring (C++ OutputWindow's scrollback array) holds screen-width rows
+// and forgets the oldest ones; every line printed is also kept whole in a
+// LineStore, which spills to disk instead of forgetting and can be
+// rendered again at any width.
+
+use crate::line_store::LineStore;
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::ops::Range;
+
+pub type Attrib = u32;
+
+/// Rows in the ring unless the config says otherwise (`scrollback`)
+pub const DEFAULT_LINES: usize = 2000;
+/// Fewest rows allowed: a full ring moves 250 rows at a time
+pub const MIN_LINES: usize = 500;
+/// Most rows allowed (#buffer, `scrollback`): 100k rows of 200 cells is 80 MB
+pub const MAX_LINES: usize = 100_000;
 
 pub struct Scrollback {
     pub width: usize,
@@ -11,21 +31,31 @@ pub struct Scrollback {
     pub(crate) rows_filled: usize,
     frozen: bool,
     pub(crate) total_lines_written: usize, // Monotonic counter for headless mode
+    word_wrap: bool,
+    pub(crate) history: LineStore,
+    pending: Vec<Attrib>, // Cells of the line being printed by OutputWindow
 }
 
 impl Scrollback {
     pub fn new(width: usize, height: usize, lines: usize) -> Self {
+        let lines = lines.min(MAX_LINES);
+        let cells = width
+            .checked_mul(lines)
+            .expect("scrollback width times rows overflows");
         Self {
             width,
             height,
             lines,
-            buf: vec![0; width * lines],
+            buf: vec![0; cells],
             canvas_off: 0,
             viewpoint: 0,
             top_line: 0,
             rows_filled: 0,
             frozen: false,
             total_lines_written: 0,
+            word_wrap: false,
+            history: LineStore::default(),
+            pending: Vec::new(),
         }
     }
     pub fn set_frozen(&mut self, f: bool) {
@@ -96,8 +126,18 @@ impl Scrollback {
     pub fn canvas_ptr(&self) -> usize {
         self.canvas_off
     }
-    pub fn print_line(&mut self, bytes: &[u8], color: u8) {
-        let screen_span = self.width * self.height;
+    /// Wrap long lines at word boundaries instead of at the last column
+    pub fn set_word_wrap(&mut self, on: bool) {
+        self.word_wrap = on;
+    }
+
+    pub fn word_wrap(&self) -> bool {
+        self.word_wrap
+    }
+
+    /// Advance to a fresh row filled with `blank`, scrolling (and compacting
+    /// the buffer) as needed; returns the row's offset in buf
+    fn next_row(&mut self, blank: Attrib) -> usize {
         let max_canvas = self.width * (self.lines - self.height);
         if self.canvas_off >= max_canvas {
             const COPY: usize = 250;
@@ -121,69 +161,176 @@ impl Scrollback {
             self.rows_filled += 1;
             s
         } else {
+            // canvas_off is the top of the live screen (as in OutputWindow);
+            // the new row goes at its bottom, and an unfrozen view follows it
             self.canvas_off += self.width;
             if !self.frozen {
-                if self.viewpoint + screen_span < self.canvas_off {
-                    self.viewpoint = self.canvas_off - screen_span;
-                }
+                self.viewpoint = self.canvas_off;
             }
-            self.viewpoint + (self.height - 1) * self.width
+            self.canvas_off + (self.height - 1) * self.width
         };
         for a in &mut self.buf[start..start + self.width] {
-            *a = ((color as u16) << 8) | b' ' as u16;
-        }
-        for (i, b) in bytes.iter().take(self.width).enumerate() {
-            self.buf[start + i] = ((color as u16) << 8) | (*b as u16);
+            *a = blank;
         }
-        self.total_lines_written += 1; // Increment monotonic counter
+        self.total_lines_written += 1; // Counts rows, continuation rows included
+        start
     }
 
-    /// Print line with per-character colors (like C++ SET_COLOR stream)
-    pub fn print_line_colored(&mut self, pairs: &[(u8, u8)]) {
-        let screen_span = self.width * self.height;
-        let max_canvas = self.width * (self.lines - self.height);
-        if self.canvas_off >= max_canvas {
-            const COPY: usize = 250;
-            let copy = COPY.min(self.lines - self.height);
-            let shift = copy * self.width;
-            self.buf.copy_within(shift.., 0);
-            self.canvas_off -= shift;
-            if self.viewpoint >= shift {
-                self.viewpoint -= shift
-            } else {
-                self.viewpoint = 0
+    /// Rows the ring holds
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Bytes held in memory: the ring plus the unwrapped lines not spilled
+    /// to disk
+    pub fn memory_bytes(&self) -> usize {
+        let cell = std::mem::size_of::<Attrib>();
+        (self.buf.capacity() + self.pending.capacity()) * cell + self.history.memory_bytes()
+    }
+
+    /// Size and memory use, for #buffer
+    pub fn usage_text(&self) -> String {
+        let history = &self.history;
+        let disk = if history.spill_enabled() {
+            format!("{} on disk", history.spilled())
+        } else {
+            "spill off".to_string()
+        };
+        format!(
+            "Scrollback: {} rows; {} lines kept ({}); {} KB in memory",
+            self.lines,
+            history.len() - history.first(),
+            disk,
+            self.memory_bytes().div_ceil(1024)
+        )
+    }
+
+    /// Let lines past the memory cap go to a temp file of up to `limit`
+    /// bytes (`scrollbackspill`), or drop them
+    pub fn set_spill(&mut self, on: bool, limit: u64) {
+        self.history.set_spill(on, limit);
+    }
+
+    /// Every line printed so far, unwrapped
+    pub fn history(&self) -> &LineStore {
+        &self.history
+    }
+
+    /// Record a cell of the line being printed a character at a time
+    pub(crate) fn record_cell(&mut self, a: Attrib) {
+        self.pending.push(a);
+    }
+
+    /// Take back the last `n` recorded cells (see OutputWindow::unprint)
+    pub(crate) fn unrecord(&mut self, n: usize) {
+        let keep = self.pending.len().saturating_sub(n);
+        self.pending.truncate(keep);
+    }
+
+    /// The line being printed is complete
+    pub(crate) fn end_line(&mut self) {
+        let line = std::mem::take(&mut self.pending);
+        self.history.push(line);
+    }
+
+    /// Carry another scrollback's history over (when rebuilding the ring)
+    pub(crate) fn take_history(&mut self, from: Scrollback) {
+        self.history = from.history;
+        self.pending = from.pending;
+    }
+
+    /// Numbered logical lines, newest first: the line being printed (even
+    /// while it's empty), then the history
+    fn lines_newest_first(&self) -> impl Iterator<Item = (usize, Cow<'_, [Attrib]>)> {
+        let newest = (self.history.len(), self.pending.as_slice().into());
+        let older = (self.history.first()..self.history.len())
+            .rev()
+            .filter_map(|n| Some((n, self.history.get(n)?)));
+        std::iter::once(newest).chain(older)
+    }
+
+    /// Where `line` breaks into rows at `width`
+    fn line_rows(&self, line: &[Attrib], width: usize) -> Vec<Range<usize>> {
+        let text: Vec<u8> = line.iter().map(|a| (a & 0xFF) as u8).collect();
+        wrap_rows(&text, width, self.word_wrap)
+    }
+
+    /// The newest `count` rows of the history (the line still being printed
+    /// included), wrapped to `width`, oldest first
+    pub fn render(&self, width: usize, count: usize) -> Vec<Vec<Attrib>> {
+        let mut rows = Vec::new();
+        let lines = self
+            .lines_newest_first()
+            .skip(self.pending.is_empty() as usize);
+        for (_, line) in lines {
+            if rows.len() >= count {
+                break;
             }
-            self.top_line += copy;
-            let tail = self.buf.len() - shift;
-            for a in &mut self.buf[tail..] {
-                *a = 0;
+            for r in self.line_rows(&line, width).into_iter().rev() {
+                rows.push(line[r].to_vec());
             }
         }
-        let start = if self.rows_filled < self.height {
-            let s = self.viewpoint + self.rows_filled * self.width;
-            self.rows_filled += 1;
-            s
-        } else {
-            self.canvas_off += self.width;
-            if !self.frozen {
-                if self.viewpoint + screen_span < self.canvas_off {
-                    self.viewpoint = self.canvas_off - screen_span;
-                }
-            }
-            self.viewpoint + (self.height - 1) * self.width
-        };
+        rows.truncate(count);
+        rows.reverse();
+        rows
+    }
 
-        // Fill with spaces first (use default color 0x07)
-        for a in &mut self.buf[start..start + self.width] {
-            *a = (0x07u16 << 8) | b' ' as u16;
+    /// The logical line on the row `rows_up` rows above the bottom one
+    /// (0 = the line being printed) when wrapped to `width`
+    pub fn line_at_row(&self, width: usize, rows_up: usize) -> Option<usize> {
+        let mut rows = 0;
+        for (n, line) in self.lines_newest_first() {
+            rows += self.line_rows(&line, width).len();
+            if rows > rows_up {
+                return Some(n);
+            }
         }
+        None
+    }
+
+    /// Rows from the first row of logical line `n` down to the bottom one
+    /// when wrapped to `width`
+    pub fn rows_from_line(&self, width: usize, n: usize) -> usize {
+        self.lines_newest_first()
+            .take_while(|(m, _)| *m >= n)
+            .map(|(_, line)| self.line_rows(&line, width).len())
+            .sum()
+    }
 
-        // Write characters with their individual colors
-        for (i, (ch, color)) in pairs.iter().take(self.width).enumerate() {
-            self.buf[start + i] = ((*color as u16) << 8) | (*ch as u16);
+    /// Print a line, continuing on as many rows as it needs
+    pub fn print_line(&mut self, bytes: &[u8], color: u8) {
+        self.history.push(
+            bytes
+                .iter()
+                .map(|&b| ((color as Attrib) << 8) | b as Attrib)
+                .collect(),
+        );
+        for row in wrap_rows(bytes, self.width, self.word_wrap) {
+            let start = self.next_row(((color as Attrib) << 8) | b' ' as Attrib);
+            for (i, b) in bytes[row].iter().enumerate() {
+                self.buf[start + i] = ((color as Attrib) << 8) | (*b as Attrib);
+            }
         }
+    }
 
-        self.total_lines_written += 1;
+    /// Print line with per-character colors (like C++ SET_COLOR stream)
+    pub fn print_line_colored(&mut self, pairs: &[(u8, u16)]) {
+        self.history.push(
+            pairs
+                .iter()
+                .map(|&(ch, color)| ((color as Attrib) << 8) | ch as Attrib)
+                .collect(),
+        );
+        let text: Vec<u8> = pairs.iter().map(|(ch, _)| *ch).collect();
+        for row in wrap_rows(&text, self.width, self.word_wrap) {
+            // Fill with spaces first (use default color 0x07)
+            let start = self.next_row(((0x07 as Attrib) << 8) | b' ' as Attrib);
+
+            // Write characters with their individual colors
+            for (i, (ch, color)) in pairs[row].iter().enumerate() {
+                self.buf[start + i] = ((*color as Attrib) << 8) | (*ch as Attrib);
+            }
+        }
     }
     pub fn viewport_slice(&self) -> &[Attrib] {
         &self.buf[self.viewpoint..self.viewpoint + self.width * self.height]
@@ -229,6 +376,46 @@ impl Scrollback {
         result
     }
 
+    /// History lines matching a regex, oldest first (spilled ones included)
+    pub fn grep(&self, re: &Regex) -> Vec<GrepMatch> {
+        let pending = (!self.pending.is_empty())
+            .then(|| (self.history.len(), self.pending.as_slice().into()));
+        self.history
+            .iter_from(0)
+            .chain(pending)
+            .map(|(line, cells)| (line, row_text(&cells)))
+            .filter(|(_, text)| re.is_match(text))
+            .map(|(line, text)| GrepMatch { line, text })
+            .collect()
+    }
+
+    /// Up to `count` history lines from line number `from` on (negative:
+    /// counted back from the newest), the line being printed included.
+    /// Also returns the oldest line number still kept and the line count
+    pub fn line_range(&self, from: i64, count: usize) -> (Vec<HistoryLine>, usize, usize) {
+        let pending = !self.pending.is_empty();
+        let total = self.history.len() + pending as usize;
+        let oldest = self.history.first();
+        let start = if from < 0 {
+            total.saturating_sub(from.unsigned_abs() as usize)
+        } else {
+            from as usize
+        };
+        let pending = pending.then(|| (self.history.len(), self.pending.as_slice().into()));
+        let lines = self
+            .history
+            .iter_from(start)
+            .chain(pending.filter(|(n, _)| *n >= start))
+            .take(count)
+            .map(|(line, cells)| HistoryLine {
+                line,
+                text: row_text(&cells),
+                ansi: crate::screen::attrib_row_to_ansi(&cells),
+            })
+            .collect();
+        (lines, oldest.min(total), total)
+    }
+
     pub fn move_viewpoint_page(&mut self, down: bool) {
         let d = (self.height / 2).max(1) * self.width;
         if down {
@@ -245,24 +432,91 @@ impl Scrollback {
             self.viewpoint = self.viewpoint.saturating_sub(d);
         }
     }
-    pub fn highlight_view(&self, line_off: usize, x: usize, len: usize) -> Vec<Attrib> {
+    /// The viewport with `spans` (offsets into buf, so a span can start
+    /// above the view or run on through wrapped rows) drawn in `color`, or
+    /// with foreground and background swapped if None. Parts outside the
+    /// view are clipped; bold and text attributes are kept
+    pub fn highlight_view(&self, spans: &[Range<usize>], color: Option<u8>) -> Vec<Attrib> {
         let mut v = self.viewport_slice().to_vec();
-        if line_off < self.height && x < self.width {
-            let start = line_off * self.width + x;
-            let end = (start + len).min(self.height * self.width);
-            for a in &mut v[start..end] {
-                let ch = *a & 0x00FF;
-                let mut color = (((*a) >> 8) as u8) & !(0x80);
-                let fg = color & 0x0F;
-                let bg = (color & 0xF0) >> 4;
-                color = (fg << 4) | bg;
-                *a = ((color as u16) << 8) | ch;
+        let view = self.viewpoint..self.viewpoint + v.len();
+        for span in spans {
+            let start = span.start.max(view.start);
+            let end = span.end.min(view.end);
+            if start >= end {
+                continue;
+            }
+            for a in &mut v[start - view.start..end - view.start] {
+                let old = (*a >> 8) as u8;
+                let new = color.unwrap_or_else(|| swap_fg_bg(old));
+                *a = (*a & !0xFF00) | ((new as Attrib) << 8);
             }
         }
         v
     }
 }
 
+/// Inverse video for a color byte (bg << 4 | fg, bold in bit 7): the
+/// colors trade places, bold and bit 3 stay
+fn swap_fg_bg(color: u8) -> u8 {
+    (color & 0x88) | ((color & 0x07) << 4) | ((color >> 4) & 0x07)
+}
+
+/// A row found by Scrollback::grep
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GrepMatch {
+    pub line: usize, // History line number, counted from the first line printed
+    pub text: String,
+}
+
+/// A logical line from Scrollback::line_range
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryLine {
+    pub line: usize, // History line number, as in GrepMatch
+    pub text: String,
+    pub ansi: String, // The same with its colors as ANSI SGR codes
+}
+
+/// Plain text of a row, trailing blanks and wrap padding removed
+pub fn row_text(row: &[Attrib]) -> String {
+    let text: String = row
+        .iter()
+        .map(|a| (a & 0xFF) as u8)
+        .filter(|&b| b != 0)
+        .map(char::from)
+        .collect();
+    text.trim_end().to_string()
+}
+
+/// Split a line into rows of at most `width` cells
+/// With `words`, a row ends before the word that doesn't fit (the space it
+/// breaks at is dropped); words longer than a row are still cut
+pub fn wrap_rows(text: &[u8], width: usize, words: bool) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while text.len() - start > width {
+        let end = start + width;
+        if words {
+            if text[end] == b' ' {
+                rows.push(start..end);
+                start = end + 1;
+                continue;
+            }
+            if let Some(p) = text[start + 1..end].iter().rposition(|&b| b == b' ') {
+                rows.push(start..start + 1 + p);
+                start += p + 2;
+                continue;
+            }
+        }
+        rows.push(start..end);
+        start = end;
+    }
+    if start < text.len() || rows.is_empty() {
+        rows.push(start..text.len());
+    }
+    rows
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -305,6 +559,21 @@ mod tests {
         assert_eq!(sb.viewpoint, vp);
     }
     #[test]
+    fn follow_tail_over_many_screens() {
+        let mut sb = Scrollback::new(4, 2, 16);
+        for line in [b"1111", b"2222", b"3333", b"4444", b"5555"] {
+            sb.print_line(line, 0);
+        }
+        let text: Vec<u8> = sb.viewport_slice().iter().map(|a| *a as u8).collect();
+        assert_eq!(text, b"44445555");
+
+        // Frozen: new rows land below the view without touching it
+        sb.set_frozen(true);
+        sb.print_line(b"6666", 0);
+        let text: Vec<u8> = sb.viewport_slice().iter().map(|a| *a as u8).collect();
+        assert_eq!(text, b"44445555");
+    }
+    #[test]
     fn page_up_and_down() {
         let mut sb = Scrollback::new(80, 24, 1000);
 
@@ -387,7 +656,8 @@ mod tests {
         sb.print_line(b"abc", 0x21);
         sb.print_line(b"def", 0x21);
         let v = sb.viewport_slice().to_vec();
-        let hl = sb.highlight_view(0, 2, 10);
+        let span = 2..12;
+        let hl = sb.highlight_view(&[span], None);
         assert_eq!(hl.len(), v.len());
         assert_eq!(v[0], hl[0]);
         assert_eq!(v[1], hl[1]);
@@ -395,6 +665,31 @@ mod tests {
             assert_ne!((v[idx] >> 8) as u8, (hl[idx] >> 8) as u8);
         }
     }
+
+    #[test]
+    fn highlight_spans_keep_bold_and_clip_to_view() {
+        let mut sb = Scrollback::new(4, 2, 10);
+        for line in [b"aaaa", b"bbbb", b"cccc"] {
+            sb.print_line(line, 0x80 | 0x12);
+        }
+        let vp = sb.viewpoint;
+        assert_eq!(vp, 4);
+        let color = |v: &[Attrib], i: usize| (v[i] >> 8) as u8;
+        // A span from the row above the view runs on into its first row,
+        // and a second one sits in the last row
+        let hl = sb.highlight_view(&[vp - 2..vp + 1, vp + 6..vp + 8], None);
+        assert_eq!(color(&hl, 0), 0x80 | 0x21);
+        assert_eq!(color(&hl, 1), 0x92);
+        assert_eq!(
+            (color(&hl, 5), color(&hl, 6), color(&hl, 7)),
+            (0x92, 0xA1, 0xA1)
+        );
+        assert_eq!(hl[0] & 0xFF, b'b' as Attrib);
+        // A fixed color, and spans outside the view change nothing
+        let hl = sb.highlight_view(&[vp + 3..vp + 4, 0..2, 40..50], Some(0x0E));
+        assert_eq!(color(&hl, 3), 0x0E);
+        assert_eq!(hl.iter().filter(|a| (**a >> 8) as u8 == 0x0E).count(), 1);
+    }
     #[test]
     fn viewpoint_invariants_under_mixed_moves() {
         let mut sb = Scrollback::new(5, 3, 50);
@@ -423,4 +718,122 @@ mod tests {
         assert_eq!(sb.top_line % 6, 0);
         assert!(sb.top_line >= 6);
     }
+
+    #[test]
+    fn long_lines_wrap_onto_continuation_rows() {
+        let row = |sb: &Scrollback, r: usize| -> String {
+            let v = sb.viewport_slice();
+            v[r * sb.width..(r + 1) * sb.width]
+                .iter()
+                .map(|a| (*a & 0xFF) as u8 as char)
+                .collect()
+        };
+        let mut sb = Scrollback::new(8, 4, 20);
+        sb.print_line(b"the quick brown fox", 0x07);
+        assert_eq!(sb.total_lines(), 3);
+        assert_eq!(
+            (row(&sb, 0), row(&sb, 1), row(&sb, 2)),
+            ("the quic".into(), "k brown ".into(), "fox     ".into())
+        );
+
+        let mut sb = Scrollback::new(8, 4, 20);
+        sb.set_word_wrap(true);
+        sb.print_line_colored(&b"the quick brown fox".map(|b| (b, 0x02)));
+        assert_eq!(sb.total_lines(), 4);
+        assert_eq!(
+            (row(&sb, 0), row(&sb, 1), row(&sb, 2)),
+            ("the     ".into(), "quick   ".into(), "brown   ".into())
+        );
+        assert_eq!(row(&sb, 3), "fox     ");
+    }
+
+    #[test]
+    fn grep_finds_lines_the_ring_dropped() {
+        let mut sb = Scrollback::new(10, 3, 300);
+        for i in 0..400 {
+            sb.print_line(format!("line {}", i).as_bytes(), 0x07);
+        }
+        assert!(sb.top_line > 0); // The ring has compacted
+        let re = Regex::new(r"^line (0|12|39\d)$").unwrap();
+        let found = sb.grep(&re);
+        assert_eq!(found.len(), 12);
+        assert_eq!(
+            found[0],
+            GrepMatch {
+                line: 0,
+                text: "line 0".to_string()
+            }
+        );
+        assert_eq!(found[11].line, 399);
+    }
+
+    #[test]
+    fn line_range_pages_through_history() {
+        let mut sb = Scrollback::new(10, 3, 300);
+        for i in 0..400 {
+            sb.print_line(format!("line {}", i).as_bytes(), 0x07);
+        }
+        sb.record_cell(0x0200 | b'>' as Attrib);
+        let (lines, oldest, total) = sb.line_range(-3, 2);
+        assert_eq!((oldest, total), (0, 401));
+        let text: Vec<_> = lines.iter().map(|l| (l.line, l.text.as_str())).collect();
+        assert_eq!(text, [(398, "line 398"), (399, "line 399")]);
+        let (lines, _, _) = sb.line_range(12, 100);
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[0].text, "line 12");
+        let (lines, _, _) = sb.line_range(-1, 10);
+        assert_eq!(lines[0].line, 400);
+        assert_eq!(lines[0].text, ">");
+        assert!(lines[0].ansi.contains("\x1b["));
+        assert!(sb.line_range(500, 10).0.is_empty());
+        assert_eq!(sb.line_range(-1000, 1).0[0].line, 0);
+    }
+
+    #[test]
+    fn renders_history_at_any_width() {
+        let mut sb = Scrollback::new(20, 3, 50);
+        sb.print_line(b"first", 0x07);
+        sb.print_line(b"second line here", 0x02);
+        sb.record_cell(0x0700 | b'>' as Attrib);
+        let text =
+            |rows: Vec<Vec<Attrib>>| -> Vec<String> { rows.iter().map(|r| row_text(r)).collect() };
+        assert_eq!(
+            text(sb.render(20, 5)),
+            vec!["first", "second line here", ">"]
+        );
+        assert_eq!(text(sb.render(7, 3)), vec!["line he", "re", ">"]);
+        sb.set_word_wrap(true);
+        assert_eq!(text(sb.render(7, 4)), vec!["second", "line", "here", ">"]);
+        assert_eq!(sb.render(7, 2)[0][0], 0x0200 | b'h' as Attrib);
+
+        sb.unrecord(1);
+        sb.end_line();
+        assert_eq!(sb.history().len(), 3);
+        assert!(sb.history().get(2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rows_map_to_logical_lines() {
+        let mut sb = Scrollback::new(20, 3, 50);
+        sb.print_line(b"0123456789", 0x07);
+        sb.print_line(b"short", 0x07);
+        // Bottom row is the (empty) line being printed
+        assert_eq!(sb.line_at_row(4, 0), Some(2));
+        assert_eq!(sb.line_at_row(4, 1), Some(1));
+        assert_eq!(sb.line_at_row(4, 2), Some(1));
+        assert_eq!(sb.line_at_row(4, 3), Some(0));
+        assert_eq!(sb.line_at_row(4, 6), None);
+        assert_eq!(sb.rows_from_line(4, 0), 6);
+        assert_eq!(sb.rows_from_line(20, 0), 3);
+    }
+
+    #[test]
+    fn wrap_rows_breaks() {
+        assert_eq!(wrap_rows(b"", 4, true), vec![0..0]);
+        assert_eq!(wrap_rows(b"abcd", 4, true), vec![0..4]);
+        assert_eq!(wrap_rows(b"abcd efg", 4, true), vec![0..4, 5..8]);
+        assert_eq!(wrap_rows(b"abcdefghij", 4, true), vec![0..4, 4..8, 8..10]);
+        assert_eq!(wrap_rows(b"ab cdef", 4, false), vec![0..4, 4..7]);
+        assert_eq!(wrap_rows(b"abcd ", 4, true), vec![0..4]);
+    }
 }
@@ -8,7 +8,48 @@
 use crate::history::HistoryId;
 use crate::input_box::InputBox;
 use crate::output_window::OutputWindow;
+use crate::scrollback::GrepMatch;
 use crate::window::Window;
+use regex::Regex;
+
+/// Most matches #grep prints (the newest ones)
+pub const GREP_MAX: usize = 100;
+
+/// Pattern of a `#grep <regex>` or `#lastlog <text>` line; None if it isn't
+/// one. #lastlog matches its text literally, ignoring case (irssi /lastlog)
+pub fn grep_command(line: &str) -> Option<Result<Regex, String>> {
+    let (cmd, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arg = arg.trim();
+    let pattern = match cmd {
+        "#grep" if arg.is_empty() => return Some(Err("Usage: #grep <regex>".to_string())),
+        "#lastlog" if arg.is_empty() => return Some(Err("Usage: #lastlog <text>".to_string())),
+        "#grep" => arg.to_string(),
+        "#lastlog" => format!("(?i){}", regex::escape(arg)),
+        _ => return None,
+    };
+    Some(Regex::new(&pattern).map_err(|e| format!("Bad pattern: {}", e)))
+}
+
+/// Output lines for #grep results: a header, then "line: text" for the
+/// newest GREP_MAX matches
+pub fn grep_report(what: &str, matches: &[GrepMatch]) -> Vec<String> {
+    let mut out = vec![format!(
+        "-- {} match{} for {}",
+        matches.len(),
+        if matches.len() == 1 { "" } else { "es" },
+        what
+    )];
+    let skip = matches.len().saturating_sub(GREP_MAX);
+    if skip > 0 {
+        out.push(format!("   ({} earlier matches not shown)", skip));
+    }
+    out.extend(
+        matches[skip..]
+            .iter()
+            .map(|m| format!("{:>7}: {}", m.line, m.text)),
+    );
+    out
+}
 
 /// Create scrollback search dialog (C++ ScrollbackSearch class)
 ///
@@ -53,6 +94,30 @@ mod tests {
     use super::*;
     use std::ptr;
 
+    #[test]
+    fn grep_and_lastlog_commands() {
+        let re = grep_command("#grep ^You (hit|miss)").unwrap().unwrap();
+        assert!(re.is_match("You hit the rat."));
+        let re = grep_command("#lastlog a.b (x)").unwrap().unwrap();
+        assert!(re.is_match("Bob says: A.B (X)"));
+        assert!(!re.is_match("aXb (x)"));
+        assert!(grep_command("#grep").unwrap().is_err());
+        assert!(grep_command("#grep (").unwrap().is_err());
+        assert!(grep_command("#grepx foo").is_none());
+
+        let matches: Vec<GrepMatch> = (0..GREP_MAX + 2)
+            .map(|line| GrepMatch {
+                line,
+                text: format!("hit {}", line),
+            })
+            .collect();
+        let report = grep_report("/hit/", &matches);
+        assert_eq!(report[0], "-- 102 matches for /hit/");
+        assert_eq!(report[1], "   (2 earlier matches not shown)");
+        assert_eq!(report[2], "      2: hit 2");
+        assert_eq!(report.len(), GREP_MAX + 2);
+    }
+
     #[test]
     fn creates_search_dialog() {
         let root = Window::new(ptr::null_mut(), 80, 24);
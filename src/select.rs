@@ -1,3 +1,4 @@
+use crate::selectable::{Interest, Selectable};
 use std::io;
 use std::os::fd::RawFd;
 
@@ -9,6 +10,29 @@ pub struct Ready {
     pub revents: i16,
 }
 
+impl Ready {
+    pub fn readable(&self) -> bool {
+        self.revents & READ != 0
+    }
+    pub fn writable(&self) -> bool {
+        self.revents & WRITE != 0
+    }
+    /// Peer hung up or fd is in an error state (reported even without interest)
+    pub fn hangup(&self) -> bool {
+        self.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0
+    }
+    /// "read", "write", "hangup" or a comma-separated mix, for script hooks
+    pub fn describe(&self) -> String {
+        let names = [
+            (self.readable(), "read"),
+            (self.writable(), "write"),
+            (self.hangup(), "hangup"),
+        ];
+        let set: Vec<&str> = names.iter().filter(|n| n.0).map(|n| n.1).collect();
+        set.join(",")
+    }
+}
+
 pub fn poll_fds(fds: &[(RawFd, i16)], timeout_ms: i32) -> io::Result<Vec<(RawFd, Ready)>> {
     let mut pfds: Vec<libc::pollfd> = fds
         .iter()
@@ -37,6 +61,178 @@ pub fn poll_fds(fds: &[(RawFd, i16)], timeout_ms: i32) -> io::Result<Vec<(RawFd,
     Ok(out)
 }
 
+/// Handle returned by Selector::register, stable until deregistered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token(pub usize);
+
+/// Readiness callback: gets the loop context, fd and events.
+/// Returning false deregisters the fd (e.g. on EOF).
+pub type Callback<C> = Box<dyn FnMut(&mut C, RawFd, Ready) -> bool>;
+
+/// A script function to run and its argument, queued by an fd callback
+pub type HookCall = (String, String);
+
+/// Callback for an fd a script watches (okros.watch): queues `function`
+/// with "<fd> <events>" for the loop to run, and drops the watch once the
+/// fd hangs up or is closed.
+pub fn script_callback(function: String) -> Callback<Vec<HookCall>> {
+    Box::new(move |calls, fd, ready| {
+        calls.push((function.clone(), format!("{} {}", fd, ready.describe())));
+        !ready.hangup()
+    })
+}
+
+/// Readiness for one registered fd
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    pub token: Token,
+    pub fd: RawFd,
+    pub ready: Ready,
+}
+
+struct Entry<C> {
+    token: Token,
+    fd: RawFd,
+    interest: Interest,
+    callback: Option<Callback<C>>,
+}
+
+/// Registry of fds the event loop waits on (C++ Selectable.cc Selector)
+///
+/// Subsystems register an fd with interest flags, optionally with a callback.
+/// `dispatch` polls once, runs callbacks for fds that have them and hands the
+/// rest back to the loop to handle inline. `C` is whatever context the loop
+/// passes to callbacks.
+pub struct Selector<C = ()> {
+    entries: Vec<Entry<C>>,
+    next_token: usize,
+}
+
+impl<C> Default for Selector<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Selector<C> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_token: 0,
+        }
+    }
+
+    fn add(&mut self, fd: RawFd, interest: Interest, callback: Option<Callback<C>>) -> Token {
+        let token = Token(self.next_token);
+        self.next_token += 1;
+        self.entries.push(Entry {
+            token,
+            fd,
+            interest,
+            callback,
+        });
+        token
+    }
+
+    /// Register fd; events are returned from poll/dispatch for the loop to handle
+    pub fn register(&mut self, fd: RawFd, interest: Interest) -> Token {
+        self.add(fd, interest, None)
+    }
+
+    /// Register fd with a callback run by dispatch
+    pub fn register_with(&mut self, fd: RawFd, interest: Interest, callback: Callback<C>) -> Token {
+        self.add(fd, interest, Some(callback))
+    }
+
+    /// Register anything implementing Selectable, using its current interest
+    pub fn register_selectable(&mut self, source: &dyn Selectable) -> Token {
+        self.register(source.fd(), source.interest())
+    }
+
+    /// Remove a registration; returns false if the token was unknown
+    pub fn deregister(&mut self, token: Token) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.token != token);
+        self.entries.len() != before
+    }
+
+    /// Change interest flags (e.g. add WRITE while a connect is in progress)
+    pub fn set_interest(&mut self, token: Token, interest: Interest) -> bool {
+        match self.entries.iter_mut().find(|e| e.token == token) {
+            Some(e) => {
+                e.interest = interest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn fd(&self, token: Token) -> Option<RawFd> {
+        self.entries.iter().find(|e| e.token == token).map(|e| e.fd)
+    }
+
+    pub fn contains(&self, token: Token) -> bool {
+        self.entries.iter().any(|e| e.token == token)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Wait for readiness on all registered fds; returns every ready fd
+    /// (callbacks are not run - use dispatch for that)
+    pub fn poll(&self, timeout_ms: i32) -> io::Result<Vec<Event>> {
+        let fds: Vec<(RawFd, i16)> = self
+            .entries
+            .iter()
+            .map(|e| (e.fd, e.interest.bits()))
+            .collect();
+        let ready = poll_fds(&fds, timeout_ms)?;
+
+        // Map back by position: the same fd may be registered more than once
+        let mut out = Vec::with_capacity(ready.len());
+        let mut ready = ready.into_iter().peekable();
+        for e in &self.entries {
+            if let Some((fd, r)) = ready.peek() {
+                if *fd == e.fd {
+                    out.push(Event {
+                        token: e.token,
+                        fd: e.fd,
+                        ready: *r,
+                    });
+                    ready.next();
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Poll once, run callbacks for ready fds that have one, and return the
+    /// events for fds without callbacks
+    pub fn dispatch(&mut self, ctx: &mut C, timeout_ms: i32) -> io::Result<Vec<Event>> {
+        let mut unhandled = Vec::new();
+        for ev in self.poll(timeout_ms)? {
+            // Entry may have been removed by an earlier callback's return value
+            let Some(entry) = self.entries.iter_mut().find(|e| e.token == ev.token) else {
+                continue;
+            };
+            match entry.callback.as_mut() {
+                Some(cb) => {
+                    if !cb(ctx, ev.fd, ev.ready) {
+                        self.deregister(ev.token);
+                    }
+                }
+                None => unhandled.push(ev),
+            }
+        }
+        Ok(unhandled)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,4 +259,116 @@ mod tests {
             libc::close(w);
         }
     }
+
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds = [0; 2];
+        unsafe {
+            libc::pipe(fds.as_mut_ptr());
+        }
+        (fds[0], fds[1])
+    }
+
+    fn write_byte(fd: RawFd) {
+        unsafe {
+            libc::write(fd, b"x".as_ptr() as *const libc::c_void, 1);
+        }
+    }
+
+    #[test]
+    fn selector_register_poll_deregister() {
+        let (r1, w1) = pipe();
+        let (r2, w2) = pipe();
+        let mut sel: Selector = Selector::new();
+        let t1 = sel.register(r1, Interest::READ);
+        let t2 = sel.register(r2, Interest::READ);
+        assert_eq!(sel.len(), 2);
+
+        write_byte(w2);
+        let evs = sel.poll(100).unwrap();
+        assert_eq!(evs.len(), 1);
+        assert_eq!(evs[0].token, t2);
+        assert_eq!(evs[0].fd, r2);
+        assert!(evs[0].ready.readable());
+
+        assert!(sel.deregister(t2));
+        assert!(!sel.deregister(t2));
+        assert!(sel.poll(0).unwrap().is_empty());
+        assert_eq!(sel.fd(t1), Some(r1));
+
+        for fd in [r1, w1, r2, w2] {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    #[test]
+    fn selector_set_interest() {
+        let (r, w) = pipe();
+        let mut sel: Selector = Selector::new();
+        let t = sel.register(w, Interest::empty());
+        assert!(sel.poll(0).unwrap().is_empty());
+
+        assert!(sel.set_interest(t, Interest::WRITE));
+        let evs = sel.poll(100).unwrap();
+        assert_eq!(evs.len(), 1);
+        assert!(evs[0].ready.writable());
+
+        unsafe {
+            libc::close(r);
+            libc::close(w);
+        }
+    }
+
+    #[test]
+    fn selector_dispatch_runs_callbacks() {
+        let (r1, w1) = pipe();
+        let (r2, w2) = pipe();
+        let mut sel: Selector<Vec<RawFd>> = Selector::new();
+        let cb_token = sel.register_with(
+            r1,
+            Interest::READ,
+            Box::new(|seen: &mut Vec<RawFd>, fd, ready| {
+                assert!(ready.readable());
+                seen.push(fd);
+                false // one-shot
+            }),
+        );
+        let plain = sel.register(r2, Interest::READ);
+
+        write_byte(w1);
+        write_byte(w2);
+        let mut seen = Vec::new();
+        let rest = sel.dispatch(&mut seen, 100).unwrap();
+        assert_eq!(seen, vec![r1]);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].token, plain);
+        assert!(!sel.contains(cb_token));
+
+        for fd in [r1, w1, r2, w2] {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    #[test]
+    fn script_watch_queues_hook_until_hangup() {
+        let (r, w) = pipe();
+        let mut sel: Selector<Vec<HookCall>> = Selector::new();
+        let t = sel.register_with(r, Interest::READ, script_callback("chat".into()));
+
+        write_byte(w);
+        let mut calls = Vec::new();
+        assert!(sel.dispatch(&mut calls, 100).unwrap().is_empty());
+        assert_eq!(calls, vec![("chat".to_string(), format!("{} read", r))]);
+        assert!(sel.contains(t));
+
+        // Writer gone: the hook hears about it once, then the watch ends
+        unsafe { libc::close(w) };
+        calls.clear();
+        let mut buf = [0u8; 1];
+        unsafe { libc::read(r, buf.as_mut_ptr() as *mut libc::c_void, 1) };
+        sel.dispatch(&mut calls, 100).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].1.ends_with("hangup"));
+        assert!(!sel.contains(t));
+        unsafe { libc::close(r) };
+    }
 }
@@ -1,6 +1,7 @@
 use std::os::fd::RawFd;
 
 bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct Interest: i16 {
         const READ = libc::POLLIN;
         const WRITE = libc::POLLOUT;
@@ -3,7 +3,8 @@
 // Ported from mcl-cpp-reference/Selection.cc (1:1 port)
 
 use crate::input::{KeyCode, KeyEvent};
-use crate::window::Window;
+use crate::scrollback::Attrib;
+use crate::window::{BorderStyle, Window};
 
 /// Base class for scrollable selection lists (C++ Selection.cc:7-37)
 /// Subclass and override get_data(), do_select(), do_choose() for custom behavior
@@ -63,6 +64,14 @@ impl Selection {
         }
     }
 
+    /// Remove every item
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.colors.clear();
+        self.selection = -1;
+        self.win.dirty = true;
+    }
+
     /// Get count of items
     pub fn count(&self) -> usize {
         self.items.len()
@@ -106,37 +115,20 @@ impl Selection {
     /// Redraw window (C++ Selection.cc:38-66)
     pub fn redraw(&mut self) {
         // Set blue background color (C++ Selection.cc:41-42)
-        let bg_blue_fg_white = 0x17u16; // bg_blue (1) | fg_white (7)
-        let bg_green_fg_black = 0x20u16; // bg_green (2) | fg_black (0)
+        let bg_blue_fg_white = 0x17 as Attrib; // bg_blue (1) | fg_white (7)
+        let bg_green_fg_black = 0x20 as Attrib; // bg_green (2) | fg_black (0)
 
         // Clear with blue background (C++ Selection.cc:42)
-        let blank = (bg_blue_fg_white << 8) | (b' ' as u16);
+        let blank = (bg_blue_fg_white << 8) | (b' ' as Attrib);
         for a in &mut self.win.canvas {
             *a = blank;
         }
 
         // Draw border (C++ Selection uses Bordered style which creates Border window)
-        // Top border
         let width = self.win.width;
         let height = self.win.height;
-        self.win.canvas[0] = (bg_blue_fg_white << 8) | (b'+' as u16);
-        for x in 1..width - 1 {
-            self.win.canvas[x] = (bg_blue_fg_white << 8) | (b'-' as u16);
-        }
-        self.win.canvas[width - 1] = (bg_blue_fg_white << 8) | (b'+' as u16);
-
-        // Left and right borders
-        for y in 1..height - 1 {
-            self.win.canvas[y * width] = (bg_blue_fg_white << 8) | (b'|' as u16);
-            self.win.canvas[y * width + width - 1] = (bg_blue_fg_white << 8) | (b'|' as u16);
-        }
-
-        // Bottom border
-        self.win.canvas[(height - 1) * width] = (bg_blue_fg_white << 8) | (b'+' as u16);
-        for x in 1..width - 1 {
-            self.win.canvas[(height - 1) * width + x] = (bg_blue_fg_white << 8) | (b'-' as u16);
-        }
-        self.win.canvas[(height - 1) * width + width - 1] = (bg_blue_fg_white << 8) | (b'+' as u16);
+        self.win
+            .draw_border(BorderStyle::Single, None, bg_blue_fg_white as u8);
 
         // Calculate top line for scrolling (C++ Selection.cc:47-48)
         // Content area is inside border, so height-2 rows available
@@ -160,7 +152,7 @@ impl Selection {
                 // Check if item has custom color (C++ Selection.cc:55-60)
                 let item_color = self.colors.get(idx).copied().unwrap_or(0);
                 if item_color != 0 {
-                    item_color as u16
+                    item_color as Attrib
                 } else {
                     bg_blue_fg_white
                 }
@@ -177,7 +169,7 @@ impl Selection {
                 } else {
                     b' '
                 };
-                self.win.canvas[content_y * width + x + 1] = (color << 8) | (ch as u16);
+                self.win.canvas[content_y * width + x + 1] = (color << 8) | (ch as Attrib);
                 // +1 for left border
             }
         }
@@ -340,11 +332,11 @@ mod tests {
         sel.redraw();
 
         // Check that canvas has blue background (0x17)
-        let bg_blue_fg_white = 0x17u16;
+        let bg_blue_fg_white = 0x17;
         for &attr in &sel.win.canvas {
             let color = (attr >> 8) as u8;
             // Should be either blue background or green selection
-            assert!(color == 0x17 || color == 0x20);
+            assert!(color == bg_blue_fg_white || color == 0x20);
         }
     }
 }
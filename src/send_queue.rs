@@ -0,0 +1,313 @@
+//! Paced sending to the MUD (#sendrate / #flush)
+//!
+//! Some MUDs disconnect clients that send too fast. SendQueue is a token
+//! bucket: up to `burst` commands go out back to back, after that one every
+//! 1/`rate` seconds, and the rest wait in order. A rate of 0 (the default)
+//! sends everything at once. Like TimerQueue, callers pass the current time
+//! in, so the TTY main loop and the headless SessionEngine pace the same way.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Commands allowed back to back when no burst size is configured
+pub const DEFAULT_BURST: usize = 5;
+/// Slowest accepted pace (one command every 100s); anything slower is a typo
+pub const MIN_RATE: f64 = 0.01;
+
+/// A rate #sendrate and the config accept: 0 (no limit) or MIN_RATE and up
+pub fn valid_rate(rate: f64) -> bool {
+    rate == 0.0 || (rate.is_finite() && rate >= MIN_RATE)
+}
+
+#[derive(Debug)]
+pub struct SendQueue {
+    rate: f64,    // Commands per second; 0 = unlimited
+    burst: usize, // Bucket size
+    tokens: f64,
+    refilled: Option<Instant>,
+    queue: VecDeque<(String, bool)>, // Line, and whether it's a password
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        Self {
+            rate: 0.0,
+            burst: DEFAULT_BURST,
+            tokens: DEFAULT_BURST as f64,
+            refilled: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// `rate` commands per second (0 disables pacing), `burst` back to back
+    pub fn set_limits(&mut self, rate: f64, burst: usize) {
+        self.rate = match rate {
+            r if !r.is_finite() || r <= 0.0 => 0.0,
+            r => r.max(MIN_RATE),
+        };
+        self.burst = burst.max(1);
+        self.tokens = self.tokens.min(self.burst as f64);
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> usize {
+        self.burst
+    }
+
+    pub fn is_paced(&self) -> bool {
+        self.rate > 0.0
+    }
+
+    /// Queue a command line (without the newline)
+    pub fn push(&mut self, line: impl Into<String>) {
+        self.queue.push_back((line.into(), false));
+    }
+
+    /// Queue a password typed while the server echo is off: it keeps its
+    /// place behind earlier commands, but isn't echoed or passed to sys/send
+    pub fn push_secret(&mut self, line: impl Into<String>) {
+        self.queue.push_back((line.into(), true));
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(last) = self.refilled {
+            let earned = now.saturating_duration_since(last).as_secs_f64() * self.rate;
+            self.tokens = (self.tokens + earned).min(self.burst as f64);
+        }
+        self.refilled = Some(now);
+    }
+
+    /// Commands that may be sent at `now`, in queue order
+    pub fn ready(&mut self, now: Instant) -> Vec<String> {
+        self.ready_sends(now)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect()
+    }
+
+    /// ready(), each line with whether it's a password (push_secret)
+    pub fn ready_sends(&mut self, now: Instant) -> Vec<(String, bool)> {
+        if !self.is_paced() {
+            return self.queue.drain(..).collect();
+        }
+        self.refill(now);
+        let mut out = Vec::new();
+        while self.tokens >= 1.0 {
+            let Some(line) = self.queue.pop_front() else {
+                break;
+            };
+            self.tokens -= 1.0;
+            out.push(line);
+        }
+        out
+    }
+
+    /// Time until the next queued command may go out (None if nothing queued)
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        if !self.is_paced() {
+            return Some(Duration::ZERO);
+        }
+        let elapsed = self
+            .refilled
+            .map_or(0.0, |t| now.saturating_duration_since(t).as_secs_f64());
+        let missing = 1.0 - (self.tokens + elapsed * self.rate);
+        let wait = Duration::try_from_secs_f64((missing / self.rate).max(0.0));
+        Some(wait.unwrap_or(Duration::MAX))
+    }
+
+    /// Clamp a poll timeout (ms) so the loop wakes up for the next send
+    pub fn poll_timeout(&self, now: Instant, max_ms: i32) -> i32 {
+        match self.next_timeout(now) {
+            Some(d) => {
+                // Round up so we don't wake a hair early and spin
+                let ms = d.as_millis() + u128::from(d.subsec_nanos() % 1_000_000 != 0);
+                ms.min(max_ms as u128) as i32
+            }
+            None => max_ms,
+        }
+    }
+
+    /// Drop everything queued; returns how many commands were dropped
+    pub fn clear(&mut self) -> usize {
+        let n = self.queue.len();
+        self.queue.clear();
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Status line segment: "Queued: 3" while commands wait, else empty
+    pub fn status_text(&self) -> String {
+        if self.queue.is_empty() {
+            String::new()
+        } else {
+            format!("Queued: {}", self.queue.len())
+        }
+    }
+
+    /// Handle a pacing command line; None if it isn't one
+    ///   #sendrate                      - show the limits
+    ///   #sendrate <per-second> [burst] - pace sends (0 or off: no limit)
+    ///   #flush                         - drop queued commands
+    ///   #send <text>                   - queue text without expansion
+    pub fn handle_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        let line = line.trim();
+        let (cmd, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        self.command(cmd.strip_prefix('#')?, args)
+    }
+
+    /// Run sendrate/flush/send (name without the '#') with its arguments
+    pub fn command(&mut self, cmd: &str, args: &str) -> Option<Result<String, String>> {
+        let text = args;
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let result = match cmd {
+            // #send <text>: queued as is, past alias/speedwalk expansion
+            "send" if text.is_empty() => Err("Usage: #send <text>".to_string()),
+            "send" => {
+                self.push(text);
+                Ok(String::new())
+            }
+            "flush" => Ok(format!("Flushed {} queued command(s)", self.clear())),
+            "sendrate" if args.is_empty() => Ok(self.describe()),
+            "sendrate" => {
+                let rate = match args[0] {
+                    "off" => Some(0.0),
+                    s => s.parse::<f64>().ok().filter(|&r| valid_rate(r)),
+                };
+                let burst = match args.get(1) {
+                    Some(s) => s.parse::<usize>().ok().filter(|b| *b > 0),
+                    None => Some(self.burst),
+                };
+                match (rate, burst, args.len()) {
+                    (Some(rate), Some(burst), 1 | 2) => {
+                        self.set_limits(rate, burst);
+                        Ok(self.describe())
+                    }
+                    _ => Err("Usage: #sendrate <commands/second>|off [burst]".to_string()),
+                }
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// One-line summary of the limits for the status line
+    pub fn describe(&self) -> String {
+        if self.is_paced() {
+            format!(
+                "Send rate: {} command(s)/s, burst {}",
+                self.rate, self.burst
+            )
+        } else {
+            "Send rate: unlimited".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_sends_everything() {
+        let t0 = Instant::now();
+        let mut q = SendQueue::new();
+        q.push("n");
+        q.push("s");
+        assert_eq!(q.next_timeout(t0), Some(Duration::ZERO));
+        assert_eq!(q.ready(t0), vec!["n", "s"]);
+        assert!(q.is_empty());
+        assert_eq!(q.next_timeout(t0), None);
+    }
+
+    #[test]
+    fn paced_sends_burst_then_rate() {
+        let t0 = Instant::now();
+        let mut q = SendQueue::new();
+        q.set_limits(2.0, 3);
+        for cmd in ["a", "b", "c", "d", "e"] {
+            q.push(cmd);
+        }
+        assert_eq!(q.ready(t0), vec!["a", "b", "c"]);
+        assert_eq!(q.status_text(), "Queued: 2");
+        assert_eq!(q.next_timeout(t0), Some(Duration::from_millis(500)));
+        assert_eq!(q.poll_timeout(t0, 250), 250);
+        assert!(q.ready(t0 + Duration::from_millis(400)).is_empty());
+        assert_eq!(q.ready(t0 + Duration::from_millis(500)), vec!["d"]);
+        assert_eq!(q.ready(t0 + Duration::from_millis(1000)), vec!["e"]);
+        assert_eq!(q.status_text(), "");
+
+        // An idle bucket refills only up to the burst size
+        for cmd in ["f", "g", "h", "i"] {
+            q.push(cmd);
+        }
+        assert_eq!(q.ready(t0 + Duration::from_secs(60)).len(), 3);
+        assert_eq!(q.clear(), 1);
+    }
+
+    #[test]
+    fn passwords_keep_their_place() {
+        let t0 = Instant::now();
+        let mut q = SendQueue::new();
+        q.set_limits(1.0, 1);
+        q.push("north");
+        q.push_secret("hunter2");
+        assert_eq!(q.ready_sends(t0), vec![("north".to_string(), false)]);
+        let later = t0 + Duration::from_secs(1);
+        assert_eq!(q.ready_sends(later), vec![("hunter2".to_string(), true)]);
+    }
+
+    #[test]
+    fn handle_commands() {
+        let mut q = SendQueue::new();
+        assert_eq!(
+            q.handle_command("#sendrate").unwrap().unwrap(),
+            "Send rate: unlimited"
+        );
+        assert!(q.handle_command("#sendrate 4 2").unwrap().is_ok());
+        assert_eq!((q.rate(), q.burst()), (4.0, 2));
+        assert!(q.handle_command("#sendrate 1.5").unwrap().is_ok());
+        assert_eq!((q.rate(), q.burst()), (1.5, 2));
+        assert!(q.handle_command("#sendrate fast").unwrap().is_err());
+        assert!(q.handle_command("#sendrate 2 0").unwrap().is_err());
+        for rate in ["1e-20", "NaN", "inf", "-1"] {
+            let line = format!("#sendrate {}", rate);
+            assert!(q.handle_command(&line).unwrap().is_err(), "{}", rate);
+        }
+        q.set_limits(1e-20, 1);
+        assert_eq!(q.rate(), MIN_RATE);
+        q.push("x");
+        q.push("y");
+        let t0 = Instant::now();
+        assert_eq!(q.ready(t0).len(), 1);
+        assert_eq!(q.next_timeout(t0), Some(Duration::from_secs(100)));
+        q.clear();
+        assert!(q.handle_command("#sendrate off").unwrap().is_ok());
+        assert!(!q.is_paced());
+
+        q.push("x");
+        assert_eq!(
+            q.handle_command("#flush").unwrap().unwrap(),
+            "Flushed 1 queued command(s)"
+        );
+        assert!(q.handle_command("look").is_none());
+    }
+}
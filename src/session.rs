@@ -1,7 +1,18 @@
-use crate::ansi::{AnsiConverter, AnsiEvent};
+use crate::ansi::{AnsiConverter, AnsiEvent, Erase};
+use crate::atcp::{Atcp, AtcpQueue, TELOPT_ATCP};
+use crate::charset::{Charset, CharsetNegotiation, SharedCharset, TELOPT_CHARSET};
+use crate::filters::{Builtin, FilterAction, FilterPipeline, Stage};
 use crate::mccp::Decompressor;
+use crate::msp::{self, MspTrigger};
+use crate::mud::Mud;
+use crate::mxp::{MxpFilter, TELOPT_MXP};
+use crate::netstats::NetStats;
+use crate::plugins::stack::Interpreter;
 use crate::scrollback::Scrollback;
-use crate::telnet::TelnetParser;
+use crate::stats::StatsReport;
+use crate::telnet::{Side, TelnetParser};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionState {
@@ -17,6 +28,20 @@ pub struct SessionStats {
     pub bytes_read: usize,
     pub connect_time: i64, // Unix timestamp
     pub dial_time: i64,    // Unix timestamp when connection started
+    pub triggers_fired: usize,
+    pub commands_sent: usize,
+}
+
+/// Something that happened while processing MUD output, recorded for the
+/// control protocol's `subscribe` stream (see set_event_recording)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// A completed (and not gagged) line, after replacements
+    Line(String),
+    /// A GA/EOR-terminated prompt
+    Prompt(String),
+    /// The trigger callback returned commands for this line
+    TriggerFired { line: String, commands: Vec<String> },
 }
 
 /// Trigger callback: receives line text, returns commands to execute
@@ -29,6 +54,28 @@ pub type ReplacementCallback = Box<dyn FnMut(&str) -> Option<String> + Send>;
 /// Returns true to show prompt, false to hide it (opt_showprompt)
 pub type PromptCallback = Box<dyn FnMut(&str) -> bool + Send>;
 
+/// Highlight callback: receives line text, returns (start, end, color) byte ranges to recolor
+pub type HighlightCallback = Box<dyn FnMut(&str) -> Vec<(usize, usize, u8)> + Send>;
+
+/// How serious a client message is (Session::client_msg): picks its
+/// prefix and color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MsgLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl MsgLevel {
+    pub fn prefix(self) -> &'static str {
+        match self {
+            MsgLevel::Info => "-- ",
+            MsgLevel::Warning => "Warning: ",
+            MsgLevel::Error => "Error: ",
+        }
+    }
+}
+
 /// Output callback: sys/output hook (C++ Session::triggerCheck line 671)
 /// Receives line text, returns modified text or None (None = no change)
 pub type OutputCallback = Box<dyn FnMut(&str) -> Option<String> + Send>;
@@ -36,6 +83,7 @@ pub type OutputCallback = Box<dyn FnMut(&str) -> Option<String> + Send>;
 pub struct Session<D: Decompressor> {
     decomp: D,
     telnet: TelnetParser,
+    raw_out: Vec<u8>, // send_raw/send_subneg bytes, already escaped
     ansi: AnsiConverter,
 
     // Output target (C++ Session.h:35 Window *window)
@@ -44,16 +92,23 @@ pub struct Session<D: Decompressor> {
     output_window: *mut crate::output_window::OutputWindow,
     scrollback: Option<Scrollback>, // Only used when output_window is null
 
-    cur_color: u8,
-    line_buf: Vec<(u8, u8)>, // (char, color) pairs like C++ SET_COLOR stream
+    cur_color: u16,           // Color byte plus text attributes (AnsiEvent::SetColor)
+    line_buf: Vec<(u8, u16)>, // (char, color) pairs like C++ SET_COLOR stream
+    line_pos: usize,          // Cursor in line_buf: CR and CSI moves rewrite the line
+    tty_stale: Option<usize>, // Cells shown for the line when it was last rewritten
     prompt_events: usize,
 
     // Session state and statistics (C++ Session.h:27, 44-49)
     pub state: SessionState,
     pub stats: SessionStats,
+    pub net: NetStats,                                 // Traffic rates and #ping
+    pub net_debug: Option<crate::net_debug::NetDebug>, // #debug net hex dump
+    pub urls: crate::url::UrlList,                     // URLs seen in output (#url)
 
     // Prompt buffering across reads (C++ Session.h:37 prompt[MAX_MUD_BUF])
     prompt_buffer: Vec<u8>,
+    // Text of the most recent GA/EOR prompt, until taken (auto-login etc.)
+    last_prompt: Option<String>,
 
     // Optional callbacks for trigger/replacement checking (C++ Session::triggerCheck lines 640-683)
     trigger_callback: Option<TriggerCallback>,
@@ -64,8 +119,87 @@ pub struct Session<D: Decompressor> {
 
     // Optional output hook callback (C++ Session::triggerCheck line 671, sys/output)
     output_callback: Option<OutputCallback>,
+
+    // Optional highlight callback (recolors spans of the final line text)
+    highlight_callback: Option<HighlightCallback>,
+
+    // Prefix completed lines with a dim HH:MM:SS stamp (#timestamps)
+    timestamps: bool,
+
+    /// The stages each completed line goes through (#filter)
+    pub filters: FilterPipeline,
+
+    /// Trigger loop protection; gags echoes of trigger commands (#loopguard)
+    pub trigger_guard: crate::trigger_guard::TriggerGuard,
+
+    // Line/prompt/trigger events, until taken (None = not recording)
+    events: Option<Vec<SessionEvent>>,
+
+    // Sticky prompt: GA/EOR prompts go to the input line, not the scrollback
+    sticky_prompt: bool,
+    prompt_update: Option<String>,
+
+    // MSP: triggers are always stripped, queued only when enabled
+    msp: bool,
+    msp_triggers: Vec<MspTrigger>,
+
+    // BEL never reaches the line; the text before it is queued instead
+    bells: Vec<String>,
+
+    // MXP markup is stripped from the text; ATCP messages go to sys/atcp
+    mxp: MxpFilter,
+    atcp: AtcpQueue,
+
+    // Charset the MUD's text is in (#charset, or CHARSET negotiation)
+    charset: SharedCharset,
+    utf8_pending: Vec<u8>, // UTF-8 sequence split across reads
+
+    // Refused telnet options and MCCP errors shown as client messages (#diagnostics)
+    diagnostics: bool,
+    diag_color: u8,
+    msg_colors: [u8; 3], // client_msg colors, in MsgLevel order
+
+    // Idle time: when the user last sent a command and the MUD last sent
+    // anything; unseen = output since the view last showed the newest line
+    last_send: Instant,
+    last_output: Instant,
+    unseen: bool,
+
+    // Text past the telnet layer not shown yet: #more paused it or the
+    // output budget ran out (a flooding MUD can't outrun the screen)
+    backlog: VecDeque<Held>,
+    backlog_bytes: usize,
+    backlog_lines: usize,
+    output_budget: Option<usize>, // Bytes shown per read or pump (outputbudget)
+    more: Option<usize>,          // Pause after this many lines without a key (#more)
+    more_count: usize,            // Lines shown since the last resume()
+    dropped_lines: usize,         // Dropped from a full backlog, in all
+}
+
+/// Text held back for showing, and how far it's been shown
+struct Held {
+    text: Vec<u8>,
+    pos: usize,
+    prompt: bool, // Ends at a GA/EOR
+    mxp: bool,    // MXP was on when it arrived
 }
 
+/// Text held back before the oldest lines are dropped
+pub const MAX_BACKLOG: usize = 1 << 20;
+
+fn count_lines(text: &[u8]) -> usize {
+    text.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// Color of the timestamp prefix (bold black = dark gray)
+const TIMESTAMP_COLOR: u8 = 0x80;
+
+/// Furthest a cursor movement can put the cursor on a line
+const MAX_COLUMN: usize = 1024;
+
+/// Bells kept between take_bells calls (a flood of BELs is one beep anyway)
+const MAX_BELLS: usize = 16;
+
 // SAFETY: Session is used in single-threaded context like C++ MCL
 // The raw pointer is only used locally, never shared across threads
 unsafe impl<D: Decompressor> Send for Session<D> {}
@@ -73,22 +207,70 @@ unsafe impl<D: Decompressor> Send for Session<D> {}
 impl<D: Decompressor> Session<D> {
     /// Create Session with own scrollback (for headless/offline modes)
     pub fn new(decomp: D, width: usize, height: usize, lines: usize) -> Self {
+        let atcp = AtcpQueue::default();
+        let mut telnet = TelnetParser::new();
+        telnet.register(TELOPT_ATCP, Box::new(Atcp::new(atcp.clone())));
+        let charset = SharedCharset::default();
+        telnet.support(TELOPT_CHARSET, Side::Him, true);
+        telnet.support(TELOPT_CHARSET, Side::Us, true);
+        telnet.register(
+            TELOPT_CHARSET,
+            Box::new(CharsetNegotiation::new(charset.clone())),
+        );
         Self {
             decomp,
-            telnet: TelnetParser::new(),
+            telnet,
+            raw_out: Vec::new(),
             ansi: AnsiConverter::new(),
             output_window: std::ptr::null_mut(),
             scrollback: Some(Scrollback::new(width, height, lines)),
             cur_color: 0x07,
             line_buf: Vec::new(),
+            line_pos: 0,
+            tty_stale: None,
             prompt_events: 0,
             state: SessionState::Disconnected,
             stats: SessionStats::default(),
+            net: NetStats::new(),
+            net_debug: None,
+            urls: crate::url::UrlList::new(),
             prompt_buffer: Vec::new(),
+            last_prompt: None,
             trigger_callback: None,
             replacement_callback: None,
             prompt_callback: None,
             output_callback: None,
+            highlight_callback: None,
+            timestamps: false,
+            filters: FilterPipeline::new(),
+            trigger_guard: crate::trigger_guard::TriggerGuard::new(),
+            events: None,
+            sticky_prompt: false,
+            prompt_update: None,
+            msp: false,
+            msp_triggers: Vec::new(),
+            bells: Vec::new(),
+            mxp: MxpFilter::new(),
+            atcp,
+            charset,
+            utf8_pending: Vec::new(),
+            diagnostics: true,
+            diag_color: crate::color::DIAG_COLOR,
+            msg_colors: [
+                crate::color::INFO_COLOR,
+                crate::color::WARNING_COLOR,
+                crate::color::ERROR_COLOR,
+            ],
+            last_send: Instant::now(),
+            last_output: Instant::now(),
+            unseen: false,
+            backlog: VecDeque::new(),
+            backlog_bytes: 0,
+            backlog_lines: 0,
+            output_budget: None,
+            more: None,
+            more_count: 0,
+            dropped_lines: 0,
         }
     }
 
@@ -100,6 +282,292 @@ impl<D: Decompressor> Session<D> {
         self.scrollback = None;
     }
 
+    /// Color for text the MUD sends without (or after resetting) ANSI colors
+    pub fn set_default_color(&mut self, color: u8) {
+        self.ansi.set_default(color);
+        self.cur_color = color as u16;
+    }
+
+    /// Wrap long lines at word boundaries instead of at the last column
+    pub fn set_word_wrap(&mut self, on: bool) {
+        if !self.output_window.is_null() {
+            unsafe {
+                (*self.output_window).set_word_wrap(on);
+            }
+        } else if let Some(ref mut sb) = self.scrollback {
+            sb.set_word_wrap(on);
+        }
+    }
+
+    /// Enable/disable the HH:MM:SS prefix on completed lines
+    pub fn set_timestamps(&mut self, on: bool) {
+        self.timestamps = on;
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    /// Start/stop recording SessionEvents (off by default, so nothing piles
+    /// up when no one calls take_events)
+    pub fn set_event_recording(&mut self, on: bool) {
+        self.events = on.then(Vec::new);
+    }
+
+    /// Take the events recorded since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<SessionEvent> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Keep GA/EOR prompts out of the output; each one is handed over
+    /// through take_prompt_update() instead (C++ sets the input line prompt)
+    pub fn set_sticky_prompt(&mut self, on: bool) {
+        self.sticky_prompt = on;
+    }
+
+    pub fn sticky_prompt(&self) -> bool {
+        self.sticky_prompt
+    }
+
+    /// Latest prompt to show on the input line (sticky prompt mode)
+    pub fn take_prompt_update(&mut self) -> Option<String> {
+        self.prompt_update.take()
+    }
+
+    /// Enable MSP: accept the telnet option and queue sound triggers
+    pub fn set_msp(&mut self, on: bool) {
+        self.msp = on;
+        self.telnet.set_msp(on);
+    }
+
+    pub fn msp(&self) -> bool {
+        self.msp
+    }
+
+    pub fn take_msp_triggers(&mut self) -> Vec<MspTrigger> {
+        std::mem::take(&mut self.msp_triggers)
+    }
+
+    /// Bells received since the last call, each with the text of the line
+    /// it arrived on (up to the BEL)
+    pub fn take_bells(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.bells)
+    }
+
+    /// Accept (or refuse) ATCP; its messages go to the sys/atcp hook
+    pub fn set_atcp(&mut self, on: bool) {
+        self.telnet.support(TELOPT_ATCP, Side::Him, on);
+    }
+
+    /// Accept (or refuse) MXP; while it's on, its tags are stripped
+    /// (servers offer it with WILL or ask with DO, so both sides count)
+    pub fn set_mxp(&mut self, on: bool) {
+        self.telnet.support(TELOPT_MXP, Side::Him, on);
+        self.telnet.support(TELOPT_MXP, Side::Us, on);
+    }
+
+    /// Charset the MUD's text is decoded from and typed text encoded to
+    pub fn charset(&self) -> Charset {
+        self.charset.lock().map(|c| *c).unwrap_or_default()
+    }
+
+    pub fn set_charset(&mut self, charset: Charset) {
+        if let Ok(mut current) = self.charset.lock() {
+            *current = charset;
+        }
+    }
+
+    /// A command line as bytes for the MUD, in its charset, with the newline
+    pub fn encode_line(&self, line: &str) -> Vec<u8> {
+        let mut data = self.charset().encode(line).into_owned();
+        data.push(b'\n');
+        data
+    }
+
+    /// Server echo state change (WILL/WONT ECHO): true = mask input
+    pub fn take_echo_change(&mut self) -> Option<bool> {
+        self.telnet.take_echo_change()
+    }
+
+    /// Telnet negotiation replies to write back to the server (the
+    /// decompressor's MCCP replies first, then send_raw/send_subneg data)
+    pub fn take_telnet_responses(&mut self) -> Vec<u8> {
+        let mut replies = self.decomp.response().unwrap_or_default();
+        replies.extend(self.telnet.take_responses());
+        replies.append(&mut self.raw_out);
+        replies
+    }
+
+    /// Queue bytes for the server with IAC doubled (NAWS, GMCP, scripts);
+    /// they go out with take_telnet_responses
+    pub fn send_raw(&mut self, data: &[u8]) {
+        self.raw_out.extend(crate::telnet::escape_iac(data));
+    }
+
+    /// Queue IAC SB option payload IAC SE, like send_raw
+    pub fn send_subneg(&mut self, option: u8, payload: &[u8]) {
+        self.raw_out
+            .extend(crate::telnet::subnegotiation(option, payload));
+    }
+
+    /// Show (or only log) refused telnet options and MCCP errors
+    pub fn set_diagnostics(&mut self, on: bool) {
+        self.diagnostics = on;
+    }
+
+    pub fn diagnostics(&self) -> bool {
+        self.diagnostics
+    }
+
+    pub fn set_diag_color(&mut self, color: u8) {
+        self.diag_color = color;
+    }
+
+    /// Print a line from the client itself into the output. It skips the
+    /// filters, triggers and line events MUD text goes through; a partial
+    /// MUD line already on screen is moved below it.
+    pub fn client_message(&mut self, text: &str, color: u8) {
+        self.sync_tty_line();
+        if self.output_window.is_null() {
+            if let Some(ref mut sb) = self.scrollback {
+                sb.print_line(text.as_bytes(), color);
+            }
+            return;
+        }
+        unsafe {
+            let window = &mut *self.output_window;
+            window.unprint(self.line_buf.len());
+            window.print(text.as_bytes(), color as u16);
+            window.print(b"\n", color as u16);
+            for &(ch, color) in &self.line_buf {
+                window.print(&[ch], color);
+            }
+        }
+    }
+
+    /// Print a client message of the given level into the output, prefixed
+    /// and in that level's color, instead of the status line where it would
+    /// be overwritten by the next one.
+    pub fn client_msg(&mut self, text: &str, level: MsgLevel) {
+        let color = self.msg_colors[level as usize];
+        self.client_message(&format!("{}{}", level.prefix(), text), color);
+    }
+
+    pub fn set_msg_colors(&mut self, colors: [u8; 3]) {
+        self.msg_colors = colors;
+    }
+
+    /// Telnet refusals and MCCP errors since the last read: logged, and
+    /// shown unless #diagnostics is off
+    fn report_diagnostics(&mut self) {
+        let mut notes = self.telnet.take_diagnostics();
+        notes.extend(self.decomp.take_warning());
+        for note in notes {
+            crate::debug_log!("{}", note);
+            if self.diagnostics {
+                self.client_message(&note, self.diag_color);
+            }
+        }
+    }
+
+    /// Option table, for registering per-option handlers and requests
+    /// (replies they queue go out with take_telnet_responses)
+    pub fn telnet_mut(&mut self) -> &mut TelnetParser {
+        &mut self.telnet
+    }
+
+    /// Count bytes read from the MUD socket (C++ Session.cc:325 counts writes)
+    /// Returns the round-trip time when this read answers a #ping
+    pub fn record_read(&mut self, n: usize) -> Option<Duration> {
+        let now = Instant::now();
+        self.stats.bytes_read += n;
+        self.last_output = now;
+        self.unseen = true;
+        self.net.record_in(n, now)
+    }
+
+    /// The user sent a command: the idle time starts again
+    pub fn record_send(&mut self) {
+        self.last_send = Instant::now();
+        self.stats.commands_sent += 1;
+    }
+
+    /// The connection is up: connect time for #stats
+    pub fn record_connected(&mut self) {
+        self.stats.connect_time = crate::stats::unix_now();
+    }
+
+    /// Counters for #stats; lines come from `scrollback`, or the session's
+    /// own when None
+    pub fn stats_report(&self, scrollback: Option<&Scrollback>) -> StatsReport {
+        let compression = self.compression_stats();
+        let connected = self.stats.connect_time > 0;
+        let mut report = StatsReport {
+            connected_at: connected.then_some(self.stats.connect_time),
+            connected_secs: connected
+                .then(|| (crate::stats::unix_now() - self.stats.connect_time).max(0) as u64),
+            bytes_received: self.stats.bytes_read,
+            bytes_sent: self.stats.bytes_written,
+            mccp_compressed: compression.map(|(comp, _)| comp),
+            mccp_uncompressed: compression.map(|(_, uncomp)| uncomp),
+            triggers_fired: self.stats.triggers_fired,
+            commands_sent: self.stats.commands_sent,
+            latency_ms: self.net.last_rtt().map(|rtt| rtt.as_millis() as u64),
+            ..Default::default()
+        };
+        if let Some(sb) = scrollback.or(self.scrollback.as_ref()) {
+            let history = sb.history();
+            report.lines = history.len();
+            report.scrollback_lines = history.len() - history.first();
+            report.scrollback_kb = sb.memory_bytes().div_ceil(1024);
+        }
+        report
+    }
+
+    /// Time since the user last sent a command (or since the session began)
+    pub fn idle(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_send)
+    }
+
+    /// Time since the MUD last sent anything
+    pub fn output_idle(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_output)
+    }
+
+    /// Output arrived since the last mark_seen()
+    pub fn has_unseen(&self) -> bool {
+        self.unseen
+    }
+
+    /// The newest output is on screen
+    pub fn mark_seen(&mut self) {
+        self.unseen = false;
+    }
+
+    /// Count bytes written to the MUD socket
+    pub fn record_written(&mut self, n: usize) {
+        self.stats.bytes_written += n;
+        self.net.record_out(n, Instant::now());
+    }
+
+    fn record(&mut self, event: SessionEvent) {
+        if let Some(ref mut events) = self.events {
+            events.push(event);
+        }
+    }
+
+    fn line_text(&self) -> String {
+        self.line_buf.iter().map(|(ch, _)| *ch as char).collect()
+    }
+
+    /// Prepend the current time to line_buf, so it is stored (and scrolls and
+    /// searches) as part of the line
+    fn stamp_line(&mut self) {
+        let stamp = chrono::Local::now().format("%H:%M:%S ").to_string();
+        let cells = stamp.bytes().map(|b| (b, TIMESTAMP_COLOR as u16));
+        self.line_buf.splice(0..0, cells);
+    }
+
     /// Write character to output (C++ Session::print → window->print)
     /// TTY mode: writes character-by-character to OutputWindow
     /// Headless mode: buffered line writing to scrollback
@@ -135,67 +603,378 @@ impl<D: Decompressor> Session<D> {
         self.output_callback = Some(callback);
     }
 
+    /// Set highlight callback, applied after replacement and sys/output
+    /// Callback receives the final line text, returns spans to recolor
+    pub fn set_highlight_callback(&mut self, callback: HighlightCallback) {
+        self.highlight_callback = Some(callback);
+    }
+
     pub fn feed(&mut self, chunk: &[u8]) {
+        self.feed_with_interp(chunk, None);
+    }
+
+    /// Feed MUD data, running the interpreter's sys/output hook on each
+    /// completed line and sys/prompt on each GA/EOR prompt
+    /// (C++ Session uses the global embed_interp for both)
+    pub fn feed_with_interp(&mut self, chunk: &[u8], interp: Option<&mut dyn Interpreter>) {
+        self.feed_with_mud(chunk, interp, None);
+    }
+
+    /// feed_with_interp(), also applying `mud`'s substitutions, gags and
+    /// highlights (compiled for `interp`) to each line before it's shown
+    /// (C++ Session holds its MUD and calls mud.checkReplacement)
+    pub fn feed_with_mud(
+        &mut self,
+        chunk: &[u8],
+        mut interp: Option<&mut dyn Interpreter>,
+        mud: Option<&Mud>,
+    ) {
+        if let Some(ref mut debug) = self.net_debug {
+            debug.raw(chunk);
+        }
         self.decomp.receive(chunk);
         while self.decomp.pending() {
             let out = self.decomp.take_output();
+            if let Some(ref mut debug) = self.net_debug {
+                debug.data(chunk, &out);
+            }
             self.telnet.feed(&out);
             let prompt_count = self.telnet.drain_prompt_events();
             self.prompt_events += prompt_count;
+            let marks = self.telnet.take_prompt_marks();
             let app = self.telnet.take_app_out();
-            for ev in self.ansi.feed(&app) {
-                match ev {
-                    AnsiEvent::SetColor(c) => self.cur_color = c,
-                    AnsiEvent::Text(b'\n') => {
-                        // C++ Session.cc:524-538 - Check triggers on complete line
-                        let should_print = self.check_line_triggers();
-
-                        // TTY mode: write newline immediately (C++ Window::print writes char-by-char)
-                        // Already written character-by-character above, always visible
+            // Text up to each GA/EOR is a prompt (C++ Session.cc:455-499, 596-602);
+            // anything after it in the same read starts a new line
+            let mut segments = Vec::with_capacity(marks.len() + 1);
+            let mut from = 0;
+            for mark in marks {
+                segments.push((from..mark, true));
+                from = mark;
+            }
+            segments.push((from..app.len(), false));
+            let mxp = self.telnet.enabled(TELOPT_MXP, Side::Him)
+                || self.telnet.enabled(TELOPT_MXP, Side::Us);
+            for (range, prompt) in segments {
+                if prompt || !range.is_empty() {
+                    self.hold(app[range].to_vec(), prompt, mxp);
+                }
+            }
+            // sys/atcp hook: one call per message ("Char.Vitals\nH:...")
+            let messages = std::mem::take(&mut *self.atcp.lock().unwrap());
+            if let Some(interp) = interp.as_deref_mut() {
+                for message in messages {
+                    let _ = interp.run_quietly("sys/atcp", &message, &mut String::new(), true);
+                }
+            }
+        }
+        self.show_backlog(interp, mud);
+        self.report_diagnostics();
+    }
+
+    /// Show more of the held-back text (after resume(), or the next tick
+    /// when the output budget ran out); see pending_output()
+    pub fn pump(&mut self, interp: Option<&mut dyn Interpreter>, mud: Option<&Mud>) {
+        self.show_backlog(interp, mud);
+    }
+
+    /// Queue text from the telnet layer to be shown, dropping the oldest
+    /// lines once more than MAX_BACKLOG bytes are waiting
+    fn hold(&mut self, text: Vec<u8>, prompt: bool, mxp: bool) {
+        self.backlog_bytes += text.len();
+        self.backlog_lines += count_lines(&text);
+        self.backlog.push_back(Held {
+            text,
+            pos: 0,
+            prompt,
+            mxp,
+        });
+        while self.backlog_bytes > MAX_BACKLOG {
+            let Some(front) = self.backlog.front_mut() else {
+                break;
+            };
+            let rest = &front.text[front.pos..];
+            let len = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(rest.len(), |i| i + 1);
+            let lines = count_lines(&rest[..len]);
+            front.pos += len;
+            if front.pos == front.text.len() {
+                self.backlog.pop_front();
+            }
+            self.backlog_bytes -= len;
+            self.backlog_lines -= lines;
+            self.dropped_lines += lines;
+        }
+    }
+
+    /// Show held text until it runs out, the output budget is spent or
+    /// #more pauses. Paging goes a line at a time; otherwise a read's text
+    /// goes in one piece, cut at a line end when it's over the budget
+    fn show_backlog(&mut self, mut interp: Option<&mut dyn Interpreter>, mud: Option<&Mud>) {
+        let mut budget = self.output_budget.unwrap_or(usize::MAX);
+        while budget > 0 && !self.more_reached() {
+            let Some(front) = self.backlog.front_mut() else {
+                break;
+            };
+            let rest = &front.text[front.pos..];
+            let line_end = |from: usize| {
+                rest[from..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(rest.len(), |i| from + i + 1)
+            };
+            let len = if self.more.is_some() {
+                line_end(0)
+            } else if rest.len() <= budget {
+                rest.len()
+            } else {
+                line_end(budget.saturating_sub(1))
+            };
+            let piece = rest[..len].to_vec();
+            let mxp = front.mxp;
+            front.pos += len;
+            let prompt = front.pos == front.text.len() && front.prompt;
+            if front.pos == front.text.len() {
+                self.backlog.pop_front();
+            }
+            let lines = count_lines(&piece);
+            self.backlog_bytes -= len;
+            self.backlog_lines -= lines;
+            self.more_count += lines;
+            budget = budget.saturating_sub(len);
+            self.show_text(&piece, prompt, mxp, interp.as_deref_mut(), mud);
+        }
+    }
+
+    /// Run text from the telnet layer through MXP, ANSI and the line
+    /// handling; `prompt` if it ended at a GA/EOR
+    fn show_text(
+        &mut self,
+        text: &[u8],
+        prompt: bool,
+        mxp: bool,
+        mut interp: Option<&mut (dyn Interpreter + '_)>,
+        mud: Option<&Mud>,
+    ) {
+        let decoded = self.charset().decode(text, &mut self.utf8_pending);
+        let text = self.mxp.filter(&decoded, mxp);
+        for ev in self.ansi.feed(&text) {
+            match ev {
+                AnsiEvent::SetColor(c) => self.cur_color = c,
+                AnsiEvent::Text(b'\n') => {
+                    self.sync_tty_line();
+                    // C++ Session.cc:524-538 - Check triggers on complete line
+                    let printed_len = self.line_buf.len();
+                    let original = (!self.output_window.is_null()).then(|| self.line_buf.clone());
+                    let should_print = self.check_line_triggers(interp.as_deref_mut(), mud);
+                    if should_print {
+                        let text = self.line_text();
+                        self.record(SessionEvent::Line(text));
+                    }
+                    if should_print && self.timestamps {
+                        self.stamp_line();
+                    }
+
+                    // TTY mode: characters were already written as they arrived, so a
+                    // gagged/replaced/highlighted line has to be taken back and redrawn
+                    if original.is_some_and(|orig| !should_print || orig != self.line_buf) {
+                        self.rewrite_tty_line(printed_len, should_print);
+                    }
+
+                    // TTY mode: write newline immediately (C++ Window::print writes char-by-char)
+                    if should_print {
                         self.print_char(b'\n');
+                    }
 
-                        // Headless mode: write buffered line to scrollback (respecting gag)
-                        if self.output_window.is_null() && should_print {
-                            if let Some(ref mut sb) = self.scrollback {
-                                sb.print_line_colored(&self.line_buf);
-                            }
+                    // Headless mode: write buffered line to scrollback (respecting gag)
+                    if self.output_window.is_null() && should_print {
+                        if let Some(ref mut sb) = self.scrollback {
+                            sb.print_line_colored(&self.line_buf);
                         }
-
-                        self.line_buf.clear();
                     }
-                    AnsiEvent::Text(b'\r') => { /* discard \r like C++ Session.cc:541 */ }
-                    AnsiEvent::Text(b) => {
-                        // Write character immediately (C++ Window::print)
-                        self.print_char(b);
-                        // Also buffer for trigger checking
-                        self.line_buf.push((b, self.cur_color));
+
+                    self.line_buf.clear();
+                    self.line_pos = 0;
+                }
+                // C++ Session.cc:541 discards \r; here it returns to the start of
+                // the line, so text after it overwrites (progress bars)
+                AnsiEvent::Text(b'\r') => self.line_pos = 0,
+                AnsiEvent::Text(0) => { /* telnet sends a bare CR as CR NUL */ }
+                AnsiEvent::Text(0x07) => {
+                    if self.bells.len() < MAX_BELLS {
+                        self.bells.push(self.line_text());
                     }
                 }
+                AnsiEvent::Text(b) => self.put_char(b),
+                AnsiEvent::CursorTo(col) => self.line_pos = col.min(MAX_COLUMN),
+                AnsiEvent::CursorBy(n) => {
+                    self.line_pos = self.line_pos.saturating_add_signed(n).min(MAX_COLUMN)
+                }
+                AnsiEvent::Erase(erase) => self.erase(erase),
+            }
+        }
+        self.sync_tty_line();
+        if prompt {
+            self.handle_prompt_event(interp);
+        }
+    }
+
+    /// Show at most `bytes` of the MUD's text per read or pump(); the rest
+    /// waits (None = no limit).
+    pub fn set_output_budget(&mut self, bytes: Option<usize>) {
+        self.output_budget = bytes;
+    }
+
+    pub fn output_budget(&self) -> Option<usize> {
+        self.output_budget
+    }
+
+    /// Pause the output after `lines` lines until resume() (None = never)
+    pub fn set_more(&mut self, lines: Option<usize>) {
+        self.more = lines;
+        self.more_count = 0;
+    }
+
+    pub fn more(&self) -> Option<usize> {
+        self.more
+    }
+
+    fn more_reached(&self) -> bool {
+        self.more.is_some_and(|n| self.more_count >= n)
+    }
+
+    /// A key was pressed: start counting #more lines again
+    pub fn resume(&mut self) {
+        self.more_count = 0;
+    }
+
+    /// Lines waiting behind a #more pause, if the output is paused
+    pub fn paused(&self) -> Option<usize> {
+        (self.more_reached() && !self.backlog.is_empty()).then_some(self.backlog_lines)
+    }
+
+    /// Held-back text pump() would show now
+    pub fn pending_output(&self) -> bool {
+        !self.backlog.is_empty() && !self.more_reached()
+    }
+
+    /// Lines dropped because too much was held back
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped_lines
+    }
+
+    /// Put a character at the cursor: appended (and printed at once, C++
+    /// Window::print) at the end of the line, else overwriting
+    fn put_char(&mut self, b: u8) {
+        let cell = (b, self.cur_color);
+        if self.line_pos < self.line_buf.len() {
+            self.tty_stale.get_or_insert(self.line_buf.len());
+            self.line_buf[self.line_pos] = cell;
+        } else {
+            if self.line_pos > self.line_buf.len() {
+                // Moved past the end: the gap is blank
+                self.tty_stale.get_or_insert(self.line_buf.len());
+                self.line_buf.resize(self.line_pos, (b' ', self.cur_color));
             }
-            // Handle prompt events (GA/EOR) with multi-read buffering (C++ Session.cc:455-499, 596-602)
-            if prompt_count > 0 {
-                self.handle_prompt_event();
+            if self.tty_stale.is_none() {
+                self.print_char(b);
+            }
+            self.line_buf.push(cell);
+        }
+        self.line_pos += 1;
+    }
+
+    /// EL/ED on the line being received
+    fn erase(&mut self, erase: Erase) {
+        if self.line_buf.is_empty() {
+            return;
+        }
+        self.tty_stale.get_or_insert(self.line_buf.len());
+        match erase {
+            Erase::ToEnd => self.line_buf.truncate(self.line_pos),
+            Erase::ToCursor => {
+                let end = (self.line_pos + 1).min(self.line_buf.len());
+                for cell in &mut self.line_buf[..end] {
+                    *cell = (b' ', self.cur_color);
+                }
+            }
+            Erase::Line => self.line_buf.clear(),
+        }
+    }
+
+    /// TTY mode: redraw the line if it was changed other than by appending
+    fn sync_tty_line(&mut self) {
+        if let Some(shown) = self.tty_stale.take() {
+            self.rewrite_tty_line(shown, true);
+        }
+    }
+
+    /// Take back the last `printed_len` cells written to the OutputWindow and,
+    /// unless the line was gagged, print line_buf again with its current colors
+    fn rewrite_tty_line(&mut self, printed_len: usize, reprint: bool) {
+        if self.output_window.is_null() {
+            return;
+        }
+        unsafe {
+            (*self.output_window).unprint(printed_len);
+            if reprint {
+                for &(ch, color) in &self.line_buf {
+                    (*self.output_window).print(&[ch], color);
+                }
             }
         }
     }
 
     /// Handle prompt event (IAC GA/EOR) with multi-read buffering
     /// C++ Session.cc lines 455-499 (prompt detection) and 596-602 (buffering)
-    fn handle_prompt_event(&mut self) {
+    fn handle_prompt_event(&mut self, interp: Option<&mut (dyn Interpreter + '_)>) {
         // Combine prompt_buffer (from previous reads) + current line_buf
         // C++ lines 479-485: if (prompt[0] || out[0]) { strcat(prompt, out_buf); set_prompt(...) }
         let mut full_prompt = self.prompt_buffer.clone();
         full_prompt.extend(self.line_buf.iter().map(|(ch, _)| *ch));
 
         let prompt_text = String::from_utf8_lossy(&full_prompt).to_string();
+        self.last_prompt = Some(prompt_text.clone());
+        self.record(SessionEvent::Prompt(prompt_text.clone()));
 
         // Call prompt callback (C++ set_prompt calls sys/prompt hook)
-        let should_show = if let Some(ref mut callback) = self.prompt_callback {
+        let mut should_show = if let Some(ref mut callback) = self.prompt_callback {
             callback(&prompt_text)
         } else {
             true // Default: show prompt
         };
 
+        // sys/prompt hook (C++ Session::set_prompt): returns the prompt to
+        // show, or "" / okros.gag() to hide it
+        if let Some(interp) = interp {
+            let mut out = String::new();
+            let ran = interp.run_quietly("sys/prompt", &prompt_text, &mut out, true);
+            let gagged = interp.take_gag();
+            if gagged {
+                out.clear();
+            }
+            if (ran || gagged) && out != prompt_text {
+                let printed_len = self.line_buf.len();
+                should_show &= !out.is_empty();
+                self.replace_line(&out);
+                self.rewrite_tty_line(printed_len, should_show);
+            }
+        }
+
+        // Sticky prompt: replace the input line prompt in place and take the
+        // characters printed as they arrived back out of the output
+        if self.sticky_prompt {
+            if should_show {
+                self.prompt_update = Some(self.line_text());
+            }
+            self.rewrite_tty_line(self.line_buf.len(), false);
+            self.prompt_buffer.clear();
+            self.line_buf.clear();
+            self.line_pos = 0;
+            return;
+        }
+
         // Note: Prompt characters were already written via print_char() as they arrived
         // prompt_event (GA/EOR) just signals completion, nothing more to print
         // In headless mode, write the buffered prompt to scrollback
@@ -208,61 +987,236 @@ impl<D: Decompressor> Session<D> {
         // Clear buffers for next prompt (C++ line 497: prompt[0] = NUL)
         self.prompt_buffer.clear();
         self.line_buf.clear();
+        self.line_pos = 0;
     }
 
-    /// Check trigger/replacement callbacks on current line (C++ Session::triggerCheck lines 640-683)
+    /// Run the current line through the filter pipeline (C++
+    /// Session::triggerCheck lines 640-683, its order made configurable)
     /// Returns false if line should be gagged (not printed)
-    fn check_line_triggers(&mut self) -> bool {
+    fn check_line_triggers(
+        &mut self,
+        mut interp: Option<&mut (dyn Interpreter + '_)>,
+        mud: Option<&Mud>,
+    ) -> bool {
         if self.line_buf.is_empty() {
             return true;
         }
-
-        // Extract plain text by stripping SET_COLOR markers (C++ Session.cc:656-664)
-        let mut plain_text: String = self
-            .line_buf
-            .iter()
-            .map(|(ch, _color)| *ch as char)
-            .collect();
-
-        // Check replacement first (can modify text)
-        if let Some(ref mut callback) = self.replacement_callback {
-            if let Some(replacement) = callback(&plain_text) {
-                // If empty replacement, this is a gag - return false to skip printing
-                if replacement.is_empty() {
-                    return false;
+        let mut plain_text = self.line_text();
+        // The MUD repeating a trigger's command isn't worth a trigger check
+        if self.trigger_guard.is_echo(&plain_text, Instant::now()) {
+            return false;
+        }
+        for i in 0..self.filters.len() {
+            let keep = match self.filters.stage(i) {
+                None => true,
+                Some(Stage::Builtin(stage)) => {
+                    let stage = *stage;
+                    self.run_builtin_filter(stage, &mut plain_text, interp.as_deref_mut(), mud)
                 }
-                plain_text = replacement.clone();
-                // Replace line_buf with new text (preserve colors for now - C++ does full re-processing)
-                self.line_buf.clear();
-                for ch in replacement.bytes() {
-                    self.line_buf.push((ch, self.cur_color));
+                Some(Stage::Script(function)) => {
+                    let function = function.clone();
+                    match interp.as_deref_mut() {
+                        Some(interp) => self.run_script_filter(&function, &mut plain_text, interp),
+                        None => true,
+                    }
                 }
+                Some(Stage::Custom(_)) => match self.filters.run_custom(i, &plain_text) {
+                    FilterAction::Pass => true,
+                    FilterAction::Gag => false,
+                    FilterAction::Replace(text) => {
+                        self.replace_line(&text);
+                        plain_text = text;
+                        true
+                    }
+                },
+            };
+            if !keep {
+                return false;
             }
         }
+        true // Print the line
+    }
 
-        // Check triggers (generate commands but don't affect line display)
-        if let Some(ref mut callback) = self.trigger_callback {
-            let _commands = callback(&plain_text);
-            // TODO: Commands should be added to interpreter queue (C++ Session.cc:667)
-            // For now, we just call the callback which can handle queueing externally
-        }
-
-        // Call sys/output hook (C++ Session.cc:671 - AFTER trigger/replacement)
-        if let Some(ref mut callback) = self.output_callback {
-            if let Some(modified) = callback(&plain_text) {
-                // Hook modified the text (or gagged it)
-                if modified.is_empty() {
-                    return false; // Gag the line
+    /// One built-in stage; false gags the line
+    fn run_builtin_filter(
+        &mut self,
+        stage: Builtin,
+        plain_text: &mut String,
+        interp: Option<&mut (dyn Interpreter + '_)>,
+        mud: Option<&Mud>,
+    ) -> bool {
+        match stage {
+            Builtin::Msp => {
+                // Strip MSP sound triggers, keeping the colors of the remaining text
+                let text: Vec<u8> = self.line_buf.iter().map(|(ch, _)| *ch).collect();
+                let found = msp::find_triggers(&text);
+                if found.is_empty() {
+                    return true;
+                }
+                for (range, _) in found.iter().rev() {
+                    self.line_buf.drain(range.clone());
                 }
-                // Replace line_buf with modified text
-                self.line_buf.clear();
-                for ch in modified.bytes() {
-                    self.line_buf.push((ch, self.cur_color));
+                if self.msp {
+                    self.msp_triggers.extend(found.into_iter().map(|(_, t)| t));
                 }
+                *plain_text = self.line_text();
+                // A line that only carried triggers isn't shown
+                !plain_text.bytes().all(|b| b.is_ascii_whitespace())
+            }
+            Builtin::Subst => {
+                // The replacement callback, then the MUD's #subst actions (C++
+                // mud.checkReplacement); an empty result is a gag
+                let replaced = match self.replacement_callback {
+                    Some(ref mut callback) => callback(plain_text),
+                    None => None,
+                };
+                if let Some(replacement) = replaced {
+                    if replacement.is_empty() {
+                        return false;
+                    }
+                    self.replace_line(&replacement);
+                    *plain_text = replacement;
+                }
+                if let (Some(mud), Some(interp)) = (mud, interp) {
+                    if let Some(replacement) = mud.check_substitution(plain_text, interp) {
+                        if replacement.is_empty() {
+                            return false;
+                        }
+                        self.replace_line(&replacement);
+                        *plain_text = replacement;
+                    }
+                }
+                true
+            }
+            Builtin::Gag => match (mud, interp) {
+                (Some(mud), Some(interp)) => !mud.check_gag(plain_text, interp),
+                _ => true,
+            },
+            Builtin::Triggers => {
+                // Generate commands but don't affect line display
+                if let Some(ref mut callback) = self.trigger_callback {
+                    let commands = callback(plain_text);
+                    // TODO: Commands should be added to interpreter queue (C++ Session.cc:667)
+                    // For now, we just call the callback which can handle queueing externally
+                    if !commands.is_empty() {
+                        self.record(SessionEvent::TriggerFired {
+                            line: plain_text.clone(),
+                            commands,
+                        });
+                    }
+                }
+                true
+            }
+            Builtin::Output => {
+                // sys/output (C++ Session.cc:671 - AFTER trigger/replacement)
+                if let Some(ref mut callback) = self.output_callback {
+                    if let Some(modified) = callback(plain_text) {
+                        // Hook modified the text (or gagged it)
+                        if modified.is_empty() {
+                            return false;
+                        }
+                        self.replace_line(&modified);
+                        *plain_text = modified;
+                    }
+                }
+                // Interpreter sys/output hook: the script returns the line to show
+                // (rewritten as it likes), or "" / okros.gag() to gag it
+                match interp {
+                    Some(interp) => self.run_script_filter("sys/output", plain_text, interp),
+                    None => true,
+                }
+            }
+            Builtin::Highlight => {
+                // Highlights recolor the text as it stands at this stage
+                let mut spans = match self.highlight_callback {
+                    Some(ref mut callback) => callback(plain_text),
+                    None => Vec::new(),
+                };
+                if let (Some(mud), Some(interp)) = (mud, interp) {
+                    spans.extend(mud.check_highlight(plain_text, interp));
+                }
+                // Spans are byte offsets into plain_text; cells are chars
+                let cell_at = |i: usize| plain_text.get(..i).map_or(i, |s| s.chars().count());
+                for (start, end, color) in spans {
+                    let end = cell_at(end).min(self.line_buf.len());
+                    for cell in self.line_buf[cell_at(start).min(end)..end].iter_mut() {
+                        cell.1 = (cell.1 & 0xFF00) | color as u16; // Keeps underline etc.
+                    }
+                }
+                true
+            }
+            Builtin::Urls => {
+                // URLs are underlined, marked for OSC 8 and numbered for #url
+                for range in crate::url::find_urls(plain_text) {
+                    self.urls.add(&plain_text[range.clone()]);
+                    // One cell per char (line bytes were taken as chars)
+                    let start = plain_text[..range.start].chars().count();
+                    let end = plain_text[..range.end]
+                        .chars()
+                        .count()
+                        .min(self.line_buf.len());
+                    for cell in self.line_buf[start.min(end)..end].iter_mut() {
+                        cell.1 |= crate::color::UNDERLINE | crate::color::LINK;
+                    }
+                }
+                true
             }
         }
+    }
 
-        true // Print the line
+    /// A script stage (sys/output or one added with #filter add): the
+    /// function returns the line to show, "" or okros.gag() gags it
+    fn run_script_filter(
+        &mut self,
+        function: &str,
+        plain_text: &mut String,
+        interp: &mut dyn Interpreter,
+    ) -> bool {
+        let mut out = String::new();
+        let ran = interp.run_quietly(function, plain_text, &mut out, true);
+        if interp.take_gag() {
+            return false;
+        }
+        if ran && out != *plain_text {
+            if out.is_empty() {
+                return false;
+            }
+            self.replace_line(&out);
+            *plain_text = out;
+        }
+        true
+    }
+
+    /// Replace line_buf with `text`. Text the old and new lines start and
+    /// end with keeps its colors; the part that changed takes the color of
+    /// the text it replaced (the current color if the line was empty)
+    fn replace_line(&mut self, text: &str) {
+        // One cell per character, as line_text() made them
+        let new: Vec<u8> = text
+            .chars()
+            .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+            .collect();
+        let old = &self.line_buf;
+        let prefix = old
+            .iter()
+            .zip(&new)
+            .take_while(|((a, _), b)| a == *b)
+            .count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|((a, _), b)| a == *b)
+            .count();
+        let color = old
+            .get(prefix)
+            .or_else(|| old.get(prefix.checked_sub(1)?))
+            .map_or(self.cur_color, |&(_, c)| c);
+        let middle = new[prefix..new.len() - suffix]
+            .iter()
+            .map(|&ch| (ch, color));
+        let end = old.len() - suffix;
+        self.line_buf.splice(prefix..end, middle);
     }
 
     pub fn drain_prompt_events(&mut self) -> usize {
@@ -271,13 +1225,24 @@ impl<D: Decompressor> Session<D> {
         n
     }
 
+    /// MCCP (compressed, uncompressed) byte counts, if compression is active
+    pub fn compression_stats(&self) -> Option<(usize, usize)> {
+        self.decomp.compression_stats()
+    }
+
+    /// Take the text of the last GA/EOR-terminated prompt, if one arrived since
+    /// the previous call (line_buf is already cleared by then)
+    pub fn take_last_prompt(&mut self) -> Option<String> {
+        self.last_prompt.take()
+    }
+
     /// Get current incomplete line (not yet terminated by newline or prompt event)
     pub fn current_line(&self) -> Vec<u8> {
         self.line_buf.iter().map(|(ch, _)| *ch).collect()
     }
 
     /// Get current incomplete line with colors (for rendering)
-    pub fn current_line_colored(&self) -> &[(u8, u8)] {
+    pub fn current_line_colored(&self) -> &[(u8, u16)] {
         &self.line_buf
     }
 
@@ -323,6 +1288,401 @@ mod tests {
         assert_eq!(&text[5..10], b"World");
     }
 
+    fn shown_lines(ses: &mut Session<PassthroughDecomp>) -> Vec<String> {
+        ses.take_events()
+            .into_iter()
+            .filter_map(|e| match e {
+                SessionEvent::Line(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn more_pauses_until_resumed() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 80, 5, 100);
+        ses.set_event_recording(true);
+        ses.set_more(Some(2));
+        ses.feed(b"one\ntwo\nthree\nfour\nHP> ");
+        assert_eq!(shown_lines(&mut ses), ["one", "two"]);
+        assert_eq!(ses.paused(), Some(2));
+        assert!(!ses.pending_output());
+        ses.resume();
+        assert!(ses.pending_output());
+        ses.pump(None, None);
+        assert_eq!(shown_lines(&mut ses), ["three", "four"]);
+        // Only the prompt's partial line waits now
+        assert_eq!(ses.paused(), Some(0));
+        ses.set_more(None);
+        ses.pump(None, None);
+        assert_eq!(ses.current_line(), b"HP> ");
+        assert_eq!(ses.paused(), None);
+    }
+
+    #[test]
+    fn output_budget_cuts_at_line_ends() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 80, 5, 100);
+        ses.set_event_recording(true);
+        ses.set_output_budget(Some(6));
+        ses.feed(b"aaaa\nbbbb\ncccc\ndddd\n");
+        assert_eq!(shown_lines(&mut ses), ["aaaa", "bbbb"]);
+        assert!(ses.pending_output());
+        ses.pump(None, None);
+        assert_eq!(shown_lines(&mut ses), ["cccc", "dddd"]);
+        assert!(!ses.pending_output());
+    }
+
+    #[test]
+    fn full_backlog_drops_the_oldest_lines() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 80, 5, 100);
+        ses.set_event_recording(true);
+        ses.set_more(Some(0));
+        // 100-byte lines, ten more than fit
+        let count = MAX_BACKLOG / 100 + 10;
+        let flood: String = (0..count).map(|i| format!("{:099}\n", i)).collect();
+        ses.feed(flood.as_bytes());
+        assert_eq!(ses.dropped_lines(), 10);
+        assert_eq!(ses.paused(), Some(count - 10));
+        ses.set_more(None);
+        ses.pump(None, None);
+        let lines = shown_lines(&mut ses);
+        assert_eq!(lines.len(), count - 10);
+        assert_eq!(lines[0], format!("{:099}", 10));
+    }
+
+    #[test]
+    fn interpreter_output_and_prompt_hooks() {
+        // sys/output upcases "tell" lines and gags "spam"; sys/prompt
+        // rewrites HP prompts and hides the rest
+        struct Hooks;
+        impl Interpreter for Hooks {
+            fn run(&mut self, function: &str, arg: &str, out: &mut String) -> bool {
+                *out = match function {
+                    "sys/output" if arg.contains("spam") => String::new(),
+                    "sys/output" if arg.contains("tells") => arg.to_uppercase(),
+                    "sys/output" => return false,
+                    "sys/prompt" => match arg.strip_prefix("HP: ") {
+                        Some(rest) => format!("[{}]", rest.trim_end_matches("> ")),
+                        None => String::new(),
+                    },
+                    _ => return false,
+                };
+                true
+            }
+        }
+
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 4, 20);
+        ses.feed_with_interp(
+            b"Bob tells you hi\nspam\nplain\nHP: 10> \xff\xf9",
+            Some(&mut Hooks),
+        );
+        ses.feed_with_interp(b"Login: \xff\xf9", Some(&mut Hooks));
+        let view = ses.scrollback_viewport().unwrap();
+        let rows: Vec<String> = view
+            .chunks(20)
+            .map(|row| {
+                let text: String = row.iter().map(|a| (a & 0xFF) as u8 as char).collect();
+                text.trim_end_matches([' ', '\0']).to_string()
+            })
+            .collect();
+        assert_eq!(rows, vec!["BOB TELLS YOU HI", "plain", "[10]", ""]);
+    }
+
+    #[test]
+    fn okros_gag_from_output_hook() {
+        // sys/output calls okros.gag() instead of returning a string
+        #[derive(Default)]
+        struct Gagger {
+            gagged: bool,
+        }
+        impl Interpreter for Gagger {
+            fn run(&mut self, function: &str, arg: &str, _: &mut String) -> bool {
+                self.gagged = function == "sys/output" && arg.contains("noise");
+                false
+            }
+            fn take_gag(&mut self) -> bool {
+                std::mem::take(&mut self.gagged)
+            }
+        }
+
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 20);
+        ses.set_event_recording(true);
+        ses.feed_with_interp(b"noise\nsignal\n", Some(&mut Gagger::default()));
+        assert_eq!(
+            ses.take_events(),
+            vec![SessionEvent::Line("signal".to_string())]
+        );
+    }
+
+    #[test]
+    fn urls_marked_and_listed() {
+        use crate::color::{LINK, UNDERLINE};
+        let mut ses = Session::new(PassthroughDecomp::new(), 30, 2, 20);
+        ses.feed(b"\xe9 see http://a.org/x.\nhttps://b.io\n");
+        assert_eq!(ses.urls.get(1), Some("https://b.io"));
+        assert_eq!(ses.urls.get(2), Some("http://a.org/x"));
+        let view = ses.scrollback_viewport().unwrap();
+        let linked = |a: &crate::scrollback::Attrib| (a >> 8) as u16 & (UNDERLINE | LINK) != 0;
+        let row: String = view[..30]
+            .iter()
+            .map(|a| if linked(a) { '^' } else { ' ' })
+            .collect();
+        assert_eq!(row.trim_end(), "      ^^^^^^^^^^^^^^");
+
+        // Cells, not bytes: the space after a non-ASCII URL isn't linked
+        ses.feed(b"http://a.org/caf\xe9 x\n");
+        let view = ses.scrollback_viewport().unwrap();
+        let row: String = view[30..]
+            .iter()
+            .map(|a| if linked(a) { '^' } else { ' ' })
+            .collect();
+        assert_eq!(row.trim_end(), "^".repeat(17));
+    }
+
+    #[test]
+    fn idle_times_and_unseen_output() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 50);
+        let later = Instant::now() + Duration::from_secs(90);
+        assert!(ses.idle(later) >= Duration::from_secs(90));
+        assert!(!ses.has_unseen());
+
+        ses.record_read(5);
+        ses.record_send();
+        assert!(ses.idle(later) <= Duration::from_secs(90));
+        assert!(ses.output_idle(Instant::now()) < Duration::from_secs(90));
+        assert!(ses.has_unseen());
+        ses.mark_seen();
+        assert!(!ses.has_unseen());
+    }
+
+    #[test]
+    fn mxp_stripped_and_atcp_hooked() {
+        use crate::atcp::TELOPT_ATCP;
+        use crate::mxp::TELOPT_MXP;
+        use crate::telnet::telnet::*;
+        #[derive(Default)]
+        struct AtcpHook(Vec<String>);
+        impl Interpreter for AtcpHook {
+            fn run(&mut self, function: &str, arg: &str, _out: &mut String) -> bool {
+                self.0.push(format!("{} {}", function, arg));
+                false
+            }
+        }
+
+        let mut ses = Session::new(PassthroughDecomp::new(), 30, 3, 20);
+        ses.set_event_recording(true);
+        // Refused by default: the tags stay, MXP line-mode escapes still go
+        ses.feed(&[IAC, WILL, TELOPT_MXP]);
+        ses.feed(b"\x1b[1z<B>a</B>\n");
+        assert_eq!(ses.take_telnet_responses(), vec![IAC, DONT, TELOPT_MXP]);
+
+        ses.set_mxp(true);
+        ses.set_atcp(true);
+        let mut hook = AtcpHook::default();
+        let mut data = vec![
+            IAC,
+            WILL,
+            TELOPT_MXP,
+            IAC,
+            WILL,
+            TELOPT_ATCP,
+            IAC,
+            SB,
+            TELOPT_ATCP,
+        ];
+        data.extend_from_slice(b"Char.Name Bob");
+        data.extend_from_slice(&[IAC, SE]);
+        data.extend_from_slice(b"\x1b[1z<SEND \"n\">north</SEND> &amp; up\n");
+        ses.feed_with_interp(&data, Some(&mut hook));
+        assert_eq!(
+            ses.take_events(),
+            vec![
+                SessionEvent::Line("<B>a</B>".to_string()),
+                SessionEvent::Line("north & up".to_string()),
+            ]
+        );
+        assert!(hook.0.contains(&"sys/atcp Char.Name Bob".to_string()));
+    }
+
+    #[test]
+    fn bell_kept_out_of_scrollback_and_queued() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 30, 3, 20);
+        ses.set_event_recording(true);
+        ses.feed(b"\x07You are being paged\x07!\n");
+        assert_eq!(
+            ses.take_bells(),
+            vec!["".to_string(), "You are being paged".to_string()]
+        );
+        assert_eq!(
+            ses.take_events(),
+            vec![SessionEvent::Line("You are being paged!".to_string())]
+        );
+        assert!(ses.take_bells().is_empty());
+
+        ses.feed(&[0x07; 100]);
+        assert_eq!(ses.take_bells().len(), MAX_BELLS);
+    }
+
+    #[test]
+    fn msp_triggers_stripped_and_queued() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 30, 3, 20);
+        ses.set_event_recording(true);
+        ses.feed(b"Rain !!SOUND(rain.wav V=50) falls\n");
+        assert!(ses.take_msp_triggers().is_empty()); // Disabled: stripped only
+
+        ses.set_msp(true);
+        ses.feed(b"!!MUSIC(theme.mid)\nThunder!!!SOUND(boom.wav)\n");
+        let files: Vec<String> = ses
+            .take_msp_triggers()
+            .into_iter()
+            .map(|t| t.file)
+            .collect();
+        assert_eq!(files, vec!["theme.mid", "boom.wav"]);
+        assert_eq!(
+            ses.take_events(),
+            vec![
+                SessionEvent::Line("Rain  falls".to_string()),
+                SessionEvent::Line("Thunder!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn sticky_prompt_stays_out_of_scrollback() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 20);
+        ses.set_sticky_prompt(true);
+        ses.feed(b"You hit.\nHP: 10> \xff\xf9");
+        ses.feed(b"HP: 9> \xff\xf9");
+        assert_eq!(ses.take_prompt_update().as_deref(), Some("HP: 9> "));
+        assert_eq!(ses.take_prompt_update(), None);
+        assert_eq!(ses.total_lines(), 1); // only "You hit."
+        assert_eq!(ses.take_last_prompt().as_deref(), Some("HP: 9> "));
+
+        // TTY mode: the characters already printed are taken back
+        let mut ow = crate::output_window::OutputWindow::new(std::ptr::null_mut(), 20, 3, 20, 0x07);
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 20);
+        ses.attach_window(&mut ow);
+        ses.set_sticky_prompt(true);
+        ses.feed(b"You hit.\nHP: 10> \xff\xf9More\n");
+        ow.redraw();
+        let text: String = ow
+            .viewport()
+            .iter()
+            .map(|a| match (a & 0xFF) as u8 {
+                0 => ' ', // Never-written cell
+                ch => ch as char,
+            })
+            .collect();
+        assert_eq!(&text[..28], "You hit.            More    ");
+        assert_eq!(ses.take_prompt_update().as_deref(), Some("HP: 10> "));
+    }
+
+    #[test]
+    fn carriage_return_and_csi_rewrite_the_line() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 5, 20);
+        ses.set_event_recording(true);
+        ses.feed(b"Loading 10%\rLoading 50%");
+        ses.feed(b"\r\x1b[KDone\r\n");
+        ses.feed(b"abc\x1b[2Gx\x1b[5Ge\n");
+        ses.feed(b"old text\x1b[2K\r\x1b[Cok\n");
+        let lines: Vec<SessionEvent> = ses.take_events();
+        assert_eq!(
+            lines,
+            vec![
+                SessionEvent::Line("Done".to_string()),
+                SessionEvent::Line("axc e".to_string()),
+                SessionEvent::Line(" ok".to_string()),
+            ]
+        );
+
+        // TTY mode: what was printed as it arrived is redrawn
+        let mut ow = crate::output_window::OutputWindow::new(std::ptr::null_mut(), 20, 3, 20, 0x07);
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 20);
+        ses.attach_window(&mut ow);
+        ses.feed(b"Loading 10%\rLoading 50%");
+        ses.feed(b"\r\x1b[KDone\r\nHP 9\x1b[1D8");
+        ow.redraw();
+        let text: String = ow
+            .viewport()
+            .iter()
+            .map(|a| match (a & 0xFF) as u8 {
+                0 => ' ',
+                ch => ch as char,
+            })
+            .collect();
+        assert_eq!(&text[..24], "Done                HP 8");
+    }
+
+    #[test]
+    fn records_events_only_when_enabled() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 40, 3, 20);
+        ses.set_trigger_callback(Box::new(|line| {
+            if line.contains("hungry") {
+                vec!["eat bread".to_string()]
+            } else {
+                Vec::new()
+            }
+        }));
+        ses.set_replacement_callback(Box::new(|line| line.contains("spam").then(String::new)));
+
+        ses.feed(b"before\n");
+        assert!(ses.take_events().is_empty());
+
+        ses.set_event_recording(true);
+        ses.feed(b"You are hungry\nspam spam\nHP: 10> \xff\xf9");
+        assert_eq!(
+            ses.take_events(),
+            vec![
+                SessionEvent::TriggerFired {
+                    line: "You are hungry".to_string(),
+                    commands: vec!["eat bread".to_string()],
+                },
+                SessionEvent::Line("You are hungry".to_string()),
+                SessionEvent::Prompt("HP: 10> ".to_string()),
+            ]
+        );
+        assert!(ses.take_events().is_empty());
+    }
+
+    #[test]
+    fn take_last_prompt_after_ga() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 2, 20);
+        ses.feed(b"Password: \xff\xf9");
+        assert!(ses.current_line().is_empty());
+        assert_eq!(ses.take_last_prompt().as_deref(), Some("Password: "));
+        assert_eq!(ses.take_last_prompt(), None);
+    }
+
+    #[test]
+    fn timestamps_prefix_completed_lines() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 40, 3, 20);
+        ses.feed(b"plain\n");
+        ses.set_timestamps(true);
+        ses.feed(b"stamped\npartial");
+
+        let v = ses.scrollback_viewport().unwrap();
+        let row = |r: usize| -> String {
+            v[r * 40..(r + 1) * 40]
+                .iter()
+                .map(|a| (a & 0xFF) as u8 as char)
+                .collect()
+        };
+        assert!(row(0).starts_with("plain "));
+        let stamped = row(1);
+        let b = stamped.as_bytes();
+        assert!(b[..8].iter().enumerate().all(|(i, c)| if i == 2 || i == 5 {
+            *c == b':'
+        } else {
+            c.is_ascii_digit()
+        }));
+        assert_eq!(&stamped[8..17], " stamped ");
+        // Stamp is dim, text keeps its own color
+        assert_eq!((v[40] >> 8) as u8, TIMESTAMP_COLOR);
+        assert_eq!((v[49] >> 8) as u8, 0x07);
+        // Incomplete line isn't stamped until it completes
+        assert_eq!(ses.current_line(), b"partial");
+    }
+
     #[test]
     fn nodeka_menu_colors() {
         // Real Nodeka output with mid-line color changes
@@ -364,4 +1724,212 @@ mod tests {
             ansi_output
         );
     }
+
+    #[test]
+    fn gag_and_highlight_callbacks() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 10, 3, 20);
+        ses.set_replacement_callback(Box::new(|line| line.contains("spam").then(String::new)));
+        ses.set_highlight_callback(Box::new(|line| {
+            line.find("orc")
+                .map(|i| vec![(i, i + 3, 0x84)])
+                .unwrap_or_default()
+        }));
+        ses.feed(b"spam spam\nan orc\n");
+
+        let v = ses.scrollback_viewport().unwrap();
+        let text: String = v[0..10].iter().map(|a| (a & 0xFF) as u8 as char).collect();
+        assert_eq!(text, "an orc    ");
+        assert_eq!((v[3] >> 8) as u8, 0x84);
+        assert_eq!((v[5] >> 8) as u8, 0x84);
+        assert_eq!((v[0] >> 8) as u8, 0x07);
+        assert_eq!(ses.total_lines(), 1);
+
+        // Past an accented character the span still covers "orc"
+        ses.feed(b"\xe9\xe9 orc\n");
+        let v = ses.scrollback_viewport().unwrap();
+        let colors: Vec<u8> = v[10..17].iter().map(|a| (a >> 8) as u8).collect();
+        assert_eq!(colors, [0x07, 0x07, 0x07, 0x84, 0x84, 0x84, 0x07]);
+    }
+
+    #[test]
+    fn mud_substitutions_keep_surrounding_colors() {
+        use crate::action::{Action, ActionType};
+        use crate::plugins::native_regex::NativeRegex;
+
+        let mut mud = Mud::empty();
+        mud.action_list
+            .push(Action::new("orc", "troll", ActionType::Replacement));
+        mud.action_list
+            .push(Action::new(r"^\[OOC\]", "", ActionType::Gag));
+        let mut re = NativeRegex::new();
+        mud.compile_actions(&mut re);
+
+        let mut ses = Session::new(PassthroughDecomp::new(), 12, 3, 20);
+        ses.feed_with_mud(
+            b"[OOC] hi\n\x1b[31man \x1b[32morc\x1b[34m!\n",
+            Some(&mut re),
+            Some(&mud),
+        );
+        assert_eq!(ses.total_lines(), 1);
+        let v = ses.scrollback_viewport().unwrap();
+        let text: String = v[0..9].iter().map(|a| (a & 0xFF) as u8 as char).collect();
+        assert_eq!(text, "an troll!");
+        let colors: Vec<u8> = v[0..9].iter().map(|a| (a >> 8) as u8).collect();
+        // "an " red, the replacement takes the replaced text's green, "!" blue
+        // (curses color order: red is 4, blue 1)
+        assert_eq!(&colors[..3], &[0x04; 3]);
+        assert_eq!(&colors[3..8], &[0x02; 5]);
+        assert_eq!(colors[8], 0x01);
+
+        // Accented text stays one cell per character and keeps its color
+        ses.feed_with_mud(b"\x1b[31mcaf\xe9 \x1b[32morc\n", Some(&mut re), Some(&mud));
+        let v = ses.scrollback_viewport().unwrap();
+        let cells: Vec<u8> = v[12..24].iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(cells, b"caf\xe9 troll  ");
+        let colors: Vec<u8> = v[12..22].iter().map(|a| (a >> 8) as u8).collect();
+        assert_eq!(
+            colors,
+            [0x04, 0x04, 0x04, 0x04, 0x04, 0x02, 0x02, 0x02, 0x02, 0x02]
+        );
+    }
+
+    #[test]
+    fn filter_stages_switch_off_and_plug_in() {
+        use crate::action::{Action, ActionType};
+        use crate::filters::{FilterAction, LineFilter};
+        use crate::plugins::native_regex::NativeRegex;
+
+        struct NoShout;
+        impl LineFilter for NoShout {
+            fn filter(&mut self, text: &str) -> FilterAction {
+                if text.contains("shouts") {
+                    FilterAction::Gag
+                } else {
+                    FilterAction::Replace(text.replace("orc", "ORC"))
+                }
+            }
+        }
+
+        let mut mud = Mud::empty();
+        mud.action_list
+            .push(Action::new(r"^\[OOC\]", "", ActionType::Gag));
+        let mut re = NativeRegex::new();
+        mud.compile_actions(&mut re);
+
+        let mut ses = Session::new(PassthroughDecomp::new(), 12, 3, 20);
+        ses.filters.command("off gag").unwrap();
+        ses.filters
+            .add(
+                "noshout",
+                Stage::Custom(Box::new(NoShout)),
+                Some("highlight"),
+            )
+            .unwrap();
+        ses.feed_with_mud(b"[OOC] hi\nBob shouts\nan orc\n", Some(&mut re), Some(&mud));
+        assert_eq!(ses.total_lines(), 2);
+        let v = ses.scrollback_viewport().unwrap();
+        let text: String = v.iter().map(|a| (a & 0xFF) as u8 as char).collect();
+        assert!(text.starts_with("[OOC] hi    an ORC"), "{:?}", text);
+    }
+
+    #[test]
+    fn diagnostics_bypass_triggers_and_events() {
+        use crate::telnet::telnet::*;
+        use std::sync::{Arc, Mutex};
+        let mut ses = Session::new(PassthroughDecomp::new(), 40, 3, 20);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_cb = seen.clone();
+        ses.set_trigger_callback(Box::new(move |line| {
+            seen_cb.lock().unwrap().push(line.to_string());
+            Vec::new()
+        }));
+        ses.set_event_recording(true);
+        ses.feed(&[IAC, WILL, 201, b'h', b'i', b'\n']);
+        assert_eq!(*seen.lock().unwrap(), vec!["hi".to_string()]);
+        assert_eq!(
+            ses.take_events(),
+            vec![SessionEvent::Line("hi".to_string())]
+        );
+
+        let v = ses.scrollback_viewport().unwrap();
+        let row: String = v[40..65].iter().map(|a| (a & 0xFF) as u8 as char).collect();
+        assert_eq!(row, "Telnet: refused WILL GMCP");
+        assert_eq!(v[40] >> 8, crate::color::DIAG_COLOR as u32);
+
+        ses.set_diagnostics(false);
+        ses.feed(&[IAC, WILL, 69]);
+        assert_eq!(ses.total_lines(), 2);
+    }
+
+    #[test]
+    fn trigger_command_echoes_gagged() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 3, 20);
+        ses.trigger_guard.set_gag_echoes(true);
+        ses.trigger_guard.sent("kill rat", Instant::now());
+        ses.feed(b"kill rat\nkill rat\n");
+        assert_eq!(ses.total_lines(), 1);
+    }
+
+    #[test]
+    fn client_msg_prefixes_and_colors_by_level() {
+        let mut ses = Session::new(PassthroughDecomp::new(), 30, 3, 20);
+        ses.client_msg("Connection closed.", MsgLevel::Error);
+        ses.set_msg_colors([0x01, 0x02, 0x03]);
+        ses.client_msg("Logged", MsgLevel::Info);
+        assert_eq!(ses.total_lines(), 2);
+
+        let v = ses.scrollback_viewport().unwrap();
+        let row = |n: usize| -> String {
+            v[n * 30..n * 30 + 30]
+                .iter()
+                .map(|a| (a & 0xFF) as u8 as char)
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        };
+        assert_eq!(row(0), "Error: Connection closed.");
+        assert_eq!(v[0] >> 8, crate::color::ERROR_COLOR as u32);
+        assert_eq!(row(1), "-- Logged");
+        assert_eq!(v[30] >> 8, 0x01);
+    }
+
+    #[test]
+    fn latin1_decoded_and_negotiated() {
+        use crate::telnet::telnet::*;
+        let mut ses = Session::new(PassthroughDecomp::new(), 12, 3, 20);
+        ses.set_charset(Charset::Latin1);
+        assert_eq!(ses.encode_line("café"), b"caf\xe9\n");
+        ses.feed(b"caf\xe9\n");
+        let v = ses.scrollback_viewport().unwrap();
+        let bytes: Vec<u8> = v[0..5].iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(bytes, b"caf\xe9 ");
+
+        // The server asks for UTF-8: accepted, and é is still one cell
+        let mut request = vec![IAC, WILL, TELOPT_CHARSET, IAC, SB, TELOPT_CHARSET, 1];
+        request.extend_from_slice(b";UTF-8;ISO-8859-1");
+        request.extend_from_slice(&[IAC, SE]);
+        ses.feed(&request);
+        assert!(ses.take_telnet_responses().ends_with(b"\x02UTF-8\xff\xf0"));
+        assert_eq!(ses.charset(), Charset::Utf8);
+        assert_eq!(ses.encode_line("café"), "café\n".as_bytes());
+        ses.feed("café\n".as_bytes());
+        let v = ses.scrollback_viewport().unwrap();
+        let bytes: Vec<u8> = v[12..17].iter().map(|a| (a & 0xFF) as u8).collect();
+        assert_eq!(bytes, b"caf\xe9 ");
+    }
+
+    #[test]
+    fn raw_sends_follow_negotiation_replies() {
+        use crate::telnet::telnet::*;
+        let mut ses = Session::new(PassthroughDecomp::new(), 20, 2, 20);
+        ses.feed(&[IAC, WILL, TELOPT_EOR]);
+        ses.send_raw(&[b'a', IAC]);
+        ses.send_subneg(201, b"Core.Hello {}");
+        let out = ses.take_telnet_responses();
+        let mut expected = vec![IAC, DO, TELOPT_EOR, b'a', IAC, IAC, IAC, SB, 201];
+        expected.extend_from_slice(b"Core.Hello {}");
+        expected.extend_from_slice(&[IAC, SE]);
+        assert_eq!(out, expected);
+        assert!(ses.take_telnet_responses().is_empty());
+    }
 }
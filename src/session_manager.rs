@@ -97,7 +97,7 @@ impl<D: Decompressor> SessionManager<D> {
 
             if written >= 0 {
                 // Track statistics (C++ lines 325-326)
-                self.session.stats.bytes_written += written as usize;
+                self.session.record_written(written as usize);
                 Ok(())
             } else {
                 Err(io::Error::last_os_error())
@@ -119,7 +119,7 @@ impl<D: Decompressor> SessionManager<D> {
 
             if n > 0 {
                 let n = n as usize;
-                self.session.stats.bytes_read += n;
+                self.session.record_read(n);
                 self.session.feed(&buf[..n]);
                 Ok(n)
             } else if n == 0 {
@@ -1,10 +1,26 @@
+use std::collections::VecDeque;
 use std::io;
 use std::mem;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
 
 use libc::{self, c_int};
 
+/// How long an attempt runs before the next address is raced against it
+/// (RFC 8305 "Connection Attempt Delay")
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How long a connect may stay Connecting before it's given up (config:
+/// connecttimeout)
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a graceful close (#close) waits for the MUD to hang up
+pub const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// How long a send waits for a full socket buffer to drain before giving up
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnState {
     Idle,
@@ -16,50 +32,119 @@ pub enum ConnState {
 #[derive(Debug)]
 pub struct Socket {
     fd: RawFd,
+    family: c_int,
     pub state: ConnState,
     pub last_error: Option<i32>,
     pub local: Option<SocketAddr>,
     pub remote: Option<SocketAddr>,
+    pending: VecDeque<SocketAddr>, // Addresses not tried yet (connect_any)
+    racing: Vec<RawFd>,            // Earlier attempts still in flight
+    started: Option<Instant>,      // When the current attempt began
+    began: Option<Instant>,        // When connect_any() started them all
 }
 
 impl Socket {
     pub fn new() -> io::Result<Self> {
-        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0) };
-        if fd < 0 {
-            return Err(io::Error::last_os_error());
-        }
-        // set nonblocking
-        unsafe {
-            let flags = libc::fcntl(fd, libc::F_GETFL);
-            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
-        }
         Ok(Self {
-            fd,
+            fd: open_fd(libc::AF_INET)?,
+            family: libc::AF_INET,
             state: ConnState::Idle,
             last_error: None,
             local: None,
             remote: None,
+            pending: VecDeque::new(),
+            racing: Vec::new(),
+            started: None,
+            began: None,
         })
     }
 
+    /// Adopt an already-connected socket fd (e.g. one inherited across #restart)
+    pub fn from_connected_fd(fd: RawFd) -> Self {
+        set_nonblocking(fd);
+        let mut s = Self {
+            fd,
+            family: libc::AF_UNSPEC,
+            state: ConnState::Connected,
+            last_error: None,
+            local: None,
+            remote: None,
+            pending: VecDeque::new(),
+            racing: Vec::new(),
+            started: None,
+            began: None,
+        };
+        s.fill_endpoints();
+        s
+    }
+
     pub fn as_raw_fd(&self) -> RawFd {
         self.fd
     }
 
+    /// Write data to the MUD with IAC bytes doubled; returns bytes written
+    pub fn send_raw(&self, data: &[u8]) -> io::Result<usize> {
+        self.write_all_now(&crate::telnet::escape_iac(data))
+    }
+
+    /// Write IAC SB option payload IAC SE; returns bytes written
+    pub fn send_subneg(&self, option: u8, payload: &[u8]) -> io::Result<usize> {
+        self.write_all_now(&crate::telnet::subnegotiation(option, payload))
+    }
+
+    /// Write all of `data`, waiting (up to WRITE_TIMEOUT) whenever the
+    /// nonblocking socket is full: a short write would cut a telnet sequence
+    fn write_all_now(&self, data: &[u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + WRITE_TIMEOUT;
+        let mut done = 0;
+        while done < data.len() {
+            let rest = &data[done..];
+            let n =
+                unsafe { libc::write(self.fd, rest.as_ptr() as *const libc::c_void, rest.len()) };
+            if n >= 0 {
+                done += n as usize;
+                continue;
+            }
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::Interrupted => {}
+                io::ErrorKind::WouldBlock => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() || !wait_for_room(self.fd, left)? {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("send stalled after {} of {} bytes", done, data.len()),
+                        ));
+                    }
+                }
+                _ => return Err(err),
+            }
+        }
+        Ok(done)
+    }
+
     pub fn connect_ipv4(&mut self, ip: Ipv4Addr, port: u16) -> io::Result<()> {
-        let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
-        addr.sin_family = libc::AF_INET as libc::sa_family_t;
-        addr.sin_port = u16::to_be(port);
-        addr.sin_addr = libc::in_addr {
-            s_addr: u32::from(ip).to_be(),
-        };
-        let ret = unsafe {
-            libc::connect(
-                self.fd,
-                &addr as *const _ as *const libc::sockaddr,
-                mem::size_of::<libc::sockaddr_in>() as u32,
-            )
-        };
+        self.connect(SocketAddr::new(IpAddr::V4(ip), port))
+    }
+
+    /// Start a nonblocking connect to `addr` (IPv4 or IPv6)
+    /// The fd is reopened when the family differs or it was already used
+    pub fn connect(&mut self, addr: SocketAddr) -> io::Result<()> {
+        let family = family_of(&addr);
+        if self.family != family || self.state != ConnState::Idle {
+            let fd = open_fd(family)?;
+            if self.fd >= 0 {
+                unsafe { libc::close(self.fd) };
+            }
+            self.fd = fd;
+            self.family = family;
+        }
+        self.started = Some(Instant::now());
+        self.local = None;
+        self.remote = None;
+
+        let (ss, len) = to_sockaddr(&addr);
+        let ret = unsafe { libc::connect(self.fd, &ss as *const _ as *const libc::sockaddr, len) };
         if ret == 0 {
             self.state = ConnState::Connected;
             self.fill_endpoints();
@@ -77,65 +162,369 @@ impl Socket {
         }
     }
 
+    /// Connect to the first address that answers, happy-eyeballs style:
+    /// a failed attempt falls through to the next address at once, and one
+    /// that hangs for ATTEMPT_DELAY gets the next address raced against it
+    /// (see tick()). `addrs` should come from resolve()
+    pub fn connect_any(&mut self, addrs: Vec<SocketAddr>) -> io::Result<()> {
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no addresses to connect to",
+            ));
+        }
+        self.close_racing();
+        self.pending = addrs.into();
+        self.began = Some(Instant::now());
+        self.start_next()
+    }
+
+    /// Time left of `limit` for a connect still in progress (None if the
+    /// socket isn't Connecting)
+    pub fn connect_time_left(&self, limit: Duration) -> Option<Duration> {
+        if self.state != ConnState::Connecting {
+            return None;
+        }
+        let began = self.began.or(self.started)?;
+        Some(limit.saturating_sub(began.elapsed()))
+    }
+
+    /// Give up a connect still Connecting after `limit`: every attempt is
+    /// closed and the state becomes Error (ETIMEDOUT). True if it timed out
+    pub fn check_timeout(&mut self, limit: Duration) -> bool {
+        if self.connect_time_left(limit) != Some(Duration::ZERO) {
+            return false;
+        }
+        self.close_attempts();
+        self.state = ConnState::Error;
+        self.last_error = Some(libc::ETIMEDOUT);
+        true
+    }
+
+    /// Abort a connect in progress (#close, Escape): every attempt is closed
+    /// and the socket goes back to Idle. False if it wasn't Connecting
+    pub fn cancel(&mut self) -> bool {
+        if self.state != ConnState::Connecting {
+            return false;
+        }
+        self.close_attempts();
+        self.state = ConnState::Idle;
+        true
+    }
+
+    /// Half-close for a graceful #close: the MUD sees end of input, but what
+    /// it still sends (its goodbye) can be read until it hangs up
+    pub fn shutdown_write(&self) -> io::Result<()> {
+        if unsafe { libc::shutdown(self.fd, libc::SHUT_WR) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn close_attempts(&mut self) {
+        self.close_racing();
+        self.pending.clear();
+        if self.fd >= 0 {
+            unsafe { libc::close(self.fd) };
+        }
+        self.fd = -1;
+        self.family = libc::AF_UNSPEC; // A later connect() opens a new fd
+        self.began = None;
+        self.started = None;
+    }
+
+    /// Try pending addresses until one is connecting or connected
+    fn start_next(&mut self) -> io::Result<()> {
+        let mut last = None;
+        while let Some(addr) = self.pending.pop_front() {
+            match self.connect(addr) {
+                Ok(()) => return Ok(()),
+                Err(e) => last = Some(e),
+            }
+        }
+        match last {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     pub fn on_writable(&mut self) -> io::Result<()> {
         if self.state != ConnState::Connecting {
             return Ok(());
         }
-        // Check SO_ERROR
-        let mut err: c_int = 0;
-        let mut len = mem::size_of::<c_int>() as libc::socklen_t;
-        let rc = unsafe {
-            libc::getsockopt(
-                self.fd,
-                libc::SOL_SOCKET,
-                libc::SO_ERROR,
-                &mut err as *mut _ as *mut _,
-                &mut len,
-            )
-        };
-        if rc < 0 {
-            return Err(io::Error::last_os_error());
+        match so_error(self.fd)? {
+            0 => {
+                self.state = ConnState::Connected;
+                self.close_racing();
+                self.fill_endpoints();
+                Ok(())
+            }
+            err => {
+                self.last_error = Some(err);
+                // Fall back to a raced attempt, then to the next address
+                if let Some(fd) = self.racing.pop() {
+                    unsafe { libc::close(self.fd) };
+                    self.fd = fd;
+                    return Ok(());
+                }
+                if !self.pending.is_empty() && self.start_next().is_ok() {
+                    return Ok(());
+                }
+                self.state = ConnState::Error;
+                Err(io::Error::from_raw_os_error(err))
+            }
         }
-        if err == 0 {
-            self.state = ConnState::Connected;
-            self.fill_endpoints();
-            Ok(())
-        } else {
-            self.state = ConnState::Error;
-            self.last_error = Some(err);
-            Err(io::Error::from_raw_os_error(err))
+    }
+
+    /// Drive connect_any() while Connecting: adopt a raced attempt that
+    /// finished, and start racing the next address once ATTEMPT_DELAY has
+    /// passed. Returns true when the socket just became Connected
+    pub fn tick(&mut self) -> bool {
+        if self.state != ConnState::Connecting {
+            return false;
+        }
+        let mut i = 0;
+        while i < self.racing.len() {
+            let fd = self.racing[i];
+            if !poll_writable(fd) {
+                i += 1;
+                continue;
+            }
+            self.racing.remove(i);
+            if so_error(fd).unwrap_or(-1) == 0 {
+                unsafe { libc::close(self.fd) };
+                self.fd = fd;
+                self.state = ConnState::Connected;
+                self.close_racing();
+                self.fill_endpoints();
+                return true;
+            }
+            unsafe { libc::close(fd) };
+        }
+        let waited = self.started.is_some_and(|t| t.elapsed() >= ATTEMPT_DELAY);
+        if waited && !self.pending.is_empty() {
+            // Keep the slow attempt running alongside the next one
+            self.racing.push(self.fd);
+            self.fd = -1;
+            self.family = libc::AF_UNSPEC; // Force connect() to open a new fd
+            self.state = ConnState::Idle;
+            if self.start_next().is_err() {
+                // Nothing else could start: go back to waiting on the race
+                let fd = self.racing.pop().unwrap();
+                if self.fd >= 0 {
+                    unsafe { libc::close(self.fd) };
+                }
+                self.fd = fd;
+                self.state = ConnState::Connecting;
+            }
+        }
+        self.state == ConnState::Connected
+    }
+
+    fn close_racing(&mut self) {
+        for fd in self.racing.drain(..) {
+            unsafe { libc::close(fd) };
         }
     }
 
     fn fill_endpoints(&mut self) {
-        // local
-        let mut ss: libc::sockaddr_in = unsafe { mem::zeroed() };
-        let mut len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        let mut ss: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
         let rc = unsafe {
             libc::getsockname(self.fd, &mut ss as *mut _ as *mut libc::sockaddr, &mut len)
         };
         if rc == 0 {
-            let port = u16::from_be(ss.sin_port);
-            let ip = Ipv4Addr::from(u32::from_be(ss.sin_addr.s_addr));
-            self.local = Some(SocketAddr::new(IpAddr::V4(ip), port));
+            self.local = from_sockaddr(&ss);
         }
-        let mut ps: libc::sockaddr_in = unsafe { mem::zeroed() };
-        let mut len2 = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        let mut ps: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len2 = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
         let rc2 = unsafe {
             libc::getpeername(self.fd, &mut ps as *mut _ as *mut libc::sockaddr, &mut len2)
         };
         if rc2 == 0 {
-            let port = u16::from_be(ps.sin_port);
-            let ip = Ipv4Addr::from(u32::from_be(ps.sin_addr.s_addr));
-            self.remote = Some(SocketAddr::new(IpAddr::V4(ip), port));
+            self.remote = from_sockaddr(&ps);
+        }
+    }
+}
+
+/// Resolve `host` (name, IPv4/IPv6 literal, or "[v6]") to the addresses to
+/// try, alternating families with IPv6 first (RFC 8305 section 4)
+pub fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses for {}", host),
+        ));
+    }
+    Ok(interleave_families(addrs))
+}
+
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| a.is_ipv6());
+    let mut out = Vec::with_capacity(v6.len() + v4.len());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (a, b) => out.extend(a.into_iter().chain(b)),
+        }
+    }
+    out
+}
+
+/// Split "host:port" or "[v6]:port" into host (without brackets) and port
+pub fn split_host_port(s: &str) -> Option<(&str, u16)> {
+    let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        (host, after.strip_prefix(':')?)
+    } else {
+        let (host, port) = s.rsplit_once(':')?;
+        if host.contains(':') {
+            return None; // Unbracketed IPv6 literal: the port is ambiguous
+        }
+        (host, port)
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+fn family_of(addr: &SocketAddr) -> c_int {
+    match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    }
+}
+
+fn open_fd(family: c_int) -> io::Result<RawFd> {
+    let fd = unsafe { libc::socket(family, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    set_nonblocking(fd);
+    Ok(fd)
+}
+
+fn set_nonblocking(fd: RawFd) {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+}
+
+fn so_error(fd: RawFd) -> io::Result<c_int> {
+    let mut err: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            &mut err as *mut _ as *mut _,
+            &mut len,
+        )
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(err)
+}
+
+/// Wait up to `timeout` for `fd` to take more data (EINTR counts as not yet)
+fn wait_for_room(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    let ms = timeout.as_millis().clamp(1, c_int::MAX as u128) as c_int;
+    let rc = unsafe { libc::poll(&mut pfd, 1, ms) };
+    if rc < 0 {
+        let err = io::Error::last_os_error();
+        return match err.kind() {
+            io::ErrorKind::Interrupted => Ok(true),
+            _ => Err(err),
+        };
+    }
+    Ok(rc > 0)
+}
+
+fn poll_writable(fd: RawFd) -> bool {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    let rc = unsafe { libc::poll(&mut pfd, 1, 0) };
+    rc > 0 && pfd.revents != 0
+}
+
+fn to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut ss: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(a) => {
+            let sin = unsafe { &mut *(&mut ss as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = a.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from(*a.ip()).to_be(),
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(a) => {
+            let sin6 = unsafe { &mut *(&mut ss as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = a.port().to_be();
+            sin6.sin6_flowinfo = a.flowinfo();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: a.ip().octets(),
+            };
+            sin6.sin6_scope_id = a.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (ss, len as libc::socklen_t)
+}
+
+fn from_sockaddr(ss: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match ss.ss_family as c_int {
+        libc::AF_INET => {
+            let sin = unsafe { &*(ss as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                ip,
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let sin6 = unsafe { &*(ss as *const _ as *const libc::sockaddr_in6) };
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
         }
+        _ => None,
     }
 }
 
 impl Drop for Socket {
     fn drop(&mut self) {
-        unsafe {
-            libc::close(self.fd);
+        self.close_racing();
+        if self.fd >= 0 {
+            unsafe {
+                libc::close(self.fd);
+            }
         }
     }
 }
@@ -179,6 +568,30 @@ mod tests {
         let _accepted = listener.accept().unwrap();
     }
 
+    #[test]
+    fn large_sends_are_written_whole() {
+        use std::io::Read;
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut s = Socket::new().unwrap();
+        s.connect_ipv4(Ipv4Addr::LOCALHOST, port).unwrap();
+        if s.state == ConnState::Connecting {
+            assert!(wait_writable(s.as_raw_fd(), 1000).unwrap());
+            let _ = s.on_writable();
+        }
+        let (mut peer, _) = listener.accept().unwrap();
+        // Far more than a socket buffer holds: the reader has to catch up
+        let reader = std::thread::spawn(move || {
+            let mut got = Vec::new();
+            peer.read_to_end(&mut got).unwrap();
+            got.len()
+        });
+        let data = vec![b'x'; 8 << 20];
+        assert_eq!(s.send_raw(&data).unwrap(), data.len());
+        drop(s);
+        assert_eq!(reader.join().unwrap(), data.len());
+    }
+
     #[test]
     fn connect_refused() {
         // Choose an unlikely port; bind a listener then close to ensure refusal.
@@ -195,4 +608,120 @@ mod tests {
         assert_eq!(s.state, ConnState::Error);
         assert_eq!(s.last_error.is_some(), true);
     }
+
+    #[test]
+    fn connect_ipv6_loopback() {
+        // Skip where the host has no IPv6 loopback
+        let Ok(listener) = TcpListener::bind((Ipv6Addr::LOCALHOST, 0)) else {
+            return;
+        };
+        let addr = listener.local_addr().unwrap();
+        let mut s = Socket::new().unwrap();
+        s.connect(addr).unwrap();
+        if s.state == ConnState::Connecting {
+            assert!(wait_writable(s.as_raw_fd(), 1000).unwrap());
+            let _ = s.on_writable();
+        }
+        assert_eq!(s.state, ConnState::Connected);
+        assert_eq!(s.remote, Some(addr));
+        let _accepted = listener.accept().unwrap();
+    }
+
+    #[test]
+    fn connect_any_falls_back_after_refusal() {
+        let dead = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let dead_addr = dead.local_addr().unwrap();
+        drop(dead);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut s = Socket::new().unwrap();
+        let _ = s.connect_any(vec![dead_addr, addr]);
+        for _ in 0..10 {
+            if s.state != ConnState::Connecting {
+                break;
+            }
+            if wait_writable(s.as_raw_fd(), 500).unwrap() {
+                let _ = s.on_writable();
+            }
+        }
+        assert_eq!(s.state, ConnState::Connected);
+        assert_eq!(s.remote, Some(addr));
+    }
+
+    #[test]
+    fn connect_times_out_or_cancels() {
+        let mut s = Socket::new().unwrap();
+        assert_eq!(s.connect_time_left(CONNECT_TIMEOUT), None);
+        assert!(!s.check_timeout(CONNECT_TIMEOUT));
+
+        // A connect that has hung (no SYN-ACK) for most of the limit
+        s.state = ConnState::Connecting;
+        s.began = Some(Instant::now() - Duration::from_secs(25));
+        let left = s.connect_time_left(CONNECT_TIMEOUT).unwrap();
+        assert!(left <= Duration::from_secs(5) && left > Duration::from_secs(4));
+        assert!(!s.check_timeout(CONNECT_TIMEOUT));
+        assert!(s.check_timeout(Duration::from_secs(20)));
+        assert_eq!(s.state, ConnState::Error);
+        assert_eq!(s.last_error, Some(libc::ETIMEDOUT));
+        assert_eq!(s.as_raw_fd(), -1);
+
+        let mut s = Socket::new().unwrap();
+        assert!(!s.cancel());
+        s.state = ConnState::Connecting;
+        assert!(s.cancel());
+        assert_eq!(s.state, ConnState::Idle);
+        assert_eq!(s.as_raw_fd(), -1);
+    }
+
+    #[test]
+    fn shutdown_write_lets_the_peer_answer() {
+        use std::io::{Read, Write};
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut s = Socket::new().unwrap();
+        s.connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        if s.state == ConnState::Connecting {
+            assert!(wait_writable(s.as_raw_fd(), 1000).unwrap());
+            let _ = s.on_writable();
+        }
+
+        s.send_raw(b"quit\r\n").unwrap();
+        s.shutdown_write().unwrap();
+        let mut got = Vec::new();
+        peer.read_to_end(&mut got).unwrap();
+        assert_eq!(got, b"quit\r\n");
+        peer.write_all(b"Goodbye!\r\n").unwrap();
+        drop(peer);
+
+        let mut buf = [0u8; 64];
+        let n = unsafe { libc::read(s.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+        assert_eq!(&buf[..n as usize], b"Goodbye!\r\n");
+    }
+
+    #[test]
+    fn split_host_port_forms() {
+        assert_eq!(split_host_port("mud.org:4000"), Some(("mud.org", 4000)));
+        assert_eq!(split_host_port("[::1]:23"), Some(("::1", 23)));
+        assert_eq!(split_host_port("[fe80::1]"), None);
+        assert_eq!(split_host_port("::1:23"), None); // Ambiguous without brackets
+        assert_eq!(split_host_port("host"), None);
+        assert_eq!(split_host_port(":23"), None);
+    }
+
+    #[test]
+    fn resolve_literals_and_family_order() {
+        let v6: SocketAddr = "[::1]:4000".parse().unwrap();
+        assert_eq!(resolve("[::1]", 4000).unwrap(), vec![v6]);
+        assert_eq!(resolve("::1", 4000).unwrap(), vec![v6]);
+
+        let a4: SocketAddr = "10.0.0.1:1".parse().unwrap();
+        let b4: SocketAddr = "10.0.0.2:1".parse().unwrap();
+        let a6: SocketAddr = "[2001:db8::1]:1".parse().unwrap();
+        let b6: SocketAddr = "[2001:db8::2]:1".parse().unwrap();
+        assert_eq!(
+            interleave_families(vec![a4, b4, a6, b6]),
+            vec![a6, a4, b6, b4]
+        );
+    }
 }
@@ -0,0 +1,132 @@
+// Stats - the #stats summary of a session
+//
+// Pulls together counters kept elsewhere: bytes and connect time
+// (SessionStats), MCCP byte counts (the decompressor), triggers fired and
+// commands sent (counted by the front-ends), scrollback size and the last
+// #ping. Shown in a popup by #stats and returned as JSON by the control
+// server's `stats` command.
+
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatsReport {
+    pub connected_at: Option<i64>, // Unix time of the connect, None when not connected
+    pub connected_secs: Option<u64>,
+    pub bytes_received: usize,
+    pub bytes_sent: usize,
+    pub mccp_compressed: Option<usize>, // Bytes on the wire while MCCP ran
+    pub mccp_uncompressed: Option<usize>,
+    pub lines: usize,            // Lines printed this session
+    pub scrollback_lines: usize, // Of those, lines still kept
+    pub scrollback_kb: usize,
+    pub triggers_fired: usize,
+    pub commands_sent: usize,
+    pub latency_ms: Option<u64>, // Last #ping round trip
+}
+
+/// Unix time now, in seconds
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// 3725 seconds as "1h 2m 5s"
+pub fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    match (s / 3600, s / 60 % 60, s % 60) {
+        (0, 0, secs) => format!("{}s", secs),
+        (0, mins, secs) => format!("{}m {}s", mins, secs),
+        (hours, mins, secs) => format!("{}h {}m {}s", hours, mins, secs),
+    }
+}
+
+/// 1536 as "1.5 KB"
+fn format_bytes(n: usize) -> String {
+    match n {
+        0..=1023 => format!("{} B", n),
+        1024..=1048575 => format!("{:.1} KB", n as f64 / 1024.0),
+        _ => format!("{:.1} MB", n as f64 / 1048576.0),
+    }
+}
+
+impl StatsReport {
+    /// Compressed size as a percentage of the uncompressed text
+    pub fn mccp_ratio(&self) -> Option<usize> {
+        let (comp, uncomp) = (self.mccp_compressed?, self.mccp_uncompressed?);
+        (comp * 100).checked_div(uncomp)
+    }
+
+    /// The popup's text, one "name: value" line each
+    pub fn text(&self) -> String {
+        let connected = match (self.connected_at, self.connected_secs) {
+            (Some(at), Some(secs)) => {
+                let since = chrono::DateTime::from_timestamp(at, 0)
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                format!("{} ({})", since, format_duration(Duration::from_secs(secs)))
+            }
+            _ => "not connected".to_string(),
+        };
+        let compression = match (self.mccp_ratio(), self.mccp_compressed) {
+            (Some(ratio), Some(comp)) => format!("{}% ({} on the wire)", ratio, format_bytes(comp)),
+            _ => "off".to_string(),
+        };
+        let latency = match self.latency_ms {
+            Some(ms) => format!("{} ms", ms),
+            None => "not measured (#ping)".to_string(),
+        };
+        [
+            format!("Connected:      {}", connected),
+            format!("Received:       {}", format_bytes(self.bytes_received)),
+            format!("Sent:           {}", format_bytes(self.bytes_sent)),
+            format!("Compression:    {}", compression),
+            format!(
+                "Scrollback:     {} lines ({} kept, {} KB)",
+                self.lines, self.scrollback_lines, self.scrollback_kb
+            ),
+            format!("Triggers fired: {}", self.triggers_fired),
+            format!("Commands sent:  {}", self.commands_sent),
+            format!("Latency:        {}", latency),
+        ]
+        .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_text_and_json() {
+        let report = StatsReport {
+            bytes_received: 1536,
+            bytes_sent: 20,
+            mccp_compressed: Some(300),
+            mccp_uncompressed: Some(1200),
+            lines: 40,
+            scrollback_lines: 40,
+            scrollback_kb: 12,
+            triggers_fired: 3,
+            commands_sent: 7,
+            latency_ms: Some(85),
+            ..Default::default()
+        };
+        let text = report.text();
+        assert!(text.starts_with("Connected:      not connected\nReceived:       1.5 KB\n"));
+        assert!(text.contains("Compression:    25% (300 B on the wire)"));
+        assert!(text.contains("Triggers fired: 3\nCommands sent:  7\nLatency:        85 ms"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["triggers_fired"], 3);
+        assert_eq!(json["connected_at"], serde_json::Value::Null);
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1h 2m 5s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m 5s");
+    }
+}
@@ -4,8 +4,160 @@
 //
 // C++ pattern: StatusLine : public Window
 // Rust pattern: StatusLine owns Window
+//
+// The line is segmented. Transient messages (set_text) take the
+// left side; named fields (connection state, send queue, clock, MCCP
+// ratio, traffic, plugin fields) are right-aligned and survive later messages.
+// Timed messages (post) show over the set_text message until they expire,
+// most serious first.
 
+use crate::scrollback::Attrib;
+use crate::session::MsgLevel;
 use crate::window::Window;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Separator drawn between fields
+const FIELD_SEPARATOR: &str = " | ";
+
+/// How long an info message stays up; warnings get twice that, errors three times
+pub const MESSAGE_TIME: Duration = Duration::from_secs(3);
+/// Timed messages waiting beyond this drop the least serious, oldest first
+pub const MAX_MESSAGES: usize = 8;
+
+/// Priorities of the built-in fields (higher = dropped last when narrow)
+pub const PRIORITY_CONNECTION: i32 = 100;
+pub const PRIORITY_ACTIVITY: i32 = 70;
+pub const PRIORITY_QUEUE: i32 = 60;
+pub const PRIORITY_CLOCK: i32 = 50;
+pub const PRIORITY_IDLE: i32 = 40;
+pub const PRIORITY_MCCP: i32 = 30;
+pub const PRIORITY_NET: i32 = 20;
+
+/// One named segment of the status line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusField {
+    pub name: String,
+    pub text: String,
+    pub priority: i32,
+}
+
+/// Named status fields, shared by the TTY StatusLine and headless engine
+#[derive(Debug, Clone, Default)]
+pub struct StatusFields {
+    fields: Vec<StatusField>, // Insertion order = display order
+}
+
+impl StatusFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or update a field; empty text removes it
+    /// Returns true if anything changed
+    pub fn set(&mut self, name: &str, text: &str, priority: i32) -> bool {
+        if text.is_empty() {
+            return self.remove(name);
+        }
+        match self.fields.iter_mut().find(|f| f.name == name) {
+            Some(f) if f.text == text && f.priority == priority => false,
+            Some(f) => {
+                f.text = text.to_string();
+                f.priority = priority;
+                true
+            }
+            None => {
+                self.fields.push(StatusField {
+                    name: name.to_string(),
+                    text: text.to_string(),
+                    priority,
+                });
+                true
+            }
+        }
+    }
+
+    /// Remove a field; returns false if it wasn't set
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.fields.len();
+        self.fields.retain(|f| f.name != name);
+        self.fields.len() != before
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StatusField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StatusField> {
+        self.fields.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Render fields into at most `width` columns
+    /// Lowest-priority fields are dropped first; survivors keep display order
+    pub fn layout(&self, width: usize) -> String {
+        let mut visible: Vec<&StatusField> = self.fields.iter().collect();
+        loop {
+            let text = visible
+                .iter()
+                .map(|f| f.text.as_str())
+                .collect::<Vec<_>>()
+                .join(FIELD_SEPARATOR);
+            if text.len() <= width {
+                return text;
+            }
+            // Drop the lowest priority (latest added on ties)
+            let victim = visible
+                .iter()
+                .enumerate()
+                .min_by_key(|(i, f)| (f.priority, usize::MAX - i))
+                .map(|(i, _)| i);
+            match victim {
+                Some(i) => {
+                    visible.remove(i);
+                }
+                None => return String::new(),
+            }
+        }
+    }
+}
+
+/// Idle field text: "Idle 5m", "Idle 1h05m"; empty under a minute
+pub fn idle_text(idle: std::time::Duration) -> String {
+    let minutes = idle.as_secs() / 60;
+    match minutes {
+        0 => String::new(),
+        1..=59 => format!("Idle {}m", minutes),
+        _ => format!("Idle {}h{:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+/// Parse field updates queued by the interpreter `status` builtin:
+/// one "name\ttext\tpriority" record per line (priority optional)
+pub fn parse_field_updates(s: &str) -> Vec<(String, String, i32)> {
+    s.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let name = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let text = parts.next().unwrap_or("");
+            let priority = parts
+                .next()
+                .and_then(|p| p.trim().parse().ok())
+                .unwrap_or(0);
+            Some((name.to_string(), text.to_string(), priority))
+        })
+        .collect()
+}
 
 /// StatusLine displays status messages at top of screen
 /// Ported from C++ StatusLine.cc:10-59
@@ -13,6 +165,17 @@ pub struct StatusLine {
     pub win: Box<Window>,
     text: String,
     color: u8,
+    fields: StatusFields,
+    flash_until: Option<Instant>, // Visual bell: drawn inverted until then
+    messages: Vec<Message>,       // Timed messages, in the order posted
+    message_time: Duration,
+}
+
+/// A timed message; its clock starts on the first tick() that shows it
+struct Message {
+    text: String,
+    level: MsgLevel,
+    until: Option<Instant>,
 }
 
 impl StatusLine {
@@ -26,9 +189,98 @@ impl StatusLine {
             win,
             text: String::new(),
             color,
+            fields: StatusFields::new(),
+            flash_until: None,
+            messages: Vec::new(),
+            message_time: MESSAGE_TIME,
+        }
+    }
+
+    /// Visual bell: draw the line inverted for `duration`
+    pub fn flash(&mut self, now: Instant, duration: Duration) {
+        self.flash_until = Some(now + duration);
+        self.redraw();
+        self.win.dirty = true;
+    }
+
+    /// End an expired flash or timed message; returns the time until the
+    /// next one ends, if any is showing
+    pub fn tick(&mut self, now: Instant) -> Option<Duration> {
+        let mut changed = false;
+        if self.flash_until.is_some_and(|until| now >= until) {
+            self.flash_until = None;
+            changed = true;
+        }
+        let before = self.messages.len();
+        self.messages
+            .retain(|m| m.until.is_none_or(|until| now < until));
+        changed |= self.messages.len() != before;
+        let time = self.message_time;
+        if let Some(m) = self.shown_message_mut() {
+            m.until.get_or_insert(now + time * (m.level as u32 + 1));
+        }
+        if changed {
+            self.redraw();
+            self.win.dirty = true;
+        }
+        let message_left = self
+            .shown_message()
+            .and_then(|m| m.until)
+            .map(|until| until - now);
+        let flash_left = self.flash_until.map(|until| until - now);
+        flash_left.into_iter().chain(message_left).min()
+    }
+
+    /// Show `s` for a while over the set_text message, then go back to it.
+    /// Errors outlast warnings, which outlast info, and a less serious
+    /// message waits its turn.
+    pub fn post<S: Into<String>>(&mut self, s: S, level: MsgLevel) {
+        if self.messages.len() >= MAX_MESSAGES {
+            if let Some(i) = (0..self.messages.len()).min_by_key(|&i| self.messages[i].level) {
+                self.messages.remove(i);
+            }
+        }
+        self.messages.push(Message {
+            text: s.into(),
+            level,
+            until: None,
+        });
+        self.redraw();
+        self.win.dirty = true;
+    }
+
+    /// How long an info message stays up (config `statustime`)
+    pub fn set_message_time(&mut self, time: Duration) {
+        self.message_time = time;
+    }
+
+    /// The most serious timed message, earliest posted first
+    fn shown_message(&self) -> Option<&Message> {
+        self.messages.iter().rev().max_by_key(|m| m.level)
+    }
+
+    fn shown_message_mut(&mut self) -> Option<&mut Message> {
+        self.messages.iter_mut().rev().max_by_key(|m| m.level)
+    }
+
+    /// Color the line is drawn in: fg and bg swapped while flashing
+    fn shown_color(&self) -> u8 {
+        match self.flash_until {
+            Some(_) => {
+                (self.color & 0x80) | ((self.color & 0x07) << 4) | ((self.color >> 4) & 0x07)
+            }
+            None => self.color,
         }
     }
 
+    /// Change the line's color (#color status)
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+        self.win.color = color;
+        self.redraw();
+        self.win.dirty = true;
+    }
+
     /// Set status text and mark dirty (C++ StatusLine.cc:40-48)
     pub fn set_text<S: Into<String>>(&mut self, s: S) {
         self.text = s.into();
@@ -36,19 +288,76 @@ impl StatusLine {
         self.win.dirty = true;
     }
 
-    /// Redraw window: fill canvas with text (C++ StatusLine.cc:50-59)
+    /// Text of the left segment: a timed message while one is up, else
+    /// the set_text message
+    pub fn text(&self) -> &str {
+        self.shown_message().map_or(&self.text, |m| &m.text)
+    }
+
+    /// Set a named right-hand field; empty text removes it
+    /// Only redraws when the field actually changed
+    pub fn set_field(&mut self, name: &str, text: &str, priority: i32) {
+        if self.fields.set(name, text, priority) {
+            self.redraw();
+            self.win.dirty = true;
+        }
+    }
+
+    /// Remove a named field
+    pub fn clear_field(&mut self, name: &str) {
+        if self.fields.remove(name) {
+            self.redraw();
+            self.win.dirty = true;
+        }
+    }
+
+    /// Replace all fields at once (headless engine keeps its own StatusFields)
+    pub fn set_fields(&mut self, fields: &StatusFields) {
+        let changed = fields.len() != self.fields.len()
+            || fields.iter().zip(self.fields.iter()).any(|(a, b)| a != b);
+        if changed {
+            self.fields = fields.clone();
+            self.redraw();
+            self.win.dirty = true;
+        }
+    }
+
+    pub fn fields(&self) -> &StatusFields {
+        &self.fields
+    }
+
+    /// Redraw window: message on the left, fields right-aligned
+    /// (C++ StatusLine.cc:50-59 for the message part)
     pub fn redraw(&mut self) {
         let width = self.win.width;
+        let color = self.shown_color() as Attrib;
 
         // Fill with spaces in status color
-        let blank = ((self.color as u16) << 8) | (b' ' as u16);
+        let blank = (color << 8) | (b' ' as Attrib);
         for a in &mut self.win.canvas {
             *a = blank;
         }
 
+        // Fields may use whatever the message doesn't need, but always at
+        // least half the line so a long message can't hide them
+        let text = self.text().to_string();
+        let reserved = text.len().min(width / 2);
+        let fields = self.fields.layout(width.saturating_sub(reserved + 1));
+        let msg_width = width.saturating_sub(if fields.is_empty() {
+            0
+        } else {
+            fields.len() + 1
+        });
+
         // Write message text
-        for (i, b) in self.text.as_bytes().iter().enumerate().take(width) {
-            self.win.canvas[i] = ((self.color as u16) << 8) | (*b as u16);
+        for (i, b) in text.as_bytes().iter().enumerate().take(msg_width) {
+            self.win.canvas[i] = (color << 8) | (*b as Attrib);
+        }
+
+        // Write fields flush right
+        let start = width - fields.len();
+        for (i, b) in fields.as_bytes().iter().enumerate() {
+            self.win.canvas[start + i] = (color << 8) | (*b as Attrib);
         }
     }
 
@@ -63,6 +372,14 @@ mod tests {
     use super::*;
     use std::ptr;
 
+    fn row(sl: &StatusLine) -> String {
+        sl.win
+            .canvas
+            .iter()
+            .map(|a| (a & 0xFF) as u8 as char)
+            .collect()
+    }
+
     #[test]
     fn set_and_render() {
         let mut sl = StatusLine::new(ptr::null_mut(), 8, 0x07);
@@ -70,4 +387,127 @@ mod tests {
         let text: Vec<u8> = sl.win.canvas.iter().map(|a| (a & 0xFF) as u8).collect();
         assert_eq!(&text[0..5], b"READY");
     }
+
+    #[test]
+    fn fields_survive_messages() {
+        let mut sl = StatusLine::new(ptr::null_mut(), 40, 0x07);
+        sl.set_field("conn", "Connected", PRIORITY_CONNECTION);
+        sl.set_field("clock", "12:34", PRIORITY_CLOCK);
+        sl.set_text("Trigger fired");
+        assert_eq!(
+            row(&sl),
+            format!("{:<17}{:>23}", "Trigger fired", "Connected | 12:34")
+        );
+
+        sl.set_text("Something else");
+        assert!(row(&sl).ends_with("Connected | 12:34"));
+
+        sl.clear_field("clock");
+        assert!(row(&sl).ends_with(" Connected"));
+    }
+
+    #[test]
+    fn flash_inverts_until_tick_expires() {
+        let mut sl = StatusLine::new(ptr::null_mut(), 8, 0x17);
+        let now = Instant::now();
+        sl.flash(now, Duration::from_millis(100));
+        assert_eq!(sl.win.canvas[0] >> 8, 0x71);
+        assert_eq!(sl.tick(now), Some(Duration::from_millis(100)));
+        assert_eq!(sl.tick(now + Duration::from_millis(100)), None);
+        assert_eq!(sl.win.canvas[0] >> 8, 0x17);
+    }
+
+    #[test]
+    fn timed_messages_expire_by_level() {
+        let mut sl = StatusLine::new(ptr::null_mut(), 20, 0x07);
+        sl.set_text("Ready");
+        let now = Instant::now();
+        let secs = |n: u64| now + MESSAGE_TIME * n as u32;
+
+        sl.post("Bad thing", MsgLevel::Error);
+        sl.post("Saved", MsgLevel::Info);
+        assert_eq!(sl.tick(now), Some(MESSAGE_TIME * 3));
+        assert!(row(&sl).starts_with("Bad thing"));
+
+        // The error outlasts the info, which then gets its own turn
+        assert_eq!(sl.tick(secs(2)), Some(MESSAGE_TIME));
+        assert_eq!(sl.tick(secs(3)), Some(MESSAGE_TIME));
+        assert!(row(&sl).starts_with("Saved"));
+        assert_eq!(sl.tick(secs(4)), None);
+        assert!(row(&sl).starts_with("Ready"));
+
+        // A later error takes over from an info already showing
+        sl.post("Saved", MsgLevel::Info);
+        sl.tick(secs(4));
+        sl.post("Oops", MsgLevel::Warning);
+        sl.tick(secs(4));
+        assert_eq!(sl.text(), "Oops");
+        sl.tick(secs(6));
+        assert_eq!(sl.text(), "Ready");
+    }
+
+    #[test]
+    fn full_queue_drops_least_serious() {
+        let mut sl = StatusLine::new(ptr::null_mut(), 20, 0x07);
+        sl.post("Keep me", MsgLevel::Warning);
+        for n in 0..MAX_MESSAGES {
+            sl.post(format!("Info {}", n), MsgLevel::Info);
+        }
+        assert_eq!(sl.messages.len(), MAX_MESSAGES);
+        assert_eq!(sl.text(), "Keep me");
+        assert_eq!(sl.messages[1].text, "Info 1");
+    }
+
+    #[test]
+    fn idle_field_text() {
+        use std::time::Duration;
+        assert_eq!(idle_text(Duration::from_secs(59)), "");
+        assert_eq!(idle_text(Duration::from_secs(5 * 60 + 30)), "Idle 5m");
+        assert_eq!(idle_text(Duration::from_secs(65 * 60)), "Idle 1h05m");
+    }
+
+    #[test]
+    fn long_message_is_truncated_before_fields() {
+        let mut sl = StatusLine::new(ptr::null_mut(), 20, 0x07);
+        sl.set_field("conn", "Online", PRIORITY_CONNECTION);
+        sl.set_text("a very long message that does not fit");
+        assert_eq!(row(&sl), "a very long m Online");
+    }
+
+    #[test]
+    fn low_priority_fields_dropped_first() {
+        let mut f = StatusFields::new();
+        f.set("conn", "Connected", 100);
+        f.set("hp", "HP 50/50", 10);
+        f.set("clock", "12:34", 50);
+        assert_eq!(f.layout(100), "Connected | HP 50/50 | 12:34");
+        assert_eq!(f.layout(20), "Connected | 12:34");
+        assert_eq!(f.layout(9), "Connected");
+        assert_eq!(f.layout(3), "");
+    }
+
+    #[test]
+    fn set_reports_changes_and_empty_removes() {
+        let mut f = StatusFields::new();
+        assert!(f.set("hp", "HP 10", 5));
+        assert!(!f.set("hp", "HP 10", 5));
+        assert!(f.set("hp", "HP 9", 5));
+        assert_eq!(f.get("hp").unwrap().text, "HP 9");
+        assert!(f.set("hp", "", 5));
+        assert!(f.is_empty());
+        assert!(!f.remove("hp"));
+    }
+
+    #[test]
+    fn parse_interpreter_updates() {
+        let updates = parse_field_updates("hp\tHP 10/20\t5\nmana\tMana 3\n\t\nbad");
+        assert_eq!(
+            updates,
+            vec![
+                ("hp".to_string(), "HP 10/20".to_string(), 5),
+                ("mana".to_string(), "Mana 3".to_string(), 0),
+                ("bad".to_string(), String::new(), 0),
+            ]
+        );
+    }
 }
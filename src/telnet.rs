@@ -1,3 +1,18 @@
+// Telnet - IAC parsing and option negotiation
+//
+// Options are negotiated with the Q method (RFC 1143): each option has a
+// state for the server's side ("him": WILL/WONT, answered with DO/DONT) and
+// for ours ("us": DO/DONT, answered with WILL/WONT). Requests are only
+// answered when they change the state, so two ends can't loop. Whether an
+// option may be enabled is set per side with support(); features that need
+// to act on an option (subnegotiations, replies when it turns on) register
+// an OptionHandler. MCCP is not in the table: the compressed stream starts
+// mid-read, so the decompressor in front of the parser negotiates it.
+// Refusals and ignored commands are kept as diagnostics for the session to
+// show.
+
+use crate::net_debug::{command_name, option_name};
+
 pub mod telnet {
     pub const IAC: u8 = 255;
     pub const DONT: u8 = 254;
@@ -6,31 +21,340 @@ pub mod telnet {
     pub const WILL: u8 = 251;
     pub const SB: u8 = 250;
     pub const GA: u8 = 249;
+    pub const AYT: u8 = 246;
+    pub const NOP: u8 = 241;
     pub const SE: u8 = 240;
+    pub const TTYPE_IS: u8 = 0;
+    pub const TTYPE_SEND: u8 = 1;
     pub const EOR: u8 = 239;
+    pub const TELOPT_ECHO: u8 = 1;
+    pub const TELOPT_TTYPE: u8 = 24;
     pub const TELOPT_EOR: u8 = 25;
+    pub const TELOPT_MSP: u8 = crate::msp::TELOPT_MSP;
+}
+
+/// Which end of the connection an option is enabled on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Him, // The server (WILL/WONT from it, DO/DONT from us)
+    Us,  // The client (DO/DONT from the server, WILL/WONT from us)
+}
+
+/// RFC 1143 option state; the flag is the "opposite" queue bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QState {
+    #[default]
+    No,
+    Yes,
+    WantNo(bool),
+    WantYes(bool),
+}
+
+/// Plug-in for one telnet option
+pub trait OptionHandler: Send {
+    /// The option turned on or off on one side; bytes for the server go in `out`
+    fn changed(&mut self, _side: Side, _on: bool, _out: &mut Vec<u8>) {}
+    /// IAC SB <option> data IAC SE arrived (IAC IAC already unescaped)
+    fn subnegotiation(&mut self, _data: &[u8], _out: &mut Vec<u8>) {}
+}
+
+/// Answers TTYPE SEND with the client name (RFC 1091)
+pub struct TerminalType(pub String);
+
+impl OptionHandler for TerminalType {
+    fn subnegotiation(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        use telnet::*;
+        if data.first() == Some(&TTYPE_SEND) {
+            let mut payload = vec![TTYPE_IS];
+            payload.extend_from_slice(self.0.as_bytes());
+            out.extend(subnegotiation(TELOPT_TTYPE, &payload));
+        }
+    }
+}
+
+/// Data with every IAC byte doubled, so the server reads it as data
+pub fn escape_iac(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        out.push(b);
+        if b == telnet::IAC {
+            out.push(telnet::IAC);
+        }
+    }
+    out
+}
+
+/// IAC SB option payload IAC SE, with IAC bytes in the payload doubled
+pub fn subnegotiation(option: u8, payload: &[u8]) -> Vec<u8> {
+    use telnet::*;
+    let mut out = vec![IAC, SB, option];
+    out.extend(escape_iac(payload));
+    out.extend_from_slice(&[IAC, SE]);
+    out
+}
+
+#[derive(Default)]
+struct TelOption {
+    him: QState,
+    us: QState,
+    accept_him: bool, // Agree when the server offers WILL
+    accept_us: bool,  // Agree when the server asks DO
+    handler: Option<Box<dyn OptionHandler>>,
+}
+
+impl TelOption {
+    fn state(&mut self, side: Side) -> &mut QState {
+        match side {
+            Side::Him => &mut self.him,
+            Side::Us => &mut self.us,
+        }
+    }
 }
 
+/// Longest subnegotiation kept; a longer one (or one never ended by SE)
+/// stops growing here and is dropped with a diagnostic.
+pub const MAX_SUBNEG: usize = 256 * 1024;
+
 pub struct TelnetParser {
     iac_seen: bool,
     cmd_pending: Option<u8>,
     sb_active: bool,
+    sb_buf: Vec<u8>,   // Option byte then data of the subnegotiation being read
+    sb_dropped: usize, // Bytes past MAX_SUBNEG, not kept
     app_out: Vec<u8>,
     responses: Vec<u8>,
     prompt_count: usize,
+    prompt_marks: Vec<usize>, // app_out offsets where each GA/EOR arrived
+    options: Vec<TelOption>,  // Indexed by option number
+    echo_change: Option<bool>,
+    diagnostics: Vec<String>, // Refusals and ignored commands, until taken
 }
 
 impl TelnetParser {
     pub fn new() -> Self {
-        Self {
+        use telnet::*;
+        let mut parser = Self {
             iac_seen: false,
             cmd_pending: None,
             sb_active: false,
+            sb_buf: Vec::new(),
+            sb_dropped: 0,
             app_out: Vec::new(),
             responses: Vec::new(),
             prompt_count: 0,
+            prompt_marks: Vec::new(),
+            options: (0..256).map(|_| TelOption::default()).collect(),
+            echo_change: None,
+            diagnostics: Vec::new(),
+        };
+        // Prompts marked with EOR; server echo while typing passwords
+        parser.support(TELOPT_EOR, Side::Him, true);
+        parser.support(TELOPT_ECHO, Side::Him, true);
+        parser.support(TELOPT_TTYPE, Side::Us, true);
+        parser.register(TELOPT_TTYPE, Box::new(TerminalType("okros".to_string())));
+        parser
+    }
+
+    /// Allow (or refuse) enabling an option on one side when the server asks
+    pub fn support(&mut self, option: u8, side: Side, on: bool) {
+        let opt = &mut self.options[option as usize];
+        match side {
+            Side::Him => opt.accept_him = on,
+            Side::Us => opt.accept_us = on,
+        }
+    }
+
+    /// Handle an option's state changes and subnegotiations
+    pub fn register(&mut self, option: u8, handler: Box<dyn OptionHandler>) {
+        self.options[option as usize].handler = Some(handler);
+    }
+
+    /// Whether an option is currently on for a side
+    pub fn enabled(&self, option: u8, side: Side) -> bool {
+        let opt = &self.options[option as usize];
+        match side {
+            Side::Him => opt.him == QState::Yes,
+            Side::Us => opt.us == QState::Yes,
+        }
+    }
+
+    pub fn state(&self, option: u8, side: Side) -> QState {
+        let opt = &self.options[option as usize];
+        match side {
+            Side::Him => opt.him,
+            Side::Us => opt.us,
+        }
+    }
+
+    /// Ask to turn an option on or off (RFC 1143 section 7, "we want")
+    pub fn request(&mut self, option: u8, side: Side, on: bool) {
+        use QState::*;
+        let opt = &mut self.options[option as usize];
+        let state = opt.state(side);
+        let before = *state;
+        let send = match (before, on) {
+            (No, true) => {
+                *state = WantYes(false);
+                true
+            }
+            (Yes, false) => {
+                *state = WantNo(false);
+                true
+            }
+            (WantNo(false), true) => {
+                *state = WantNo(true);
+                false
+            }
+            (WantNo(true), false) => {
+                *state = WantNo(false);
+                false
+            }
+            (WantYes(false), false) => {
+                *state = WantYes(true);
+                false
+            }
+            (WantYes(true), true) => {
+                *state = WantYes(false);
+                false
+            }
+            _ => false,
+        };
+        if send {
+            self.send_verb(option, side, on);
+        }
+        self.notify(option, side, before);
+    }
+
+    /// A WILL/WONT (him) or DO/DONT (us) arrived (RFC 1143 section 7)
+    fn receive(&mut self, option: u8, side: Side, on: bool) {
+        use QState::*;
+        let opt = &mut self.options[option as usize];
+        let accept = match side {
+            Side::Him => opt.accept_him,
+            Side::Us => opt.accept_us,
+        };
+        let state = opt.state(side);
+        let before = *state;
+        let mut refused = None;
+        // Reply: Some(true) agrees/asks on, Some(false) refuses/asks off
+        let reply = match (before, on) {
+            (No, true) if accept => {
+                *state = Yes;
+                Some(true)
+            }
+            (No, true) => {
+                let verb = if side == Side::Him { "WILL" } else { "DO" };
+                refused = Some(format!("refused {} {}", verb, option_name(option)));
+                Some(false)
+            }
+            (Yes, false) => {
+                *state = No;
+                Some(false)
+            }
+            (WantNo(false), _) => {
+                // WILL answering our DONT is an error; either way it's off
+                *state = No;
+                None
+            }
+            (WantNo(true), true) => {
+                *state = Yes;
+                None
+            }
+            (WantNo(true), false) => {
+                *state = WantYes(false);
+                Some(true)
+            }
+            (WantYes(false), true) => {
+                *state = Yes;
+                None
+            }
+            (WantYes(true), true) => {
+                *state = WantNo(false);
+                Some(false)
+            }
+            (WantYes(_), false) => {
+                *state = No;
+                let verb = if side == Side::Him { "DO" } else { "WILL" };
+                refused = Some(format!("server refused {} {}", verb, option_name(option)));
+                None
+            }
+            (Yes, true) | (No, false) => None,
+        };
+        if let Some(text) = refused {
+            self.diagnose(text);
+        }
+        if let Some(on) = reply {
+            self.send_verb(option, side, on);
+        }
+        self.notify(option, side, before);
+    }
+
+    fn send_verb(&mut self, option: u8, side: Side, on: bool) {
+        use telnet::*;
+        let verb = match (side, on) {
+            (Side::Him, true) => DO,
+            (Side::Him, false) => DONT,
+            (Side::Us, true) => WILL,
+            (Side::Us, false) => WONT,
+        };
+        self.responses.extend_from_slice(&[IAC, verb, option]);
+    }
+
+    /// Tell the handler (and the echo watcher) if the option turned on/off
+    fn notify(&mut self, option: u8, side: Side, before: QState) {
+        let opt = &mut self.options[option as usize];
+        let now = *opt.state(side) == QState::Yes;
+        if now == (before == QState::Yes) {
+            return;
+        }
+        if let Some(handler) = opt.handler.as_mut() {
+            handler.changed(side, now, &mut self.responses);
+        }
+        if option == telnet::TELOPT_ECHO && side == Side::Him {
+            self.echo_change = Some(now);
+        }
+    }
+
+    fn sb_push(&mut self, b: u8) {
+        if self.sb_buf.len() < MAX_SUBNEG {
+            self.sb_buf.push(b);
+        } else {
+            self.sb_dropped += 1;
         }
     }
+
+    fn end_subnegotiation(&mut self) {
+        let Some((&option, data)) = self.sb_buf.split_first() else {
+            return;
+        };
+        if self.sb_dropped > 0 {
+            // Cut short, so not worth handing on
+            self.diagnostics.push(format!(
+                "Telnet: dropped subnegotiation for {} ({} bytes, limit {} KiB)",
+                option_name(option),
+                data.len() + self.sb_dropped,
+                MAX_SUBNEG / 1024
+            ));
+            self.sb_buf.clear();
+            self.sb_dropped = 0;
+            return;
+        }
+        match self.options[option as usize].handler.as_mut() {
+            Some(handler) => handler.subnegotiation(data, &mut self.responses),
+            None => self.diagnostics.push(format!(
+                "Telnet: ignored subnegotiation for {} ({} bytes)",
+                option_name(option),
+                data.len()
+            )),
+        }
+        self.sb_buf.clear();
+    }
+
+    /// Note something the server sent that we refused or ignored
+    fn diagnose(&mut self, text: String) {
+        self.diagnostics.push(format!("Telnet: {}", text));
+    }
+
     pub fn feed(&mut self, chunk: &[u8]) {
         use telnet::*;
         let mut i = 0;
@@ -41,15 +365,16 @@ impl TelnetParser {
                 if !self.iac_seen {
                     if b == IAC {
                         self.iac_seen = true;
+                    } else {
+                        self.sb_push(b);
                     }
                 } else {
+                    self.iac_seen = false;
                     if b == SE {
                         self.sb_active = false;
-                        self.iac_seen = false;
+                        self.end_subnegotiation();
                     } else if b == IAC {
-                        self.iac_seen = false;
-                    } else {
-                        self.iac_seen = false;
+                        self.sb_push(IAC);
                     }
                 }
                 continue;
@@ -60,21 +385,30 @@ impl TelnetParser {
                     IAC => self.app_out.push(IAC),
                     GA | EOR => {
                         self.prompt_count += 1;
+                        self.prompt_marks.push(self.app_out.len());
                     }
                     SB => {
                         self.sb_active = true;
+                        self.sb_buf.clear();
+                        self.sb_dropped = 0;
                     }
                     DO | DONT | WILL | WONT => {
                         self.cmd_pending = Some(b);
                     }
-                    _ => {}
+                    NOP => {}
+                    _ => {
+                        let name = command_name(b).map_or_else(|| b.to_string(), str::to_string);
+                        self.diagnose(format!("ignored IAC {}", name));
+                    }
                 }
                 continue;
             }
             if let Some(cmd) = self.cmd_pending.take() {
-                // process option byte b
-                if cmd == WILL && b == TELOPT_EOR {
-                    self.responses.extend_from_slice(&[IAC, DO, b]);
+                match cmd {
+                    WILL => self.receive(b, Side::Him, true),
+                    WONT => self.receive(b, Side::Him, false),
+                    DO => self.receive(b, Side::Us, true),
+                    _ => self.receive(b, Side::Us, false),
                 }
                 continue;
             }
@@ -85,12 +419,32 @@ impl TelnetParser {
             self.app_out.push(b);
         }
     }
+    /// Where in the next take_app_out() each prompt ended
+    pub fn take_prompt_marks(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.prompt_marks)
+    }
     pub fn take_app_out(&mut self) -> Vec<u8> {
         std::mem::take(&mut self.app_out)
     }
     pub fn take_responses(&mut self) -> Vec<u8> {
         std::mem::take(&mut self.responses)
     }
+    /// True while the server echoes input (local echo should be off)
+    pub fn remote_echo(&self) -> bool {
+        self.enabled(telnet::TELOPT_ECHO, Side::Him)
+    }
+    /// New remote echo state, if it changed since the last call
+    pub fn take_echo_change(&mut self) -> Option<bool> {
+        self.echo_change.take()
+    }
+    /// Negotiations refused and commands ignored since the last call
+    pub fn take_diagnostics(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.diagnostics)
+    }
+    /// Accept WILL MSP (otherwise refuse it)
+    pub fn set_msp(&mut self, on: bool) {
+        self.support(telnet::TELOPT_MSP, Side::Him, on);
+    }
     pub fn drain_prompt_events(&mut self) -> usize {
         let n = self.prompt_count;
         self.prompt_count = 0;
@@ -103,6 +457,14 @@ mod tests {
     use super::*;
     use telnet::*;
     #[test]
+    fn escapes_iac_in_raw_data() {
+        assert_eq!(escape_iac(&[1, IAC, 2]), vec![1, IAC, IAC, 2]);
+        assert_eq!(
+            subnegotiation(TELOPT_TTYPE, &[0, IAC]),
+            vec![IAC, SB, TELOPT_TTYPE, 0, IAC, IAC, IAC, SE]
+        );
+    }
+    #[test]
     fn plain_text_passthrough() {
         let mut p = TelnetParser::new();
         p.feed(b"hello");
@@ -124,11 +486,168 @@ mod tests {
         assert_eq!(p.take_responses(), vec![IAC, DO, TELOPT_EOR]);
     }
     #[test]
-    fn do_and_wont_ignored() {
+    fn echo_negotiation_tracks_state() {
+        let mut p = TelnetParser::new();
+        p.feed(&[IAC, WILL, TELOPT_ECHO]);
+        assert_eq!(p.take_responses(), vec![IAC, DO, TELOPT_ECHO]);
+        assert_eq!(p.take_echo_change(), Some(true));
+        assert!(p.remote_echo());
+        // Repeated WILL is not acknowledged again
+        p.feed(&[IAC, WILL, TELOPT_ECHO]);
+        assert!(p.take_responses().is_empty());
+        assert_eq!(p.take_echo_change(), None);
+        p.feed(&[IAC, WONT, TELOPT_ECHO]);
+        assert_eq!(p.take_responses(), vec![IAC, DONT, TELOPT_ECHO]);
+        assert_eq!(p.take_echo_change(), Some(false));
+    }
+    #[test]
+    fn msp_negotiation_follows_setting() {
+        let mut p = TelnetParser::new();
+        p.feed(&[IAC, WILL, TELOPT_MSP]);
+        assert_eq!(p.take_responses(), vec![IAC, DONT, TELOPT_MSP]);
+        p.set_msp(true);
+        p.feed(&[IAC, WILL, TELOPT_MSP]);
+        assert_eq!(p.take_responses(), vec![IAC, DO, TELOPT_MSP]);
+    }
+    #[test]
+    fn prompt_marks_record_positions() {
+        let mut p = TelnetParser::new();
+        p.feed(b"HP> ");
+        p.feed(&[IAC, GA]);
+        p.feed(b"text\nMP> ");
+        p.feed(&[IAC, EOR]);
+        assert_eq!(p.take_prompt_marks(), vec![4, 13]);
+        assert_eq!(p.drain_prompt_events(), 2);
+        assert_eq!(p.take_app_out(), b"HP> text\nMP> ");
+    }
+    #[test]
+    fn unsupported_options_are_refused_once() {
         let mut p = TelnetParser::new();
         p.feed(&[IAC, DO, 1]);
+        assert_eq!(p.take_responses(), vec![IAC, WONT, 1]);
+        p.feed(&[IAC, WILL, 201]); // GMCP, no handler
+        assert_eq!(p.take_responses(), vec![IAC, DONT, 201]);
+        // Already off: nothing to answer
         p.feed(&[IAC, WONT, 31]);
+        p.feed(&[IAC, DONT, 31]);
+        assert!(p.take_responses().is_empty());
+        assert_eq!(
+            p.take_diagnostics(),
+            vec!["Telnet: refused DO ECHO", "Telnet: refused WILL GMCP"]
+        );
+    }
+    #[test]
+    fn ignored_commands_and_subnegotiations_are_reported() {
+        let mut p = TelnetParser::new();
+        p.feed(&[IAC, NOP, IAC, 246, IAC, SB, 201, b'h', b'i', IAC, SE]);
+        p.request(31, Side::Us, true);
+        p.feed(&[IAC, DONT, 31]);
+        assert_eq!(
+            p.take_diagnostics(),
+            vec![
+                "Telnet: ignored IAC AYT",
+                "Telnet: ignored subnegotiation for GMCP (2 bytes)",
+                "Telnet: server refused WILL NAWS",
+            ]
+        );
+    }
+    #[test]
+    fn oversized_subnegotiations_are_capped_and_dropped() {
+        let mut p = TelnetParser::new();
+        p.feed(&[IAC, SB, 201]);
+        p.feed(&vec![b'x'; MAX_SUBNEG + 10]);
+        assert_eq!(p.sb_buf.len(), MAX_SUBNEG);
+        p.feed(&[IAC, SE]);
+        p.feed(b"text");
+        assert_eq!(p.take_app_out(), b"text");
+        assert_eq!(
+            p.take_diagnostics(),
+            vec![format!(
+                "Telnet: dropped subnegotiation for GMCP ({} bytes, limit 256 KiB)",
+                MAX_SUBNEG + 10
+            )]
+        );
+        // The next one starts afresh
+        p.feed(&[IAC, SB, 201, b'h', b'i', IAC, SE]);
+        assert_eq!(
+            p.take_diagnostics(),
+            vec!["Telnet: ignored subnegotiation for GMCP (2 bytes)"]
+        );
+    }
+    #[test]
+    fn q_method_requests_do_not_loop() {
+        let mut p = TelnetParser::new();
+        p.request(31, Side::Us, true);
+        assert_eq!(p.take_responses(), vec![IAC, WILL, 31]);
+        assert_eq!(p.state(31, Side::Us), QState::WantYes(false));
+        // Changing our mind while waiting only queues the opposite
+        p.request(31, Side::Us, false);
         assert!(p.take_responses().is_empty());
+        // The DO acknowledges the WILL; the queued request goes out now
+        p.feed(&[IAC, DO, 31]);
+        assert_eq!(p.take_responses(), vec![IAC, WONT, 31]);
+        assert_eq!(p.state(31, Side::Us), QState::WantNo(false));
+        p.feed(&[IAC, DONT, 31]);
+        assert!(p.take_responses().is_empty());
+        assert_eq!(p.state(31, Side::Us), QState::No);
+
+        // A refused request ends up off without a reply
+        p.request(TELOPT_EOR, Side::Him, true);
+        assert_eq!(p.take_responses(), vec![IAC, DO, TELOPT_EOR]);
+        p.feed(&[IAC, WONT, TELOPT_EOR]);
+        assert!(p.take_responses().is_empty());
+        assert!(!p.enabled(TELOPT_EOR, Side::Him));
+    }
+    #[test]
+    fn handlers_see_changes_and_subnegotiations() {
+        use std::sync::{Arc, Mutex};
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl OptionHandler for Recorder {
+            fn changed(&mut self, side: Side, on: bool, out: &mut Vec<u8>) {
+                self.0.lock().unwrap().push(format!("{:?} {}", side, on));
+                if on {
+                    out.extend(subnegotiation(201, b"Core.Hello {}"));
+                }
+            }
+            fn subnegotiation(&mut self, data: &[u8], _out: &mut Vec<u8>) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(data).into_owned());
+            }
+        }
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut p = TelnetParser::new();
+        p.support(201, Side::Him, true);
+        p.register(201, Box::new(Recorder(log.clone())));
+        p.feed(&[IAC, WILL, 201]);
+        let mut expected = vec![IAC, DO, 201];
+        expected.extend(subnegotiation(201, b"Core.Hello {}"));
+        assert_eq!(p.take_responses(), expected);
+        p.feed(&[IAC, SB, 201, b'a', IAC, IAC, b'b', IAC, SE]);
+        p.feed(&[IAC, WONT, 201]);
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["Him true", "a\u{FFFD}b", "Him false"]
+        );
+    }
+    #[test]
+    fn ttype_answers_send() {
+        let mut p = TelnetParser::new();
+        p.feed(&[IAC, DO, TELOPT_TTYPE]);
+        assert_eq!(p.take_responses(), vec![IAC, WILL, TELOPT_TTYPE]);
+        p.feed(&[IAC, SB, TELOPT_TTYPE, TTYPE_SEND, IAC, SE]);
+        let mut reply = vec![IAC, SB, TELOPT_TTYPE, TTYPE_IS];
+        reply.extend_from_slice(b"okros");
+        reply.extend_from_slice(&[IAC, SE]);
+        assert_eq!(p.take_responses(), reply);
+    }
+    #[test]
+    fn subnegotiation_escapes_iac() {
+        assert_eq!(
+            subnegotiation(24, &[0, IAC, 1]),
+            vec![IAC, SB, 24, 0, IAC, IAC, 1, IAC, SE]
+        );
     }
     #[test]
     fn iac_escaped_255_in_output() {
@@ -0,0 +1,409 @@
+//! Delayed and repeating commands (#wait / #tick)
+//!
+//! A TimerQueue holds one-shot delays and repeating tickers keyed by id.
+//! Callers pass the current time in, so the TTY main loop and the headless
+//! SessionEngine drive the same queue the same way (and tests stay
+//! deterministic).
+
+use std::time::{Duration, Instant};
+
+/// Shortest accepted ticker interval, so a typo can't flood the MUD
+pub const MIN_TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// Longest accepted delay or interval (a year), so `now + delay` can't overflow
+pub const MAX_DELAY: Duration = Duration::from_secs(365 * 24 * 3600);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timer {
+    pub id: u32,
+    pub command: String,
+    pub due: Instant,
+    /// Some(interval) for tickers, None for one-shot #wait
+    pub interval: Option<Duration>,
+    /// #group it belongs to (None: always active)
+    pub group: Option<String>,
+}
+
+impl Timer {
+    pub fn is_ticker(&self) -> bool {
+        self.interval.is_some()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TimerQueue {
+    timers: Vec<Timer>,
+    next_id: u32,
+}
+
+/// Parse a delay in seconds ("5", "0.5", "1.25")
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Duration::try_from_secs_f64(secs)
+            .ok()
+            .filter(|d| *d <= MAX_DELAY)
+            .ok_or_else(|| format!("Too many seconds: {} (at most {})", s, MAX_DELAY.as_secs())),
+        _ => Err(format!("Invalid number of seconds: {}", s)),
+    }
+}
+
+/// `now + delay`, held at MAX_DELAY
+fn due_after(now: Instant, delay: Duration) -> Instant {
+    now.checked_add(delay.min(MAX_DELAY)).unwrap_or(now)
+}
+
+/// Split "<seconds> <command>" into its parts
+fn parse_delay_and_command(args: &str) -> Result<(Duration, String), String> {
+    let args = args.trim();
+    let (secs, command) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let command = crate::command_queue::strip_braces(command);
+    if secs.is_empty() || command.is_empty() {
+        return Err(String::new());
+    }
+    Ok((parse_seconds(secs)?, command.to_string()))
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self {
+            timers: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    fn alloc_id(&mut self) -> u32 {
+        let id = self.next_id.max(1);
+        self.next_id = id.wrapping_add(1);
+        id
+    }
+
+    /// Run `command` once, `delay` after `now`
+    pub fn add_wait(&mut self, delay: Duration, command: &str, now: Instant) -> u32 {
+        let id = self.alloc_id();
+        self.timers.push(Timer {
+            id,
+            command: command.to_string(),
+            due: due_after(now, delay),
+            interval: None,
+            group: None,
+        });
+        id
+    }
+
+    /// Run `command` every `interval`, first firing one interval from `now`
+    pub fn add_tick(&mut self, interval: Duration, command: &str, now: Instant) -> u32 {
+        let interval = interval.clamp(MIN_TICK_INTERVAL, MAX_DELAY);
+        let id = self.alloc_id();
+        self.timers.push(Timer {
+            id,
+            command: command.to_string(),
+            due: due_after(now, interval),
+            interval: Some(interval),
+            group: None,
+        });
+        id
+    }
+
+    /// Cancel a timer by id; returns false if no such timer
+    pub fn cancel(&mut self, id: u32) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|t| t.id != id);
+        self.timers.len() != before
+    }
+
+    /// Put a timer in a #group (None: no group); false if no such timer
+    pub fn set_group(&mut self, id: u32, group: Option<&str>) -> bool {
+        match self.timers.iter_mut().find(|t| t.id == id) {
+            Some(t) => {
+                t.group = group.map(str::to_string);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Pending timers, soonest first
+    pub fn list(&self) -> Vec<&Timer> {
+        let mut v: Vec<&Timer> = self.timers.iter().collect();
+        v.sort_by_key(|t| (t.due, t.id));
+        v
+    }
+
+    /// Time until the next timer is due (zero if one is overdue)
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.timers
+            .iter()
+            .map(|t| t.due.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Clamp a poll timeout (ms) so the loop wakes up for the next timer
+    pub fn poll_timeout(&self, now: Instant, max_ms: i32) -> i32 {
+        match self.next_timeout(now) {
+            Some(d) => {
+                // Round up so we don't wake a hair early and spin
+                let ms = d.as_millis() + u128::from(d.subsec_nanos() % 1_000_000 != 0);
+                ms.min(max_ms as u128) as i32
+            }
+            None => max_ms,
+        }
+    }
+
+    /// Remove/reschedule expired timers and return their commands in due order
+    /// A ticker that fell behind fires once and is rescheduled from `now`
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        self.expire_active(now, |_| true)
+    }
+
+    /// expire(), except that timers whose group isn't `active` come due
+    /// without firing (a #wait is dropped, a ticker waits for its next turn)
+    pub fn expire_active(
+        &mut self,
+        now: Instant,
+        active: impl Fn(Option<&str>) -> bool,
+    ) -> Vec<String> {
+        let mut fired: Vec<(Instant, u32, String)> = Vec::new();
+        self.timers.retain_mut(|t| {
+            if t.due > now {
+                return true;
+            }
+            if active(t.group.as_deref()) {
+                fired.push((t.due, t.id, t.command.clone()));
+            }
+            match t.interval {
+                Some(interval) => {
+                    t.due = due_after(t.due, interval);
+                    if t.due <= now {
+                        t.due = due_after(now, interval);
+                    }
+                    true
+                }
+                None => false,
+            }
+        });
+        fired.sort_by_key(|(due, id, _)| (*due, *id));
+        fired.into_iter().map(|(_, _, cmd)| cmd).collect()
+    }
+
+    /// Handle the timer client commands; returns None if `line` isn't one
+    ///   #wait <seconds> <command>   - run command once after a delay
+    ///   #tick <seconds> <command>   - run command repeatedly
+    ///   #tick                       - list pending timers
+    ///   #untick <id>|all            - cancel timers
+    pub fn handle_command(&mut self, line: &str, now: Instant) -> Option<Result<String, String>> {
+        let line = line.trim();
+        let (cmd, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        self.command(cmd.strip_prefix('#')?, args.trim(), now)
+    }
+
+    /// Run wait/tick/untick (name without the '#') with its arguments
+    pub fn command(
+        &mut self,
+        cmd: &str,
+        args: &str,
+        now: Instant,
+    ) -> Option<Result<String, String>> {
+        let result = match cmd {
+            "wait" => match parse_delay_and_command(args) {
+                Ok((delay, command)) => {
+                    let id = self.add_wait(delay, &command, now);
+                    Ok(format!(
+                        "Timer {}: '{}' in {}s",
+                        id,
+                        command,
+                        delay.as_secs_f64()
+                    ))
+                }
+                Err(e) if e.is_empty() => Err("Usage: #wait <seconds> <command>".to_string()),
+                Err(e) => Err(e),
+            },
+            "tick" if args.is_empty() => Ok(self.describe(now)),
+            "tick" => match parse_delay_and_command(args) {
+                Ok((interval, command)) => {
+                    let id = self.add_tick(interval, &command, now);
+                    let interval = interval.max(MIN_TICK_INTERVAL);
+                    Ok(format!(
+                        "Ticker {}: '{}' every {}s",
+                        id,
+                        command,
+                        interval.as_secs_f64()
+                    ))
+                }
+                Err(e) if e.is_empty() => Err("Usage: #tick <seconds> <command>".to_string()),
+                Err(e) => Err(e),
+            },
+            "untick" => {
+                if args == "all" {
+                    let n = self.len();
+                    self.clear();
+                    Ok(format!("Cancelled {} timer(s)", n))
+                } else {
+                    match args.parse::<u32>() {
+                        Ok(id) if self.cancel(id) => Ok(format!("Cancelled timer {}", id)),
+                        Ok(id) => Err(format!("No timer {}", id)),
+                        Err(_) => Err("Usage: #untick <id>|all".to_string()),
+                    }
+                }
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// One-line summary of pending timers for the status line
+    pub fn describe(&self, now: Instant) -> String {
+        if self.timers.is_empty() {
+            return "No timers.".to_string();
+        }
+        let parts: Vec<String> = self
+            .list()
+            .iter()
+            .map(|t| {
+                let left = t.due.saturating_duration_since(now).as_secs_f64();
+                match t.interval {
+                    Some(iv) => format!(
+                        "{}: every {}s '{}' ({:.1}s)",
+                        t.id,
+                        iv.as_secs_f64(),
+                        t.command,
+                        left
+                    ),
+                    None => format!("{}: '{}' in {:.1}s", t.id, t.command, left),
+                }
+            })
+            .collect();
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn wait_fires_once() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        q.add_wait(secs(5.0), "say hi", t0);
+        assert!(q.expire(t0 + secs(4.9)).is_empty());
+        assert_eq!(q.expire(t0 + secs(5.0)), vec!["say hi".to_string()]);
+        assert!(q.expire(t0 + secs(20.0)).is_empty());
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn tick_repeats_and_catches_up_once() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        let id = q.add_tick(secs(2.0), "score", t0);
+        assert_eq!(q.expire(t0 + secs(2.0)), vec!["score"]);
+        assert!(q.expire(t0 + secs(3.0)).is_empty());
+        assert_eq!(q.expire(t0 + secs(4.0)), vec!["score"]);
+        // Way behind: fire once, reschedule from now
+        assert_eq!(q.expire(t0 + secs(100.0)), vec!["score"]);
+        assert!(q.expire(t0 + secs(101.0)).is_empty());
+        assert_eq!(q.expire(t0 + secs(102.0)), vec!["score"]);
+        assert!(q.cancel(id));
+        assert!(!q.cancel(id));
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn fires_in_due_order_and_clamps_poll_timeout() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        assert_eq!(q.poll_timeout(t0, 250), 250);
+        q.add_wait(secs(3.0), "third", t0);
+        q.add_wait(secs(1.0), "first", t0);
+        q.add_wait(secs(2.0), "second", t0);
+        assert_eq!(q.poll_timeout(t0, 250), 250);
+        assert_eq!(q.poll_timeout(t0 + secs(0.9), 250), 100);
+        assert_eq!(q.poll_timeout(t0 + secs(5.0), 250), 0);
+        assert_eq!(q.expire(t0 + secs(5.0)), vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    fn inactive_groups_do_not_fire() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        let tick = q.add_tick(secs(1.0), "score", t0);
+        let wait = q.add_wait(secs(1.0), "flee", t0);
+        assert!(q.set_group(tick, Some("combat")));
+        assert!(q.set_group(wait, Some("combat")));
+        assert!(!q.set_group(99, None));
+        let off = |g: Option<&str>| g != Some("combat");
+        assert!(q.expire_active(t0 + secs(1.0), off).is_empty());
+        // The #wait is gone, the ticker comes round again
+        assert_eq!(q.len(), 1);
+        assert_eq!(q.expire(t0 + secs(2.0)), vec!["score"]);
+    }
+
+    #[test]
+    fn tick_interval_has_floor() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        q.add_tick(Duration::ZERO, "spam", t0);
+        assert_eq!(q.list()[0].interval, Some(MIN_TICK_INTERVAL));
+    }
+
+    #[test]
+    fn huge_delays_are_refused_not_panicked_on() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        for line in ["#wait 1e30 x", "#wait 1e18 x", "#tick 1e300 x"] {
+            let err = q.handle_command(line, t0).unwrap().unwrap_err();
+            assert!(err.starts_with("Too many seconds"), "{}", err);
+        }
+        assert!(q.is_empty());
+
+        q.add_wait(Duration::MAX, "later", t0);
+        q.add_tick(Duration::MAX, "rarely", t0);
+        assert_eq!(q.next_timeout(t0), Some(MAX_DELAY));
+        assert_eq!(q.expire(t0 + MAX_DELAY).len(), 2);
+    }
+
+    #[test]
+    fn handle_command_parses_wait_tick_untick() {
+        let t0 = Instant::now();
+        let mut q = TimerQueue::new();
+        assert!(q.handle_command("say hi", t0).is_none());
+        assert!(q.handle_command("#waiting", t0).is_none());
+
+        assert!(q.handle_command("#wait 1.5 kill orc", t0).unwrap().is_ok());
+        assert!(q.handle_command("#tick 60 save", t0).unwrap().is_ok());
+        assert_eq!(q.len(), 2);
+
+        assert!(q.handle_command("#wait", t0).unwrap().is_err());
+        assert!(q.handle_command("#wait 5", t0).unwrap().is_err());
+        assert!(q.handle_command("#wait soon x", t0).unwrap().is_err());
+        assert!(q.handle_command("#tick -1 x", t0).unwrap().is_err());
+
+        let listing = q.handle_command("#tick", t0).unwrap().unwrap();
+        assert!(listing.contains("kill orc"));
+        assert!(listing.contains("every 60s 'save'"));
+
+        assert!(q.handle_command("#untick 1", t0).unwrap().is_ok());
+        assert!(q.handle_command("#untick 1", t0).unwrap().is_err());
+        assert!(q.handle_command("#untick all", t0).unwrap().is_ok());
+        assert!(q.is_empty());
+        assert_eq!(
+            q.handle_command("#tick", t0).unwrap().unwrap(),
+            "No timers."
+        );
+    }
+}
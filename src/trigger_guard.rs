@@ -0,0 +1,246 @@
+//! Trigger loop protection (#loopguard)
+//!
+//! CommandQueue stops an alias that expands into itself after 100 commands,
+//! but a trigger whose command makes the MUD print its pattern again loops
+//! through the network instead, where the queue never sees it. TriggerGuard
+//! counts the trigger commands in a chain - each sent within CHAIN_WINDOW
+//! of the last, with no typed command between - and once a chain passes
+//! the depth limit refuses trigger commands until the user types something
+//! or the MUD goes quiet. It can also gag lines that only echo a command a
+//! trigger just sent. Callers pass the current time in, like SendQueue.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Trigger commands further apart than this start a new chain
+pub const CHAIN_WINDOW: Duration = Duration::from_secs(1);
+/// Trigger commands allowed in one chain when no limit is configured
+pub const DEFAULT_DEPTH: usize = 50;
+
+#[derive(Debug)]
+pub struct TriggerGuard {
+    max_depth: Option<usize>, // None = no limit
+    gag_echoes: bool,
+    depth: usize,                      // Trigger commands in the current chain
+    last: Option<Instant>,             // When the chain's last one was sent
+    tripped: bool,                     // Refusing until the chain ends
+    alert: Option<String>,             // Message for the first refusal
+    sent: VecDeque<(String, Instant)>, // Recent trigger commands, for gag_echoes
+}
+
+impl Default for TriggerGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TriggerGuard {
+    pub fn new() -> Self {
+        Self {
+            max_depth: Some(DEFAULT_DEPTH),
+            gag_echoes: false,
+            depth: 0,
+            last: None,
+            tripped: false,
+            alert: None,
+            sent: VecDeque::new(),
+        }
+    }
+
+    pub fn set_max_depth(&mut self, depth: Option<usize>) {
+        self.max_depth = depth;
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    pub fn set_gag_echoes(&mut self, on: bool) {
+        self.gag_echoes = on;
+        self.sent.clear();
+    }
+
+    pub fn gag_echoes(&self) -> bool {
+        self.gag_echoes
+    }
+
+    /// A trigger fired and wants to run its commands; false once the chain
+    /// is past the depth limit
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if self
+            .last
+            .is_none_or(|last| now.duration_since(last) > CHAIN_WINDOW)
+        {
+            self.depth = 0;
+            self.tripped = false;
+        }
+        self.last = Some(now);
+        if self.tripped {
+            return false;
+        }
+        self.depth += 1;
+        match self.max_depth {
+            Some(max) if self.depth > max => {
+                self.tripped = true;
+                self.alert = Some(format!(
+                    "Trigger loop: {} trigger commands in a row without a typed one; triggers stopped until you type a command",
+                    max
+                ));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// A trigger's command went to the MUD (remembered for gag_echoes)
+    pub fn sent(&mut self, command: &str, now: Instant) {
+        if self.gag_echoes {
+            self.expire(now);
+            self.sent.push_back((command.trim().to_string(), now));
+        }
+    }
+
+    /// The user typed a command: the chain ends
+    pub fn user_input(&mut self) {
+        self.depth = 0;
+        self.last = None;
+        self.tripped = false;
+    }
+
+    /// Message to show for a chain that was just cut off
+    pub fn take_alert(&mut self) -> Option<String> {
+        self.alert.take()
+    }
+
+    /// True if `line` is the MUD echoing a command a trigger just sent; each
+    /// command gags one echo
+    pub fn is_echo(&mut self, line: &str, now: Instant) -> bool {
+        if !self.gag_echoes {
+            return false;
+        }
+        self.expire(now);
+        let line = line.trim();
+        match self.sent.iter().position(|(command, _)| command == line) {
+            Some(i) => {
+                self.sent.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .sent
+            .front()
+            .is_some_and(|(_, at)| now.duration_since(*at) > CHAIN_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+    }
+
+    /// #loopguard [<depth>|off | gag on|off]
+    pub fn command(&mut self, args: &str) -> Result<String, String> {
+        let args: Vec<&str> = args.split_whitespace().collect();
+        match args.as_slice() {
+            [] => {}
+            ["gag", on] => match crate::config::parse_bool(on) {
+                Some(on) => self.set_gag_echoes(on),
+                None => return Err(USAGE.to_string()),
+            },
+            ["off"] => self.set_max_depth(None),
+            [depth] => match depth.parse::<usize>() {
+                Ok(depth) if depth > 0 => self.set_max_depth(Some(depth)),
+                _ => return Err(USAGE.to_string()),
+            },
+            _ => return Err(USAGE.to_string()),
+        }
+        Ok(self.describe())
+    }
+
+    /// One-line summary for the status line
+    pub fn describe(&self) -> String {
+        format!(
+            "Loop guard: {}, echoes {}",
+            self.max_depth.map_or("off".to_string(), |depth| format!(
+                "{} trigger commands",
+                depth
+            )),
+            if self.gag_echoes { "gagged" } else { "shown" }
+        )
+    }
+}
+
+const USAGE: &str = "Usage: #loopguard [<depth>|off | gag on|off]";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_past_depth_is_stopped_until_typed_command() {
+        let mut guard = TriggerGuard::new();
+        guard.set_max_depth(Some(3));
+        let now = Instant::now();
+        let tick = Duration::from_millis(100);
+        for n in 0..3 {
+            assert!(guard.allow(now + tick * n));
+        }
+        assert!(!guard.allow(now + tick * 3));
+        assert!(guard.take_alert().unwrap().starts_with("Trigger loop: 3 "));
+        assert!(!guard.allow(now + tick * 4));
+        assert_eq!(guard.take_alert(), None);
+
+        guard.user_input();
+        assert!(guard.allow(now + tick * 5));
+    }
+
+    #[test]
+    fn quiet_gap_starts_a_new_chain() {
+        let mut guard = TriggerGuard::new();
+        guard.set_max_depth(Some(2));
+        let now = Instant::now();
+        assert!(guard.allow(now));
+        assert!(guard.allow(now + CHAIN_WINDOW));
+        assert!(!guard.allow(now + CHAIN_WINDOW * 2));
+        let later = now + CHAIN_WINDOW * 4;
+        assert!(guard.allow(later));
+
+        guard.set_max_depth(None);
+        assert!((1..200).all(|n| guard.allow(later + Duration::from_millis(n))));
+    }
+
+    #[test]
+    fn echoes_gagged_once_each_within_window() {
+        let mut guard = TriggerGuard::new();
+        let now = Instant::now();
+        guard.sent("kill rat", now);
+        assert!(!guard.is_echo("kill rat", now), "gag is off by default");
+
+        guard.command("gag on").unwrap();
+        guard.sent("kill rat", now);
+        assert!(guard.is_echo(" kill rat ", now));
+        assert!(!guard.is_echo("kill rat", now));
+
+        guard.sent("eat bread", now);
+        assert!(!guard.is_echo("eat bread", now + CHAIN_WINDOW * 2));
+    }
+
+    #[test]
+    fn loopguard_command() {
+        let mut guard = TriggerGuard::new();
+        assert_eq!(
+            guard.command("").unwrap(),
+            "Loop guard: 50 trigger commands, echoes shown"
+        );
+        assert_eq!(
+            guard.command("off").unwrap(),
+            "Loop guard: off, echoes shown"
+        );
+        assert_eq!(guard.max_depth(), None);
+        guard.command("10").unwrap();
+        assert_eq!(guard.max_depth(), Some(10));
+        assert!(guard.command("0").is_err());
+        assert!(guard.command("gag maybe").is_err());
+    }
+}
@@ -7,6 +7,7 @@ mod unix {
     pub struct Tty {
         old: libc::termios,
         enabled: bool,
+        keypad: (String, String), // Terminfo smkx / rmkx
     }
 
     impl Tty {
@@ -16,9 +17,11 @@ mod unix {
                 if libc::tcgetattr(libc::STDIN_FILENO, &mut old) != 0 {
                     return Err(io::Error::last_os_error());
                 }
+                let caps = crate::curses::get_term_caps();
                 Ok(Self {
                     old,
                     enabled: false,
+                    keypad: (caps.smkx, caps.rmkx),
                 })
             }
         }
@@ -53,7 +56,17 @@ mod unix {
         }
 
         pub fn keypad_application_mode(&self, on: bool) -> io::Result<()> {
-            let seq = if on { b"\x1b=" } else { b"\x1b>" };
+            let seq = if on { &self.keypad.0 } else { &self.keypad.1 };
+            let mut out = io::stdout();
+            out.write_all(seq.as_bytes())?;
+            out.flush()?;
+            Ok(())
+        }
+
+        /// xterm bracketed paste: pasted text arrives wrapped in
+        /// ESC [ 200 ~ ... ESC [ 201 ~ (see KeyDecoder)
+        pub fn bracketed_paste(&self, on: bool) -> io::Result<()> {
+            let seq: &[u8] = if on { b"\x1b[?2004h" } else { b"\x1b[?2004l" };
             let mut out = io::stdout();
             out.write_all(seq)?;
             out.flush()?;
@@ -65,6 +78,7 @@ mod unix {
         fn drop(&mut self) {
             let _ = self.disable_raw();
             let _ = self.keypad_application_mode(false);
+            let _ = self.bracketed_paste(false);
         }
     }
 
@@ -88,6 +102,9 @@ mod nonunix {
         pub fn keypad_application_mode(&self, _: bool) -> io::Result<()> {
             Ok(())
         }
+        pub fn bracketed_paste(&self, _: bool) -> io::Result<()> {
+            Ok(())
+        }
     }
     pub use Tty as PlatformTty;
 }
@@ -0,0 +1,426 @@
+// TtyUi - the terminal front-end of the interactive mode
+//
+// C++ main.cc builds Screen, OutputWindow, InputLine and StatusLine in that
+// order and tears the terminal down on exit. The widget tree, key decoding
+// and redraw sequence live here; the main loop only decides what to do with
+// keys and command lines.
+
+use crate::color::ColorTheme;
+use crate::command_queue::CommandQueue;
+use crate::curses::{get_acs_caps, get_term_caps, AcsCaps};
+use crate::history::{HistoryId, HistorySet};
+use crate::input::{paste_lines, KeyCode, KeyDecoder, KeyEvent};
+use crate::input_line::InputLine;
+use crate::keybind::{KeyAction, KeyBindings};
+use crate::layout::{self, Layout, PaneId, Rect, SplitDir};
+use crate::output_window::OutputWindow;
+use crate::pane::TextPane;
+use crate::screen::Screen;
+use crate::status_line::StatusLine;
+use crate::tty::Tty;
+use crate::window::Window;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+/// #grep results (cyan)
+const GREP_COLOR: u8 = 0x06;
+
+/// Poll timeout while a frame waits for the terminal writer (ms)
+const FRAME_RETRY_MS: i32 = 10;
+
+pub struct TtyUi {
+    pub tty: Tty,
+    pub screen: Screen,
+    // Boxed: Session keeps a raw pointer to it (Session::attach_window)
+    pub output: Box<OutputWindow>,
+    pub input: InputLine,
+    pub status: StatusLine,
+    pub history: HistorySet,            // Input line history
+    pub bindings: KeyBindings,          // Checked before macros and line editing
+    pub layout: Layout,                 // Panes above the input line (#window)
+    pub paste: String,                  // Last text copied in copy mode
+    pending_paste: Option<Vec<String>>, // Multi-line paste awaiting "send?"
+    panes: BTreeMap<PaneId, TextPane>,
+    pane_color: u8,
+    keys: KeyDecoder,
+    caps: AcsCaps,
+    status_left: Option<std::time::Duration>, // Visual bell or timed message still showing
+    resized: Option<(usize, usize)>,          // Output size change not yet published
+}
+
+/// Terminal size from the tty, 80x24 if it can't be read (C++ Screen.cc:16-34)
+pub fn terminal_size() -> (usize, usize) {
+    unsafe {
+        let mut ws: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut ws) < 0 || ws.ws_col == 0 {
+            (80, 24)
+        } else {
+            (ws.ws_col as usize, ws.ws_row as usize)
+        }
+    }
+}
+
+impl TtyUi {
+    /// Raw mode, cleared screen and the widget tree (C++ main.cc:52-76)
+    /// `lines` is the output window's scrollback size
+    pub fn new(lines: usize, theme: &ColorTheme) -> io::Result<Self> {
+        let mut tty = Tty::new()?;
+        tty.enable_raw()?;
+        let _ = tty.keypad_application_mode(true);
+        let _ = tty.bracketed_paste(true);
+
+        // Alternate screen (the shell's text comes back on exit), cleared,
+        // cursor hidden - all from terminfo
+        let term = get_term_caps();
+        print!("{}{}{}", term.smcup, term.clear, term.civis);
+        io::stdout().flush()?;
+
+        // Nonblocking stdin: the loops poll it
+        unsafe {
+            let _ = libc::fcntl(libc::STDIN_FILENO, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+
+        let (width, height) = terminal_size();
+        let mut screen = Screen::new(width, height);
+        let mut keys = KeyDecoder::new();
+        for (seq, key) in &term.keys {
+            keys.add_sequence(seq, *key);
+        }
+        screen.term = term;
+        let parent = screen.window_mut() as *mut Window;
+
+        // C++ OutputWindow.cc:9-10: Window(_parent, wh_full, _parent->height-1)
+        // Row 0; the StatusLine overlaps it at the top (higher z-order)
+        let mut output = Box::new(OutputWindow::new(
+            parent,
+            width,
+            height - 1,
+            lines,
+            theme.output_color(),
+        ));
+        output.win.parent_y = 0;
+
+        // Input line on the bottom row (created before StatusLine for z-order)
+        let mut input = InputLine::new(parent, width, theme.input_color(), HistoryId::MainInput);
+        input.win.parent_y = (height - 1) as isize;
+
+        // Status line: created last = top z-order, overlays OutputWindow's top row
+        let mut status = StatusLine::new(parent, width, theme.status_color());
+        status.win.parent_y = 0;
+
+        Ok(Self {
+            tty,
+            screen,
+            output,
+            input,
+            status,
+            history: HistorySet::new(crate::history::DEFAULT_SIZE),
+            bindings: KeyBindings::new(),
+            layout: Layout::new(),
+            paste: String::new(),
+            pending_paste: None,
+            panes: BTreeMap::new(),
+            pane_color: theme.output_color(),
+            keys,
+            caps: get_acs_caps(),
+            status_left: None,
+            resized: None,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.screen.window.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.screen.window.height
+    }
+
+    pub fn output_ptr(&mut self) -> *mut OutputWindow {
+        &mut *self.output as *mut OutputWindow
+    }
+
+    /// Redraw dirty widgets and push the composited screen to the terminal
+    /// (C++ main.cc:142 screen->refresh())
+    pub fn render(&mut self) {
+        self.status_left = self.status.tick(std::time::Instant::now());
+        // Composition workaround: C++ widgets are Windows (virtual redraw);
+        // here each owns its Window, so redraw() is called before the tree
+        // refresh and the window stays dirty for it
+        if self.output.win.dirty {
+            self.output.redraw();
+            self.output.win.dirty = true;
+        }
+        if self.input.win.dirty {
+            self.input.redraw();
+            self.input.win.dirty = true;
+        }
+        for pane in self.panes.values_mut() {
+            if pane.win.dirty {
+                pane.redraw();
+                pane.win.dirty = true;
+            }
+        }
+        // Output scrolling becomes a terminal scroll when it spans the
+        // screen; the status line covers row 0
+        let win = &self.output.win;
+        let top = (win.parent_y.max(0) as usize).max(1);
+        let bottom = win.parent_y.max(0) as usize + win.height;
+        if win.parent_x == 0 && win.width == self.width() && bottom > top {
+            self.screen
+                .set_scrolling_region(0, top, win.width, bottom - top);
+        } else {
+            self.screen.set_scrolling_region(0, 0, 0, 0);
+        }
+        self.screen.refresh(&self.caps);
+    }
+
+    /// `timeout` (ms), cut short while a frame is waiting to be drawn
+    pub fn poll_timeout(&self, timeout: i32) -> i32 {
+        let timeout = match self.status_left {
+            Some(left) => timeout.min(left.as_millis() as i32 + 1),
+            None => timeout,
+        };
+        if self.screen.frame_pending() {
+            timeout.min(FRAME_RETRY_MS)
+        } else {
+            timeout
+        }
+    }
+
+    /// Clear the terminal and repaint everything on the next render
+    pub fn redraw_all(&mut self) {
+        print!("{}", self.screen.term.clear);
+        let _ = io::stdout().flush();
+        self.screen.invalidate();
+        self.output.win.dirty = true;
+        self.input.win.dirty = true;
+        self.apply_layout();
+    }
+
+    /// Screen area the layout divides: everything above the input line
+    fn layout_area(&self) -> Rect {
+        Rect::new(0, 0, self.width(), self.height().saturating_sub(1))
+    }
+
+    /// Run a #window command line; None if it isn't one
+    pub fn window_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        let cmd = layout::parse_command(line)?;
+        let area = self.layout_area();
+        let result = cmd.and_then(|cmd| self.layout.apply(cmd, area));
+        self.apply_layout();
+        Some(result)
+    }
+
+    /// Place the output window and pane windows per the layout, creating and
+    /// destroying pane windows to match, and paint the separators
+    fn apply_layout(&mut self) {
+        let arrangement = self.layout.arrange(self.layout_area());
+        let parent = self.screen.window_mut() as *mut Window;
+
+        self.panes.retain(|id, pane| {
+            let keep = arrangement.panes.iter().any(|(p, _)| p == id);
+            if !keep {
+                pane.win.die();
+            }
+            keep
+        });
+        for (id, rect) in &arrangement.panes {
+            match self.layout.source(*id) {
+                Some(layout::Source::Output) => {
+                    let win = &self.output.win;
+                    if (win.width, win.height) != (rect.width, rect.height) {
+                        self.resized = Some((rect.width, rect.height));
+                    }
+                    self.output.resize(rect.width, rect.height);
+                    self.output.win.parent_x = rect.x as isize;
+                    self.output.win.parent_y = rect.y as isize;
+                    self.output.win.dirty = true;
+                }
+                Some(source) => {
+                    let color = self.pane_color;
+                    self.panes
+                        .entry(*id)
+                        .or_insert_with(|| TextPane::new(parent, source, *rect, color))
+                        .place(*rect);
+                }
+                None => {}
+            }
+        }
+
+        let root = self.screen.window_mut();
+        for (dir, rect) in &arrangement.separators {
+            let ch = match dir {
+                SplitDir::Horizontal => b'-',
+                SplitDir::Vertical => b'|',
+            };
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    root.put_char(x, y, ch, self.pane_color);
+                }
+            }
+        }
+
+        // New pane windows went in on top; the status line overlays row 0
+        let status = self.status.win.as_mut() as *mut Window;
+        root.remove(status);
+        root.insert(status);
+    }
+
+    /// Run a #grep/#lastlog line over the output scrollback, printing the
+    /// matches into the output window; None if it isn't one
+    pub fn grep_command(&mut self, line: &str) -> Option<Result<String, String>> {
+        let re = match crate::scrollback_search::grep_command(line)? {
+            Ok(re) => re,
+            Err(e) => return Some(Err(e)),
+        };
+        let matches = self.output.sb.grep(&re);
+        let what = format!("/{}/", re.as_str());
+        for row in crate::scrollback_search::grep_report(&what, &matches) {
+            self.output.print_line(row.as_bytes(), GREP_COLOR);
+        }
+        Some(Ok(format!("{} matches", matches.len())))
+    }
+
+    /// Completed MUD line, for chat panes
+    pub fn pane_line(&mut self, line: &str) {
+        for pane in self.panes.values_mut() {
+            pane.mud_line(line);
+        }
+    }
+
+    /// Let log panes check their files
+    pub fn poll_panes(&mut self) {
+        let now = Instant::now();
+        for pane in self.panes.values_mut() {
+            pane.poll(now);
+        }
+    }
+
+    /// Page the focused pane; false if it is the output pane (the caller
+    /// scrolls the scrollback then)
+    pub fn page_focused(&mut self, up: bool) -> bool {
+        match self.panes.get_mut(&self.layout.focus()) {
+            Some(pane) => {
+                pane.page(up);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The output window's new size, once after it changes
+    pub fn take_resize(&mut self) -> Option<(usize, usize)> {
+        self.resized.take()
+    }
+
+    /// Incoming BEL: beep the terminal or flash the status line
+    pub fn bell(&mut self, mode: crate::bell::BellMode) {
+        match mode {
+            crate::bell::BellMode::Beep => {
+                print!("\x07");
+                let _ = io::stdout().flush();
+            }
+            crate::bell::BellMode::Visual => {
+                let now = std::time::Instant::now();
+                self.status.flash(now, crate::bell::FLASH_DURATION);
+                self.status_left = Some(crate::bell::FLASH_DURATION);
+            }
+            crate::bell::BellMode::Hook | crate::bell::BellMode::Off => {}
+        }
+    }
+
+    /// Keep copied text for pasting and offer it to the terminal clipboard
+    /// (OSC 52; terminals without support ignore it)
+    pub fn copy(&mut self, text: String) {
+        print!("{}", crate::copy_mode::osc52(&text));
+        let _ = io::stdout().flush();
+        self.paste = text;
+    }
+
+    /// Action bound to a key, if any: the MUD's own bind/unbind lines
+    /// (through its inheritance chain) win over the global table
+    pub fn binding(&self, ev: KeyEvent, mud: &crate::mud::Mud) -> Option<KeyAction> {
+        let key = crate::macro_def::event_key(ev)?;
+        match mud.find_binding(key) {
+            Some(action) => action.cloned(),
+            None => self.bindings.get(key).cloned(),
+        }
+    }
+
+    /// Keys typed since the last call; None if stdin had nothing
+    pub fn read_keys(&mut self) -> Option<Vec<KeyEvent>> {
+        let mut buf = [0u8; 1024];
+        match io::stdin().read(&mut buf) {
+            Ok(n) if n > 0 => Some(self.keys.feed(&buf[..n])),
+            _ => None,
+        }
+    }
+
+    /// Bracketed paste: a single line goes into the input line verbatim; more
+    /// lines ask first (y sends each as if typed, e inserts them as one line,
+    /// anything else discards). True if the key was consumed
+    pub fn paste_key(&mut self, ev: KeyEvent, queue: &mut CommandQueue) -> bool {
+        if let Some(lines) = self.pending_paste.take() {
+            match ev {
+                KeyEvent::Byte(b'y' | b'Y') => {
+                    let typed = self.input.get_input();
+                    for line in &lines {
+                        self.input.set(line);
+                        self.edit_key(KeyEvent::Byte(b'\n'), queue);
+                    }
+                    self.input.set(&typed);
+                    self.status
+                        .set_text(format!("Sent {} pasted lines", lines.len()));
+                }
+                KeyEvent::Byte(b'e' | b'E') => {
+                    self.input.insert(&lines.join("\n"));
+                    self.status.set_text("");
+                }
+                _ => self.status.set_text("Paste discarded"),
+            }
+            return true;
+        }
+        if ev != KeyEvent::Key(KeyCode::Paste) {
+            return false;
+        }
+        let Some(text) = self.keys.take_paste() else {
+            return true;
+        };
+        let lines = paste_lines(&text);
+        if lines.len() <= 1 {
+            self.input.insert(lines.first().map_or("", String::as_str));
+        } else {
+            self.status.set_text(format!(
+                "Send {} pasted lines? (y = send, e = edit as one line, other key = discard)",
+                lines.len()
+            ));
+            self.pending_paste = Some(lines);
+        }
+        true
+    }
+
+    /// Line editing: pass a key to the input line; Enter queues the line
+    pub fn edit_key(&mut self, ev: KeyEvent, queue: &mut CommandQueue) {
+        if let Some(key_code) = crate::input_line::event_key_code(ev) {
+            self.input.keypress(key_code, &mut self.history, queue);
+        }
+    }
+}
+
+impl Drop for TtyUi {
+    /// Show the cursor and leave the alternate screen (or clear the screen
+    /// without one); Tty restores the terminal modes
+    fn drop(&mut self) {
+        self.screen.flush_output();
+        let term = &self.screen.term;
+        let leave = if term.rmcup.is_empty() {
+            &term.clear
+        } else {
+            &term.rmcup
+        };
+        print!("{}{}{}", term.sgr0, term.cnorm, leave);
+        let _ = io::stdout().flush();
+    }
+}
@@ -0,0 +1,143 @@
+// Session recording - the terminal frame stream saved for replay
+//
+// `#record <file>` saves every frame Screen sends to the terminal, with its
+// time, as a ttyrec file (play with ttyplay/ipbt) or, for a `.cast` name, an
+// asciinema v2 recording (asciinema play, or upload it to share).
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ttyrec,    // Per frame: seconds, microseconds, length (u32 LE) and the bytes
+    Asciicast, // JSON header line, then [time, "o", text] per frame
+}
+
+impl Format {
+    /// Asciicast for a .cast file, otherwise ttyrec
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("cast") => Format::Asciicast,
+            _ => Format::Ttyrec,
+        }
+    }
+}
+
+/// Writes frames to a recording file
+pub struct TtyRecorder {
+    file: File,
+    path: PathBuf,
+    format: Format,
+    start: Instant,
+}
+
+impl TtyRecorder {
+    /// Start a recording of a `width`x`height` terminal
+    pub fn create(path: &Path, width: usize, height: usize) -> Result<Self, String> {
+        let err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut file = File::create(path).map_err(err)?;
+        let format = Format::for_path(path);
+        if format == Format::Asciicast {
+            let header = serde_json::json!({
+                "version": 2,
+                "width": width,
+                "height": height,
+                "timestamp": unix_time().as_secs(),
+                "env": {"TERM": std::env::var("TERM").unwrap_or_default()},
+            });
+            writeln!(file, "{}", header).map_err(err)?;
+        }
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            format,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one frame, as sent to the terminal now
+    pub fn write_frame(&mut self, frame: &str) -> Result<(), String> {
+        let record = match self.format {
+            Format::Ttyrec => ttyrec_record(unix_time().as_micros() as u64, frame.as_bytes()),
+            Format::Asciicast => asciicast_event(self.start.elapsed().as_secs_f64(), frame),
+        };
+        self.file.write_all(&record).map_err(|e| e.to_string())
+    }
+}
+
+fn unix_time() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// A ttyrec frame written at `micros` since the epoch
+fn ttyrec_record(micros: u64, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + data.len());
+    out.extend_from_slice(&((micros / 1_000_000) as u32).to_le_bytes());
+    out.extend_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// An asciicast output event `secs` into the recording
+fn asciicast_event(secs: f64, text: &str) -> Vec<u8> {
+    let mut line = serde_json::json!([(secs * 1e6).round() / 1e6, "o", text]).to_string();
+    line.push('\n');
+    line.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::for_path(Path::new("a.cast")), Format::Asciicast);
+        assert_eq!(Format::for_path(Path::new("a.ttyrec")), Format::Ttyrec);
+        assert_eq!(Format::for_path(Path::new("session")), Format::Ttyrec);
+    }
+
+    #[test]
+    fn record_layouts() {
+        assert_eq!(
+            ttyrec_record(3_000_250, b"\x1b[Hx"),
+            [
+                &3u32.to_le_bytes()[..],
+                &250u32.to_le_bytes(),
+                &4u32.to_le_bytes(),
+                b"\x1b[Hx"
+            ]
+            .concat()
+        );
+        assert_eq!(
+            asciicast_event(1.5, "\x1b[Hx\"y"),
+            b"[1.5,\"o\",\"\\u001b[Hx\\\"y\"]\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn asciicast_file_has_header_then_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("s.cast");
+        let mut rec = TtyRecorder::create(&path, 80, 24).unwrap();
+        rec.write_frame("hi").unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(
+            (header["version"].as_u64(), header["width"].as_u64()),
+            (Some(2), Some(80))
+        );
+        let event: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(event[1], "o");
+        assert_eq!(event[2], "hi");
+    }
+}
@@ -0,0 +1,147 @@
+// URLs - detection in MUD output, the recent list and the system opener
+//
+// Session marks http(s) URLs in completed lines (underlined, color::LINK)
+// and adds them to a numbered list; `#url <n>` hands one to xdg-open (open
+// on macOS). With `hyperlinks on` the screen also wraps LINK cells in OSC 8
+// so terminals that support it make them clickable.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::process::{Command, Stdio};
+
+/// URLs kept for #url
+pub const MAX_URLS: usize = 100;
+
+/// Byte ranges of http:// and https:// URLs in `text`; trailing punctuation
+/// (the end of a sentence, a closing bracket) isn't part of the URL
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    // ASCII lowercasing keeps byte offsets, so positions carry over to `text`
+    let lower = text.to_ascii_lowercase();
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find("http") {
+        let start = from + pos;
+        let rest = &text[start..];
+        let scheme = ["https://", "http://"]
+            .iter()
+            .find(|s| rest.len() >= s.len() && rest[..s.len()].eq_ignore_ascii_case(s));
+        let boundary = start == 0 || !text.as_bytes()[start - 1].is_ascii_alphanumeric();
+        let Some(scheme) = scheme.filter(|_| boundary) else {
+            from = start + 4;
+            continue;
+        };
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c.is_control() || matches!(c, '"' | '<' | '>'))
+            .unwrap_or(rest.len());
+        let url = rest[..len].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '}', '\'']);
+        if url.len() > scheme.len() {
+            found.push(start..start + url.len());
+        }
+        from = start + len.max(scheme.len());
+    }
+    found
+}
+
+/// Recently seen URLs, newest first (#url 1 is the latest)
+#[derive(Debug, Default)]
+pub struct UrlList {
+    urls: VecDeque<String>,
+}
+
+impl UrlList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember `url`; seeing it again moves it to the front
+    pub fn add(&mut self, url: &str) {
+        self.urls.retain(|u| u != url);
+        self.urls.push_front(url.to_string());
+        self.urls.truncate(MAX_URLS);
+    }
+
+    /// URL number `n` (1 = newest)
+    pub fn get(&self, n: usize) -> Option<&str> {
+        self.urls.get(n.checked_sub(1)?).map(String::as_str)
+    }
+
+    /// Numbered entries, newest first
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.urls
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (i + 1, u.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+}
+
+/// The desktop's "open this" command
+fn system_opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    }
+}
+
+/// Open `url` in the system browser without waiting for it
+pub fn open_url(url: &str) -> Result<(), String> {
+    let mut child = Command::new(system_opener())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", system_opener(), e))?;
+    // Reap it when it exits (openers hand off to the browser and quit) so
+    // no zombie is left behind
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// OSC 8: text written after this links to `url` (an empty url ends the link)
+pub fn osc8(url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find_urls(text).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn finds_urls_without_trailing_punctuation() {
+        assert_eq!(
+            urls("See https://example.com/a?b=1. Or (http://x.org/wiki_(y)), HTTPS://UP.ORG"),
+            vec![
+                "https://example.com/a?b=1",
+                "http://x.org/wiki_(y",
+                "HTTPS://UP.ORG"
+            ]
+        );
+        assert!(urls("http:// nothing, xhttp://no.pe, httpd is not one").is_empty());
+        assert_eq!(urls("<http://a.b>"), vec!["http://a.b"]);
+    }
+
+    #[test]
+    fn list_is_newest_first_without_duplicates() {
+        let mut list = UrlList::new();
+        list.add("http://a");
+        list.add("http://b");
+        list.add("http://a");
+        assert_eq!(list.get(1), Some("http://a"));
+        assert_eq!(list.get(2), Some("http://b"));
+        assert_eq!(list.get(3), None);
+        assert_eq!(list.get(0), None);
+        for i in 0..MAX_URLS + 5 {
+            list.add(&format!("http://{}", i));
+        }
+        assert_eq!(list.iter().count(), MAX_URLS);
+    }
+}
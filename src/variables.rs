@@ -0,0 +1,181 @@
+// Variables - user variables set with #var
+//
+// `$name` or `%{name}` in a sent command, alias or action
+// is replaced by the value and `$$` is a literal `$`. Names are letters,
+// digits and '_', not starting with a digit. An unknown `$name` is left as
+// typed (so "$5" or "costs $x" survive); an unknown `%{name}` expands to
+// nothing. Expansion is a single pass: a value mentioning another
+// variable isn't expanded again.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    vars: BTreeMap<String, String>,
+}
+
+pub fn valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Length of the variable name at the start of `s`
+fn name_len(s: &str) -> usize {
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        return 0;
+    }
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len())
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        if !valid_name(name) {
+            return Err(format!("Invalid variable name: {}", name));
+        }
+        self.vars.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.vars.remove(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// (name, value) in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// A reference at the start of `s` ("$$", "$name" or "%{name}"):
+    /// its replacement and length, None if there's nothing to replace
+    pub fn reference<'a>(&'a self, s: &str) -> Option<(&'a str, usize)> {
+        if let Some(rest) = s.strip_prefix('$') {
+            if rest.starts_with('$') {
+                return Some(("$", 2));
+            }
+            let len = name_len(rest);
+            return self.get(&rest[..len]).map(|v| (v, len + 1));
+        }
+        let rest = s.strip_prefix("%{")?;
+        let end = rest.find('}')?;
+        valid_name(&rest[..end]).then(|| (self.get(&rest[..end]).unwrap_or(""), end + 3))
+    }
+
+    /// Replace every reference in `s`
+    pub fn expand(&self, s: &str) -> String {
+        if !s.contains(['$', '%']) {
+            return s.to_string();
+        }
+        let mut out = String::with_capacity(s.len());
+        let mut i = 0;
+        while let Some(at) = s[i..].find(['$', '%']).map(|n| i + n) {
+            out.push_str(&s[i..at]);
+            match self.reference(&s[at..]) {
+                Some((value, len)) => {
+                    out.push_str(value);
+                    i = at + len;
+                }
+                None => {
+                    out.push(s.as_bytes()[at] as char);
+                    i = at + 1;
+                }
+            }
+        }
+        out.push_str(&s[i..]);
+        out
+    }
+
+    /// #var [name [value]] / #unvar <name>; None for other commands
+    pub fn command(&mut self, cmd: &str, args: &str) -> Option<Result<String, String>> {
+        let (name, value) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let value = value.trim();
+        let result = match cmd {
+            "var" if name.is_empty() => Ok(self.describe()),
+            "var" if value.is_empty() => match self.get(name) {
+                Some(v) => Ok(format!("{} = {}", name, v)),
+                None => Err(format!("No variable {}", name)),
+            },
+            "var" => self
+                .set(name, value)
+                .map(|()| format!("{} = {}", name, value)),
+            "unvar" if name.is_empty() => Err("Usage: #unvar <name>".to_string()),
+            "unvar" => match self.remove(name) {
+                Some(_) => Ok(format!("Removed variable {}", name)),
+                None => Err(format!("No variable {}", name)),
+            },
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// Listing for #var: one "name = value" line each
+    pub fn describe(&self) -> String {
+        if self.vars.is_empty() {
+            return "No variables.".to_string();
+        }
+        let mut text = String::from("Variables:");
+        for (name, value) in self.iter() {
+            text.push_str(&format!("\n  {} = {}", name, value));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_references() {
+        let mut vars = Variables::new();
+        vars.set("hp", "100").unwrap();
+        vars.set("target", "orc_2").unwrap();
+        assert!(vars.set("2x", "no").is_err());
+        assert_eq!(
+            vars.expand("kill $target;say $hp%{hp}hp"),
+            "kill orc_2;say 100100hp"
+        );
+        assert_eq!(vars.expand("costs $5, $$hp, $nope"), "costs $5, $hp, $nope");
+        assert_eq!(vars.expand("%{nope}|%h|%{bad name}"), "|%h|%{bad name}");
+        assert_eq!(vars.expand("trailing $"), "trailing $");
+    }
+
+    #[test]
+    fn var_commands() {
+        let mut vars = Variables::new();
+        assert_eq!(vars.command("var", "").unwrap().unwrap(), "No variables.");
+        assert_eq!(
+            vars.command("var", "target big orc").unwrap().unwrap(),
+            "target = big orc"
+        );
+        assert_eq!(vars.get("target"), Some("big orc"));
+        assert_eq!(
+            vars.command("var", "target").unwrap().unwrap(),
+            "target = big orc"
+        );
+        assert!(vars
+            .command("var", "")
+            .unwrap()
+            .unwrap()
+            .contains("\n  target = big orc"));
+        assert!(vars.command("unvar", "target").unwrap().is_ok());
+        assert!(vars.command("unvar", "target").unwrap().is_err());
+        assert!(vars.command("look", "").is_none());
+    }
+}
@@ -11,6 +11,41 @@
 use crate::scrollback::Attrib;
 use std::ptr;
 
+/// Canvas bytes for the line-drawing characters (C++ SPECIAL_CHARS, the
+/// bc_* border characters); Screen turns them into the terminal's ACS
+/// characters (see screen::special_char)
+pub mod special {
+    pub const VLINE: u8 = 0xEC;
+    pub const HLINE: u8 = 0xED;
+    pub const ULCORNER: u8 = 0xEE;
+    pub const URCORNER: u8 = 0xEF;
+    pub const LLCORNER: u8 = 0xF0;
+    pub const LRCORNER: u8 = 0xF1;
+    pub const CKBOARD: u8 = 0xF2;
+    pub const BULLET: u8 = 0xF3;
+}
+
+/// Frame drawn by Window::draw_border
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    /// Line-drawing characters
+    Single,
+    /// '=' rules with '#' corners: ACS has no double lines, so this is
+    /// plain ASCII that stands out from Single
+    Double,
+}
+
+impl BorderStyle {
+    /// (horizontal, vertical, ul, ur, ll, lr)
+    fn chars(self) -> [u8; 6] {
+        use special::*;
+        match self {
+            BorderStyle::Single => [HLINE, VLINE, ULCORNER, URCORNER, LLCORNER, LRCORNER],
+            BorderStyle::Double => [b'=', b'|', b'#', b'#', b'#', b'#'],
+        }
+    }
+}
+
 /// Window tree node
 pub struct Window {
     // Tree structure (C++ Window.cc:10-14)
@@ -42,8 +77,8 @@ pub struct Window {
 impl Window {
     /// Create new window (C++ Window.cc:10-57)
     pub fn new(parent: *mut Window, width: usize, height: usize) -> Box<Self> {
-        let clear_line = vec![((0x07u16) << 8) | (b' ' as u16); width];
-        let canvas = vec![((0x07u16) << 8) | (b' ' as u16); width * height];
+        let clear_line = vec![((0x07 as Attrib) << 8) | (b' ' as Attrib); width];
+        let canvas = vec![((0x07 as Attrib) << 8) | (b' ' as Attrib); width * height];
 
         let mut win = Box::new(Self {
             parent,
@@ -116,13 +151,24 @@ impl Window {
 
     /// Clear canvas (C++ Window.cc:342-351)
     pub fn clear(&mut self) {
-        let fill = ((self.color as u16) << 8) | (b' ' as u16);
+        let fill = ((self.color as Attrib) << 8) | (b' ' as Attrib);
         for a in &mut self.canvas {
             *a = fill;
         }
         self.dirty = true;
     }
 
+    /// Reallocate the canvas at a new size, blank
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let fill = ((self.color as Attrib) << 8) | (b' ' as Attrib);
+        self.width = width;
+        self.height = height;
+        self.canvas = vec![fill; width * height];
+        self.clear_line = vec![fill; width];
+        self.set_cursor(self.cursor_x, self.cursor_y);
+        self.dirty = true;
+    }
+
     /// Handle keypress (C++ Window.h:33 - virtual bool keypress(int key))
     /// Returns true if the key was handled
     /// Default implementation does nothing
@@ -241,7 +287,7 @@ impl Window {
             return;
         }
         let off = y * self.width + x;
-        self.canvas[off] = ((color as u16) << 8) | (ch as u16);
+        self.canvas[off] = ((color as Attrib) << 8) | (ch as Attrib);
         self.dirty = true;
     }
 
@@ -249,7 +295,7 @@ impl Window {
         if y >= self.height {
             return;
         }
-        let fill = ((color as u16) << 8) | (b' ' as u16);
+        let fill = ((color as Attrib) << 8) | (b' ' as Attrib);
         let off = y * self.width;
         for a in &mut self.canvas[off..off + self.width] {
             *a = fill;
@@ -301,12 +347,42 @@ impl Window {
                 }
 
                 let off = self.cursor_y * self.width + self.cursor_x;
-                self.canvas[off] = ((self.color as u16) << 8) | (ch as u16);
+                self.canvas[off] = ((self.color as Attrib) << 8) | (ch as Attrib);
                 self.cursor_x += 1;
             }
         }
     }
 
+    /// Frame the canvas edge, with `title` in the top edge (" Title ")
+    /// The content area is then (1, 1) to (width - 2, height - 2)
+    pub fn draw_border(&mut self, style: BorderStyle, title: Option<&str>, color: u8) {
+        let (w, h) = (self.width, self.height);
+        if w < 2 || h < 2 {
+            return;
+        }
+        let [hline, vline, ul, ur, ll, lr] = style.chars();
+        for x in 1..w - 1 {
+            self.put_char(x, 0, hline, color);
+            self.put_char(x, h - 1, hline, color);
+        }
+        for y in 1..h - 1 {
+            self.put_char(0, y, vline, color);
+            self.put_char(w - 1, y, vline, color);
+        }
+        self.put_char(0, 0, ul, color);
+        self.put_char(w - 1, 0, ur, color);
+        self.put_char(0, h - 1, ll, color);
+        self.put_char(w - 1, h - 1, lr, color);
+        if let Some(title) = title.filter(|t| !t.is_empty()) {
+            // Left-aligned after one rule character, cut to fit
+            let room = w.saturating_sub(4);
+            let text = format!(" {} ", title);
+            for (i, b) in text.bytes().take(room).enumerate() {
+                self.put_char(2 + i, 0, b, color);
+            }
+        }
+    }
+
     /// Destroy window and notify parent (C++ Window.cc:526-531)
     /// NOTE: In C++, this is `delete this`. In Rust, we need the caller to handle Box cleanup
     /// This method removes the window from parent's child list
@@ -340,10 +416,33 @@ mod tests {
         let mut win = Window::new(ptr::null_mut(), 10, 5);
         win.clear();
 
-        let source = vec![((0x0Fu16) << 8) | (b'X' as u16); 20];
+        let source = vec![((0x0F as Attrib) << 8) | (b'X' as Attrib); 20];
         win.copy(&source, 4, 5, 0, 0);
 
         // Check first cell copied
-        assert_eq!(win.canvas[0] & 0xFF, b'X' as u16);
+        assert_eq!(win.canvas[0] & 0xFF, b'X' as Attrib);
+    }
+
+    #[test]
+    fn window_border_with_title() {
+        let mut win = Window::new(ptr::null_mut(), 12, 3);
+        win.draw_border(BorderStyle::Single, Some("Quit?"), 0x1F);
+        let row = |win: &Window, y: usize| -> Vec<u8> {
+            win.canvas[y * 12..(y + 1) * 12]
+                .iter()
+                .map(|a| (a & 0xFF) as u8)
+                .collect()
+        };
+        let mut top = vec![special::ULCORNER, special::HLINE];
+        top.extend_from_slice(b" Quit? ");
+        top.extend([special::HLINE, special::HLINE, special::URCORNER]);
+        assert_eq!(row(&win, 0), top);
+        assert_eq!(row(&win, 1)[0], special::VLINE);
+        assert_eq!(row(&win, 1)[11], special::VLINE);
+        assert_eq!(row(&win, 2)[0], special::LLCORNER);
+        assert_eq!(win.canvas[0] >> 8, 0x1F);
+
+        win.draw_border(BorderStyle::Double, Some("A very long title"), 0x07);
+        assert_eq!(&row(&win, 0)[..], b"#= A very =#");
     }
 }
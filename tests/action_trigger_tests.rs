@@ -174,3 +174,34 @@ fn test_gag_returns_empty_on_match() {
     assert_eq!(gag.commands, "");
     assert_eq!(gag.action_type, ActionType::Gag);
 }
+
+#[test]
+fn test_mud_actions_with_native_regex() {
+    // Without Perl/Python, actions compile and match through the native backend
+    use okros::plugins::native_regex::NativeRegex;
+
+    let mut re = NativeRegex::new();
+    let mut mud = Mud::empty();
+    for mut action in [
+        Action::new(r"^(\w+) arrives\.", "wave %1", ActionType::Trigger),
+        Action::new("stupid", "smart", ActionType::Replacement),
+        Action::new(r"^\[chat\]", "", ActionType::Gag),
+    ] {
+        action.compile(&mut re);
+        mud.action_list.push(action);
+    }
+
+    assert_eq!(
+        mud.check_action_match("Gandalf arrives.", &mut re),
+        vec!["wave Gandalf".to_string()]
+    );
+    assert_eq!(
+        mud.check_replacement("a stupid idea", &mut re),
+        Some("a smart idea".to_string())
+    );
+    assert_eq!(
+        mud.check_replacement("[chat] buy gold", &mut re),
+        Some(String::new())
+    );
+    assert_eq!(mud.check_replacement("nothing here", &mut re), None);
+}
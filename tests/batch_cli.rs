@@ -0,0 +1,82 @@
+// --send/--script against a headless offline instance (the real binary)
+
+use okros::control::default_socket_path;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn send_and_script_print_the_output() {
+    let exe = env!("CARGO_BIN_EXE_okros");
+    let instance = format!("batch_cli_{}", std::process::id());
+    let mut server = Command::new(exe)
+        .args(["--headless", "--offline", "--instance", &instance])
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let socket = default_socket_path(&instance);
+    for _ in 0..50 {
+        if socket.exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let out = Command::new(exe)
+        .args(["--send", &instance, "look;inventory", "--wait", "200"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("Forest Clearing"), "{}", text);
+    assert!(text.ends_with("You are carrying nothing.\n"), "{}", text);
+
+    let mut script = Command::new(exe)
+        .args(["--script", "-", &instance, "--wait", "200"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    script
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"north\n\n")
+        .unwrap();
+    let out = script.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.starts_with("Dense Forest\n"), "{}", text);
+    assert!(!text.contains("Forest Clearing"), "{}", text);
+
+    let out = Command::new(exe)
+        .args(["--send", "batch_cli_nobody_home", "look"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+
+    server.kill().ok();
+    server.wait().ok();
+    std::fs::remove_file(&socket).ok();
+}
+
+#[test]
+fn startup_target_errors_before_the_screen() {
+    let exe = env!("CARGO_BIN_EXE_okros");
+    let mut config = tempfile::NamedTempFile::new().unwrap();
+    writeln!(config, "MUD Nodeka {{\n  host nodeka.com 23;\n}}").unwrap();
+    config.flush().unwrap();
+    let path = config.path().to_str().unwrap();
+
+    let stderr = |args: &[&str]| {
+        let out = Command::new(exe).args(args).output().unwrap();
+        String::from_utf8_lossy(&out.stderr).into_owned()
+    };
+    let missing = stderr(&["--config", path, "aardwolf"]);
+    assert!(missing.contains("No MUD named 'aardwolf'"), "{}", missing);
+    assert!(missing.contains(path), "{}", missing);
+    assert!(stderr(&["nodeka.com", "telnet"]).contains("Bad port 'telnet'"));
+    assert!(stderr(&["a", "b", "c"]).starts_with("Usage: okros"));
+    assert!(stderr(&["--config"]).contains("--config needs a file name"));
+}
@@ -0,0 +1,65 @@
+// The library facade against a scripted MUD on a loopback TCP port
+
+use okros::{Client, ClientEvent};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn client_events_sends_and_triggers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // The MUD: greets, prompts (IAC GA), then reports the commands it got
+    let mud = thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(b"Welcome!\r\nYou are hungry.\r\nHP 10> \xff\xf9")
+            .unwrap();
+        let mut reader = BufReader::new(conn.try_clone().unwrap());
+        let mut got = Vec::new();
+        for _ in 0..2 {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            got.push(line.trim_end().to_string());
+        }
+        conn.write_all(format!("Got {}\r\n", got.join(", ")).as_bytes())
+            .unwrap();
+    });
+
+    let mut client = Client::connect_to(&addr.to_string()).unwrap();
+    client
+        .on_match("^You are hungry", |_| vec!["eat bread".to_string()])
+        .unwrap();
+    assert!(client.on_match("(", |_| Vec::new()).is_err());
+
+    let mut events = Vec::new();
+    for event in client.events() {
+        let done = event == ClientEvent::Prompt("HP 10> ".to_string());
+        events.push(event);
+        if done {
+            break;
+        }
+    }
+    assert_eq!(
+        events,
+        vec![
+            ClientEvent::Line("Welcome!".to_string()),
+            ClientEvent::Line("You are hungry.".to_string()),
+            ClientEvent::Prompt("HP 10> ".to_string()),
+        ]
+    );
+
+    client.send("look").unwrap();
+    let rest: Vec<_> = client.events().collect();
+    assert_eq!(
+        rest,
+        vec![
+            ClientEvent::Line("Got eat bread, look".to_string()),
+            ClientEvent::Disconnected,
+        ]
+    );
+    assert!(!client.is_connected());
+    assert!(client.send("look").is_err());
+    assert!(client.poll(Duration::ZERO).is_err());
+    mud.join().unwrap();
+}
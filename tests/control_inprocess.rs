@@ -340,3 +340,602 @@ fn test_inprocess_connect_bad_address() {
 
     std::fs::remove_file(&socket_path).ok();
 }
+
+#[test]
+fn test_inprocess_wait_timer_fires() {
+    let instance = format!("inproc_wait_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // Queue a delayed command (not connected, so it is echoed into the buffer)
+    writeln!(
+        stream,
+        r##"{{"cmd":"command","data":"#wait 0.1 timer fired"}}"##
+    )
+    .unwrap();
+    let mut response = String::new();
+    reader.read_line(&mut response).ok();
+    let resp: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(resp["event"], "Ok");
+
+    // Listing timers
+    response.clear();
+    writeln!(stream, r##"{{"cmd":"command","data":"#tick"}}"##).unwrap();
+    reader.read_line(&mut response).ok();
+    let resp: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(resp["event"], "Ok");
+
+    // Bad usage is reported back
+    response.clear();
+    writeln!(stream, r##"{{"cmd":"command","data":"#wait nope"}}"##).unwrap();
+    reader.read_line(&mut response).ok();
+    let resp: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(resp["event"], "Error");
+
+    thread::sleep(Duration::from_millis(400));
+
+    response.clear();
+    writeln!(stream, r#"{{"cmd":"peek","lines":10}}"#).unwrap();
+    reader.read_line(&mut response).ok();
+    let resp: serde_json::Value = serde_json::from_str(&response).unwrap();
+    let lines = resp["lines"].as_array().unwrap();
+    assert!(lines
+        .iter()
+        .any(|l| l.as_str().unwrap_or("").contains("timer fired")));
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_status_fields() {
+    let instance = format!("inproc_fields_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = |req: serde_json::Value| -> serde_json::Value {
+        writeln!(stream, "{}", req).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).ok();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    let resp = request(json!({"cmd":"set_field","name":"hp","data":"HP 10/20","priority":5}));
+    assert_eq!(resp["event"], "Ok");
+    request(json!({"cmd":"set_field","name":"mana","data":"Mana 3"}));
+    request(json!({"cmd":"set_field","name":"mana"})); // clears
+
+    let resp = request(json!({"cmd":"set_field","data":"x"}));
+    assert_eq!(resp["event"], "Error");
+
+    let resp = request(json!({"cmd":"get_fields"}));
+    assert_eq!(resp["event"], "Fields");
+    let fields = resp["fields"].as_array().unwrap();
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0]["name"], "hp");
+    assert_eq!(fields[0]["text"], "HP 10/20");
+    assert_eq!(fields[0]["priority"], 5);
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_subscribe_pushes_events() {
+    use std::net::TcpListener;
+
+    let instance = format!("inproc_subscribe_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    // Fake MUD on loopback
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut next = || -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    writeln!(stream, r#"{{"cmd":"subscribe"}}"#).unwrap();
+    assert_eq!(next()["event"], "Ok");
+
+    let address = format!("127.0.0.1:{}", port);
+    writeln!(stream, "{}", json!({"cmd":"connect","data":address})).unwrap();
+    let (mut server_side, _) = mud.accept().unwrap();
+
+    // Connect event and the command's response may arrive in either order
+    let mut got = [next(), next()];
+    got.sort_by_key(|v| v["event"].as_str().unwrap_or("").to_string());
+    assert_eq!(got[0]["event"], "Ok");
+    assert_eq!(got[1]["event"], "connect");
+    assert_eq!(got[1]["address"], address);
+
+    server_side
+        .write_all(b"Welcome!\r\nHP: 10> \xff\xf9")
+        .unwrap();
+    let line = next();
+    assert_eq!(line["event"], "line");
+    assert_eq!(line["text"], "Welcome!");
+    let prompt = next();
+    assert_eq!(prompt["event"], "prompt");
+    assert_eq!(prompt["text"], "HP: 10> ");
+
+    // Commands still work on a subscribed connection
+    writeln!(stream, r#"{{"cmd":"status"}}"#).unwrap();
+    assert_eq!(next()["event"], "Status");
+
+    drop(server_side);
+    assert_eq!(next()["event"], "disconnect");
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_subscribe_by_event_type() {
+    use std::net::TcpListener;
+
+    let instance = format!("inproc_subscribe_types_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut next = || -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    writeln!(stream, r#"{{"cmd":"subscribe","data":"prompt,tick"}}"#).unwrap();
+    let bad = next();
+    assert_eq!(bad["event"], "Error");
+    assert!(bad["message"].as_str().unwrap().contains("tick"));
+    writeln!(
+        stream,
+        r#"{{"cmd":"subscribe","data":"prompt disconnect"}}"#
+    )
+    .unwrap();
+    assert_eq!(next()["event"], "Ok");
+
+    // No connect or line events: only the prompt and the disconnect
+    let address = format!("127.0.0.1:{}", port);
+    writeln!(stream, "{}", json!({"cmd":"connect","data":address})).unwrap();
+    let (mut server_side, _) = mud.accept().unwrap();
+    assert_eq!(next()["event"], "Ok");
+    server_side
+        .write_all(b"Welcome!\r\nHP: 10> \xff\xf9")
+        .unwrap();
+    let prompt = next();
+    assert_eq!(prompt["event"], "prompt");
+    assert_eq!(prompt["text"], "HP: 10> ");
+    drop(server_side);
+    assert_eq!(next()["event"], "disconnect");
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_ping_and_net_stats() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    let instance = format!("inproc_ping_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut next = || -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    writeln!(stream, r#"{{"cmd":"ping"}}"#).unwrap();
+    assert_eq!(next()["event"], "Error");
+
+    writeln!(stream, r#"{{"cmd":"subscribe"}}"#).unwrap();
+    assert_eq!(next()["event"], "Ok");
+    let address = format!("127.0.0.1:{}", port);
+    writeln!(stream, "{}", json!({"cmd":"connect","data":address})).unwrap();
+    let (mut server_side, _) = mud.accept().unwrap();
+    next(); // Ok and connect, in either order
+    next();
+
+    // IAC AYT goes out; the server's answer completes the ping
+    writeln!(stream, r#"{{"cmd":"ping"}}"#).unwrap();
+    assert_eq!(next()["event"], "Ok");
+    let mut ayt = [0u8; 2];
+    server_side.read_exact(&mut ayt).unwrap();
+    assert_eq!(ayt, [255, 246]);
+    server_side.write_all(b"[Yes]\r\n").unwrap();
+    let ping = next();
+    assert_eq!(ping["event"], "ping");
+    assert!(ping["rtt_ms"].is_u64());
+    assert_eq!(next()["event"], "line");
+
+    writeln!(stream, r#"{{"cmd":"net_stats"}}"#).unwrap();
+    let stats = next();
+    assert_eq!(stats["event"], "NetStats");
+    assert_eq!(stats["bytes_in"], 7);
+    assert_eq!(stats["bytes_out"], 2);
+    assert!(stats["rate_in"].as_f64().unwrap() > 0.0);
+    assert_eq!(stats["rtt_ms"], ping["rtt_ms"]);
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_send_rate_paces_commands() {
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    let instance = format!("inproc_sendrate_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = |req: serde_json::Value| -> serde_json::Value {
+        writeln!(stream, "{}", req).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    let address = format!("127.0.0.1:{}", port);
+    assert_eq!(
+        request(json!({"cmd":"connect","data":address}))["event"],
+        "Ok"
+    );
+    let (mut server_side, _) = mud.accept().unwrap();
+    server_side
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // Two commands per second, one at a time
+    let resp = request(json!({"cmd":"command","data":"#sendrate 2 1"}));
+    assert_eq!(resp["event"], "Ok");
+    let start = Instant::now();
+    request(json!({"cmd":"command","data":"north"}));
+    request(json!({"cmd":"command","data":"south"}));
+
+    let fields = request(json!({"cmd":"get_fields"}));
+    let queue = fields["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["name"] == "queue")
+        .cloned()
+        .unwrap();
+    assert_eq!(queue["text"], "Queued: 1");
+
+    let mut got = [0u8; 12];
+    server_side.read_exact(&mut got).unwrap();
+    assert_eq!(&got, b"north\nsouth\n");
+    assert!(start.elapsed() >= Duration::from_millis(400));
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_get_screen_and_keys() {
+    let instance = format!("inproc_screen_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = |req: serde_json::Value| -> serde_json::Value {
+        writeln!(stream, "{}", req).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).ok();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    // Row 0 is covered by the status line, as in TTY mode
+    request(json!({"cmd":"send","data":"Welcome!"}));
+    request(json!({"cmd":"send","data":"You are in a forest."}));
+
+    // Typed (not yet entered) text shows on the input line
+    let resp = request(json!({"cmd":"key","data":"look"}));
+    assert_eq!(resp["event"], "Ok");
+    let screen = request(json!({"cmd":"get_screen"}));
+    assert_eq!(screen["event"], "Screen");
+    let rows = screen["rows"].as_array().unwrap();
+    assert_eq!(rows.len(), 25); // 24 output rows + input line
+    assert!(rows[1].as_str().unwrap().contains("You are in a forest."));
+    assert!(rows[24].as_str().unwrap().contains("mcl>look"));
+    assert_eq!(screen["cursor_y"], 24);
+    assert_eq!(screen["cursor_x"], 8);
+
+    // Enter with no connection: the line is echoed into the buffer
+    request(json!({"cmd":"key","data":"\r"}));
+    let screen = request(json!({"cmd":"get_screen"}));
+    let rows = screen["rows"].as_array().unwrap();
+    assert!(rows[2].as_str().unwrap().contains("look"));
+    assert!(!rows[24].as_str().unwrap().contains("look"));
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_attach_arbitration() {
+    let instance = format!("inproc_arbitration_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let client = || {
+        let stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+        stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (stream, reader)
+    };
+    let next = |reader: &mut BufReader<UnixStream>| -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+    let (mut a, mut a_reader) = client();
+    let (mut b, mut b_reader) = client();
+
+    writeln!(a, r#"{{"cmd":"attach"}}"#).unwrap();
+    assert_eq!(next(&mut a_reader)["event"], "Ok");
+    writeln!(a, r#"{{"cmd":"status"}}"#).unwrap();
+    let status = next(&mut a_reader);
+    assert_eq!(status["attached"], true);
+    let a_id = status["client"].clone();
+    assert_eq!(status["attached_by"], a_id);
+
+    // Second client is an observer: no attach without force, no keys
+    writeln!(b, r#"{{"cmd":"attach"}}"#).unwrap();
+    let resp = next(&mut b_reader);
+    assert_eq!(resp["event"], "Error");
+    assert!(resp["message"]
+        .as_str()
+        .unwrap()
+        .contains("already attached"));
+    writeln!(b, r#"{{"cmd":"key","data":"x"}}"#).unwrap();
+    assert_eq!(next(&mut b_reader)["event"], "Error");
+    // ...but read-only commands work
+    writeln!(b, r#"{{"cmd":"get_buffer"}}"#).unwrap();
+    assert_eq!(next(&mut b_reader)["event"], "Buffer");
+
+    // Forced takeover notifies the displaced client
+    writeln!(b, r#"{{"cmd":"attach","force":true}}"#).unwrap();
+    assert_eq!(next(&mut b_reader)["event"], "Ok");
+    writeln!(b, r#"{{"cmd":"status"}}"#).unwrap();
+    let b_id = next(&mut b_reader)["client"].clone();
+    let note = next(&mut a_reader);
+    assert_eq!(note["event"], "detached");
+    assert_eq!(note["by"], b_id);
+    writeln!(a, r#"{{"cmd":"key","data":"x"}}"#).unwrap();
+    assert_eq!(next(&mut a_reader)["event"], "Error");
+
+    // Hanging up releases the attachment
+    drop(b_reader);
+    drop(b);
+    thread::sleep(Duration::from_millis(100));
+    writeln!(a, r#"{{"cmd":"status"}}"#).unwrap();
+    let status = next(&mut a_reader);
+    assert_eq!(status["attached"], false);
+    assert!(status["attached_by"].is_null());
+    writeln!(a, r#"{{"cmd":"attach"}}"#).unwrap();
+    assert_eq!(next(&mut a_reader)["event"], "Ok");
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_aliases_and_triggers() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    let instance = format!("inproc_automation_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut next = || -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+    let mut send = |req: serde_json::Value| writeln!(stream, "{}", req).unwrap();
+
+    send(json!({"cmd":"add_alias","name":"k","data":"{kill %1;look}"}));
+    assert_eq!(next()["event"], "Ok");
+    send(json!({"cmd":"add_trigger","pattern":"^You are (\\w+)","data":"say I am %1"}));
+    assert_eq!(next()["event"], "Ok");
+    send(json!({"cmd":"add_trigger","kind":"gag","pattern":"^spam"}));
+    assert_eq!(next()["event"], "Ok");
+    send(json!({"cmd":"add_trigger","pattern":"(","data":"x"}));
+    assert_eq!(next()["event"], "Error");
+    send(json!({"cmd":"add_trigger","kind":"highlight","pattern":"x","data":"nope"}));
+    assert_eq!(next()["event"], "Error");
+    send(json!({"cmd":"add_trigger","pattern":"x","data":"{@py{greet(args[1])}}"}));
+    let refused = next();
+    assert_eq!(refused["event"], "Error");
+    assert!(refused["message"]
+        .as_str()
+        .unwrap()
+        .contains("supported in headless mode"));
+
+    send(json!({"cmd":"list_aliases"}));
+    let aliases = next();
+    assert_eq!(
+        aliases["aliases"],
+        json!([{"name":"k","text":"kill %1;look"}])
+    );
+    send(json!({"cmd":"list_triggers"}));
+    let triggers = next();
+    assert_eq!(triggers["event"], "Triggers");
+    assert_eq!(triggers["triggers"].as_array().unwrap().len(), 2);
+    assert_eq!(triggers["triggers"][1]["kind"], "gag");
+
+    send(json!({"cmd":"subscribe"}));
+    assert_eq!(next()["event"], "Ok");
+    let address = format!("127.0.0.1:{}", port);
+    send(json!({"cmd":"connect","data":address}));
+    let (mut server_side, _) = mud.accept().unwrap();
+    server_side
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    next(); // Ok and connect, in either order
+    next();
+
+    // Gagged lines never show; a trigger's commands reach the MUD
+    server_side
+        .write_all(b"spam spam\r\nYou are hungry\r\n")
+        .unwrap();
+    let line = next();
+    assert_eq!(line["text"], "You are hungry");
+    let fired = next();
+    assert_eq!(fired["event"], "trigger_fired");
+    assert_eq!(fired["commands"], json!(["say I am hungry"]));
+    let mut got = [0u8; 16];
+    server_side.read_exact(&mut got).unwrap();
+    assert_eq!(&got, b"say I am hungry\n");
+
+    // Commands are alias-expanded
+    send(json!({"cmd":"command","data":"k orc"}));
+    assert_eq!(next()["event"], "Ok");
+    let mut got = [0u8; 14];
+    server_side.read_exact(&mut got).unwrap();
+    assert_eq!(&got, b"kill orc\nlook\n");
+
+    send(json!({"cmd":"remove_trigger","pattern":"^spam"}));
+    assert_eq!(next()["event"], "Ok");
+    send(json!({"cmd":"remove_trigger","pattern":"^spam"}));
+    assert_eq!(next()["event"], "Error");
+    send(json!({"cmd":"remove_alias","name":"k"}));
+    assert_eq!(next()["event"], "Ok");
+    server_side.write_all(b"spam again\r\n").unwrap();
+    assert_eq!(next()["text"], "spam again");
+
+    // #stats counts: one trigger, three commands sent
+    send(json!({"cmd":"stats"}));
+    let stats = next();
+    assert_eq!(stats["event"], "Stats");
+    assert_eq!(stats["triggers_fired"], 1);
+    assert_eq!(stats["commands_sent"], 3);
+    assert_eq!(stats["bytes_received"], 39);
+    assert!(stats["connected_at"].is_i64());
+    assert_eq!(stats["lines"], 2);
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_get_lines_pages_history() {
+    let instance = format!("inproc_lines_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(1))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request = |req: serde_json::Value| -> serde_json::Value {
+        writeln!(stream, "{}", req).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).ok();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    for i in 0..50 {
+        request(json!({"cmd":"send","data":format!("line {}", i)}));
+    }
+    let resp = request(json!({"cmd":"get_lines","from":-5,"count":2}));
+    assert_eq!(resp["event"], "Lines");
+    assert_eq!(resp["total"], 50);
+    assert_eq!(resp["oldest"], 0);
+    let lines = resp["lines"].as_array().unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["line"], 45);
+    assert_eq!(lines[0]["text"], "line 45");
+    assert!(lines[0]["ansi"].as_str().unwrap().contains("line 45"));
+
+    // Absolute line numbers page forward; the default is the newest 100
+    let resp = request(json!({"cmd":"get_lines","from":10,"count":3}));
+    assert_eq!(resp["lines"][2]["text"], "line 12");
+    let resp = request(json!({"cmd":"get_lines"}));
+    assert_eq!(resp["lines"].as_array().unwrap().len(), 50);
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[test]
+fn test_inprocess_close_and_zap() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    let instance = format!("inproc_close_{}", std::process::id());
+    let socket_path = start_test_server(&instance);
+
+    let mud = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = mud.local_addr().unwrap().port();
+
+    let mut stream = UnixStream::connect(&socket_path).expect("Failed to connect");
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut next = || -> serde_json::Value {
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    };
+
+    writeln!(stream, r#"{{"cmd":"zap"}}"#).unwrap();
+    assert_eq!(next()["message"], "not connected");
+    writeln!(stream, r#"{{"cmd":"subscribe","data":"line disconnect"}}"#).unwrap();
+    assert_eq!(next()["event"], "Ok");
+
+    // close: the logout command goes out, then EOF; the MUD's goodbye still
+    // arrives before it hangs up
+    let address = format!("127.0.0.1:{}", port);
+    writeln!(stream, "{}", json!({"cmd":"connect","data":address})).unwrap();
+    let (mut server_side, _) = mud.accept().unwrap();
+    assert_eq!(next()["event"], "Ok");
+    writeln!(stream, r#"{{"cmd":"close","data":"quit"}}"#).unwrap();
+    assert_eq!(next()["event"], "Ok");
+    let mut sent = String::new();
+    server_side.read_to_string(&mut sent).unwrap();
+    assert_eq!(sent.trim_end(), "quit");
+    server_side.write_all(b"Goodbye.\r\n").unwrap();
+    drop(server_side);
+    let goodbye = next();
+    assert_eq!(goodbye["event"], "line");
+    assert_eq!(goodbye["text"], "Goodbye.");
+    assert_eq!(next()["event"], "disconnect");
+
+    // zap: gone at once, with the buffer kept
+    writeln!(stream, "{}", json!({"cmd":"connect","data":address})).unwrap();
+    let (_server_side, _) = mud.accept().unwrap();
+    assert_eq!(next()["event"], "Ok");
+    writeln!(stream, r#"{{"cmd":"zap"}}"#).unwrap();
+    let mut events = vec![next()["event"].clone(), next()["event"].clone()];
+    events.sort_by_key(|e| e.to_string());
+    assert_eq!(events, vec![json!("Ok"), json!("disconnect")]);
+    writeln!(stream, r#"{{"cmd":"get_buffer"}}"#).unwrap();
+    assert!(next().to_string().contains("Goodbye."));
+
+    std::fs::remove_file(&socket_path).ok();
+}
@@ -28,12 +28,13 @@ fn pipeline_passthrough_telnet_ansi_scrollback() {
             if !app.is_empty() {
                 for ev in ansi.feed(&app) {
                     match ev {
-                        AnsiEvent::SetColor(c) => cur_color = c,
+                        AnsiEvent::SetColor(c) => cur_color = c as u8,
                         AnsiEvent::Text(b'\n') => {
                             sb.print_line(&line_bytes, cur_color);
                             line_bytes.clear();
                         }
                         AnsiEvent::Text(b) => line_bytes.push(b),
+                        _ => {} // Cursor movement: Session's job
                     }
                 }
             }
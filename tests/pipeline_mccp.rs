@@ -40,7 +40,7 @@ fn pipeline_real_mccp_v2_telnet_ansi_scrollback() {
         let app = telnet.take_app_out();
         for ev in ansi.feed(&app) {
             match ev {
-                AnsiEvent::SetColor(c) => cur_color = c,
+                AnsiEvent::SetColor(c) => cur_color = c as u8,
                 AnsiEvent::Text(b'\n') => {
                     sb.print_line(&line_bytes, cur_color);
                     line_bytes.clear();
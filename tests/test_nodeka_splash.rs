@@ -118,7 +118,7 @@ fn test_single_splash(ansi_data: &str, filename: &str) -> bool {
 
         for (i, (ch, color)) in session.current_line_colored().iter().enumerate() {
             if line_start + i < viewport.len() {
-                viewport[line_start + i] = ((*color as u16) << 8) | (*ch as u16);
+                viewport[line_start + i] = ((*color as u32) << 8) | (*ch as u32);
             }
         }
     }
@@ -274,7 +274,7 @@ fn test_window_rendering(ansi_data: &str, filename: &str) -> bool {
 
         for (i, (ch, color)) in session.current_line_colored().iter().enumerate() {
             if line_start + i < viewport.len() {
-                viewport[line_start + i] = ((*color as u16) << 8) | (*ch as u16);
+                viewport[line_start + i] = ((*color as u32) << 8) | (*ch as u32);
             }
         }
     }